@@ -0,0 +1,66 @@
+// Cel-shaded look, run with `post toon` in the console or `--post toon`. Brightness is
+// quantized into a few flat bands, the darker ones crosshatched, and outlines are drawn where the
+// depth jumps or the face normal changes between neighbouring pixels.
+
+// Flat brightness levels the image is quantized into.
+const float BANDS = 4.0;
+// Bands below these brightnesses get a first and a second layer of hatching.
+const float HATCH_LIGHT = 0.35;
+const float HATCH_DARK = 0.15;
+// Pixels between hatching lines and how much they darken.
+const float HATCH_SPACING = 6.0;
+const float HATCH_STRENGTH = 0.35;
+// Relative change of depth between neighbours drawn as an outline.
+const float DEPTH_EDGE = 0.05;
+const vec3 OUTLINE_COLOR = vec3(0.02);
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.2126, 0.7152, 0.0722));
+}
+
+// Whether `pixel` lies on a line of the hatching running along `direction` in screen space.
+bool hatched(ivec2 pixel, vec2 direction) {
+    return mod(dot(vec2(pixel), direction), HATCH_SPACING) < 1.0;
+}
+
+// Whether `pixel` sits on a silhouette or crease, judged by its neighbours to the right and up.
+bool outline(ivec2 pixel) {
+    float dist = depth(pixel);
+    vec3 faceNormal = normal(pixel);
+    for (int i = 0; i < 2; i++) {
+        ivec2 neighbour = pixel + (i == 0 ? ivec2(1, 0) : ivec2(0, 1));
+        float neighbourDist = depth(neighbour);
+        // Sky next to a surface, or two surfaces at different depths.
+        if ((dist < 0.0) != (neighbourDist < 0.0)) {
+            return true;
+        }
+        if (dist > 0.0 && abs(dist - neighbourDist) > DEPTH_EDGE * min(dist, neighbourDist)) {
+            return true;
+        }
+        if (dist > 0.0 && normal(neighbour) != faceNormal) {
+            return true;
+        }
+    }
+    return false;
+}
+
+vec4 postProcess(ivec2 pixel) {
+    if (outline(pixel)) {
+        return vec4(OUTLINE_COLOR, 1.0);
+    }
+    vec3 result = color(pixel);
+    float brightness = luma(result);
+    if (brightness > 0.0) {
+        float band = ceil(brightness * BANDS) / BANDS;
+        result *= band / brightness;
+        if (depth(pixel) > 0.0) {
+            if (band < HATCH_LIGHT && hatched(pixel, vec2(1.0, 1.0))) {
+                result *= 1.0 - HATCH_STRENGTH;
+            }
+            if (band < HATCH_DARK && hatched(pixel, vec2(1.0, -1.0))) {
+                result *= 1.0 - HATCH_STRENGTH;
+            }
+        }
+    }
+    return vec4(result, 1.0);
+}
@@ -0,0 +1,22 @@
+// Example post-process shader, run with `--post assets/post/vignette.glsl` or
+// `post assets/post/vignette.glsl` in the console. See assets/shader/post.glsl for what is
+// available.
+
+const vec3 FOG_COLOR = vec3(0.6, 0.7, 0.9);
+const float FOG_DENSITY = 0.004;
+
+vec4 postProcess(ivec2 pixel) {
+    vec3 result = color(pixel);
+
+    // Distant voxels fade into the fog, sky is left alone.
+    float dist = depth(pixel);
+    if (dist > 0.0) {
+        result = mix(result, FOG_COLOR, 1.0 - exp(-dist * FOG_DENSITY));
+    }
+
+    // Corners darken, breathing slowly.
+    vec2 uv = (vec2(pixel) + 0.5) / vec2(constants.resolution);
+    float strength = 0.35 + 0.05 * sin(constants.time);
+    result *= 1.0 - strength * dot(uv - 0.5, uv - 0.5) * 2.0;
+    return vec4(result, 1.0);
+}
@@ -0,0 +1,57 @@
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+// Mean of the samples in rgb, sample count in alpha.
+#ifdef HALF_ACCUMULATION
+layout(set = 0, binding = 0, rgba16f) uniform readonly image2D accumulation;
+#else
+layout(set = 0, binding = 0, rgba32f) uniform readonly image2D accumulation;
+#endif
+
+// Mean of the squared sample luminance.
+layout(set = 0, binding = 1, r32f) uniform readonly image2D moments;
+
+layout(set = 0, binding = 2) buffer WorkQueue {
+    uint count;
+    uint pixels[];
+} queue;
+
+// Indirect dispatch arguments for tracing the queue, one trace workgroup per `group_pixels`.
+layout(set = 0, binding = 3) buffer DispatchArgs {
+    uint x;
+    uint y;
+    uint z;
+} args;
+
+layout(push_constant) uniform PushConstants {
+    uvec2 resolution;
+    float variance_threshold;
+    uint min_samples;
+    // Invocations in a workgroup of the trace shader.
+    uint group_pixels;
+} constants;
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+void main() {
+    if (any(greaterThanEqual(gl_GlobalInvocationID.xy, constants.resolution))) {
+        return;
+    }
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    vec4 acc = imageLoad(accumulation, pixel);
+    if (acc.a < float(constants.min_samples)) {
+        return;
+    }
+    float mean = luma(acc.rgb);
+    float variance = max(imageLoad(moments, pixel).r - mean * mean, 0.0);
+
+    // The variance of the mean shrinks with every sample, so converged pixels drop out.
+    if (variance / acc.a > constants.variance_threshold) {
+        uint index = atomicAdd(queue.count, 1);
+        queue.pixels[index] = uint(pixel.x) | (uint(pixel.y) << 16);
+        atomicMax(args.x, index / constants.group_pixels + 1);
+    }
+}
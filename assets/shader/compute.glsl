@@ -1,22 +1,354 @@
 #version 450
 
+#ifdef CHUNK_PAGES
+#extension GL_EXT_nonuniform_qualifier : require
+#endif
+#ifdef CHUNK_ADDRESSES
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_buffer_reference_uvec2 : require
+#endif
+
+// 16x16 unless the workgroup size was autotuned for the GPU, see `autotune.rs`.
 layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+layout(local_size_x_id = 1, local_size_y_id = 2) in;
+
+// Selects what a dispatch does: 0 traces every pixel, 1-3 trace the tiles of one bin with a
+// variant suited to it, 4 sorts the screen tiles into those bins.
+layout(constant_id = 0) const uint TILE_CLASS = 0;
+const uint TILE_CLASS_ALL = 0;
+const uint TILE_CLASS_COMPLEX = 1;
+const uint TILE_CLASS_SIMPLE = 2;
+const uint TILE_CLASS_SKY = 3;
+const uint TILE_CLASS_CLASSIFY = 4;
 
 layout(set = 0, binding = 0, rgba8) uniform writeonly image2D img;
 
-layout(set = 0, binding = 1) buffer Data {
-    uint data[][256][256];
+// Every chunk packed against a palette of the voxel types in it, see chunk_palette.rs. A header
+// per chunk comes first: the width of its palette indices in the lowest 5 bits, and in the
+// others its only voxel type when the width is 0, otherwise where its words start: a 16 bit
+// entry for every brick, its palette and the packed indices of its bricks holding more than one
+// voxel type. Read through `worldWord`.
+#if defined(CHUNK_PAGES)
+// Split into pages of PAGE_WORDS words bound as a runtime descriptor array, see `GpuChunks`.
+layout(set = 1, binding = 0) readonly buffer Page {
+    uint words[];
+} pages[];
+#elif defined(CHUNK_ADDRESSES)
+// Split into pages of PAGE_WORDS words read through the device addresses in a table of
+// MAX_PAGES, see `GpuChunks`.
+layout(buffer_reference, std430, buffer_reference_align = 4) readonly buffer Page {
+    uint words[];
+};
+layout(set = 0, binding = 1) readonly buffer PageAddresses {
+    uvec2 pageAddresses[];
+};
+#else
+layout(set = 0, binding = 1) readonly buffer Data {
+    uint data[];
+};
+#endif
+
+// Mean of the samples in rgb, sample count in alpha. Half floats count up to 2048 samples, after
+// which new ones keep a fixed weight.
+#ifdef HALF_ACCUMULATION
+layout(set = 0, binding = 2, rgba16f) uniform image2D accumulation;
+#else
+layout(set = 0, binding = 2, rgba32f) uniform image2D accumulation;
+#endif
+
+// Mean of the squared sample luminance.
+layout(set = 0, binding = 3, r32f) uniform image2D moments;
+
+layout(set = 0, binding = 4) buffer WorkQueue {
+    uint count;
+    uint pixels[];
+} queue;
+
+// Tile count of each bin followed by one list of packed tile coordinates per bin.
+layout(set = 0, binding = 5) buffer TileBins {
+    uint counts[3];
+    uint tiles[];
+} bins;
+
+// Indirect dispatch arguments of each bin, one workgroup per tile.
+layout(set = 0, binding = 6) buffer TileBinArgs {
+    uint args[9];
+} binArgs;
+
+// Unlit color of the primary hit in rgb and its voxel type in a (0 for sky), written by the
+// G-buffer pass.
+layout(set = 0, binding = 7, rgba16f) uniform image2D albedo;
+
+// Face normal in xyz and hit distance in w (negative for sky), written by the G-buffer pass.
+layout(set = 0, binding = 8, rgba16f) uniform image2D gbuffer;
+
+// Half resolution lighting factor the albedo is multiplied with.
+layout(set = 0, binding = 9, rgba16f) uniform image2D lightingImage;
+
+// Offset in uv units from where each traced pixel's surface was seen in the previous frame to
+// where it is now in xy, read by the temporal upscaler, and the distance to the primary hit in z,
+// -1 for sky, read by post-processing.
+layout(set = 0, binding = 10, rgba16f) uniform image2D motion;
+
+// The pixel to report the primary hit of, filled in by the invocation tracing it. `voxel` stays 0
+// for sky.
+layout(set = 0, binding = 11) buffer Pick {
+    ivec2 pixel;
+    uint voxel;
+    float distance;
+    ivec4 position;
+    ivec4 normal;
+    // Factor the lighting multiplied the hit surface's color with, written once it is known.
+    vec4 light;
+    // Primary rays traced this frame and how many of them ran out of steps, counted with
+    // FLAG_STEP_LOG.
+    uint rays;
+    uint truncatedRays;
+} pick;
+
+// Palette indexed by voxel type.
+struct Material {
+    // Albedo in rgb, emissive strength in a.
+    vec4 color;
+    // Roughness in x, transparency in y, metalness in z, 1 in w for detail normals.
+    vec4 params;
+    // Layer in x, see layers.rs.
+    uvec4 layer;
+};
+layout(set = 0, binding = 12) readonly buffer Materials {
+    Material materials[];
+};
+
+// Box drawn translucently over the image to preview an edit, from `min` (inclusive) to `max`
+// (exclusive). Hidden when `min.w` is 0.
+layout(set = 0, binding = 13) uniform Preview {
+    ivec4 min;
+    ivec4 max;
+    vec4 color;
+} preview;
+
+// Distance from every chunk to the nearest chunk holding voxels, in chunks along the axis farthest
+// apart, as uploaded, indexed by (x * CHUNKS + y) * CHUNKS + z. See distance_field.rs.
+layout(set = 0, binding = 14) readonly buffer ChunkDistances {
+    uint distances[];
+} chunkDistances;
+
+// Boxes of portal voxels from `min` (inclusive) to `max` (exclusive). Rays entering one continue
+// from the portal at index `min.w`, turned around y by `max.w` quarter turns. See portal.rs.
+struct Portal {
+    ivec4 min;
+    ivec4 max;
+};
+layout(set = 0, binding = 15) readonly buffer Portals {
+    uint count;
+    Portal portals[];
+} portals;
+
+// Light baked for the faces of a static world, see light_bake.rs: where the entries of every
+// chunk start and where the last ones end, then two words per entry, a face's key and its light.
+layout(set = 0, binding = 16) readonly buffer LightBake {
+    uint words[];
+} lightBake;
+
+// Cubemaps of the world around a few points for glossy reflections, see reflection_probes.rs. A
+// 2D array with six layers per probe, faces towards +x, -x, +y, -y, +z and -z, rather than a cube
+// array, so it needs no imageCubeArray feature.
+layout(set = 0, binding = 17) uniform sampler2DArray probeImages;
+layout(set = 0, binding = 18) readonly buffer Probes {
+    uint count;
+    vec4 positions[];
+} probes;
+
+// Tangent space normal map tiled over the faces of materials with detail normals, see
+// detail_normals.rs. A single flat texel while none is loaded.
+layout(set = 0, binding = 19) uniform sampler2D detailNormals;
+
+// Upload state of every chunk for the chunk overlay, indexed like chunkDistances: one of
+// CHUNK_RESIDENT, CHUNK_STREAMING and CHUNK_DIRTY. Only written while the overlay is shown.
+layout(set = 0, binding = 20) readonly buffer ChunkStates {
+    uint states[];
+} chunkStates;
+
+// Radius in voxels of the camera's lens and distance along its view to the plane in focus, see
+// `lensRay`. A pinhole camera, everything in focus, while `aperture` is 0.
+layout(set = 0, binding = 21) uniform Lens {
+    float aperture;
+    float focus_distance;
+} lens;
+
+// Boxes moving freely through the world, drawn over the voxel grid, see entities.rs. The lower
+// corner in `min.xyz`, the upper in `max.xyz` and the voxel type whose material they are drawn
+// with in `min.w`.
+struct Entity {
+    vec4 min;
+    vec4 max;
 };
+layout(set = 0, binding = 22) readonly buffer Entities {
+    uint count;
+    Entity entities[];
+} entities;
+
+// Voxel models drawn over the voxel grid under transforms of their own, see objects.rs. The
+// rotation in `rotation.xyz` like the camera's, the edge length of their voxels in `rotation.w`,
+// where their center lies in `translation.xyz` with the bits of their layer in `translation.w`,
+// and the size of their model in `size.xyz`, whose voxels start at word `size.w` of
+// `objectVoxels`.
+struct Object {
+    vec4 rotation;
+    vec4 translation;
+    uvec4 size;
+};
+layout(set = 0, binding = 23) readonly buffer Objects {
+    uint count;
+    Object objects[];
+} objects;
+
+// The voxels of the objects' models, a byte per voxel and four to a word, z varying fastest.
+// Objects sharing a model share its voxels.
+layout(set = 0, binding = 24) readonly buffer ObjectVoxels {
+    uint words[];
+} objectVoxels;
+
+// Indices of the objects camera rays can meet, then from `indices[objects.count]` on those
+// other rays can meet, as found by cull_objects.glsl for this frame.
+layout(set = 0, binding = 25) readonly buffer CulledObjects {
+    uint viewCount;
+    uint rangeCount;
+    uint indices[];
+} culled;
+
+// Marks blended over the color of the voxel faces around their center when they are shaded, see
+// decals.rs. The center in `center.xyz`, the radius in `center.w`, the color in `color.rgb` and
+// the opacity in `color.a`.
+struct Decal {
+    vec4 center;
+    vec4 color;
+};
+layout(set = 0, binding = 26) readonly buffer Decals {
+    uint count;
+    Decal decals[];
+} decals;
+
+// Bit `i` of `hidden` is set when layer `i` is hidden, see layers.rs. Voxels of materials on a
+// hidden layer are passed through like empty space, objects on one are left out by the culling
+// pass.
+layout(set = 0, binding = 27) uniform LayerMask {
+    uint hidden;
+} layerMask;
+
+// Planes cutting the world open, see clipping.rs. Voxels whose center `p` has
+// `dot(planes[i].xyz, p) > planes[i].w` for any of the first `count` planes are passed through
+// like empty space. When `capped` is 1, faces left facing voxels that were cut away are drawn
+// in `capColor.rgb`.
+layout(set = 0, binding = 28) uniform ClipPlanes {
+    uint count;
+    uint capped;
+    vec4 capColor;
+    vec4 planes[3];
+} clipPlanes;
+
+// Mean of the samples' coverage, 1 for those hitting a voxel and 0 for sky, so captures can
+// leave the sky transparent. Only written while accumulating.
+layout(set = 0, binding = 29, r32f) uniform image2D coverage;
+
+// Outputs library users asked for with `Controller::request_outputs`, only written with
+// FLAG_OUTPUTS and otherwise a texel each: the distance from the eye to the primary hit along the
+// ray, infinite for sky, the normal of the face hit, zero for sky, and the voxel type hit, 0 for
+// sky. Like the motion they match the CPU tracer's `Outputs`.
+layout(set = 0, binding = 30, r32f) uniform writeonly image2D depthOutput;
+layout(set = 0, binding = 31, rgba16f) uniform writeonly image2D normalOutput;
+layout(set = 0, binding = 32, r32ui) uniform writeonly uimage2D materialOutput;
 
 layout(push_constant) uniform PushConstants {
     uvec2 resolution;
     vec3 camera_dir;
-    vec3 rotation; 
+    vec3 rotation;
     vec3 position;
     uint render_distance;
+    uint max_ray_steps;
+    uint max_bounces;
+    uint flags;
+    uint frame;
+    uint sample_index;
+    vec3 previous_rotation;
+    vec3 previous_position;
+    // Hour of the day from 0 to 24, see sunDirection in trace/brdf.glsl.
+    float time_of_day;
 } constants;
 
-float sdSphere(vec3 p, float d) { return length(p) - d; } 
+const uint FLAG_AMBIENT_OCCLUSION = 1;
+const uint FLAG_SHADOWS = 2;
+const uint FLAG_GLOBAL_ILLUMINATION = 4;
+const uint FLAG_STEP_WARNING = 8;
+const uint FLAG_ACCUMULATE = 16;
+const uint FLAG_WORK_QUEUE = 32;
+const uint FLAG_GBUFFER_PASS = 64;
+const uint FLAG_LIGHTING_PASS = 128;
+const uint FLAG_UPSAMPLE_PASS = 256;
+const uint FLAG_TEMPORAL_UPSCALE = 512;
+const uint FLAG_DISTANCE_FIELD = 1024;
+const uint FLAG_FACE_SHADING = 2048;
+const uint FLAG_GRID_LINES = 4096;
+const uint FLAG_MOTION_VIEW = 8192;
+const uint FLAG_BAKED_LIGHTING = 16384;
+const uint FLAG_COLORED_SHADOWS = 32768;
+const uint FLAG_CHUNK_OVERLAY = 65536;
+const uint FLAG_STEP_LOG = 131072;
+const uint FLAG_AXIS_GIZMO = 262144;
+const uint FLAG_OUTPUTS = 524288;
+
+// Voxel type of portals, see simulation.rs.
+const uint PORTAL = 14;
+// Portals a single ray goes through at most, so facing portals can't trap it.
+const uint MAX_TELEPORTS = 4;
+
+const int WORLD_SIZE = 256;
+const int CHUNK_SIZE = 16;
+const int CHUNKS = WORLD_SIZE / CHUNK_SIZE;
+const uint CHUNK_VOXELS = uint(CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE);
+const int BRICK_SIZE = 4;
+const int BRICKS = CHUNK_SIZE / BRICK_SIZE;
+// Words before a chunk's palette holding its brick entries: 0 for an empty brick, the palette
+// index shifted up with the lowest bit set for a brick of a single voxel type, otherwise where
+// the brick's indices start shifted up.
+const uint BRICK_TABLE_WORDS = 32;
+// Bits of an offset into the packed chunks addressing a word within its page with CHUNK_PAGES
+// or CHUNK_ADDRESSES, and the most pages there are, see chunk_palette.rs.
+const uint PAGE_BITS = 20;
+const uint PAGE_WORDS = 1u << PAGE_BITS;
+const uint MAX_PAGES = 128;
+// Words of the light bake before its entries.
+const uint BAKE_HEADER_WORDS = uint(CHUNKS * CHUNKS * CHUNKS) + 1;
+const float MAX_BAKED_LIGHT = 4.0;
+// Materials at least this rough and rougher than the mirror like ones look their reflections up
+// in the nearest probe instead of tracing them, while there are probes.
+const float PROBE_MIN_ROUGHNESS = 0.1;
+const float PROBE_MAX_ROUGHNESS = 0.7;
+
+const float PI = 3.14159265;
+// Sun light reflected by the specular lobe of lit surfaces, see trace/brdf.glsl.
+const float SUN_SPECULAR = 1.0;
+// Narrowest lobe the sun's highlight is spread over.
+const float MIN_SPECULAR_ALPHA = 0.01;
+// Voxels the detail normal map repeats after.
+const float DETAIL_TILE = 4.0;
+
+// States of chunks in the chunk overlay. Culled chunks are resident but empty, so rays skip them
+// whole.
+const uint CHUNK_RESIDENT = 0;
+const uint CHUNK_STREAMING = 1;
+const uint CHUNK_DIRTY = 2;
+const uint CHUNK_CULLED = 3;
+const vec3 CHUNK_STATE_COLORS[4] = vec3[4](vec3(0.2, 0.9, 0.3), vec3(1.0, 0.8, 0.1), vec3(1.0, 0.2, 0.2), vec3(0.2, 0.4, 1.0));
+// Opacity per voxel of the tint chunks of every state but culled ones lay over what lies behind.
+const float CHUNK_OVERLAY_DENSITY = 0.01;
+// Length of the axis gizmo's arms as a fraction of the image height, and their width in pixels.
+const float GIZMO_RADIUS = 0.06;
+const float GIZMO_LINE_WIDTH = 1.5;
+const vec3 SKY_COLOR = vec3(0.1);
+const vec3 STEP_WARNING_COLOR = vec3(1.0, 0.0, 1.0);
+
+float sdSphere(vec3 p, float d) { return length(p) - d; }
 
 float sdBox( vec3 p, vec3 b )
 {
@@ -24,106 +356,715 @@ float sdBox( vec3 p, vec3 b )
   return min(max(d.x,max(d.y,d.z)),0.0) +
          length(max(d,0.0));
 }
-	
+
+
+uint worldWord(uint index) {
+#if defined(CHUNK_PAGES)
+    return pages[nonuniformEXT(index >> PAGE_BITS)].words[index & (PAGE_WORDS - 1u)];
+#elif defined(CHUNK_ADDRESSES)
+    Page page = Page(pageAddresses[min(index >> PAGE_BITS, MAX_PAGES - 1u)]);
+    return page.words[index & (PAGE_WORDS - 1u)];
+#else
+    return data[index];
+#endif
+}
+
+// Returns the table entry of `brick` in the chunk whose words are at `start`.
+uint brickEntry(uint start, ivec3 brick) {
+    int index = (brick.x * BRICKS + brick.y) * BRICKS + brick.z;
+    return (worldWord(start + uint(index / 2)) >> (index % 2 * 16)) & 0xffffu;
+}
 
 uint getVoxel(ivec3 c) {
-    if (
-        c.x <= 0 || c.x >= data.length() ||
-        c.y <= 0 || c.y >= data[0].length() ||
-        c.z <= 0 || c.z >= data[0][0].length()
-    ) {
-        return 0; 
+    if (any(lessThan(c, ivec3(0))) || any(greaterThanEqual(c, ivec3(WORLD_SIZE)))) {
+        return 0;
+    }
+    ivec3 chunk = c / CHUNK_SIZE;
+    uint header = worldWord(uint((chunk.x * CHUNKS + chunk.y) * CHUNKS + chunk.z));
+    uint bits = header & 31u;
+    if (bits == 0u) {
+        return header >> 5;
+    }
+    uint start = header >> 5;
+    ivec3 local = c % CHUNK_SIZE;
+    uint entry = brickEntry(start, local / BRICK_SIZE);
+    if (entry == 0u) {
+        return 0;
     }
-    return data[c.x][c.y][c.z];
+    // Bricks of a single voxel type need no indices.
+    if ((entry & 1u) != 0u) {
+        return worldWord(start + BRICK_TABLE_WORDS + (entry >> 1));
+    }
+    ivec3 inner = local % BRICK_SIZE;
+    uint index = uint((inner.x * BRICK_SIZE + inner.y) * BRICK_SIZE + inner.z);
+    uint perWord = 32u / bits;
+    uint word = worldWord(start + (entry >> 1) + index / perWord);
+    uint paletteIndex = (word >> (index % perWord * bits)) & ((1u << bits) - 1u);
+    return worldWord(start + BRICK_TABLE_WORDS + paletteIndex);
+}
+
+// Returns the edge length of the empty box `c`, which lies in the world, is known to be in: a
+// whole chunk holding nothing, one of the bricks its table marks empty, or 0 when neither.
+int emptyBox(ivec3 c) {
+    ivec3 chunk = c / CHUNK_SIZE;
+    uint header = worldWord(uint((chunk.x * CHUNKS + chunk.y) * CHUNKS + chunk.z));
+    if (header == 0u) {
+        return CHUNK_SIZE;
+    }
+    if ((header & 31u) == 0u) {
+        return 0;
+    }
+    return brickEntry(header >> 5, c % CHUNK_SIZE / BRICK_SIZE) == 0u ? BRICK_SIZE : 0;
 }
 vec2 rotate2d(vec2 v, float a) {
 	float sinA = sin(a);
 	float cosA = cos(a);
-	return vec2(v.x * cosA - v.y * sinA, v.y * cosA + v.x * sinA);	
+	return vec2(v.x * cosA - v.y * sinA, v.y * cosA + v.x * sinA);
 }
 
-void main() {
-	vec2 screenPos = (gl_GlobalInvocationID.xy / vec2(constants.resolution.x , constants.resolution.y)) * 2.0 - 1.0;
-	vec3 cameraPlaneU = vec3(1.0, 0.0, 0.0);
-	vec3 cameraPlaneV = vec3(0.0, 1.0, 0.0) * constants.resolution.y / constants.resolution.x;
-	vec3 rayDir = constants.camera_dir + screenPos.x * cameraPlaneU + screenPos.y * cameraPlaneV;
-	vec3 rayPos = constants.position;
-
-    rayPos.yz = rotate2d(rayPos.yz, constants.rotation.x);
-	rayDir.yz = rotate2d(rayDir.yz, constants.rotation.x);
-    rayPos.xz = rotate2d(rayPos.xz, constants.rotation.y);
-	rayDir.xz = rotate2d(rayDir.xz, constants.rotation.y);
-    rayPos.xy = rotate2d(rayPos.xy, constants.rotation.z);
-	rayDir.xy = rotate2d(rayDir.xy, constants.rotation.z);
-	
-	ivec3 mapPos = ivec3(floor(rayPos + 0.));
-
-	vec3 deltaDist = abs(vec3(length(rayDir)) / rayDir);
-	
-	ivec3 rayStep = ivec3(sign(rayDir));
-
-	vec3 sideDist = (sign(rayDir) * (vec3(mapPos) - rayPos) + (sign(rayDir) * 0.5) + 0.5) * deltaDist; 
-	
-	bvec3 mask = bvec3(false);
-
-    uint u_voxel = 0;
-	
-	for (int i = 0; i <= constants.render_distance; i++) {
-        uint voxel = getVoxel(mapPos);
-		if (voxel != 0) {
-            u_voxel = voxel;
+// Rotates `v` by `rotation`, around z, then x, then y, see `camera_to_world` in camera.rs.
+vec3 rotateToWorld(vec3 v, vec3 rotation) {
+    v.xy = rotate2d(v.xy, rotation.z);
+    v.yz = rotate2d(v.yz, rotation.x);
+    v.xz = rotate2d(v.xz, rotation.y);
+    return v;
+}
+
+// Undoes `rotateToWorld`.
+vec3 rotateFromWorld(vec3 v, vec3 rotation) {
+    v.xz = rotate2d(v.xz, -rotation.y);
+    v.yz = rotate2d(v.yz, -rotation.x);
+    v.xy = rotate2d(v.xy, -rotation.z);
+    return v;
+}
+
+uint hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352dU;
+    x ^= x >> 15;
+    x *= 0x846ca68bU;
+    x ^= x >> 16;
+    return x;
+}
+
+float random(inout uint state) {
+    state = hash(state);
+    return float(state) / 4294967295.0;
+}
+
+float luma(vec3 color) {
+    return dot(color, vec3(0.299, 0.587, 0.114));
+}
+
+// Returns whether voxels of type `voxel` are drawn, their material's layer not being hidden.
+bool voxelShown(uint voxel) {
+    uint layer = materials[min(voxel, uint(materials.length()) - 1)].layer.x;
+    return layer >= 32u || (layerMask.hidden & (1u << layer)) == 0u;
+}
+
+// Returns whether a clipping plane cuts away the point `p`.
+bool clipped(vec3 p) {
+    for (uint i = 0; i < clipPlanes.count; i++) {
+        if (dot(clipPlanes.planes[i].xyz, p) > clipPlanes.planes[i].w) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Returns whether the voxel of type `voxel` at `c` is drawn, neither hidden nor cut away.
+bool voxelDrawn(uint voxel, ivec3 c) {
+    return voxel != 0 && voxelShown(voxel) && !clipped(vec3(c) + 0.5);
+}
+
+#include "trace/traversal.glsl"
+#include "trace/objects.glsl"
+#include "trace/entities.glsl"
+
+Material voxelMaterial(uint voxel) {
+    return materials[min(voxel, uint(materials.length()) - 1)];
+}
+
+// Blends the decals covering the surface hit by `hit` over its `color`, later ones over earlier
+// ones, each fading out over the outer half of its radius.
+vec3 applyDecals(vec3 color, Hit hit) {
+    vec3 p = hit.origin + hit.direction * hit.dist;
+    for (uint i = 0; i < decals.count; i++) {
+        Decal decal = decals.decals[i];
+        float dist = distance(p, decal.center.xyz);
+        if (dist < decal.center.w) {
+            float fade = 1.0 - smoothstep(0.5, 1.0, dist / decal.center.w);
+            color = mix(color, decal.color.rgb, decal.color.a * fade);
+        }
+    }
+    return color;
+}
+
+// Returns whether `hit` lies on the cut surface the clipping planes left, a face whose voxel in
+// front was cut away.
+bool onCut(Hit hit) {
+    if (clipPlanes.count == 0 || hit.normal == ivec3(0)) {
+        return false;
+    }
+    ivec3 front = hit.pos + hit.normal;
+    uint voxel = getVoxel(front);
+    return voxel != 0 && voxelShown(voxel) && clipped(vec3(front) + 0.5);
+}
+
+vec3 voxelColor(Hit hit) {
+    if (hit.voxel == 0) {
+        return SKY_COLOR;
+    }
+    if (clipPlanes.capped != 0 && onCut(hit)) {
+        return clipPlanes.capColor.rgb;
+    }
+    vec3 color = applyDecals(voxelMaterial(hit.voxel).color.rgb, hit);
+    if ((constants.flags & FLAG_FACE_SHADING) == 0) {
+        return color;
+    }
+    // Every face direction gets its own shade so the grid stays readable without lighting, top
+    // faces brightest and bottom faces darkest.
+    float shade = 1.0;
+    if (hit.normal.x != 0) {
+        shade = hit.normal.x > 0 ? 0.6 : 0.5;
+    } else if (hit.normal.z != 0) {
+        shade = hit.normal.z > 0 ? 0.85 : 0.75;
+    } else if (hit.normal.y < 0) {
+        shade = 0.4;
+    }
+    return color * shade;
+}
+
+// Darkens `color` near the edges of the hit voxel's face, with lines getting wider in the
+// distance so they stay visible.
+vec3 applyGridLines(vec3 color, Hit hit) {
+    if ((constants.flags & FLAG_GRID_LINES) == 0 || hit.normal == ivec3(0)) {
+        return color;
+    }
+    vec3 onFace = hit.origin + hit.direction * hit.dist - vec3(hit.pos);
+    // Distance to the nearest edge along both axes lying in the face.
+    vec3 edge = min(onFace, 1.0 - onFace);
+    edge = mix(edge, vec3(1.0), notEqual(hit.normal, ivec3(0)));
+    float width = 0.03 + hit.dist * 0.001;
+    return min(edge.x, min(edge.y, edge.z)) < width ? color * 0.45 : color;
+}
+
+// Light emitted by voxels of type `voxel`, added on top of their lit color.
+vec3 voxelEmission(uint voxel) {
+    if (voxel == 0) {
+        return vec3(0.0);
+    }
+    Material material = voxelMaterial(voxel);
+    return material.color.rgb * material.color.a;
+}
+
+// Lighting terms compiled in, all of them without defines. `shader_build` compiles variants
+// without the disabled terms by defining LIGHTING_VARIANT along with the terms to keep.
+#ifndef LIGHTING_VARIANT
+#define AMBIENT_OCCLUSION
+#define SHADOWS
+#define GLOBAL_ILLUMINATION
+#endif
+
+#include "trace/brdf.glsl"
+#ifdef AMBIENT_OCCLUSION
+#include "trace/ambient_occlusion.glsl"
+#endif
+#ifdef SHADOWS
+#include "trace/shadows.glsl"
+#endif
+#ifdef GLOBAL_ILLUMINATION
+#include "trace/global_illumination.glsl"
+#include "trace/reflection_probes.glsl"
+#endif
+#include "trace/baked_lighting.glsl"
+
+// Returns the factor the color of a surface at `hitPos` is multiplied with for the enabled
+// lighting terms. `cell` is the empty voxel in front of the hit face, `viewDir` the direction it
+// was hit from and `roughness` how diffusely it scatters bounces. `sunVisibility` is how much of
+// the sun reaches it for its specular highlight, none when every lighting term is off.
+vec3 lighting(vec3 hitPos, ivec3 cell, ivec3 normal, vec3 viewDir, float roughness, uint flags, inout uint rng, out float sunVisibility) {
+    // Looked up instead of traced.
+    if ((flags & FLAG_BAKED_LIGHTING) != 0) {
+        vec3 baked = bakedLight(cell - normal, normal);
+        sunVisibility = clamp(luma(baked), 0.0, 1.0);
+        return baked;
+    }
+    sunVisibility = (flags & (FLAG_AMBIENT_OCCLUSION | FLAG_SHADOWS | FLAG_GLOBAL_ILLUMINATION)) != 0 ? 1.0 : 0.0;
+    vec3 light = vec3(1.0);
+#ifdef AMBIENT_OCCLUSION
+    if ((flags & FLAG_AMBIENT_OCCLUSION) != 0) {
+        light *= ambientOcclusion(cell, normal);
+    }
+#endif
+#ifdef SHADOWS
+    if ((flags & FLAG_SHADOWS) != 0) {
+        vec3 shadow = sunShadow(hitPos, flags);
+        light *= shadow;
+        // Shadows darken to half, the rest is the sun.
+        sunVisibility = luma(shadow) * 2.0 - 1.0;
+    }
+#endif
+#ifdef GLOBAL_ILLUMINATION
+    if ((flags & FLAG_GLOBAL_ILLUMINATION) != 0) {
+        if (probes.count > 0 && roughness >= PROBE_MIN_ROUGHNESS && roughness < PROBE_MAX_ROUGHNESS) {
+            light += 0.3 * light * probeReflection(hitPos, normal, viewDir, roughness, rng);
+        } else {
+            light += bounceLight(hitPos, normal, viewDir, roughness, 0.3 * light, rng);
+        }
+    }
+#endif
+    return light;
+}
+
+// Blends the preview box over `color` when the ray meets it no later than at `hitDist`.
+vec3 applyPreview(vec3 color, vec3 rayPos, vec3 rayDir, float hitDist) {
+    if (preview.min.w == 0) {
+        return color;
+    }
+    vec3 t0 = (vec3(preview.min.xyz) - rayPos) / rayDir;
+    vec3 t1 = (vec3(preview.max.xyz) - rayPos) / rayDir;
+    vec3 tMin = min(t0, t1);
+    vec3 tMax = max(t0, t1);
+    float enter = max(max(tMin.x, tMin.y), max(tMin.z, 0.0));
+    float exit = min(min(tMax.x, tMax.y), tMax.z);
+    if (enter > exit || enter > hitDist + 0.001) {
+        return color;
+    }
+    return mix(color, preview.color.rgb, preview.color.a);
+}
+
+// Returns the state of `chunk` in the chunk overlay.
+uint chunkState(ivec3 chunk) {
+    uint index = uint((chunk.x * CHUNKS + chunk.y) * CHUNKS + chunk.z);
+    uint state = chunkStates.states[index];
+    return state == CHUNK_RESIDENT && chunkDistances.distances[index] != 0 ? CHUNK_CULLED : state;
+}
+
+// Tints `color` by the states of the chunks the ray passes through before `hitDist`, walking the
+// chunk grid, and draws the edges of the chunks which aren't culled where it crosses them.
+vec3 applyChunkOverlay(vec3 color, vec3 rayPos, vec3 rayDir, float hitDist) {
+    if ((constants.flags & FLAG_CHUNK_OVERLAY) == 0) {
+        return color;
+    }
+    vec3 dir = mix(rayDir, vec3(1e-6), equal(rayDir, vec3(0.0)));
+    vec3 t0 = -rayPos / dir;
+    vec3 t1 = (vec3(WORLD_SIZE) - rayPos) / dir;
+    float enter = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), max(min(t0.z, t1.z), 0.0));
+    float leave = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), min(max(t0.z, t1.z), hitDist));
+    if (enter >= leave) {
+        return color;
+    }
+    ivec3 chunk = clamp(ivec3(floor((rayPos + dir * enter) / float(CHUNK_SIZE))), ivec3(0), ivec3(CHUNKS - 1));
+    ivec3 rayStep = ivec3(sign(dir));
+    vec3 next = (vec3((chunk + max(rayStep, ivec3(0))) * CHUNK_SIZE) - rayPos) / dir;
+    vec3 delta = abs(float(CHUNK_SIZE) / dir);
+    // Composited front to back, premultiplied.
+    vec3 tint = vec3(0.0);
+    float opacity = 0.0;
+    float t = enter;
+    uint state = chunkState(chunk);
+    for (int i = 0; i < CHUNKS * 3; i++) {
+        float exit = min(min(next.x, next.y), next.z);
+        float density = state == CHUNK_CULLED ? CHUNK_OVERLAY_DENSITY * 0.3 : CHUNK_OVERLAY_DENSITY;
+        float alpha = 1.0 - exp(-(min(exit, leave) - t) * density);
+        tint += (1.0 - opacity) * alpha * CHUNK_STATE_COLORS[state];
+        opacity += (1.0 - opacity) * alpha;
+        if (exit >= leave) {
             break;
         }
-        if (i == constants.render_distance) {
-            mask = bvec3(false);
+        bvec3 crossed = equal(next, vec3(exit));
+        chunk += ivec3(crossed) * rayStep;
+        if (any(lessThan(chunk, ivec3(0))) || any(greaterThanEqual(chunk, ivec3(CHUNKS)))) {
             break;
         }
-        if (sideDist.x < sideDist.y) {
-            if (sideDist.x < sideDist.z) {
-                sideDist.x += deltaDist.x;
-                mapPos.x += rayStep.x;
-                mask = bvec3(true, false, false);
-            }
-            else {
-                sideDist.z += deltaDist.z;
-                mapPos.z += rayStep.z;
-                mask = bvec3(false, false, true);
-            }
+        uint previous = state;
+        state = chunkState(chunk);
+        // Distance of the crossing to the nearest edge of the face crossed.
+        vec3 local = mod(rayPos + dir * exit, float(CHUNK_SIZE));
+        vec3 edge = mix(min(local, float(CHUNK_SIZE) - local), vec3(1e30), crossed);
+        float width = 0.05 + exit * 0.0015;
+        if (min(edge.x, min(edge.y, edge.z)) < width && (previous != CHUNK_CULLED || state != CHUNK_CULLED)) {
+            return tint + (1.0 - opacity) * CHUNK_STATE_COLORS[state == CHUNK_CULLED ? previous : state];
+        }
+        next += delta * vec3(crossed);
+        t = exit;
+    }
+    return tint + (1.0 - opacity) * color;
+}
+
+// Rotates `v` from camera into world space, see `camera_to_world` in camera.rs.
+vec3 cameraToWorld(vec3 v) {
+    return rotateToWorld(v, constants.rotation);
+}
+
+// Rotates `v` from world space into that of a camera rotated by `rotation`.
+vec3 worldToCamera(vec3 v, vec3 rotation) {
+    return rotateFromWorld(v, rotation);
+}
+
+// Returns the primary ray through `screenPos` (-1 to 1 on both axes).
+void cameraRay(vec2 screenPos, out vec3 rayPos, out vec3 rayDir) {
+	vec3 cameraPlaneU = vec3(1.0, 0.0, 0.0);
+	vec3 cameraPlaneV = vec3(0.0, 1.0, 0.0) * constants.resolution.y / constants.resolution.x;
+	rayDir = normalize(cameraToWorld(constants.camera_dir + screenPos.x * cameraPlaneU + screenPos.y * cameraPlaneV));
+	rayPos = cameraToWorld(constants.position);
+}
+
+// Moves a primary ray from the eye to a random point on the lens, aimed at where it met the plane
+// in focus, so only what lies on that plane stays sharp once samples are averaged.
+void lensRay(inout vec3 rayPos, inout vec3 rayDir, inout uint rng) {
+    if (lens.aperture <= 0.0) {
+        return;
+    }
+    vec3 forward = normalize(cameraToWorld(constants.camera_dir));
+    vec3 focus = rayPos + rayDir * lens.focus_distance / dot(rayDir, forward);
+    float radius = lens.aperture * sqrt(random(rng));
+    float angle = 2.0 * PI * random(rng);
+    rayPos += cameraToWorld(vec3(cos(angle), sin(angle), 0.0) * radius);
+    rayDir = normalize(focus - rayPos);
+}
+
+// Returns where the point `p` appeared on screen (-1 to 1 on both axes) for the previous frame's
+// camera, or where the direction `p` pointed to when `direction` is set.
+vec2 previousScreenPos(vec3 p, bool direction) {
+    p = worldToCamera(p, constants.previous_rotation);
+    if (!direction) {
+        p -= constants.previous_position;
+    }
+    p *= constants.camera_dir.z / p.z;
+    vec2 screenPos = p.xy - constants.camera_dir.xy;
+    screenPos.y *= float(constants.resolution.x) / float(constants.resolution.y);
+    return screenPos;
+}
+
+// Draws the axis gizmo over `pixel` when it lies in the bottom right corner of the screen: the
+// world's x, y and z axes in red, green and blue as the camera sees them, dimmed where they point
+// away from it, around a white dot for the camera's forward, which points into the screen.
+vec3 applyAxisGizmo(ivec2 pixel, vec3 color) {
+    if ((constants.flags & FLAG_AXIS_GIZMO) == 0) {
+        return color;
+    }
+    float radius = GIZMO_RADIUS * float(constants.resolution.y);
+    // Image rows are shown bottom up, like the camera's y axis.
+    vec2 center = vec2(float(constants.resolution.x) - radius * 1.5, radius * 1.5);
+    vec2 p = vec2(pixel) + 0.5 - center;
+    if (length(p) > radius * 1.2) {
+        return color;
+    }
+    color *= 0.5;
+    // The arm closest to the camera covers the others.
+    float nearest = 2.0;
+    for (int axis = 0; axis < 3; axis++) {
+        vec3 v = vec3(0.0);
+        v[axis] = 1.0;
+        v = worldToCamera(v, constants.rotation);
+        vec2 arm = v.xy * radius;
+        float along = clamp(dot(p, arm) / max(dot(arm, arm), 1e-6), 0.0, 1.0);
+        if (length(p - arm * along) < GIZMO_LINE_WIDTH && v.z < nearest) {
+            nearest = v.z;
+            vec3 axisColor = vec3(0.0);
+            axisColor[axis] = 1.0;
+            color = v.z > 0.0 ? axisColor * 0.5 : axisColor;
+        }
+    }
+    if (length(p) < GIZMO_LINE_WIDTH * 2.0) {
+        color = vec3(1.0);
+    }
+    return color;
+}
+
+// Stores the motion of `pixel`, seen at `screenPos`, for a primary ray hitting `p` at distance
+// `dist`. Written every frame so later passes can rely on it, but not by the adaptive passes which
+// trace pixels again.
+void writeMotion(ivec2 pixel, vec2 screenPos, vec3 p, bool direction, float dist) {
+    if ((constants.flags & FLAG_WORK_QUEUE) != 0) {
+        return;
+    }
+    vec2 offset = (screenPos - previousScreenPos(p, direction)) * 0.5;
+    imageStore(motion, pixel, vec4(offset, dist, 0.0));
+}
+
+// Stores the outputs of `pixel` for a primary ray hitting `voxel` at distance `dist` on a face
+// looking along `normal`, `voxel` being 0 for sky. Skipped by the adaptive passes like the motion.
+void writeOutputs(ivec2 pixel, float dist, ivec3 normal, uint voxel) {
+    if ((constants.flags & FLAG_OUTPUTS) == 0 || (constants.flags & FLAG_WORK_QUEUE) != 0) {
+        return;
+    }
+    imageStore(depthOutput, pixel, vec4(voxel == 0 ? uintBitsToFloat(0x7f800000u) : dist));
+    imageStore(normalOutput, pixel, vec4(vec3(normal), 0.0));
+    imageStore(materialOutput, pixel, uvec4(voxel));
+}
+
+// Element `index` of the Halton sequence with the given `base`.
+float halton(uint index, uint base) {
+    float result = 0.0;
+    float fraction = 1.0;
+    while (index > 0) {
+        fraction /= float(base);
+        result += fraction * float(index % base);
+        index /= base;
+    }
+    return result;
+}
+
+// Screen tiles match the workgroup size.
+uint tileCount() {
+    uvec2 tiles = (constants.resolution + gl_WorkGroupSize.xy - 1) / gl_WorkGroupSize.xy;
+    return tiles.x * tiles.y;
+}
+
+shared vec3 probeDirs[5];
+shared bool probeHits[5];
+
+// Sorts the tile of this workgroup into a bin. Five probe rays through the corners and center
+// bound the tile's view cone: tiles whose cone misses the world's bounding sphere only see sky,
+// tiles whose probes all miss are silhouettes that skip secondary rays, the rest is traced fully.
+void classifyTile() {
+    uvec2 tile = gl_WorkGroupID.xy;
+    uint probe = gl_LocalInvocationIndex;
+    vec3 rayPos;
+    if (probe < 5) {
+        vec2 offset = probe == 4 ? vec2(0.5) : vec2(probe & 1, probe >> 1);
+        vec2 corner = min((vec2(tile) + offset) * vec2(gl_WorkGroupSize.xy), vec2(constants.resolution));
+        vec3 rayDir;
+        cameraRay(corner / vec2(constants.resolution) * 2.0 - 1.0, rayPos, rayDir);
+        probeDirs[probe] = rayDir;
+        probeHits[probe] = traceCamera(rayPos, rayDir, constants.max_ray_steps, float(constants.render_distance)).voxel != 0;
+    }
+    memoryBarrierShared();
+    barrier();
+    if (probe != 0) {
+        return;
+    }
+
+    vec3 worldSize = vec3(WORLD_SIZE);
+    vec3 toWorld = worldSize * 0.5 - rayPos;
+    float worldDist = length(toWorld);
+    float worldRadius = length(worldSize) * 0.5;
+    bool sky = false;
+    if (worldDist > worldRadius) {
+        float coneCos = 1.0;
+        for (int i = 0; i < 4; i++) {
+            coneCos = min(coneCos, dot(probeDirs[4], probeDirs[i]));
+        }
+        float coneAngle = acos(clamp(coneCos, -1.0, 1.0));
+        float worldAngle = asin(worldRadius / worldDist);
+        float angle = acos(clamp(dot(probeDirs[4], toWorld / worldDist), -1.0, 1.0));
+        sky = angle > coneAngle + worldAngle || worldDist - worldRadius > float(constants.render_distance);
+    }
+    bool anyHit = probeHits[0] || probeHits[1] || probeHits[2] || probeHits[3] || probeHits[4];
+    uint bin = sky ? TILE_CLASS_SKY : (anyHit ? TILE_CLASS_COMPLEX : TILE_CLASS_SIMPLE);
+
+    uint index = atomicAdd(bins.counts[bin - 1], 1);
+    bins.tiles[(bin - 1) * tileCount() + index] = tile.x | (tile.y << 16);
+    atomicMax(binArgs.args[(bin - 1) * 3], index + 1);
+}
+
+// Computes the lighting factor of one half resolution pixel from the G-buffer texel it covers.
+void lightingPass() {
+    uvec2 halfResolution = (constants.resolution + 1) / 2;
+    if (any(greaterThanEqual(gl_GlobalInvocationID.xy, halfResolution))) {
+        return;
+    }
+    ivec2 halfPixel = ivec2(gl_GlobalInvocationID.xy);
+    ivec2 pixel = min(halfPixel * 2, ivec2(constants.resolution) - 1);
+    vec4 surface = imageLoad(gbuffer, pixel);
+    if (surface.w < 0.0) {
+        imageStore(lightingImage, halfPixel, vec4(1.0));
+        return;
+    }
+
+    uint rng = hash(halfPixel.x + halfPixel.y * halfResolution.x) ^ hash(constants.frame * 2);
+    vec3 rayPos;
+    vec3 rayDir;
+    cameraRay((vec2(pixel) / vec2(constants.resolution)) * 2.0 - 1.0, rayPos, rayDir);
+    ivec3 normal = ivec3(round(surface.xyz));
+    // The G-buffer doesn't keep where rays came out of portals, so surfaces seen through one are
+    // lit as if they were on this side of it.
+    vec3 hitPos = rayPos + rayDir * surface.w + vec3(normal) * 0.001;
+    ivec3 cell = ivec3(floor(hitPos + vec3(normal) * 0.5));
+    float roughness = voxelMaterial(uint(imageLoad(albedo, pixel).a)).params.x;
+    float sunVisibility;
+    vec3 light = lighting(hitPos, cell, normal, rayDir, roughness, constants.flags, rng, sunVisibility);
+    imageStore(lightingImage, halfPixel, vec4(light, sunVisibility));
+}
+
+// Writes the sample for `pixel`, averaging it with the previous ones when accumulating.
+// `covered` is 1 when the sample hit a voxel and 0 when it shows sky.
+void writeColor(ivec2 pixel, vec3 color, float covered) {
+    if ((constants.flags & FLAG_MOTION_VIEW) != 0) {
+        // Motion of a tenth of the screen saturates.
+        color = vec3(clamp(0.5 + imageLoad(motion, pixel).xy * 5.0, 0.0, 1.0), 0.5);
+    }
+    if ((constants.flags & FLAG_ACCUMULATE) != 0) {
+        bool restart = constants.sample_index == 0 && (constants.flags & FLAG_WORK_QUEUE) == 0;
+        vec4 mean = restart ? vec4(0.0) : imageLoad(accumulation, pixel);
+        float moment = restart ? 0.0 : imageLoad(moments, pixel).r;
+        float cover = restart ? 0.0 : imageLoad(coverage, pixel).r;
+        // Running means instead of sums so half floats don't run out of range.
+        mean.a += 1.0;
+        mean.rgb += (color - mean.rgb) / mean.a;
+        moment += (luma(color) * luma(color) - moment) / mean.a;
+        cover += (covered - cover) / mean.a;
+        imageStore(accumulation, pixel, mean);
+        imageStore(moments, pixel, vec4(moment));
+        imageStore(coverage, pixel, vec4(cover));
+        color = mean.rgb;
+    }
+    imageStore(img, pixel, vec4(applyAxisGizmo(pixel, color), 1.0));
+}
+
+// Joins the full resolution albedo with the half resolution lighting, weighting the four nearest
+// lighting texels by how well their depth and normal match this pixel's.
+void upsamplePass(ivec2 pixel) {
+    vec4 surfaceAlbedo = imageLoad(albedo, pixel);
+    vec3 color = surfaceAlbedo.rgb;
+    vec4 surface = imageLoad(gbuffer, pixel);
+    vec3 rayPos;
+    vec3 rayDir;
+    cameraRay((vec2(pixel) / vec2(constants.resolution)) * 2.0 - 1.0, rayPos, rayDir);
+    if (surface.w < 0.0) {
+        writeColor(pixel, applyChunkOverlay(color, rayPos, rayDir, float(constants.render_distance)), 0.0);
+        return;
+    }
+
+    ivec2 halfMax = ivec2((constants.resolution + 1) / 2) - 1;
+    vec2 halfPos = (vec2(pixel) + 0.5) * 0.5 - 0.5;
+    ivec2 base = ivec2(floor(halfPos));
+    vec2 f = fract(halfPos);
+    vec4 light = vec4(0.0);
+    float totalWeight = 0.0;
+    for (int y = 0; y <= 1; y++) {
+        for (int x = 0; x <= 1; x++) {
+            ivec2 halfPixel = clamp(base + ivec2(x, y), ivec2(0), halfMax);
+            vec4 neighbour = imageLoad(gbuffer, min(halfPixel * 2, ivec2(constants.resolution) - 1));
+            float bilinear = (x == 0 ? 1.0 - f.x : f.x) * (y == 0 ? 1.0 - f.y : f.y);
+            float depthWeight = neighbour.w < 0.0 ? 0.0 : exp(-abs(neighbour.w - surface.w) * 4.0 / max(surface.w, 1.0));
+            float normalWeight = pow(max(dot(neighbour.xyz, surface.xyz), 0.0), 8.0);
+            float weight = bilinear * depthWeight * normalWeight + 1e-4;
+            light += imageLoad(lightingImage, halfPixel) * weight;
+            totalWeight += weight;
         }
-        else {
-            if (sideDist.y < sideDist.z) {
-                sideDist.y += deltaDist.y;
-                mapPos.y += rayStep.y;
-                mask = bvec3(false, true, false);
-            }
-            else {
-                sideDist.z += deltaDist.z;
-                mapPos.z += rayStep.z;
-                mask = bvec3(false, false, true);
-            }
+    }
+    light /= totalWeight;
+    if (pixel == pick.pixel) {
+        pick.light = vec4(light.rgb, 1.0);
+    }
+    uint voxel = uint(surfaceAlbedo.a);
+    vec3 hitPos = rayPos + rayDir * surface.w;
+    color = shade(color, light.rgb, light.a, voxel, hitPos, ivec3(round(surface.xyz)), rayDir);
+    writeColor(pixel, applyChunkOverlay(color + voxelEmission(voxel), rayPos, rayDir, surface.w), 1.0);
+}
+
+void main() {
+    if (TILE_CLASS == TILE_CLASS_CLASSIFY) {
+        classifyTile();
+        return;
+    }
+    if ((constants.flags & FLAG_LIGHTING_PASS) != 0) {
+        lightingPass();
+        return;
+    }
+
+    ivec2 pixel;
+    if ((constants.flags & FLAG_WORK_QUEUE) != 0) {
+        uint index = gl_WorkGroupID.x * gl_WorkGroupSize.x * gl_WorkGroupSize.y + gl_LocalInvocationIndex;
+        if (index >= queue.count) {
+            return;
+        }
+        pixel = ivec2(queue.pixels[index] & 0xffffu, queue.pixels[index] >> 16);
+    } else if (TILE_CLASS != TILE_CLASS_ALL) {
+        uint tile = bins.tiles[(TILE_CLASS - 1) * tileCount() + gl_WorkGroupID.x];
+        pixel = ivec2(tile & 0xffffu, tile >> 16) * ivec2(gl_WorkGroupSize.xy) + ivec2(gl_LocalInvocationID.xy);
+        if (any(greaterThanEqual(uvec2(pixel), constants.resolution))) {
+            return;
+        }
+    } else {
+        if (any(greaterThanEqual(gl_GlobalInvocationID.xy, constants.resolution))) {
+            return;
+        }
+        pixel = ivec2(gl_GlobalInvocationID.xy);
+    }
+    if ((constants.flags & FLAG_UPSAMPLE_PASS) != 0) {
+        upsamplePass(pixel);
+        return;
+    }
+    // Silhouette tiles are mostly sky, secondary rays aren't worth it there.
+    uint flags = constants.flags;
+    if (TILE_CLASS == TILE_CLASS_SIMPLE) {
+        flags &= ~(FLAG_SHADOWS | FLAG_GLOBAL_ILLUMINATION);
+    }
+    uint rng = hash(pixel.x + pixel.y * constants.resolution.x) ^ hash(constants.frame * 2 + ((constants.flags & FLAG_WORK_QUEUE) != 0 ? 1 : 0));
+
+    // Jitter inside the pixel while accumulating so the average is anti-aliased too. The temporal
+    // upscaler needs a jitter it can reproduce, so it gets a Halton sequence instead.
+    vec2 jitter = vec2(0.0);
+    if ((constants.flags & FLAG_ACCUMULATE) != 0) {
+        jitter = vec2(random(rng), random(rng));
+    } else if ((constants.flags & FLAG_TEMPORAL_UPSCALE) != 0) {
+        jitter = vec2(halton(constants.frame % 8 + 1, 2), halton(constants.frame % 8 + 1, 3));
+    }
+	vec2 screenPos = ((vec2(pixel) + jitter) / vec2(constants.resolution.x , constants.resolution.y)) * 2.0 - 1.0;
+    vec3 rayPos;
+    vec3 rayDir;
+    cameraRay(screenPos, rayPos, rayDir);
+    lensRay(rayPos, rayDir, rng);
+    if (TILE_CLASS == TILE_CLASS_SKY) {
+        writeMotion(pixel, screenPos, rayDir, true, -1.0);
+        writeOutputs(pixel, -1.0, ivec3(0), 0u);
+        writeColor(pixel, applyChunkOverlay(SKY_COLOR, rayPos, rayDir, float(constants.render_distance)), 0.0);
+        return;
+    }
+
+    Hit hit = traceCamera(rayPos, rayDir, constants.max_ray_steps, float(constants.render_distance));
+    if ((constants.flags & FLAG_STEP_LOG) != 0) {
+        atomicAdd(pick.rays, 1);
+        if (hit.truncated) {
+            atomicAdd(pick.truncatedRays, 1);
+        }
+    }
+    if (hit.voxel == 0) {
+        writeMotion(pixel, screenPos, rayDir, true, -1.0);
+        writeOutputs(pixel, -1.0, ivec3(0), 0u);
+        vec3 color = SKY_COLOR;
+        if (hit.truncated && (flags & FLAG_STEP_WARNING) != 0) {
+            color = mix(color, STEP_WARNING_COLOR, 0.5);
         }
-	}
-	
-	vec3 color = vec3(0.1);
-    if (mask.x) {
-		color = vec3(0.25);
-	}
-    if (mask.y) {
-		color = vec3(0.75);
-	}
-    if (mask.z) {
-		color = vec3(0.5);
-	}
-    switch(u_voxel) {
-        case 1: color.r += 0.25; break;
-        case 2: color.g += 0.25; break;
-        case 3: color.b += 0.25; break;
-        case 4: color *= vec3(0.3, 0.4, 0.5); break;
-        case 5: color *= vec3(0.6, 0.3, 0.9); break;
-        case 6: color *= vec3(0.1, 0.4, 0.6); break;
-        case 7: color *= vec3(0.8, 0.3, 0.6); break;
-        case 8: color *= vec3(0.2, 0.9, 0.4); break;
-        case 9: color *= vec3(0.1, 0.5, 0.8); break;
-    }
-    imageStore(img, ivec2(gl_GlobalInvocationID.xy), vec4(color, 1.0));
+        color = applyPreview(color, rayPos, rayDir, float(constants.render_distance));
+        if ((flags & FLAG_GBUFFER_PASS) != 0) {
+            imageStore(albedo, pixel, vec4(color, 0.0));
+            imageStore(gbuffer, pixel, vec4(0.0, 0.0, 0.0, -1.0));
+        } else {
+            writeColor(pixel, applyChunkOverlay(color, rayPos, rayDir, float(constants.render_distance)), 0.0);
+        }
+        return;
+    }
+
+    writeMotion(pixel, screenPos, rayPos + rayDir * hit.dist, false, hit.dist);
+    writeOutputs(pixel, hit.dist, hit.normal, hit.voxel);
+    vec3 color = applyGridLines(voxelColor(hit), hit);
+    ivec3 normal = hit.normal;
+    if (pixel == pick.pixel && (constants.flags & FLAG_WORK_QUEUE) == 0) {
+        pick.voxel = hit.voxel;
+        pick.distance = hit.dist;
+        pick.position = ivec4(hit.pos, 0);
+        pick.normal = ivec4(normal, 0);
+    }
+    if ((flags & FLAG_GBUFFER_PASS) != 0) {
+        color = applyPreview(color, rayPos, rayDir, hit.dist);
+        imageStore(albedo, pixel, vec4(color, float(hit.voxel)));
+        imageStore(gbuffer, pixel, vec4(vec3(normal), hit.dist));
+        return;
+    }
+    vec3 hitPos = hit.origin + hit.direction * hit.dist + vec3(normal) * 0.001;
+    float roughness = voxelMaterial(hit.voxel).params.x;
+    float sunVisibility;
+    vec3 light = lighting(hitPos, hit.pos + normal, normal, hit.direction, roughness, flags, rng, sunVisibility);
+    if (pixel == pick.pixel && (constants.flags & FLAG_WORK_QUEUE) == 0) {
+        pick.light = vec4(light, 1.0);
+    }
+    color = shade(color, light, sunVisibility, hit.voxel, hitPos, normal, hit.direction);
+    color = applyPreview(color + voxelEmission(hit.voxel), rayPos, rayDir, hit.dist);
+    writeColor(pixel, applyChunkOverlay(color, rayPos, rayDir, hit.dist), 1.0);
 }
@@ -0,0 +1,103 @@
+#version 450
+
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
+
+// Voxel objects as the tracer reads them, see `Objects` in compute.glsl.
+struct Object {
+    vec4 rotation;
+    vec4 translation;
+    uvec4 size;
+};
+layout(set = 0, binding = 0) readonly buffer Objects {
+    uint count;
+    Object objects[];
+} objects;
+
+// Indices of the objects camera rays can meet, those in view and within the cull distance,
+// followed from `indices[objects.count]` on by those other rays can meet, within the cull
+// distance. The counts start at 0.
+layout(set = 0, binding = 1) buffer CulledObjects {
+    uint viewCount;
+    uint rangeCount;
+    uint indices[];
+} culled;
+
+layout(push_constant) uniform PushConstants {
+    vec3 camera_dir;
+    // Height of the view over its width.
+    float aspect;
+    vec3 rotation;
+    // Objects farther from the eye than this are culled.
+    float cull_distance;
+    vec3 position;
+    // Distance objects may lie outside the view and still count as in it, covering the lens and
+    // the jitter of camera rays.
+    float margin;
+    // Objects whose center lies farther from the eye than this are drawn as impostors by the
+    // graphics pass instead, 0 when there are none.
+    float impostor_distance;
+    // Objects on the layers whose bit is set are left out, see `LayerMask` in compute.glsl.
+    uint hidden_layers;
+} constants;
+
+vec2 rotate2d(vec2 v, float a) {
+	float sinA = sin(a);
+	float cosA = cos(a);
+	return vec2(v.x * cosA - v.y * sinA, v.y * cosA + v.x * sinA);
+}
+
+// Rotates `v` by `rotation`, see `rotateToWorld` in compute.glsl.
+vec3 rotateToWorld(vec3 v, vec3 rotation) {
+    v.xy = rotate2d(v.xy, rotation.z);
+    v.yz = rotate2d(v.yz, rotation.x);
+    v.xz = rotate2d(v.xz, rotation.y);
+    return v;
+}
+
+// Undoes `rotateToWorld`.
+vec3 rotateFromWorld(vec3 v, vec3 rotation) {
+    v.xz = rotate2d(v.xz, -rotation.y);
+    v.yz = rotate2d(v.yz, -rotation.x);
+    v.xy = rotate2d(v.xy, -rotation.z);
+    return v;
+}
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index >= objects.count) {
+        return;
+    }
+    Object object = objects.objects[index];
+    uint layer = floatBitsToUint(object.translation.w);
+    if (layer < 32u && (constants.hidden_layers & (1u << layer)) != 0u) {
+        return;
+    }
+    // Bounding sphere of the object, whichever way it is turned.
+    float radius = length(vec3(object.size.xyz)) * 0.5 * object.rotation.w;
+    vec3 eye = rotateToWorld(constants.position, constants.rotation);
+    vec3 center = object.translation.xyz - eye;
+    if (length(center) - radius > constants.cull_distance) {
+        return;
+    }
+    if (constants.impostor_distance > 0.0 && length(center) > constants.impostor_distance) {
+        return;
+    }
+    culled.indices[objects.count + atomicAdd(culled.rangeCount, 1)] = index;
+
+    // Camera rays run along `camera_dir + (x, y * aspect, 0)` for x and y from -1 to 1, so the
+    // sides of the view are the planes through the eye with these outward normals.
+    vec3 c = rotateFromWorld(center, constants.rotation);
+    float d = constants.camera_dir.z;
+    vec3 sides[4] = vec3[](
+        normalize(vec3(d, 0.0, -1.0)),
+        normalize(vec3(-d, 0.0, -1.0)),
+        normalize(vec3(0.0, d, -constants.aspect)),
+        normalize(vec3(0.0, -d, -constants.aspect))
+    );
+    for (int i = 0; i < 4; i++) {
+        if (dot(sides[i], c) > radius + constants.margin) {
+            return;
+        }
+    }
+    culled.indices[atomicAdd(culled.viewCount, 1)] = index;
+}
@@ -0,0 +1,157 @@
+#version 450
+
+layout(local_size_x = 8, local_size_y = 8, local_size_z = 8) in;
+
+// Cells of the chunks of a `LiquidBatch`, CHUNK_CELLS per chunk indexed by
+// (x * CHUNK_SIZE + y) * CHUNK_SIZE + z: the voxel type in the lowest byte and the level of
+// flowing liquid in the next one, 0 for voxels which don't flow. A tick reads one buffer and
+// writes the next state into the other, then the next tick swaps them.
+layout(set = 0, binding = 0) readonly buffer Front {
+    uint cells[];
+} front;
+
+layout(set = 0, binding = 1) writeonly buffer Back {
+    uint cells[];
+} back;
+
+// For every simulated chunk the chunk of the batch holding each chunk of the 3x3x3 block around
+// it, indexed by (x * 3 + y) * 3 + z, or -1 outside the world.
+layout(set = 0, binding = 2) readonly buffer Neighbors {
+    int chunks[];
+} neighbors;
+
+layout(push_constant) uniform PushConstants {
+    // Ticks since the simulation started, lava only flows on multiples of LAVA_TICKS.
+    uint tick;
+    // Whether sand and gravel fall.
+    uint falling_blocks;
+} constants;
+
+const int CHUNK_SIZE = 16;
+const uint CHUNK_CELLS = 4096;
+
+// Voxel types with rules of their own, as in simulation.rs.
+const uint STONE = 4;
+const uint WATER = 10;
+const uint LAVA = 11;
+const uint SAND = 12;
+const uint GRAVEL = 13;
+// How far liquids spread sideways from what feeds them.
+const uint WATER_SPREAD = 7;
+const uint LAVA_SPREAD = 3;
+const uint LAVA_TICKS = 3;
+
+const ivec3 UP = ivec3(0, 1, 0);
+const ivec3 HORIZONTAL[4] = ivec3[](ivec3(1, 0, 0), ivec3(-1, 0, 0), ivec3(0, 0, 1), ivec3(0, 0, -1));
+const ivec3 NEIGHBORS[6] = ivec3[](
+    ivec3(1, 0, 0), ivec3(-1, 0, 0), ivec3(0, 1, 0), ivec3(0, -1, 0), ivec3(0, 0, 1), ivec3(0, 0, -1)
+);
+
+uint chunk;
+
+// Returns the cell at `position` relative to the chunk's corner, at most a voxel outside of it.
+// Voxels outside the world are empty.
+uint cell(ivec3 position) {
+    ivec3 offset = (position + CHUNK_SIZE) / CHUNK_SIZE - 1;
+    int neighbor = neighbors.chunks[chunk * 27 + (offset.x + 1) * 9 + (offset.y + 1) * 3 + offset.z + 1];
+    if (neighbor < 0) {
+        return 0;
+    }
+    ivec3 local = position - offset * CHUNK_SIZE;
+    return front.cells[uint(neighbor) * CHUNK_CELLS + uint((local.x * CHUNK_SIZE + local.y) * CHUNK_SIZE + local.z)];
+}
+
+uint voxelAt(ivec3 position) {
+    return cell(position) & 0xff;
+}
+
+uint levelAt(ivec3 position) {
+    return (cell(position) >> 8) & 0xff;
+}
+
+bool inWorld(ivec3 position) {
+    ivec3 offset = (position + CHUNK_SIZE) / CHUNK_SIZE - 1;
+    return neighbors.chunks[chunk * 27 + (offset.x + 1) * 9 + (offset.y + 1) * 3 + offset.z + 1] >= 0;
+}
+
+bool falls(uint voxel) {
+    return voxel == SAND || voxel == GRAVEL;
+}
+
+// Returns the level `liquid` at `position` would have if anything feeds it, 0 if nothing does:
+// liquid above it or liquid spreading sideways from a neighbor resting on something else.
+uint feed(ivec3 position, uint liquid) {
+    if (voxelAt(position + UP) == liquid) {
+        return 1;
+    }
+    uint spread = liquid == LAVA ? LAVA_SPREAD : WATER_SPREAD;
+    uint fed = 0;
+    for (int i = 0; i < 4; i++) {
+        ivec3 neighbor = position + HORIZONTAL[i];
+        uint below = voxelAt(neighbor - UP);
+        if (voxelAt(neighbor) != liquid || below == 0 || below == liquid) {
+            continue;
+        }
+        uint level = levelAt(neighbor);
+        if (level < spread && (fed == 0 || level + 1 < fed)) {
+            fed = level + 1;
+        }
+    }
+    return fed;
+}
+
+// Returns the cell at `position` after this tick.
+uint nextState(ivec3 position, bool lavaTick) {
+    uint voxel = voxelAt(position);
+    uint level = levelAt(position);
+    if (constants.falling_blocks != 0) {
+        if (falls(voxel) && inWorld(position - UP) && voxelAt(position - UP) == 0) {
+            return 0;
+        }
+        uint above = voxelAt(position + UP);
+        if (voxel == 0 && falls(above)) {
+            return above;
+        }
+    }
+    if (voxel == WATER || voxel == LAVA) {
+        if (voxel == LAVA) {
+            for (int i = 0; i < 6; i++) {
+                if (voxelAt(position + NEIGHBORS[i]) == WATER) {
+                    return STONE;
+                }
+            }
+        }
+        // Sources stay, and so does lava waiting for its tick.
+        if (level == 0 || (voxel == LAVA && !lavaTick)) {
+            return voxel | level << 8;
+        }
+        uint fed = feed(position, voxel);
+        return fed == 0 ? 0 : voxel | fed << 8;
+    }
+    if (voxel == 0) {
+        uint fed = feed(position, WATER);
+        if (fed != 0) {
+            return WATER | fed << 8;
+        }
+        if (lavaTick) {
+            fed = feed(position, LAVA);
+            if (fed != 0) {
+                return LAVA | fed << 8;
+            }
+        }
+    }
+    return voxel;
+}
+
+void main() {
+    // Two workgroups along x per chunk.
+    chunk = gl_WorkGroupID.x / 2;
+    ivec3 position = ivec3(
+        (gl_WorkGroupID.x % 2) * gl_WorkGroupSize.x + gl_LocalInvocationID.x,
+        gl_GlobalInvocationID.y,
+        gl_GlobalInvocationID.z
+    );
+    bool lavaTick = constants.tick % LAVA_TICKS == 0;
+    uint index = chunk * CHUNK_CELLS + uint((position.x * CHUNK_SIZE + position.y) * CHUNK_SIZE + position.z);
+    back.cells[index] = nextState(position, lavaTick);
+}
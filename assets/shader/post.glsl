@@ -0,0 +1,80 @@
+#version 450
+
+// Header of user post-process shaders, compiled at runtime with the user's file appended, see
+// `post_process::compile`. The user's file defines `vec4 postProcess(ivec2 pixel)` returning the
+// final color of `pixel` and may use everything declared here.
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+// The finished frame before post-processing.
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D colorImage;
+
+// Motion in xy and distance to the primary hit in z, -1 for sky, of every traced pixel. At the
+// trace resolution, which is lower than the frame's while the temporal upscaler runs.
+layout(set = 0, binding = 1, rgba16f) uniform readonly image2D motionImage;
+
+layout(set = 0, binding = 2, rgba8) uniform writeonly image2D img;
+
+// Voxel type of every traced pixel, 0 for sky, see `material`. The trace pass only writes it
+// while a post-process shader reads it.
+layout(set = 0, binding = 3, r32ui) uniform readonly uimage2D materialImage;
+
+// Distance from the eye to the face hit, infinite for sky, and the face's normal, zero for sky, of
+// every traced pixel. Like `materialImage` only written while a post-process shader reads them.
+layout(set = 0, binding = 4, r32f) uniform readonly image2D depthImage;
+layout(set = 0, binding = 5, rgba16f) uniform readonly image2D normalImage;
+
+layout(push_constant) uniform PushConstants {
+    uvec2 resolution;
+    uvec2 trace_resolution;
+    // Seconds since the renderer started.
+    float time;
+    uint frame;
+} constants;
+
+// Color of `pixel` before post-processing, clamped to the frame.
+vec3 color(ivec2 pixel) {
+    return imageLoad(colorImage, clamp(pixel, ivec2(0), ivec2(constants.resolution) - 1)).rgb;
+}
+
+// Traced pixel covering `pixel`.
+ivec2 tracedPixel(ivec2 pixel) {
+    vec2 uv = (vec2(pixel) + 0.5) / vec2(constants.resolution);
+    return clamp(ivec2(uv * vec2(constants.trace_resolution)), ivec2(0), ivec2(constants.trace_resolution) - 1);
+}
+
+// Distance from the eye to the voxel `pixel` shows, -1 for sky. Read with the motion, so it
+// doesn't need the depth image.
+float depth(ivec2 pixel) {
+    return imageLoad(motionImage, tracedPixel(pixel)).z;
+}
+
+// Distance along the ray to the face `pixel` shows, infinite for sky.
+float hitDistance(ivec2 pixel) {
+    return imageLoad(depthImage, tracedPixel(pixel)).r;
+}
+
+// Normal of the face `pixel` shows, zero for sky.
+vec3 normal(ivec2 pixel) {
+    return imageLoad(normalImage, tracedPixel(pixel)).xyz;
+}
+
+// Offset in uv units from where `pixel`'s surface was seen in the previous frame.
+vec2 motion(ivec2 pixel) {
+    return imageLoad(motionImage, tracedPixel(pixel)).xy;
+}
+
+// Voxel type `pixel` shows, 0 for sky.
+uint material(ivec2 pixel) {
+    return imageLoad(materialImage, tracedPixel(pixel)).r;
+}
+
+vec4 postProcess(ivec2 pixel);
+
+void main() {
+    if (any(greaterThanEqual(gl_GlobalInvocationID.xy, constants.resolution))) {
+        return;
+    }
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    imageStore(img, pixel, vec4(postProcess(pixel).rgb, 1.0));
+}
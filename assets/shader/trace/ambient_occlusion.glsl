@@ -0,0 +1,16 @@
+// Ambient occlusion lighting term, included by compute.glsl.
+
+// Darkens corners by counting the solid voxels around the empty cell in front of the hit face.
+float ambientOcclusion(ivec3 cell, ivec3 normal) {
+    ivec3 tangent = normal.yzx;
+    ivec3 bitangent = normal.zxy;
+    float occluded = 0.0;
+    for (int u = -1; u <= 1; u++) {
+        for (int v = -1; v <= 1; v++) {
+            if ((u != 0 || v != 0) && occupied(cell + tangent * u + bitangent * v)) {
+                occluded += 1.0;
+            }
+        }
+    }
+    return 1.0 - occluded / 8.0 * 0.6;
+}
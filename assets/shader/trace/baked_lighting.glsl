@@ -0,0 +1,41 @@
+// Baked lighting term, included by compute.glsl.
+
+// Number of the face facing `normal`: towards +x, -x, +y, -y, +z and -z.
+uint faceIndex(ivec3 normal) {
+    if (normal.x != 0) {
+        return normal.x > 0 ? 0u : 1u;
+    }
+    if (normal.y != 0) {
+        return normal.y > 0 ? 2u : 3u;
+    }
+    return normal.z > 0 ? 4u : 5u;
+}
+
+// Returns the light baked for the face of the voxel `c` facing `normal`, searching the sorted
+// entries of its chunk. Faces which weren't baked, like those uncovered by edits since, are lit
+// fully.
+vec3 bakedLight(ivec3 c, ivec3 normal) {
+    if (any(lessThan(c, ivec3(0))) || any(greaterThanEqual(c, ivec3(WORLD_SIZE)))) {
+        return vec3(1.0);
+    }
+    ivec3 chunk = c / CHUNK_SIZE;
+    uint header = uint((chunk.x * CHUNKS + chunk.y) * CHUNKS + chunk.z);
+    ivec3 local = c % CHUNK_SIZE;
+    uint key = uint((local.x * CHUNK_SIZE + local.y) * CHUNK_SIZE + local.z) * 6u + faceIndex(normal);
+    uint low = lightBake.words[header];
+    uint end = lightBake.words[header + 1];
+    uint high = end;
+    while (low < high) {
+        uint middle = (low + high) / 2;
+        if (lightBake.words[BAKE_HEADER_WORDS + middle * 2] < key) {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    if (low == end || lightBake.words[BAKE_HEADER_WORDS + low * 2] != key) {
+        return vec3(1.0);
+    }
+    uint light = lightBake.words[BAKE_HEADER_WORDS + low * 2 + 1];
+    return vec3(light & 1023u, (light >> 10) & 1023u, (light >> 20) & 1023u) / 1023.0 * MAX_BAKED_LIGHT;
+}
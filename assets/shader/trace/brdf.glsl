@@ -0,0 +1,59 @@
+// Sun and surface response, included by compute.glsl.
+
+// Direction towards the sun, which rises towards +x at 6, stands highest at 12 and sets towards -x
+// at 18, tilted a little towards -z.
+vec3 sunDirection() {
+    float angle = (constants.time_of_day - 6.0) / 12.0 * PI;
+    return normalize(vec3(cos(angle), sin(angle), -0.3));
+}
+
+// Returns the sun light a surface facing `normal`, seen along `viewDir`, reflects towards the
+// viewer through the GGX specular lobe of its `roughness`, with `f0` its reflectance head on.
+vec3 sunSpecular(vec3 normal, vec3 viewDir, float roughness, vec3 f0) {
+    vec3 l = sunDirection();
+    vec3 v = -normalize(viewDir);
+    float nl = dot(normal, l);
+    float nv = dot(normal, v);
+    // Below the horizon the sun is shadowed by the ground, as in sunShadow.
+    if (nl <= 0.0 || nv <= 0.0 || l.y <= 0.0) {
+        return vec3(0.0);
+    }
+    vec3 h = normalize(l + v);
+    float nh = max(dot(normal, h), 0.0);
+    // Mirror like materials would only ever catch the sun in a single pixel.
+    float a = max(roughness * roughness, MIN_SPECULAR_ALPHA);
+    float a2 = a * a;
+    float denominator = nh * nh * (a2 - 1.0) + 1.0;
+    float d = a2 / (PI * denominator * denominator);
+    // Smith's shadowing with Schlick's approximation for direct light.
+    float k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    float g = nv / (nv * (1.0 - k) + k) * nl / (nl * (1.0 - k) + k);
+    vec3 f = f0 + (1.0 - f0) * pow(1.0 - max(dot(h, v), 0.0), 5.0);
+    return d * g * f / (4.0 * nv) * SUN_SPECULAR;
+}
+
+// Returns the normal of the face of `voxel` facing `normal` at `hitPos`, perturbed by the detail
+// normal map when its material has detail normals. The map is projected onto the plane of the
+// face, tiled every DETAIL_TILE voxels.
+vec3 detailNormal(vec3 hitPos, ivec3 normal, uint voxel) {
+    vec3 n = vec3(normal);
+    if (voxelMaterial(voxel).params.w == 0.0) {
+        return n;
+    }
+    vec3 bitangent = normal.y != 0 ? vec3(0.0, 0.0, 1.0) : vec3(0.0, 1.0, 0.0);
+    vec3 tangent = cross(bitangent, n);
+    vec2 uv = vec2(dot(hitPos, tangent), -dot(hitPos, bitangent)) / DETAIL_TILE;
+    vec3 detail = texture(detailNormals, uv).xyz * 2.0 - 1.0;
+    return normalize(tangent * detail.x + bitangent * detail.y + n * detail.z);
+}
+
+// Returns the color of a surface of `albedo` made of `voxel`'s material at `hitPos`, facing
+// `normal` and seen along `viewDir`, lit by the lighting factor `light` with `sunVisibility` of
+// the sun reaching it. Dielectrics reflect the sun untinted with a reflectance of 4%, metals
+// tinted by their albedo, whose light and bounces they reflect rather than scatter.
+vec3 shade(vec3 albedo, vec3 light, float sunVisibility, uint voxel, vec3 hitPos, ivec3 normal, vec3 viewDir) {
+    vec4 params = voxelMaterial(voxel).params;
+    vec3 f0 = mix(vec3(0.04), albedo, params.z);
+    vec3 n = detailNormal(hitPos, normal, voxel);
+    return albedo * light + sunVisibility * sunSpecular(n, viewDir, params.x, f0);
+}
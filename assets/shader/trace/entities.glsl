@@ -0,0 +1,81 @@
+// Rays meeting the entities drawn over the voxel grid, included by compute.glsl.
+
+// Returns the distance along the ray to where it enters the box from `boxMin` to `boxMax`, with
+// the axis of the face it enters through in `enterMask`, or -1 when it misses the box, enters it
+// beyond `maxDist` or starts inside it.
+float enterBox(vec3 rayPos, vec3 rayDir, vec3 boxMin, vec3 boxMax, float maxDist, out bvec3 enterMask) {
+    vec3 dir = mix(rayDir, vec3(1e-6), equal(rayDir, vec3(0.0)));
+    vec3 t0 = (boxMin - rayPos) / dir;
+    vec3 t1 = (boxMax - rayPos) / dir;
+    vec3 tMin = min(t0, t1);
+    vec3 tMax = max(t0, t1);
+    float enter = max(tMin.x, max(tMin.y, tMin.z));
+    float exit = min(tMax.x, min(tMax.y, tMax.z));
+    enterMask = equal(tMin, vec3(enter));
+    return enter < 0.0 || enter > exit || enter > maxDist ? -1.0 : enter;
+}
+
+// Walks the ray like `traverse`, but stops at the entities and voxel objects too, which are
+// hit like voxels of the type their material belongs to. They are met along the ray as it
+// started, before a portal moved it, so they aren't seen through portals. Only the objects the
+// culling pass kept are met: for rays from the camera, `fromCamera`, those in view, for others
+// those within the cull distance.
+Hit traceCulled(vec3 rayPos, vec3 rayDir, uint maxSteps, float maxDist, bool fromCamera) {
+    Hit hit = traverse(rayPos, rayDir, maxSteps, maxDist);
+    float nearest = hit.voxel != 0 ? hit.dist : maxDist;
+    uint voxel = 0u;
+    ivec3 normal = ivec3(0);
+    for (uint i = 0; i < entities.count; i++) {
+        bvec3 enterMask;
+        float dist = enterBox(rayPos, rayDir, entities.entities[i].min.xyz, entities.entities[i].max.xyz, nearest, enterMask);
+        if (dist >= 0.0 && dist < nearest) {
+            nearest = dist;
+            voxel = uint(entities.entities[i].min.w);
+            normal = -ivec3(enterMask) * ivec3(sign(rayDir));
+        }
+    }
+    uint count = fromCamera ? culled.viewCount : culled.rangeCount;
+    uint first = fromCamera ? 0u : objects.count;
+    for (uint i = 0; i < count; i++) {
+        uint found;
+        ivec3 objectNormal;
+        float dist = traceObject(culled.indices[first + i], rayPos, rayDir, nearest, found, objectNormal);
+        if (dist >= 0.0 && dist < nearest) {
+            nearest = dist;
+            voxel = found;
+            normal = objectNormal;
+        }
+    }
+    if (voxel == 0u) {
+        return hit;
+    }
+    // The cell behind the face, so the one in front of it is `pos + normal` like for voxels.
+    ivec3 pos = ivec3(floor(rayPos + rayDir * nearest - vec3(normal) * 0.5));
+    return Hit(voxel, pos, notEqual(normal, ivec3(0)), normal, nearest, false, rayPos, rayDir);
+}
+
+// Traces a ray from anywhere but the camera, see `traceCulled`.
+Hit traceScene(vec3 rayPos, vec3 rayDir, uint maxSteps, float maxDist) {
+    return traceCulled(rayPos, rayDir, maxSteps, maxDist, false);
+}
+
+// Traces a ray from the camera, see `traceCulled`.
+Hit traceCamera(vec3 rayPos, vec3 rayDir, uint maxSteps, float maxDist) {
+    return traceCulled(rayPos, rayDir, maxSteps, maxDist, true);
+}
+
+// Returns whether the voxel `c` is solid and drawn or its center lies in an entity, so ambient
+// occlusion darkens the world next to entities but not next to hidden layers or cut surfaces. Cells in front of an entity's faces never have their
+// center inside it, so entities don't darken themselves.
+bool occupied(ivec3 c) {
+    if (voxelDrawn(getVoxel(c), c)) {
+        return true;
+    }
+    vec3 center = vec3(c) + 0.5;
+    for (uint i = 0; i < entities.count; i++) {
+        if (all(greaterThanEqual(center, entities.entities[i].min.xyz)) && all(lessThan(center, entities.entities[i].max.xyz))) {
+            return true;
+        }
+    }
+    return false;
+}
@@ -0,0 +1,36 @@
+// Global illumination lighting term, included by compute.glsl.
+
+// Returns a cosine weighted direction around `normal`.
+vec3 sampleHemisphere(vec3 normal, inout uint rng) {
+    float phi = 6.2831853 * random(rng);
+    float r = sqrt(random(rng));
+    vec3 tangent = normalize(abs(normal.x) > 0.5 ? cross(normal, vec3(0.0, 1.0, 0.0)) : cross(normal, vec3(1.0, 0.0, 0.0)));
+    vec3 bitangent = cross(normal, tangent);
+    return normalize(tangent * (r * cos(phi)) + bitangent * (r * sin(phi)) + normal * sqrt(1.0 - r * r));
+}
+
+// Returns the light bouncing off the scene onto a surface at `hitPos` facing `normal`, hit from
+// `viewDir` and scattering bounces as diffusely as `roughness`. Each bounce contributes `weight`
+// times its color and emission, tinting the bounces after it.
+vec3 bounceLight(vec3 hitPos, ivec3 normal, vec3 viewDir, float roughness, vec3 weight, inout uint rng) {
+    vec3 light = vec3(0.0);
+    vec3 bouncePos = hitPos;
+    ivec3 bounceNormal = normal;
+    vec3 incoming = viewDir;
+    for (uint i = 0; i < constants.max_bounces; i++) {
+        vec3 bounceDir = sampleHemisphere(vec3(bounceNormal), rng);
+        bounceDir = normalize(mix(reflect(incoming, vec3(bounceNormal)), bounceDir, roughness));
+        Hit bounce = traceScene(bouncePos, bounceDir, constants.max_ray_steps, float(constants.render_distance));
+        vec3 bounceColor = voxelColor(bounce);
+        light += weight * (bounceColor + voxelEmission(bounce.voxel));
+        if (bounce.voxel == 0) {
+            break;
+        }
+        weight *= 0.3 * bounceColor;
+        incoming = bounce.direction;
+        roughness = voxelMaterial(bounce.voxel).params.x;
+        bounceNormal = bounce.normal;
+        bouncePos = bounce.origin + bounce.direction * bounce.dist + vec3(bounceNormal) * 0.001;
+    }
+    return light;
+}
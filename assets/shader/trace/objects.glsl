@@ -0,0 +1,70 @@
+// Rays meeting the voxel objects drawn over the voxel grid, included by compute.glsl.
+
+// Returns the voxel type at `c` in the model of `object`, which must lie inside it.
+uint objectVoxel(Object object, ivec3 c) {
+    uvec3 size = object.size.xyz;
+    uint index = (uint(c.x) * size.y + uint(c.y)) * size.z + uint(c.z);
+    uint word = objectVoxels.words[object.size.w + index / 4];
+    return word >> (index % 4 * 8) & 255u;
+}
+
+// Returns the axis of the world closest to the direction `v`, pointing the same way.
+ivec3 nearestAxis(vec3 v) {
+    vec3 a = abs(v);
+    ivec3 axis = a.x >= a.y && a.x >= a.z ? ivec3(1, 0, 0) : a.y >= a.z ? ivec3(0, 1, 0) : ivec3(0, 0, 1);
+    return axis * ivec3(sign(v));
+}
+
+// Returns the distance along the ray to the first voxel of object `index` it meets before
+// `maxDist` whose center the clipping planes don't cut away, or -1 when it meets none. The voxel's type goes to `voxel` and the normal of the
+// face the ray entered through, turned to the nearest axis of the world, to `normal`. Rays
+// starting inside a voxel leave it without meeting it, as rays leaving a turned face start a
+// little off it along that axis, which may lie inside the voxel.
+float traceObject(uint index, vec3 rayPos, vec3 rayDir, float maxDist, out uint voxel, out ivec3 normal) {
+    Object object = objects.objects[index];
+    vec3 size = vec3(object.size.xyz);
+    float scale = object.rotation.w;
+    // The ray in voxels of the model from its lower corner. The direction isn't normalized, so
+    // distances along the ray stay those in the world.
+    vec3 pos = rotateFromWorld(rayPos - object.translation.xyz, object.rotation.xyz) / scale + size * 0.5;
+    vec3 dir = rotateFromWorld(rayDir, object.rotation.xyz) / scale;
+    bvec3 parallel = equal(dir, vec3(0.0));
+    vec3 safeDir = mix(dir, vec3(1e-6), parallel);
+    vec3 t0 = -pos / safeDir;
+    vec3 t1 = (size - pos) / safeDir;
+    vec3 tMin = min(t0, t1);
+    float exit = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), max(t0.z, t1.z));
+    float enter = max(tMin.x, max(tMin.y, tMin.z));
+    float t = max(enter, 0.0);
+    if (t > exit || t > maxDist) {
+        return -1.0;
+    }
+
+    ivec3 rayStep = ivec3(sign(dir));
+    ivec3 cell = clamp(ivec3(floor(pos + dir * t)), ivec3(0), ivec3(object.size.xyz) - 1);
+    vec3 delta = abs(1.0 / safeDir);
+    vec3 next = mix((vec3(cell) + max(vec3(rayStep), 0.0) - pos) / safeDir, vec3(1e30), parallel);
+    ivec3 localNormal = -ivec3(equal(tMin, vec3(t))) * rayStep;
+    int steps = int(object.size.x + object.size.y + object.size.z);
+    for (int i = 0; i < steps; i++) {
+        uint found = objectVoxel(object, cell);
+        vec3 center = object.translation.xyz + rotateToWorld((vec3(cell) + 0.5 - size * 0.5) * scale, object.rotation.xyz);
+        if (found != 0u && (i > 0 || enter > 0.0) && !clipped(center)) {
+            voxel = found;
+            normal = nearestAxis(rotateToWorld(vec3(localNormal), object.rotation.xyz));
+            return t;
+        }
+        t = min(next.x, min(next.y, next.z));
+        if (t > maxDist) {
+            break;
+        }
+        bvec3 crossed = equal(next, vec3(t));
+        cell += ivec3(crossed) * rayStep;
+        if (any(lessThan(cell, ivec3(0))) || any(greaterThanEqual(cell, ivec3(object.size.xyz)))) {
+            break;
+        }
+        next += delta * vec3(crossed);
+        localNormal = -ivec3(crossed) * rayStep;
+    }
+    return -1.0;
+}
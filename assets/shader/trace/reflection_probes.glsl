@@ -0,0 +1,39 @@
+// Reflection probe lookups for glossy surfaces, included by compute.glsl along with global
+// illumination.
+
+// Returns the color probe `probe` holds in `dir`, picking the cubemap face and its texel the way
+// reflection_probes.rs renders them.
+vec3 probeTexel(uint probe, vec3 dir) {
+    vec3 a = abs(dir);
+    uint face;
+    vec2 uv;
+    if (a.x >= a.y && a.x >= a.z) {
+        face = dir.x > 0.0 ? 0u : 1u;
+        uv = vec2(dir.x > 0.0 ? -dir.z : dir.z, -dir.y) / a.x;
+    } else if (a.y >= a.z) {
+        face = dir.y > 0.0 ? 2u : 3u;
+        uv = vec2(dir.x, dir.y > 0.0 ? dir.z : -dir.z) / a.y;
+    } else {
+        face = dir.z > 0.0 ? 4u : 5u;
+        uv = vec2(dir.z > 0.0 ? dir.x : -dir.x, -dir.y) / a.z;
+    }
+    return texture(probeImages, vec3(uv * 0.5 + 0.5, float(probe * 6u + face))).rgb;
+}
+
+// Returns the light a glossy surface at `hitPos` facing `normal`, hit from `viewDir`, reflects
+// out of the probe nearest to it, blurred by jittering the reflection as much as `roughness`.
+vec3 probeReflection(vec3 hitPos, ivec3 normal, vec3 viewDir, float roughness, inout uint rng) {
+    uint nearest = 0;
+    float nearestDist = 1e30;
+    for (uint i = 0; i < probes.count; i++) {
+        vec3 offset = probes.positions[i].xyz - hitPos;
+        float dist = dot(offset, offset);
+        if (dist < nearestDist) {
+            nearest = i;
+            nearestDist = dist;
+        }
+    }
+    vec3 dir = sampleHemisphere(vec3(normal), rng);
+    dir = normalize(mix(reflect(viewDir, vec3(normal)), dir, roughness));
+    return probeTexel(nearest, dir);
+}
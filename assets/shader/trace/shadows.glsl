@@ -0,0 +1,41 @@
+// Sun shadow lighting term, included by compute.glsl.
+
+// Transparent voxels a colored shadow ray passes through at most before it counts as blocked.
+const uint MAX_SHADOW_LAYERS = 8;
+
+// Returns how much sun reaches `hitPos`, which is in shadow when something lies between it and
+// the sun. With colored shadows the sun shines through transparent voxels, tinted by each one.
+vec3 sunShadow(vec3 hitPos, uint flags) {
+    vec3 sunDir = sunDirection();
+    // Everything is in shadow while the sun is below the horizon.
+    if (sunDir.y <= 0.0) {
+        return vec3(0.5);
+    }
+    if ((flags & FLAG_COLORED_SHADOWS) == 0) {
+        Hit shadow = traceScene(hitPos, sunDir, constants.max_ray_steps, float(constants.render_distance));
+        return vec3(shadow.voxel != 0 ? 0.5 : 1.0);
+    }
+    vec3 tint = vec3(1.0);
+    vec3 rayPos = hitPos;
+    vec3 rayDir = sunDir;
+    float maxDist = float(constants.render_distance);
+    for (uint i = 0; i <= MAX_SHADOW_LAYERS; i++) {
+        Hit shadow = traceScene(rayPos, rayDir, constants.max_ray_steps, maxDist);
+        if (shadow.voxel == 0) {
+            return mix(vec3(0.5), vec3(1.0), tint);
+        }
+        Material material = voxelMaterial(shadow.voxel);
+        tint *= material.color.rgb * material.params.y;
+        if (all(lessThan(tint, vec3(0.01)))) {
+            break;
+        }
+        // Continues from where the ray leaves the voxel, as moved by the portals it went through.
+        vec3 entry = shadow.origin + shadow.direction * shadow.dist;
+        bvec3 exitMask;
+        float exit = leaveBox(entry, shadow.direction, vec3(shadow.pos), vec3(shadow.pos + 1), exitMask);
+        rayPos = entry + shadow.direction * (exit + 0.001);
+        rayDir = shadow.direction;
+        maxDist -= shadow.dist + exit;
+    }
+    return vec3(0.5);
+}
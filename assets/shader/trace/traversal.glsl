@@ -0,0 +1,175 @@
+// Walking rays through the voxel grid, included by compute.glsl.
+
+struct Hit {
+    uint voxel;
+    ivec3 pos;
+    bvec3 mask;
+    // Normal of the face the ray entered through, zero when it started inside the voxel.
+    ivec3 normal;
+    float dist;
+    // Whether the ray ran out of steps before reaching `maxDist`.
+    bool truncated;
+    // The ray as moved by the portals it went through, `origin + direction * dist` is the hit
+    // point. The origin is moved back along the ray by the distance already covered.
+    vec3 origin;
+    vec3 direction;
+};
+
+// Returns the distance along the ray to where it leaves the box from `boxMin` to `boxMax`, with
+// the axis of the face it leaves through in `exitMask`.
+float leaveBox(vec3 rayPos, vec3 rayDir, vec3 boxMin, vec3 boxMax, out bvec3 exitMask) {
+    vec3 t = (mix(boxMin, boxMax, greaterThan(rayDir, vec3(0.0))) - rayPos) / rayDir;
+    t = mix(t, vec3(1e30), equal(rayDir, vec3(0.0)));
+    float exit = min(t.x, min(t.y, t.z));
+    exitMask = equal(t, vec3(exit));
+    return exit;
+}
+
+// Returns the distance along the ray from `rayPos` to the first voxel it can hit after being in
+// `cell` at `dist`, skipping the empty chunks around it, or the empty chunk or brick of `cell`
+// when the chunks around it hold voxels, or -1 when `cell` isn't known to be empty. Rays outside the world skip to where they enter it, or past `maxDist` if they never do.
+float skipEmptySpace(vec3 rayPos, vec3 rayDir, ivec3 cell, float dist, float maxDist, out bvec3 enterMask) {
+    if (any(lessThan(cell, ivec3(0))) || any(greaterThanEqual(cell, ivec3(WORLD_SIZE)))) {
+        bvec3 parallel = equal(rayDir, vec3(0.0));
+        vec3 tNear = (mix(vec3(WORLD_SIZE), vec3(0.0), greaterThan(rayDir, vec3(0.0))) - rayPos) / rayDir;
+        vec3 tFar = (mix(vec3(0.0), vec3(WORLD_SIZE), greaterThan(rayDir, vec3(0.0))) - rayPos) / rayDir;
+        tNear = mix(tNear, vec3(-1e30), parallel);
+        tFar = mix(tFar, vec3(1e30), parallel);
+        float enter = max(tNear.x, max(tNear.y, tNear.z));
+        float leave = min(tFar.x, min(tFar.y, tFar.z));
+        enterMask = equal(tNear, vec3(enter));
+        // Rays parallel to the faces of an axis stay outside when they start outside along it.
+        vec3 outsideSlab = vec3(lessThan(rayPos, vec3(0.0))) + vec3(greaterThan(rayPos, vec3(WORLD_SIZE)));
+        bool misses = enter > leave || any(greaterThan(outsideSlab * vec3(parallel), vec3(0.0)));
+        // Rays which already left the world can't hit anything anymore either.
+        return misses || enter <= dist ? maxDist + 1.0 : enter;
+    }
+    ivec3 chunk = cell / CHUNK_SIZE;
+    uint chunkDistance = chunkDistances.distances[(chunk.x * CHUNKS + chunk.y) * CHUNKS + chunk.z];
+    if (chunkDistance == 0) {
+        int size = emptyBox(cell);
+        if (size == 0) {
+            return -1.0;
+        }
+        vec3 boxMin = vec3(cell / size * size);
+        return leaveBox(rayPos, rayDir, boxMin, boxMin + float(size), enterMask);
+    }
+    // Nothing lies beyond the world, so the box may reach past it.
+    int reach = int(chunkDistance) - 1;
+    vec3 boxMin = vec3((chunk - reach) * CHUNK_SIZE);
+    vec3 boxMax = vec3((chunk + reach + 1) * CHUNK_SIZE);
+    return leaveBox(rayPos, rayDir, boxMin, boxMax, enterMask);
+}
+
+// Returns the index of the first portal containing `cell`, or -1.
+int findPortal(ivec3 cell) {
+    for (uint i = 0; i < portals.count; i++) {
+        if (all(greaterThanEqual(cell, portals.portals[i].min.xyz)) && all(lessThan(cell, portals.portals[i].max.xyz))) {
+            return int(i);
+        }
+    }
+    return -1;
+}
+
+// Moves `p` from `portal` to its target, keeping its place relative to the center of the box.
+vec3 teleportPoint(int portal, vec3 p) {
+    Portal from = portals.portals[portal];
+    Portal to = portals.portals[from.min.w];
+    vec3 local = p - vec3(from.min.xyz + from.max.xyz) * 0.5;
+    local.xz = rotate2d(local.xz, float(from.max.w) * 1.5707963);
+    return vec3(to.min.xyz + to.max.xyz) * 0.5 + local;
+}
+
+// Walks the voxel grid along `rayDir` (normalized) until a solid voxel on a shown layer and not
+// cut away by the clipping planes is found, `maxDist` is exceeded or `maxSteps` steps were taken. `voxel` is 0 when nothing was hit. Rays entering a
+// linked portal continue from its target.
+//
+// With FLAG_DISTANCE_FIELD the walk jumps over empty chunks in a single step, as far as the chunk
+// distance field guarantees nothing can be hit, and only walks voxel by voxel in and next to
+// chunks holding voxels, jumping over their empty bricks.
+Hit traverse(vec3 rayPos, vec3 rayDir, uint maxSteps, float maxDist) {
+	ivec3 mapPos = ivec3(floor(rayPos + 0.));
+
+	vec3 deltaDist = abs(vec3(length(rayDir)) / rayDir);
+
+	ivec3 rayStep = ivec3(sign(rayDir));
+
+	vec3 sideDist = (sign(rayDir) * (vec3(mapPos) - rayPos) + (sign(rayDir) * 0.5) + 0.5) * deltaDist;
+
+	bvec3 mask = bvec3(false);
+    float dist = 0.0;
+    bool distanceField = (constants.flags & FLAG_DISTANCE_FIELD) != 0;
+    // The portal the ray came out of, which it passes through.
+    int exitPortal = -1;
+    uint teleports = 0;
+
+	for (uint i = 0; i < maxSteps && dist <= maxDist; i++) {
+        uint voxel = getVoxel(mapPos);
+        if (voxel == PORTAL) {
+            int portal = findPortal(mapPos);
+            if (portal >= 0 && portal == exitPortal) {
+                voxel = 0;
+            } else if (portal >= 0 && teleports < MAX_TELEPORTS) {
+                teleports++;
+                vec3 p = teleportPoint(portal, rayPos + rayDir * dist);
+                rayDir.xz = rotate2d(rayDir.xz, float(portals.portals[portal].max.w) * 1.5707963);
+                rayPos = p - rayDir * dist;
+                exitPortal = portals.portals[portal].min.w;
+                deltaDist = abs(vec3(length(rayDir)) / rayDir);
+                rayStep = ivec3(sign(rayDir));
+                mapPos = ivec3(floor(p + rayDir * 0.001));
+                sideDist = (sign(rayDir) * (vec3(mapPos) - rayPos) + (sign(rayDir) * 0.5) + 0.5) * deltaDist;
+                continue;
+            }
+        } else if (exitPortal >= 0 && findPortal(mapPos) != exitPortal) {
+            exitPortal = -1;
+        }
+		if (voxelDrawn(voxel, mapPos)) {
+            return Hit(voxel, mapPos, mask, -ivec3(mask) * rayStep, dist, false, rayPos, rayDir);
+        }
+        if (distanceField) {
+            bvec3 enterMask;
+            float skipTo = skipEmptySpace(rayPos, rayDir, mapPos, dist, maxDist, enterMask);
+            // Jumps shorter than a voxel are left to the walk.
+            if (skipTo > dist + 1.0) {
+                dist = skipTo;
+                vec3 p = rayPos + rayDir * dist;
+                // The cell entered, even where rounding puts `p` just short of the face.
+                mapPos = ivec3(floor(p));
+                mapPos = mix(mapPos, ivec3(round(p)) - ivec3(lessThan(rayDir, vec3(0.0))), enterMask);
+                sideDist = (sign(rayDir) * (vec3(mapPos) - p) + (sign(rayDir) * 0.5) + 0.5) * deltaDist + dist;
+                mask = enterMask;
+                continue;
+            }
+        }
+        if (sideDist.x < sideDist.y) {
+            if (sideDist.x < sideDist.z) {
+                dist = sideDist.x;
+                sideDist.x += deltaDist.x;
+                mapPos.x += rayStep.x;
+                mask = bvec3(true, false, false);
+            }
+            else {
+                dist = sideDist.z;
+                sideDist.z += deltaDist.z;
+                mapPos.z += rayStep.z;
+                mask = bvec3(false, false, true);
+            }
+        }
+        else {
+            if (sideDist.y < sideDist.z) {
+                dist = sideDist.y;
+                sideDist.y += deltaDist.y;
+                mapPos.y += rayStep.y;
+                mask = bvec3(false, true, false);
+            }
+            else {
+                dist = sideDist.z;
+                sideDist.z += deltaDist.z;
+                mapPos.z += rayStep.z;
+                mask = bvec3(false, false, true);
+            }
+        }
+	}
+    return Hit(0, mapPos, bvec3(false), ivec3(0), dist, dist <= maxDist, rayPos, rayDir);
+}
@@ -0,0 +1,95 @@
+#version 450
+
+layout(local_size_x = 16, local_size_y = 16, local_size_z = 1) in;
+
+// Output of the trace at the scaled resolution.
+layout(set = 0, binding = 0, rgba8) uniform readonly image2D traced;
+
+// Per traced pixel offset in uv units since the previous frame.
+layout(set = 0, binding = 1, rgba16f) uniform readonly image2D motion;
+
+// Upscaled result of the previous frame and the one written now, both at output resolution.
+layout(set = 0, binding = 2, rgba16f) uniform readonly image2D historyIn;
+layout(set = 0, binding = 3, rgba16f) uniform writeonly image2D historyOut;
+
+layout(set = 0, binding = 4, rgba8) uniform writeonly image2D img;
+
+layout(push_constant) uniform PushConstants {
+    uvec2 input_resolution;
+    uvec2 output_resolution;
+    uint frame;
+    // Non zero when the history holds nothing usable.
+    uint reset;
+} constants;
+
+// Share of the current frame in the blended result.
+const float CURRENT_WEIGHT = 0.1;
+
+// Element `index` of the Halton sequence with the given `base`, matching the trace's jitter.
+float halton(uint index, uint base) {
+    float result = 0.0;
+    float fraction = 1.0;
+    while (index > 0) {
+        fraction /= float(base);
+        result += fraction * float(index % base);
+        index /= base;
+    }
+    return result;
+}
+
+// Bilinearly samples the history at `uv`.
+vec3 sampleHistory(vec2 uv) {
+    ivec2 maxPixel = ivec2(constants.output_resolution) - 1;
+    vec2 pos = uv * vec2(constants.output_resolution) - 0.5;
+    ivec2 base = ivec2(floor(pos));
+    vec2 f = fract(pos);
+    vec3 a = imageLoad(historyIn, clamp(base, ivec2(0), maxPixel)).rgb;
+    vec3 b = imageLoad(historyIn, clamp(base + ivec2(1, 0), ivec2(0), maxPixel)).rgb;
+    vec3 c = imageLoad(historyIn, clamp(base + ivec2(0, 1), ivec2(0), maxPixel)).rgb;
+    vec3 d = imageLoad(historyIn, clamp(base + ivec2(1, 1), ivec2(0), maxPixel)).rgb;
+    return mix(mix(a, b, f.x), mix(c, d, f.x), f.y);
+}
+
+void main() {
+    if (any(greaterThanEqual(gl_GlobalInvocationID.xy, constants.output_resolution))) {
+        return;
+    }
+    ivec2 pixel = ivec2(gl_GlobalInvocationID.xy);
+    vec2 uv = (vec2(pixel) + 0.5) / vec2(constants.output_resolution);
+
+    // Traced pixels were sampled at their corner plus this frame's jitter.
+    vec2 jitter = vec2(halton(constants.frame % 8 + 1, 2), halton(constants.frame % 8 + 1, 3));
+    vec2 inputPos = uv * vec2(constants.input_resolution) - jitter;
+    ivec2 nearest = ivec2(round(inputPos));
+    ivec2 maxInput = ivec2(constants.input_resolution) - 1;
+
+    // Gaussian weighted reconstruction of the current frame and the colour range of the
+    // neighbourhood the history is clamped to.
+    vec3 current = vec3(0.0);
+    float totalWeight = 0.0;
+    vec3 minColor = vec3(1.0);
+    vec3 maxColor = vec3(0.0);
+    for (int y = -1; y <= 1; y++) {
+        for (int x = -1; x <= 1; x++) {
+            ivec2 samplePixel = clamp(nearest + ivec2(x, y), ivec2(0), maxInput);
+            vec3 color = imageLoad(traced, samplePixel).rgb;
+            vec2 offset = vec2(samplePixel) - inputPos;
+            float weight = exp(-2.0 * dot(offset, offset));
+            current += color * weight;
+            totalWeight += weight;
+            minColor = min(minColor, color);
+            maxColor = max(maxColor, color);
+        }
+    }
+    current /= max(totalWeight, 1e-4);
+
+    vec3 color = current;
+    vec2 previousUv = uv - imageLoad(motion, clamp(nearest, ivec2(0), maxInput)).xy;
+    bool onScreen = all(greaterThanEqual(previousUv, vec2(0.0))) && all(lessThanEqual(previousUv, vec2(1.0)));
+    if (constants.reset == 0 && onScreen) {
+        vec3 history = clamp(sampleHistory(previousUv), minColor, maxColor);
+        color = mix(history, current, CURRENT_WEIGHT);
+    }
+    imageStore(historyOut, pixel, vec4(color, 1.0));
+    imageStore(img, pixel, vec4(color, 1.0));
+}
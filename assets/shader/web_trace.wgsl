@@ -0,0 +1,85 @@
+// Traces the world for the browser build, see `web.rs`. A port of the CPU tracer's primary
+// rays: faces are shaded by their axis and rays hitting nothing show the sky color.
+// `WORLD_SIZE` and `SKY_COLOR` are prepended by `WebRenderer::new`.
+
+struct View {
+    eye: vec3<f32>,
+    aspect: f32,
+    // World directions of the camera's x and y axes and of `CAMERA_DIR`.
+    right: vec3<f32>,
+    max_distance: f32,
+    down: vec3<f32>,
+    width: f32,
+    forward: vec3<f32>,
+    height: f32,
+}
+
+@group(0) @binding(0) var<uniform> view: View;
+// Voxel types, a byte each, four to a word in the order of `World::region`.
+@group(0) @binding(1) var<storage, read> voxels: array<u32>;
+// Color and emissive strength of every voxel type.
+@group(0) @binding(2) var<storage, read> materials: array<vec4<f32>>;
+
+@vertex
+fn vertex(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    // A triangle covering the whole screen.
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn getVoxel(position: vec3<i32>) -> u32 {
+    if (any(position < vec3<i32>(0)) || any(position >= vec3<i32>(WORLD_SIZE))) {
+        return 0u;
+    }
+    let index = u32((position.x * WORLD_SIZE + position.y) * WORLD_SIZE + position.z);
+    return (voxels[index / 4u] >> ((index % 4u) * 8u)) & 0xffu;
+}
+
+@fragment
+fn fragment(@builtin(position) pixel: vec4<f32>) -> @location(0) vec4<f32> {
+    let screen = pixel.xy / vec2<f32>(view.width, view.height) * 2.0 - 1.0;
+    let direction = normalize(view.right * screen.x + view.down * screen.y / view.aspect + view.forward);
+
+    // Walks the grid the way `World::raycast` does.
+    let step = vec3<i32>(sign(direction));
+    let delta = abs(1.0 / direction);
+    var position = vec3<i32>(floor(view.eye));
+    var side = (vec3<f32>(step) * (vec3<f32>(position) - view.eye) + vec3<f32>(step) * 0.5 + 0.5) * delta;
+    var axis = -1;
+    var distance = 0.0;
+    loop {
+        if (distance > view.max_distance) {
+            break;
+        }
+        if (any((position < vec3<i32>(0)) & (step <= vec3<i32>(0)))
+            || any((position >= vec3<i32>(WORLD_SIZE)) & (step >= vec3<i32>(0)))) {
+            break;
+        }
+        let voxel = getVoxel(position);
+        if (voxel != 0u) {
+            let material = materials[min(voxel, arrayLength(&materials) - 1u)];
+            var shade = 0.75;
+            if (axis == 0) {
+                shade = 0.5;
+            } else if (axis == 1) {
+                shade = 1.0;
+            }
+            return vec4<f32>(material.rgb * shade + material.rgb * material.a, 1.0);
+        }
+        if (side.x < side.y) {
+            if (side.x < side.z) {
+                axis = 0;
+            } else {
+                axis = 2;
+            }
+        } else if (side.y < side.z) {
+            axis = 1;
+        } else {
+            axis = 2;
+        }
+        distance = side[axis];
+        side[axis] += delta[axis];
+        position[axis] += step[axis];
+    }
+    return vec4<f32>(SKY_COLOR, 1.0);
+}
@@ -0,0 +1,206 @@
+#version 450
+
+layout(local_size_x = 4, local_size_y = 4, local_size_z = 4) in;
+
+// Voxel types indexed by (x * world_size + y) * world_size + z, 0 is empty.
+layout(set = 0, binding = 0) buffer World {
+    uint voxels[];
+} world;
+
+// Two heightmaps indexed by x * world_size + z, the one at `iteration % 2` holds the current
+// heights and erosion writes the other.
+layout(set = 0, binding = 1) buffer Heights {
+    float heights[];
+} heightmap;
+
+layout(push_constant) uniform PushConstants {
+    uint seed;
+    uint world_size;
+    // Noise features per voxel of the coarsest octave.
+    float frequency;
+    uint octaves;
+    // Height of the hills above and depth of the valleys below base_height.
+    float amplitude;
+    // Voxels the heightmap is sampled away from where it is drawn at most.
+    float warp;
+    float base_height;
+    // Empty voxels below this height are water.
+    float water_level;
+    // PASS_HEIGHTS, PASS_EROSION or PASS_VOXELS.
+    uint pass;
+    // Erosion steps done so far.
+    uint iteration;
+    // Height difference between neighboring columns above which material slides down.
+    float talus;
+    // Width of the tunnels carved underground in noise units, 0 for none.
+    float caves;
+} constants;
+
+// Passes run one after the other: the heightmap is drawn from noise, eroded a step per dispatch,
+// then filled with voxels. Heightmap passes run on the invocations with y = 0 only.
+const uint PASS_HEIGHTS = 0;
+const uint PASS_EROSION = 1;
+const uint PASS_VOXELS = 2;
+
+// Voxel types of the default palette, see materials.rs and simulation.rs.
+const uint GRASS = 2;
+const uint STONE = 4;
+const uint WATER = 10;
+const uint SAND = 12;
+
+// Share of the height difference above the talus moving to each lower neighbor per step. At most a
+// fifth, so a peak sliding down to all four neighbors ends up no lower than them.
+const float EROSION_RATE = 0.2;
+
+// Layers of grass or sand over the stone.
+const float SOIL_DEPTH = 3.0;
+// Cave noise features per voxel, vertically twice as many so tunnels run flatter than they climb.
+const vec3 CAVE_FREQUENCY = vec3(1.0 / 48.0, 1.0 / 24.0, 1.0 / 48.0);
+// Voxels of stone left above the caves below the surface, worms break through it, see caves.rs.
+const float CAVE_ROOF = 4.0;
+// Octaves of the heightmap's noise come before the cave noise's.
+const uint CAVE_OCTAVE = 16;
+// Surfaces this close above the water are beaches.
+const float BEACH_HEIGHT = 2.0;
+
+// Integer hash, see "lowbias32" by Chris Wellons.
+uint hash(uint x) {
+    x ^= x >> 16;
+    x *= 0x7feb352du;
+    x ^= x >> 15;
+    x *= 0x846ca68bu;
+    x ^= x >> 16;
+    return x;
+}
+
+// Random value from 0 to 1 at a lattice point of an octave.
+float lattice(ivec2 point, uint octave) {
+    uint h = hash(uint(point.x) ^ hash(uint(point.y) ^ hash(constants.seed + octave)));
+    return float(h) / 4294967295.0;
+}
+
+// Random value from 0 to 1 at a lattice point of the cave noise.
+float lattice3(ivec3 point, uint octave) {
+    uint h = hash(constants.seed + octave);
+    h = hash(uint(point.z) ^ hash(uint(point.y) ^ hash(uint(point.x) ^ h)));
+    return float(h) / 4294967295.0;
+}
+
+// Smoothly interpolated value noise from 0 to 1.
+float value_noise(vec2 position, uint octave) {
+    ivec2 cell = ivec2(floor(position));
+    vec2 t = fract(position);
+    t = t * t * (3.0 - 2.0 * t);
+    float a = lattice(cell, octave);
+    float b = lattice(cell + ivec2(1, 0), octave);
+    float c = lattice(cell + ivec2(0, 1), octave);
+    float d = lattice(cell + ivec2(1, 1), octave);
+    return mix(mix(a, b, t.x), mix(c, d, t.x), t.y);
+}
+
+// Smoothly interpolated 3D value noise from -1 to 1.
+float value_noise3(vec3 position, uint octave) {
+    ivec3 cell = ivec3(floor(position));
+    vec3 t = fract(position);
+    t = t * t * (3.0 - 2.0 * t);
+    float corners[8];
+    for (int i = 0; i < 8; i++) {
+        corners[i] = lattice3(cell + ivec3(i & 1, (i >> 1) & 1, i >> 2), octave);
+    }
+    float front = mix(mix(corners[0], corners[1], t.x), mix(corners[2], corners[3], t.x), t.y);
+    float back = mix(mix(corners[4], corners[5], t.x), mix(corners[6], corners[7], t.x), t.y);
+    return mix(front, back, t.z) * 2.0 - 1.0;
+}
+
+// Sum of octaves of value noise at doubling frequency and halving weight, from -1 to 1.
+float fbm(vec2 position) {
+    float sum = 0.0;
+    float weight = 1.0;
+    float total = 0.0;
+    for (uint octave = 0; octave < constants.octaves; octave++) {
+        sum += value_noise(position, octave) * weight;
+        total += weight;
+        weight *= 0.5;
+        position *= 2.0;
+    }
+    return sum / total * 2.0 - 1.0;
+}
+
+// Moves `position` by up to `constants.warp` voxels along two more fbm patterns, offset so they
+// don't line up with the heightmap's.
+vec2 warp(vec2 position) {
+    vec2 offset = vec2(fbm(position + vec2(17.3, 5.9)), fbm(position + vec2(-8.1, 31.7)));
+    return position + offset * constants.warp * constants.frequency;
+}
+
+// Whether the voxel at `position` is in a cave: near where the zero surfaces of two noise patterns
+// cross, which is along curves winding through the ground and meeting in a network of tunnels.
+bool cave(vec3 position) {
+    if (constants.caves <= 0.0) {
+        return false;
+    }
+    position *= CAVE_FREQUENCY;
+    vec2 noise = vec2(
+        value_noise3(position, CAVE_OCTAVE),
+        value_noise3(position + vec3(31.4, 7.1, 19.7), CAVE_OCTAVE + 1));
+    return length(noise) < constants.caves;
+}
+
+// Index of the column at `column` in the heightmap at `iteration` steps of erosion.
+uint height_index(ivec2 column, uint iteration) {
+    uint size = constants.world_size;
+    return iteration % 2 * size * size + uint(column.x) * size + uint(column.y);
+}
+
+// Thermal erosion: every column hands material to lower neighbors steeper than the talus and
+// takes it from higher ones, evening slopes out so peaks crumble into the valleys around them.
+// Each column gathers both sides itself, so no two invocations write the same height.
+float eroded(ivec2 column) {
+    int size = int(constants.world_size);
+    float height = heightmap.heights[height_index(column, constants.iteration)];
+    float change = 0.0;
+    ivec2 neighbors[4] = ivec2[](ivec2(1, 0), ivec2(-1, 0), ivec2(0, 1), ivec2(0, -1));
+    for (int i = 0; i < 4; i++) {
+        ivec2 neighbor = column + neighbors[i];
+        if (any(lessThan(neighbor, ivec2(0))) || any(greaterThanEqual(neighbor, ivec2(size)))) {
+            continue;
+        }
+        float difference = heightmap.heights[height_index(neighbor, constants.iteration)] - height;
+        change += sign(difference) * max(abs(difference) - constants.talus, 0.0) * EROSION_RATE;
+    }
+    return height + change;
+}
+
+void main() {
+    uvec3 voxel = gl_GlobalInvocationID;
+    uint size = constants.world_size;
+    if (any(greaterThanEqual(voxel, uvec3(size)))) {
+        return;
+    }
+    ivec2 column = ivec2(voxel.xz);
+    if (constants.pass != PASS_VOXELS) {
+        if (voxel.y != 0) {
+            return;
+        }
+        if (constants.pass == PASS_HEIGHTS) {
+            heightmap.heights[height_index(column, 0)] = constants.base_height
+                + constants.amplitude * fbm(warp(vec2(voxel.xz) * constants.frequency));
+        } else {
+            heightmap.heights[height_index(column, constants.iteration + 1)] = eroded(column);
+        }
+        return;
+    }
+    float height = heightmap.heights[height_index(column, constants.iteration)];
+    float y = float(voxel.y);
+    uint voxel_type = 0;
+    if (y < height - SOIL_DEPTH - CAVE_ROOF && y >= 1.0 && cave(vec3(voxel))) {
+        voxel_type = 0;
+    } else if (y < height - SOIL_DEPTH) {
+        voxel_type = STONE;
+    } else if (y < height) {
+        voxel_type = height < constants.water_level + BEACH_HEIGHT ? SAND : GRASS;
+    } else if (y < constants.water_level) {
+        voxel_type = WATER;
+    }
+    world.voxels[(voxel.x * size + voxel.y) * size + voxel.z] = voxel_type;
+}
@@ -0,0 +1,49 @@
+/* C API of the rvengine library, built as librvengine.so / librvengine.a. */
+
+#ifndef RAYVOX_H
+#define RAYVOX_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A world along with the palette and camera it is rendered with. */
+typedef struct RayVox RayVox;
+
+/* Creates an empty world with the default palette and camera. */
+RayVox *rayvox_create(void);
+
+/* Frees a RayVox created by rayvox_create. NULL is ignored. */
+void rayvox_destroy(RayVox *rayvox);
+
+/* Replaces the world with the first model of a MagicaVoxel .vox file, placed at the world
+ * origin. Returns 0 on success and -1 if the file couldn't be loaded. */
+int rayvox_load_world(RayVox *rayvox, const char *path);
+
+/* Places the camera. position and rotation are 3 floats each, rotation in radians around z,
+ * then x, then y. Both the position and the view are rotated around the world origin. The
+ * camera placed before is kept for the motion of rayvox_render_outputs. */
+void rayvox_set_camera(RayVox *rayvox, const float *position, const float *rotation);
+
+/* Renders the world into rgba, width * height pixels of 4 bytes each, rows from top to bottom.
+ * Returns 0 on success and -1 if the size is 0. */
+int rayvox_render_to_buffer(const RayVox *rayvox, uint32_t width, uint32_t height, uint8_t *rgba);
+
+/* Renders like rayvox_render_to_buffer and writes per pixel outputs along with the color, rows
+ * from top to bottom. NULL outputs aren't written.
+ *   depth:    1 float, distance from the eye to the face hit, infinity for sky
+ *   normal:   3 floats, normal of the face hit, zero for sky
+ *   material: 1 integer, voxel type hit, 0 for sky
+ *   motion:   2 floats, how far the pixel's content moved on screen since the previous camera,
+ *             as a fraction of the width and height, x right and y down
+ * Returns 0 on success and -1 if the size is 0. */
+int rayvox_render_outputs(const RayVox *rayvox, uint32_t width, uint32_t height, uint8_t *rgba,
+                          float *depth, float *normal, uint32_t *material, float *motion);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,206 @@
+//! Shows RayVox worlds in Bevy games, traced on the CPU.
+//!
+//! `RayVoxCpuPlugin` adds the world as the `VoxelWorld` resource. Every entity with a
+//! `RayVoxView` gets the world traced from its camera with `rvengine::tracer`, and a render graph
+//! node uploads the pixels into the view's image before Bevy's cameras draw, so it can be shown on
+//! a sprite, UI node or material.
+//!
+//! This is a fallback for small views, not a GPU backend: the engine's compute tracer runs on its
+//! own Vulkan device and isn't dispatched on Bevy's, so every traced pixel is copied from the CPU.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_graph::{self, NodeRunError, RenderGraph, RenderGraphContext},
+        render_resource::{
+            Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect, TextureDimension,
+            TextureFormat,
+        },
+        renderer::{RenderContext, RenderQueue},
+        Extract, ExtractSchedule, Render, RenderApp, RenderSet,
+    },
+    utils::HashMap,
+};
+use rvengine::{camera::Camera, materials::Palette, tracer, world::World};
+
+/// Name of the node uploading traced views into their images in the render graph.
+pub const RAYVOX_UPLOAD_NODE: &str = "rayvox_upload";
+
+/// Default distance views trace primary rays to.
+pub const DEFAULT_MAX_DISTANCE: f32 = 512.0;
+
+/// The voxel world. Views are traced again whenever it changes.
+#[derive(Resource, Deref, DerefMut)]
+pub struct VoxelWorld(pub World);
+
+impl Default for VoxelWorld {
+    fn default() -> VoxelWorld {
+        VoxelWorld(World::new())
+    }
+}
+
+/// The materials voxels are shaded with.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct VoxelPalette(pub Palette);
+
+/// A view of the world traced into `image`.
+#[derive(Component)]
+pub struct RayVoxView {
+    pub camera: Camera,
+    pub max_distance: f32,
+    pub image: Handle<Image>,
+    extent: [u32; 2],
+    pixels: Vec<u8>,
+    /// Whether `pixels` were traced this frame.
+    traced: bool,
+}
+
+impl RayVoxView {
+    /// Creates a view of `extent` pixels along with the image it is traced into.
+    pub fn new(images: &mut Assets<Image>, extent: [u32; 2], camera: Camera) -> RayVoxView {
+        let image = Image::new_fill(
+            Extent3d {
+                width: extent[0],
+                height: extent[1],
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[0, 0, 0, 255],
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        RayVoxView {
+            camera,
+            max_distance: DEFAULT_MAX_DISTANCE,
+            image: images.add(image),
+            extent,
+            pixels: vec![0; extent[0] as usize * extent[1] as usize * 4],
+            traced: false,
+        }
+    }
+
+    pub fn extent(&self) -> [u32; 2] {
+        self.extent
+    }
+}
+
+/// Adds `VoxelWorld` and `VoxelPalette` if the app has none yet and traces every `RayVoxView`.
+pub struct RayVoxCpuPlugin;
+
+impl Plugin for RayVoxCpuPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<VoxelWorld>()
+            .init_resource::<VoxelPalette>()
+            .add_systems(PostUpdate, trace_views);
+
+        let render_app = app.sub_app_mut(RenderApp);
+        render_app
+            .init_resource::<ExtractedViews>()
+            .add_systems(ExtractSchedule, extract_views)
+            .add_systems(Render, cleanup_views.in_set(RenderSet::Cleanup));
+        let mut render_graph = render_app.world.resource_mut::<RenderGraph>();
+        render_graph.add_node(RAYVOX_UPLOAD_NODE, UploadNode);
+        render_graph.add_node_edge(
+            RAYVOX_UPLOAD_NODE,
+            bevy::render::main_graph::node::CAMERA_DRIVER,
+        );
+    }
+}
+
+/// Traces views whose camera moved or which look at a changed world.
+fn trace_views(
+    world: Res<VoxelWorld>,
+    palette: Res<VoxelPalette>,
+    mut views: Query<&mut RayVoxView>,
+) {
+    let world_changed = world.is_changed() || palette.is_changed();
+    for mut view in &mut views {
+        // Tracing into the view itself mustn't count as a change, or it'd be traced every frame.
+        let changed = view.is_changed();
+        let view = view.bypass_change_detection();
+        view.traced = world_changed || changed;
+        if !view.traced {
+            continue;
+        }
+        tracer::render(
+            &world,
+            &palette,
+            &view.camera,
+            view.extent,
+            view.max_distance,
+            &mut view.pixels,
+        );
+    }
+}
+
+/// Traced views waiting to be copied into their images, in the render world.
+#[derive(Resource, Default)]
+struct ExtractedViews(HashMap<Handle<Image>, ExtractedView>);
+
+struct ExtractedView {
+    extent: [u32; 2],
+    pixels: Vec<u8>,
+}
+
+fn extract_views(mut extracted: ResMut<ExtractedViews>, views: Extract<Query<&RayVoxView>>) {
+    for view in views.iter().filter(|view| view.traced) {
+        extracted.0.insert(
+            view.image.clone_weak(),
+            ExtractedView {
+                extent: view.extent,
+                pixels: view.pixels.clone(),
+            },
+        );
+    }
+}
+
+/// Forgets views once they were copied. Images are uploaded a frame after they were created, so
+/// views traced right away are kept until their image exists.
+fn cleanup_views(mut extracted: ResMut<ExtractedViews>, gpu_images: Res<RenderAssets<Image>>) {
+    extracted
+        .0
+        .retain(|image, _| gpu_images.get(image).is_none());
+}
+
+/// Uploads traced views into their images once those are on the GPU.
+struct UploadNode;
+
+impl render_graph::Node for UploadNode {
+    fn run(
+        &self,
+        _graph: &mut RenderGraphContext,
+        _render_context: &mut RenderContext,
+        world: &bevy::ecs::world::World,
+    ) -> Result<(), NodeRunError> {
+        let Some(views) = world.get_resource::<ExtractedViews>() else {
+            return Ok(());
+        };
+        let gpu_images = world.resource::<RenderAssets<Image>>();
+        let queue = world.resource::<RenderQueue>();
+        for (image, view) in &views.0 {
+            let Some(gpu_image) = gpu_images.get(image) else {
+                continue;
+            };
+            queue.write_texture(
+                ImageCopyTexture {
+                    texture: &gpu_image.texture,
+                    mip_level: 0,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                &view.pixels,
+                ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(view.extent[0] * 4),
+                    rows_per_image: None,
+                },
+                Extent3d {
+                    width: view.extent[0],
+                    height: view.extent[1],
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+        Ok(())
+    }
+}
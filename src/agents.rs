@@ -0,0 +1,157 @@
+use crate::{
+    entities::EntityBox,
+    world::{World, WORLD_SIZE},
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::f32::consts::{PI, TAU};
+
+/// Voxel type whose material agents are drawn with.
+pub const AGENT: u32 = 15;
+
+/// Voxels an agent is tall.
+const HEIGHT: i32 = 2;
+/// Voxels an agent is wide along x and z.
+const WIDTH: f32 = 0.8;
+/// Voxels per second an agent walks.
+const SPEED: f32 = 4.0;
+/// Radians per second an agent's heading drifts by at most while wandering.
+const WANDER: f32 = 2.0;
+/// Voxels ahead an agent looks for walls to turn away from.
+const LOOK_AHEAD: f32 = 3.0;
+/// Voxels an agent steps down at most, deeper drops make it turn around.
+const MAX_DROP: f32 = 4.0;
+/// Random columns tried for every agent spawned before giving up.
+const SPAWN_ATTEMPTS: u32 = 32;
+
+/// An entity wandering over the ground.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Agent {
+    /// Its feet, the lower corner of the voxel they are in.
+    position: [f32; 3],
+    /// Radians around y, 0 walks along +z.
+    heading: f32,
+}
+
+impl Agent {
+    /// Returns the voxels its body fills, feet first.
+    fn body(&self) -> [[i32; 3]; HEIGHT as usize] {
+        let feet = self.position.map(|c| c.floor() as i32);
+        std::array::from_fn(|y| [feet[0], feet[1] + y as i32, feet[2]])
+    }
+}
+
+/// Entities wandering over the world, a demo of the gameplay side of the world API: they find
+/// the ground and look for walls with `World::raycast`, step up single voxels, turn away from
+/// drops and walls. They are drawn as entity boxes, see `entities`, and never change the world,
+/// so they stay out of saves and the undo history.
+pub struct Agents {
+    agents: Vec<Agent>,
+    rng: ChaCha8Rng,
+}
+
+impl Agents {
+    pub fn new(seed: u64) -> Agents {
+        Agents {
+            agents: Vec::new(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.agents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+
+    /// Returns the middle of the body of the agent at `index`.
+    pub fn position(&self, index: usize) -> Option<[f32; 3]> {
+        let [x, y, z] = self.agents.get(index)?.position;
+        Some([x, y + HEIGHT as f32 / 2.0, z])
+    }
+
+    /// Returns the box every agent is drawn as.
+    pub fn entities(&self) -> impl Iterator<Item = EntityBox> + '_ {
+        self.agents.iter().map(|agent| {
+            let [x, y, z] = agent.position;
+            EntityBox {
+                min: [x - WIDTH / 2.0, y, z - WIDTH / 2.0],
+                max: [x + WIDTH / 2.0, y + HEIGHT as f32, z + WIDTH / 2.0],
+                voxel: AGENT,
+            }
+        })
+    }
+
+    /// Places up to `count` agents on the ground at random and returns how many found room.
+    pub fn spawn(&mut self, world: &World, count: usize) -> usize {
+        let mut spawned = 0;
+        for _ in 0..count {
+            for _ in 0..SPAWN_ATTEMPTS {
+                let column = [0, 2].map(|_| self.rng.gen_range(1..WORLD_SIZE - 1) as f32 + 0.5);
+                let top = [column[0], WORLD_SIZE as f32 - 0.5, column[1]];
+                let Some(ground) = world.raycast(top, [0.0, -1.0, 0.0], WORLD_SIZE as f32) else {
+                    continue;
+                };
+                let agent = Agent {
+                    position: [column[0], ground.position[1] as f32 + 1.0, column[1]],
+                    heading: self.rng.gen_range(0.0..TAU),
+                };
+                if agent.body().iter().all(|&voxel| world.voxel(voxel) == 0) {
+                    self.agents.push(agent);
+                    spawned += 1;
+                    break;
+                }
+            }
+        }
+        spawned
+    }
+
+    /// Removes every agent.
+    pub fn clear(&mut self) {
+        self.agents.clear();
+    }
+
+    /// Moves every agent `dt` seconds further.
+    pub fn update(&mut self, world: &World, dt: f32) {
+        for agent in &mut self.agents {
+            agent.heading += self.rng.gen_range(-1.0..1.0) * WANDER * dt;
+            if let Some(position) = step(world, agent, dt) {
+                agent.position = position;
+            } else {
+                agent.heading += self.rng.gen_range(PI / 2.0..PI * 1.5);
+            }
+            agent.heading = agent.heading.rem_euclid(TAU);
+        }
+    }
+}
+
+/// Returns where `agent` is after walking for `dt` seconds, `None` when a wall, a drop or the
+/// edge of the world is in its way.
+fn step(world: &World, agent: &Agent, dt: f32) -> Option<[f32; 3]> {
+    let direction = [agent.heading.sin(), 0.0, agent.heading.cos()];
+    let [x, y, z] = agent.position;
+    let head = [x, y + HEIGHT as f32 - 0.5, z];
+    if world.raycast(head, direction, LOOK_AHEAD).is_some() {
+        return None;
+    }
+    let next = [
+        x + direction[0] * SPEED * dt,
+        y,
+        z + direction[2] * SPEED * dt,
+    ];
+    if [next[0], next[2]]
+        .iter()
+        .any(|&c| c < 1.0 || c >= WORLD_SIZE as f32 - 1.0)
+    {
+        return None;
+    }
+    // Steps up onto a single voxel, the look ahead at head height already saw taller ones.
+    let feet = next.map(|c| c.floor() as i32);
+    let climb = if world.voxel(feet) != 0 { 1.0 } else { 0.0 };
+    let above_feet = [next[0], next[1] + climb + 0.5, next[2]];
+    let ground = world.raycast(above_feet, [0.0, -1.0, 0.0], MAX_DROP + 0.5)?;
+    let ground_y = ground.position[1] as f32 + 1.0;
+    (ground.normal == [0, 1, 0]).then_some([next[0], ground_y, next[2]])
+}
@@ -0,0 +1,155 @@
+use crate::flythrough::Easing;
+use std::collections::BTreeMap;
+
+/// A placement of a voxel object its track passes through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObjectKey {
+    /// Where the object's center lies, see `objects::Transform`.
+    pub translation: [f32; 3],
+    /// Rotation in radians around z, then x, then y, see `objects::Transform`.
+    pub rotation: [f32; 3],
+    /// Seconds the move from the previous keyframe takes. For the first keyframe that's the move
+    /// back from the last one, which only looping tracks make.
+    pub travel: f32,
+    /// Seconds the object stays once it arrived.
+    pub hold: f32,
+    /// How the move from the previous keyframe speeds up and slows down.
+    pub easing: Easing,
+}
+
+/// Where an object is at some time of its track.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ObjectPose {
+    pub translation: [f32; 3],
+    pub rotation: [f32; 3],
+}
+
+/// The keyframes one voxel object moves through. Rotations are interpolated angle by angle, not
+/// the short way around, so a keyframe turned a whole turn further spins the object once.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Track {
+    keys: Vec<ObjectKey>,
+    /// Whether the track starts over after its last keyframe, moving back to the first.
+    pub looping: bool,
+}
+
+impl Track {
+    pub fn keys(&self) -> &[ObjectKey] {
+        &self.keys
+    }
+
+    pub fn push(&mut self, key: ObjectKey) {
+        self.keys.push(key);
+    }
+
+    /// Returns the seconds the move to the keyframe at `index` takes.
+    fn travel(&self, index: usize) -> f32 {
+        if index > 0 || self.looping {
+            self.keys[index].travel
+        } else {
+            0.0
+        }
+    }
+
+    /// Returns the seconds from the start to the end of the last keyframe's hold, which for
+    /// looping tracks starts with the move back to the first keyframe.
+    pub fn duration(&self) -> f32 {
+        (0..self.keys.len())
+            .map(|index| self.travel(index) + self.keys[index].hold)
+            .sum()
+    }
+
+    /// Returns the seconds into the track at which the object arrives at each keyframe.
+    pub fn arrivals(&self) -> Vec<f32> {
+        let mut time = 0.0;
+        (0..self.keys.len())
+            .map(|index| {
+                time += self.travel(index);
+                let arrival = time;
+                time += self.keys[index].hold;
+                arrival
+            })
+            .collect()
+    }
+
+    /// Returns where the object is `time` seconds into the track, `None` without keyframes.
+    /// Times past the end give the last keyframe unless the track loops.
+    pub fn sample(&self, time: f32) -> Option<ObjectPose> {
+        let last = self.keys.len().checked_sub(1)?;
+        let duration = self.duration();
+        let time = if self.looping && duration > 0.0 {
+            time.rem_euclid(duration)
+        } else {
+            time
+        };
+        let arrivals = self.arrivals();
+        // The keyframe the object is moving towards or holding at.
+        let next = arrivals
+            .iter()
+            .zip(&self.keys)
+            .position(|(arrival, key)| time < arrival + key.hold)
+            .unwrap_or(last);
+        let to = &self.keys[next];
+        let travel = self.travel(next);
+        if travel <= 0.0 || time >= arrivals[next] {
+            return Some(ObjectPose {
+                translation: to.translation,
+                rotation: to.rotation,
+            });
+        }
+        let from = &self.keys[if next == 0 { last } else { next - 1 }];
+        let t = to.easing.apply(1.0 - (arrivals[next] - time) / travel);
+        let lerp = |a: [f32; 3], b: [f32; 3]| [0, 1, 2].map(|i| a[i] + (b[i] - a[i]) * t);
+        Some(ObjectPose {
+            translation: lerp(from.translation, to.translation),
+            rotation: lerp(from.rotation, to.rotation),
+        })
+    }
+}
+
+/// Tracks moving voxel objects, by the index of the object they move, played back together.
+/// Moving platforms and doors loop, cutscenes play once.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Animation {
+    tracks: BTreeMap<usize, Track>,
+}
+
+impl Animation {
+    pub fn new() -> Animation {
+        Animation::default()
+    }
+
+    pub fn tracks(&self) -> impl Iterator<Item = (usize, &Track)> {
+        self.tracks.iter().map(|(&object, track)| (object, track))
+    }
+
+    /// Returns the track of the object at `object`, starting an empty one if it has none.
+    pub fn track_mut(&mut self, object: usize) -> &mut Track {
+        self.tracks.entry(object).or_default()
+    }
+
+    pub fn clear(&mut self) {
+        self.tracks.clear();
+    }
+
+    /// Returns whether every track ended by `time`, which looping tracks never do.
+    pub fn finished(&self, time: f32) -> bool {
+        self.tracks
+            .values()
+            .all(|track| !track.looping && time > track.duration())
+    }
+
+    /// Returns where each object with a track is `time` seconds into the animation.
+    pub fn sample(&self, time: f32) -> impl Iterator<Item = (usize, ObjectPose)> + '_ {
+        self.tracks
+            .iter()
+            .filter_map(move |(&object, track)| Some((object, track.sample(time)?)))
+    }
+
+    /// Moves every keyframe by `offset`, after the world moved under the objects.
+    pub fn shift(&mut self, offset: [f32; 3]) {
+        for key in self.tracks.values_mut().flat_map(|track| &mut track.keys) {
+            key.translation = [0, 1, 2].map(|i| key.translation[i] + offset[i]);
+        }
+    }
+}
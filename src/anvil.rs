@@ -0,0 +1,745 @@
+use crate::materials::MATERIAL_COUNT;
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Voxel types of the common Minecraft blocks, used when no mapping file is given.
+pub const DEFAULT_MAPPING: &str = include_str!("../assets/minecraft_blocks.txt");
+/// What `parse_mapping_line` expects.
+const MAPPING_SYNTAX: &str = "expected `block type`";
+/// Lowest block of a Minecraft world since 1.18, the bottom of imported maps.
+pub const MIN_Y: i32 = -64;
+/// Blocks between `MIN_Y` and the build limit, the height of imported maps. Worlds of older
+/// versions, from 0 to 256, fit as well.
+pub const HEIGHT: u32 = 384;
+/// Blocks in a chunk along x and z and in a section along every axis.
+const SECTION_SIZE: i32 = 16;
+/// Chunks in a region file along x and z.
+const REGION_CHUNKS: i32 = 32;
+/// Bytes in a sector of a region file, chunks start at whole sectors.
+const SECTOR: usize = 4096;
+/// First data version, 20w17a, whose block states don't span two longs.
+const PADDED_STATES_VERSION: i32 = 2529;
+/// Deepest nesting of NBT lists and compounds read, far beyond what chunks use.
+const MAX_NBT_DEPTH: usize = 64;
+/// Blocks which are always empty space.
+const AIR: [&str; 3] = ["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+
+/// Voxel types of Minecraft blocks, read from a file with one `block type` line per block, see
+/// `parse_mapping_line`. The first line matching a block wins, so specific lines go before the
+/// patterns they are exceptions of. Only the block's name counts, its properties are ignored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockMapping {
+    /// Name patterns and voxel types, in the order of the file.
+    rules: Vec<(String, u32)>,
+}
+
+impl BlockMapping {
+    pub fn load(path: impl AsRef<Path>) -> Result<BlockMapping, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("can't read block mapping `{}`: {err}", path.display()))?;
+        BlockMapping::parse(&text).map_err(|err| format!("{}:{err}", path.display()))
+    }
+
+    /// Parses the lines of a mapping file, errors start with the line number.
+    pub fn parse(text: &str) -> Result<BlockMapping, String> {
+        let mut rules = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            rules.push(parse_mapping_line(line).map_err(|err| format!("{}: {err}", number + 1))?);
+        }
+        Ok(BlockMapping { rules })
+    }
+
+    /// Returns the voxel type of the block `name`, 0 for air and blocks mapped to 0, `None`
+    /// for blocks no line matches.
+    pub fn voxel(&self, name: &str) -> Option<u32> {
+        if AIR.contains(&name) {
+            return Some(0);
+        }
+        self.rules
+            .iter()
+            .find(|(pattern, _)| matches(pattern, name))
+            .map(|(_, voxel)| *voxel)
+    }
+}
+
+impl Default for BlockMapping {
+    fn default() -> Self {
+        BlockMapping::parse(DEFAULT_MAPPING).expect("invalid default block mapping")
+    }
+}
+
+/// Parses a `block type` line. Blocks without a namespace are in `minecraft:`, a `*` in the
+/// name matches any text, so `*_log` maps the logs of every tree.
+pub fn parse_mapping_line(line: &str) -> Result<(String, u32), String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let [block, voxel] = words[..] else {
+        return Err(MAPPING_SYNTAX.to_string());
+    };
+    let voxel = voxel
+        .parse::<u32>()
+        .ok()
+        .filter(|&voxel| (voxel as usize) < MATERIAL_COUNT)
+        .ok_or(format!("invalid voxel type `{voxel}`"))?;
+    let block = if block.contains(':') || block.starts_with('*') {
+        block.to_string()
+    } else {
+        format!("minecraft:{block}")
+    };
+    Ok((block, voxel))
+}
+
+/// Returns whether `name` matches `pattern`, whose `*`s match any text.
+fn matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*`, the whole name has to match.
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+/// Rectangle of chunks of a Minecraft world, in chunk coordinates, both corners included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkArea {
+    pub min: [i32; 2],
+    pub max: [i32; 2],
+}
+
+impl ChunkArea {
+    /// Returns the area between the chunks `a` and `b`, in any order.
+    pub fn new(a: [i32; 2], b: [i32; 2]) -> ChunkArea {
+        ChunkArea {
+            min: [a[0].min(b[0]), a[1].min(b[1])],
+            max: [a[0].max(b[0]), a[1].max(b[1])],
+        }
+    }
+
+    /// Returns the size of the area in blocks, and of a map holding it, or an error when it
+    /// doesn't fit a map.
+    pub fn map_size(&self) -> Result<[u32; 3], String> {
+        let [x, z] = [0, 1].map(|i| {
+            let chunks = self.max[i] as i64 - self.min[i] as i64 + 1;
+            u32::try_from(chunks * SECTION_SIZE as i64).ok()
+        });
+        match (x, z) {
+            (Some(x), Some(z)) => Ok([x, HEIGHT, z]),
+            _ => Err(format!(
+                "area from chunk {:?} to {:?} too large for a map",
+                self.min, self.max
+            )),
+        }
+    }
+}
+
+/// What `import` read.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnvilImport {
+    /// Chunks read.
+    pub chunks: usize,
+    /// Chunks of the area which were never generated.
+    pub missing: usize,
+    /// Voxels visited.
+    pub voxels: u64,
+    /// Blocks no line of the mapping matches and how many were left out.
+    pub unmapped: BTreeMap<String, u64>,
+    /// Chunks which couldn't be read and why, left out of the import.
+    pub skipped: Vec<String>,
+}
+
+/// Returns the directory holding the region files of the Minecraft save `path`, which is either
+/// the save's directory or the region directory itself.
+pub fn region_directory(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    let region = path.join("region");
+    if region.is_dir() {
+        region
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Reads the chunks of `area` from the region files in `directory`, calling `visit` with the
+/// position of every block `mapping` gives a voxel type other than 0, within a map of
+/// `area.map_size()` with its bottom at `MIN_Y`, and the voxel type. Reads the Anvil format of
+/// Minecraft 1.13 and newer, in which blocks are named. Chunks which can't be read are skipped
+/// and listed, errors of `visit` end the import.
+pub fn import(
+    directory: impl AsRef<Path>,
+    area: ChunkArea,
+    mapping: &BlockMapping,
+    mut visit: impl FnMut([u32; 3], u32) -> Result<(), String>,
+) -> Result<AnvilImport, String> {
+    let directory = directory.as_ref();
+    // Block positions within the map fit.
+    area.map_size()?;
+    let mut import = AnvilImport::default();
+    let regions = area.min.map(|c| c.div_euclid(REGION_CHUNKS));
+    let last_regions = area.max.map(|c| c.div_euclid(REGION_CHUNKS));
+    for region_x in regions[0]..=last_regions[0] {
+        for region_z in regions[1]..=last_regions[1] {
+            let path = directory.join(format!("r.{region_x}.{region_z}.mca"));
+            let region = match fs::read(&path) {
+                Ok(region) => region,
+                Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+                Err(err) => return Err(format!("can't read `{}`: {err}", path.display())),
+            };
+            let first = [region_x, region_z].map(|c| c * REGION_CHUNKS);
+            let [min_x, min_z] = [0, 1].map(|i| area.min[i].max(first[i]));
+            let [max_x, max_z] = [0, 1].map(|i| area.max[i].min(first[i] + REGION_CHUNKS - 1));
+            // Along x innermost, so a row of chunks stays within the tiles of one z.
+            for chunk_z in min_z..=max_z {
+                for chunk_x in min_x..=max_x {
+                    let data = match read_chunk(directory, &region, [chunk_x, chunk_z]) {
+                        Ok(Some(data)) => data,
+                        Ok(None) => {
+                            import.missing += 1;
+                            continue;
+                        }
+                        Err(err) => {
+                            import
+                                .skipped
+                                .push(format!("chunk {chunk_x} {chunk_z}: {err}"));
+                            continue;
+                        }
+                    };
+                    let sections = match parse_nbt(&data).and_then(|chunk| sections(&chunk)) {
+                        Ok(sections) => sections,
+                        Err(err) => {
+                            import
+                                .skipped
+                                .push(format!("chunk {chunk_x} {chunk_z}: {err}"));
+                            continue;
+                        }
+                    };
+                    import.chunks += 1;
+                    let origin = [
+                        (chunk_x - area.min[0]) * SECTION_SIZE,
+                        (chunk_z - area.min[1]) * SECTION_SIZE,
+                    ];
+                    for section in sections {
+                        section.visit_blocks(mapping, &mut import, |[x, y, z], voxel| {
+                            let y = y - MIN_Y;
+                            if y < 0 || y >= HEIGHT as i32 {
+                                return Ok(());
+                            }
+                            visit([origin[0] + x, y, origin[1] + z].map(|c| c as u32), voxel)
+                        })?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(import)
+}
+
+/// Returns the uncompressed NBT of the chunk at `chunk`, in world chunk coordinates, from the
+/// region file `region`. `None` when the chunk was never generated.
+fn read_chunk(directory: &Path, region: &[u8], chunk: [i32; 2]) -> Result<Option<Vec<u8>>, String> {
+    let [x, z] = chunk.map(|c| c.rem_euclid(REGION_CHUNKS) as usize);
+    // Files of regions without chunks may be empty.
+    let Some(&[a, b, c, _]) = region
+        .get((x + z * REGION_CHUNKS as usize) * 4..)
+        .and_then(|entry| entry.get(..4))
+    else {
+        return Ok(None);
+    };
+    let offset = u32::from_be_bytes([0, a, b, c]) as usize * SECTOR;
+    if offset == 0 {
+        return Ok(None);
+    }
+    let header = region
+        .get(offset..offset + 5)
+        .ok_or("location beyond the end of the region file")?;
+    let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let compression = header[4];
+    // Chunks too large for the region file are stored in a file of their own.
+    let external;
+    let compressed = if compression & 0x80 != 0 {
+        let path = directory.join(format!("c.{}.{}.mcc", chunk[0], chunk[1]));
+        external =
+            fs::read(&path).map_err(|err| format!("can't read `{}`: {err}", path.display()))?;
+        &external[..]
+    } else {
+        region
+            .get(offset + 5..offset + 4 + length.max(1))
+            .ok_or("chunk beyond the end of the region file")?
+    };
+    match compression & 0x7f {
+        2 => miniz_oxide::inflate::decompress_to_vec_zlib(compressed)
+            .map(Some)
+            .map_err(|err| format!("invalid zlib data: {err}")),
+        3 => Ok(Some(compressed.to_vec())),
+        compression => Err(format!("unsupported compression {compression}")),
+    }
+}
+
+/// Blocks of a 16³ section of a chunk.
+struct Section {
+    /// Height of the section in sections, negative below 0.
+    y: i32,
+    /// Names of the blocks in the section.
+    palette: Vec<String>,
+    /// Indices into `palette` of the blocks, y, z, then x, packed into longs.
+    states: Vec<i64>,
+    /// Whether indices never span two longs, which leaves the high bits of longs unused.
+    padded: bool,
+}
+
+impl Section {
+    /// Calls `visit` with the position of every block of the section `mapping` gives a voxel
+    /// type other than 0, relative to the chunk, and the voxel type. Counts the blocks in
+    /// `import`.
+    fn visit_blocks(
+        &self,
+        mapping: &BlockMapping,
+        import: &mut AnvilImport,
+        mut visit: impl FnMut([i32; 3], u32) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let voxels: Vec<Option<u32>> = self
+            .palette
+            .iter()
+            .map(|name| mapping.voxel(name))
+            .collect();
+        if voxels.iter().all(|&voxel| voxel == Some(0)) {
+            return Ok(());
+        }
+        // A palette of one block leaves out the states.
+        let bits = if self.palette.len() > 1 {
+            (usize::BITS - (self.palette.len() - 1).leading_zeros()).max(4) as usize
+        } else {
+            0
+        };
+        let per_long = 64usize.checked_div(bits).unwrap_or(0);
+        for index in 0..(SECTION_SIZE * SECTION_SIZE * SECTION_SIZE) as usize {
+            let state = if bits == 0 {
+                0
+            } else if self.padded {
+                let long = self.states.get(index / per_long).copied().unwrap_or(0) as u64;
+                (long >> (index % per_long * bits)) as usize & ((1 << bits) - 1)
+            } else {
+                let bit = index * bits;
+                let low = self.states.get(bit / 64).copied().unwrap_or(0) as u64 >> (bit % 64);
+                let high = if bit % 64 + bits > 64 {
+                    (self.states.get(bit / 64 + 1).copied().unwrap_or(0) as u64) << (64 - bit % 64)
+                } else {
+                    0
+                };
+                (low | high) as usize & ((1 << bits) - 1)
+            };
+            let Some(&voxel) = voxels.get(state) else {
+                continue;
+            };
+            match voxel {
+                Some(0) => (),
+                Some(voxel) => {
+                    let index = index as i32;
+                    let position = [
+                        index % SECTION_SIZE,
+                        self.y * SECTION_SIZE + index / (SECTION_SIZE * SECTION_SIZE),
+                        index / SECTION_SIZE % SECTION_SIZE,
+                    ];
+                    visit(position, voxel)?;
+                    import.voxels += 1;
+                }
+                None => {
+                    *import
+                        .unmapped
+                        .entry(self.palette[state].clone())
+                        .or_default() += 1
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns the sections of a chunk, in the layout of 1.18 and newer, with sections and their
+/// block states in the chunk's root, or of 1.13 to 1.17, with them in its `Level`.
+fn sections(chunk: &Tag) -> Result<Vec<Section>, String> {
+    let version = match chunk.get("DataVersion") {
+        Some(Tag::Int(version)) => *version,
+        _ => 0,
+    };
+    let (list, legacy) = match (chunk.get("sections"), chunk.get("Level")) {
+        (Some(sections), _) => (sections, false),
+        (None, Some(level)) => (level.get("Sections").ok_or("no sections")?, true),
+        (None, None) => return Err("no sections".to_string()),
+    };
+    let Tag::List(list) = list else {
+        return Err("sections aren't a list".to_string());
+    };
+    let mut sections = Vec::new();
+    for section in list {
+        let y = match section.get("Y") {
+            Some(Tag::Byte(y)) => *y as i32,
+            Some(Tag::Int(y)) => *y,
+            _ => return Err("section without height".to_string()),
+        };
+        let (palette, states) = if legacy {
+            (section.get("Palette"), section.get("BlockStates"))
+        } else {
+            let states = section.get("block_states");
+            (
+                states.and_then(|states| states.get("palette")),
+                states.and_then(|states| states.get("data")),
+            )
+        };
+        // Sections of lighting only, above and below the blocks.
+        let Some(Tag::List(palette)) = palette else {
+            continue;
+        };
+        let palette = palette
+            .iter()
+            .map(|block| match block.get("Name") {
+                Some(Tag::String(name)) => Ok(name.clone()),
+                _ => Err("block without name".to_string()),
+            })
+            .collect::<Result<_, _>>()?;
+        let states = match states {
+            Some(Tag::LongArray(states)) => states.clone(),
+            None => Vec::new(),
+            _ => return Err("block states aren't longs".to_string()),
+        };
+        sections.push(Section {
+            y,
+            palette,
+            states,
+            padded: version >= PADDED_STATES_VERSION,
+        });
+    }
+    Ok(sections)
+}
+
+/// Value of Minecraft's Named Binary Tag format, the chunks' format.
+#[derive(Clone, Debug, PartialEq)]
+enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<u8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// Returns the value named `name` of a compound.
+    fn get(&self, name: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(values) => values
+                .iter()
+                .find(|(value, _)| value == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the root compound of NBT `bytes`.
+fn parse_nbt(bytes: &[u8]) -> Result<Tag, String> {
+    let mut reader = NbtReader { bytes, offset: 0 };
+    if reader.take(1)?[0] != 10 {
+        return Err("NBT doesn't start with a compound".to_string());
+    }
+    reader.string()?;
+    reader.payload(10, 0)
+}
+
+/// Reads the big endian values of NBT.
+struct NbtReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl NbtReader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset.saturating_add(count))
+            .ok_or("unexpected end of NBT")?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    /// Reads the length of an array or list, which may not be negative.
+    fn length(&mut self) -> Result<usize, String> {
+        let length = i32::from_be_bytes(self.array()?);
+        usize::try_from(length).map_err(|_| format!("negative length {length}"))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = u16::from_be_bytes(self.array()?) as usize;
+        // Modified UTF-8, which only differs for characters block names don't use.
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    /// Reads the value of a tag of type `id`, nested `depth` lists and compounds deep.
+    fn payload(&mut self, id: u8, depth: usize) -> Result<Tag, String> {
+        if depth > MAX_NBT_DEPTH {
+            return Err("NBT nested too deeply".to_string());
+        }
+        Ok(match id {
+            1 => Tag::Byte(self.take(1)?[0] as i8),
+            2 => Tag::Short(i16::from_be_bytes(self.array()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.array()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.array()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.array()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.array()?)),
+            7 => {
+                let length = self.length()?;
+                Tag::ByteArray(self.take(length)?.to_vec())
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let id = self.take(1)?[0];
+                let length = self.length()?;
+                // Every element takes a byte at least, which bounds lengths of broken files.
+                if length > self.bytes.len() - self.offset {
+                    return Err("unexpected end of NBT".to_string());
+                }
+                Tag::List(
+                    (0..length)
+                        .map(|_| self.payload(id, depth + 1))
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            10 => {
+                let mut values = Vec::new();
+                loop {
+                    let id = self.take(1)?[0];
+                    if id == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    values.push((name, self.payload(id, depth + 1)?));
+                }
+                Tag::Compound(values)
+            }
+            11 => {
+                let length = self.length()?;
+                let bytes = self.take(length.saturating_mul(4))?;
+                Tag::IntArray(
+                    bytes
+                        .chunks_exact(4)
+                        .map(|int| i32::from_be_bytes(int.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            12 => {
+                let length = self.length()?;
+                let bytes = self.take(length.saturating_mul(8))?;
+                Tag::LongArray(
+                    bytes
+                        .chunks_exact(8)
+                        .map(|long| i64::from_be_bytes(long.try_into().unwrap()))
+                        .collect(),
+                )
+            }
+            id => return Err(format!("invalid NBT tag {id}")),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// NBT of a root compound named `a` holding `payload`, which has to end it.
+    fn nbt(payload: &[u8]) -> Vec<u8> {
+        [&[10, 0, 1, b'a'][..], payload].concat()
+    }
+
+    /// A section at height 1 whose blocks cycle through `palette` blocks named `b0`, `b1` and
+    /// so on, with their indices packed the way the data version `padded` tells.
+    fn section(palette: usize, padded: bool) -> Section {
+        let bits = (usize::BITS - (palette - 1).leading_zeros()).max(4) as usize;
+        let blocks = (SECTION_SIZE * SECTION_SIZE * SECTION_SIZE) as usize;
+        let per_long = 64 / bits;
+        let longs = if padded {
+            blocks.div_ceil(per_long)
+        } else {
+            (blocks * bits).div_ceil(64)
+        };
+        let mut states = vec![0u64; longs];
+        for index in 0..blocks {
+            let state = (index % palette) as u64;
+            if padded {
+                states[index / per_long] |= state << (index % per_long * bits);
+            } else {
+                let bit = index * bits;
+                states[bit / 64] |= state << (bit % 64);
+                if bit % 64 + bits > 64 {
+                    states[bit / 64 + 1] |= state >> (64 - bit % 64);
+                }
+            }
+        }
+        Section {
+            y: 1,
+            palette: (0..palette).map(|i| format!("minecraft:b{i}")).collect(),
+            states: states.into_iter().map(|long| long as i64).collect(),
+            padded,
+        }
+    }
+
+    #[test]
+    fn nbt_reads_nested_values() {
+        // {a: {b: 7b, c: [2, 3]}}.
+        let bytes = nbt(&[
+            10, 0, 1, b'a', 1, 0, 1, b'b', 7, 9, 0, 1, b'c', 3, 0, 0, 0, 2, 0, 0, 0, 2, 0, 0, 0, 3,
+            0, 0,
+        ]);
+        let root = parse_nbt(&bytes).unwrap();
+        let inner = root.get("a").unwrap();
+        assert_eq!(inner.get("b"), Some(&Tag::Byte(7)));
+        assert_eq!(
+            inner.get("c"),
+            Some(&Tag::List(vec![Tag::Int(2), Tag::Int(3)]))
+        );
+    }
+
+    #[test]
+    fn nbt_rejects_truncated_compounds() {
+        let complete = nbt(&[1, 0, 1, b'b', 7, 0]);
+        assert!(parse_nbt(&complete).is_ok());
+        for end in 3..complete.len() {
+            assert_eq!(
+                parse_nbt(&complete[..end]),
+                Err("unexpected end of NBT".to_string()),
+                "{end} bytes"
+            );
+        }
+        // A list claiming more elements than there are bytes left.
+        let list = nbt(&[9, 0, 1, b'c', 1, 0x7f, 0xff, 0xff, 0xff, 0]);
+        assert_eq!(parse_nbt(&list), Err("unexpected end of NBT".to_string()));
+    }
+
+    #[test]
+    fn nbt_rejects_compounds_nested_too_deeply() {
+        let nested = |depth: usize| {
+            let mut payload = [10, 0, 0].repeat(depth);
+            payload.extend(vec![0; depth + 1]);
+            nbt(&payload)
+        };
+        assert!(parse_nbt(&nested(MAX_NBT_DEPTH)).is_ok());
+        assert_eq!(
+            parse_nbt(&nested(MAX_NBT_DEPTH + 1)),
+            Err("NBT nested too deeply".to_string())
+        );
+    }
+
+    #[test]
+    fn visit_blocks_unpacks_both_state_layouts() {
+        let mapping =
+            BlockMapping::parse(&(1..17).map(|i| format!("b{i} {i}\n")).collect::<String>())
+                .unwrap();
+        // 4 and 5 bits, the second with a block left unmapped, in the layout before data
+        // version 2529 and the padded one after it.
+        for (palette, bits) in [(16, 4), (18, 5)] {
+            for padded in [false, true] {
+                let section = section(palette, padded);
+                let mut import = AnvilImport::default();
+                let mut visited = Vec::new();
+                section
+                    .visit_blocks(&mapping, &mut import, |position, voxel| {
+                        visited.push((position, voxel));
+                        Ok(())
+                    })
+                    .unwrap();
+                let expected: Vec<([i32; 3], u32)> = (0..4096)
+                    .filter(|index| (1..17).contains(&(index % palette)))
+                    .map(|index| {
+                        let position =
+                            [index % 16, 16 + index / 256, index / 16 % 16].map(|c| c as i32);
+                        (position, (index % palette) as u32)
+                    })
+                    .collect();
+                assert_eq!(visited, expected, "{bits} bits, padded {padded}");
+                assert_eq!(import.voxels, expected.len() as u64);
+                let unmapped = if palette == 18 { 4096 / 18 } else { 0 };
+                assert_eq!(
+                    import.unmapped.get("minecraft:b17").copied().unwrap_or(0),
+                    unmapped,
+                    "{bits} bits, padded {padded}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn mapping_patterns_match_any_text() {
+        assert!(matches("minecraft:oak_log", "minecraft:oak_log"));
+        assert!(!matches("minecraft:oak_log", "minecraft:oak_logs"));
+        assert!(matches("minecraft:*_log", "minecraft:dark_oak_log"));
+        assert!(!matches("minecraft:*_log", "minecraft:oak_log_top"));
+        assert!(matches("*:stone", "mod:stone"));
+        assert!(matches("minecraft:*ore*", "minecraft:deepslate_iron_ore"));
+        assert!(matches("minecraft:*ore*", "minecraft:ore"));
+        assert!(!matches("minecraft:a*a", "minecraft:a"));
+        assert!(matches("*", "anything"));
+    }
+
+    #[test]
+    fn mapping_takes_the_first_matching_line() {
+        let mapping = BlockMapping::parse(
+            "# comment\n\noak_log 7\n*_log 5\nmod:glass 3\n*:glass 4\nstone 0\n",
+        )
+        .unwrap();
+        assert_eq!(mapping.voxel("minecraft:oak_log"), Some(7));
+        assert_eq!(mapping.voxel("minecraft:birch_log"), Some(5));
+        assert_eq!(mapping.voxel("mod:glass"), Some(3));
+        assert_eq!(mapping.voxel("other:glass"), Some(4));
+        assert_eq!(mapping.voxel("minecraft:stone"), Some(0));
+        assert_eq!(mapping.voxel("minecraft:cave_air"), Some(0));
+        assert_eq!(mapping.voxel("minecraft:dirt"), None);
+        assert_eq!(
+            BlockMapping::parse("stone 1\ndirt\n"),
+            Err(format!("2: {MAPPING_SYNTAX}"))
+        );
+        assert_eq!(
+            BlockMapping::parse("stone 32"),
+            Err("1: invalid voxel type `32`".to_string())
+        );
+        BlockMapping::default();
+    }
+
+    #[test]
+    fn map_size_rejects_areas_too_large_for_a_map() {
+        assert_eq!(
+            ChunkArea::new([2, -3], [-1, 4]).map_size(),
+            Ok([64, HEIGHT, 128])
+        );
+        assert!(ChunkArea::new([i32::MIN, 0], [i32::MAX, 0])
+            .map_size()
+            .is_err());
+        assert!(ChunkArea::new([0, 0], [1 << 28, 0]).map_size().is_err());
+    }
+}
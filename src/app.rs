@@ -1,15 +1,54 @@
-use crate::{fractal_compute_pipeline::Controller, place_over_frame::RenderPassPlaceOverFrame};
+use crate::{
+    audio::{Audio, EditSound},
+    autotune::{self, Autotuner, DEFAULT_AUTOTUNE_PATH, DEFAULT_GROUP_SIZE},
+    console::{Command, Console},
+    fractal_compute_pipeline::Controller,
+    frame_graph::{FrameGraph, FrameSample, MARKER_SWAPCHAIN, MARKER_TARGETS, MARKER_UPLOAD},
+    memory_budget::MemoryKind,
+    picking::Pick,
+    place_over_frame::RenderPassPlaceOverFrame,
+    settings::{Preset, Settings, Upscaler},
+    stats::{Scene, SessionStats, DEFAULT_STATS_PATH},
+    terrain_panel::TerrainPanel,
+    window_renderer::{WindowRenderer, WINDOW_SIZE},
+};
 use cgmath::Vector2;
-use std::{sync::Arc, time::Instant};
-use vulkano::{
-    command_buffer::allocator::StandardCommandBufferAllocator,
-    descriptor_set::allocator::StandardDescriptorSetAllocator, device::Queue,
-    memory::allocator::StandardMemoryAllocator, sync::GpuFuture,
+use egui_winit_vulkano::egui;
+use rvengine::{
+    agents::Agents,
+    animation::{Animation, ObjectKey},
+    autosave::Autosave,
+    camera::{self, camera_to_world, world_to_camera, Camera, Orbit},
+    clipping::ClipPlane,
+    demo,
+    detail_normals::DetailNormals,
+    flythrough::{Flythrough, Keyframe},
+    history::History,
+    inspect::Report,
+    layers::Layers,
+    light_bake::{LightBake, BAKE_EXTENSION},
+    materials::{self, Material, Palette, MATERIAL_COUNT},
+    objects::{self, Transform},
+    prefab::Prefab,
+    projectiles::Projectiles,
+    scene::{Bookmark, SceneFile},
+    screenshot::{self, ShotMetadata},
+    simulation::{Simulation, PORTAL},
+    symmetry::Symmetry,
+    tiled_map::MapStream,
+    vox_scene::VoxScene,
+    world::{box_between, CHUNK_SIZE, WORLD_SIZE},
+    worldgen::Terrain,
 };
-use vulkano_util::{
-    renderer::{DeviceImageView, VulkanoWindowRenderer},
-    window::WindowDescriptor,
+use std::{
+    f32::consts::FRAC_PI_2,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
+use vulkano::sync::GpuFuture;
+use vulkano_util::renderer::{DeviceImageView, SwapchainImageView};
 use winit::{
     dpi::PhysicalPosition,
     event::{
@@ -19,6 +58,28 @@ use winit::{
     window::Fullscreen,
 };
 
+mod decals;
+mod lighting;
+
+/// Voxels per second projectiles are fired at.
+const PROJECTILE_SPEED: f32 = 80.0;
+/// Radius in voxels of the craters projectiles blast.
+const CRATER_RADIUS: u32 = 3;
+/// Radians the view turns by per press of an arrow key.
+const TURN_STEP: f32 = 0.05;
+/// Radians per second the free camera rolls at while Q or E is held.
+const ROLL_SPEED: f32 = 1.5;
+/// Radians the orbiting camera circles by per pixel the mouse is dragged.
+const ORBIT_SENSITIVITY: f32 = 0.01;
+/// Factor the orbiting camera's distance changes by per line scrolled.
+const ZOOM_STEP: f32 = 0.9;
+/// Radians per second the model viewer's turntable spins the camera around the model.
+const TURNTABLE_SPEED: f32 = 0.5;
+/// Rotation around x and y the model viewer looks at a model from, a bit from above and aside.
+const MODEL_VIEW_ANGLES: [f32; 2] = [0.4, 0.7];
+/// Fraction of the usual speed the camera moves and turns at in photo mode.
+const PHOTO_SPEED: f32 = 0.1;
+
 pub struct FractalApp {
     controller_pipeline: Controller,
     pub place_over_frame: RenderPassPlaceOverFrame,
@@ -28,52 +89,1453 @@ pub struct FractalApp {
     frame_count: f32,
     avg_fps: f32,
     input_state: InputState,
+    settings: Settings,
+    /// Voxel type whose material is edited from the keyboard and which is placed.
+    selected_material: usize,
+    /// File the palette is saved to and loaded from.
+    palette_path: PathBuf,
+    /// Mirror planes placing and removing voxels is repeated across.
+    symmetry: Symmetry,
+    prefabs: Vec<Prefab>,
+    /// Index into `prefabs` of the prefab left clicks stamp instead of placing a voxel.
+    active_prefab: Option<usize>,
+    /// Latest pick under the cursor, placing voxels and stamping prefabs happens where it shows.
+    hover: Option<Pick>,
+    /// Opposite corner voxels of the box region commands operate on.
+    selection: [Option<[i32; 3]>; 2],
+    history: History,
+    /// Portal placed with the `portal` command waiting for the one it gets linked to.
+    unlinked_portal: Option<([i32; 3], [i32; 3])>,
+    /// Portal the camera came out of, it only goes through another one after leaving it.
+    camera_portal: Option<usize>,
+    /// World file, bookmarks and lights of the scene written by `scene save`. Its palette and
+    /// time of day are taken from the renderer when saving.
+    scene: SceneFile,
+    flythrough: Flythrough,
+    /// Seconds into the flythrough while it plays.
+    flight: Option<f32>,
+    /// Tracks moving the voxel objects.
+    animation: Animation,
+    /// Names of the layers materials and voxel objects are put on.
+    layers: Layers,
+    /// Seconds into the animation while it plays.
+    animation_time: Option<f32>,
+    console: Console,
+    stats: SessionStats,
+    /// File the session statistics are written to on exit, if any.
+    stats_path: Option<PathBuf>,
+    /// Bytes the world had uploaded when the previous frame was recorded in `stats`.
+    recorded_upload_bytes: u64,
+    frame_graph: FrameGraph,
+    show_frame_graph: bool,
+    /// When handling the input of the current frame started.
+    frame_start: Instant,
+    /// Milliseconds from handling input to submitting the latest frame.
+    cpu_time: f32,
+    /// `MARKER_*` bits of what happened during the current frame.
+    frame_markers: u32,
+    /// None without an audio device.
+    audio: Option<Audio>,
+    simulation: Simulation,
+    agents: Agents,
+    /// Shots fired from the camera.
+    projectiles: Projectiles,
+    /// How many times as fast as real time the time of day runs, 0 while it stands still.
+    time_speed: f32,
+    /// Times the trace shader's workgroup sizes while running.
+    autotuner: Option<Autotuner>,
+    /// Seed the world was generated from, `None` once a scene or demo replaced it.
+    world_seed: Option<u64>,
+    /// Whether photo mode is on, see `photo_mode`.
+    photo_mode: bool,
+    /// File the requested screenshot is saved to, with how it was taken.
+    pending_screenshot: Option<(PathBuf, ShotMetadata)>,
+    /// Whether screenshots get how they were taken drawn into their corner, see
+    /// `Screenshot::burn_in`.
+    annotate_shots: bool,
+    /// Whether screenshots leave the sky transparent, see
+    /// `screenshot::HdrScreenshot::mask_sky`. Only the HDR formats hold it, so shots without a
+    /// file name are saved as PNG.
+    sky_alpha: bool,
+    /// The camera circles this instead of walking or flying while set, see `Command::Orbit`.
+    orbit: Option<Orbit>,
+    /// Index of the agent the orbit follows.
+    orbit_agent: Option<usize>,
+    /// Center and bounding radius of the model shown in the model viewer, see `view_model`.
+    model: Option<([f32; 3], f32)>,
+    /// Whether the model viewer's camera spins around the model on its own.
+    turntable: bool,
+    autosave: Autosave,
+    /// Terrain the world was last generated with on the GPU.
+    terrain: Option<Terrain>,
+    terrain_panel: Option<TerrainPanel>,
+    /// Map larger than the world the world is a window of, moved along with the camera.
+    map: Option<MapStream>,
+}
+
+/// Edit applied to the voxel under the cursor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PickAction {
+    Remove,
+    /// Selects the material of the picked voxel.
+    Eyedropper,
+    /// Moves the mirror planes through the picked voxel.
+    SetMirrorOrigin,
+    /// Sets the first or second corner of the selection to the picked voxel.
+    SelectCorner(usize),
+}
+
+/// Change to the selected material requested from the keyboard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MaterialEdit {
+    /// Steps a color channel (0 red, 1 green, 2 blue).
+    Channel(usize),
+    Emissive,
+    Roughness,
 }
 
 impl FractalApp {
     pub fn new(
-        gfx_queue: Arc<Queue>,
-        image_format: vulkano::format::Format,
-        render_distance: u32,
+        mut controller_pipeline: Controller,
+        place_over_frame: RenderPassPlaceOverFrame,
+        settings: Settings,
+        palette: Palette,
+        palette_path: PathBuf,
+        prefabs: Vec<Prefab>,
+        stats_path: Option<PathBuf>,
     ) -> FractalApp {
-        let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(
-            gfx_queue.device().clone(),
-        ));
-        let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(
-            gfx_queue.device().clone(),
-            Default::default(),
-        ));
-        let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
-            gfx_queue.device().clone(),
-        ));
+        *controller_pipeline.palette_mut() = palette;
 
         FractalApp {
-            controller_pipeline: Controller::new(
-                gfx_queue.clone(),
-                memory_allocator.clone(),
-                command_buffer_allocator.clone(),
-                descriptor_set_allocator.clone(),
-                render_distance,
-            ),
-            place_over_frame: RenderPassPlaceOverFrame::new(
-                gfx_queue,
-                &memory_allocator,
-                command_buffer_allocator,
-                descriptor_set_allocator,
-                image_format,
-            ),
+            controller_pipeline,
+            place_over_frame,
             time: Instant::now(),
             dt: 0.0,
             dt_sum: 0.0,
             frame_count: 0.0,
             avg_fps: 0.0,
             input_state: InputState::new(),
+            settings,
+            selected_material: 1,
+            palette_path,
+            symmetry: Symmetry::new([WORLD_SIZE as i32 / 2; 3]),
+            prefabs,
+            active_prefab: None,
+            hover: None,
+            selection: [None; 2],
+            history: History::new(),
+            unlinked_portal: None,
+            camera_portal: None,
+            scene: SceneFile::default(),
+            flythrough: Flythrough::new(),
+            flight: None,
+            animation: Animation::new(),
+            layers: Layers::default(),
+            animation_time: None,
+            console: Console::new(),
+            stats: SessionStats::new(),
+            stats_path,
+            recorded_upload_bytes: 0,
+            frame_graph: FrameGraph::new(),
+            show_frame_graph: false,
+            frame_start: Instant::now(),
+            cpu_time: 0.0,
+            frame_markers: 0,
+            audio: Audio::new(),
+            simulation: Simulation::new(),
+            agents: Agents::new(rand::random()),
+            projectiles: Projectiles::new(CRATER_RADIUS),
+            time_speed: 0.0,
+            autotuner: None,
+            world_seed: None,
+            photo_mode: false,
+            pending_screenshot: None,
+            annotate_shots: false,
+            sky_alpha: false,
+            orbit: None,
+            orbit_agent: None,
+            model: None,
+            turntable: false,
+            autosave: Autosave::default(),
+            terrain: None,
+            terrain_panel: None,
+            map: None,
+        }
+    }
+
+    /// Times the trace shader with every candidate workgroup size over the coming frames, then
+    /// keeps the fastest and saves it for the GPU called `device`.
+    pub fn start_autotune(&mut self, device: String) {
+        println!("timing trace workgroup sizes on {device}");
+        self.autotuner = Some(Autotuner::new(device));
+    }
+
+    /// Runs our compute pipeline after `before` and return a future of when the compute is
+    /// finished.
+    pub fn compute(
+        &mut self,
+        image_target: DeviceImageView,
+        before: Box<dyn GpuFuture>,
+    ) -> Box<dyn GpuFuture> {
+        if let Some(autotuner) = &self.autotuner {
+            self.controller_pipeline
+                .set_trace_group_size(autotuner.group_size());
+        }
+        let settings = if self.photo_mode {
+            self.settings.photo()
+        } else {
+            self.settings
+        };
+        let future = self
+            .controller_pipeline
+            .compute(image_target, &settings, before);
+        let preset = self
+            .settings
+            .preset
+            .map_or("custom", |preset| preset.name());
+        if let Some(mut screenshot) = self.controller_pipeline.take_screenshot() {
+            if let Some((path, metadata)) = self.pending_screenshot.take() {
+                if self.annotate_shots {
+                    screenshot.burn_in(&metadata.caption(preset));
+                }
+                match screenshot.save(&path, &metadata) {
+                    Ok(()) => println!("saved screenshot to {}", path.display()),
+                    Err(err) => println!("{err}"),
+                }
+            }
+        }
+        if let Some(mut screenshot) = self.controller_pipeline.take_hdr_screenshot() {
+            if let Some((path, metadata)) = self.pending_screenshot.take() {
+                if self.annotate_shots {
+                    screenshot.burn_in(&metadata.caption(preset));
+                }
+                match screenshot.save(&path, &metadata) {
+                    Ok(()) => println!("saved HDR screenshot to {}", path.display()),
+                    Err(err) => println!("{err}"),
+                }
+            }
+        }
+        if let Some(pick) = self.controller_pipeline.take_pick() {
+            self.hover = (pick.voxel != 0).then_some(pick);
+        }
+        for count in self.controller_pipeline.take_ray_counts() {
+            println!(
+                "{:.2}% of {} primary rays ran out of steps (max ray steps {}, render distance {})",
+                count.truncated_percent(),
+                count.rays,
+                self.settings.max_ray_steps,
+                self.settings.render_distance
+            );
+        }
+        if self.controller_pipeline.take_targets_recreated() {
+            self.frame_markers |= MARKER_TARGETS;
+        }
+        future
+    }
+
+    /// Places `view`, the frame traced last, over `target` with the voxel objects drawn as
+    /// impostors and the frame graph while it is shown over it.
+    pub fn render_frame<F>(
+        &mut self,
+        before_future: F,
+        view: DeviceImageView,
+        target: SwapchainImageView,
+    ) -> Box<dyn GpuFuture>
+    where
+        F: GpuFuture + 'static,
+    {
+        let impostors = self.controller_pipeline.impostor_frame(&self.settings);
+        let frame_graph = (self.show_frame_graph && !self.photo_mode).then_some(&self.frame_graph);
+        self.place_over_frame.render(
+            before_future,
+            view,
+            target,
+            &self.settings,
+            impostors.as_ref(),
+            frame_graph,
+        )
+    }
+
+    /// Returns the frame graph while it is shown.
+    pub fn frame_graph(&self) -> Option<&FrameGraph> {
+        (self.show_frame_graph && !self.photo_mode).then_some(&self.frame_graph)
+    }
+
+    /// Returns whether photo mode is on: the simulation, agents, projectiles and time of day
+    /// stand still, the camera moves and turns slowly without going through portals, samples
+    /// accumulate and nothing is drawn over the image.
+    pub fn photo_mode(&self) -> bool {
+        self.photo_mode
+    }
+
+    /// Remembers the seed the world was generated from, for the metadata of screenshots.
+    pub fn set_world_seed(&mut self, seed: u64) {
+        self.world_seed = Some(seed);
+    }
+
+    /// Returns how the current view is taken, for a screenshot.
+    fn shot_metadata(&self) -> ShotMetadata {
+        let rotation = self.controller_pipeline.rotation;
+        ShotMetadata {
+            seed: self.world_seed,
+            eye: camera_to_world(self.controller_pipeline.position, rotation),
+            rotation,
+            fov: camera::fov(self.controller_pipeline.camera_dir),
+            lens: self.controller_pipeline.lens,
+            time_of_day: self.controller_pipeline.time_of_day,
+        }
+    }
+
+    /// Returns the distance along the view to the voxel under the cursor, if any.
+    fn hover_focus_distance(&self) -> Option<f32> {
+        let hover = self.hover?;
+        let camera = Camera {
+            position: self.controller_pipeline.position,
+            rotation: self.controller_pipeline.rotation,
+        };
+        let eye = camera.eye();
+        let forward = camera.forward();
+        let length = forward.iter().map(|f| f * f).sum::<f32>().sqrt();
+        let center = hover.position.map(|c| c as f32 + 0.5);
+        Some(
+            (0..3)
+                .map(|i| (center[i] - eye[i]) * forward[i])
+                .sum::<f32>()
+                / length,
+        )
+    }
+
+    /// Saves the next finished frame with how it was taken, to `path` or a file named after the
+    /// time. Paths ending in one of `screenshot::HDR_EXTENSIONS` get the accumulated samples
+    /// unclamped, which needs accumulation on, and only those can leave the sky transparent.
+    fn request_screenshot(&mut self, path: Option<String>) {
+        let path = path.map(PathBuf::from).unwrap_or_else(|| {
+            let seconds = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs());
+            let extension = if self.sky_alpha { "png" } else { "ppm" };
+            PathBuf::from(format!("rayvox-{seconds}.{extension}"))
+        });
+        if screenshot::is_hdr_path(&path) {
+            if !self.settings.accumulate && !self.photo_mode {
+                println!("HDR screenshots need accumulation, turn photo mode on first");
+                return;
+            }
+            self.controller_pipeline
+                .request_hdr_screenshot(self.sky_alpha);
+        } else if self.sky_alpha {
+            println!(
+                "can't leave the sky transparent in `{}`, expected a .exr or .png file",
+                path.display()
+            );
+            return;
+        } else {
+            self.controller_pipeline.request_screenshot();
+        }
+        self.pending_screenshot = Some((path, self.shot_metadata()));
+    }
+
+    /// Replaces the autosave settings, see `cli::Args::autosave`.
+    pub fn set_autosave(&mut self, autosave: Autosave) {
+        self.autosave = autosave;
+    }
+
+    /// Reports a finished autosave and starts the next one when it is due, or right away when
+    /// `now`.
+    fn autosave(&mut self, now: bool) {
+        if let Some(result) = self.autosave.poll() {
+            match result {
+                Ok(path) => println!("autosaved to {}", path.display()),
+                Err(err) => println!("{err}"),
+            }
+        }
+        let world = self.controller_pipeline.world();
+        if !self.autosave.tick(self.dt, world) && !now {
+            return;
+        }
+        let mut scene = self.scene.clone();
+        scene.palette = self.controller_pipeline.palette().clone();
+        scene.time_of_day = self.controller_pipeline.time_of_day;
+        let rotation = self.controller_pipeline.rotation;
+        let eye = camera_to_world(self.controller_pipeline.position, rotation);
+        self.autosave.save(world, &scene, eye, rotation);
+    }
+
+    /// Explores the map `stream` reads, the world holding the window at its origin. The camera
+    /// starts in the middle of the world.
+    pub fn explore_map(&mut self, stream: MapStream) {
+        let [x, y, z] = stream.origin();
+        println!("exploring the map from {x} {y} {z}");
+        self.map = Some(stream);
+        let rotation = self.controller_pipeline.rotation;
+        let center = [WORLD_SIZE as f32 / 2.0; 3];
+        self.controller_pipeline.position = world_to_camera(center, rotation);
+    }
+
+    /// Moves the world on along the map when the camera nears its edge, see `MapStream`, and
+    /// the camera back by as much, so it stays where it was in the map.
+    fn stream_map(&mut self) {
+        let Some(mut map) = self.map.take() else {
+            return;
+        };
+        let rotation = self.controller_pipeline.rotation;
+        let eye = camera_to_world(self.controller_pipeline.position, rotation);
+        map.update(eye);
+        match map.poll() {
+            Some(Ok(window)) => {
+                self.controller_pipeline.world_mut().set_region(
+                    [0; 3],
+                    [WORLD_SIZE; 3],
+                    &window.voxels,
+                );
+                // Voxel objects, their animation, decals and clipping planes stay where they were
+                // on the map.
+                let mut objects = self.controller_pipeline.objects().to_vec();
+                let mut decals = self.controller_pipeline.decals().clone();
+                let mut clip_planes = self.controller_pipeline.clip_planes().clone();
+                let mut animation = std::mem::take(&mut self.animation);
+                let animation_time = self.animation_time;
+                self.forget_world();
+                let moved = window.moved.map(|c| c as f32);
+                for object in &mut objects {
+                    let translation = &mut object.transform.translation;
+                    *translation = [0, 1, 2].map(|i| translation[i] - moved[i]);
+                }
+                self.controller_pipeline.set_objects(objects);
+                animation.shift(moved.map(|c| -c));
+                self.animation = animation;
+                decals.shift(moved.map(|c| -c));
+                *self.controller_pipeline.decals_mut() = decals;
+                clip_planes.shift(moved.map(|c| -c));
+                *self.controller_pipeline.clip_planes_mut() = clip_planes;
+                self.animation_time = animation_time;
+                let eye = [0, 1, 2].map(|i| eye[i] - moved[i]);
+                self.controller_pipeline.position = world_to_camera(eye, rotation);
+                if let Some(orbit) = &mut self.orbit {
+                    orbit.focus = [0, 1, 2].map(|i| orbit.focus[i] - moved[i]);
+                }
+                let [x, y, z] = map.origin();
+                println!("moved the world to {x} {y} {z} of the map");
+            }
+            Some(Err(err)) => println!("{err}"),
+            None => (),
+        }
+        self.map = Some(map);
+    }
+
+    /// Turns the app into a model viewer for the model `name` filling `size` voxels from `min`,
+    /// which the world holds on its own: the camera orbits the model and frames it, the world
+    /// stands still and T spins a turntable, P takes a screenshot and Home frames the model again.
+    pub fn view_model(&mut self, name: &str, min: [i32; 3], size: [u32; 3]) {
+        let center = [0, 1, 2].map(|i| min[i] as f32 + size[i] as f32 / 2.0);
+        let radius = size
+            .map(|size| size as f32 * size as f32)
+            .iter()
+            .sum::<f32>()
+            .sqrt()
+            / 2.0;
+        self.model = Some((center, radius));
+        self.world_seed = None;
+        self.input_state.viewing_model = true;
+        self.frame_model();
+        println!(
+            "viewing {name}: drag with the middle button to orbit, scroll to zoom, T turntable, \
+             P screenshot, Home frame the model"
+        );
+    }
+
+    /// Points the orbit camera at the model viewer's model from far enough away to see all of it.
+    fn frame_model(&mut self) {
+        let Some((center, radius)) = self.model else {
+            return;
+        };
+        let [pitch, yaw] = MODEL_VIEW_ANGLES;
+        let mut orbit = Orbit {
+            focus: center,
+            distance: radius,
+            pitch,
+            yaw,
+        };
+        let [width, height] = self.input_state.window_size;
+        orbit.fit(radius, self.controller_pipeline.camera_dir, width / height);
+        self.orbit = Some(orbit);
+        self.orbit_agent = None;
+        self.input_state.free_camera = false;
+        self.input_state.orbiting = true;
+    }
+
+    /// Sets the camera up like it was for the screenshot at `path`.
+    fn load_shot(&mut self, path: &str) -> Result<(), String> {
+        let shot = ShotMetadata::load(path)?;
+        let controller = &mut self.controller_pipeline;
+        controller.rotation = shot.rotation;
+        controller.position = world_to_camera(shot.eye, shot.rotation);
+        controller.camera_dir = camera::camera_dir(shot.fov);
+        controller.lens = shot.lens;
+        controller.time_of_day = shot.time_of_day;
+        if let Some(seed) = shot.seed.filter(|&seed| Some(seed) != self.world_seed) {
+            println!("the shot was taken in a world generated from seed {seed}");
         }
+        Ok(())
     }
 
-    /// Runs our compute pipeline and return a future of when the compute is finished.
-    pub fn compute(&self, image_target: DeviceImageView) -> Box<dyn GpuFuture> {
-        self.controller_pipeline.compute(image_target)
+    /// Ends the CPU side of the frame, called right before it is presented.
+    pub fn end_cpu_frame(&mut self) {
+        self.cpu_time = self.frame_start.elapsed().as_secs_f32() * 1000.0;
+    }
+
+    fn apply_pick_action(&mut self, action: PickAction, pick: Pick) {
+        println!(
+            "picked voxel {} at {:?} (normal {:?}, distance {:.2}, pixel {:?})",
+            pick.voxel, pick.position, pick.normal, pick.distance, pick.pixel
+        );
+        match action {
+            PickAction::Remove => {
+                let boxes = self.mirrored_boxes(pick.position, [1; 3]);
+                self.history
+                    .record(self.controller_pipeline.world(), &boxes);
+                self.set_voxel_mirrored(pick.position, 0);
+                self.play_edit_sound(pick.position, [1; 3], pick.voxel, EditSound::Break);
+            }
+            PickAction::Eyedropper => {
+                if (pick.voxel as usize) < MATERIAL_COUNT {
+                    self.selected_material = pick.voxel as usize;
+                }
+            }
+            PickAction::SetMirrorOrigin => self.symmetry.origin = pick.position,
+            PickAction::SelectCorner(corner) => {
+                self.selection[corner] = Some(pick.position);
+                if let Some((min, max)) = self.selection_box() {
+                    println!("selected {min:?} to {max:?}");
+                }
+            }
+        }
+    }
+
+    /// Returns the boxes of `size` voxels starting at `min` and at its mirror images.
+    fn mirrored_boxes(&self, min: [i32; 3], size: [u32; 3]) -> Vec<([i32; 3], [i32; 3])> {
+        self.symmetry
+            .positions(min)
+            .into_iter()
+            .map(|min| (min, [0, 1, 2].map(|i| min[i] + size[i] as i32)))
+            .collect()
+    }
+
+    /// Describes the voxel under the cursor: its position, chunk, type, distance and the
+    /// brightness of its lighting, or "-" over sky.
+    pub fn target_info(&self) -> String {
+        let Some(hover) = self.hover else {
+            return "-".to_string();
+        };
+        let chunk = hover.position.map(|c| c.div_euclid(CHUNK_SIZE as i32));
+        let name =
+            materials::type_name(hover.voxel).map_or(String::new(), |name| format!(" {name}"));
+        let light = hover.light.map_or("-".to_string(), |light| {
+            format!("{:.2}", (light[0] + light[1] + light[2]) / 3.0)
+        });
+        format!(
+            "{:?} chunk {chunk:?} type {}{name} at {:.1} light {light}",
+            hover.position, hover.voxel, hover.distance
+        )
+    }
+
+    /// Returns the selected box from its lower (inclusive) to its upper (exclusive) corner,
+    /// clipped to the world, once both corners are set.
+    pub fn selection_box(&self) -> Option<([u32; 3], [u32; 3])> {
+        let [Some(a), Some(b)] = self.selection else {
+            return None;
+        };
+        Some(box_between(a, b))
+    }
+
+    /// Writes the session statistics to `path`, printing what went wrong if it failed.
+    fn save_stats(&self, path: &Path) {
+        let scene = Scene {
+            world_size: WORLD_SIZE,
+            solid_voxels: self.controller_pipeline.world().solid_voxels(),
+            resolution: self.input_state.window_size.map(|d| d as u32),
+            settings: self.settings,
+            memory_budget: self.controller_pipeline.memory_budget().budget(),
+            memory_used: MemoryKind::ALL.map(|kind| {
+                (
+                    kind.name(),
+                    self.controller_pipeline.memory_budget().used_by(kind),
+                )
+            }),
+        };
+        match self.stats.save(path, &scene) {
+            Ok(()) => println!("saved stats to {}", path.display()),
+            Err(err) => println!("{err}"),
+        }
+    }
+
+    /// Writes the session statistics to the file given with `--stats`, if any. Called on exit.
+    pub fn save_stats_on_exit(&self) {
+        if let Some(path) = &self.stats_path {
+            self.save_stats(path);
+        }
+    }
+
+    /// Runs a line typed into the console, printing what went wrong if it failed.
+    fn run_command(&mut self, line: &str) {
+        let command = match Command::parse(line) {
+            Ok(command) => command,
+            Err(err) => {
+                println!("{err}");
+                return;
+            }
+        };
+        match command {
+            Command::Deselect => self.selection = [None; 2],
+            Command::Stats(path) => {
+                let path = path
+                    .map(PathBuf::from)
+                    .or_else(|| self.stats_path.clone())
+                    .unwrap_or_else(|| DEFAULT_STATS_PATH.into());
+                self.save_stats(&path);
+            }
+            Command::Gravity => {
+                self.simulation.falling_blocks = !self.simulation.falling_blocks;
+                if self.simulation.falling_blocks {
+                    println!("sand and gravel fall when unsupported");
+                } else {
+                    println!("sand and gravel stay where they are");
+                }
+            }
+            Command::Terrain(seed) => self.generate_terrain(seed.unwrap_or_else(rand::random)),
+            Command::TerrainPanel(enabled) => {
+                self.terrain_panel = enabled.then(|| TerrainPanel::new(self.last_terrain()))
+            }
+            Command::Erode(steps) => {
+                println!("generating terrain with {steps} steps of erosion");
+                let terrain = self.last_terrain();
+                self.generate(Terrain {
+                    erosion: steps,
+                    ..terrain
+                });
+            }
+            Command::Caves(width, worms) => {
+                println!("generating terrain with caves {width} wide and {worms} entrances");
+                let terrain = self.last_terrain();
+                self.generate(Terrain {
+                    caves: width.max(0.0),
+                    worms,
+                    ..terrain
+                });
+            }
+            Command::Demo(name) => self.load_demo(&name),
+            Command::SaveScene(path) => self.save_scene(Path::new(&path)),
+            Command::LoadScene(path) => self.load_scene(Path::new(&path)),
+            Command::Bookmark(name) => {
+                let rotation = self.controller_pipeline.rotation;
+                self.scene.set_bookmark(Bookmark {
+                    name,
+                    eye: camera_to_world(self.controller_pipeline.position, rotation),
+                    rotation,
+                });
+            }
+            Command::Goto(name) => match self.scene.bookmark(&name) {
+                Some(bookmark) => {
+                    let bookmark = bookmark.clone();
+                    self.go_to(&bookmark);
+                }
+                None => println!("no bookmark called `{name}`"),
+            },
+            Command::Fov(fov) => self.controller_pipeline.camera_dir = camera::camera_dir(fov),
+            Command::FlyKey {
+                travel,
+                hold,
+                easing,
+            } => {
+                let rotation = self.controller_pipeline.rotation;
+                self.flythrough.push(Keyframe {
+                    eye: camera_to_world(self.controller_pipeline.position, rotation),
+                    rotation,
+                    fov: camera::fov(self.controller_pipeline.camera_dir),
+                    travel,
+                    hold,
+                    easing,
+                });
+            }
+            Command::FlyPlay => {
+                if self.flythrough.keyframes().is_empty() {
+                    println!("add keyframes with `fly key` first");
+                } else {
+                    self.flight = Some(0.0);
+                }
+            }
+            Command::FlyStop => self.flight = None,
+            Command::FlyList => {
+                let arrivals = self.flythrough.arrivals();
+                for (index, (keyframe, arrival)) in
+                    self.flythrough.keyframes().iter().zip(arrivals).enumerate()
+                {
+                    println!(
+                        "{index}: {arrival:.1}s eye {:?} fov {:.0} travel {}s {} hold {}s",
+                        keyframe.eye.map(|c| c.round()),
+                        keyframe.fov,
+                        keyframe.travel,
+                        keyframe.easing.name(),
+                        keyframe.hold
+                    );
+                }
+                println!(
+                    "{} {:.1}s",
+                    self.flythrough.timeline(0.0, 40),
+                    self.flythrough.duration()
+                );
+            }
+            Command::FlyClear => {
+                self.flythrough.clear();
+                self.flight = None;
+            }
+            Command::PostProcess(path) => {
+                if let Err(err) = self
+                    .controller_pipeline
+                    .load_post_process(path.as_deref().map(Path::new))
+                {
+                    println!("{err}");
+                }
+            }
+            Command::TraceShader(path) => {
+                if let Err(err) = self
+                    .controller_pipeline
+                    .load_trace_shader(path.as_deref().map(Path::new))
+                {
+                    println!("{err}");
+                }
+            }
+            Command::Agents(count) => {
+                let spawned = self.agents.spawn(self.controller_pipeline.world(), count);
+                println!("spawned {spawned} agents, {} wandering", self.agents.len());
+            }
+            Command::ClearAgents => self.agents.clear(),
+            Command::Bake(samples) => self.bake_lighting(samples),
+            Command::BakedLighting(enabled) => self.set_baked_lighting(enabled),
+            Command::AddProbe => self.add_probe(),
+            Command::RemoveProbe(index) => {
+                if let Err(err) = self
+                    .controller_pipeline
+                    .reflection_probes_mut()
+                    .remove(index)
+                {
+                    println!("{err}");
+                }
+            }
+            Command::ClearProbes => self.controller_pipeline.reflection_probes_mut().clear(),
+            Command::ListProbes => self.list_probes(),
+            Command::RenderProbes => self.controller_pipeline.render_reflection_probes(),
+            Command::Metalness(metalness) => {
+                let selected = self.selected_material;
+                self.controller_pipeline.palette_mut().materials[selected].metalness = metalness;
+            }
+            Command::DetailNormals(path) => match path {
+                Some(path) => match DetailNormals::load(&path) {
+                    Ok(normals) => self.controller_pipeline.set_detail_normals(normals),
+                    Err(err) => println!("{err}"),
+                },
+                None => self
+                    .controller_pipeline
+                    .set_detail_normals(DetailNormals::default()),
+            },
+            Command::MaterialDetail(enabled) => {
+                let selected = self.selected_material;
+                self.controller_pipeline.palette_mut().materials[selected].detail_normals = enabled;
+            }
+            Command::ChunkOverlay(enabled) => self.settings.chunk_overlay = enabled,
+            Command::AxisGizmo(enabled) => self.settings.axis_gizmo = enabled,
+            Command::FreeCamera(enabled) => {
+                self.orbit = None;
+                self.input_state.orbiting = false;
+                self.input_state.free_camera = enabled;
+                self.input_state.roll_left = false;
+                self.input_state.roll_right = false;
+            }
+            Command::Orbit(agent) => {
+                let focus = match agent {
+                    Some(index) => self.agents.position(index),
+                    None => self
+                        .hover
+                        .map(|hover| hover.position.map(|c| c as f32 + 0.5)),
+                };
+                let Some(focus) = focus else {
+                    match agent {
+                        Some(index) => println!("there is no agent {index}"),
+                        None => println!("nothing under the cursor to orbit around"),
+                    }
+                    return;
+                };
+                let camera = Camera {
+                    position: self.controller_pipeline.position,
+                    rotation: self.controller_pipeline.rotation,
+                };
+                self.orbit = Some(Orbit::around(&camera, focus));
+                self.orbit_agent = agent;
+                self.input_state.free_camera = false;
+                self.input_state.orbiting = true;
+            }
+            Command::Autosave(interval) => self.autosave.set_interval(interval),
+            Command::AutosaveNow => self.autosave(true),
+            Command::LoadObjects(path) => match self.load_objects(&path) {
+                Ok(count) => println!("loaded {count} objects from {path}"),
+                Err(err) => println!("{err}"),
+            },
+            Command::ScatterObjects(path, count) => match Prefab::load(&path) {
+                Ok(model) => {
+                    let scattered = objects::scatter(
+                        &Arc::new(model),
+                        count,
+                        self.controller_pipeline.world(),
+                        &mut rand::thread_rng(),
+                    );
+                    println!("scattered {} objects", scattered.len());
+                    let mut placed = self.controller_pipeline.objects().to_vec();
+                    placed.extend(scattered);
+                    self.controller_pipeline.set_objects(placed);
+                }
+                Err(err) => println!("{err}"),
+            },
+            Command::ClearObjects => {
+                self.controller_pipeline.set_objects(Vec::new());
+                self.animation.clear();
+                self.animation_time = None;
+            }
+            Command::ListObjects => {
+                for (index, object) in self.controller_pipeline.objects().iter().enumerate() {
+                    let Transform {
+                        translation,
+                        rotation,
+                        scale,
+                    } = object.transform;
+                    let [x, y, z] = object.model.size;
+                    println!(
+                        "{index}: {} ({x}x{y}x{z}) at {:?} turned {:?} scaled {scale}",
+                        object.model.name,
+                        translation.map(|c| c.round()),
+                        rotation.map(|angle| angle.to_degrees().round()),
+                    );
+                }
+            }
+            Command::ObjectDistance(distance) => {
+                self.settings.object_distance = distance;
+                self.settings.preset = None;
+            }
+            Command::ImpostorDistance(distance) => {
+                self.settings.impostor_distance = distance.unwrap_or(0);
+                self.settings.preset = None;
+            }
+            Command::MoveObject(index, translation) => {
+                self.edit_object(index, |transform| transform.translation = translation)
+            }
+            Command::TurnObject(index, degrees) => self.edit_object(index, |transform| {
+                transform.rotation = degrees.map(f32::to_radians)
+            }),
+            Command::ScaleObject(index, scale) => {
+                self.edit_object(index, |transform| transform.scale = scale)
+            }
+            Command::ObjectLayer(index, name) => match self.layers.find(&name) {
+                Ok(_) if index >= self.controller_pipeline.objects().len() => {
+                    println!("no object {index}")
+                }
+                Ok(layer) => self.controller_pipeline.set_object_layer(index, layer),
+                Err(err) => println!("{err}"),
+            },
+            Command::AnimKey {
+                object,
+                travel,
+                hold,
+                easing,
+            } => match self.controller_pipeline.objects().get(object) {
+                Some(placed) => self.animation.track_mut(object).push(ObjectKey {
+                    translation: placed.transform.translation,
+                    rotation: placed.transform.rotation,
+                    travel,
+                    hold,
+                    easing,
+                }),
+                None => println!("no object {object}"),
+            },
+            Command::AnimLoop(object, looping) => {
+                self.animation.track_mut(object).looping = looping
+            }
+            Command::AnimPlay => {
+                if self.animation.tracks().next().is_none() {
+                    println!("add keyframes with `anim key` first");
+                } else {
+                    self.animation_time = Some(0.0);
+                }
+            }
+            Command::AnimStop => self.animation_time = None,
+            Command::AnimList => {
+                for (object, track) in self.animation.tracks() {
+                    println!(
+                        "object {object}: {:.1}s{}",
+                        track.duration(),
+                        if track.looping { " looping" } else { "" }
+                    );
+                    for (index, (key, arrival)) in
+                        track.keys().iter().zip(track.arrivals()).enumerate()
+                    {
+                        println!(
+                            "  {index}: {arrival:.1}s at {:?} turned {:?} travel {}s {} hold {}s",
+                            key.translation.map(|c| c.round()),
+                            key.rotation.map(|angle| angle.to_degrees().round()),
+                            key.travel,
+                            key.easing.name(),
+                            key.hold
+                        );
+                    }
+                }
+            }
+            Command::AnimClear => {
+                self.animation.clear();
+                self.animation_time = None;
+            }
+            Command::AddDecal(radius, opacity) => self.add_decal(radius, opacity),
+            Command::DecalPath(radius, opacity) => self.add_decal_path(radius, opacity),
+            Command::RemoveDecal(index) => {
+                if let Err(err) = self.controller_pipeline.decals_mut().remove(index) {
+                    println!("{err}");
+                }
+            }
+            Command::ClearDecals => self.controller_pipeline.decals_mut().clear(),
+            Command::ListDecals => self.list_decals(),
+            Command::AddClipPlane(normal) => {
+                // The middle of the face under the cursor, or the eye.
+                let point = match self.hover.filter(|hover| hover.voxel != 0) {
+                    Some(hover) => [0, 1, 2]
+                        .map(|i| hover.position[i] as f32 + 0.5 + hover.normal[i] as f32 * 0.5),
+                    None => camera_to_world(
+                        self.controller_pipeline.position,
+                        self.controller_pipeline.rotation,
+                    ),
+                };
+                let added = ClipPlane::through(point, normal)
+                    .and_then(|plane| self.controller_pipeline.clip_planes_mut().add(plane));
+                match added {
+                    Ok(index) => println!("clipping plane {index} through {point:?}"),
+                    Err(err) => println!("{err}"),
+                }
+            }
+            Command::RemoveClipPlane(index) => {
+                if let Err(err) = self.controller_pipeline.clip_planes_mut().remove(index) {
+                    println!("{err}");
+                }
+            }
+            Command::ClearClipPlanes => self.controller_pipeline.clip_planes_mut().clear(),
+            Command::ListClipPlanes => {
+                for (index, plane) in self
+                    .controller_pipeline
+                    .clip_planes()
+                    .planes()
+                    .iter()
+                    .enumerate()
+                {
+                    println!(
+                        "{index}: normal {:?} distance {}",
+                        plane.normal, plane.distance
+                    );
+                }
+            }
+            Command::ClipCap(color) => self.controller_pipeline.clip_planes_mut().cap = color,
+            Command::ListLayers => {
+                let materials = &self.controller_pipeline.palette().materials;
+                for (layer, name) in self.layers.names().iter().enumerate() {
+                    let types: Vec<String> = (1..MATERIAL_COUNT)
+                        .filter(|&voxel| materials[voxel].layer == layer as u32)
+                        .map(|voxel| voxel.to_string())
+                        .collect();
+                    let hidden = self.settings.hidden_layers & (1 << layer) != 0;
+                    println!(
+                        "{layer}: {name}{} types [{}]",
+                        if hidden { " (hidden)" } else { "" },
+                        types.join(" ")
+                    );
+                }
+            }
+            Command::AddLayer(name) => {
+                if let Err(err) = self.layers.add(&name) {
+                    println!("{err}");
+                }
+            }
+            Command::HideLayer(name) => match self.layers.find(&name) {
+                Ok(layer) => self.settings.hidden_layers |= 1 << layer,
+                Err(err) => println!("{err}"),
+            },
+            Command::ShowLayer(None) => self.settings.hidden_layers = 0,
+            Command::ShowLayer(Some(name)) => match self.layers.find(&name) {
+                Ok(layer) => self.settings.hidden_layers &= !(1 << layer),
+                Err(err) => println!("{err}"),
+            },
+            Command::AssignLayer(name) => match self.layers.find(&name) {
+                Ok(layer) => {
+                    self.controller_pipeline.palette_mut().materials[self.selected_material].layer =
+                        layer
+                }
+                Err(err) => println!("{err}"),
+            },
+            Command::Photo(enabled) => {
+                self.photo_mode = enabled;
+                if enabled {
+                    self.flight = None;
+                }
+            }
+            Command::Screenshot(path) => self.request_screenshot(path),
+            Command::AnnotateShots(enabled) => self.annotate_shots = enabled,
+            Command::SkyAlpha(enabled) => self.sky_alpha = enabled,
+            Command::LoadShot(path) => {
+                if let Err(err) = self.load_shot(&path) {
+                    println!("{err}");
+                }
+            }
+            Command::Roll(degrees) => {
+                let mut camera = Camera {
+                    position: self.controller_pipeline.position,
+                    rotation: self.controller_pipeline.rotation,
+                };
+                camera.roll_to(degrees.to_radians());
+                self.controller_pipeline.position = camera.position;
+                self.controller_pipeline.rotation = camera.rotation;
+            }
+            Command::DepthOfField(aperture, distance) => {
+                let distance = distance.or_else(|| self.hover_focus_distance());
+                let lens = &mut self.controller_pipeline.lens;
+                lens.aperture = aperture;
+                match distance {
+                    Some(distance) => lens.focus_distance = distance,
+                    None if aperture > 0.0 => println!(
+                        "nothing under the cursor to focus on, keeping {}",
+                        lens.focus_distance
+                    ),
+                    None => {}
+                }
+            }
+            Command::StepLog(enabled) => {
+                self.settings.step_log = enabled;
+                // The pixels counted are the ones tinted.
+                self.settings.step_warning |= enabled;
+            }
+            Command::ColoredShadows(enabled) => {
+                self.settings.colored_shadows = enabled;
+                self.settings.preset = None;
+            }
+            Command::WorldStats => print!("{}", Report::new(self.controller_pipeline.world())),
+            Command::Sun(hour) => self.controller_pipeline.time_of_day = hour.rem_euclid(24.0),
+            Command::TimeSpeed(speed) => self.time_speed = speed,
+            Command::Undo => {
+                if !self.history.undo(self.controller_pipeline.world_mut()) {
+                    println!("nothing to undo");
+                }
+            }
+            Command::Redo => {
+                if !self.history.redo(self.controller_pipeline.world_mut()) {
+                    println!("nothing to redo");
+                }
+            }
+            Command::ClearPortals => {
+                self.controller_pipeline.portals_mut().clear();
+                self.unlinked_portal = None;
+                self.camera_portal = None;
+            }
+            Command::Fill(_)
+            | Command::Replace(..)
+            | Command::Hollow
+            | Command::Walls(_)
+            | Command::Portal(_) => {
+                let Some((min, max)) = self.selection_box() else {
+                    println!("select two corners first");
+                    return;
+                };
+                self.history.record(
+                    self.controller_pipeline.world(),
+                    &[(min.map(|c| c as i32), max.map(|c| c as i32))],
+                );
+                let world = self.controller_pipeline.world_mut();
+                match command {
+                    Command::Fill(voxel) => world.fill(min, max, voxel),
+                    Command::Replace(from, to) => world.replace(min, max, from, to),
+                    Command::Hollow => world.hollow(min, max),
+                    Command::Walls(voxel) => {
+                        world.walls(min, max, voxel.unwrap_or(self.selected_material as u32))
+                    }
+                    Command::Portal(quarter_turns) => {
+                        world.fill(min, max, PORTAL);
+                        let portal = (min.map(|c| c as i32), max.map(|c| c as i32));
+                        let Some(other) = self.unlinked_portal.take() else {
+                            self.unlinked_portal = Some(portal);
+                            println!("select the portal to link it to and run `portal` again");
+                            return;
+                        };
+                        if let Err(err) = self.controller_pipeline.portals_mut().link(
+                            other,
+                            portal,
+                            quarter_turns.unwrap_or(0),
+                        ) {
+                            println!("{err}");
+                        }
+                    }
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+
+    /// Plays the sound of `voxel` being placed or broken in the box of `size` voxels at `min`.
+    fn play_edit_sound(&self, min: [i32; 3], size: [u32; 3], voxel: u32, sound: EditSound) {
+        if let Some(audio) = &self.audio {
+            let center = [0, 1, 2].map(|i| min[i] as f32 + size[i] as f32 / 2.0);
+            audio.play_edit(
+                self.controller_pipeline.world(),
+                self.controller_pipeline.palette(),
+                center,
+                voxel,
+                sound,
+            );
+        }
+    }
+
+    /// Sets the voxel at `position` and its mirror images. Positions outside the world are
+    /// ignored.
+    fn set_voxel_mirrored(&mut self, position: [i32; 3], voxel: u32) {
+        for position in self.symmetry.positions(position) {
+            if position.iter().all(|&c| c >= 0) {
+                self.controller_pipeline
+                    .world_mut()
+                    .set_voxel(position.map(|c| c as u32), voxel);
+            }
+        }
+    }
+
+    pub fn symmetry(&self) -> &Symmetry {
+        &self.symmetry
+    }
+
+    /// Returns the prefab left clicks stamp, if any.
+    pub fn active_prefab(&self) -> Option<&Prefab> {
+        self.active_prefab.map(|index| &self.prefabs[index])
+    }
+
+    /// Returns the box a left click would fill, the active prefab or a single voxel against the
+    /// face under the cursor, as its lower and upper corner.
+    fn placement_target(&self) -> Option<([i32; 3], [i32; 3])> {
+        let hover = self.hover.filter(|hover| hover.normal != [0; 3])?;
+        let anchor = [0, 1, 2].map(|i| hover.position[i] + hover.normal[i]);
+        let (min, size) = match self.active_prefab() {
+            Some(prefab) => (prefab.origin_at(anchor), prefab.size),
+            None => (anchor, [1; 3]),
+        };
+        Some((min, [0, 1, 2].map(|i| min[i] + size[i] as i32)))
+    }
+
+    /// Returns the bytes of GPU memory in use and the budget they have to fit.
+    pub fn memory_usage(&self) -> (u64, u64) {
+        let memory = self.controller_pipeline.memory_budget();
+        (memory.used(), memory.budget())
+    }
+
+    /// Replaces the world with terrain generated on the GPU from `seed`.
+    pub fn generate_terrain(&mut self, seed: u64) {
+        println!("generating terrain from seed {seed}");
+        self.generate(Terrain::new(seed));
+        self.world_seed = Some(seed);
+    }
+
+    /// Returns the terrain the world was last generated with, or the default one of its seed.
+    fn last_terrain(&self) -> Terrain {
+        self.terrain
+            .unwrap_or_else(|| Terrain::new(self.world_seed.unwrap_or_else(rand::random)))
+    }
+
+    /// Replaces the world with `terrain` generated on the GPU.
+    fn generate(&mut self, terrain: Terrain) {
+        self.controller_pipeline.generate_terrain(terrain);
+        self.forget_world();
+        self.terrain = Some(terrain);
+    }
+
+    /// Draws the windows shown over the frame.
+    pub fn draw_ui(&mut self, ctx: &egui::Context) {
+        let generating = self.controller_pipeline.generating_terrain();
+        let Some(panel) = &mut self.terrain_panel else {
+            return;
+        };
+        if let Some(terrain) = panel.show(ctx, generating) {
+            self.generate(terrain);
+        }
+    }
+
+    /// Replaces the world with the demo scene `name` and moves the camera into it. Bounces only
+    /// show with global illumination, so it is turned on.
+    fn load_demo(&mut self, name: &str) {
+        let mut palette = self.controller_pipeline.palette().clone();
+        let demo = match demo::load(name, self.controller_pipeline.world_mut(), &mut palette) {
+            Ok(demo) => demo,
+            Err(err) => {
+                println!("{err}");
+                return;
+            }
+        };
+        *self.controller_pipeline.palette_mut() = palette;
+        self.forget_world();
+        let rotation = [0.0; 3];
+        self.controller_pipeline.rotation = rotation;
+        self.controller_pipeline.position = world_to_camera(demo.eye, rotation);
+        self.settings.global_illumination = true;
+        self.settings.preset = None;
+        println!(
+            "loaded demo `{name}` with {} bounces per pixel, change them to see more or fewer \
+             reflections",
+            self.settings.max_bounces
+        );
+    }
+
+    /// Takes the materials, sun, bookmarks, lights and light bake of `scene`, whose world was
+    /// already loaded, and moves the camera to its first bookmark.
+    pub fn apply_scene(&mut self, scene: SceneFile) {
+        *self.controller_pipeline.palette_mut() = scene.palette.clone();
+        self.controller_pipeline.time_of_day = scene.time_of_day;
+        if let Some(path) = &scene.light_bake {
+            match LightBake::load(path) {
+                Ok(bake) => {
+                    self.controller_pipeline.set_light_bake(Some(bake));
+                    self.settings.baked_lighting = true;
+                }
+                Err(err) => println!("{err}"),
+            }
+        }
+        if let Some(bookmark) = scene.bookmarks.first() {
+            self.go_to(bookmark);
+        }
+        self.scene = scene;
+    }
+
+    /// Replaces the world and everything else `apply_scene` takes with the scene file at `path`,
+    /// printing what went wrong if it failed.
+    fn load_scene(&mut self, path: &Path) {
+        let scene = match SceneFile::load(path) {
+            Ok(scene) => scene,
+            Err(err) => {
+                println!("{err}");
+                return;
+            }
+        };
+        if let Err(err) = scene.load_world(self.controller_pipeline.world_mut()) {
+            println!("{err}");
+            return;
+        }
+        self.forget_world();
+        self.apply_scene(scene);
+        println!("loaded scene {}", path.display());
+    }
+
+    /// Replaces the voxel objects with the models of the MagicaVoxel scene at `path`, laid out like
+    /// in it, centered over the middle of the world and resting on the ground there. Returns how
+    /// many objects it holds.
+    fn load_objects(&mut self, path: &str) -> Result<usize, String> {
+        let bytes = fs::read(path).map_err(|err| format!("can't read `{path}`: {err}"))?;
+        let scene =
+            VoxScene::parse(&bytes).map_err(|err| format!("invalid scene `{path}`: {err}"))?;
+        let mut objects = scene.objects();
+        if objects.is_empty() {
+            return Err(format!("`{path}` holds no voxels"));
+        }
+        let (min, max) = scene.bounds();
+        let middle = WORLD_SIZE as f32 / 2.0;
+        let top = [middle, WORLD_SIZE as f32 - 0.5, middle];
+        let ground = self
+            .controller_pipeline
+            .world()
+            .raycast(top, [0.0, -1.0, 0.0], WORLD_SIZE as f32)
+            .map_or(0, |hit| hit.position[1] + 1);
+        let offset = [
+            middle - (min[0] + max[0] + 1) as f32 / 2.0,
+            (ground - min[1]) as f32,
+            middle - (min[2] + max[2] + 1) as f32 / 2.0,
+        ];
+        for object in &mut objects {
+            let translation = &mut object.transform.translation;
+            *translation = [0, 1, 2].map(|i| translation[i] + offset[i]);
+        }
+        let count = objects.len();
+        self.controller_pipeline.set_objects(objects);
+        self.animation.clear();
+        self.animation_time = None;
+        Ok(count)
+    }
+
+    /// Changes the transform of the voxel object at `index` with `edit`, printing when there is
+    /// no such object.
+    fn edit_object(&mut self, index: usize, edit: impl FnOnce(&mut Transform)) {
+        let Some(object) = self.controller_pipeline.objects().get(index) else {
+            println!("no object {index}");
+            return;
+        };
+        let mut transform = object.transform;
+        edit(&mut transform);
+        self.controller_pipeline
+            .set_object_transform(index, transform);
+    }
+
+    /// Writes the scene to `path` and the world to an `.rvox` file of the same name next to it,
+    /// printing what went wrong if either failed.
+    fn save_scene(&mut self, path: &Path) {
+        let world_path = path.with_extension("rvox");
+        if let Err(err) = self.controller_pipeline.world().save(&world_path) {
+            println!("{err}");
+            return;
+        }
+        self.scene.world = Some(world_path);
+        self.scene.light_bake = None;
+        if let Some(bake) = self.controller_pipeline.light_bake() {
+            let bake_path = path.with_extension(BAKE_EXTENSION);
+            if let Err(err) = bake.save(&bake_path) {
+                println!("{err}");
+                return;
+            }
+            self.scene.light_bake = Some(bake_path);
+        }
+        self.scene.palette = self.controller_pipeline.palette().clone();
+        self.scene.time_of_day = self.controller_pipeline.time_of_day;
+        match self.scene.save(path) {
+            Ok(()) => println!("saved scene to {}", path.display()),
+            Err(err) => println!("{err}"),
+        }
+    }
+
+    /// Places the camera at `bookmark`.
+    fn go_to(&mut self, bookmark: &Bookmark) {
+        self.controller_pipeline.rotation = bookmark.rotation;
+        self.controller_pipeline.position = world_to_camera(bookmark.eye, bookmark.rotation);
+        self.camera_portal = None;
+    }
+
+    /// Drops the undo history, portals, decals, clipping planes, scene lights, flowing liquids,
+    /// agents, projectiles, voxel objects and their animation and the light bake, which belong
+    /// to the world that was just replaced.
+    fn forget_world(&mut self) {
+        self.history = History::new();
+        self.scene.lights.clear();
+        self.controller_pipeline.portals_mut().clear();
+        self.controller_pipeline.decals_mut().clear();
+        self.controller_pipeline.clip_planes_mut().clear();
+        self.unlinked_portal = None;
+        self.camera_portal = None;
+        let falling_blocks = self.simulation.falling_blocks;
+        self.simulation = Simulation::new();
+        self.simulation.falling_blocks = falling_blocks;
+        self.agents.clear();
+        self.projectiles.clear();
+        self.controller_pipeline.set_objects(Vec::new());
+        self.animation.clear();
+        self.animation_time = None;
+        self.controller_pipeline.set_light_bake(None);
+        self.settings.baked_lighting = false;
+        self.controller_pipeline.reflection_probes_mut().clear();
+        self.world_seed = None;
+        self.terrain = None;
+        self.map = None;
+    }
+
+    /// Moves the camera along the flythrough while it plays.
+    fn fly(&mut self) {
+        let Some(time) = &mut self.flight else {
+            return;
+        };
+        *time += self.dt;
+        let Some(pose) = self.flythrough.sample(*time) else {
+            self.flight = None;
+            return;
+        };
+        if *time > self.flythrough.duration() {
+            self.flight = None;
+        }
+        self.controller_pipeline.rotation = pose.rotation;
+        self.controller_pipeline.position = world_to_camera(pose.eye, pose.rotation);
+        self.controller_pipeline.camera_dir = camera::camera_dir(pose.fov);
+    }
+
+    /// Moves the voxel objects along their tracks while the animation plays.
+    fn animate(&mut self) {
+        let Some(time) = &mut self.animation_time else {
+            return;
+        };
+        *time += self.dt;
+        let time = *time;
+        for (object, pose) in self.animation.sample(time) {
+            if let Some(placed) = self.controller_pipeline.objects().get(object) {
+                let transform = Transform {
+                    translation: pose.translation,
+                    rotation: pose.rotation,
+                    ..placed.transform
+                };
+                self.controller_pipeline
+                    .set_object_transform(object, transform);
+            }
+        }
+        if self.animation.finished(time) {
+            self.animation_time = None;
+        }
+    }
+
+    /// Returns the flythrough's timeline while it plays, "-" otherwise.
+    pub fn flight_info(&self) -> String {
+        match self.flight {
+            Some(time) => format!(
+                "{} {time:.1}/{:.1}s",
+                self.flythrough.timeline(time, 20),
+                self.flythrough.duration()
+            ),
+            None => "-".to_string(),
+        }
+    }
+
+    /// Moves the camera to the target of the portal its eye entered, turning it with the portal.
+    fn move_through_portals(&mut self) {
+        let rotation = self.controller_pipeline.rotation;
+        let eye = camera_to_world(self.controller_pipeline.position, rotation);
+        let portals = self.controller_pipeline.portals();
+        let entered = portals.find(eye.map(|c| c.floor() as i32));
+        match entered {
+            Some(portal) if self.camera_portal != Some(portal) => {
+                let (eye, _) = portals.teleport(portal, eye, [0.0; 3]);
+                let turned = portals.portals()[portal];
+                let mut rotation = rotation;
+                rotation[1] += turned.quarter_turns as f32 * FRAC_PI_2;
+                self.controller_pipeline.rotation = rotation;
+                self.controller_pipeline.position = world_to_camera(eye, rotation);
+                self.camera_portal = Some(turned.target);
+            }
+            Some(_) => {}
+            None => self.camera_portal = None,
+        }
+    }
+
+    /// Returns whether terrain generated on the GPU hasn't reached the CPU copy of the world yet.
+    pub fn generating_terrain(&self) -> bool {
+        self.controller_pipeline.generating_terrain()
+    }
+
+    /// Returns the number of chunks of the world which haven't been uploaded yet.
+    pub fn pending_chunks(&self) -> usize {
+        self.controller_pipeline.world().pending_chunks()
+    }
+
+    /// Returns the number of samples per pixel accumulated so far.
+    pub fn samples(&self) -> u32 {
+        self.controller_pipeline.samples()
+    }
+
+    /// Returns the voxel type whose material is edited along with that material.
+    pub fn selected_material(&self) -> (usize, &Material) {
+        (
+            self.selected_material,
+            &self.controller_pipeline.palette().materials[self.selected_material],
+        )
+    }
+
+    /// Returns the rendering settings currently in use.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
     }
 
     /// Returns whether the app should quit. (Happens on when pressing ESC.)
@@ -103,9 +1565,76 @@ impl FractalApp {
         self.dt_sum += self.dt;
         self.frame_count += 1.0;
         self.time = Instant::now();
+        let uploaded_bytes = self.controller_pipeline.world().uploaded_bytes();
+        let pass_timings = self.controller_pipeline.pass_timings();
+        self.stats.record_frame(
+            self.dt * 1000.0,
+            uploaded_bytes - self.recorded_upload_bytes,
+            pass_timings,
+        );
+        if uploaded_bytes != self.recorded_upload_bytes {
+            self.frame_markers |= MARKER_UPLOAD;
+        }
+        let memory = self.controller_pipeline.memory_budget();
+        self.frame_graph
+            .set_memory_usage(memory.used() as f32 / memory.budget().max(1) as f32);
+        self.frame_graph.set_pass_timings(pass_timings);
+        self.frame_graph.push(FrameSample {
+            cpu: self.cpu_time,
+            gpu: pass_timings.iter().map(|(_, time)| time).sum(),
+            markers: std::mem::take(&mut self.frame_markers),
+        });
+        self.recorded_upload_bytes = uploaded_bytes;
+        if let Some(autotuner) = &mut self.autotuner {
+            // The trace shader runs the lighting passes too.
+            let trace_time = pass_timings
+                .iter()
+                .filter(|(name, _)| matches!(*name, "trace" | "lighting"))
+                .map(|&(_, time)| time)
+                .reduce(|sum, time| sum + time);
+            if let Some(result) = autotuner.record(trace_time) {
+                self.finish_autotune(result);
+            }
+        }
+    }
+
+    /// Traces with the workgroup size the autotuner picked and saves it, or goes back to the
+    /// default size when it failed.
+    fn finish_autotune(&mut self, result: Result<[u32; 2], String>) {
+        let Some(autotuner) = self.autotuner.take() else {
+            return;
+        };
+        let size = match result {
+            Ok(size) => size,
+            Err(err) => {
+                println!("{err}");
+                self.controller_pipeline
+                    .set_trace_group_size(DEFAULT_GROUP_SIZE);
+                return;
+            }
+        };
+        self.controller_pipeline.set_trace_group_size(size);
+        let path = Path::new(DEFAULT_AUTOTUNE_PATH);
+        match autotune::save(path, autotuner.device(), size) {
+            Ok(()) => println!(
+                "tracing in {}x{} workgroups on {}, saved to {}",
+                size[0],
+                size[1],
+                autotuner.device(),
+                path.display()
+            ),
+            Err(err) => println!("{err}"),
+        }
     }
 
     pub fn handle_input(&mut self, window_size: [f32; 2], event: &Event<()>) {
+        if let Event::WindowEvent {
+            event: WindowEvent::Resized(..) | WindowEvent::ScaleFactorChanged { .. },
+            ..
+        } = event
+        {
+            self.frame_markers |= MARKER_SWAPCHAIN;
+        }
         self.input_state.handle_input(window_size, event);
     }
 
@@ -113,40 +1642,273 @@ impl FractalApp {
     pub fn reset_input_state(&mut self) {
         self.input_state.reset()
     }
-    pub fn update_state_after_inputs(&mut self, renderer: &mut VulkanoWindowRenderer) {
-        if self.input_state.forward {
-            self.controller_pipeline.position[2] += 5.0 * self.dt * self.input_state.move_speed;
+    /// Moves the simulation, agents and projectiles a frame further and fires a projectile when
+    /// asked to.
+    fn update_world(&mut self) {
+        if let Some((batch, cells)) = self.controller_pipeline.take_simulated_liquids() {
+            self.simulation
+                .apply(self.controller_pipeline.world_mut(), &batch, &cells);
+        }
+        if let Some(batch) = self
+            .simulation
+            .update(self.controller_pipeline.world_mut(), self.dt)
+        {
+            self.controller_pipeline.simulate_liquids(batch);
+        }
+        self.agents
+            .update(self.controller_pipeline.world(), self.dt);
+        if self.input_state.fire {
+            let rotation = self.controller_pipeline.rotation;
+            self.projectiles.fire(
+                camera_to_world(self.controller_pipeline.position, rotation),
+                camera_to_world([0.0, 0.0, 1.0], rotation),
+                PROJECTILE_SPEED,
+            );
+        }
+        let hits = self
+            .projectiles
+            .update(self.controller_pipeline.world_mut(), self.dt);
+        for hit in hits {
+            self.play_edit_sound(hit.position, [1; 3], hit.voxel, EditSound::Break);
+        }
+    }
+
+    pub fn update_state_after_inputs(&mut self, renderer: &mut WindowRenderer) {
+        self.frame_start = Instant::now();
+        let speed = if self.photo_mode { PHOTO_SPEED } else { 1.0 };
+        let step = 5.0 * self.dt * self.input_state.move_speed * speed;
+        let axis =
+            |positive: bool, negative: bool| (positive as i32 - negative as i32) as f32 * step;
+        let movement = [
+            axis(self.input_state.right, self.input_state.left),
+            axis(self.input_state.up, self.input_state.down),
+            axis(self.input_state.forward, self.input_state.backward),
+        ];
+        // The arrow keys nudge `mouse_pos` by a tenth per press, up and left positive.
+        let pitch = self.input_state.mouse_pos.y * 10.0 * TURN_STEP * speed;
+        let yaw = -self.input_state.mouse_pos.x * 10.0 * TURN_STEP * speed;
+        self.input_state.mouse_pos = Vector2::new(0.0, 0.0);
+        let mut camera = Camera {
+            position: self.controller_pipeline.position,
+            rotation: self.controller_pipeline.rotation,
+        };
+        if self.input_state.toggle_turntable {
+            self.turntable = !self.turntable;
+        }
+        if self.input_state.frame_model {
+            self.frame_model();
+        }
+        if let Some(orbit) = &mut self.orbit {
+            if let Some(focus) = self
+                .orbit_agent
+                .and_then(|index| self.agents.position(index))
+            {
+                orbit.focus = focus;
+            }
+            let drag = self.input_state.drag * ORBIT_SENSITIVITY * speed;
+            let spin = if self.turntable {
+                TURNTABLE_SPEED * self.dt
+            } else {
+                0.0
+            };
+            orbit.rotate(pitch + drag.y, yaw - drag.x + spin);
+            orbit.zoom(ZOOM_STEP.powf(self.input_state.zoom));
+            camera = orbit.camera();
+        } else if self.input_state.free_camera {
+            let roll = (self.input_state.roll_left as i32 - self.input_state.roll_right as i32)
+                as f32
+                * ROLL_SPEED
+                * self.dt
+                * speed;
+            camera.fly(movement);
+            camera.free_turn(pitch, yaw, roll);
+        } else {
+            camera.walk(movement);
+            camera.turn(pitch, yaw);
+        }
+        self.controller_pipeline.position = camera.position;
+        self.controller_pipeline.rotation = camera.rotation;
+        if !self.photo_mode {
+            self.move_through_portals();
+        }
+        self.fly();
+        self.animate();
+        if self.time_speed != 0.0 && !self.photo_mode {
+            let time_of_day = &mut self.controller_pipeline.time_of_day;
+            *time_of_day = (*time_of_day + self.dt * self.time_speed / 3600.0).rem_euclid(24.0);
+        }
+        if let Some(audio) = &mut self.audio {
+            audio.update(
+                self.controller_pipeline.world(),
+                self.controller_pipeline.palette(),
+                self.controller_pipeline.position,
+                self.controller_pipeline.rotation,
+                self.dt,
+            );
         }
-        if self.input_state.backward {
-            self.controller_pipeline.position[2] -= 5.0 * self.dt * self.input_state.move_speed;
+        if let Some(preset) = self.input_state.preset {
+            self.settings.apply_preset(preset);
         }
-        if self.input_state.left {
-            self.controller_pipeline.position[0] -= 5.0 * self.dt * self.input_state.move_speed;
+        self.settings
+            .scale_max_ray_steps(self.input_state.ray_steps_change);
+        self.settings
+            .change_max_bounces(self.input_state.bounces_change);
+        if self.input_state.toggle_step_warning {
+            self.settings.step_warning = !self.settings.step_warning;
         }
-        if self.input_state.right {
-            self.controller_pipeline.position[0] += 5.0 * self.dt * self.input_state.move_speed;
+        if self.input_state.toggle_accumulate {
+            self.settings.accumulate = !self.settings.accumulate;
         }
-        if self.input_state.up {
-            self.controller_pipeline.position[1] += 5.0 * self.dt * self.input_state.move_speed;
+        if self.input_state.toggle_adaptive_sampling {
+            self.settings.adaptive_sampling = !self.settings.adaptive_sampling;
         }
-        if self.input_state.down {
-            self.controller_pipeline.position[1] -= 5.0 * self.dt * self.input_state.move_speed;
+        if self.input_state.toggle_half_float_accumulation {
+            self.settings.half_float_accumulation = !self.settings.half_float_accumulation;
         }
-        if self.input_state.mouse_pos.x == 0.1 {
-            self.controller_pipeline.rotation[0] += 0.05;
-            self.input_state.mouse_pos.x = 0.0;
+        if self.input_state.toggle_tile_classification {
+            self.settings.tile_classification = !self.settings.tile_classification;
         }
-        if self.input_state.mouse_pos.x == -0.1 {
-            self.controller_pipeline.rotation[0] -= 0.05;
-            self.input_state.mouse_pos.x = 0.0;
+        if self.input_state.toggle_half_res_lighting {
+            self.settings.half_res_lighting = !self.settings.half_res_lighting;
         }
-        if self.input_state.mouse_pos.y == 0.1 {
-            self.controller_pipeline.rotation[2] += 0.05;
-            self.input_state.mouse_pos.y = 0.0;
+        if self.input_state.toggle_distance_field {
+            self.settings.distance_field = !self.settings.distance_field;
         }
-        if self.input_state.mouse_pos.y == -0.1 {
-            self.controller_pipeline.rotation[2] -= 0.05;
-            self.input_state.mouse_pos.y = 0.0;
+        if self.input_state.toggle_face_shading {
+            self.settings.face_shading = !self.settings.face_shading;
+        }
+        if self.input_state.toggle_grid_lines {
+            self.settings.grid_lines = !self.settings.grid_lines;
+        }
+        if self.input_state.toggle_motion_view {
+            self.settings.motion_view = !self.settings.motion_view;
+        }
+        if self.input_state.cycle_render_scale {
+            self.settings.cycle_render_scale();
+        }
+        if self.input_state.toggle_upscaler {
+            self.settings.upscaler = match self.settings.upscaler {
+                Upscaler::Bilinear => Upscaler::Temporal,
+                Upscaler::Temporal => Upscaler::Bilinear,
+            };
+            self.settings.preset = None;
+        }
+        for axis in 0..3 {
+            if self.input_state.toggle_mirror[axis] {
+                self.symmetry.axes[axis] = !self.symmetry.axes[axis];
+            }
+        }
+        if self.input_state.cycle_prefab && !self.prefabs.is_empty() {
+            self.active_prefab = match self.active_prefab {
+                None => Some(0),
+                Some(index) if index + 1 < self.prefabs.len() => Some(index + 1),
+                Some(_) => None,
+            };
+        }
+        if self.input_state.rotate_prefab {
+            if let Some(index) = self.active_prefab {
+                self.prefabs[index] = self.prefabs[index].rotated();
+            }
+        }
+        // Placing happens where the preview shows instead of waiting for a new pick.
+        if self.input_state.place {
+            if let Some((min, max)) = self.placement_target() {
+                let boxes = match self.active_prefab {
+                    Some(_) => vec![(min, max)],
+                    None => self.mirrored_boxes(min, [1; 3]),
+                };
+                self.history
+                    .record(self.controller_pipeline.world(), &boxes);
+                match self.active_prefab {
+                    Some(index) => {
+                        let prefab = &self.prefabs[index];
+                        self.controller_pipeline.world_mut().paste_region(
+                            min,
+                            prefab.size,
+                            &prefab.voxels,
+                        );
+                    }
+                    None => self.set_voxel_mirrored(min, self.selected_material as u32),
+                }
+                let size = [0, 1, 2].map(|i| (max[i] - min[i]) as u32);
+                let voxel = match self.active_prefab() {
+                    Some(prefab) => prefab.voxels.iter().copied().find(|&v| v != 0).unwrap_or(0),
+                    None => self.selected_material as u32,
+                };
+                self.play_edit_sound(min, size, voxel, EditSound::Place);
+            }
+        }
+        if let (Some(action), Some(hover)) = (self.input_state.pick_action, self.hover) {
+            self.apply_pick_action(action, hover);
+        }
+        if self.input_state.undo && !self.history.undo(self.controller_pipeline.world_mut()) {
+            println!("nothing to undo");
+        }
+        if self.input_state.redo && !self.history.redo(self.controller_pipeline.world_mut()) {
+            println!("nothing to redo");
+        }
+        for line in self.console.poll() {
+            self.run_command(&line);
+        }
+        if !self.photo_mode && self.model.is_none() {
+            self.update_world();
+        }
+        self.controller_pipeline.set_entities(
+            self.projectiles
+                .entities()
+                .chain(self.agents.entities())
+                .collect(),
+        );
+        if self.input_state.screenshot {
+            self.request_screenshot(None);
+        }
+        if self.model.is_none() {
+            self.autosave(false);
+        }
+        self.stream_map();
+        // Keeps the pick under the cursor current.
+        self.controller_pipeline
+            .request_pick(self.input_state.normalized_cursor_pos().into());
+        self.controller_pipeline
+            .set_preview(self.placement_target().filter(|_| !self.photo_mode));
+        if self.input_state.material_change != 0 {
+            let count = MATERIAL_COUNT as i32 - 1;
+            self.selected_material = ((self.selected_material as i32 - 1
+                + self.input_state.material_change)
+                .rem_euclid(count)
+                + 1) as usize;
+        }
+        if let Some(edit) = self.input_state.material_edit {
+            let material =
+                &mut self.controller_pipeline.palette_mut().materials[self.selected_material];
+            match edit {
+                MaterialEdit::Channel(channel) => {
+                    material.color[channel] = (material.color[channel] + 0.125) % 1.125
+                }
+                MaterialEdit::Emissive => {
+                    material.emissive = match material.emissive {
+                        e if e <= 0.0 => 1.0,
+                        e if e >= 4.0 => 0.0,
+                        e => e * 2.0,
+                    }
+                }
+                MaterialEdit::Roughness => material.roughness = (material.roughness + 0.25) % 1.25,
+            }
+        }
+        if self.input_state.save_palette {
+            match self.controller_pipeline.palette().save(&self.palette_path) {
+                Ok(()) => println!("saved palette to {}", self.palette_path.display()),
+                Err(err) => println!("{err}"),
+            }
+        }
+        if self.input_state.load_palette {
+            match Palette::load(&self.palette_path) {
+                Ok(palette) => *self.controller_pipeline.palette_mut() = palette,
+                Err(err) => println!("{err}"),
+            }
+        }
+        if self.input_state.toggle_frame_graph {
+            self.show_frame_graph = !self.show_frame_graph;
         }
         if self.input_state.toggle_full_screen {
             let is_full_screen = renderer.window().fullscreen().is_some();
@@ -175,18 +1937,64 @@ struct InputState {
     pub up: bool,
     pub down: bool,
     pub toggle_full_screen: bool,
+    pub toggle_frame_graph: bool,
     pub should_quit: bool,
+    pub preset: Option<Preset>,
+    pub ray_steps_change: i32,
+    pub bounces_change: i32,
+    pub toggle_step_warning: bool,
+    pub toggle_accumulate: bool,
+    pub toggle_adaptive_sampling: bool,
+    pub toggle_half_float_accumulation: bool,
+    pub toggle_tile_classification: bool,
+    pub toggle_half_res_lighting: bool,
+    pub toggle_distance_field: bool,
+    pub toggle_face_shading: bool,
+    pub toggle_grid_lines: bool,
+    pub toggle_motion_view: bool,
+    pub cycle_render_scale: bool,
+    pub toggle_upscaler: bool,
+    pub place: bool,
+    pub pick_action: Option<PickAction>,
+    pub toggle_mirror: [bool; 3],
+    pub cycle_prefab: bool,
+    pub rotate_prefab: bool,
+    pub undo: bool,
+    pub redo: bool,
+    pub fire: bool,
+    pub material_change: i32,
+    pub material_edit: Option<MaterialEdit>,
+    pub save_palette: bool,
+    pub load_palette: bool,
     pub move_speed: f32,
+    /// Whether Q and E roll the camera instead of rotating the prefab and editing emission, see
+    /// `Command::FreeCamera`.
+    pub free_camera: bool,
+    pub roll_left: bool,
+    pub roll_right: bool,
+    /// Whether the middle button and the wheel orbit and zoom the camera instead of picking a
+    /// material and changing the move speed, see `Command::Orbit`.
+    pub orbiting: bool,
+    /// Whether the middle button is held while orbiting.
+    pub dragging: bool,
+    /// Pixels the cursor moved while dragging.
+    pub drag: Vector2<f32>,
+    /// Lines scrolled while orbiting.
+    pub zoom: f32,
+    /// Whether T, P and Home drive the model viewer instead of editing the material and saving
+    /// the palette, see `FractalApp::view_model`.
+    pub viewing_model: bool,
+    pub toggle_turntable: bool,
+    pub screenshot: bool,
+    pub frame_model: bool,
     pub mouse_pos: Vector2<f32>,
+    pub cursor_pos: Vector2<f32>,
 }
 
 impl InputState {
     fn new() -> InputState {
         InputState {
-            window_size: [
-                WindowDescriptor::default().width,
-                WindowDescriptor::default().height,
-            ],
+            window_size: WINDOW_SIZE.map(|size| size as f32),
             forward: false,
             backward: false,
             right: false,
@@ -194,22 +2002,95 @@ impl InputState {
             up: false,
             down: false,
             toggle_full_screen: false,
+            toggle_frame_graph: false,
             should_quit: false,
+            preset: None,
+            ray_steps_change: 0,
+            bounces_change: 0,
+            toggle_step_warning: false,
+            toggle_accumulate: false,
+            toggle_adaptive_sampling: false,
+            toggle_half_float_accumulation: false,
+            toggle_tile_classification: false,
+            toggle_half_res_lighting: false,
+            toggle_distance_field: false,
+            toggle_face_shading: false,
+            toggle_grid_lines: false,
+            toggle_motion_view: false,
+            cycle_render_scale: false,
+            toggle_upscaler: false,
+            place: false,
+            pick_action: None,
+            toggle_mirror: [false; 3],
+            cycle_prefab: false,
+            rotate_prefab: false,
+            undo: false,
+            redo: false,
+            fire: false,
+            material_change: 0,
+            material_edit: None,
+            save_palette: false,
+            load_palette: false,
             move_speed: 1.0,
+            free_camera: false,
+            roll_left: false,
+            roll_right: false,
+            orbiting: false,
+            dragging: false,
+            drag: Vector2::new(0.0, 0.0),
+            zoom: 0.0,
+            viewing_model: false,
+            toggle_turntable: false,
+            screenshot: false,
+            frame_model: false,
             mouse_pos: Vector2::new(0.0, 0.0),
+            cursor_pos: Vector2::new(0.0, 0.0),
         }
     }
 
-    /*fn normalized_mouse_pos(&self) -> Vector2<f32> {
+    fn normalized_cursor_pos(&self) -> Vector2<f32> {
         Vector2::new(
-            (self.mouse_pos.x / self.window_size[0]).clamp(0.0, 1.0),
-            (self.mouse_pos.y / self.window_size[1]).clamp(0.0, 1.0),
+            (self.cursor_pos.x / self.window_size[0]).clamp(0.0, 1.0),
+            (self.cursor_pos.y / self.window_size[1]).clamp(0.0, 1.0),
         )
-    }*/
+    }
 
     fn reset(&mut self) {
         *self = InputState {
             toggle_full_screen: false,
+            toggle_frame_graph: false,
+            preset: None,
+            ray_steps_change: 0,
+            bounces_change: 0,
+            toggle_step_warning: false,
+            toggle_accumulate: false,
+            toggle_adaptive_sampling: false,
+            toggle_half_float_accumulation: false,
+            toggle_tile_classification: false,
+            toggle_half_res_lighting: false,
+            toggle_distance_field: false,
+            toggle_face_shading: false,
+            toggle_grid_lines: false,
+            toggle_motion_view: false,
+            cycle_render_scale: false,
+            toggle_upscaler: false,
+            place: false,
+            pick_action: None,
+            toggle_mirror: [false; 3],
+            cycle_prefab: false,
+            rotate_prefab: false,
+            undo: false,
+            redo: false,
+            fire: false,
+            material_change: 0,
+            material_edit: None,
+            save_palette: false,
+            load_palette: false,
+            drag: Vector2::new(0.0, 0.0),
+            zoom: 0.0,
+            toggle_turntable: false,
+            screenshot: false,
+            frame_model: false,
             ..*self
         }
     }
@@ -240,6 +2121,78 @@ impl InputState {
                 VirtualKeyCode::Space => self.up = state_is_pressed(input.state),
                 VirtualKeyCode::LControl => self.down = state_is_pressed(input.state),
                 VirtualKeyCode::RShift => self.toggle_full_screen = state_is_pressed(input.state),
+                VirtualKeyCode::F1 => self.select_preset(input.state, Preset::Low),
+                VirtualKeyCode::F2 => self.select_preset(input.state, Preset::Medium),
+                VirtualKeyCode::F3 => self.select_preset(input.state, Preset::High),
+                VirtualKeyCode::F4 => self.select_preset(input.state, Preset::Ultra),
+                VirtualKeyCode::LBracket if state_is_pressed(input.state) => {
+                    self.ray_steps_change -= 1
+                }
+                VirtualKeyCode::RBracket if state_is_pressed(input.state) => {
+                    self.ray_steps_change += 1
+                }
+                VirtualKeyCode::Minus if state_is_pressed(input.state) => self.bounces_change -= 1,
+                VirtualKeyCode::Equals if state_is_pressed(input.state) => self.bounces_change += 1,
+                VirtualKeyCode::F5 => self.toggle_step_warning = state_is_pressed(input.state),
+                VirtualKeyCode::F6 => self.toggle_accumulate = state_is_pressed(input.state),
+                VirtualKeyCode::F7 => self.toggle_adaptive_sampling = state_is_pressed(input.state),
+                VirtualKeyCode::J => {
+                    self.toggle_half_float_accumulation = state_is_pressed(input.state)
+                }
+                VirtualKeyCode::F8 => {
+                    self.toggle_tile_classification = state_is_pressed(input.state)
+                }
+                VirtualKeyCode::F9 => self.toggle_half_res_lighting = state_is_pressed(input.state),
+                VirtualKeyCode::H => self.toggle_distance_field = state_is_pressed(input.state),
+                VirtualKeyCode::F => self.toggle_face_shading = state_is_pressed(input.state),
+                VirtualKeyCode::K => self.toggle_grid_lines = state_is_pressed(input.state),
+                VirtualKeyCode::M => self.toggle_motion_view = state_is_pressed(input.state),
+                VirtualKeyCode::F10 => self.cycle_render_scale = state_is_pressed(input.state),
+                VirtualKeyCode::F11 => self.toggle_upscaler = state_is_pressed(input.state),
+                VirtualKeyCode::F12 => self.toggle_frame_graph = state_is_pressed(input.state),
+                VirtualKeyCode::Comma if state_is_pressed(input.state) => self.material_change -= 1,
+                VirtualKeyCode::Period if state_is_pressed(input.state) => {
+                    self.material_change += 1
+                }
+                VirtualKeyCode::Q if self.free_camera => {
+                    self.roll_left = state_is_pressed(input.state)
+                }
+                VirtualKeyCode::E if self.free_camera => {
+                    self.roll_right = state_is_pressed(input.state)
+                }
+                VirtualKeyCode::T if self.viewing_model => {
+                    self.toggle_turntable = state_is_pressed(input.state)
+                }
+                VirtualKeyCode::P if self.viewing_model => {
+                    self.screenshot = state_is_pressed(input.state)
+                }
+                VirtualKeyCode::Home if self.viewing_model => {
+                    self.frame_model = state_is_pressed(input.state)
+                }
+                VirtualKeyCode::R => self.edit_material(input.state, MaterialEdit::Channel(0)),
+                VirtualKeyCode::G => self.edit_material(input.state, MaterialEdit::Channel(1)),
+                VirtualKeyCode::B => self.edit_material(input.state, MaterialEdit::Channel(2)),
+                VirtualKeyCode::E => self.edit_material(input.state, MaterialEdit::Emissive),
+                VirtualKeyCode::T => self.edit_material(input.state, MaterialEdit::Roughness),
+                VirtualKeyCode::P => self.save_palette = state_is_pressed(input.state),
+                VirtualKeyCode::L => self.load_palette = state_is_pressed(input.state),
+                VirtualKeyCode::X => self.toggle_mirror[0] = state_is_pressed(input.state),
+                VirtualKeyCode::Y => self.toggle_mirror[1] = state_is_pressed(input.state),
+                VirtualKeyCode::Z => self.toggle_mirror[2] = state_is_pressed(input.state),
+                VirtualKeyCode::O if state_is_pressed(input.state) => {
+                    self.pick_action = Some(PickAction::SetMirrorOrigin)
+                }
+                VirtualKeyCode::Tab => self.cycle_prefab = state_is_pressed(input.state),
+                VirtualKeyCode::Q => self.rotate_prefab = state_is_pressed(input.state),
+                VirtualKeyCode::C if state_is_pressed(input.state) => {
+                    self.pick_action = Some(PickAction::SelectCorner(0))
+                }
+                VirtualKeyCode::V if state_is_pressed(input.state) => {
+                    self.pick_action = Some(PickAction::SelectCorner(1))
+                }
+                VirtualKeyCode::U => self.undo = state_is_pressed(input.state),
+                VirtualKeyCode::N => self.redo = state_is_pressed(input.state),
+                VirtualKeyCode::I => self.fire = state_is_pressed(input.state),
                 VirtualKeyCode::Up => self.mouse_pos.y += 0.1,
                 VirtualKeyCode::Down => self.mouse_pos.y -= 0.1,
                 VirtualKeyCode::Left => self.mouse_pos.x += 0.1,
@@ -248,17 +2201,44 @@ impl InputState {
             }
         }
     }
+    fn select_preset(&mut self, state: ElementState, preset: Preset) {
+        if state_is_pressed(state) {
+            self.preset = Some(preset);
+        }
+    }
+    fn edit_material(&mut self, state: ElementState, edit: MaterialEdit) {
+        if state_is_pressed(state) {
+            self.material_edit = Some(edit);
+        }
+    }
     fn on_mouse_wheel_event(&mut self, delta: &MouseScrollDelta) {
         let change = match delta {
             MouseScrollDelta::LineDelta(_x, y) => *y,
             MouseScrollDelta::PixelDelta(pos) => pos.y as f32,
         };
-        self.move_speed += change;
+        if self.orbiting {
+            self.zoom += change;
+        } else {
+            self.move_speed += change;
+        }
     }
     fn on_cursor_moved_event(&mut self, pos: &PhysicalPosition<f64>) {
-        self.mouse_pos = Vector2::new(pos.x as f32, pos.y as f32);
+        let cursor_pos = Vector2::new(pos.x as f32, pos.y as f32);
+        if self.dragging {
+            self.drag += cursor_pos - self.cursor_pos;
+        }
+        self.cursor_pos = cursor_pos;
     }
     fn on_mouse_click_event(&mut self, state: ElementState, mouse_btn: winit::event::MouseButton) {
-        if mouse_btn == MouseButton::Right {}
+        if mouse_btn == MouseButton::Middle && self.orbiting {
+            self.dragging = state_is_pressed(state);
+        } else if state_is_pressed(state) {
+            match mouse_btn {
+                MouseButton::Left => self.place = true,
+                MouseButton::Right => self.pick_action = Some(PickAction::Remove),
+                MouseButton::Middle => self.pick_action = Some(PickAction::Eyedropper),
+                MouseButton::Other(_) => (),
+            }
+        }
     }
 }
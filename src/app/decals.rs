@@ -0,0 +1,65 @@
+use super::FractalApp;
+use rvengine::decals::Decal;
+
+impl FractalApp {
+    /// Paints a decal of the selected material's color in the middle of the face under the
+    /// cursor.
+    pub(super) fn add_decal(&mut self, radius: f32, opacity: f32) {
+        let Some(hover) = self.hover.filter(|hover| hover.voxel != 0) else {
+            println!("no surface under the cursor");
+            return;
+        };
+        // The middle of the face under the cursor.
+        let center =
+            [0, 1, 2].map(|i| hover.position[i] as f32 + 0.5 + hover.normal[i] as f32 * 0.5);
+        let decal = Decal {
+            center,
+            radius,
+            color: self.selected_material().1.color,
+            opacity,
+        };
+        match self.controller_pipeline.decals_mut().add(decal) {
+            Ok(index) => println!("decal {index} at {center:?}"),
+            Err(err) => println!("{err}"),
+        }
+    }
+
+    /// Paints decals of the selected material's color along the surface between the corners of
+    /// the selection.
+    pub(super) fn add_decal_path(&mut self, radius: f32, opacity: f32) {
+        let [Some(a), Some(b)] = self.selection else {
+            println!("select both ends of the path first");
+            return;
+        };
+        let decal = Decal {
+            center: [0.0; 3],
+            radius,
+            color: self.selected_material().1.color,
+            opacity,
+        };
+        let [from, to] = [a, b].map(|corner| corner.map(|c| c as f32 + 0.5));
+        let controller = &mut self.controller_pipeline;
+        let mut decals = controller.decals().clone();
+        let added = decals.add_path(from, to, decal, controller.world());
+        *controller.decals_mut() = decals;
+        match added {
+            Ok(count) => println!("{count} decals along the path"),
+            Err(err) => println!("{err}"),
+        }
+    }
+
+    pub(super) fn list_decals(&self) {
+        for (index, decal) in self
+            .controller_pipeline
+            .decals()
+            .decals()
+            .iter()
+            .enumerate()
+        {
+            println!(
+                "{index}: at {:?} radius {} color {:?} opacity {}",
+                decal.center, decal.radius, decal.color, decal.opacity
+            );
+        }
+    }
+}
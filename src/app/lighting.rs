@@ -0,0 +1,62 @@
+use super::FractalApp;
+use rvengine::{
+    camera::camera_to_world,
+    light_bake::{BakeSettings, LightBake},
+};
+use std::time::Instant;
+
+impl FractalApp {
+    /// Bakes the lighting of the world as it is now with `samples` paths per face on the CPU and
+    /// lights it with the bake. Blocks until it is done.
+    pub(super) fn bake_lighting(&mut self, samples: Option<u32>) {
+        let defaults = BakeSettings::default();
+        let settings = BakeSettings {
+            samples: samples.unwrap_or(defaults.samples),
+            max_bounces: self.settings.max_bounces.max(1),
+            max_distance: self.settings.render_distance as f32,
+            time_of_day: self.controller_pipeline.time_of_day,
+        };
+        println!("baking lighting with {} paths per face", settings.samples);
+        let start = Instant::now();
+        let bake = LightBake::bake(
+            self.controller_pipeline.world(),
+            self.controller_pipeline.palette(),
+            &settings,
+        );
+        println!(
+            "baked {} faces in {:.1}s",
+            bake.faces(),
+            start.elapsed().as_secs_f32()
+        );
+        self.controller_pipeline.set_light_bake(Some(bake));
+        self.settings.baked_lighting = true;
+        self.settings.preset = None;
+    }
+
+    /// Lights the world with the light bake or traces its lighting, once there is a bake.
+    pub(super) fn set_baked_lighting(&mut self, enabled: bool) {
+        if enabled && self.controller_pipeline.light_bake().is_none() {
+            println!("nothing baked yet, `bake` first");
+        } else {
+            self.settings.baked_lighting = enabled;
+            self.settings.preset = None;
+        }
+    }
+
+    /// Places a reflection probe at the eye.
+    pub(super) fn add_probe(&mut self) {
+        let rotation = self.controller_pipeline.rotation;
+        let eye = camera_to_world(self.controller_pipeline.position, rotation);
+        match self.controller_pipeline.add_reflection_probe(eye) {
+            Ok(index) => println!("reflection probe {index} at {:?}", eye.map(|c| c.round())),
+            Err(err) => println!("{err}"),
+        }
+    }
+
+    pub(super) fn list_probes(&self) {
+        let probes = self.controller_pipeline.reflection_probes();
+        for (index, position) in probes.positions().iter().enumerate() {
+            println!("{index}: {:?}", position.map(|c| c.round()));
+        }
+    }
+}
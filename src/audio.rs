@@ -0,0 +1,189 @@
+use rand::Rng;
+use rodio::{buffer::SamplesBuffer, OutputStream, OutputStreamHandle, Sink, Source, SpatialSink};
+use rvengine::{
+    camera::camera_to_world,
+    materials::{Material, Palette},
+    world::World,
+};
+
+const SAMPLE_RATE: u32 = 44100;
+/// Distance of each ear from the listener's eye.
+const EAR_DISTANCE: f32 = 0.5;
+/// Horizontal distance moved between two footsteps.
+const STRIDE: f32 = 1.5;
+/// How far below the eye the ground can be for footsteps to sound.
+const STEP_HEIGHT: f32 = 3.0;
+/// Volume of sounds which have no line of sight to the listener.
+const OCCLUDED_VOLUME: f32 = 0.3;
+/// Volume of the wind out in the open.
+const WIND_VOLUME: f32 = 0.25;
+/// Distance to the sky above which the listener counts as out in the open.
+const WIND_DISTANCE: f32 = 64.0;
+/// Seconds between updates of the wind volume.
+const WIND_INTERVAL: f32 = 0.5;
+
+/// An edit to the world which makes a sound where it happened.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditSound {
+    Place,
+    Break,
+}
+
+/// Footsteps, edit sounds and ambient wind. Sounds are synthesized from the material they come
+/// from and muffled when the world blocks the line of sight to them.
+pub struct Audio {
+    /// Keeps the output device open.
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+    wind: Sink,
+    wind_timer: f32,
+    eye: [f32; 3],
+    /// Direction of the right ear.
+    right: [f32; 3],
+    /// Horizontal distance moved since the last footstep.
+    stride: f32,
+}
+
+impl Audio {
+    /// Opens the default output device, printing why if there is none.
+    pub fn new() -> Option<Audio> {
+        let (stream, handle) = match OutputStream::try_default() {
+            Ok(output) => output,
+            Err(err) => {
+                println!("audio disabled: {err}");
+                return None;
+            }
+        };
+        let wind = Sink::try_new(&handle).ok()?;
+        wind.set_volume(0.0);
+        wind.append(wind_noise().repeat_infinite());
+        Some(Audio {
+            _stream: stream,
+            handle,
+            wind,
+            wind_timer: 0.0,
+            eye: [0.0; 3],
+            right: [1.0, 0.0, 0.0],
+            stride: 0.0,
+        })
+    }
+
+    /// Moves the listener to the camera, playing footsteps on the ground below it and fading the
+    /// wind with how open the sky above is.
+    pub fn update(
+        &mut self,
+        world: &World,
+        palette: &Palette,
+        position: [f32; 3],
+        rotation: [f32; 3],
+        dt: f32,
+    ) {
+        let eye = camera_to_world(position, rotation);
+        let moved = [eye[0] - self.eye[0], eye[2] - self.eye[2]];
+        self.eye = eye;
+        self.right = camera_to_world([1.0, 0.0, 0.0], rotation);
+
+        match world.raycast(eye, [0.0, -1.0, 0.0], STEP_HEIGHT) {
+            Some(ground) => {
+                self.stride += (moved[0] * moved[0] + moved[1] * moved[1]).sqrt();
+                if self.stride >= STRIDE {
+                    self.stride = 0.0;
+                    let material = &palette.materials[material_index(palette, ground.voxel)];
+                    let step = impact(material, 0.08, 0.6);
+                    let _ = self.handle.play_raw(step.amplify(0.5));
+                }
+            }
+            None => self.stride = 0.0,
+        }
+
+        self.wind_timer -= dt;
+        if self.wind_timer <= 0.0 {
+            self.wind_timer = WIND_INTERVAL;
+            let directions = [
+                [0.0, 1.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [-1.0, 1.0, 0.0],
+                [0.0, 1.0, 1.0],
+                [0.0, 1.0, -1.0],
+            ];
+            let open = directions
+                .iter()
+                .filter(|&&direction| world.raycast(eye, direction, WIND_DISTANCE).is_none())
+                .count();
+            self.wind
+                .set_volume(WIND_VOLUME * open as f32 / directions.len() as f32);
+        }
+    }
+
+    /// Plays the sound of `voxel` being placed or broken at `center`.
+    pub fn play_edit(
+        &self,
+        world: &World,
+        palette: &Palette,
+        center: [f32; 3],
+        voxel: u32,
+        sound: EditSound,
+    ) {
+        let material = &palette.materials[material_index(palette, voxel)];
+        let source = match sound {
+            EditSound::Place => impact(material, 0.12, 0.9),
+            EditSound::Break => impact(material, 0.3, 0.4),
+        };
+        let ear = |side: f32| [0, 1, 2].map(|i| self.eye[i] + self.right[i] * side * EAR_DISTANCE);
+        let Ok(sink) = SpatialSink::try_new(&self.handle, center, ear(-1.0), ear(1.0)) else {
+            return;
+        };
+        sink.set_volume(self.occlusion(world, center));
+        sink.append(source);
+        sink.detach();
+    }
+
+    /// Returns the volume of a sound at `source`, lowered when the world is in the way. The
+    /// last voxel before the source is skipped, as sounds come from the voxel they are at.
+    fn occlusion(&self, world: &World, source: [f32; 3]) -> f32 {
+        let direction = [0, 1, 2].map(|i| source[i] - self.eye[i]);
+        let distance = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+        if distance <= 1.0 || world.raycast(self.eye, direction, distance - 1.0).is_none() {
+            1.0
+        } else {
+            OCCLUDED_VOLUME
+        }
+    }
+}
+
+fn material_index(palette: &Palette, voxel: u32) -> usize {
+    (voxel as usize).min(palette.materials.len() - 1)
+}
+
+/// Sound of something hitting `material`: rough materials crunch with noise, smooth ones ring
+/// with a tone pitched by their brightness.
+fn impact(material: &Material, duration: f32, smoothing: f32) -> SamplesBuffer<f32> {
+    let brightness = material.color.iter().sum::<f32>() / 3.0;
+    let pitch = 200.0 + 800.0 * brightness;
+    let roughness = material.roughness.clamp(0.0, 1.0);
+    let mut rng = rand::thread_rng();
+    let mut smoothed: f32 = 0.0;
+    let samples: Vec<f32> = (0..(duration * SAMPLE_RATE as f32) as usize)
+        .map(|i| {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            smoothed = smoothed * smoothing + rng.gen_range(-1.0..1.0) * (1.0 - smoothing);
+            let tone = (t * pitch * std::f32::consts::TAU).sin();
+            let envelope = (-t * 8.0 / duration).exp();
+            (smoothed * 4.0 * roughness + tone * (1.0 - roughness)) * envelope
+        })
+        .collect();
+    SamplesBuffer::new(1, SAMPLE_RATE, samples)
+}
+
+/// A few seconds of heavily low-passed noise to loop as wind.
+fn wind_noise() -> SamplesBuffer<f32> {
+    let mut rng = rand::thread_rng();
+    let mut smoothed: f32 = 0.0;
+    let samples: Vec<f32> = (0..2 * SAMPLE_RATE)
+        .map(|_| {
+            smoothed = smoothed * 0.98 + rng.gen_range(-1.0..1.0) * 0.02;
+            smoothed * 8.0
+        })
+        .collect();
+    SamplesBuffer::new(1, SAMPLE_RATE, samples)
+}
@@ -0,0 +1,118 @@
+use crate::{
+    scene::{Bookmark, SceneFile},
+    world::{rvox_bytes, World, WORLD_SIZE},
+    world_file,
+};
+use std::{
+    path::PathBuf,
+    thread::{self, JoinHandle},
+};
+
+/// Seconds between autosaves unless set otherwise.
+pub const DEFAULT_AUTOSAVE_INTERVAL: f32 = 300.0;
+/// Slots autosaves rotate through unless set otherwise.
+pub const DEFAULT_AUTOSAVE_SLOTS: u32 = 3;
+/// Name of the bookmark holding the camera in an autosaved scene, which starts there.
+pub const AUTOSAVE_BOOKMARK: &str = "autosave";
+
+/// Periodic saves of the world and camera, so a crash loses at most an interval of edits. Saves
+/// go to `autosave-<slot>.rvscene` and `.rvox` in the working directory, the oldest slot
+/// overwritten first, and are written on a worker thread through
+/// `world_file::write_atomically`, so a crash while saving leaves the slot's previous save.
+pub struct Autosave {
+    /// Seconds between saves, `None` while autosaving is off.
+    interval: Option<f32>,
+    slots: u32,
+    /// Slot the next save goes to.
+    next_slot: u32,
+    /// Seconds since the last save.
+    elapsed: f32,
+    /// `World::uploaded_bytes` when the last save was taken, nothing is saved while the world
+    /// stays the same.
+    saved_revision: u64,
+    worker: Option<JoinHandle<Result<PathBuf, String>>>,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Autosave::new(Some(DEFAULT_AUTOSAVE_INTERVAL), DEFAULT_AUTOSAVE_SLOTS)
+    }
+}
+
+impl Autosave {
+    pub fn new(interval: Option<f32>, slots: u32) -> Autosave {
+        Autosave {
+            interval,
+            slots: slots.max(1),
+            next_slot: 0,
+            elapsed: 0.0,
+            saved_revision: 0,
+            worker: None,
+        }
+    }
+
+    /// Changes the seconds between saves, `None` to stop autosaving, counting from now.
+    pub fn set_interval(&mut self, interval: Option<f32>) {
+        self.interval = interval;
+        self.elapsed = 0.0;
+    }
+
+    /// Advances the clock by `dt` seconds and returns whether a save is due: the interval passed,
+    /// the world changed since the last save and no save is still being written.
+    pub fn tick(&mut self, dt: f32, world: &World) -> bool {
+        let Some(interval) = self.interval else {
+            return false;
+        };
+        self.elapsed += dt;
+        self.elapsed >= interval
+            && world.uploaded_bytes() != self.saved_revision
+            && self.worker.is_none()
+    }
+
+    /// Starts writing `world` to the next slot with `scene`, its camera at `eye` and `rotation`,
+    /// on a worker thread. Only the voxels are copied on the calling thread.
+    pub fn save(&mut self, world: &World, scene: &SceneFile, eye: [f32; 3], rotation: [f32; 3]) {
+        if self.worker.is_some() {
+            return;
+        }
+        let slot = self.next_slot + 1;
+        self.next_slot = (self.next_slot + 1) % self.slots;
+        self.elapsed = 0.0;
+        self.saved_revision = world.uploaded_bytes();
+        let voxels = world.region([0; 3], [WORLD_SIZE; 3]);
+        let scene_path = PathBuf::from(format!("autosave-{slot}.rvscene"));
+        let world_path = scene_path.with_extension("rvox");
+        let mut scene = scene.clone();
+        scene.world = Some(world_path.clone());
+        scene
+            .bookmarks
+            .retain(|bookmark| bookmark.name != AUTOSAVE_BOOKMARK);
+        scene.bookmarks.insert(
+            0,
+            Bookmark {
+                name: AUTOSAVE_BOOKMARK.to_string(),
+                eye,
+                rotation,
+            },
+        );
+        self.worker = Some(thread::spawn(move || {
+            world_file::write_atomically(&world_path, &rvox_bytes(&voxels))
+                .map_err(|err| format!("can't write world `{}`: {err}", world_path.display()))?;
+            scene.save(&scene_path)?;
+            Ok(scene_path)
+        }));
+    }
+
+    /// Returns where the last save went once it was written, or why it failed.
+    pub fn poll(&mut self) -> Option<Result<PathBuf, String>> {
+        if !self.worker.as_ref()?.is_finished() {
+            return None;
+        }
+        let worker = self.worker.take()?;
+        Some(
+            worker
+                .join()
+                .unwrap_or_else(|_| Err("autosave worker panicked".to_string())),
+        )
+    }
+}
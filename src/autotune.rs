@@ -0,0 +1,125 @@
+use std::{fs, io::ErrorKind, path::Path};
+
+/// File the workgroup size picked for each GPU is saved to.
+pub const DEFAULT_AUTOTUNE_PATH: &str = "autotune.txt";
+
+/// Workgroup size of the trace shader before it is tuned.
+pub const DEFAULT_GROUP_SIZE: [u32; 2] = [16, 16];
+
+/// Workgroup sizes of the trace shader the autotuner times. GPUs schedule invocations in groups
+/// of 32 or 64 and differ in how well square or wide workgroups keep rays coherent.
+const CANDIDATES: [[u32; 2]; 6] = [[8, 8], [16, 8], [8, 16], [16, 16], [32, 8], [8, 32]];
+/// Frames traced with a candidate before its timings count, covering building its pipelines and
+/// the frames `GpuTimer` is behind.
+const WARMUP_FRAMES: u32 = 5;
+/// Frames each candidate is timed over.
+const TIMED_FRAMES: u32 = 20;
+
+/// Times the trace pass with every candidate workgroup size in turn over the frames it is fed,
+/// then picks the fastest.
+pub struct Autotuner {
+    /// Name of the GPU the sizes are timed on.
+    device: String,
+    /// Index into `CANDIDATES` of the size being timed.
+    candidate: usize,
+    /// Frames traced with the current candidate.
+    frame: u32,
+    /// Milliseconds the timed frames of each candidate took in total.
+    totals: [f32; CANDIDATES.len()],
+}
+
+impl Autotuner {
+    pub fn new(device: String) -> Autotuner {
+        Autotuner {
+            device,
+            candidate: 0,
+            frame: 0,
+            totals: [0.0; CANDIDATES.len()],
+        }
+    }
+
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+
+    /// Returns the workgroup size the next frame is traced with.
+    pub fn group_size(&self) -> [u32; 2] {
+        CANDIDATES[self.candidate]
+    }
+
+    /// Records how many milliseconds the trace pass of the latest finished frame took, `None`
+    /// when the GPU can't tell. Returns the fastest size once every candidate was timed, or an
+    /// error when timings aren't available.
+    pub fn record(&mut self, trace_time: Option<f32>) -> Option<Result<[u32; 2], String>> {
+        self.frame += 1;
+        if self.frame <= WARMUP_FRAMES {
+            return None;
+        }
+        let Some(trace_time) = trace_time else {
+            return Some(Err(format!(
+                "{} can't time GPU passes, keeping the default workgroup size",
+                self.device
+            )));
+        };
+        self.totals[self.candidate] += trace_time;
+        if self.frame < WARMUP_FRAMES + TIMED_FRAMES {
+            return None;
+        }
+        self.candidate += 1;
+        self.frame = 0;
+        if self.candidate < CANDIDATES.len() {
+            return None;
+        }
+        let fastest = (0..CANDIDATES.len())
+            .min_by(|&a, &b| self.totals[a].total_cmp(&self.totals[b]))
+            .unwrap();
+        Some(Ok(CANDIDATES[fastest]))
+    }
+}
+
+/// Returns the workgroup size saved for `device` in the file at `path`, `None` when the file
+/// doesn't exist or has none for it.
+pub fn load(path: &Path, device: &str) -> Result<Option<[u32; 2]>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("can't read {}: {err}", path.display())),
+    };
+    for (line_number, line) in text.lines().enumerate() {
+        let invalid = || {
+            format!(
+                "{}:{}: invalid line `{line}`",
+                path.display(),
+                line_number + 1
+            )
+        };
+        let (size, name) = line.split_once(' ').ok_or_else(invalid)?;
+        if name != device {
+            continue;
+        }
+        let (width, height) = size.split_once('x').ok_or_else(invalid)?;
+        let size = [width, height].map(|d| d.parse::<u32>().ok().filter(|&d| d > 0));
+        let [Some(width), Some(height)] = size else {
+            return Err(invalid());
+        };
+        return Ok(Some([width, height]));
+    }
+    Ok(None)
+}
+
+/// Saves `size` as the workgroup size of `device` to the file at `path`, keeping the sizes of
+/// other GPUs. Each line holds a size like `16x8` and the name of the GPU it is for.
+pub fn save(path: &Path, device: &str, size: [u32; 2]) -> Result<(), String> {
+    let mut text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(format!("can't read {}: {err}", path.display())),
+    };
+    text = text
+        .lines()
+        .filter(|line| line.split_once(' ').map(|(_, name)| name) != Some(device))
+        .map(|line| format!("{line}\n"))
+        .collect();
+    text.push_str(&format!("{}x{} {device}\n", size[0], size[1]));
+    fs::write(path, text).map_err(|err| format!("can't write {}: {err}", path.display()))
+}
@@ -0,0 +1,261 @@
+/// Direction the camera looks along before rotation, also the distance to the image plane.
+pub const CAMERA_DIR: [f32; 3] = [0.0, 0.0, 0.8];
+
+/// Returns the horizontal field of view in degrees of a camera looking along `camera_dir`, for
+/// an image plane reaching from -1 to 1.
+pub fn fov(camera_dir: [f32; 3]) -> f32 {
+    (2.0 * (1.0 / camera_dir[2]).atan()).to_degrees()
+}
+
+/// Returns the direction a camera with a horizontal field of view of `fov` degrees looks along,
+/// the inverse of `fov`.
+pub fn camera_dir(fov: f32) -> [f32; 3] {
+    [0.0, 0.0, 1.0 / (fov.to_radians() / 2.0).tan()]
+}
+
+/// Lens of the camera, blurring what lies off the plane in focus with depth of field.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Lens {
+    /// Radius of the lens in voxels, 0 for a pinhole camera with everything in focus.
+    pub aperture: f32,
+    /// Distance in voxels along the view to the plane in focus.
+    pub focus_distance: f32,
+}
+
+impl Default for Lens {
+    fn default() -> Self {
+        Lens {
+            aperture: 0.0,
+            focus_distance: 32.0,
+        }
+    }
+}
+
+/// Angle in radians the camera looks up or down by at most, short of straight up or down where
+/// turning left and right would spin it around its view instead.
+pub const MAX_PITCH: f32 = 1.5;
+
+/// A camera placed the way the compute shader's `cameraRay` places it: `position` and the view
+/// are both rotated around the world origin by `rotation`, in radians around z, then x, then y.
+///
+/// The world's y axis points up. Unrotated, the camera looks along +z with +x to the right of the
+/// image. Positive rotations around z roll the view counterclockwise, around x pitch it down and
+/// around y turn it left. Rolling first keeps it a roll around the view whichever way the camera
+/// looks.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub rotation: [f32; 3],
+}
+
+impl Camera {
+    /// Returns the origin and normalized direction of the ray through `screen_pos` (-1 to 1 on
+    /// both axes, y down) of an image `aspect` times as wide as it is high.
+    pub fn ray(&self, screen_pos: [f32; 2], aspect: f32) -> ([f32; 3], [f32; 3]) {
+        let direction = [
+            CAMERA_DIR[0] + screen_pos[0],
+            CAMERA_DIR[1] + screen_pos[1] / aspect,
+            CAMERA_DIR[2],
+        ];
+        let direction = camera_to_world(direction, self.rotation);
+        let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+        (
+            camera_to_world(self.position, self.rotation),
+            direction.map(|d| d / length),
+        )
+    }
+
+    /// Returns the position of the eye in the world.
+    pub fn eye(&self) -> [f32; 3] {
+        camera_to_world(self.position, self.rotation)
+    }
+
+    /// Returns the direction through the center of the image.
+    pub fn forward(&self) -> [f32; 3] {
+        camera_to_world(CAMERA_DIR, self.rotation)
+    }
+
+    /// Turns the view by `pitch` radians up and `yaw` radians to the right, keeping the eye where
+    /// it is. Pitch is kept within `MAX_PITCH`.
+    pub fn turn(&mut self, pitch: f32, yaw: f32) {
+        let eye = self.eye();
+        self.rotation[0] = (self.rotation[0] - pitch).clamp(-MAX_PITCH, MAX_PITCH);
+        self.rotation[1] -= yaw;
+        self.position = world_to_camera(eye, self.rotation);
+    }
+
+    /// Turns the view around its own axes by `pitch` radians up, `yaw` radians to the right and
+    /// `roll` radians counterclockwise, keeping the eye where it is. Unlike `turn` nothing is
+    /// clamped, yawing after a roll turns around the tilted up of the view rather than world y.
+    pub fn free_turn(&mut self, pitch: f32, yaw: f32, roll: f32) {
+        let eye = self.eye();
+        let local = [-pitch, -yaw, roll];
+        let [right, forward] = [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0]]
+            .map(|axis| camera_to_world(camera_to_world(axis, local), self.rotation));
+        self.rotation = rotation_from_axes(right, forward);
+        self.position = world_to_camera(eye, self.rotation);
+    }
+
+    /// Moves the eye by `right`, `up` and `forward` in `movement` along the axes of the view, for
+    /// flying with `free_turn`.
+    pub fn fly(&mut self, movement: [f32; 3]) {
+        let axes = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+            .map(|axis| camera_to_world(axis, self.rotation));
+        let mut eye = self.eye();
+        for (axis, distance) in axes.iter().zip(movement) {
+            for i in 0..3 {
+                eye[i] += axis[i] * distance;
+            }
+        }
+        self.position = world_to_camera(eye, self.rotation);
+    }
+
+    /// Rolls the view to `roll` radians counterclockwise, keeping the eye where it is.
+    pub fn roll_to(&mut self, roll: f32) {
+        let eye = self.eye();
+        self.rotation[2] = roll;
+        self.position = world_to_camera(eye, self.rotation);
+    }
+
+    /// Moves the eye by `right`, `up` and `forward` in `movement`, forward and right along the
+    /// ground where the camera faces and up along y, so looking up or down doesn't change how
+    /// moving forward changes height.
+    pub fn walk(&mut self, movement: [f32; 3]) {
+        let heading = [0.0, self.rotation[1], 0.0];
+        let right = camera_to_world([1.0, 0.0, 0.0], heading);
+        let forward = camera_to_world([0.0, 0.0, 1.0], heading);
+        let mut eye = self.eye();
+        for i in 0..3 {
+            eye[i] += right[i] * movement[0] + forward[i] * movement[2];
+        }
+        eye[1] += movement[1];
+        self.position = world_to_camera(eye, self.rotation);
+    }
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            position: [0.0, 0.0, -10.0],
+            rotation: [0.0; 3],
+        }
+    }
+}
+
+/// Voxels an orbiting camera stays away from its focus at least.
+pub const MIN_ORBIT_DISTANCE: f32 = 1.0;
+
+/// A camera circling a focus point and looking at it, like a model viewer's.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Orbit {
+    pub focus: [f32; 3],
+    /// Voxels from the eye to `focus`.
+    pub distance: f32,
+    /// Rotation of the camera around x and y, see `Camera`.
+    pub pitch: f32,
+    pub yaw: f32,
+}
+
+impl Orbit {
+    /// Starts circling `focus` from where `camera`'s eye is.
+    pub fn around(camera: &Camera, focus: [f32; 3]) -> Orbit {
+        let eye = camera.eye();
+        let to_focus = [0, 1, 2].map(|i| focus[i] - eye[i]);
+        let ground = to_focus[0].hypot(to_focus[2]);
+        Orbit {
+            focus,
+            distance: ground.hypot(to_focus[1]).max(MIN_ORBIT_DISTANCE),
+            pitch: (-to_focus[1]).atan2(ground).clamp(-MAX_PITCH, MAX_PITCH),
+            yaw: (-to_focus[0]).atan2(to_focus[2]),
+        }
+    }
+
+    /// Circles the eye `up` radians over and `right` radians to the right of the focus, no
+    /// further up or down than `MAX_PITCH`.
+    pub fn rotate(&mut self, up: f32, right: f32) {
+        self.pitch = (self.pitch + up).clamp(-MAX_PITCH, MAX_PITCH);
+        self.yaw += right;
+    }
+
+    /// Multiplies the distance to the focus by `factor`, down to `MIN_ORBIT_DISTANCE`.
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance = (self.distance * factor).max(MIN_ORBIT_DISTANCE);
+    }
+
+    /// Moves the eye just far enough away for a sphere of `radius` around the focus to fill the
+    /// view of a camera looking along `camera_dir`, with an image `aspect` times as wide as high.
+    pub fn fit(&mut self, radius: f32, camera_dir: [f32; 3], aspect: f32) {
+        // Of half the narrower field of view, rays reach 1 to the side and 1 / aspect up.
+        let half_fov = (1.0 / (camera_dir[2] * aspect.max(1.0))).atan();
+        self.distance = (radius / half_fov.sin()).max(MIN_ORBIT_DISTANCE);
+    }
+
+    /// Returns the camera looking at the focus from `distance` away.
+    pub fn camera(&self) -> Camera {
+        let rotation = [self.pitch, self.yaw, 0.0];
+        let forward = camera_to_world([0.0, 0.0, 1.0], rotation);
+        let eye = [0, 1, 2].map(|i| self.focus[i] - forward[i] * self.distance);
+        Camera {
+            position: world_to_camera(eye, rotation),
+            rotation,
+        }
+    }
+}
+
+/// Returns the rotation turning the camera's x and z axes into `right` and `forward`, which
+/// have to be orthonormal. Looking straight up or down yaw and roll turn the view around the same
+/// axis, whatever yaw comes out the roll makes up for.
+fn rotation_from_axes(right: [f32; 3], forward: [f32; 3]) -> [f32; 3] {
+    let pitch = (-forward[1]).atan2(forward[0].hypot(forward[2]));
+    let yaw = (-forward[0]).atan2(forward[2]);
+    let [x, y, _] = world_to_camera(right, [pitch, yaw, 0.0]);
+    [pitch, yaw, y.atan2(x)]
+}
+
+/// Rotates `v` from world into camera space, undoing `camera_to_world`.
+pub fn world_to_camera(v: [f32; 3], rotation: [f32; 3]) -> [f32; 3] {
+    let rotate2d = |a: f32, b: f32, angle: f32| {
+        let (sin, cos) = angle.sin_cos();
+        (a * cos + b * sin, b * cos - a * sin)
+    };
+    let [mut x, mut y, mut z] = v;
+    (x, z) = rotate2d(x, z, rotation[1]);
+    (y, z) = rotate2d(y, z, rotation[0]);
+    (x, y) = rotate2d(x, y, rotation[2]);
+    [x, y, z]
+}
+
+/// Rotates `v` from camera into world space the way `cameraRay` in the compute shader does.
+pub fn camera_to_world(v: [f32; 3], rotation: [f32; 3]) -> [f32; 3] {
+    let rotate2d = |a: f32, b: f32, angle: f32| {
+        let (sin, cos) = angle.sin_cos();
+        (a * cos - b * sin, b * cos + a * sin)
+    };
+    let [mut x, mut y, mut z] = v;
+    (x, y) = rotate2d(x, y, rotation[2]);
+    (y, z) = rotate2d(y, z, rotation[0]);
+    (x, z) = rotate2d(x, z, rotation[1]);
+    [x, y, z]
+}
+
+/// Returns roughly which part of the image a sphere around `center` of `radius` covers, seen
+/// from `eye` looking along `forward`, whose length is the distance to an image plane reaching
+/// from -1 to 1 like `CAMERA_DIR`'s. Spheres outside the field of view cover nothing and those
+/// on its edge only the part inside it.
+pub fn screen_coverage(eye: [f32; 3], forward: [f32; 3], center: [f32; 3], radius: f32) -> f32 {
+    let length = |v: [f32; 3]| v.iter().map(|c| c * c).sum::<f32>().sqrt();
+    let to_center = [0, 1, 2].map(|i| center[i] - eye[i]);
+    let distance = length(to_center);
+    if distance <= radius {
+        return 1.0;
+    }
+    let plane = length(forward);
+    let along: f32 = (0..3).map(|i| to_center[i] * forward[i]).sum();
+    let angle = (along / (distance * plane)).clamp(-1.0, 1.0).acos();
+    let angular_radius = (radius / distance).asin();
+    let half_fov = (1.0 / plane).atan();
+    let inside = ((half_fov - angle + angular_radius) / (2.0 * angular_radius)).clamp(0.0, 1.0);
+    // The image is 2 wide, the sphere's disc on it is as wide as the cone it spans.
+    let projected_radius = plane * angular_radius.tan();
+    (std::f32::consts::PI * projected_radius * projected_radius / 4.0 * inside).min(1.0)
+}
@@ -0,0 +1,83 @@
+use crate::{
+    simulation::{LAVA, WATER},
+    world::WORLD_SIZE,
+    worldgen::{chunk_rng, Terrain},
+};
+use rand::Rng;
+use std::f32::consts::{FRAC_PI_2, PI};
+
+/// Steps of one voxel a worm takes at most.
+const WORM_LENGTH: u32 = 240;
+/// Radius of the tunnels worms dig, in voxels.
+const WORM_RADIUS: [f32; 2] = [1.5, 3.0];
+/// Radians a worm turns by at most per step, up and down only half as much.
+const WORM_TURN: f32 = 0.15;
+/// Steepest pitch a worm starts down from the surface with, in radians below the horizon.
+const WORM_DIVE: [f32; 2] = [0.5, 1.2];
+/// Share of its pitch a worm levels out by per step once below the surface.
+const WORM_LEVELING: f32 = 0.02;
+/// Columns tried at most per worm to find one not under water.
+const ENTRANCE_TRIES: u32 = 16;
+/// Mixed into the terrain's seed so worms don't draw the same random numbers as the veins.
+const WORM_SEED: u64 = 0x6361_7665;
+
+/// Digs `terrain.worms` tunnels into `voxels`, a whole world generated from `terrain` indexed
+/// like `World`'s. Every worm starts at the surface of a dry column, an entrance to the caves,
+/// dives down and wanders until it runs out of steps or hits the bottom of the world, crossing the
+/// tunnels worldgen.glsl carves on its way. Water and lava are left alone, so worms running
+/// under a lake don't drain it.
+pub fn carve_worms(terrain: &Terrain, voxels: &mut [u32]) {
+    for worm in 0..terrain.worms {
+        // Every worm gets its own random numbers, so adding one changes none of the others.
+        let mut rng = chunk_rng(terrain.seed as u64 ^ WORM_SEED, [worm, 0, 0]);
+        let Some(entrance) = (0..ENTRANCE_TRIES).find_map(|_| {
+            let [x, z] = [0; 2].map(|_| rng.gen_range(0..WORLD_SIZE));
+            let y = (0..WORLD_SIZE)
+                .rev()
+                .find(|&y| voxels[index([x, y, z])] != 0)?;
+            (voxels[index([x, y, z])] != WATER).then_some([x, y, z])
+        }) else {
+            continue;
+        };
+        let radius = rng.gen_range(WORM_RADIUS[0]..WORM_RADIUS[1]);
+        let mut position = entrance.map(|c| c as f32 + 0.5);
+        let mut yaw = rng.gen_range(-PI..PI);
+        let mut pitch = -rng.gen_range(WORM_DIVE[0]..WORM_DIVE[1]);
+        for _ in 0..WORM_LENGTH {
+            dig(voxels, position, radius);
+            yaw += rng.gen_range(-WORM_TURN..WORM_TURN);
+            pitch += rng.gen_range(-WORM_TURN..WORM_TURN) * 0.5 - pitch * WORM_LEVELING;
+            pitch = pitch.clamp(-FRAC_PI_2, FRAC_PI_2);
+            position[0] += yaw.cos() * pitch.cos();
+            position[1] += pitch.sin();
+            position[2] += yaw.sin() * pitch.cos();
+            if position[1] < radius + 1.0
+                || position.iter().any(|&c| c < 0.0 || c >= WORLD_SIZE as f32)
+            {
+                break;
+            }
+        }
+    }
+}
+
+/// Empties the voxels within `radius` of `center`, except water and lava.
+fn dig(voxels: &mut [u32], center: [f32; 3], radius: f32) {
+    let min = center.map(|c| (c - radius).max(0.0) as u32);
+    let max = center.map(|c| ((c + radius).ceil() as u32).min(WORLD_SIZE));
+    for x in min[0]..max[0] {
+        for y in min[1]..max[1] {
+            for z in min[2]..max[2] {
+                let offset = [x, y, z].map(|c| c as f32 + 0.5);
+                let distance: f32 = (0..3).map(|i| (offset[i] - center[i]).powi(2)).sum();
+                let voxel = &mut voxels[index([x, y, z])];
+                if distance <= radius * radius && *voxel != WATER && *voxel != LAVA {
+                    *voxel = 0;
+                }
+            }
+        }
+    }
+}
+
+fn index([x, y, z]: [u32; 3]) -> usize {
+    ((x * WORLD_SIZE + y) * WORLD_SIZE + z) as usize
+}
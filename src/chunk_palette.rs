@@ -0,0 +1,453 @@
+use crate::{distance_field::CHUNKS, world::CHUNK_SIZE};
+use std::{mem, ops::Range};
+
+/// Voxels in a chunk.
+pub const CHUNK_VOXELS: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+/// Words at the start of the GPU copy holding one header per chunk, see `PackedChunk::header`.
+pub const HEADER_WORDS: u32 = CHUNKS * CHUNKS * CHUNKS;
+/// Edge length of the cubic bricks a chunk is split into, so bricks of a single voxel type are
+/// stored without indices.
+pub const BRICK_SIZE: u32 = 4;
+/// Bricks along every axis of a chunk.
+const BRICKS: u32 = CHUNK_SIZE / BRICK_SIZE;
+/// Voxels in a brick.
+const BRICK_VOXELS: usize = (BRICK_SIZE * BRICK_SIZE * BRICK_SIZE) as usize;
+/// Words at the start of a paletted chunk's words holding a 16 bit entry for every brick, see
+/// `PackedChunk::Paletted`.
+const BRICK_TABLE_WORDS: usize = (BRICKS * BRICKS * BRICKS) as usize / 2;
+/// Most words a chunk packs into apart from its header: the brick table, a palette entry for
+/// every voxel and 16 bit indices.
+pub const MAX_PACKED_WORDS: usize = BRICK_TABLE_WORDS + CHUNK_VOXELS + CHUNK_VOXELS / 2;
+
+/// Widths in bits palette indices are packed with, the narrowest one a chunk's palette fits in
+/// is used. All of them divide 32, so no index straddles two words.
+const INDEX_BITS: [u32; 5] = [1, 2, 4, 8, 16];
+/// Bits of a header holding the index width, the others hold a voxel type or an offset.
+const WIDTH_BITS: u32 = 5;
+/// Bits of an offset into a GPU copy split into pages addressing a word within its page, the
+/// others pick the page.
+pub const PAGE_BITS: u32 = 20;
+/// Words in every page of a GPU copy split into pages.
+pub const PAGE_WORDS: u32 = 1 << PAGE_BITS;
+/// Most pages a GPU copy is split into, as many as the offsets in headers reach.
+pub const MAX_PAGES: u32 = 1 << (32 - WIDTH_BITS - PAGE_BITS);
+
+/// A chunk as the trace shader reads it: either a single voxel type filling all of it, or the
+/// types it holds in a palette and for every voxel the index of its type packed into words.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PackedChunk {
+    /// Nothing but the header, which rays skip whole when the voxel type is empty.
+    Uniform(u32),
+    Paletted {
+        /// Width of the indices in bits.
+        bits: u32,
+        /// A 16 bit entry for every brick, two to a word and indexed by
+        /// `(x * BRICKS + y) * BRICKS + z`: 0 for a brick holding no voxels, which rays skip
+        /// without walking it, the palette index shifted up with the lowest bit set for a brick
+        /// filled by a single voxel type, read without any indices, and otherwise where the
+        /// brick's indices start shifted up. Then the palette, and the packed indices of every
+        /// brick holding more than one type, indexed like the bricks and the lowest bits of a
+        /// word holding the first of its indices.
+        words: Vec<u32>,
+    },
+}
+
+impl PackedChunk {
+    /// Packs the voxels of a chunk, indexed by `(x * CHUNK_SIZE + y) * CHUNK_SIZE + z`.
+    pub fn pack(voxels: &[u32]) -> PackedChunk {
+        let mut palette: Vec<u32> = Vec::new();
+        let indices: Vec<u32> = voxels
+            .iter()
+            .map(
+                |&voxel| match palette.iter().position(|&entry| entry == voxel) {
+                    Some(index) => index as u32,
+                    None => {
+                        palette.push(voxel);
+                        palette.len() as u32 - 1
+                    }
+                },
+            )
+            .collect();
+        if let [voxel] = palette[..] {
+            if voxel >> (32 - WIDTH_BITS) == 0 {
+                return PackedChunk::Uniform(voxel);
+            }
+        }
+        let bits = INDEX_BITS
+            .into_iter()
+            .find(|&bits| palette.len() <= 1 << bits)
+            .unwrap();
+        let per_word = (32 / bits) as usize;
+        let mut table = vec![0; BRICK_TABLE_WORDS];
+        let mut mixed = Vec::new();
+        for brick in 0..BRICK_TABLE_WORDS * 2 {
+            let brick_indices: Vec<u32> = (0..BRICK_VOXELS)
+                .map(|voxel| indices[brick_voxel(brick, voxel)])
+                .collect();
+            let first = brick_indices[0];
+            let entry = if brick_indices.iter().any(|&index| index != first) {
+                let start = BRICK_TABLE_WORDS + palette.len() + mixed.len();
+                mixed.extend(brick_indices.chunks(per_word).map(|indices| {
+                    indices
+                        .iter()
+                        .enumerate()
+                        .fold(0, |word, (i, &index)| word | index << (i as u32 * bits))
+                }));
+                (start as u32) << 1
+            } else if palette[first as usize] == 0 {
+                0
+            } else {
+                first << 1 | 1
+            };
+            table[brick / 2] |= entry << (brick % 2 * 16);
+        }
+        let mut words = table;
+        words.extend(palette);
+        words.extend(mixed);
+        PackedChunk::Paletted { bits, words }
+    }
+
+    /// Returns the words stored apart from the header, none for a uniform chunk.
+    pub fn words(&self) -> &[u32] {
+        match self {
+            PackedChunk::Uniform(_) => &[],
+            PackedChunk::Paletted { words, .. } => words,
+        }
+    }
+
+    /// Returns the chunk's header, with its words stored at `offset`. The lowest bits hold the
+    /// width of the indices, 0 for a uniform chunk, and the others its voxel type or `offset`.
+    pub fn header(&self, offset: u32) -> u32 {
+        match self {
+            PackedChunk::Uniform(voxel) => voxel << WIDTH_BITS,
+            PackedChunk::Paletted { bits, .. } => offset << WIDTH_BITS | bits,
+        }
+    }
+}
+
+/// Returns the index into a chunk of the `voxel`th voxel of its `brick`th brick, both indexed
+/// like the voxels of a chunk.
+fn brick_voxel(brick: usize, voxel: usize) -> usize {
+    let (bricks, brick_size, size) = (BRICKS as usize, BRICK_SIZE as usize, CHUNK_SIZE as usize);
+    let corner = [
+        brick / (bricks * bricks),
+        brick / bricks % bricks,
+        brick % bricks,
+    ];
+    let offset = [
+        voxel / (brick_size * brick_size),
+        voxel / brick_size % brick_size,
+        voxel % brick_size,
+    ];
+    let cell: [usize; 3] = std::array::from_fn(|axis| corner[axis] * brick_size + offset[axis]);
+    (cell[0] * size + cell[1]) * size + cell[2]
+}
+
+/// Where the words of every chunk are in the GPU copy, after the headers. A chunk keeps its
+/// place while its words fit and moves otherwise, into the first gap left behind by chunks which
+/// moved before it or to the end. In a copy split into pages of `PAGE_WORDS` words no chunk
+/// straddles two of them.
+pub struct ChunkSlots {
+    /// Words reserved for each chunk, indexed by `(x * CHUNKS + y) * CHUNKS + z`.
+    slots: Vec<Range<u32>>,
+    /// Words no chunk holds before `end`, sorted and with no two touching.
+    gaps: Vec<Range<u32>>,
+    /// Where the words of chunks fitting in no gap go.
+    end: u32,
+    /// Words in the GPU copy.
+    capacity: u32,
+    /// Whether the GPU copy is split into pages.
+    paged: bool,
+}
+
+impl ChunkSlots {
+    /// Places for the chunks of a cleared GPU copy holding `capacity` words, split into pages
+    /// when `paged`.
+    pub fn new(capacity: u32, paged: bool) -> ChunkSlots {
+        ChunkSlots {
+            slots: vec![0..0; HEADER_WORDS as usize],
+            gaps: Vec::new(),
+            end: HEADER_WORDS,
+            capacity,
+            paged,
+        }
+    }
+
+    /// Returns the index of `chunk`'s header.
+    pub fn index(chunk: [u32; 3]) -> usize {
+        ((chunk[0] * CHUNKS + chunk[1]) * CHUNKS + chunk[2]) as usize
+    }
+
+    /// Returns where to store `len` words of `chunk`, `None` when they don't fit anymore. A
+    /// chunk which moves leaves a gap behind, and may move into the gap it leaves when that
+    /// touches another.
+    pub fn place(&mut self, chunk: [u32; 3], len: u32) -> Option<u32> {
+        let index = ChunkSlots::index(chunk);
+        if len as usize <= self.slots[index].len() {
+            return Some(self.slots[index].start);
+        }
+        self.release(chunk);
+        let start = match self.take_gap(len) {
+            Some(start) => start,
+            None => {
+                let start = self.page_start(self.end, len);
+                if start + len > self.capacity {
+                    return None;
+                }
+                let skipped = self.end..start;
+                self.end = start + len;
+                self.free(skipped);
+                start
+            }
+        };
+        self.slots[index] = start..start + len;
+        Some(start)
+    }
+
+    /// Gives the words of `chunk` back, once it needs none besides its header.
+    pub fn release(&mut self, chunk: [u32; 3]) {
+        let slot = mem::replace(&mut self.slots[ChunkSlots::index(chunk)], 0..0);
+        self.free(slot);
+    }
+
+    /// Makes room for at least `len` more words at the end, doubling the capacity at least so
+    /// the GPU copy is seldom replaced, or adding a page to a copy split into pages, which every
+    /// chunk fits in. Returns the new capacity.
+    pub fn grow(&mut self, len: u32) -> u32 {
+        self.capacity = if self.paged {
+            self.capacity + PAGE_WORDS
+        } else {
+            (self.capacity * 2).max(self.end + len)
+        };
+        self.capacity
+    }
+
+    /// Returns where `len` words starting at `start` or after it go, the start of the next page
+    /// when they would straddle two.
+    fn page_start(&self, start: u32, len: u32) -> u32 {
+        if self.paged && start % PAGE_WORDS + len > PAGE_WORDS {
+            start + PAGE_WORDS - start % PAGE_WORDS
+        } else {
+            start
+        }
+    }
+
+    /// Takes `len` words from the first gap holding them, returning where they start.
+    fn take_gap(&mut self, len: u32) -> Option<u32> {
+        let (index, start) = self.gaps.iter().enumerate().find_map(|(index, gap)| {
+            let start = self.page_start(gap.start, len);
+            (start + len <= gap.end).then_some((index, start))
+        })?;
+        let gap = self.gaps[index].clone();
+        let rest = [gap.start..start, start + len..gap.end];
+        self.gaps.splice(
+            index..=index,
+            rest.into_iter().filter(|rest| !rest.is_empty()),
+        );
+        Some(start)
+    }
+
+    /// Adds `words` to the gaps, merging it with the ones it touches. Gaps reaching `end` move
+    /// it back instead.
+    fn free(&mut self, mut words: Range<u32>) {
+        if words.is_empty() {
+            return;
+        }
+        let index = self.gaps.partition_point(|gap| gap.end < words.start);
+        let mut touching = index;
+        while touching < self.gaps.len() && self.gaps[touching].start <= words.end {
+            words.start = words.start.min(self.gaps[touching].start);
+            words.end = words.end.max(self.gaps[touching].end);
+            touching += 1;
+        }
+        if words.end == self.end {
+            self.end = words.start;
+            self.gaps.drain(index..touching);
+        } else {
+            self.gaps.splice(index..touching, [words]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the voxels of `packed` back the way the trace shader does.
+    fn unpack(packed: &PackedChunk) -> Vec<u32> {
+        let header = packed.header(0);
+        let bits = header & ((1 << WIDTH_BITS) - 1);
+        if bits == 0 {
+            return vec![header >> WIDTH_BITS; CHUNK_VOXELS];
+        }
+        let words = packed.words();
+        let per_word = (32 / bits) as usize;
+        let mut voxels = vec![0; CHUNK_VOXELS];
+        for brick in 0..BRICK_TABLE_WORDS * 2 {
+            let entry = (words[brick / 2] >> (brick % 2 * 16)) & 0xffff;
+            for voxel in 0..BRICK_VOXELS {
+                let palette_index = if entry & 1 != 0 {
+                    entry >> 1
+                } else if entry == 0 {
+                    continue;
+                } else {
+                    let word = words[(entry >> 1) as usize + voxel / per_word];
+                    (word >> ((voxel % per_word) as u32 * bits)) & ((1 << bits) - 1)
+                };
+                voxels[brick_voxel(brick, voxel)] =
+                    words[BRICK_TABLE_WORDS + palette_index as usize];
+            }
+        }
+        voxels
+    }
+
+    /// Returns the table entry of the brick at `brick` in `packed`.
+    fn brick_entry(packed: &PackedChunk, brick: [u32; 3]) -> u32 {
+        let index = ((brick[0] * BRICKS + brick[1]) * BRICKS + brick[2]) as usize;
+        (packed.words()[index / 2] >> (index % 2 * 16)) & 0xffff
+    }
+
+    /// A chunk cycling through `types` voxel types, starting at type 1.
+    fn chunk_of(types: u32) -> Vec<u32> {
+        (0..CHUNK_VOXELS as u32)
+            .map(|index| index % types + 1)
+            .collect()
+    }
+
+    #[test]
+    fn pack_uses_the_narrowest_index_width() {
+        for (types, bits) in [(2, 1), (4, 2), (16, 4), (256, 8), (257, 16), (4096, 16)] {
+            let voxels = chunk_of(types);
+            let packed = PackedChunk::pack(&voxels);
+            match &packed {
+                PackedChunk::Paletted {
+                    bits: packed_bits,
+                    words,
+                } => {
+                    assert_eq!(*packed_bits, bits, "{types} types");
+                    let indices = CHUNK_VOXELS * bits as usize / 32;
+                    assert_eq!(words.len(), BRICK_TABLE_WORDS + types as usize + indices);
+                }
+                PackedChunk::Uniform(_) => panic!("{types} types packed uniform"),
+            }
+            assert_eq!(unpack(&packed), voxels, "{types} types");
+        }
+    }
+
+    #[test]
+    fn pack_stores_a_single_type_in_the_header() {
+        let packed = PackedChunk::pack(&[7; CHUNK_VOXELS]);
+        assert_eq!(packed, PackedChunk::Uniform(7));
+        assert!(packed.words().is_empty());
+        assert_eq!(unpack(&packed), vec![7; CHUNK_VOXELS]);
+        assert_eq!(PackedChunk::pack(&[0; CHUNK_VOXELS]).header(0), 0);
+        // Too large for the header, so paletted after all.
+        let large = 1 << (32 - WIDTH_BITS);
+        let packed = PackedChunk::pack(&[large; CHUNK_VOXELS]);
+        assert!(matches!(packed, PackedChunk::Paletted { bits: 1, .. }));
+        assert_eq!(unpack(&packed), vec![large; CHUNK_VOXELS]);
+    }
+
+    #[test]
+    fn pack_marks_empty_bricks() {
+        let mut voxels = vec![0; CHUNK_VOXELS];
+        // In the brick at (1, 0, 2).
+        let size = CHUNK_SIZE as usize;
+        voxels[(BRICK_SIZE as usize * size) * size + 2 * BRICK_SIZE as usize] = 5;
+        let packed = PackedChunk::pack(&voxels);
+        assert_eq!(brick_entry(&packed, [0, 0, 0]), 0);
+        assert_ne!(brick_entry(&packed, [1, 0, 2]), 0);
+        assert_eq!(unpack(&packed), voxels);
+    }
+
+    #[test]
+    fn pack_stores_solid_bricks_without_indices() {
+        // Type 3 below y = 4, type 5 above with a single empty voxel, so only the brick holding
+        // that one needs indices.
+        let size = CHUNK_SIZE as usize;
+        let mut voxels: Vec<u32> = (0..CHUNK_VOXELS)
+            .map(|index| if index / size % size < 4 { 3 } else { 5 })
+            .collect();
+        voxels[(size + 9) * size + 2] = 0;
+        let packed = PackedChunk::pack(&voxels);
+        let PackedChunk::Paletted { bits: 2, words } = &packed else {
+            panic!("{packed:?}");
+        };
+        assert_eq!(words.len(), BRICK_TABLE_WORDS + 3 + BRICK_VOXELS * 2 / 32);
+        for brick in [[0, 0, 0], [3, 0, 3]] {
+            assert_eq!(brick_entry(&packed, brick) & 1, 1);
+        }
+        assert_eq!(brick_entry(&packed, [0, 2, 0]) & 1, 0);
+        assert_eq!(unpack(&packed), voxels);
+    }
+
+    #[test]
+    fn slots_keep_chunks_in_place_while_they_fit() {
+        let mut slots = ChunkSlots::new(HEADER_WORDS + 100, false);
+        assert_eq!(slots.place([0, 0, 0], 50), Some(HEADER_WORDS));
+        assert_eq!(slots.place([0, 0, 1], 20), Some(HEADER_WORDS + 50));
+        assert_eq!(slots.place([0, 0, 0], 30), Some(HEADER_WORDS));
+        assert_eq!(slots.place([0, 0, 0], 50), Some(HEADER_WORDS));
+        assert_eq!(slots.place([0, 0, 1], 60), None);
+    }
+
+    #[test]
+    fn slots_reuse_the_gaps_chunks_leave() {
+        let mut slots = ChunkSlots::new(HEADER_WORDS + 100, false);
+        slots.place([0, 0, 0], 30);
+        slots.place([0, 0, 1], 30);
+        // Moves to the end, leaving its words for the next chunk.
+        assert_eq!(slots.place([0, 0, 0], 40), Some(HEADER_WORDS + 60));
+        assert_eq!(slots.place([0, 0, 2], 20), Some(HEADER_WORDS));
+        // Merged with the words it leaves behind.
+        assert_eq!(slots.place([0, 0, 1], 40), Some(HEADER_WORDS + 20));
+        // Released at the end, so the next chunk goes there.
+        slots.release([0, 0, 0]);
+        assert_eq!(slots.place([0, 0, 3], 40), Some(HEADER_WORDS + 60));
+    }
+
+    #[test]
+    fn slots_stop_growing_while_chunks_change_size() {
+        const MAX_LEN: u32 = 300;
+        let mut slots = ChunkSlots::new(HEADER_WORDS, false);
+        let chunks = [[0, 0, 0], [0, 0, 1], [3, 2, 1], [CHUNKS - 1; 3]];
+        for step in 0..10_000u32 {
+            let chunk = chunks[step as usize % chunks.len()];
+            let len = 1 + step.wrapping_mul(2_654_435_761) % MAX_LEN;
+            if step % 7 == 0 {
+                slots.release(chunk);
+            } else if slots.place(chunk, len).is_none() {
+                slots.grow(len);
+                assert!(slots.place(chunk, len).is_some());
+            }
+        }
+        // Twice what the chunks could hold at once, doubled once more at most.
+        let held = chunks.len() as u32 * MAX_LEN;
+        assert!(
+            slots.capacity <= 2 * (HEADER_WORDS + 2 * held),
+            "{}",
+            slots.capacity
+        );
+    }
+
+    #[test]
+    fn paged_slots_keep_chunks_within_a_page() {
+        let mut slots = ChunkSlots::new(2 * PAGE_WORDS, true);
+        let len = MAX_PACKED_WORDS as u32;
+        let chunk = |index: u32| {
+            [
+                index / CHUNKS / CHUNKS,
+                index / CHUNKS % CHUNKS,
+                index % CHUNKS,
+            ]
+        };
+        let mut index = 0;
+        while let Some(start) = slots.place(chunk(index), len) {
+            assert_eq!(start / PAGE_WORDS, (start + len - 1) / PAGE_WORDS);
+            index += 1;
+        }
+        assert_eq!(slots.grow(len), 3 * PAGE_WORDS);
+        let start = slots.place(chunk(index), len).unwrap();
+        assert_eq!(start, 2 * PAGE_WORDS);
+    }
+}
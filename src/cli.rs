@@ -0,0 +1,239 @@
+use crate::{
+    settings::{Preset, Settings},
+    swapchain,
+};
+use rvengine::{
+    autosave::{DEFAULT_AUTOSAVE_INTERVAL, DEFAULT_AUTOSAVE_SLOTS},
+    worldgen,
+};
+use vulkano::swapchain::PresentMode;
+
+pub const USAGE: &str =
+    "usage: rvengine [render_distance] [--preset <low|medium|high|ultra>] [--palette <file>] [--prefab <file.vox>]... [--stats <file.json>] \
+     [--present-mode <fifo|relaxed|mailbox|immediate>] [--swapchain-images <2|3>] [--low-latency] \
+     [--compute-gpu <discrete|integrated|name>] [--seed <number>] [--gpu-terrain] \
+     [--generator <scatter|menger|sierpinski|mandelbulb>] [--generator-size <voxels>] [--veins <file>] [--structures <file>] [--scene <file.rvscene>] [--map <directory>] \
+     [--post <file.glsl|file.spv|toon>] [--trace-shader <file.glsl|file.spv>] [--autotune] \
+     [--autosave <seconds|off>] [--autosave-slots <count>]\n\
+     usage: rvengine view <model.vox> [options]";
+
+/// Palette file used when `--palette` isn't given.
+pub const DEFAULT_PALETTE_PATH: &str = "palette.txt";
+
+/// Options given on the command line.
+pub struct Args {
+    pub settings: Settings,
+    /// Palette file to load at startup, also where edits are saved.
+    pub palette: Option<String>,
+    /// `.vox` files to load as prefabs.
+    pub prefabs: Vec<String>,
+    /// File the session statistics are written to on exit.
+    pub stats: Option<String>,
+    /// Present mode to use if the surface supports it, fifo or mailbox for `low_latency` when
+    /// `None`.
+    pub present_mode: Option<PresentMode>,
+    /// Swapchain images to ask for, the surface's minimum when `None`.
+    pub swapchain_images: Option<u32>,
+    /// Paces frames so input is sampled as late as possible, see `LatencyLimiter`.
+    pub low_latency: bool,
+    /// Selects the GPU tracing and presenting run on, see `gpu::matches`.
+    pub compute_gpu: Option<String>,
+    /// Seed the world is generated from, a random one when `None`.
+    pub seed: Option<u64>,
+    /// Generates terrain on the GPU instead of scattering voxels on the CPU.
+    pub gpu_terrain: bool,
+    /// Name of the `worldgen::preset` generating the world on the CPU.
+    pub generator: String,
+    /// Edge length of the region the generator fills, its own default when `None`.
+    pub generator_size: Option<u32>,
+    /// File listing the ore veins scattered through generated worlds, see `Veins`.
+    pub veins: Option<String>,
+    /// File of the rules placing structures on generated worlds, see `Structures`.
+    pub structures: Option<String>,
+    /// Directory of a map written by `rvengine import` or `rvengine import-mca` to explore, see
+    /// `MapStream`.
+    pub map: Option<String>,
+    /// Scene file to open instead of generating a world.
+    pub scene: Option<String>,
+    /// Post-process shader to run over every frame, see `post_process`.
+    pub post_process: Option<String>,
+    /// Compute shader to trace with instead of the built-in one, see
+    /// `Controller::load_trace_shader`.
+    pub trace_shader: Option<String>,
+    /// Times the trace shader's workgroup sizes again even if one was saved for the GPU, see
+    /// `autotune`.
+    pub autotune: bool,
+    /// `.vox` model shown on its own in the model viewer instead of a world, see
+    /// `FractalApp::view_model`.
+    pub view: Option<String>,
+    /// Seconds between autosaves, `None` to not autosave, see `Autosave`.
+    pub autosave: Option<f32>,
+    /// Autosave slots written in turn.
+    pub autosave_slots: u32,
+}
+
+impl Args {
+    /// Parses the arguments following the program name.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+        let mut preset = None;
+        let mut render_distance = None;
+        let mut palette = None;
+        let mut prefabs = Vec::new();
+        let mut stats = None;
+        let mut present_mode = None;
+        let mut swapchain_images = None;
+        let mut low_latency = false;
+        let mut compute_gpu = None;
+        let mut seed = None;
+        let mut gpu_terrain = false;
+        let mut generator = "scatter".to_string();
+        let mut generator_size = None;
+        let mut veins = None;
+        let mut structures = None;
+        let mut map = None;
+        let mut scene = None;
+        let mut post_process = None;
+        let mut trace_shader = None;
+        let mut autotune = false;
+        let mut autosave = Some(DEFAULT_AUTOSAVE_INTERVAL);
+        let mut autosave_slots = DEFAULT_AUTOSAVE_SLOTS;
+        let mut args = args.into_iter().peekable();
+        let view = match args.peek().map(String::as_str) {
+            Some("view") => {
+                args.next();
+                Some(args.next().ok_or("view needs a model")?)
+            }
+            _ => None,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--preset" => {
+                    let name = args.next().ok_or("--preset needs a value")?;
+                    preset =
+                        Some(Preset::from_name(&name).ok_or(format!("unknown preset `{name}`"))?);
+                }
+                "--palette" => palette = Some(args.next().ok_or("--palette needs a value")?),
+                "--prefab" => prefabs.push(args.next().ok_or("--prefab needs a value")?),
+                "--stats" => stats = Some(args.next().ok_or("--stats needs a value")?),
+                "--scene" => scene = Some(args.next().ok_or("--scene needs a value")?),
+                "--map" => map = Some(args.next().ok_or("--map needs a value")?),
+                "--veins" => veins = Some(args.next().ok_or("--veins needs a value")?),
+                "--structures" => {
+                    structures = Some(args.next().ok_or("--structures needs a value")?)
+                }
+                "--post" => post_process = Some(args.next().ok_or("--post needs a value")?),
+                "--trace-shader" => {
+                    trace_shader = Some(args.next().ok_or("--trace-shader needs a value")?)
+                }
+                "--present-mode" => {
+                    let name = args.next().ok_or("--present-mode needs a value")?;
+                    present_mode = Some(
+                        swapchain::present_mode_from_name(&name)
+                            .ok_or(format!("unknown present mode `{name}`"))?,
+                    );
+                }
+                "--low-latency" => low_latency = true,
+                "--gpu-terrain" => gpu_terrain = true,
+                "--autotune" => autotune = true,
+                "--generator" => {
+                    generator = args.next().ok_or("--generator needs a value")?;
+                    if !worldgen::PRESETS.contains(&generator.as_str()) {
+                        return Err(format!("unknown generator `{generator}`"));
+                    }
+                }
+                "--generator-size" => {
+                    let value = args.next().ok_or("--generator-size needs a value")?;
+                    generator_size = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|err| format!("invalid generator size `{value}`: {err}"))?,
+                    );
+                }
+                "--autosave" => {
+                    let value = args.next().ok_or("--autosave needs a value")?;
+                    autosave = match value.as_str() {
+                        "off" => None,
+                        _ => Some(
+                            value
+                                .parse::<f32>()
+                                .ok()
+                                .filter(|&seconds| seconds > 0.0)
+                                .ok_or(format!("invalid autosave interval `{value}`"))?,
+                        ),
+                    };
+                }
+                "--autosave-slots" => {
+                    let value = args.next().ok_or("--autosave-slots needs a value")?;
+                    autosave_slots = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&slots| slots > 0)
+                        .ok_or(format!("invalid autosave slot count `{value}`"))?;
+                }
+                "--compute-gpu" => {
+                    compute_gpu = Some(args.next().ok_or("--compute-gpu needs a value")?)
+                }
+                "--seed" => {
+                    let value = args.next().ok_or("--seed needs a value")?;
+                    seed = Some(
+                        value
+                            .parse::<u64>()
+                            .map_err(|err| format!("invalid seed `{value}`: {err}"))?,
+                    );
+                }
+                "--swapchain-images" => {
+                    let count = args.next().ok_or("--swapchain-images needs a value")?;
+                    swapchain_images = Some(
+                        count
+                            .parse::<u32>()
+                            .ok()
+                            .filter(|count| (2..=3).contains(count))
+                            .ok_or(format!("swapchain images must be 2 or 3, not `{count}`"))?,
+                    );
+                }
+                _ if render_distance.is_none() && !arg.starts_with("--") => {
+                    render_distance = Some(
+                        arg.parse::<u32>()
+                            .map_err(|err| format!("invalid render distance `{arg}`: {err}"))?,
+                    );
+                }
+                _ => return Err(format!("unexpected argument `{arg}`")),
+            }
+        }
+
+        // Mailbox replaces queued frames with newer ones and two images keep the queue short.
+        if low_latency {
+            present_mode = present_mode.or(Some(PresentMode::Mailbox));
+            swapchain_images = swapchain_images.or(Some(2));
+        }
+        let mut settings = preset.map(Settings::from_preset).unwrap_or_default();
+        if let Some(render_distance) = render_distance {
+            settings.render_distance = render_distance;
+            settings.preset = None;
+        }
+        Ok(Args {
+            settings,
+            palette,
+            prefabs,
+            stats,
+            present_mode,
+            swapchain_images,
+            low_latency,
+            compute_gpu,
+            seed,
+            gpu_terrain,
+            generator,
+            generator_size,
+            veins,
+            structures,
+            map,
+            scene,
+            post_process,
+            trace_shader,
+            autotune,
+            view,
+            autosave,
+            autosave_slots,
+        })
+    }
+}
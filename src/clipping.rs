@@ -0,0 +1,93 @@
+/// Clipping planes the renderer keeps room for.
+pub const MAX_CLIP_PLANES: usize = 3;
+
+/// A plane cutting the world open: voxels whose center lies on the side `normal` points to are
+/// passed through by rays like empty space, so whatever they hid, like caves or the rooms of a
+/// building, can be seen. Points `p` with `normal · p > distance` are cut away.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipPlane {
+    /// Unit length.
+    pub normal: [f32; 3],
+    pub distance: f32,
+}
+
+impl ClipPlane {
+    /// Returns the plane through `point` cutting away what lies towards `normal`, which needn't
+    /// have unit length.
+    pub fn through(point: [f32; 3], normal: [f32; 3]) -> Result<ClipPlane, String> {
+        let length = normal.iter().map(|c| c * c).sum::<f32>().sqrt();
+        if !length.is_normal() {
+            return Err(format!("invalid clipping plane normal {normal:?}"));
+        }
+        let normal = normal.map(|c| c / length);
+        Ok(ClipPlane {
+            normal,
+            distance: dot(normal, point),
+        })
+    }
+
+    /// Returns whether `point` is cut away.
+    pub fn clips(&self, point: [f32; 3]) -> bool {
+        dot(self.normal, point) > self.distance
+    }
+}
+
+/// The clipping planes the world is cut by, see `ClipPlane`. Voxels are cut away when any plane
+/// cuts their center.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClipPlanes {
+    planes: Vec<ClipPlane>,
+    /// Color the faces of voxels left next to voxels that were cut away are drawn in, so the cut
+    /// surface stands out from the world's own faces, `None` to draw them like any other.
+    pub cap: Option<[f32; 3]>,
+}
+
+impl ClipPlanes {
+    pub fn new() -> ClipPlanes {
+        ClipPlanes::default()
+    }
+
+    pub fn planes(&self) -> &[ClipPlane] {
+        &self.planes
+    }
+
+    /// Adds `plane`, returning its index.
+    pub fn add(&mut self, plane: ClipPlane) -> Result<usize, String> {
+        if self.planes.len() >= MAX_CLIP_PLANES {
+            return Err(format!(
+                "there can't be more than {MAX_CLIP_PLANES} clipping planes"
+            ));
+        }
+        self.planes.push(plane);
+        Ok(self.planes.len() - 1)
+    }
+
+    /// Removes the plane at `index`, moving the ones after it down.
+    pub fn remove(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.planes.len() {
+            return Err(format!("there is no clipping plane {index}"));
+        }
+        self.planes.remove(index);
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.planes.clear();
+    }
+
+    /// Returns whether any plane cuts `point` away.
+    pub fn clips(&self, point: [f32; 3]) -> bool {
+        self.planes.iter().any(|plane| plane.clips(point))
+    }
+
+    /// Moves every plane by `offset`, after the world moved under them.
+    pub fn shift(&mut self, offset: [f32; 3]) {
+        for plane in &mut self.planes {
+            plane.distance += dot(plane.normal, offset);
+        }
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
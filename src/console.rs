@@ -0,0 +1,885 @@
+use rvengine::{demo::DEMOS, flythrough::Easing, materials::MATERIAL_COUNT};
+use std::{
+    io::BufRead,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+pub const HELP: &str = "commands: fill <type>, replace <from> <to>, hollow, walls [type], \
+                        deselect, undo, redo, stats [file], gravity, terrain [seed], \
+                        terrain panel on, terrain panel off, terrain erode <steps>, \
+                        terrain caves <width> <entrances>, \
+                        portal [quarter turns], portal clear, demo <name>, scene save <file>, \
+                        scene load <file>, bookmark <name>, goto <name>, sun <hour>, sun angle <degrees>, \
+                        time set <hh:mm>, time speed <factor>, world stats, \
+                        fov <degrees>, fly key [travel] [hold] [easing], fly play, fly stop, \
+                        fly list, fly clear, post <file.glsl|file.spv>, post toon, post off, \
+                        shader <file.glsl|file.spv>, shader default, agents <count>, \
+                        agents clear, bake [samples], bake on, bake off, probe add, \
+                        probe remove <index>, probe clear, probe list, probe render, \
+                        shadows colored, shadows plain, metalness <0..1>, \
+                        normals <file.ppm>, normals off, detail on, detail off, chunks on, \
+                        chunks off, steps log on, steps log off, gizmo on, gizmo off, \
+                        photo on, photo off, photo shot [file.ppm|file.exr|file.png], \
+                        photo load <file>, photo annotate on, photo annotate off, \
+                        photo alpha on, photo alpha off, \
+                        roll <degrees>, dof <aperture> [distance], dof off, camera free, \
+                        camera walk, camera orbit [agent], autosave <seconds>, autosave off, \
+                        autosave now, objects load <scene.vox>, objects scatter <model.vox> <count>, \
+                        objects clear, objects list, objects distance <voxels>, \
+                        objects impostors <voxels>, objects impostors off, \
+                        object <index> move <x> <y> <z>, object <index> turn <x> <y> <z>, \
+                        object <index> scale <factor>, object <index> layer <name>, \
+                        anim key <object> [travel] [hold] [easing], anim loop <object> on|off, \
+                        anim play, anim stop, anim list, anim clear, decal <radius> [opacity], \
+                        decal path <radius> [opacity], decal remove <index>, decal clear, \
+                        decal list, layer list, layer add <name>, layer hide <name>, \
+                        layer show <name>, layer show all, layer assign <name>, clip <x> <y> <z>, \
+                        clip remove <index>, clip clear, clip list, clip cap <r> <g> <b>, \
+                        clip cap off";
+
+/// Lines typed into the terminal, read on their own thread so frames never wait for input.
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+impl Console {
+    pub fn new() -> Console {
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Console { lines }
+    }
+
+    /// Returns the non empty lines typed since the last call.
+    pub fn poll(&self) -> Vec<String> {
+        self.lines
+            .try_iter()
+            .filter(|line| !line.trim().is_empty())
+            .collect()
+    }
+}
+
+/// A console command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Sets every voxel of the selection to a type.
+    Fill(u32),
+    /// Changes the voxels of one type in the selection to another.
+    Replace(u32, u32),
+    /// Empties the selection except for its shell.
+    Hollow,
+    /// Sets the sides of the selection, to the selected material when no type is given.
+    Walls(Option<u32>),
+    Deselect,
+    Undo,
+    Redo,
+    /// Writes the session statistics, to the given file or the default one.
+    Stats(Option<String>),
+    /// Toggles whether sand and gravel fall when nothing supports them.
+    Gravity,
+    /// Replaces the world with terrain generated on the GPU, from a random seed when none is
+    /// given.
+    Terrain(Option<u64>),
+    /// Shows or hides the window tuning the terrain's noise, see `TerrainPanel`.
+    TerrainPanel(bool),
+    /// Generates the last terrain again with the given steps of erosion, 0 for none.
+    Erode(u32),
+    /// Generates the last terrain again with caves of the given width and entrances dug down to
+    /// them, 0 for none.
+    Caves(f32, u32),
+    /// Fills the selection with portal voxels. Every second portal is linked to the one before,
+    /// turning what goes through by the given quarter turns around y.
+    Portal(Option<u32>),
+    /// Removes the links between all portals.
+    ClearPortals,
+    /// Replaces the world with a built-in scene, see `demo::DEMOS`.
+    Demo(String),
+    /// Writes the scene and its world next to it.
+    SaveScene(String),
+    /// Replaces the world, materials, sun and bookmarks with those of a scene file.
+    LoadScene(String),
+    /// Remembers the camera placement under a name, replacing the one with the same name.
+    Bookmark(String),
+    /// Moves the camera to a bookmark.
+    Goto(String),
+    /// Moves the sun to an hour of the day.
+    Sun(f32),
+    /// Lets the time of day run this many times as fast as real time, 0 stops it.
+    TimeSpeed(f32),
+    /// Prints what the world holds, see `inspect::Report`.
+    WorldStats,
+    /// Sets the horizontal field of view in degrees.
+    Fov(f32),
+    /// Adds the camera placement and field of view as a flythrough keyframe, reached after
+    /// `travel` seconds and held for `hold` seconds.
+    FlyKey {
+        travel: f32,
+        hold: f32,
+        easing: Easing,
+    },
+    FlyPlay,
+    FlyStop,
+    /// Prints the flythrough's keyframes and timeline.
+    FlyList,
+    FlyClear,
+    /// Compiles a post-process shader and runs it over every frame, see `post_process`, or stops
+    /// post-processing with `None`.
+    PostProcess(Option<String>),
+    /// Traces with a custom compute shader, see `Controller::load_trace_shader`, or the built-in
+    /// one with `None`.
+    TraceShader(Option<String>),
+    /// Spawns agents wandering over the ground, see `agents::Agents`.
+    Agents(usize),
+    ClearAgents,
+    /// Bakes the lighting of the world with the given paths per face, see `light_bake`, and
+    /// lights it with the bake.
+    Bake(Option<u32>),
+    /// Switches between lighting with the bake and tracing the lighting terms.
+    BakedLighting(bool),
+    /// Places a reflection probe at the camera, see `reflection_probes`.
+    AddProbe,
+    RemoveProbe(usize),
+    ClearProbes,
+    /// Prints the reflection probes' positions.
+    ListProbes,
+    /// Renders every reflection probe again, after the world changed.
+    RenderProbes,
+    /// Switches shadow rays between passing through transparent materials tinted by them and
+    /// stopping at them, see `Settings::colored_shadows`.
+    ColoredShadows(bool),
+    /// Sets the metalness of the selected material.
+    Metalness(f32),
+    /// Loads the detail normal map from a binary PPM file, see `detail_normals`, or goes back to
+    /// flat faces with `None`.
+    DetailNormals(Option<String>),
+    /// Switches detail normals of the selected material on or off.
+    MaterialDetail(bool),
+    /// Shows or hides chunk borders tinted by upload state, see `Settings::chunk_overlay`.
+    ChunkOverlay(bool),
+    /// Starts or stops logging the share of primary rays cut short by the step cap, see
+    /// `Settings::step_log`.
+    StepLog(bool),
+    /// Shows or hides the axis gizmo, see `Settings::axis_gizmo`.
+    AxisGizmo(bool),
+    /// Turns photo mode on or off, see `FractalApp::photo_mode`.
+    Photo(bool),
+    /// Saves the next frame with how it was taken, to the given file or one named after the
+    /// time. `.exr` and `.png` files keep the frame's colors unclamped, see
+    /// `screenshot::HdrScreenshot`.
+    Screenshot(Option<String>),
+    /// Switches drawing the eye, rotation, seed, time of day and preset into the corner of
+    /// screenshots on or off.
+    AnnotateShots(bool),
+    /// Switches leaving the sky transparent in `.exr` and `.png` screenshots on or off.
+    SkyAlpha(bool),
+    /// Sets the camera up like it was for a screenshot.
+    LoadShot(String),
+    /// Rolls the view to the given degrees counterclockwise.
+    Roll(f32),
+    /// Sets the lens radius, 0 for no depth of field, and the distance in focus, the voxel under
+    /// the cursor's when none is given.
+    DepthOfField(f32, Option<f32>),
+    /// Switches between flying along the view, turning around its own axes and rolling with Q
+    /// and E, and walking along the ground with the horizon level.
+    FreeCamera(bool),
+    /// Circles the camera around the voxel under the cursor, or follows the agent at the given
+    /// index, orbiting while the middle mouse button is dragged and zooming with the wheel.
+    Orbit(Option<usize>),
+    /// Sets the seconds between autosaves, `None` to stop autosaving.
+    Autosave(Option<f32>),
+    /// Autosaves right away, changed or not.
+    AutosaveNow,
+    /// Replaces the voxel objects with the models of a MagicaVoxel scene, laid out like in it,
+    /// see `objects`.
+    LoadObjects(String),
+    /// Adds the given number of voxel objects sharing the first model of a MagicaVoxel file,
+    /// standing on the ground at random.
+    ScatterObjects(String, usize),
+    ClearObjects,
+    /// Prints every voxel object's model and transform.
+    ListObjects,
+    /// Culls voxel objects farther than the given voxels from the eye, see
+    /// `Settings::object_distance`.
+    ObjectDistance(u32),
+    /// Draws voxel objects farther than the given voxels from the eye as impostors, `None` to
+    /// trace them all, see `Settings::impostor_distance`.
+    ImpostorDistance(Option<u32>),
+    /// Moves the center of the voxel object at the index to the given position.
+    MoveObject(usize, [f32; 3]),
+    /// Turns the voxel object at the index to the given degrees around x, y and z.
+    TurnObject(usize, [f32; 3]),
+    /// Scales the voxels of the voxel object at the index by the given factor.
+    ScaleObject(usize, f32),
+    /// Puts the voxel object at the index on the named layer, see `layers`.
+    ObjectLayer(usize, String),
+    /// Adds the placement of the voxel object at `object` as a keyframe of its track, reached
+    /// after `travel` seconds and held for `hold` seconds, see `animation`.
+    AnimKey {
+        object: usize,
+        travel: f32,
+        hold: f32,
+        easing: Easing,
+    },
+    /// Makes the track of the voxel object at the index start over after its last keyframe or
+    /// end there.
+    AnimLoop(usize, bool),
+    AnimPlay,
+    AnimStop,
+    /// Prints every track's keyframes.
+    AnimList,
+    AnimClear,
+    /// Marks the surface under the cursor with a decal of the given radius and opacity in the
+    /// selected material's color, see `decals`.
+    AddDecal(f32, f32),
+    /// Marks the ground along the line between the selection's corners with decals of the
+    /// given radius and opacity in the selected material's color.
+    DecalPath(f32, f32),
+    RemoveDecal(usize),
+    ClearDecals,
+    /// Prints every decal's center, radius, color and opacity.
+    ListDecals,
+    /// Prints every layer, whether it is hidden and the voxel types on it.
+    ListLayers,
+    AddLayer(String),
+    /// Stops drawing the voxels and objects on the named layer, see `Settings::hidden_layers`.
+    HideLayer(String),
+    /// Draws the named layer again, or every layer with `None`.
+    ShowLayer(Option<String>),
+    /// Puts the selected material, and with it the voxels of its type, on the named layer.
+    AssignLayer(String),
+    /// Cuts away the world on the side of the given normal of a plane through the surface under
+    /// the cursor, or through the eye when there is none, see `clipping`.
+    AddClipPlane([f32; 3]),
+    RemoveClipPlane(usize),
+    ClearClipPlanes,
+    /// Prints every clipping plane's normal and distance.
+    ListClipPlanes,
+    /// Draws the cut surfaces in the given color, or like the world's own faces with `None`.
+    ClipCap(Option<[f32; 3]>),
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let mut voxel_type = |required: bool| -> Result<Option<u32>, String> {
+            match words.next() {
+                Some(word) => word
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&voxel| (voxel as usize) < MATERIAL_COUNT)
+                    .map(Some)
+                    .ok_or(format!("invalid voxel type `{word}`")),
+                None if required => Err(format!("`{name}` needs a voxel type")),
+                None => Ok(None),
+            }
+        };
+        let command = match name {
+            "fill" => Command::Fill(voxel_type(true)?.unwrap()),
+            "replace" => {
+                let from = voxel_type(true)?.unwrap();
+                Command::Replace(from, voxel_type(true)?.unwrap())
+            }
+            "hollow" => Command::Hollow,
+            "walls" => Command::Walls(voxel_type(false)?),
+            "deselect" => Command::Deselect,
+            "undo" => Command::Undo,
+            "redo" => Command::Redo,
+            "stats" => Command::Stats(words.next().map(str::to_string)),
+            "gravity" => Command::Gravity,
+            "terrain" => match words.next() {
+                Some("panel") => match words.next() {
+                    Some("on") => Command::TerrainPanel(true),
+                    Some("off") => Command::TerrainPanel(false),
+                    _ => return Err("expected `terrain panel on` or `terrain panel off`".into()),
+                },
+                Some("erode") => {
+                    let word = words
+                        .next()
+                        .ok_or("`terrain erode` needs a number of steps")?;
+                    Command::Erode(
+                        word.parse()
+                            .map_err(|err| format!("invalid steps `{word}`: {err}"))?,
+                    )
+                }
+                Some("caves") => {
+                    let (Some(width), Some(entrances)) = (words.next(), words.next()) else {
+                        return Err("expected `terrain caves <width> <entrances>`".into());
+                    };
+                    Command::Caves(
+                        width
+                            .parse()
+                            .map_err(|err| format!("invalid cave width `{width}`: {err}"))?,
+                        entrances
+                            .parse()
+                            .map_err(|err| format!("invalid entrances `{entrances}`: {err}"))?,
+                    )
+                }
+                word => Command::Terrain(
+                    word.map(|word| {
+                        word.parse()
+                            .map_err(|err| format!("invalid seed `{word}`: {err}"))
+                    })
+                    .transpose()?,
+                ),
+            },
+            "portal" => match words.next() {
+                Some("clear") => Command::ClearPortals,
+                Some(word) => Command::Portal(Some(
+                    word.parse()
+                        .map_err(|err| format!("invalid quarter turns `{word}`: {err}"))?,
+                )),
+                None => Command::Portal(None),
+            },
+            "demo" => Command::Demo(
+                words
+                    .next()
+                    .ok_or_else(|| format!("`demo` needs one of {}", DEMOS.join(", ")))?
+                    .to_string(),
+            ),
+            "scene" => {
+                let action = words.next().unwrap_or_default();
+                let mut file = || words.next().ok_or(format!("`scene {action}` needs a file"));
+                match action {
+                    "save" => Command::SaveScene(file()?.to_string()),
+                    "load" => Command::LoadScene(file()?.to_string()),
+                    _ => return Err("expected `scene save <file>` or `scene load <file>`".into()),
+                }
+            }
+            "bookmark" | "goto" => {
+                let bookmark = words
+                    .next()
+                    .ok_or(format!("`{name}` needs a name"))?
+                    .to_string();
+                if name == "bookmark" {
+                    Command::Bookmark(bookmark)
+                } else {
+                    Command::Goto(bookmark)
+                }
+            }
+            "sun" => match words.next() {
+                Some("angle") => {
+                    let degrees = words.next().ok_or("`sun angle` needs degrees")?;
+                    let degrees = degrees
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|degrees| degrees.is_finite())
+                        .ok_or(format!("invalid angle `{degrees}`"))?;
+                    // The sun rises at 6 and climbs 15 degrees an hour, see `sunDirection`.
+                    Command::Sun(6.0 + degrees / 15.0)
+                }
+                Some(hour) => Command::Sun(
+                    hour.parse::<f32>()
+                        .ok()
+                        .filter(|hour| hour.is_finite())
+                        .ok_or(format!("invalid hour `{hour}`"))?,
+                ),
+                None => return Err("expected `sun <hour>` or `sun angle <degrees>`".into()),
+            },
+            "time" => match words.next() {
+                Some("set") => {
+                    let time = words.next().ok_or("`time set` needs a time like 13:30")?;
+                    Command::Sun(parse_clock(time).ok_or(format!("invalid time `{time}`"))?)
+                }
+                Some("speed") => {
+                    let speed = words.next().ok_or("`time speed` needs a factor")?;
+                    Command::TimeSpeed(
+                        speed
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|speed| speed.is_finite())
+                            .ok_or(format!("invalid time speed `{speed}`"))?,
+                    )
+                }
+                _ => return Err("expected `time set <hh:mm>` or `time speed <factor>`".into()),
+            },
+            "fov" => {
+                let fov = words.next().ok_or("`fov` needs degrees")?;
+                Command::Fov(
+                    fov.parse::<f32>()
+                        .ok()
+                        .filter(|fov| (1.0..180.0).contains(fov))
+                        .ok_or(format!("invalid field of view `{fov}`"))?,
+                )
+            }
+            "fly" => match words.next() {
+                Some("key") => {
+                    let mut seconds = |default: f32| -> Result<f32, String> {
+                        words.next().map_or(Ok(default), |word| {
+                            word.parse()
+                                .ok()
+                                .filter(|seconds: &f32| *seconds >= 0.0)
+                                .ok_or(format!("invalid seconds `{word}`"))
+                        })
+                    };
+                    let travel = seconds(2.0)?;
+                    let hold = seconds(0.0)?;
+                    let easing = match words.next() {
+                        Some(name) => Easing::from_name(name).ok_or(format!(
+                            "unknown easing `{name}`, expected one of {}",
+                            Easing::ALL.map(Easing::name).join(", ")
+                        ))?,
+                        None => Easing::InOut,
+                    };
+                    Command::FlyKey {
+                        travel,
+                        hold,
+                        easing,
+                    }
+                }
+                Some("play") => Command::FlyPlay,
+                Some("stop") => Command::FlyStop,
+                Some("list") => Command::FlyList,
+                Some("clear") => Command::FlyClear,
+                _ => return Err("expected `fly key`, `play`, `stop`, `list` or `clear`".into()),
+            },
+            "post" => match words.next() {
+                Some("off") => Command::PostProcess(None),
+                Some(file) => Command::PostProcess(Some(file.to_string())),
+                None => return Err("expected `post <file>` or `post off`".into()),
+            },
+            "shader" => match words.next() {
+                Some("default") => Command::TraceShader(None),
+                Some(file) => Command::TraceShader(Some(file.to_string())),
+                None => return Err("expected `shader <file>` or `shader default`".into()),
+            },
+            "agents" => match words.next() {
+                Some("clear") => Command::ClearAgents,
+                Some(word) => Command::Agents(
+                    word.parse()
+                        .map_err(|err| format!("invalid agent count `{word}`: {err}"))?,
+                ),
+                None => return Err("expected `agents <count>` or `agents clear`".into()),
+            },
+            "bake" => match words.next() {
+                Some("on") => Command::BakedLighting(true),
+                Some("off") => Command::BakedLighting(false),
+                Some(word) => Command::Bake(Some(
+                    word.parse()
+                        .ok()
+                        .filter(|&samples| samples > 0)
+                        .ok_or(format!("invalid sample count `{word}`"))?,
+                )),
+                None => Command::Bake(None),
+            },
+            "probe" => match words.next() {
+                Some("add") => Command::AddProbe,
+                Some("remove") => {
+                    let word = words.next().ok_or("expected `probe remove <index>`")?;
+                    Command::RemoveProbe(
+                        word.parse()
+                            .map_err(|err| format!("invalid probe index `{word}`: {err}"))?,
+                    )
+                }
+                Some("clear") => Command::ClearProbes,
+                Some("list") => Command::ListProbes,
+                Some("render") => Command::RenderProbes,
+                _ => {
+                    return Err(
+                        "expected `probe add`, `probe remove <index>`, `probe clear`, \
+                         `probe list` or `probe render`"
+                            .into(),
+                    )
+                }
+            },
+            "shadows" => match words.next() {
+                Some("colored") => Command::ColoredShadows(true),
+                Some("plain") => Command::ColoredShadows(false),
+                _ => return Err("expected `shadows colored` or `shadows plain`".into()),
+            },
+            "metalness" => {
+                let word = words.next().ok_or("expected `metalness <0..1>`")?;
+                Command::Metalness(
+                    word.parse()
+                        .ok()
+                        .filter(|metalness| (0.0..=1.0).contains(metalness))
+                        .ok_or(format!("invalid metalness `{word}`, expected 0 to 1"))?,
+                )
+            }
+            "normals" => match words.next() {
+                Some("off") => Command::DetailNormals(None),
+                Some(path) => Command::DetailNormals(Some(path.to_string())),
+                None => return Err("expected `normals <file.ppm>` or `normals off`".into()),
+            },
+            "detail" => match words.next() {
+                Some("on") => Command::MaterialDetail(true),
+                Some("off") => Command::MaterialDetail(false),
+                _ => return Err("expected `detail on` or `detail off`".into()),
+            },
+            "chunks" => match words.next() {
+                Some("on") => Command::ChunkOverlay(true),
+                Some("off") => Command::ChunkOverlay(false),
+                _ => return Err("expected `chunks on` or `chunks off`".into()),
+            },
+            "steps" => match (words.next(), words.next()) {
+                (Some("log"), Some("on")) => Command::StepLog(true),
+                (Some("log"), Some("off")) => Command::StepLog(false),
+                _ => return Err("expected `steps log on` or `steps log off`".into()),
+            },
+            "photo" => match words.next() {
+                Some("on") => Command::Photo(true),
+                Some("off") => Command::Photo(false),
+                Some("shot") => Command::Screenshot(words.next().map(str::to_string)),
+                Some("annotate") => match words.next() {
+                    Some("on") => Command::AnnotateShots(true),
+                    Some("off") => Command::AnnotateShots(false),
+                    _ => return Err("expected `photo annotate on` or `photo annotate off`".into()),
+                },
+                Some("alpha") => match words.next() {
+                    Some("on") => Command::SkyAlpha(true),
+                    Some("off") => Command::SkyAlpha(false),
+                    _ => return Err("expected `photo alpha on` or `photo alpha off`".into()),
+                },
+                Some("load") => Command::LoadShot(
+                    words
+                        .next()
+                        .ok_or("expected `photo load <file>`")?
+                        .to_string(),
+                ),
+                _ => return Err(
+                    "expected `photo on`, `photo off`, `photo shot [file.ppm|file.exr|file.png]`, \
+                         `photo load <file>`, `photo annotate on|off` or `photo alpha on|off`"
+                        .into(),
+                ),
+            },
+            "roll" => {
+                let word = words.next().ok_or("expected `roll <degrees>`")?;
+                Command::Roll(
+                    word.parse()
+                        .ok()
+                        .filter(|degrees: &f32| degrees.is_finite())
+                        .ok_or(format!("invalid angle `{word}`"))?,
+                )
+            }
+            "dof" => match words.next() {
+                Some("off") => Command::DepthOfField(0.0, None),
+                Some(word) => {
+                    let number = |word: &str| word.parse::<f32>().ok().filter(|n| n.is_finite());
+                    let aperture = number(word)
+                        .filter(|&aperture| aperture >= 0.0)
+                        .ok_or(format!("invalid aperture `{word}`"))?;
+                    let distance = match words.next() {
+                        Some(word) => Some(
+                            number(word)
+                                .filter(|&distance| distance > 0.0)
+                                .ok_or(format!("invalid distance `{word}`"))?,
+                        ),
+                        None => None,
+                    };
+                    Command::DepthOfField(aperture, distance)
+                }
+                None => return Err("expected `dof <aperture> [distance]` or `dof off`".into()),
+            },
+            "gizmo" => match words.next() {
+                Some("on") => Command::AxisGizmo(true),
+                Some("off") => Command::AxisGizmo(false),
+                _ => return Err("expected `gizmo on` or `gizmo off`".into()),
+            },
+            "autosave" => match words.next() {
+                Some("off") => Command::Autosave(None),
+                Some("now") => Command::AutosaveNow,
+                Some(seconds) => Command::Autosave(Some(
+                    seconds
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|&seconds| seconds > 0.0)
+                        .ok_or(format!("invalid autosave interval `{seconds}`"))?,
+                )),
+                None => {
+                    return Err(
+                        "expected `autosave <seconds>`, `autosave off` or `autosave now`".into(),
+                    )
+                }
+            },
+            "camera" => match words.next() {
+                Some("free") => Command::FreeCamera(true),
+                Some("walk") => Command::FreeCamera(false),
+                Some("orbit") => match words.next() {
+                    Some(word) => Command::Orbit(Some(
+                        word.parse()
+                            .map_err(|err| format!("invalid agent `{word}`: {err}"))?,
+                    )),
+                    None => Command::Orbit(None),
+                },
+                _ => return Err("expected `camera free`, `camera walk` or `camera orbit`".into()),
+            },
+            "objects" => {
+                let usage = "expected `objects load <scene.vox>`, \
+                             `objects scatter <model.vox> <count>`, `objects clear`, \
+                             `objects list`, `objects distance <voxels>` or \
+                             `objects impostors <voxels>|off`";
+                match words.next() {
+                    Some("load") => Command::LoadObjects(words.next().ok_or(usage)?.to_string()),
+                    Some("scatter") => {
+                        let path = words.next().ok_or(usage)?.to_string();
+                        let word = words.next().ok_or(usage)?;
+                        Command::ScatterObjects(
+                            path,
+                            word.parse()
+                                .map_err(|err| format!("invalid count `{word}`: {err}"))?,
+                        )
+                    }
+                    Some("clear") => Command::ClearObjects,
+                    Some("list") => Command::ListObjects,
+                    Some("distance") => {
+                        let word = words.next().ok_or(usage)?;
+                        Command::ObjectDistance(
+                            word.parse()
+                                .map_err(|err| format!("invalid distance `{word}`: {err}"))?,
+                        )
+                    }
+                    Some("impostors") => match words.next().ok_or(usage)? {
+                        "off" => Command::ImpostorDistance(None),
+                        word => Command::ImpostorDistance(Some(
+                            word.parse()
+                                .ok()
+                                .filter(|&distance| distance > 0)
+                                .ok_or(format!("invalid distance `{word}`"))?,
+                        )),
+                    },
+                    _ => return Err(usage.into()),
+                }
+            }
+            "object" => {
+                let usage = "expected `object <index> move <x> <y> <z>`, \
+                             `object <index> turn <x> <y> <z>`, `object <index> scale <factor>` \
+                             or `object <index> layer <name>`";
+                let word = words.next().ok_or(usage)?;
+                let index = word
+                    .parse()
+                    .map_err(|err| format!("invalid object index `{word}`: {err}"))?;
+                let action = words.next();
+                let mut number = || -> Result<f32, String> {
+                    let word = words.next().ok_or(usage)?;
+                    word.parse::<f32>()
+                        .ok()
+                        .filter(|n| n.is_finite())
+                        .ok_or(format!("invalid number `{word}`"))
+                };
+                match action {
+                    Some("move") => Command::MoveObject(index, [number()?, number()?, number()?]),
+                    Some("turn") => Command::TurnObject(index, [number()?, number()?, number()?]),
+                    Some("scale") => {
+                        let factor = number()?;
+                        if factor <= 0.0 {
+                            return Err(format!("invalid scale `{factor}`"));
+                        }
+                        Command::ScaleObject(index, factor)
+                    }
+                    Some("layer") => Command::ObjectLayer(index, words.next().ok_or(usage)?.into()),
+                    _ => return Err(usage.into()),
+                }
+            }
+            "anim" => match words.next() {
+                Some("key") => {
+                    let word = words
+                        .next()
+                        .ok_or("expected `anim key <object> [travel] [hold] [easing]`")?;
+                    let object = word
+                        .parse()
+                        .map_err(|err| format!("invalid object index `{word}`: {err}"))?;
+                    let mut seconds = |default: f32| -> Result<f32, String> {
+                        words.next().map_or(Ok(default), |word| {
+                            word.parse()
+                                .ok()
+                                .filter(|seconds: &f32| *seconds >= 0.0)
+                                .ok_or(format!("invalid seconds `{word}`"))
+                        })
+                    };
+                    let travel = seconds(2.0)?;
+                    let hold = seconds(0.0)?;
+                    let easing = match words.next() {
+                        Some(name) => Easing::from_name(name).ok_or(format!(
+                            "unknown easing `{name}`, expected one of {}",
+                            Easing::ALL.map(Easing::name).join(", ")
+                        ))?,
+                        None => Easing::InOut,
+                    };
+                    Command::AnimKey {
+                        object,
+                        travel,
+                        hold,
+                        easing,
+                    }
+                }
+                Some("loop") => {
+                    let usage = "expected `anim loop <object> on` or `anim loop <object> off`";
+                    let word = words.next().ok_or(usage)?;
+                    let object = word
+                        .parse()
+                        .map_err(|err| format!("invalid object index `{word}`: {err}"))?;
+                    match words.next() {
+                        Some("on") => Command::AnimLoop(object, true),
+                        Some("off") => Command::AnimLoop(object, false),
+                        _ => return Err(usage.into()),
+                    }
+                }
+                Some("play") => Command::AnimPlay,
+                Some("stop") => Command::AnimStop,
+                Some("list") => Command::AnimList,
+                Some("clear") => Command::AnimClear,
+                _ => {
+                    return Err(
+                        "expected `anim key`, `anim loop`, `play`, `stop`, `list` or `clear`"
+                            .into(),
+                    )
+                }
+            },
+            "decal" => {
+                let usage =
+                    "expected `decal <radius> [opacity]`, `decal path <radius> [opacity]`, \
+                             `decal remove <index>`, `decal clear` or `decal list`";
+                match words.next().ok_or(usage)? {
+                    "remove" => {
+                        let word = words.next().ok_or(usage)?;
+                        Command::RemoveDecal(
+                            word.parse()
+                                .map_err(|err| format!("invalid decal index `{word}`: {err}"))?,
+                        )
+                    }
+                    "clear" => Command::ClearDecals,
+                    "list" => Command::ListDecals,
+                    first => {
+                        let path = first == "path";
+                        let word = if path {
+                            words.next().ok_or(usage)?
+                        } else {
+                            first
+                        };
+                        let radius = word
+                            .parse::<f32>()
+                            .ok()
+                            .filter(|radius| radius.is_finite() && *radius > 0.0)
+                            .ok_or(format!("invalid radius `{word}`"))?;
+                        let opacity = match words.next() {
+                            Some(word) => word
+                                .parse()
+                                .ok()
+                                .filter(|opacity| (0.0..=1.0).contains(opacity))
+                                .ok_or(format!("invalid opacity `{word}`, expected 0 to 1"))?,
+                            None => 1.0,
+                        };
+                        if path {
+                            Command::DecalPath(radius, opacity)
+                        } else {
+                            Command::AddDecal(radius, opacity)
+                        }
+                    }
+                }
+            }
+            "layer" => {
+                let usage = "expected `layer list`, `layer add <name>`, `layer hide <name>`, \
+                             `layer show <name>|all` or `layer assign <name>`";
+                let action = words.next();
+                let mut name =
+                    || -> Result<String, String> { Ok(words.next().ok_or(usage)?.to_string()) };
+                match action {
+                    Some("list") => Command::ListLayers,
+                    Some("add") => Command::AddLayer(name()?),
+                    Some("hide") => Command::HideLayer(name()?),
+                    Some("show") => match name()?.as_str() {
+                        "all" => Command::ShowLayer(None),
+                        layer => Command::ShowLayer(Some(layer.to_string())),
+                    },
+                    Some("assign") => Command::AssignLayer(name()?),
+                    _ => return Err(usage.into()),
+                }
+            }
+            "clip" => {
+                let usage = "expected `clip <x> <y> <z>`, `clip remove <index>`, `clip clear`, \
+                             `clip list` or `clip cap <r> <g> <b>|off`";
+                let first = words.next().ok_or(usage)?;
+                let number = |word: Option<&str>| -> Result<f32, String> {
+                    let word = word.ok_or(usage)?;
+                    word.parse::<f32>()
+                        .ok()
+                        .filter(|n| n.is_finite())
+                        .ok_or(format!("invalid number `{word}`"))
+                };
+                match first {
+                    "remove" => {
+                        let word = words.next().ok_or(usage)?;
+                        Command::RemoveClipPlane(
+                            word.parse()
+                                .map_err(|err| format!("invalid plane index `{word}`: {err}"))?,
+                        )
+                    }
+                    "clear" => Command::ClearClipPlanes,
+                    "list" => Command::ListClipPlanes,
+                    "cap" => match words.next().ok_or(usage)? {
+                        "off" => Command::ClipCap(None),
+                        r => Command::ClipCap(Some([
+                            number(Some(r))?,
+                            number(words.next())?,
+                            number(words.next())?,
+                        ])),
+                    },
+                    x => Command::AddClipPlane([
+                        number(Some(x))?,
+                        number(words.next())?,
+                        number(words.next())?,
+                    ]),
+                }
+            }
+            "world" => match words.next() {
+                Some("stats") => Command::WorldStats,
+                _ => return Err("expected `world stats`".to_string()),
+            },
+            _ => return Err(format!("unknown command `{name}`\n{HELP}")),
+        };
+        match words.next() {
+            Some(word) => Err(format!("unexpected argument `{word}`")),
+            None => Ok(command),
+        }
+    }
+}
+
+/// Returns the hour of the day a time like `13:30` stands for.
+fn parse_clock(time: &str) -> Option<f32> {
+    let (hours, minutes) = time.split_once(':')?;
+    let hours = hours.parse::<u32>().ok().filter(|&hours| hours < 24)?;
+    let minutes = minutes
+        .parse::<u32>()
+        .ok()
+        .filter(|&minutes| minutes < 60)?;
+    Some(hours as f32 + minutes as f32 / 60.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_selection_commands() {
+        let err = |err: &str| Err(err.to_string());
+        let cases = [
+            ("fill 3", Ok(Command::Fill(3))),
+            ("fill  0 ", Ok(Command::Fill(0))),
+            ("fill", err("`fill` needs a voxel type")),
+            ("fill 32", err("invalid voxel type `32`")),
+            ("fill -1", err("invalid voxel type `-1`")),
+            ("fill 3 4", err("unexpected argument `4`")),
+            ("replace 1 2", Ok(Command::Replace(1, 2))),
+            ("replace 1", err("`replace` needs a voxel type")),
+            ("replace 40 2", err("invalid voxel type `40`")),
+            ("replace 1 stone", err("invalid voxel type `stone`")),
+            ("hollow", Ok(Command::Hollow)),
+            ("hollow 2", err("unexpected argument `2`")),
+            ("walls", Ok(Command::Walls(None))),
+            ("walls 31", Ok(Command::Walls(Some(31)))),
+            ("walls 32", err("invalid voxel type `32`")),
+        ];
+        for (line, command) in cases {
+            assert_eq!(Command::parse(line), command, "`{line}`");
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use crate::gpu;
+use std::{iter, sync::Arc};
+use vulkano::{
+    device::{Device, DeviceCreateInfo, Queue, QueueCreateInfo, QueueFlags},
+    instance::{Instance, InstanceCreateInfo},
+    VulkanLibrary,
+};
+
+/// The Vulkan instance and the device tracing and presenting run on, created in place of
+/// `vulkano_util`'s `VulkanoContext`, which only asks for graphics and compute queues.
+pub struct Context {
+    instance: Arc<Instance>,
+    device: Arc<Device>,
+    graphics_queue: Arc<Queue>,
+    transfer_queue: Option<Arc<Queue>>,
+}
+
+impl Context {
+    /// Creates a device on the GPU `gpu::priority` ranks first among the ones able to present,
+    /// with the optional features it supports, see `gpu::optional_features`. Besides the
+    /// graphics queue it gets a queue of a family which can do nothing but copy when the GPU
+    /// has one, served by its copy engines alongside the frame's work.
+    pub fn new(selector: Option<&str>) -> Result<Context, String> {
+        let library = VulkanLibrary::new().map_err(|err| format!("can't load Vulkan: {err}"))?;
+        let instance = Instance::new(
+            library.clone(),
+            InstanceCreateInfo {
+                enabled_extensions: vulkano_win::required_extensions(&library),
+                enumerate_portability: true,
+                ..Default::default()
+            },
+        )
+        .map_err(|err| format!("can't create a Vulkan instance: {err}"))?;
+        let physical_device = instance
+            .enumerate_physical_devices()
+            .map_err(|err| format!("can't list GPUs: {err}"))?
+            .filter(|device| device.supported_extensions().khr_swapchain)
+            .min_by_key(|device| gpu::priority(device, selector))
+            .ok_or("no GPU can present")?;
+        let families = physical_device.queue_family_properties();
+        let graphics_family = families
+            .iter()
+            .position(|family| family.queue_flags.intersects(QueueFlags::GRAPHICS))
+            .ok_or("no queue of the GPU can draw")? as u32;
+        let transfer_family = families
+            .iter()
+            .position(|family| {
+                family.queue_flags.intersects(QueueFlags::TRANSFER)
+                    && !family
+                        .queue_flags
+                        .intersects(QueueFlags::GRAPHICS | QueueFlags::COMPUTE)
+            })
+            .map(|family| family as u32);
+        let (device, mut queues) = Device::new(
+            physical_device.clone(),
+            DeviceCreateInfo {
+                enabled_extensions: gpu::enabled_extensions(&physical_device),
+                enabled_features: gpu::optional_features(&physical_device),
+                queue_create_infos: iter::once(graphics_family)
+                    .chain(transfer_family)
+                    .map(|queue_family_index| QueueCreateInfo {
+                        queue_family_index,
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+        )
+        .map_err(|err| format!("can't create a Vulkan device: {err}"))?;
+        let graphics_queue = queues.next().unwrap();
+        let transfer_queue = queues.next();
+        Ok(Context {
+            instance,
+            device,
+            graphics_queue,
+            transfer_queue,
+        })
+    }
+
+    pub fn instance(&self) -> &Arc<Instance> {
+        &self.instance
+    }
+
+    pub fn device(&self) -> &Arc<Device> {
+        &self.device
+    }
+
+    /// Returns the queue tracing, drawing and presenting run on.
+    pub fn graphics_queue(&self) -> &Arc<Queue> {
+        &self.graphics_queue
+    }
+
+    /// Returns the queue of a family which can only copy, when the GPU has one.
+    pub fn transfer_queue(&self) -> Option<&Arc<Queue>> {
+        self.transfer_queue.as_ref()
+    }
+}
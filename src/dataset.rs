@@ -0,0 +1,161 @@
+use crate::{
+    camera::{camera_to_world, world_to_camera, Camera, CAMERA_DIR},
+    world::{World, WORLD_SIZE},
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{f32::consts::PI, fmt::Write};
+
+/// Seed poses are sampled with unless another is given.
+pub const DEFAULT_SEED: u64 = 0;
+/// Empty voxels kept around the eye of sampled poses unless another clearance is given.
+pub const DEFAULT_CLEARANCE: u32 = 2;
+/// Pitch in radians sampled poses look up or down by at most, so views mostly run along the
+/// ground like those taken by hand.
+const MAX_POSE_PITCH: f32 = 0.75;
+/// Positions `PoseSampler::sample` tries before giving up on a world without enough free space.
+const MAX_POSE_ATTEMPTS: u32 = 10_000;
+
+/// Samples random camera poses in the free space of a world, so frames aren't rendered from
+/// inside walls. The same seed samples the same poses in the same world.
+pub struct PoseSampler {
+    rng: ChaCha8Rng,
+    /// Empty voxels every sampled eye keeps to the nearest solid one along each axis.
+    clearance: u32,
+}
+
+impl PoseSampler {
+    pub fn new(seed: u64, clearance: u32) -> PoseSampler {
+        PoseSampler {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            clearance,
+        }
+    }
+
+    /// Returns a camera turned a random way with its eye in an empty voxel of `world` whose
+    /// neighbors within the clearance are empty too, the cube around it checked with
+    /// `World::voxel`.
+    pub fn sample(&mut self, world: &World) -> Result<Camera, String> {
+        let clearance = self.clearance as i32;
+        let range = clearance as f32..(WORLD_SIZE as i32 - clearance) as f32;
+        if range.is_empty() {
+            return Err(format!(
+                "a clearance of {clearance} voxels doesn't fit in the world"
+            ));
+        }
+        for _ in 0..MAX_POSE_ATTEMPTS {
+            let eye = [(); 3].map(|_| self.rng.gen_range(range.clone()));
+            let cell = eye.map(|c| c.floor() as i32);
+            let blocked = (-clearance..=clearance).any(|x| {
+                (-clearance..=clearance).any(|y| {
+                    (-clearance..=clearance)
+                        .any(|z| world.voxel([cell[0] + x, cell[1] + y, cell[2] + z]) != 0)
+                })
+            });
+            if blocked {
+                continue;
+            }
+            let rotation = [
+                self.rng.gen_range(-MAX_POSE_PITCH..MAX_POSE_PITCH),
+                self.rng.gen_range(-PI..PI),
+                0.0,
+            ];
+            return Ok(Camera {
+                position: world_to_camera(eye, rotation),
+                rotation,
+            });
+        }
+        Err(format!(
+            "found no empty voxel with {clearance} voxels of clearance in {MAX_POSE_ATTEMPTS} tries"
+        ))
+    }
+}
+
+/// Pinhole intrinsics of an image the tracer renders, in pixels with the center of the top left
+/// pixel at 0, 0. Camera space has x to the right of the image, y down it and z along the view,
+/// so a point `p` in it shows at `fx * p.x / p.z + cx`, `fy * p.y / p.z + cy`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Intrinsics {
+    pub fx: f32,
+    pub fy: f32,
+    pub cx: f32,
+    pub cy: f32,
+}
+
+impl Intrinsics {
+    /// Returns the intrinsics of images of `extent` pixels, see `Camera::ray`.
+    pub fn new(extent: [u32; 2]) -> Intrinsics {
+        // The image plane reaches from -1 to 1 across the width and keeps pixels square.
+        let focal = CAMERA_DIR[2] * extent[0] as f32 / 2.0;
+        Intrinsics {
+            fx: focal,
+            fy: focal,
+            cx: extent[0] as f32 / 2.0 - 0.5,
+            cy: extent[1] as f32 / 2.0 - 0.5,
+        }
+    }
+}
+
+/// A frame of a dataset: the files rendered from `camera`, named `name` followed by the
+/// extension of each.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DatasetFrame {
+    pub name: String,
+    pub camera: Camera,
+}
+
+/// Returns the JSON describing a dataset of `frames` rendered at `extent`: the intrinsics they
+/// share and, for every frame, its files and its camera to world transform as a row major 4x4
+/// matrix in the camera space of `Intrinsics`. `image` names the extension of the colors and
+/// `passes` the render passes, each in an OpenEXR file named after the frame and the pass.
+pub fn cameras_json(
+    extent: [u32; 2],
+    image: &str,
+    passes: &[&str],
+    frames: &[DatasetFrame],
+) -> String {
+    let Intrinsics { fx, fy, cx, cy } = Intrinsics::new(extent);
+    let mut json = String::from("{\n");
+    writeln!(json, "  \"width\": {},", extent[0]).unwrap();
+    writeln!(json, "  \"height\": {},", extent[1]).unwrap();
+    writeln!(
+        json,
+        "  \"intrinsics\": {{ \"fx\": {fx}, \"fy\": {fy}, \"cx\": {cx}, \"cy\": {cy} }},"
+    )
+    .unwrap();
+    writeln!(json, "  \"camera_axes\": \"x right, y down, z forward\",").unwrap();
+    writeln!(
+        json,
+        "  \"depth\": \"distance from the eye along the ray, infinite for sky\","
+    )
+    .unwrap();
+    writeln!(json, "  \"frames\": [").unwrap();
+    for (index, frame) in frames.iter().enumerate() {
+        let name = &frame.name;
+        writeln!(json, "    {{").unwrap();
+        writeln!(json, "      \"image\": \"{name}.{image}\",").unwrap();
+        for pass in passes {
+            writeln!(json, "      \"{pass}\": \"{name}.{pass}.exr\",").unwrap();
+        }
+        let axes = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]
+            .map(|axis| camera_to_world(axis, frame.camera.rotation));
+        let eye = frame.camera.eye();
+        let rows = [0, 1, 2].map(|i| {
+            format!(
+                "[{}, {}, {}, {}]",
+                axes[0][i], axes[1][i], axes[2][i], eye[i]
+            )
+        });
+        writeln!(json, "      \"camera_to_world\": [").unwrap();
+        for row in rows {
+            writeln!(json, "        {row},").unwrap();
+        }
+        writeln!(json, "        [0, 0, 0, 1]").unwrap();
+        writeln!(json, "      ]").unwrap();
+        let separator = if index + 1 < frames.len() { "," } else { "" };
+        writeln!(json, "    }}{separator}").unwrap();
+    }
+    writeln!(json, "  ]").unwrap();
+    json.push_str("}\n");
+    json
+}
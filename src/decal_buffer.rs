@@ -0,0 +1,99 @@
+use rvengine::decals::{Decals, MAX_DECALS};
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+};
+
+/// Layout of `Decal` in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuDecal {
+    /// Center in xyz, w is the radius.
+    center: [f32; 4],
+    /// Color in rgb, a is the opacity.
+    color: [f32; 4],
+}
+
+/// Layout of the `Decals` buffer in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct GpuDecals {
+    count: u32,
+    _padding: [u32; 3],
+    decals: [GpuDecal; MAX_DECALS],
+}
+
+/// The decals painted over the world with their GPU copy, updated with the frame after they
+/// were edited.
+pub struct DecalBuffer {
+    decals: Decals,
+    /// Whether `decals` changed since the last upload.
+    dirty: bool,
+    buffer: Subbuffer<GpuDecals>,
+}
+
+impl DecalBuffer {
+    pub fn new(memory_allocator: &StandardMemoryAllocator) -> DecalBuffer {
+        let buffer = Buffer::new_sized(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        DecalBuffer {
+            decals: Decals::new(),
+            dirty: true,
+            buffer,
+        }
+    }
+
+    pub fn decals(&self) -> &Decals {
+        &self.decals
+    }
+
+    /// Returns the decals for editing. Edits reach the GPU with the next frame.
+    pub fn decals_mut(&mut self) -> &mut Decals {
+        self.dirty = true;
+        &mut self.decals
+    }
+
+    pub fn buffer(&self) -> &Subbuffer<GpuDecals> {
+        &self.buffer
+    }
+
+    /// Records copying the decals to the GPU when they changed, returning whether they did.
+    pub fn record_upload(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        let mut decals = GpuDecals {
+            count: self.decals.decals().len() as u32,
+            _padding: [0; 3],
+            decals: [GpuDecal {
+                center: [0.0; 4],
+                color: [0.0; 4],
+            }; MAX_DECALS],
+        };
+        for (gpu, decal) in decals.decals.iter_mut().zip(self.decals.decals()) {
+            let [x, y, z] = decal.center;
+            let [r, g, b] = decal.color;
+            gpu.center = [x, y, z, decal.radius];
+            gpu.color = [r, g, b, decal.opacity];
+        }
+        builder
+            .update_buffer(self.buffer.clone(), Box::new(decals))
+            .unwrap();
+        self.dirty = false;
+        true
+    }
+}
@@ -0,0 +1,94 @@
+use crate::world::{World, WORLD_SIZE};
+
+/// Decals the renderer keeps room for.
+pub const MAX_DECALS: usize = 256;
+
+/// A mark like a scorch mark or a worn path, blended over the color of every voxel face within
+/// `radius` of `center` when it is shaded, so surfaces are marked without changing their voxels.
+/// The mark fades out smoothly over the outer half of the radius, see `applyDecals` in
+/// `compute.glsl`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Decal {
+    pub center: [f32; 3],
+    pub radius: f32,
+    pub color: [f32; 3],
+    /// How much of the surface's color the mark covers at its center, from 0 to 1.
+    pub opacity: f32,
+}
+
+/// The decals drawn over the world, later ones over earlier ones.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Decals {
+    decals: Vec<Decal>,
+}
+
+impl Decals {
+    pub fn new() -> Decals {
+        Decals::default()
+    }
+
+    pub fn decals(&self) -> &[Decal] {
+        &self.decals
+    }
+
+    /// Adds `decal` over the others, returning its index.
+    pub fn add(&mut self, decal: Decal) -> Result<usize, String> {
+        if self.decals.len() >= MAX_DECALS {
+            return Err(format!("there can't be more than {MAX_DECALS} decals"));
+        }
+        self.decals.push(decal);
+        Ok(self.decals.len() - 1)
+    }
+
+    /// Adds copies of `decal` every half its radius along the line from `from` to `to`, each
+    /// dropped onto the topmost ground of `world` in its column, returning how many were added.
+    /// Columns without ground are left out.
+    pub fn add_path(
+        &mut self,
+        from: [f32; 3],
+        to: [f32; 3],
+        decal: Decal,
+        world: &World,
+    ) -> Result<usize, String> {
+        let length = (0..3)
+            .map(|i| (to[i] - from[i]).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        let steps = (length / (decal.radius * 0.5).max(0.5)).ceil() as usize;
+        let mut added = 0;
+        for step in 0..=steps {
+            let t = step as f32 / steps.max(1) as f32;
+            let point = [0, 1, 2].map(|i| from[i] + (to[i] - from[i]) * t);
+            let top = [point[0], WORLD_SIZE as f32 - 0.5, point[2]];
+            let Some(ground) = world.raycast(top, [0.0, -1.0, 0.0], WORLD_SIZE as f32) else {
+                continue;
+            };
+            self.add(Decal {
+                center: [point[0], (ground.position[1] + 1) as f32, point[2]],
+                ..decal
+            })?;
+            added += 1;
+        }
+        Ok(added)
+    }
+
+    /// Removes the decal at `index`, moving the ones after it down.
+    pub fn remove(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.decals.len() {
+            return Err(format!("there is no decal {index}"));
+        }
+        self.decals.remove(index);
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.decals.clear();
+    }
+
+    /// Moves every decal by `offset`, after the world moved under them.
+    pub fn shift(&mut self, offset: [f32; 3]) {
+        for decal in &mut self.decals {
+            decal.center = [0, 1, 2].map(|i| decal.center[i] + offset[i]);
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    layers::BUILDINGS,
+    materials::{Material, Palette},
+    world::{World, WORLD_SIZE},
+};
+
+/// Names of the scenes `load` builds.
+pub const DEMOS: [&str; 1] = ["mirrors"];
+
+/// Voxel types the demo scenes give materials of their own.
+pub const MIRROR: u32 = 7;
+pub const LIGHT: u32 = 8;
+/// The renderer doesn't refract, so glass is a tinted and nearly smooth reflector.
+pub const GLASS: u32 = 9;
+
+/// Where a demo scene wants the camera. It looks along +z from `eye`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Demo {
+    pub eye: [f32; 3],
+}
+
+/// Replaces the world with the scene called `name`, one of `DEMOS`, and sets the materials of
+/// the voxel types it uses in `palette`.
+pub fn load(name: &str, world: &mut World, palette: &mut Palette) -> Result<Demo, String> {
+    match name {
+        "mirrors" => Ok(mirror_room(world, palette)),
+        _ => Err(format!(
+            "unknown demo `{name}`, expected one of {}",
+            DEMOS.join(", ")
+        )),
+    }
+}
+
+/// A closed room of mirrors lit by glowing pillars, with glass and a mirror sphere in the
+/// middle. Rays bounce between the walls until the bounce budget runs out, so every bounce
+/// added shows another reflection of the room.
+fn mirror_room(world: &mut World, palette: &mut Palette) -> Demo {
+    palette.materials[MIRROR as usize] = Material {
+        color: [0.9, 0.92, 0.95],
+        emissive: 0.0,
+        roughness: 0.0,
+        transparency: 0.0,
+        metalness: 1.0,
+        detail_normals: false,
+        layer: BUILDINGS,
+    };
+    palette.materials[LIGHT as usize] = Material {
+        color: [1.0, 0.8, 0.55],
+        emissive: 3.0,
+        roughness: 1.0,
+        transparency: 0.0,
+        metalness: 0.0,
+        detail_normals: false,
+        layer: BUILDINGS,
+    };
+    palette.materials[GLASS as usize] = Material {
+        color: [0.6, 0.85, 0.9],
+        emissive: 0.0,
+        roughness: 0.05,
+        transparency: 0.9,
+        metalness: 0.0,
+        detail_normals: false,
+        layer: BUILDINGS,
+    };
+
+    world.fill([0; 3], [WORLD_SIZE; 3], 0);
+    let center = WORLD_SIZE / 2;
+    let min = [center - 24, center - 12, center - 24];
+    let max = [center + 24, center + 12, center + 24];
+    world.fill(min, max, MIRROR);
+    world.hollow(min, max);
+
+    // Checkered floor, so reflections of reflections are easy to tell apart.
+    const TILE: u32 = 4;
+    for x in (min[0] + 1..max[0] - 1).step_by(TILE as usize) {
+        for z in (min[2] + 1..max[2] - 1).step_by(TILE as usize) {
+            let voxel = if (x / TILE + z / TILE) % 2 == 1 { 3 } else { 1 };
+            world.fill(
+                [x, min[1], z],
+                [
+                    (x + TILE).min(max[0] - 1),
+                    min[1] + 1,
+                    (z + TILE).min(max[2] - 1),
+                ],
+                voxel,
+            );
+        }
+    }
+
+    // Glowing pillars in the corners, from floor to ceiling.
+    for x in [min[0] + 3, max[0] - 5] {
+        for z in [min[2] + 3, max[2] - 5] {
+            world.fill([x, min[1] + 1, z], [x + 2, max[1] - 1, z + 2], LIGHT);
+        }
+    }
+
+    // A glass block with a mirror sphere floating above it.
+    let floor = min[1] + 1;
+    world.fill(
+        [center - 4, floor, center - 4],
+        [center + 4, floor + 8, center + 4],
+        GLASS,
+    );
+    const RADIUS: i32 = 4;
+    let sphere_center = [center as i32, (floor + 14) as i32, center as i32];
+    for x in -RADIUS..=RADIUS {
+        for y in -RADIUS..=RADIUS {
+            for z in -RADIUS..=RADIUS {
+                if x * x + y * y + z * z <= RADIUS * RADIUS {
+                    let offset = [x, y, z];
+                    world.set_voxel(
+                        [0, 1, 2].map(|i| (sphere_center[i] + offset[i]) as u32),
+                        MIRROR,
+                    );
+                }
+            }
+        }
+    }
+
+    Demo {
+        eye: [center as f32 + 0.5, floor as f32 + 8.0, min[2] as f32 + 4.0],
+    }
+}
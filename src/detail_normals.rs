@@ -0,0 +1,104 @@
+use std::{fs, path::Path};
+
+/// Texel of a normal map pointing straight out of the face, RGBA8 packed like `texels`.
+const FLAT_TEXEL: u32 = 128 | 128 << 8 | 255 << 16 | 255 << 24;
+
+/// Tangent space normal map tiled over the faces of voxels whose material has
+/// `Material::detail_normals` set, so large flat surfaces catch the sun's highlight unevenly.
+/// Faces are mapped by projecting the hit point onto the plane of the face, the only one of the
+/// three triplanar projections a voxel face sees.
+///
+/// Texels are RGBA8 packed into a word each, red in the lowest byte, rows from top to bottom.
+/// Red and green hold the normal's x and y along the face, blue its z out of it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DetailNormals {
+    size: [u32; 2],
+    texels: Vec<u32>,
+}
+
+impl Default for DetailNormals {
+    /// A single flat texel, which leaves faces as they are.
+    fn default() -> Self {
+        DetailNormals {
+            size: [1, 1],
+            texels: vec![FLAT_TEXEL],
+        }
+    }
+}
+
+impl DetailNormals {
+    /// Reads a normal map from a binary PPM (`P6`) file.
+    pub fn load(path: impl AsRef<Path>) -> Result<DetailNormals, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .map_err(|err| format!("can't read normal map `{}`: {err}", path.display()))?;
+        parse_ppm(&bytes).map_err(|err| format!("normal map `{}`: {err}", path.display()))
+    }
+
+    /// Returns the width and height in texels.
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn texels(&self) -> &[u32] {
+        &self.texels
+    }
+}
+
+/// Parses a binary PPM image with at most 8 bits per channel.
+fn parse_ppm(bytes: &[u8]) -> Result<DetailNormals, String> {
+    let mut position = 0;
+    // The magic number and the three header values, separated by whitespace and comments.
+    let mut fields = Vec::new();
+    while fields.len() < 4 {
+        while position < bytes.len() {
+            match bytes[position] {
+                b'#' => {
+                    while position < bytes.len() && bytes[position] != b'\n' {
+                        position += 1;
+                    }
+                }
+                byte if byte.is_ascii_whitespace() => position += 1,
+                _ => break,
+            }
+        }
+        let start = position;
+        while position < bytes.len() && !bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        if start == position {
+            return Err("truncated header".to_string());
+        }
+        fields.push(String::from_utf8_lossy(&bytes[start..position]).into_owned());
+    }
+    if fields[0] != "P6" {
+        return Err(format!("expected a binary PPM (P6), found `{}`", fields[0]));
+    }
+    let [width, height, max] = [1, 2, 3].map(|i| fields[i].parse::<u32>().unwrap_or(0));
+    if width == 0 || height == 0 {
+        return Err(format!("invalid size {} by {}", fields[1], fields[2]));
+    }
+    if max == 0 || max > 255 {
+        return Err(format!("unsupported maximum value {}", fields[3]));
+    }
+    // A single whitespace byte separates the header from the pixels.
+    let pixels = bytes.get(position + 1..).unwrap_or_default();
+    let len = width as usize * height as usize * 3;
+    if pixels.len() < len {
+        return Err(format!(
+            "expected {len} bytes of pixels, found {}",
+            pixels.len()
+        ));
+    }
+    let texels = pixels[..len]
+        .chunks(3)
+        .map(|rgb| {
+            let [r, g, b] = [0, 1, 2].map(|i| rgb[i] as u32 * 255 / max);
+            r | g << 8 | b << 16 | 255 << 24
+        })
+        .collect();
+    Ok(DetailNormals {
+        size: [width, height],
+        texels,
+    })
+}
@@ -0,0 +1,150 @@
+use crate::world::{CHUNK_SIZE, WORLD_SIZE};
+use std::collections::BTreeSet;
+
+/// Chunks along every axis of the world.
+pub const CHUNKS: u32 = WORLD_SIZE / CHUNK_SIZE;
+/// Distances are capped at this many chunks, the farthest a ray skips at once.
+pub const MAX_CHUNK_DISTANCE: u32 = 8;
+
+/// Chunk occupancy skip: the distance from every chunk to the nearest chunk holding voxels, in
+/// chunks along the axis farthest apart (0 for chunks holding voxels themselves, 1 next to
+/// them). A ray inside a chunk at distance `d` can skip straight to where it leaves the box of
+/// `2 * d - 1` chunks around it, which is empty, instead of walking every voxel in between.
+/// It knows nothing finer than whole chunks, rays walk voxel by voxel again in chunks holding
+/// any, so it isn't a signed distance field of the voxels.
+///
+/// Edits only mark their chunks, `update` looks at the voxels of the marked chunks again and
+/// recomputes the distances near the chunks which became empty or stopped being so.
+pub struct ChunkDistances {
+    /// Whether each chunk holds voxels, indexed like `distances`.
+    occupied: Vec<bool>,
+    /// Indexed by `(x * CHUNKS + y) * CHUNKS + z`.
+    distances: Vec<u32>,
+    /// Chunks edited since the last update.
+    edited: BTreeSet<[u32; 3]>,
+    /// Smallest and largest corner of the box of chunks which became empty or stopped being so
+    /// since the last update, `None` if none did.
+    changed: Option<([u32; 3], [u32; 3])>,
+}
+
+impl ChunkDistances {
+    /// The distances of an empty world.
+    pub fn new() -> ChunkDistances {
+        let len = (CHUNKS * CHUNKS * CHUNKS) as usize;
+        ChunkDistances {
+            occupied: vec![false; len],
+            distances: vec![MAX_CHUNK_DISTANCE; len],
+            edited: BTreeSet::new(),
+            changed: Some(([0; 3], [CHUNKS - 1; 3])),
+        }
+    }
+
+    fn index(chunk: [u32; 3]) -> usize {
+        ((chunk[0] * CHUNKS + chunk[1]) * CHUNKS + chunk[2]) as usize
+    }
+
+    /// Returns the distances of all chunks, indexed by `(x * CHUNKS + y) * CHUNKS + z`.
+    pub fn distances(&self) -> &[u32] {
+        &self.distances
+    }
+
+    /// Returns the distance of `chunk` to the nearest chunk holding voxels.
+    pub fn distance(&self, chunk: [u32; 3]) -> u32 {
+        self.distances[ChunkDistances::index(chunk)]
+    }
+
+    /// Marks `chunk` to be looked at again by the next update.
+    pub fn mark_edited(&mut self, chunk: [u32; 3]) {
+        self.edited.insert(chunk);
+    }
+
+    /// Records whether `chunk` holds voxels, for callers which already know.
+    pub fn set_occupied(&mut self, chunk: [u32; 3], occupied: bool) {
+        let index = ChunkDistances::index(chunk);
+        if self.occupied[index] != occupied {
+            self.changed = Some(match self.changed {
+                Some((min, max)) => (
+                    [0, 1, 2].map(|i| min[i].min(chunk[i])),
+                    [0, 1, 2].map(|i| max[i].max(chunk[i])),
+                ),
+                None => (chunk, chunk),
+            });
+        }
+        self.occupied[index] = occupied;
+        self.edited.remove(&chunk);
+    }
+
+    /// Asks `is_occupied` about the chunks edited since the last update and recomputes the
+    /// distances within `MAX_CHUNK_DISTANCE` of any which changed, the only ones it can change.
+    /// Returns whether any distance was recomputed.
+    pub fn update(&mut self, is_occupied: impl Fn([u32; 3]) -> bool) -> bool {
+        for chunk in std::mem::take(&mut self.edited) {
+            self.set_occupied(chunk, is_occupied(chunk));
+        }
+        let Some(changed) = self.changed.take() else {
+            return false;
+        };
+        // Chunks `MAX_CHUNK_DISTANCE` or more apart don't change each other's distance, which
+        // is capped there. The distances near the changed chunks depend on the chunks near them
+        // in turn, so those are looked at too.
+        let reach = MAX_CHUNK_DISTANCE - 1;
+        let grow = |(min, max): ([u32; 3], [u32; 3])| {
+            (
+                min.map(|c| c.saturating_sub(reach)),
+                max.map(|c| (c + reach).min(CHUNKS - 1)),
+            )
+        };
+        let affected = grow(changed);
+        let (min, max) = grow(affected);
+        let size = [0, 1, 2].map(|i| (max[i] - min[i] + 1) as usize);
+        let local_index = |chunk: [u32; 3]| {
+            let [x, y, z] = [0, 1, 2].map(|i| (chunk[i] - min[i]) as usize);
+            (x * size[1] + y) * size[2] + z
+        };
+
+        let mut distances = vec![MAX_CHUNK_DISTANCE; size.iter().product()];
+        for_each_chunk(min, max, |chunk| {
+            if self.occupied[ChunkDistances::index(chunk)] {
+                distances[local_index(chunk)] = 0;
+            }
+        });
+        // The distance along the axis farthest apart is the smallest of the largest distances
+        // along each axis, so it is found one axis after the other.
+        for (axis, stride) in [(0, size[1] * size[2]), (1, size[2]), (2, 1)] {
+            let previous = distances.clone();
+            for (index, distance) in distances.iter_mut().enumerate() {
+                let along = index / stride % size[axis];
+                let row_start = index - along * stride;
+                for other in 0..size[axis] {
+                    let apart = along.abs_diff(other) as u32;
+                    if apart < *distance {
+                        *distance =
+                            (*distance).min(apart.max(previous[row_start + other * stride]));
+                    }
+                }
+            }
+        }
+        let (affected_min, affected_max) = affected;
+        for_each_chunk(affected_min, affected_max, |chunk| {
+            self.distances[ChunkDistances::index(chunk)] = distances[local_index(chunk)];
+        });
+        true
+    }
+}
+
+/// Calls `visit` with every chunk from `min` to `max`, both inclusive.
+fn for_each_chunk(min: [u32; 3], max: [u32; 3], mut visit: impl FnMut([u32; 3])) {
+    for x in min[0]..=max[0] {
+        for y in min[1]..=max[1] {
+            for z in min[2]..=max[2] {
+                visit([x, y, z]);
+            }
+        }
+    }
+}
+
+impl Default for ChunkDistances {
+    fn default() -> Self {
+        ChunkDistances::new()
+    }
+}
@@ -0,0 +1,27 @@
+/// Entities the renderer keeps room for, the rest aren't drawn.
+pub const MAX_ENTITIES: usize = 64;
+
+/// A box moving freely through the world, drawn by the tracer on top of the voxel grid. Unlike
+/// voxels its corners lie anywhere, not only on the grid. It is lit like a voxel of type `voxel`
+/// and takes part in the lighting like one: it casts shadows onto the world and the world's fall
+/// onto it, it darkens the ambient occlusion of the voxels next to it and light bounces off it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EntityBox {
+    /// Lower corner.
+    pub min: [f32; 3],
+    /// Upper corner.
+    pub max: [f32; 3],
+    /// Voxel type whose material the box is drawn with.
+    pub voxel: u32,
+}
+
+impl EntityBox {
+    /// Returns the cube of edge length `size` centered on `center`.
+    pub fn cube(center: [f32; 3], size: f32, voxel: u32) -> EntityBox {
+        EntityBox {
+            min: center.map(|c| c - size * 0.5),
+            max: center.map(|c| c + size * 0.5),
+            voxel,
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! C API for embedding the tracer, declared in `include/rayvox.h`. Every function taking a
+//! `RayVox` pointer expects one returned by `rayvox_create` and not yet destroyed.
+
+use crate::{
+    camera::Camera,
+    materials::Palette,
+    prefab::Prefab,
+    tracer,
+    world::{World, WORLD_SIZE},
+};
+use std::{
+    ffi::{c_char, c_int, CStr},
+    slice,
+};
+
+/// Distance rendered images trace primary rays to.
+const RENDER_DISTANCE: f32 = 512.0;
+
+/// A world along with the palette and camera it is rendered with.
+pub struct RayVox {
+    world: World,
+    palette: Palette,
+    camera: Camera,
+    /// The camera before the last `rayvox_set_camera`, which motion is measured against.
+    previous_camera: Camera,
+}
+
+/// Creates an empty world with the default palette and camera. Free it with `rayvox_destroy`.
+#[no_mangle]
+pub extern "C" fn rayvox_create() -> *mut RayVox {
+    Box::into_raw(Box::new(RayVox {
+        world: World::new(),
+        palette: Palette::default(),
+        camera: Camera::default(),
+        previous_camera: Camera::default(),
+    }))
+}
+
+/// Frees a `RayVox` created by `rayvox_create`. Null is ignored.
+///
+/// # Safety
+/// `rayvox` has to be null or come from `rayvox_create` and not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rayvox_destroy(rayvox: *mut RayVox) {
+    if !rayvox.is_null() {
+        drop(Box::from_raw(rayvox));
+    }
+}
+
+/// Replaces the world with the first model of the MagicaVoxel `.vox` file at `path`, placed at
+/// the world origin. Returns 0 on success and -1 if the file couldn't be loaded, leaving the
+/// world as it was.
+///
+/// # Safety
+/// `rayvox` has to be valid and `path` a null terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn rayvox_load_world(rayvox: *mut RayVox, path: *const c_char) -> c_int {
+    let rayvox = &mut *rayvox;
+    let path = CStr::from_ptr(path).to_string_lossy();
+    match Prefab::load(path.as_ref()) {
+        Ok(prefab) => {
+            rayvox.world.fill([0; 3], [WORLD_SIZE; 3], 0);
+            rayvox
+                .world
+                .paste_region([0; 3], prefab.size, &prefab.voxels);
+            0
+        }
+        Err(_) => -1,
+    }
+}
+
+/// Places the camera, see `Camera`. `position` and `rotation` point to 3 floats each. The
+/// camera placed before is kept for the motion of `rayvox_render_outputs`.
+///
+/// # Safety
+/// `rayvox` has to be valid and `position` and `rotation` readable for 3 floats.
+#[no_mangle]
+pub unsafe extern "C" fn rayvox_set_camera(
+    rayvox: *mut RayVox,
+    position: *const f32,
+    rotation: *const f32,
+) {
+    let rayvox = &mut *rayvox;
+    rayvox.previous_camera = rayvox.camera;
+    rayvox
+        .camera
+        .position
+        .copy_from_slice(slice::from_raw_parts(position, 3));
+    rayvox
+        .camera
+        .rotation
+        .copy_from_slice(slice::from_raw_parts(rotation, 3));
+}
+
+/// Renders the world into `rgba`, `width` by `height` pixels of 4 bytes each, rows from top to
+/// bottom. Returns 0 on success and -1 if the size is 0.
+///
+/// # Safety
+/// `rayvox` has to be valid and `rgba` writable for `width * height * 4` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rayvox_render_to_buffer(
+    rayvox: *const RayVox,
+    width: u32,
+    height: u32,
+    rgba: *mut u8,
+) -> c_int {
+    if width == 0 || height == 0 {
+        return -1;
+    }
+    let rayvox = &*rayvox;
+    let rgba = slice::from_raw_parts_mut(rgba, width as usize * height as usize * 4);
+    tracer::render(
+        &rayvox.world,
+        &rayvox.palette,
+        &rayvox.camera,
+        [width, height],
+        RENDER_DISTANCE,
+        rgba,
+    );
+    0
+}
+
+/// Renders like `rayvox_render_to_buffer` and writes the outputs of `tracer::Outputs` along with
+/// the color: `depth` 1 float, `normal` 3 floats, `material` 1 integer and `motion` 2 floats per
+/// pixel. Null outputs aren't written. Returns 0 on success and -1 if the size is 0.
+///
+/// # Safety
+/// `rayvox` has to be valid, `rgba` writable for `width * height * 4` bytes and every output
+/// that isn't null writable for its values of `width * height` pixels.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn rayvox_render_outputs(
+    rayvox: *const RayVox,
+    width: u32,
+    height: u32,
+    rgba: *mut u8,
+    depth: *mut f32,
+    normal: *mut f32,
+    material: *mut u32,
+    motion: *mut f32,
+) -> c_int {
+    if width == 0 || height == 0 {
+        return -1;
+    }
+    let rayvox = &*rayvox;
+    let pixels = width as usize * height as usize;
+    let output = |pointer: *mut f32, values: usize| {
+        (!pointer.is_null()).then(|| slice::from_raw_parts_mut(pointer, pixels * values))
+    };
+    let outputs = tracer::Outputs {
+        depth: output(depth, 1),
+        normal: output(normal, 3),
+        material: (!material.is_null()).then(|| slice::from_raw_parts_mut(material, pixels)),
+        motion: output(motion, 2),
+        color: None,
+    };
+    tracer::render_with_outputs(
+        &rayvox.world,
+        &rayvox.palette,
+        &rayvox.camera,
+        &rayvox.previous_camera,
+        [width, height],
+        RENDER_DISTANCE,
+        slice::from_raw_parts_mut(rgba, pixels * 4),
+        outputs,
+    );
+    0
+}
@@ -0,0 +1,173 @@
+use std::f32::consts::{PI, TAU};
+
+/// How a flight towards a keyframe speeds up and slows down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    /// Starts slow.
+    In,
+    /// Ends slow.
+    Out,
+    /// Starts and ends slow.
+    InOut,
+}
+
+impl Easing {
+    pub const ALL: [Easing; 4] = [Easing::Linear, Easing::In, Easing::Out, Easing::InOut];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::In => "in",
+            Easing::Out => "out",
+            Easing::InOut => "in-out",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Easing> {
+        Easing::ALL.into_iter().find(|easing| easing.name() == name)
+    }
+
+    /// Maps the fraction `t` (0 to 1) of the flight's time to the fraction of its way.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::In => t * t,
+            Easing::Out => t * (2.0 - t),
+            Easing::InOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// A camera placement a flythrough passes through.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    /// Position of the eye in the world.
+    pub eye: [f32; 3],
+    /// Rotation in radians around z, then x, then y, see `camera::Camera`.
+    pub rotation: [f32; 3],
+    /// Horizontal field of view in degrees, see `camera::fov`.
+    pub fov: f32,
+    /// Seconds the flight from the previous keyframe takes, unused for the first one.
+    pub travel: f32,
+    /// Seconds the camera stays once it arrived.
+    pub hold: f32,
+    /// How the flight from the previous keyframe speeds up and slows down.
+    pub easing: Easing,
+}
+
+/// Where the camera is at some time of a flythrough.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pose {
+    pub eye: [f32; 3],
+    pub rotation: [f32; 3],
+    pub fov: f32,
+}
+
+/// A camera path through keyframes, played back by sampling it at increasing times.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Flythrough {
+    keyframes: Vec<Keyframe>,
+}
+
+impl Flythrough {
+    pub fn new() -> Flythrough {
+        Flythrough::default()
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn push(&mut self, keyframe: Keyframe) {
+        self.keyframes.push(keyframe);
+    }
+
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+    }
+
+    /// Returns the seconds from the first keyframe to the end of the last one's hold.
+    pub fn duration(&self) -> f32 {
+        self.keyframes
+            .iter()
+            .enumerate()
+            .map(|(index, keyframe)| keyframe.hold + if index == 0 { 0.0 } else { keyframe.travel })
+            .sum()
+    }
+
+    /// Returns the seconds into the flythrough at which the camera arrives at each keyframe.
+    pub fn arrivals(&self) -> Vec<f32> {
+        let mut time = 0.0;
+        self.keyframes
+            .iter()
+            .enumerate()
+            .map(|(index, keyframe)| {
+                if index > 0 {
+                    time += keyframe.travel;
+                }
+                let arrival = time;
+                time += keyframe.hold;
+                arrival
+            })
+            .collect()
+    }
+
+    /// Returns where the camera is `time` seconds into the flythrough, `None` without keyframes.
+    /// Times past the end give the last keyframe.
+    pub fn sample(&self, time: f32) -> Option<Pose> {
+        if self.keyframes.is_empty() {
+            return None;
+        }
+        let arrivals = self.arrivals();
+        // The keyframe the camera is flying towards or holding at.
+        let next = arrivals
+            .iter()
+            .zip(&self.keyframes)
+            .position(|(arrival, keyframe)| time < arrival + keyframe.hold)
+            .unwrap_or(self.keyframes.len() - 1);
+        let to = &self.keyframes[next];
+        if next == 0 || time >= arrivals[next] {
+            return Some(Pose {
+                eye: to.eye,
+                rotation: to.rotation,
+                fov: to.fov,
+            });
+        }
+        let from = &self.keyframes[next - 1];
+        let t = to
+            .easing
+            .apply(1.0 - (arrivals[next] - time) / to.travel.max(f32::EPSILON));
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        Some(Pose {
+            eye: [0, 1, 2].map(|i| lerp(from.eye[i], to.eye[i])),
+            // Turns the short way around.
+            rotation: [0, 1, 2].map(|i| {
+                let turn = (to.rotation[i] - from.rotation[i] + PI).rem_euclid(TAU) - PI;
+                from.rotation[i] + turn * t
+            }),
+            fov: lerp(from.fov, to.fov),
+        })
+    }
+
+    /// Returns a one line bar showing the keyframes as `|` and `time` as `>`, `width` characters
+    /// wide.
+    pub fn timeline(&self, time: f32, width: usize) -> String {
+        let width = width.max(1);
+        let duration = self.duration();
+        let column = |time: f32| {
+            if duration <= 0.0 {
+                0
+            } else {
+                ((time / duration * (width - 1) as f32).round() as usize).min(width - 1)
+            }
+        };
+        let mut bar = vec!['-'; width];
+        for arrival in self.arrivals() {
+            bar[column(arrival)] = '|';
+        }
+        bar[column(time)] = '>';
+        bar.into_iter().collect()
+    }
+}
@@ -1,69 +1,750 @@
-use rand::Rng;
-use std::sync::Arc;
+use crate::decal_buffer::DecalBuffer;
+use crate::impostor_pipeline::{ImpostorFrame, ImpostorImage};
+use crate::light_bake_buffer::LightBakeBuffer;
+use crate::liquids_pipeline::LiquidsPipeline;
+use crate::memory_budget::{MemoryBudget, MemoryKind};
+use crate::picking::{Pick, PickRing, RayCount};
+use crate::post_process::{self, PostProcess};
+use crate::probe_images::ProbeImages;
+use crate::settings::{
+    Settings, Upscaler, FLAG_GBUFFER_PASS, FLAG_LIGHTING_PASS, FLAG_OUTPUTS, FLAG_UPSAMPLE_PASS,
+    FLAG_WORK_QUEUE,
+};
+use crate::timing::GpuTimer;
+use crate::trace_variants::{
+    adaptive_cs, chunk_binding, cs, TraceVariant, TraceVariants, ALL_LIGHTING, TILE_BINS,
+};
+use rvengine::{
+    camera::{camera_to_world, Lens, CAMERA_DIR},
+    caves,
+    chunk_palette::{ChunkSlots, HEADER_WORDS},
+    clipping::{ClipPlanes, MAX_CLIP_PLANES},
+    decals::Decals,
+    detail_normals::DetailNormals,
+    distance_field::{ChunkDistances, CHUNKS},
+    entities::{EntityBox, MAX_ENTITIES},
+    gpu_chunks::{ChunkBinding, GpuChunks},
+    light_bake::LightBake,
+    materials::{Palette, MATERIAL_COUNT},
+    objects::{object_words, voxel_words, Transform, VoxelObject},
+    portal::{Portals, MAX_PORTALS},
+    reflection_probes::ReflectionProbes,
+    scene::DEFAULT_TIME_OF_DAY,
+    screenshot::{HdrScreenshot, Screenshot},
+    simulation::LiquidBatch,
+    structures::Structures,
+    veins::Veins,
+    world::{StagedChunks, World, INITIAL_GPU_WORDS, WORLD_SIZE},
+    worldgen::Terrain,
+};
+use std::{path::Path, sync::Arc, time::Instant};
 use vulkano::{
-    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        PrimaryCommandBufferAbstract,
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferImageCopy,
+        CommandBufferUsage, CopyBufferInfo, CopyBufferToImageInfo, CopyImageToBufferInfo,
+        DispatchIndirectCommand, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
     },
     descriptor_set::{
         allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
     },
     device::Queue,
-    image::ImageAccess,
+    format::{Format, FormatFeatures},
+    image::{
+        view::ImageView, ImageAccess, ImageCreateFlags, ImageDimensions, ImageUsage, StorageImage,
+    },
     memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
     pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
-    sync::GpuFuture,
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+    sync::{self, GpuFuture},
 };
 use vulkano_util::renderer::DeviceImageView;
 
+/// Color and opacity of the edit preview box.
+const PREVIEW_COLOR: [f32; 4] = [0.3, 0.8, 1.0, 0.35];
+
+/// Number of samples a pixel needs before its variance is trusted by adaptive sampling.
+const ADAPTIVE_MIN_SAMPLES: u32 = 4;
+
+/// Edge length of the cubic workgroups of the world generation shader.
+const WORLDGEN_GROUP_SIZE: u32 = 4;
+/// Passes of worldgen.glsl, see its `PASS_` constants.
+const WORLDGEN_PASS_HEIGHTS: u32 = 0;
+const WORLDGEN_PASS_EROSION: u32 = 1;
+const WORLDGEN_PASS_VOXELS: u32 = 2;
+
+/// Edge length of the square workgroups of the upscale and post-process shaders, and of the
+/// trace shader's unless autotuned, see `Controller::set_trace_group_size`.
+pub const TILE_SIZE: u32 = 16;
+
+/// Words of `CulledObjects` in `compute.glsl` before its indices: the counts of objects in view
+/// and in range.
+const CULLED_HEADER_WORDS: u64 = 2;
+/// Objects culled by a workgroup of `cull_objects.glsl`.
+const CULL_GROUP_SIZE: u32 = 64;
+
+/// States of chunks in the chunk overlay, see `ChunkStates` in `compute.glsl`.
+const CHUNK_RESIDENT: u32 = 0;
+const CHUNK_STREAMING: u32 = 1;
+const CHUNK_DIRTY: u32 = 2;
+
+/// Indirect dispatch arguments of the tile bins before classification.
+static EMPTY_TILE_BIN_ARGS: [DispatchIndirectCommand; TILE_BINS as usize] =
+    [DispatchIndirectCommand { x: 0, y: 1, z: 1 }; TILE_BINS as usize];
+
+/// Images the trace pass writes besides the color once `Controller::request_outputs` asked for
+/// them, for building post effects and integrations on top of the tracer. All sized to the traced
+/// extent and matching the CPU tracer's `Outputs`.
+#[derive(Clone)]
+pub struct TraceOutputs {
+    /// Distance from the eye to the face hit along the ray in r, infinite for sky.
+    pub depth: DeviceImageView,
+    /// Normal of the face hit in rgb, zero for sky and rays starting inside a voxel.
+    pub normal: DeviceImageView,
+    /// Voxel type hit in r, 0 for sky.
+    pub material: DeviceImageView,
+    /// Screen space motion since the previous frame in rg, see `compute.glsl`.
+    pub motion: DeviceImageView,
+}
+
+/// Layout of `Material` in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuMaterial {
+    color: [f32; 4],
+    params: [f32; 4],
+    layer: [u32; 4],
+}
+
+/// Layout of the `Preview` uniform in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuPreview {
+    /// Lower corner in xyz, w is 1 when the preview is shown.
+    min: [i32; 4],
+    max: [i32; 4],
+    color: [f32; 4],
+}
+
+/// Layout of the `Lens` uniform in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuLens {
+    aperture: f32,
+    focus_distance: f32,
+}
+
+/// Layout of the `LayerMask` uniform in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuLayerMask {
+    hidden: u32,
+}
+
+/// Layout of `Portal` in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuPortal {
+    /// Lower corner in xyz, w is the index of the target.
+    min: [i32; 4],
+    /// Upper corner in xyz, w is the quarter turns.
+    max: [i32; 4],
+}
+
+/// Layout of the `Portals` buffer in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuPortals {
+    count: u32,
+    _padding: [u32; 3],
+    portals: [GpuPortal; MAX_PORTALS],
+}
+
+/// Layout of `Entity` in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuEntity {
+    /// Lower corner in xyz, w is the voxel type.
+    min: [f32; 4],
+    /// Upper corner in xyz, w is unused.
+    max: [f32; 4],
+}
+
+/// Layout of the `Entities` buffer in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuEntities {
+    count: u32,
+    _padding: [u32; 3],
+    entities: [GpuEntity; MAX_ENTITIES],
+}
+
+/// Layout of the `ClipPlanes` uniform in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuClipPlanes {
+    count: u32,
+    /// 1 when cut surfaces are drawn in `cap_color`.
+    capped: u32,
+    _padding: [u32; 2],
+    cap_color: [f32; 4],
+    /// Normal in xyz, w is the distance.
+    planes: [[f32; 4]; MAX_CLIP_PLANES],
+}
+
+/// Everything a traced image depends on besides the world: camera position, rotation,
+/// direction and lens, time of day, settings and preview box.
+type View = (
+    [f32; 3],
+    [f32; 3],
+    [f32; 3],
+    Lens,
+    f32,
+    Settings,
+    Option<([i32; 3], [i32; 3])>,
+);
+
+/// Host visible buffer accumulated samples are copied into, with the one their coverage is
+/// copied into for a transparent sky, the traced extent and whether the samples are half floats.
+type HdrReadback = (Subbuffer<[u8]>, Option<Subbuffer<[u8]>>, [u32; 2], bool);
+
 pub struct Controller {
     queue: Arc<Queue>,
-    pipeline: Arc<ComputePipeline>,
-    //memory_allocator: Arc<StandardMemoryAllocator>,
+    /// Queue of a family which can only copy, chunk uploads are copied into device local
+    /// memory on while the previous frame is traced on `queue`, see `stage_landing`.
+    transfer_queue: Option<Arc<Queue>>,
+    /// Chunks staged while the previous frame was traced, with when the transfer queue is done
+    /// copying them into device local memory.
+    landing: Option<(StagedChunks, Box<dyn GpuFuture + Send + Sync>)>,
+    trace_variants: TraceVariants,
+    /// Whether the GPU can store half floats in the accumulation target and GLSL is compiled at
+    /// runtime.
+    half_accumulation_supported: bool,
+    /// Whether falling back from half float accumulation was reported already.
+    half_accumulation_reported: bool,
+    /// Workgroup size the trace shader runs in.
+    trace_group_size: [u32; 2],
+    memory_allocator: Arc<StandardMemoryAllocator>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
-    world_buffer: Subbuffer<[[[u32; 256]; 256]]>,
+    world: World,
+    /// GPU copy of `world`, updated once per frame before tracing, with every chunk packed
+    /// against its own palette. Split into pages read through their device addresses or bound
+    /// as a descriptor array when the device supports either, see `ChunkBinding`.
+    gpu_chunks: GpuChunks,
+    /// Two level grid over `gpu_chunks`: how far every chunk is from chunks holding voxels,
+    /// built from the chunks as they are uploaded rather than as they are edited, so rays only
+    /// skip chunks which are empty on the GPU.
+    macro_cells: ChunkDistances,
+    /// GPU copy of `macro_cells`.
+    distance_buffer: Subbuffer<[u32]>,
+    worldgen_pipeline: Arc<ComputePipeline>,
+    /// Terrain to generate with the next frame.
+    terrain_request: Option<Terrain>,
+    /// Host visible buffer terrain is generated into on the GPU, until it is read back into
+    /// `world`, and the terrain. The world isn't flushed meanwhile, the readback replaces it
+    /// anyway.
+    terrain_readback: Option<(Subbuffer<[u32]>, Terrain)>,
+    /// Veins carved into terrain once it is read back, after its worms.
+    veins: Veins,
+    /// Structures placed on terrain once it is read back.
+    structures: Structures,
+    liquids: LiquidsPipeline,
+    palette: Palette,
+    /// Whether `palette` changed since it was last copied to `material_buffer`.
+    palette_dirty: bool,
+    material_buffer: Subbuffer<[GpuMaterial]>,
+    portals: Portals,
+    /// Whether `portals` changed since the last upload.
+    portals_dirty: bool,
+    portals_buffer: Subbuffer<GpuPortals>,
+    /// Boxes drawn over the world, the first `MAX_ENTITIES` of them.
+    entities: Vec<EntityBox>,
+    /// Whether `entities` changed since the last upload.
+    entities_dirty: bool,
+    entities_buffer: Subbuffer<GpuEntities>,
+    decals: DecalBuffer,
+    clip_planes: ClipPlanes,
+    /// Whether `clip_planes` changed since the last upload.
+    clip_planes_dirty: bool,
+    clip_planes_buffer: Subbuffer<GpuClipPlanes>,
+    /// Voxel models drawn over the world.
+    objects: Vec<VoxelObject>,
+    /// Whether the transforms of `objects` changed since they were last copied to
+    /// `objects_buffer`.
+    objects_dirty: bool,
+    /// Whether the models of `objects` changed since they were last copied to
+    /// `object_voxels_buffer`.
+    object_models_dirty: bool,
+    /// GPU copy of `objects` packed by `objects::object_words`, replaced with one of their size
+    /// whenever their count changes.
+    objects_buffer: Subbuffer<[u32]>,
+    /// Indices of the objects left after culling, see `CulledObjects` in `compute.glsl`,
+    /// replaced along with `objects_buffer`.
+    culled_objects_buffer: Subbuffer<[u32]>,
+    /// Culls the objects every frame, see `cull_objects.glsl`.
+    cull_pipeline: Arc<ComputePipeline>,
+    /// GPU copy of the voxels of every model, replaced with one of their size whenever they
+    /// change.
+    object_voxels_buffer: Subbuffer<[u32]>,
+    /// Billboards of the models of `objects` past `Settings::impostor_distance`.
+    impostors: ImpostorImage,
+    light_bake: LightBakeBuffer,
+    reflection_probes: ProbeImages,
+    detail_normals: DetailNormals,
+    /// Whether `detail_normals` changed since it was last copied to `detail_normals_image`.
+    detail_normals_dirty: bool,
+    /// GPU copy of `detail_normals`, replaced with one of its size whenever it changes.
+    detail_normals_image: Arc<ImageView<StorageImage>>,
+    /// Repeats the detail normal map over the faces.
+    detail_normals_sampler: Arc<Sampler>,
+    /// State of every chunk for `Settings::chunk_overlay`, see `ChunkStates` in `compute.glsl`.
+    /// Only written while the overlay is shown.
+    chunk_states_buffer: Subbuffer<[u32]>,
+    lens_buffer: Subbuffer<GpuLens>,
+    layer_mask_buffer: Subbuffer<GpuLayerMask>,
+    /// Box from its lower (inclusive) to its upper (exclusive) corner drawn over the image.
+    preview: Option<([i32; 3], [i32; 3])>,
+    preview_buffer: Subbuffer<GpuPreview>,
+    targets: Option<TraceTargets>,
+    /// Extents of the trace targets which were refused for not fitting the memory budget.
+    refused_targets: Option<([u32; 2], [u32; 2])>,
+    memory: MemoryBudget,
+    /// Whether `targets` were recreated since `take_targets_recreated` was last called.
+    targets_recreated: bool,
+    /// Whether the trace targets are to hold the depth, normal and material outputs, see
+    /// `request_outputs`.
+    outputs_request: bool,
+    upscale_pipeline: Arc<ComputePipeline>,
+    /// User shader run over the finished frame, see `post_process`.
+    post_process: Option<PostProcess>,
+    /// When the controller was created, the post-process shader's time counts from it.
+    started: Instant,
     pub position: [f32; 3],
     pub rotation: [f32; 3],
-    pub render_distance: u32,
+    /// Direction the camera looks along before rotation, setting the field of view, see
+    /// `camera::camera_dir`.
+    pub camera_dir: [f32; 3],
+    pub lens: Lens,
+    /// Hour of the day from 0 to 24 setting where the sun stands.
+    pub time_of_day: f32,
+    /// Camera position and rotation of the previous frame, for motion vectors.
+    previous_camera: ([f32; 3], [f32; 3]),
+    history_valid: bool,
+    picks: PickRing,
+    timer: GpuTimer,
+    /// Cursor position (0 to 1 on both axes) to pick the voxel under in the next frame.
+    pick_request: Option<[f32; 2]>,
+    /// Whether the next frame is copied into a screenshot.
+    screenshot_request: bool,
+    /// Host visible buffer the last requested frame is copied into, with the frame's extent,
+    /// until it is read back into `screenshot`.
+    screenshot_readback: Option<(Subbuffer<[u8]>, [u32; 2])>,
+    screenshot: Option<Screenshot>,
+    /// Whether the accumulated samples of the next frame are copied into an HDR screenshot.
+    hdr_screenshot_request: bool,
+    /// Whether the requested HDR screenshot leaves the sky transparent.
+    hdr_screenshot_sky_alpha: bool,
+    /// Samples copied for the next HDR screenshot until they are read back into
+    /// `hdr_screenshot`.
+    hdr_screenshot_readback: Option<HdrReadback>,
+    hdr_screenshot: Option<HdrScreenshot>,
+    frame: u32,
+    samples: u32,
+    last_view: Option<View>,
+}
+
+/// Per pixel running means used for progressive accumulation, the queue of pixels adaptive
+/// sampling decided need more samples, the classified screen tiles and the temporal upscaler's
+/// inputs. All sized to the traced extent except for the upscaler's history.
+struct TraceTargets {
+    extent: [u32; 2],
+    output_extent: [u32; 2],
+    /// Whether `color` holds half floats instead of 32-bit ones.
+    half_accumulation: bool,
+    /// Size of the screen tiles the tile bins hold.
+    group_size: [u32; 2],
+    /// Mean of the samples in rgb, sample count in alpha.
+    color: DeviceImageView,
+    /// Mean of the squared sample luminance.
+    moments: DeviceImageView,
+    /// Mean of the sample coverage, 1 for voxels and 0 for sky.
+    coverage: DeviceImageView,
+    /// Unlit primary hit color for half resolution lighting.
+    albedo: DeviceImageView,
+    /// Face normal and hit distance for half resolution lighting.
+    gbuffer: DeviceImageView,
+    /// Lighting factor at half the traced extent.
+    lighting: DeviceImageView,
+    /// Trace result when the temporal upscaler writes the output image.
+    traced: DeviceImageView,
+    /// Whether `depth`, `normal` and `material` are written, otherwise they are a texel each.
+    outputs: bool,
+    depth: DeviceImageView,
+    normal: DeviceImageView,
+    material: DeviceImageView,
+    /// Screen space motion of every traced pixel since the previous frame and its hit distance.
+    motion: DeviceImageView,
+    /// Upscaled frames at output extent, read and written alternately.
+    history: [DeviceImageView; 2],
+    /// Finished frame at output extent the post-process shader reads while one is loaded.
+    post_input: DeviceImageView,
+    /// Pixel count followed by the packed coordinates of the queued pixels.
+    work_queue: Subbuffer<[u32]>,
+    work_queue_args: Subbuffer<[DispatchIndirectCommand]>,
+    /// Tile count of each bin followed by one list of packed tile coordinates per bin.
+    tile_bins: Subbuffer<[u32]>,
+    tile_bin_args: Subbuffer<[DispatchIndirectCommand]>,
+}
+
+impl TraceTargets {
+    /// Returns the bytes of the targets for tracing `extent` and upscaling to `output_extent`.
+    fn bytes(
+        extent: [u32; 2],
+        output_extent: [u32; 2],
+        half_accumulation: bool,
+        group_size: [u32; 2],
+        outputs: bool,
+    ) -> u64 {
+        let pixels = |extent: [u32; 2]| extent[0] as u64 * extent[1] as u64;
+        let half_pixels = pixels(extent.map(|d| d.div_ceil(2)));
+        let tile_count = pixels([0, 1].map(|i| extent[i].div_ceil(group_size[i])));
+        let color = if half_accumulation { 8 } else { 16 };
+        // Bytes per pixel of color, moments, coverage, albedo, gbuffer, traced, motion and work
+        // queue.
+        pixels(extent) * (color + 4 + 4 + 8 + 8 + 4 + 8 + 4)
+            + half_pixels * 8
+            // Both histories and the post-process input.
+            + pixels(output_extent) * (2 * 8 + 4)
+            + (TILE_BINS as u64 + TILE_BINS as u64 * tile_count) * 4
+            // Depth, normal and material.
+            + if outputs { pixels(extent) * (4 + 8 + 4) } else { 0 }
+    }
+
+    fn new(
+        queue: Arc<Queue>,
+        memory_allocator: &StandardMemoryAllocator,
+        extent: [u32; 2],
+        output_extent: [u32; 2],
+        half_accumulation: bool,
+        group_size: [u32; 2],
+        outputs: bool,
+    ) -> Self {
+        let storage_image = |extent: [u32; 2], format| {
+            StorageImage::general_purpose_image_view(
+                memory_allocator,
+                queue.clone(),
+                extent,
+                format,
+                ImageUsage::STORAGE,
+            )
+            .unwrap()
+        };
+        // Copied out of for HDR screenshots.
+        let color = StorageImage::general_purpose_image_view(
+            memory_allocator,
+            queue.clone(),
+            extent,
+            if half_accumulation {
+                Format::R16G16B16A16_SFLOAT
+            } else {
+                Format::R32G32B32A32_SFLOAT
+            },
+            ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+        )
+        .unwrap();
+        let moments = storage_image(extent, Format::R32_SFLOAT);
+        // Copied out of for HDR screenshots with a transparent sky.
+        let coverage = StorageImage::general_purpose_image_view(
+            memory_allocator,
+            queue.clone(),
+            extent,
+            Format::R32_SFLOAT,
+            ImageUsage::STORAGE | ImageUsage::TRANSFER_SRC,
+        )
+        .unwrap();
+        let albedo = storage_image(extent, Format::R16G16B16A16_SFLOAT);
+        let gbuffer = storage_image(extent, Format::R16G16B16A16_SFLOAT);
+        let lighting = storage_image(extent.map(|d| d.div_ceil(2)), Format::R16G16B16A16_SFLOAT);
+        let traced = storage_image(extent, Format::R8G8B8A8_UNORM);
+        let motion = storage_image(extent, Format::R16G16B16A16_SFLOAT);
+        // Sampled or copied out of by whoever asked for them.
+        let output = |format| {
+            StorageImage::general_purpose_image_view(
+                memory_allocator,
+                queue.clone(),
+                if outputs { extent } else { [1, 1] },
+                format,
+                ImageUsage::STORAGE | ImageUsage::SAMPLED | ImageUsage::TRANSFER_SRC,
+            )
+            .unwrap()
+        };
+        let depth = output(Format::R32_SFLOAT);
+        let normal = output(Format::R16G16B16A16_SFLOAT);
+        let material = output(Format::R32_UINT);
+        let history = [(); 2].map(|_| storage_image(output_extent, Format::R16G16B16A16_SFLOAT));
+        let post_input = storage_image(output_extent, Format::R8G8B8A8_UNORM);
+        let work_queue = Buffer::new_slice(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+            1 + extent[0] as u64 * extent[1] as u64,
+        )
+        .unwrap();
+        let work_queue_args = Buffer::from_iter(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER
+                    | BufferUsage::INDIRECT_BUFFER
+                    | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            [DispatchIndirectCommand { x: 0, y: 1, z: 1 }],
+        )
+        .unwrap();
+        let tile_count = [0, 1].map(|i| extent[i].div_ceil(group_size[i]));
+        let tile_bins = Buffer::new_slice(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+            (TILE_BINS + TILE_BINS * tile_count[0] * tile_count[1]) as u64,
+        )
+        .unwrap();
+        let tile_bin_args = Buffer::from_iter(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER
+                    | BufferUsage::INDIRECT_BUFFER
+                    | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            EMPTY_TILE_BIN_ARGS,
+        )
+        .unwrap();
+
+        TraceTargets {
+            extent,
+            output_extent,
+            half_accumulation,
+            group_size,
+            color,
+            moments,
+            coverage,
+            albedo,
+            gbuffer,
+            lighting,
+            traced,
+            outputs,
+            depth,
+            normal,
+            material,
+            motion,
+            history,
+            post_input,
+            work_queue,
+            work_queue_args,
+            tile_bins,
+            tile_bin_args,
+        }
+    }
+
+    /// Returns the output images, a texel each unless `outputs` is set.
+    fn outputs(&self) -> TraceOutputs {
+        TraceOutputs {
+            depth: self.depth.clone(),
+            normal: self.normal.clone(),
+            material: self.material.clone(),
+            motion: self.motion.clone(),
+        }
+    }
 }
 
 impl Controller {
+    /// Creates the controller tracing on `queue`. Chunk uploads go through `transfer_queue`
+    /// when given, which belongs to a family that can only copy.
     pub fn new(
         queue: Arc<Queue>,
+        transfer_queue: Option<Arc<Queue>>,
         memory_allocator: Arc<StandardMemoryAllocator>,
         command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
         descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
-        render_distance: u32,
+        mut world: World,
     ) -> Self {
-        let mut world = vec![[[0; 256]; 256]; 256];
-        for x in 0..250 {
-            for y in 0..250 {
-                for z in 0..250 {
-                    if rand::thread_rng().gen_range(1..20) == 1 {
-                        world[x][y][z] = rand::thread_rng().gen_range(1..10);
-                    }
-                }
-            }
-        }
-        let world_buffer = Buffer::from_iter(
+        world.set_queue_families(
+            [&queue]
+                .into_iter()
+                .chain(&transfer_queue)
+                .map(|queue| queue.queue_family_index())
+                .collect(),
+        );
+        let chunk_binding = chunk_binding(queue.device());
+        // Filled over the first frames by flushing the world.
+        let gpu_chunks = world.gpu_chunks(&memory_allocator, chunk_binding, INITIAL_GPU_WORDS);
+        let distance_buffer = Buffer::new_slice(
             &memory_allocator,
             BufferCreateInfo {
-                usage: BufferUsage::STORAGE_BUFFER
-                    | BufferUsage::TRANSFER_SRC
-                    | BufferUsage::TRANSFER_DST,
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
                 ..Default::default()
             },
             AllocationCreateInfo {
-                usage: MemoryUsage::Upload,
+                usage: MemoryUsage::DeviceOnly,
                 ..Default::default()
             },
-            world,
+            (CHUNKS * CHUNKS * CHUNKS) as u64,
+        )
+        .unwrap();
+        let material_buffer = Buffer::new_slice(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+            MATERIAL_COUNT as u64,
         )
         .unwrap();
-        let pipeline = {
-            let shader = cs::load(queue.device().clone()).unwrap();
+        let portals_buffer = Buffer::new_sized(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let entities_buffer = Buffer::new_sized(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let clip_planes_buffer = Buffer::new_sized(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let objects_buffer = word_buffer(&memory_allocator, object_words(&[]).len() as u64);
+        let culled_objects_buffer = word_buffer(&memory_allocator, CULLED_HEADER_WORDS);
+        let object_voxels_buffer = word_buffer(&memory_allocator, 1);
+        let chunk_states_buffer = Buffer::new_slice(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+            HEADER_WORDS as u64,
+        )
+        .unwrap();
+        let decals = DecalBuffer::new(&memory_allocator);
+        let impostors = ImpostorImage::new(&memory_allocator, &queue);
+        let light_bake = LightBakeBuffer::new(&memory_allocator);
+        let reflection_probes = ProbeImages::new(&memory_allocator, &queue);
+        let detail_normals = DetailNormals::default();
+        let detail_normals_image = texture_image(&memory_allocator, &queue, detail_normals.size());
+        let detail_normals_sampler = Sampler::new(
+            queue.device().clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::Repeat; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let preview_buffer = Buffer::new_sized(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let lens_buffer = Buffer::new_sized(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let layer_mask_buffer = Buffer::new_sized(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::UNIFORM_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let trace_variants = TraceVariants::new(&queue, chunk_binding);
+        // Half float variants are compiled at runtime.
+        let half_accumulation_supported = cfg!(feature = "runtime-shaders")
+            && queue
+                .device()
+                .physical_device()
+                .format_properties(Format::R16G16B16A16_SFLOAT)
+                .is_ok_and(|properties| {
+                    properties
+                        .optimal_tiling_features
+                        .intersects(FormatFeatures::STORAGE_IMAGE)
+                });
+
+        let upscale_pipeline = {
+            let shader = upscale_cs::load(queue.device().clone()).unwrap();
             ComputePipeline::new(
                 queue.device().clone(),
                 shader.entry_point("main").unwrap(),
@@ -73,61 +754,1619 @@ impl Controller {
             )
             .unwrap()
         };
+        let cull_pipeline = {
+            let shader = cull_objects_cs::load(queue.device().clone()).unwrap();
+            ComputePipeline::new(
+                queue.device().clone(),
+                shader.entry_point("main").unwrap(),
+                &(),
+                None,
+                |_| {},
+            )
+            .unwrap()
+        };
+        let worldgen_pipeline = {
+            let shader = worldgen_cs::load(queue.device().clone()).unwrap();
+            ComputePipeline::new(
+                queue.device().clone(),
+                shader.entry_point("main").unwrap(),
+                &(),
+                None,
+                |_| {},
+            )
+            .unwrap()
+        };
+        let liquids = LiquidsPipeline::new(
+            &queue,
+            memory_allocator.clone(),
+            descriptor_set_allocator.clone(),
+        );
+        let position = [0.0, 0.0, -10.0];
+        let rotation = [0.0, 0.0, 0.0];
+        let picks = PickRing::new(&memory_allocator);
+        let timer = GpuTimer::new(&queue);
+        let mut memory = MemoryBudget::new(queue.device().physical_device());
+        memory.set(MemoryKind::World, gpu_chunks.bytes());
 
         Self {
             queue,
-            pipeline,
+            transfer_queue,
+            landing: None,
+            trace_variants,
+            half_accumulation_supported,
+            half_accumulation_reported: false,
+            trace_group_size: [TILE_SIZE; 2],
+            memory_allocator,
             command_buffer_allocator,
             descriptor_set_allocator,
-            world_buffer,
-            position: [0.0, 0.0, -10.0],
-            rotation: [0.0, 0.0, 0.0],
-            render_distance,
+            world,
+            gpu_chunks,
+            macro_cells: ChunkDistances::new(),
+            distance_buffer,
+            worldgen_pipeline,
+            terrain_request: None,
+            terrain_readback: None,
+            veins: Veins::default(),
+            structures: Structures::default(),
+            liquids,
+            palette: Palette::default(),
+            palette_dirty: true,
+            material_buffer,
+            portals: Portals::new(),
+            portals_dirty: true,
+            portals_buffer,
+            entities: Vec::new(),
+            entities_dirty: true,
+            entities_buffer,
+            decals,
+            clip_planes: ClipPlanes::new(),
+            clip_planes_dirty: true,
+            clip_planes_buffer,
+            objects: Vec::new(),
+            objects_dirty: true,
+            object_models_dirty: true,
+            objects_buffer,
+            culled_objects_buffer,
+            cull_pipeline,
+            object_voxels_buffer,
+            impostors,
+            light_bake,
+            reflection_probes,
+            detail_normals,
+            detail_normals_dirty: true,
+            detail_normals_image,
+            detail_normals_sampler,
+            chunk_states_buffer,
+            lens_buffer,
+            layer_mask_buffer,
+            preview: None,
+            preview_buffer,
+            targets: None,
+            refused_targets: None,
+            memory,
+            targets_recreated: false,
+            outputs_request: false,
+            upscale_pipeline,
+            post_process: None,
+            started: Instant::now(),
+            position,
+            rotation,
+            camera_dir: CAMERA_DIR,
+            lens: Lens::default(),
+            time_of_day: DEFAULT_TIME_OF_DAY,
+            previous_camera: (position, rotation),
+            history_valid: false,
+            picks,
+            timer,
+            pick_request: None,
+            screenshot_request: false,
+            screenshot_readback: None,
+            screenshot: None,
+            hdr_screenshot_request: false,
+            hdr_screenshot_sky_alpha: false,
+            hdr_screenshot_readback: None,
+            hdr_screenshot: None,
+            frame: 0,
+            samples: 0,
+            last_view: None,
         }
     }
 
-    pub fn compute(&self, image: DeviceImageView) -> Box<dyn GpuFuture> {
-        let img_dims = image.image().dimensions().width_height();
-        let pipeline_layout = self.pipeline.layout();
-        let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
-        let set = PersistentDescriptorSet::new(
-            &self.descriptor_set_allocator,
-            desc_layout.clone(),
-            [
-                WriteDescriptorSet::image_view(0, image),
-                WriteDescriptorSet::buffer(1, self.world_buffer.clone()),
-            ],
-        )
-        .unwrap();
+    /// Returns how many samples per pixel have been accumulated since the view last changed.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    /// Returns the world for editing. Edits reach the GPU with the next frame.
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Replaces the world with `terrain` generated on the GPU with the next frame. The world is
+    /// read back a few frames later, edits made until then are lost.
+    pub fn generate_terrain(&mut self, terrain: Terrain) {
+        self.terrain_request = Some(terrain);
+    }
+
+    /// Sets the veins carved into terrain generated from now on.
+    pub fn set_veins(&mut self, veins: Veins) {
+        self.veins = veins;
+    }
+
+    /// Sets the structures placed on terrain generated from now on.
+    pub fn set_structures(&mut self, structures: Structures) {
+        self.structures = structures;
+    }
+
+    /// Returns whether terrain is being generated or read back.
+    pub fn generating_terrain(&self) -> bool {
+        self.terrain_request.is_some() || self.terrain_readback.is_some()
+    }
+
+    /// Runs the ticks of `batch` on the GPU with the next frame. `take_simulated_liquids`
+    /// returns the result a few frames later.
+    pub fn simulate_liquids(&mut self, batch: LiquidBatch) {
+        self.liquids.simulate(batch);
+    }
+
+    /// Returns a batch passed to `simulate_liquids` along with the cells of its chunks after it,
+    /// once they are read back.
+    pub fn take_simulated_liquids(&mut self) -> Option<(LiquidBatch, Vec<u32>)> {
+        self.liquids.take_simulated()
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Returns the palette for editing. Edits reach the GPU with the next frame.
+    pub fn palette_mut(&mut self) -> &mut Palette {
+        self.palette_dirty = true;
+        self.impostors.mark_dirty();
+        &mut self.palette
+    }
+
+    pub fn portals(&self) -> &Portals {
+        &self.portals
+    }
+
+    /// Returns the portals for editing. Edits reach the GPU with the next frame.
+    pub fn portals_mut(&mut self) -> &mut Portals {
+        self.portals_dirty = true;
+        &mut self.portals
+    }
+
+    /// Replaces the boxes drawn over the world, which reach the GPU with the next frame. Samples
+    /// accumulated so far are dropped when they moved.
+    pub fn set_entities(&mut self, entities: Vec<EntityBox>) {
+        if entities != self.entities {
+            self.entities = entities;
+            self.entities_dirty = true;
+        }
+    }
+
+    pub fn decals(&self) -> &Decals {
+        self.decals.decals()
+    }
+
+    /// Returns the decals for editing. Edits reach the GPU with the next frame.
+    pub fn decals_mut(&mut self) -> &mut Decals {
+        self.decals.decals_mut()
+    }
+
+    pub fn clip_planes(&self) -> &ClipPlanes {
+        &self.clip_planes
+    }
+
+    /// Returns the clipping planes for editing. Edits reach the GPU with the next frame.
+    pub fn clip_planes_mut(&mut self) -> &mut ClipPlanes {
+        self.clip_planes_dirty = true;
+        &mut self.clip_planes
+    }
+
+    pub fn objects(&self) -> &[VoxelObject] {
+        &self.objects
+    }
+
+    /// Replaces the voxel objects drawn over the world, which reach the GPU with the next frame.
+    pub fn set_objects(&mut self, objects: Vec<VoxelObject>) {
+        self.objects = objects;
+        self.objects_dirty = true;
+        self.object_models_dirty = true;
+        self.impostors.mark_dirty();
+    }
+
+    /// Moves the object at `index` to `transform`, which reaches the GPU with the next frame.
+    /// Does nothing when there is no such object.
+    pub fn set_object_transform(&mut self, index: usize, transform: Transform) {
+        if let Some(object) = self.objects.get_mut(index) {
+            if object.transform != transform {
+                object.transform = transform;
+                self.objects_dirty = true;
+            }
+        }
+    }
+
+    /// Puts the object at `index` on `layer`, see `layers::Layers`. Does nothing when there is
+    /// no such object.
+    pub fn set_object_layer(&mut self, index: usize, layer: u32) {
+        if let Some(object) = self.objects.get_mut(index) {
+            if object.layer != layer {
+                object.layer = layer;
+                self.objects_dirty = true;
+            }
+        }
+    }
+
+    pub fn light_bake(&self) -> Option<&LightBake> {
+        self.light_bake.bake()
+    }
+
+    /// Replaces the light bake, which reaches the GPU with the next frame.
+    pub fn set_light_bake(&mut self, light_bake: Option<LightBake>) {
+        self.light_bake.set(light_bake);
+    }
+
+    pub fn reflection_probes(&self) -> &ReflectionProbes {
+        self.reflection_probes.probes()
+    }
+
+    /// Returns the reflection probes for editing. Edits reach the GPU with the next frame.
+    pub fn reflection_probes_mut(&mut self) -> &mut ReflectionProbes {
+        self.reflection_probes.probes_mut()
+    }
+
+    /// Places a reflection probe at `position`, rendered from the world as it is, and returns
+    /// its index.
+    pub fn add_reflection_probe(&mut self, position: [f32; 3]) -> Result<usize, String> {
+        self.reflection_probes
+            .add(&self.world, &self.palette, position)
+    }
+
+    /// Renders every reflection probe again from the world as it is.
+    pub fn render_reflection_probes(&mut self) {
+        self.reflection_probes.render(&self.world, &self.palette);
+    }
+
+    /// Replaces the detail normal map, which reaches the GPU with the next frame.
+    pub fn set_detail_normals(&mut self, detail_normals: DetailNormals) {
+        self.detail_normals = detail_normals;
+        self.detail_normals_dirty = true;
+    }
+
+    /// Shows a translucent box from `min` (inclusive) to `max` (exclusive) over the image, or
+    /// nothing for `None`.
+    pub fn set_preview(&mut self, preview: Option<([i32; 3], [i32; 3])>) {
+        self.preview = preview;
+    }
+
+    /// Runs the trace shader in workgroups of `size` from the next frame on, once the trace
+    /// targets were recreated for its tiles. Custom kernels keep the default size.
+    pub fn set_trace_group_size(&mut self, size: [u32; 2]) {
+        self.trace_group_size = size;
+    }
+
+    /// Traces with the compute shader at `path` instead of the built-in one, or goes back to the
+    /// built-in one with `None`. The shader is SPIR-V or GLSL like `assets/shader/compute.glsl`,
+    /// with the same bindings and push constants, compiled with `CHUNK_PAGES` or
+    /// `CHUNK_ADDRESSES` defined when the world is split into pages, see `ChunkBinding`. Its
+    /// `#include`s are looked up next to it first, so a copy of `compute.glsl` with its own
+    /// `trace/traversal.glsl` swaps the traversal only. Keeps the previous shader when loading
+    /// fails.
+    pub fn load_trace_shader(&mut self, path: Option<&Path>) -> Result<(), String> {
+        self.trace_variants.load_custom(self.queue.device(), path)?;
+        self.samples = 0;
+        Ok(())
+    }
+
+    /// Compiles the post-process shader at `path` and runs it over every frame from now on, or
+    /// stops post-processing with `None`. Keeps the previous shader when compiling fails. Asks
+    /// for the outputs when the shader reads the depth, normal or material, see
+    /// `request_outputs`.
+    pub fn load_post_process(&mut self, path: Option<&Path>) -> Result<(), String> {
+        self.post_process = match path {
+            Some(path) => Some(post_process::compile(self.queue.device(), path)?),
+            None => None,
+        };
+        self.request_outputs(
+            self.post_process
+                .as_ref()
+                .is_some_and(PostProcess::uses_outputs),
+        );
+        Ok(())
+    }
+
+    /// Picks the voxel under `position` (0 to 1 on both axes of the window) in the next frame.
+    pub fn request_pick(&mut self, position: [f32; 2]) {
+        self.pick_request = Some(position);
+    }
+
+    /// Returns how long each GPU pass of the latest finished frame took, in milliseconds.
+    pub fn pass_timings(&self) -> &[(&'static str, f32)] {
+        self.timer.latest()
+    }
+
+    /// Returns the GPU memory taken by the renderer's large allocations.
+    pub fn memory_budget(&self) -> &MemoryBudget {
+        &self.memory
+    }
+
+    /// Returns whether the trace targets were recreated, after a resize or a change of render
+    /// scale, since the last call.
+    pub fn take_targets_recreated(&mut self) -> bool {
+        std::mem::take(&mut self.targets_recreated)
+    }
+
+    /// Writes the depth, normal and material outputs of every frame from the next one on, or
+    /// stops writing them, see `outputs`. Recreates the trace targets. Loading a post-process
+    /// shader replaces the request with whether it reads the material.
+    pub fn request_outputs(&mut self, enabled: bool) {
+        self.outputs_request = enabled;
+    }
+
+    /// Returns the outputs of the frames traced from now on, `None` until `request_outputs`
+    /// asked for them and the trace targets holding them were created. The images are replaced
+    /// whenever the targets are recreated, see `take_targets_recreated`.
+    pub fn outputs(&self) -> Option<TraceOutputs> {
+        self.targets
+            .as_ref()
+            .filter(|targets| targets.outputs)
+            .map(TraceTargets::outputs)
+    }
+
+    /// Copies the next frame into a screenshot, see `take_screenshot`.
+    pub fn request_screenshot(&mut self) {
+        self.screenshot_request = true;
+    }
+
+    /// Returns the requested screenshot once the GPU finished its frame and it was read back.
+    pub fn take_screenshot(&mut self) -> Option<Screenshot> {
+        self.screenshot.take()
+    }
+
+    /// Copies the samples accumulated by the next frame into an HDR screenshot, see
+    /// `take_hdr_screenshot`. They only hold the frame while `Settings::accumulate` is on. With
+    /// `sky_alpha` the sky is left transparent, see `HdrScreenshot::mask_sky`.
+    pub fn request_hdr_screenshot(&mut self, sky_alpha: bool) {
+        self.hdr_screenshot_request = true;
+        self.hdr_screenshot_sky_alpha = sky_alpha;
+    }
+
+    /// Returns the requested HDR screenshot once the GPU finished its frame and it was read
+    /// back.
+    pub fn take_hdr_screenshot(&mut self) -> Option<HdrScreenshot> {
+        self.hdr_screenshot.take()
+    }
+
+    /// Returns the latest pick the GPU finished since the last call, if any.
+    pub fn take_pick(&mut self) -> Option<Pick> {
+        self.picks.take()
+    }
+
+    /// Returns the ray counts of the frames the GPU finished since the last call, see
+    /// `Settings::step_log`.
+    pub fn take_ray_counts(&mut self) -> Vec<RayCount> {
+        self.picks.take_ray_counts()
+    }
+
+    /// Returns the impostors of the frame traced last, `None` when `settings` draws none or all
+    /// objects are close enough to be traced.
+    pub fn impostor_frame(&self, settings: &Settings) -> Option<ImpostorFrame> {
+        let targets = self.targets.as_ref()?;
+        let atlas = self.impostors.atlas()?;
+        if settings.impostor_distance == 0 {
+            return None;
+        }
+        let eye = camera_to_world(self.position, self.rotation);
+        let billboards = atlas.billboards(
+            &self.objects,
+            eye,
+            settings.impostor_distance as f32,
+            settings.object_distance as f32,
+            settings.hidden_layers,
+        );
+        (!billboards.is_empty()).then(|| ImpostorFrame {
+            atlas: self.impostors.image().clone(),
+            motion: targets.motion.clone(),
+            billboards,
+            eye,
+            rotation: self.rotation,
+            camera_dir: self.camera_dir,
+        })
+    }
+
+    /// Records and submits tracing a frame into `image` after `before`, returning when it is
+    /// done.
+    pub fn compute(
+        &mut self,
+        image: DeviceImageView,
+        settings: &Settings,
+        before: Box<dyn GpuFuture>,
+    ) -> Box<dyn GpuFuture> {
+        let output_dims = image.image().dimensions().width_height();
+        let img_dims = settings.scaled_extent(output_dims);
+
+        // Restart accumulation whenever anything affecting the image changed.
+        let view = (
+            self.position,
+            self.rotation,
+            self.camera_dir,
+            self.lens,
+            self.time_of_day,
+            *settings,
+            self.preview,
+        );
+        if self.last_view != Some(view) || !settings.accumulate {
+            self.samples = 0;
+            self.last_view = Some(view);
+        }
+        let variant = TraceVariant {
+            lighting: settings.flags() & ALL_LIGHTING,
+            // Custom kernels declare a 32-bit accumulation target like the built-in shader.
+            half_accumulation: settings.half_float_accumulation
+                && self.half_accumulation_supported
+                && !self.trace_variants.has_custom(),
+            group_size: self.trace_group_size,
+        };
+        let pipelines = self.trace_variants.get(self.queue.device(), variant);
+        // Differs from what was asked for when building that failed.
+        let TraceVariant {
+            half_accumulation,
+            group_size,
+            ..
+        } = pipelines.variant;
+        if settings.half_float_accumulation
+            && !half_accumulation
+            && !self.half_accumulation_reported
+        {
+            println!("half float accumulation isn't available, accumulating in 32-bit floats");
+            self.half_accumulation_reported = true;
+        }
+        if self.targets.as_ref().is_none_or(|targets| {
+            targets.extent != img_dims
+                || targets.output_extent != output_dims
+                || targets.half_accumulation != half_accumulation
+                || targets.group_size != group_size
+                || targets.outputs != self.outputs_request
+        }) {
+            // Skips frames instead of running the allocator out of memory, until the window or
+            // render scale shrink.
+            let bytes = TraceTargets::bytes(
+                img_dims,
+                output_dims,
+                half_accumulation,
+                group_size,
+                self.outputs_request,
+            );
+            if !self.memory.fits(MemoryKind::Targets, bytes) {
+                if self.refused_targets != Some((img_dims, output_dims)) {
+                    println!(
+                        "trace targets for {img_dims:?} need {} MiB, exceeding the VRAM budget \
+                         ({} of {} MiB in use), lower the render scale or window size",
+                        bytes >> 20,
+                        (self.memory.used() - self.memory.used_by(MemoryKind::Targets)) >> 20,
+                        self.memory.budget() >> 20,
+                    );
+                    self.refused_targets = Some((img_dims, output_dims));
+                }
+                return before;
+            }
+            self.refused_targets = None;
+            // Drop the old targets first so both sets are never alive at once.
+            self.targets = None;
+            self.memory.set(MemoryKind::Targets, bytes);
+            self.targets = Some(TraceTargets::new(
+                self.queue.clone(),
+                &self.memory_allocator,
+                img_dims,
+                output_dims,
+                half_accumulation,
+                group_size,
+                self.outputs_request,
+            ));
+            self.samples = 0;
+            self.history_valid = false;
+            self.targets_recreated = true;
+        }
+        let pick_pixel = self.pick_request.take().map(|position| {
+            [0, 1].map(|i| ((position[i] * img_dims[i] as f32) as u32).min(img_dims[i] - 1))
+        });
+        let (pick_buffer, pick_data) = self.picks.next_frame(pick_pixel, settings.step_log);
+        // Fails without blocking while the GPU still writes the readback buffer.
+        let screenshot = self
+            .screenshot_readback
+            .as_ref()
+            .and_then(|(readback, extent)| {
+                let pixels = readback.read().ok()?;
+                Some(Screenshot::from_traced(*extent, &pixels))
+            });
+        if screenshot.is_some() {
+            self.screenshot = screenshot;
+            self.screenshot_readback = None;
+        }
+        let hdr_screenshot =
+            self.hdr_screenshot_readback
+                .as_ref()
+                .and_then(|(readback, coverage, extent, half)| {
+                    let coverage = match coverage {
+                        Some(coverage) => Some(coverage.read().ok()?),
+                        None => None,
+                    };
+                    let texels = readback.read().ok()?;
+                    Some(HdrScreenshot::from_traced(
+                        *extent,
+                        &texels,
+                        *half,
+                        coverage.as_deref(),
+                    ))
+                });
+        if let Some(hdr_screenshot) = hdr_screenshot {
+            self.hdr_screenshot = hdr_screenshot
+                .map_err(|err| println!("can't take the HDR screenshot: {err}"))
+                .ok();
+            self.hdr_screenshot_readback = None;
+        }
+        let targets = self.targets.as_ref().unwrap();
+        let tile_count = [0, 1].map(|i| img_dims[i].div_ceil(group_size[i]));
+
+        // The post-process shader reads the finished frame from its own image and writes the
+        // output one.
+        let frame_image = match self.post_process {
+            Some(_) => targets.post_input.clone(),
+            None => image.clone(),
+        };
+
+        let objects_words = self.objects_dirty.then(|| {
+            let words = object_words(&self.objects);
+            if words.len() as u64 != self.objects_buffer.len() {
+                self.objects_buffer = word_buffer(&self.memory_allocator, words.len() as u64);
+                self.culled_objects_buffer = word_buffer(
+                    &self.memory_allocator,
+                    CULLED_HEADER_WORDS + 2 * self.objects.len() as u64,
+                );
+            }
+            words
+        });
+        let object_voxel_words = self.object_models_dirty.then(|| {
+            let words = voxel_words(&self.objects);
+            self.object_voxels_buffer =
+                word_buffer(&self.memory_allocator, words.len().max(1) as u64);
+            words
+        });
+        if self.detail_normals_dirty {
+            self.detail_normals_image = texture_image(
+                &self.memory_allocator,
+                &self.queue,
+                self.detail_normals.size(),
+            );
+        }
+
         let mut builder = AutoCommandBufferBuilder::primary(
-            &self.command_buffer_allocator,
+            self.command_buffer_allocator.as_ref(),
             self.queue.queue_family_index(),
             CommandBufferUsage::OneTimeSubmit,
         )
         .unwrap();
+        self.timer.begin_frame(&mut builder);
+        builder
+            .update_buffer(pick_buffer.clone(), Box::new(pick_data))
+            .unwrap();
+        let preview = match self.preview {
+            Some((min, max)) => GpuPreview {
+                min: [min[0], min[1], min[2], 1],
+                max: [max[0], max[1], max[2], 0],
+                color: PREVIEW_COLOR,
+            },
+            None => GpuPreview {
+                min: [0; 4],
+                max: [0; 4],
+                color: PREVIEW_COLOR,
+            },
+        };
+        builder
+            .update_buffer(self.preview_buffer.clone(), Box::new(preview))
+            .unwrap()
+            .update_buffer(
+                self.lens_buffer.clone(),
+                Box::new(GpuLens {
+                    aperture: self.lens.aperture,
+                    focus_distance: self.lens.focus_distance,
+                }),
+            )
+            .unwrap()
+            .update_buffer(
+                self.layer_mask_buffer.clone(),
+                Box::new(GpuLayerMask {
+                    hidden: settings.hidden_layers,
+                }),
+            )
+            .unwrap();
+        if self.palette_dirty {
+            let materials: Box<[GpuMaterial]> = self
+                .palette
+                .materials
+                .iter()
+                .map(|material| GpuMaterial {
+                    color: [
+                        material.color[0],
+                        material.color[1],
+                        material.color[2],
+                        material.emissive,
+                    ],
+                    params: [
+                        material.roughness,
+                        material.transparency,
+                        material.metalness,
+                        if material.detail_normals { 1.0 } else { 0.0 },
+                    ],
+                    layer: [material.layer, 0, 0, 0],
+                })
+                .collect();
+            builder
+                .update_buffer(self.material_buffer.clone(), materials)
+                .unwrap();
+            self.palette_dirty = false;
+            self.samples = 0;
+        }
+        if self.portals_dirty {
+            let mut portals = GpuPortals {
+                count: self.portals.portals().len() as u32,
+                _padding: [0; 3],
+                portals: [GpuPortal {
+                    min: [0; 4],
+                    max: [0; 4],
+                }; MAX_PORTALS],
+            };
+            for (gpu, portal) in portals.portals.iter_mut().zip(self.portals.portals()) {
+                gpu.min = [
+                    portal.min[0],
+                    portal.min[1],
+                    portal.min[2],
+                    portal.target as i32,
+                ];
+                gpu.max = [
+                    portal.max[0],
+                    portal.max[1],
+                    portal.max[2],
+                    portal.quarter_turns as i32,
+                ];
+            }
+            builder
+                .update_buffer(self.portals_buffer.clone(), Box::new(portals))
+                .unwrap();
+            self.portals_dirty = false;
+            self.samples = 0;
+        }
+        if self.entities_dirty {
+            let mut entities = GpuEntities {
+                count: self.entities.len().min(MAX_ENTITIES) as u32,
+                _padding: [0; 3],
+                entities: [GpuEntity {
+                    min: [0.0; 4],
+                    max: [0.0; 4],
+                }; MAX_ENTITIES],
+            };
+            for (gpu, entity) in entities.entities.iter_mut().zip(&self.entities) {
+                gpu.min = [
+                    entity.min[0],
+                    entity.min[1],
+                    entity.min[2],
+                    entity.voxel as f32,
+                ];
+                gpu.max = [entity.max[0], entity.max[1], entity.max[2], 0.0];
+            }
+            builder
+                .update_buffer(self.entities_buffer.clone(), Box::new(entities))
+                .unwrap();
+            self.entities_dirty = false;
+            self.samples = 0;
+        }
+        if self.decals.record_upload(&mut builder) {
+            self.samples = 0;
+        }
+        if self.clip_planes_dirty {
+            let cap = self.clip_planes.cap;
+            let [r, g, b] = cap.unwrap_or_default();
+            let mut clip_planes = GpuClipPlanes {
+                count: self.clip_planes.planes().len() as u32,
+                capped: cap.is_some() as u32,
+                _padding: [0; 2],
+                cap_color: [r, g, b, 1.0],
+                planes: [[0.0; 4]; MAX_CLIP_PLANES],
+            };
+            for (gpu, plane) in clip_planes.planes.iter_mut().zip(self.clip_planes.planes()) {
+                let [x, y, z] = plane.normal;
+                *gpu = [x, y, z, plane.distance];
+            }
+            builder
+                .update_buffer(self.clip_planes_buffer.clone(), Box::new(clip_planes))
+                .unwrap();
+            self.clip_planes_dirty = false;
+            self.samples = 0;
+        }
+        if let Some(words) = objects_words {
+            copy_words(
+                &self.memory_allocator,
+                &mut builder,
+                words,
+                self.objects_buffer.clone(),
+            );
+            self.objects_dirty = false;
+            self.samples = 0;
+        }
+        if let Some(words) = object_voxel_words {
+            if words.is_empty() {
+                builder
+                    .fill_buffer(self.object_voxels_buffer.clone(), 0)
+                    .unwrap();
+            } else {
+                copy_words(
+                    &self.memory_allocator,
+                    &mut builder,
+                    words,
+                    self.object_voxels_buffer.clone(),
+                );
+            }
+            self.object_models_dirty = false;
+            self.samples = 0;
+        }
+        if self
+            .light_bake
+            .record_upload(&mut builder, &self.memory_allocator)
+        {
+            self.memory
+                .set(MemoryKind::LightBake, self.light_bake.bytes());
+            self.samples = 0;
+        }
+        if self
+            .reflection_probes
+            .record_upload(&mut builder, &self.memory_allocator)
+        {
+            self.samples = 0;
+        }
+        if self.detail_normals_dirty {
+            let texels = Buffer::from_iter(
+                &self.memory_allocator,
+                BufferCreateInfo {
+                    usage: BufferUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    usage: MemoryUsage::Upload,
+                    ..Default::default()
+                },
+                self.detail_normals.texels().iter().copied(),
+            )
+            .unwrap();
+            builder
+                .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                    texels,
+                    self.detail_normals_image.image().clone(),
+                ))
+                .unwrap();
+            self.detail_normals_dirty = false;
+            self.samples = 0;
+        }
+        if settings.impostor_distance > 0 {
+            self.impostors.record_bake(
+                &mut builder,
+                &self.memory_allocator,
+                &self.queue,
+                &self.objects,
+                &self.palette,
+            );
+        }
+        self.memory.set(
+            MemoryKind::Objects,
+            self.objects_buffer.size()
+                + self.culled_objects_buffer.size()
+                + self.object_voxels_buffer.size()
+                + self.impostors.bytes(),
+        );
+        self.liquids.record(&mut builder);
+        let eye = camera_to_world(self.position, self.rotation);
+        let forward = camera_to_world(self.camera_dir, self.rotation);
+        // Fails without blocking while the GPU still writes the readback buffer.
+        let read_back = match &self.terrain_readback {
+            Some((readback, terrain)) => match readback.read() {
+                Ok(voxels) if terrain.worms == 0 && self.veins.veins.is_empty() => {
+                    self.world.replace_with_generated(&voxels);
+                    true
+                }
+                Ok(voxels) => {
+                    let mut voxels = voxels.to_vec();
+                    caves::carve_worms(terrain, &mut voxels);
+                    self.veins.carve_world(terrain.seed as u64, &mut voxels);
+                    self.world.replace_with_generated(&voxels);
+                    true
+                }
+                Err(_) => false,
+            },
+            None => false,
+        };
+        if read_back {
+            if let Some((_, terrain)) = self.terrain_readback.take() {
+                let placed = self.structures.place(terrain.seed as u64, &mut self.world);
+                if placed > 0 {
+                    println!("placed {placed} structures");
+                }
+            }
+        }
+        if let Some(terrain) = self.terrain_request.take() {
+            self.terrain_readback = Some((self.record_terrain(&mut builder, terrain), terrain));
+            self.samples = 0;
+        }
+        // With a transfer queue the chunks were staged while the previous frame was traced, see
+        // `stage_landing`, and only copying them into the world is left. Every write to the
+        // world happens on this queue, after the previous frame's trace is done with it.
+        let mut landed = None;
+        let uploaded = match self.landing.take() {
+            Some((staged, future)) => {
+                staged.record(&mut builder, &self.memory_allocator, &mut self.gpu_chunks);
+                landed = Some(future);
+                true
+            }
+            None => {
+                self.transfer_queue.is_none()
+                    && self.terrain_readback.is_none()
+                    && self.world.flush(
+                        &mut builder,
+                        &self.memory_allocator,
+                        &mut self.gpu_chunks,
+                        eye,
+                        forward,
+                    )
+            }
+        };
+        if uploaded {
+            self.samples = 0;
+        }
+        self.memory.set(MemoryKind::World, self.gpu_chunks.bytes());
+        self.memory
+            .set(MemoryKind::Staging, self.world.staging_bytes());
+        let pipeline_layout = self.trace_variants.builtin().all.layout();
+        let desc_layout = pipeline_layout.set_layouts().first().unwrap();
+        let mut writes = vec![
+            WriteDescriptorSet::image_view(
+                0,
+                if settings.upscaler == Upscaler::Temporal {
+                    targets.traced.clone()
+                } else {
+                    frame_image.clone()
+                },
+            ),
+            WriteDescriptorSet::image_view(2, targets.color.clone()),
+            WriteDescriptorSet::image_view(3, targets.moments.clone()),
+            WriteDescriptorSet::buffer(4, targets.work_queue.clone()),
+            WriteDescriptorSet::buffer(5, targets.tile_bins.clone()),
+            WriteDescriptorSet::buffer(6, targets.tile_bin_args.clone()),
+            WriteDescriptorSet::image_view(7, targets.albedo.clone()),
+            WriteDescriptorSet::image_view(8, targets.gbuffer.clone()),
+            WriteDescriptorSet::image_view(9, targets.lighting.clone()),
+            WriteDescriptorSet::image_view(10, targets.motion.clone()),
+            WriteDescriptorSet::buffer(11, pick_buffer.clone()),
+            WriteDescriptorSet::buffer(12, self.material_buffer.clone()),
+            WriteDescriptorSet::buffer(13, self.preview_buffer.clone()),
+            WriteDescriptorSet::buffer(14, self.distance_buffer.clone()),
+            WriteDescriptorSet::buffer(15, self.portals_buffer.clone()),
+            WriteDescriptorSet::buffer(16, self.light_bake.buffer().clone()),
+            WriteDescriptorSet::image_view_sampler(
+                17,
+                self.reflection_probes.images().clone(),
+                self.reflection_probes.sampler().clone(),
+            ),
+            WriteDescriptorSet::buffer(18, self.reflection_probes.buffer().clone()),
+            WriteDescriptorSet::image_view_sampler(
+                19,
+                self.detail_normals_image.clone(),
+                self.detail_normals_sampler.clone(),
+            ),
+            WriteDescriptorSet::buffer(20, self.chunk_states_buffer.clone()),
+            WriteDescriptorSet::buffer(21, self.lens_buffer.clone()),
+            WriteDescriptorSet::buffer(22, self.entities_buffer.clone()),
+            WriteDescriptorSet::buffer(23, self.objects_buffer.clone()),
+            WriteDescriptorSet::buffer(24, self.object_voxels_buffer.clone()),
+            WriteDescriptorSet::buffer(25, self.culled_objects_buffer.clone()),
+            WriteDescriptorSet::buffer(26, self.decals.buffer().clone()),
+            WriteDescriptorSet::buffer(27, self.layer_mask_buffer.clone()),
+            WriteDescriptorSet::buffer(28, self.clip_planes_buffer.clone()),
+            WriteDescriptorSet::image_view(29, targets.coverage.clone()),
+            WriteDescriptorSet::image_view(30, targets.depth.clone()),
+            WriteDescriptorSet::image_view(31, targets.normal.clone()),
+            WriteDescriptorSet::image_view(32, targets.material.clone()),
+        ];
+        // After the flush, which may have added pages or replaced the buffer.
+        match self.gpu_chunks.binding() {
+            ChunkBinding::Buffer => writes.push(WriteDescriptorSet::buffer(
+                1,
+                self.gpu_chunks.pages()[0].clone(),
+            )),
+            ChunkBinding::DeviceAddresses => writes.push(WriteDescriptorSet::buffer(
+                1,
+                self.gpu_chunks.addresses().unwrap().clone(),
+            )),
+            // In a set of their own, see below.
+            ChunkBinding::DescriptorArray => {}
+        }
+        let mut sets = vec![PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            desc_layout.clone(),
+            writes,
+        )
+        .unwrap()];
+        if self.gpu_chunks.binding() == ChunkBinding::DescriptorArray {
+            let pages = self.gpu_chunks.pages();
+            sets.push(
+                PersistentDescriptorSet::new_variable(
+                    &self.descriptor_set_allocator,
+                    pipeline_layout.set_layouts()[1].clone(),
+                    pages.len() as u32,
+                    [WriteDescriptorSet::buffer_array(
+                        0,
+                        0,
+                        pages.iter().cloned(),
+                    )],
+                )
+                .unwrap(),
+            );
+        }
+        let flushed = self.world.take_flushed_chunks();
+        for &chunk in &flushed {
+            self.macro_cells.mark_edited(chunk);
+        }
+        if settings.chunk_overlay {
+            let mut states = vec![CHUNK_RESIDENT; HEADER_WORDS as usize];
+            for chunk in flushed {
+                states[ChunkSlots::index(chunk)] = CHUNK_STREAMING;
+            }
+            for chunk in self.world.dirty_chunks() {
+                states[ChunkSlots::index(chunk)] = CHUNK_DIRTY;
+            }
+            builder
+                .update_buffer(self.chunk_states_buffer.clone(), states.into_boxed_slice())
+                .unwrap();
+        }
+        let world = &self.world;
+        if self.macro_cells.update(|chunk| world.chunk_occupied(chunk)) {
+            builder
+                .update_buffer(
+                    self.distance_buffer.clone(),
+                    Box::<[u32]>::from(self.macro_cells.distances()),
+                )
+                .unwrap();
+        }
+        // Culling the objects is cheap enough to count as part of the upload.
+        self.cull_objects(&mut builder, settings, img_dims);
+        self.timer.mark(&mut builder, "upload");
+
+        let mut flags = settings.flags();
+        if targets.outputs {
+            flags |= FLAG_OUTPUTS;
+        }
 
         let push_constants = cs::PushConstants {
             resolution: img_dims.into(),
-            camera_dir: [0.0, 0.0, 0.8].into(),
+            camera_dir: self.camera_dir.into(),
             rotation: self.rotation.into(),
-            position: self.position.into(),
-            render_distance: self.render_distance,
+            position: self.position,
+            render_distance: settings.render_distance,
+            max_ray_steps: settings.max_ray_steps,
+            max_bounces: settings.max_bounces,
+            flags,
+            frame: self.frame,
+            sample_index: self.samples.into(),
+            previous_rotation: self.previous_camera.1.into(),
+            previous_position: self.previous_camera.0,
+            time_of_day: self.time_of_day,
         };
-        builder
-            .bind_pipeline_compute(self.pipeline.clone())
-            .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, set)
-            .push_constants(pipeline_layout.clone(), 0, push_constants)
-            .dispatch([img_dims[0] / 16, img_dims[1] / 16, 1])
+        if settings.half_res_lighting {
+            // Primary visibility at full resolution, the expensive lighting terms at half
+            // resolution, then a depth and normal aware upsample joining both.
+            let half_tile_count = [0, 1].map(|i| img_dims[i].div_ceil(2).div_ceil(group_size[i]));
+            builder
+                .bind_pipeline_compute(pipelines.all.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    pipeline_layout.clone(),
+                    0,
+                    sets.clone(),
+                )
+                .push_constants(
+                    pipeline_layout.clone(),
+                    0,
+                    cs::PushConstants {
+                        flags: flags | FLAG_GBUFFER_PASS,
+                        ..push_constants
+                    },
+                )
+                .dispatch([tile_count[0], tile_count[1], 1])
+                .unwrap();
+            // Primary hits count as tracing, the lighting pass and upsample as lighting.
+            self.timer.mark(&mut builder, "trace");
+            builder
+                .push_constants(
+                    pipeline_layout.clone(),
+                    0,
+                    cs::PushConstants {
+                        flags: flags | FLAG_LIGHTING_PASS,
+                        ..push_constants
+                    },
+                )
+                .dispatch([half_tile_count[0], half_tile_count[1], 1])
+                .unwrap()
+                .push_constants(
+                    pipeline_layout.clone(),
+                    0,
+                    cs::PushConstants {
+                        flags: flags | FLAG_UPSAMPLE_PASS,
+                        ..push_constants
+                    },
+                )
+                .dispatch([tile_count[0], tile_count[1], 1])
+                .unwrap();
+        } else if settings.tile_classification {
+            // Sort the tiles into bins first, then trace each bin with its own specialized
+            // pipeline so tiles which only see sky skip traversal entirely.
+            builder
+                .fill_buffer(targets.tile_bins.clone().slice(0..TILE_BINS as u64), 0)
+                .unwrap()
+                .update_buffer(targets.tile_bin_args.clone(), &EMPTY_TILE_BIN_ARGS[..])
+                .unwrap()
+                .bind_pipeline_compute(pipelines.classify.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    pipeline_layout.clone(),
+                    0,
+                    sets.clone(),
+                )
+                .push_constants(pipeline_layout.clone(), 0, push_constants)
+                .dispatch([tile_count[0], tile_count[1], 1])
+                .unwrap();
+            for (bin, pipeline) in pipelines.tiles.iter().enumerate() {
+                builder
+                    .bind_pipeline_compute(pipeline.clone())
+                    .bind_descriptor_sets(
+                        PipelineBindPoint::Compute,
+                        pipeline_layout.clone(),
+                        0,
+                        sets.clone(),
+                    )
+                    .push_constants(pipeline_layout.clone(), 0, push_constants)
+                    .dispatch_indirect(
+                        targets
+                            .tile_bin_args
+                            .clone()
+                            .slice(bin as u64..bin as u64 + 1),
+                    )
+                    .unwrap();
+            }
+        } else {
+            builder
+                .bind_pipeline_compute(pipelines.all.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    pipeline_layout.clone(),
+                    0,
+                    sets.clone(),
+                )
+                .push_constants(pipeline_layout.clone(), 0, push_constants)
+                .dispatch([tile_count[0], tile_count[1], 1])
+                .unwrap();
+        }
+        let pass = if settings.half_res_lighting {
+            "lighting"
+        } else {
+            "trace"
+        };
+        self.timer.mark(&mut builder, pass);
+        self.samples += 1;
+
+        // Once every pixel has a few samples, trace one more for each pixel whose estimate is
+        // still noisy. The pixels are compacted into a queue so converged ones cost nothing.
+        if settings.accumulate && settings.adaptive_sampling && self.samples >= ADAPTIVE_MIN_SAMPLES
+        {
+            let adaptive_layout = self.trace_variants.builtin().adaptive.layout();
+            let adaptive_set = PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                adaptive_layout.set_layouts().first().unwrap().clone(),
+                [
+                    WriteDescriptorSet::image_view(0, targets.color.clone()),
+                    WriteDescriptorSet::image_view(1, targets.moments.clone()),
+                    WriteDescriptorSet::buffer(2, targets.work_queue.clone()),
+                    WriteDescriptorSet::buffer(3, targets.work_queue_args.clone()),
+                ],
+            )
             .unwrap();
+            let adaptive_push_constants = adaptive_cs::PushConstants {
+                resolution: img_dims,
+                variance_threshold: settings.variance_threshold,
+                min_samples: ADAPTIVE_MIN_SAMPLES,
+                group_pixels: group_size[0] * group_size[1],
+            };
+            builder
+                .fill_buffer(targets.work_queue.clone().slice(0..1), 0)
+                .unwrap()
+                .fill_buffer(
+                    targets
+                        .work_queue_args
+                        .clone()
+                        .try_cast_slice::<u32>()
+                        .unwrap()
+                        .slice(0..1),
+                    0,
+                )
+                .unwrap()
+                .bind_pipeline_compute(pipelines.adaptive.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    adaptive_layout.clone(),
+                    0,
+                    adaptive_set,
+                )
+                .push_constants(adaptive_layout.clone(), 0, adaptive_push_constants)
+                .dispatch([tile_count[0], tile_count[1], 1])
+                .unwrap()
+                .bind_pipeline_compute(pipelines.all.clone())
+                .bind_descriptor_sets(PipelineBindPoint::Compute, pipeline_layout.clone(), 0, sets)
+                .push_constants(
+                    pipeline_layout.clone(),
+                    0,
+                    cs::PushConstants {
+                        flags: flags | FLAG_WORK_QUEUE,
+                        ..push_constants
+                    },
+                )
+                .dispatch_indirect(targets.work_queue_args.clone())
+                .unwrap();
+            self.timer.mark(&mut builder, "adaptive");
+        }
+
+        // Reconstruct the output resolution image from the traced one and the reprojected
+        // history of previous frames.
+        if settings.upscaler == Upscaler::Temporal {
+            let upscale_layout = self.upscale_pipeline.layout();
+            let history = self.frame as usize % 2;
+            let upscale_set = PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                upscale_layout.set_layouts().first().unwrap().clone(),
+                [
+                    WriteDescriptorSet::image_view(0, targets.traced.clone()),
+                    WriteDescriptorSet::image_view(1, targets.motion.clone()),
+                    WriteDescriptorSet::image_view(2, targets.history[1 - history].clone()),
+                    WriteDescriptorSet::image_view(3, targets.history[history].clone()),
+                    WriteDescriptorSet::image_view(4, frame_image),
+                ],
+            )
+            .unwrap();
+            let upscale_push_constants = upscale_cs::PushConstants {
+                input_resolution: img_dims,
+                output_resolution: output_dims,
+                frame: self.frame,
+                reset: !self.history_valid as u32,
+            };
+            builder
+                .bind_pipeline_compute(self.upscale_pipeline.clone())
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    upscale_layout.clone(),
+                    0,
+                    upscale_set,
+                )
+                .push_constants(upscale_layout.clone(), 0, upscale_push_constants)
+                .dispatch([
+                    output_dims[0].div_ceil(TILE_SIZE),
+                    output_dims[1].div_ceil(TILE_SIZE),
+                    1,
+                ])
+                .unwrap();
+            self.timer.mark(&mut builder, "upscale");
+            self.history_valid = true;
+        } else {
+            self.history_valid = false;
+        }
+
+        // Without the temporal upscaler the frame only covers the traced extent of the image,
+        // which is stretched when it is drawn.
+        let frame_extent = if settings.upscaler == Upscaler::Temporal {
+            output_dims
+        } else {
+            img_dims
+        };
+        if let Some(post_process) = &self.post_process {
+            // A texel of sky each when the outputs were turned off again after loading the
+            // shader.
+            let outputs = self.outputs().unwrap_or_else(|| targets.outputs());
+            post_process.record(
+                &mut builder,
+                &self.descriptor_set_allocator,
+                targets.post_input.clone(),
+                image.clone(),
+                outputs,
+                post_process::PushConstants {
+                    resolution: frame_extent,
+                    trace_resolution: img_dims,
+                    time: self.started.elapsed().as_secs_f32(),
+                    frame: self.frame,
+                },
+            );
+            self.timer.mark(&mut builder, "post");
+        }
+        if self.screenshot_request {
+            self.record_screenshot(&mut builder, &image, frame_extent);
+            self.screenshot_request = false;
+        }
+        if self.hdr_screenshot_request {
+            self.record_hdr_screenshot(&mut builder);
+            self.hdr_screenshot_request = false;
+        }
+        self.previous_camera = (self.position, self.rotation);
+        self.frame = self.frame.wrapping_add(1);
+
         let command_buffer = builder.build().unwrap();
-        let finished = command_buffer.execute(self.queue.clone()).unwrap();
-        finished.then_signal_fence_and_flush().unwrap().boxed()
+        let future = match landed {
+            // Waits for the copies into device local memory through a semaphore, and for
+            // nothing else the transfer queue does.
+            Some(landed) => landed
+                .then_execute(self.queue.clone(), command_buffer)
+                .unwrap()
+                .then_signal_fence_and_flush()
+                .unwrap()
+                .join(before)
+                .boxed(),
+            None => command_buffer
+                .execute(self.queue.clone())
+                .unwrap()
+                .then_signal_fence_and_flush()
+                .unwrap()
+                .join(before)
+                .boxed(),
+        };
+        if let Some(transfer_queue) = self.transfer_queue.clone() {
+            if self.terrain_readback.is_none() {
+                self.stage_landing(transfer_queue, eye, forward);
+            }
+        }
+        future
+    }
+
+    /// Stages the chunks the next frame uploads, seen from `eye` looking along `forward`, and
+    /// submits copying them into device local memory on `transfer_queue`. The copies run
+    /// alongside the frame just submitted, and edits reach the GPU a frame later than without
+    /// a transfer queue.
+    fn stage_landing(&mut self, transfer_queue: Arc<Queue>, eye: [f32; 3], forward: [f32; 3]) {
+        let Some(staged) = self
+            .world
+            .stage(&self.memory_allocator, &self.gpu_chunks, eye, forward)
+        else {
+            return;
+        };
+        let mut builder = AutoCommandBufferBuilder::primary(
+            self.command_buffer_allocator.as_ref(),
+            transfer_queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        staged.record_landing(&mut builder);
+        let future = sync::now(self.queue.device().clone())
+            .then_execute(transfer_queue, builder.build().unwrap())
+            .unwrap()
+            .then_signal_semaphore_and_flush()
+            .unwrap()
+            .boxed_send_sync();
+        self.landing = Some((staged, future));
+    }
+
+    /// Records the culling pass writing the objects rays can meet this frame to
+    /// `culled_objects_buffer`: those in view and within `Settings::object_distance` for camera
+    /// rays, those within it for the others. Objects past `Settings::impostor_distance` are left
+    /// to the graphics pass, those on `Settings::hidden_layers` out.
+    fn cull_objects(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        settings: &Settings,
+        img_dims: [u32; 2],
+    ) {
+        builder
+            .fill_buffer(
+                self.culled_objects_buffer
+                    .clone()
+                    .slice(0..CULLED_HEADER_WORDS),
+                0,
+            )
+            .unwrap();
+        if self.objects.is_empty() {
+            return;
+        }
+        let layout = self.cull_pipeline.layout();
+        let set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            layout.set_layouts().first().unwrap().clone(),
+            [
+                WriteDescriptorSet::buffer(0, self.objects_buffer.clone()),
+                WriteDescriptorSet::buffer(1, self.culled_objects_buffer.clone()),
+            ],
+        )
+        .unwrap();
+        let push_constants = cull_objects_cs::PushConstants {
+            camera_dir: self.camera_dir,
+            aspect: img_dims[1] as f32 / img_dims[0] as f32,
+            rotation: self.rotation,
+            cull_distance: settings.object_distance as f32,
+            position: self.position,
+            margin: self.lens.aperture + 1.0,
+            impostor_distance: settings.impostor_distance as f32,
+            hidden_layers: settings.hidden_layers,
+        };
+        builder
+            .bind_pipeline_compute(self.cull_pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, layout.clone(), 0, set)
+            .push_constants(layout.clone(), 0, push_constants)
+            .dispatch([(self.objects.len() as u32).div_ceil(CULL_GROUP_SIZE), 1, 1])
+            .unwrap();
+    }
+
+    /// Records copying the `extent` of the finished frame in `image` into a new readback buffer.
+    fn record_screenshot(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        image: &DeviceImageView,
+        extent: [u32; 2],
+    ) {
+        let readback = Buffer::new_slice(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Download,
+                ..Default::default()
+            },
+            extent[0] as u64 * extent[1] as u64 * 4,
+        )
+        .unwrap();
+        let image = image.image().clone();
+        let region = BufferImageCopy {
+            image_subresource: image.subresource_layers(),
+            image_extent: [extent[0], extent[1], 1],
+            ..Default::default()
+        };
+        builder
+            .copy_image_to_buffer(CopyImageToBufferInfo {
+                regions: [region].into(),
+                ..CopyImageToBufferInfo::image_buffer(image, readback.clone())
+            })
+            .unwrap();
+        self.screenshot_readback = Some((readback, extent));
+    }
+
+    /// Records copying the accumulated samples of the trace targets, and their coverage for a
+    /// transparent sky, into new readback buffers.
+    fn record_hdr_screenshot(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        let targets = self.targets.as_ref().unwrap();
+        let extent = targets.extent;
+        let mut copy = |image: &DeviceImageView, texel_bytes: u64| {
+            let readback = Buffer::new_slice(
+                &self.memory_allocator,
+                BufferCreateInfo {
+                    usage: BufferUsage::TRANSFER_DST,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    usage: MemoryUsage::Download,
+                    ..Default::default()
+                },
+                extent[0] as u64 * extent[1] as u64 * texel_bytes,
+            )
+            .unwrap();
+            let image = image.image().clone();
+            let region = BufferImageCopy {
+                image_subresource: image.subresource_layers(),
+                image_extent: [extent[0], extent[1], 1],
+                ..Default::default()
+            };
+            builder
+                .copy_image_to_buffer(CopyImageToBufferInfo {
+                    regions: [region].into(),
+                    ..CopyImageToBufferInfo::image_buffer(image, readback.clone())
+                })
+                .unwrap();
+            readback
+        };
+        let readback = copy(
+            &targets.color,
+            if targets.half_accumulation { 8 } else { 16 },
+        );
+        let coverage = self
+            .hdr_screenshot_sky_alpha
+            .then(|| copy(&targets.coverage, 4));
+        self.hdr_screenshot_readback =
+            Some((readback, coverage, extent, targets.half_accumulation));
+    }
+
+    /// Records generating `terrain` into a new readback buffer, which is returned, unpacked so
+    /// the CPU copy can take it as it is: the heightmap is drawn, eroded a step per dispatch, then
+    /// voxelized.
+    fn record_terrain(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        terrain: Terrain,
+    ) -> Subbuffer<[u32]> {
+        let readback = Buffer::new_slice(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Download,
+                ..Default::default()
+            },
+            (WORLD_SIZE * WORLD_SIZE * WORLD_SIZE) as u64,
+        )
+        .unwrap();
+        let heights = Buffer::new_slice::<f32>(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+            // Read from one heightmap and eroded into the other.
+            2 * (WORLD_SIZE * WORLD_SIZE) as u64,
+        )
+        .unwrap();
+        let layout = self.worldgen_pipeline.layout();
+        let set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            layout.set_layouts()[0].clone(),
+            [
+                WriteDescriptorSet::buffer(0, readback.clone()),
+                WriteDescriptorSet::buffer(1, heights),
+            ],
+        )
+        .unwrap();
+        let push_constants = |pass, iteration| worldgen_cs::PushConstants {
+            seed: terrain.seed,
+            world_size: WORLD_SIZE,
+            frequency: terrain.frequency,
+            octaves: terrain.octaves,
+            amplitude: terrain.amplitude,
+            warp: terrain.warp,
+            base_height: terrain.base_height,
+            water_level: terrain.water_level,
+            pass,
+            iteration,
+            talus: terrain.talus,
+            caves: terrain.caves,
+        };
+        let columns = [
+            WORLD_SIZE / WORLDGEN_GROUP_SIZE,
+            1,
+            WORLD_SIZE / WORLDGEN_GROUP_SIZE,
+        ];
+        builder
+            .bind_pipeline_compute(self.worldgen_pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, layout.clone(), 0, set)
+            .push_constants(layout.clone(), 0, push_constants(WORLDGEN_PASS_HEIGHTS, 0))
+            .dispatch(columns)
+            .unwrap();
+        for iteration in 0..terrain.erosion {
+            builder
+                .push_constants(
+                    layout.clone(),
+                    0,
+                    push_constants(WORLDGEN_PASS_EROSION, iteration),
+                )
+                .dispatch(columns)
+                .unwrap();
+        }
+        builder
+            .push_constants(
+                layout.clone(),
+                0,
+                push_constants(WORLDGEN_PASS_VOXELS, terrain.erosion),
+            )
+            .dispatch([WORLD_SIZE / WORLDGEN_GROUP_SIZE; 3])
+            .unwrap();
+        readback
+    }
+}
+
+/// Creates a device local buffer of `words` words to copy the light bake or objects into.
+pub fn word_buffer(memory_allocator: &StandardMemoryAllocator, words: u64) -> Subbuffer<[u32]> {
+    Buffer::new_slice(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::DeviceOnly,
+            ..Default::default()
+        },
+        words,
+    )
+    .unwrap()
+}
+
+/// Records copying `words` to the start of `buffer` through a staging buffer.
+fn copy_words(
+    memory_allocator: &StandardMemoryAllocator,
+    builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    words: Vec<u32>,
+    buffer: Subbuffer<[u32]>,
+) {
+    let staging = Buffer::from_iter(
+        memory_allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Upload,
+            ..Default::default()
+        },
+        words,
+    )
+    .unwrap();
+    builder
+        .copy_buffer(CopyBufferInfo::buffers(staging, buffer))
+        .unwrap();
+}
+
+/// Creates the RGBA8 image of `size` texels the detail normal map or the impostor atlas is
+/// copied into.
+pub fn texture_image(
+    memory_allocator: &StandardMemoryAllocator,
+    queue: &Arc<Queue>,
+    size: [u32; 2],
+) -> Arc<ImageView<StorageImage>> {
+    ImageView::new_default(
+        StorageImage::with_usage(
+            memory_allocator,
+            ImageDimensions::Dim2d {
+                width: size[0],
+                height: size[1],
+                array_layers: 1,
+            },
+            Format::R8G8B8A8_UNORM,
+            ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+            ImageCreateFlags::empty(),
+            [queue.queue_family_index()],
+        )
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+mod upscale_cs {
+    vulkano_shaders::shader! {
+         ty: "compute",
+         path: "./assets/shader/upscale.glsl"
+    }
+}
+
+mod worldgen_cs {
+    vulkano_shaders::shader! {
+         ty: "compute",
+         path: "./assets/shader/worldgen.glsl"
     }
 }
 
-mod cs {
+mod cull_objects_cs {
     vulkano_shaders::shader! {
          ty: "compute",
-         path: "./assets/shader/compute.glsl"
+         path: "./assets/shader/cull_objects.glsl"
     }
 }
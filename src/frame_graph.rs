@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+
+/// Number of frames the graph spans.
+pub const GRAPH_FRAMES: usize = 240;
+
+/// GPU passes the breakdown above the graph shows, one row each from the top, see the marks in
+/// `Controller::compute`.
+pub const PASSES: [&str; 6] = ["upload", "trace", "lighting", "adaptive", "upscale", "post"];
+
+/// Milliseconds a frame may take to keep up with a 60 Hz display.
+pub const FRAME_BUDGET: f32 = 1000.0 / 60.0;
+
+/// Bit set in `FrameSample::markers` when the swapchain was recreated before the frame.
+pub const MARKER_SWAPCHAIN: u32 = 1 << 0;
+/// Bit set in `FrameSample::markers` when the frame uploaded world chunks.
+pub const MARKER_UPLOAD: u32 = 1 << 1;
+/// Bit set in `FrameSample::markers` when the frame recreated the trace targets.
+pub const MARKER_TARGETS: u32 = 1 << 2;
+
+/// Where the time of one frame went.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameSample {
+    /// Milliseconds from handling input to submitting the frame.
+    pub cpu: f32,
+    /// Milliseconds the GPU passes of the latest finished frame took.
+    pub gpu: f32,
+    /// `MARKER_*` bits of what happened during the frame.
+    pub markers: u32,
+}
+
+/// Frame times of the last `GRAPH_FRAMES` frames, oldest first.
+pub struct FrameGraph {
+    samples: VecDeque<FrameSample>,
+    /// Fraction of the VRAM budget in use.
+    memory_usage: f32,
+    /// Milliseconds each of `PASSES` took in the latest finished frame, smoothed over frames.
+    pass_times: [f32; PASSES.len()],
+}
+
+impl FrameGraph {
+    pub fn new() -> FrameGraph {
+        FrameGraph {
+            samples: VecDeque::from(vec![FrameSample::default(); GRAPH_FRAMES]),
+            memory_usage: 0.0,
+            pass_times: [0.0; PASSES.len()],
+        }
+    }
+
+    /// Adds the newest frame, dropping the oldest.
+    pub fn push(&mut self, sample: FrameSample) {
+        self.samples.pop_front();
+        self.samples.push_back(sample);
+    }
+
+    /// Returns the samples, oldest first.
+    pub fn samples(&self) -> impl ExactSizeIterator<Item = &FrameSample> {
+        self.samples.iter()
+    }
+
+    /// Sets the fraction of the VRAM budget in use, shown as a bar above the graph.
+    pub fn set_memory_usage(&mut self, fraction: f32) {
+        self.memory_usage = fraction;
+    }
+
+    pub fn memory_usage(&self) -> f32 {
+        self.memory_usage
+    }
+
+    /// Adds the GPU pass timings of the latest finished frame. Passes which didn't run fall to
+    /// zero over a few frames.
+    pub fn set_pass_timings(&mut self, timings: &[(&'static str, f32)]) {
+        for (name, time) in PASSES.iter().zip(&mut self.pass_times) {
+            let latest: f32 = timings
+                .iter()
+                .filter(|(pass, _)| pass == name)
+                .map(|(_, time)| time)
+                .sum();
+            // Averages over roughly ten frames so the numbers are readable.
+            *time += (latest - *time) * 0.1;
+        }
+    }
+
+    /// Returns the milliseconds each of `PASSES` takes.
+    pub fn pass_times(&self) -> [f32; PASSES.len()] {
+        self.pass_times
+    }
+
+    /// Describes the passes taking time and their share of the frame budget, like
+    /// `trace 6.20ms (37%) upscale 0.40ms (2%)`.
+    pub fn pass_summary(&self) -> String {
+        PASSES
+            .iter()
+            .zip(self.pass_times)
+            .filter(|&(_, time)| time >= 0.005)
+            .map(|(name, time)| format!("{name} {time:.2}ms ({:.0}%)", time / FRAME_BUDGET * 100.0))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Returns the frame time at the top of the graph, a multiple of a 60 Hz frame fitting the
+    /// slowest frame and at least two of them.
+    pub fn scale(&self) -> f32 {
+        let slowest = self
+            .samples
+            .iter()
+            .map(|sample| sample.cpu.max(sample.gpu))
+            .fold(0.0, f32::max);
+        ((slowest / FRAME_BUDGET).ceil().max(2.0)) * FRAME_BUDGET
+    }
+}
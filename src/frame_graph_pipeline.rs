@@ -0,0 +1,343 @@
+use crate::{
+    frame_graph::{FrameGraph, GRAPH_FRAMES},
+    pixels_draw_pipeline::{textured_quad, TexturedVertex},
+};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        CommandBufferInheritanceInfo, CommandBufferUsage, SecondaryAutoCommandBuffer,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Queue,
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            color_blend::ColorBlendState,
+            input_assembly::InputAssemblyState,
+            vertex_input::Vertex,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::Subpass,
+};
+
+/// Size of the graph in pixels, with the pass breakdown and VRAM bar above it.
+const GRAPH_SIZE: [f32; 2] = [480.0, 180.0];
+/// Distance of the graph from the bottom left corner of the frame in pixels.
+const GRAPH_MARGIN: f32 = 8.0;
+
+/// Layout of `Sample` in the fragment shader.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+struct GpuSample {
+    cpu: f32,
+    gpu: f32,
+    markers: u32,
+    padding: u32,
+}
+
+/// A subpass pipeline drawing the frame graph as a translucent box in the bottom left corner,
+/// below a bar per GPU pass showing its share of a 60 Hz frame.
+pub struct FrameGraphPipeline {
+    gfx_queue: Arc<Queue>,
+    subpass: Subpass,
+    pipeline: Arc<GraphicsPipeline>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    vertices: Subbuffer<[TexturedVertex]>,
+    indices: Subbuffer<[u32]>,
+}
+
+impl FrameGraphPipeline {
+    pub fn new(
+        gfx_queue: Arc<Queue>,
+        subpass: Subpass,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    ) -> FrameGraphPipeline {
+        let (vertices, indices) = textured_quad(2.0, 2.0);
+        let vertex_buffer = Buffer::from_iter(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            vertices,
+        )
+        .unwrap();
+        let index_buffer = Buffer::from_iter(
+            &memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::INDEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            indices,
+        )
+        .unwrap();
+
+        let pipeline = {
+            let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+            let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+            GraphicsPipeline::start()
+                .vertex_input_state(TexturedVertex::per_vertex())
+                .vertex_shader(vs.entry_point("main").unwrap(), ())
+                .input_assembly_state(InputAssemblyState::new())
+                .fragment_shader(fs.entry_point("main").unwrap(), ())
+                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .color_blend_state(
+                    ColorBlendState::new(subpass.num_color_attachments()).blend_alpha(),
+                )
+                .render_pass(subpass.clone())
+                .build(gfx_queue.device().clone())
+                .unwrap()
+        };
+
+        FrameGraphPipeline {
+            gfx_queue,
+            subpass,
+            pipeline,
+            memory_allocator,
+            command_buffer_allocator,
+            descriptor_set_allocator,
+            vertices: vertex_buffer,
+            indices: index_buffer,
+        }
+    }
+
+    /// Draws `graph` into the bottom left corner of a frame of `viewport_dimensions`.
+    pub fn draw(
+        &self,
+        viewport_dimensions: [u32; 2],
+        graph: &FrameGraph,
+    ) -> SecondaryAutoCommandBuffer {
+        let mut builder = AutoCommandBufferBuilder::secondary(
+            &self.command_buffer_allocator,
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::MultipleSubmit,
+            CommandBufferInheritanceInfo {
+                render_pass: Some(self.subpass.clone().into()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let samples = Buffer::from_iter(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            graph.samples().map(|sample| GpuSample {
+                cpu: sample.cpu,
+                gpu: sample.gpu,
+                markers: sample.markers,
+                padding: 0,
+            }),
+        )
+        .unwrap();
+        let desc_set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            self.pipeline
+                .layout()
+                .set_layouts()
+                .first()
+                .unwrap()
+                .clone(),
+            [WriteDescriptorSet::buffer(0, samples)],
+        )
+        .unwrap();
+        let push_constants = fs::PushConstants {
+            count: GRAPH_FRAMES as u32,
+            scale: graph.scale(),
+            memory_usage: graph.memory_usage(),
+            passes: graph.pass_times(),
+        };
+        // Shrinks to fit small windows, the graph stays readable down to half its size.
+        let size = [
+            GRAPH_SIZE[0].min(viewport_dimensions[0] as f32 - 2.0 * GRAPH_MARGIN),
+            GRAPH_SIZE[1].min(viewport_dimensions[1] as f32 / 2.0),
+        ];
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    origin: [
+                        GRAPH_MARGIN,
+                        viewport_dimensions[1] as f32 - GRAPH_MARGIN - size[1],
+                    ],
+                    dimensions: size.map(|d| d.max(1.0)),
+                    depth_range: 0.0..1.0,
+                }],
+            )
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                desc_set,
+            )
+            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+            .bind_vertex_buffers(0, self.vertices.clone())
+            .bind_index_buffer(self.indices.clone())
+            .draw_indexed(self.indices.len() as u32, 1, 0, 0, 0)
+            .unwrap();
+        builder.build().unwrap()
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+            layout(location=0) in vec2 position;
+            layout(location=1) in vec2 tex_coords;
+
+            layout(location = 0) out vec2 f_tex_coords;
+
+            void main() {
+                gl_Position =  vec4(position, 0.0, 1.0);
+                f_tex_coords = tex_coords;
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+            layout(location = 0) in vec2 v_tex_coords;
+
+            layout(location = 0) out vec4 f_color;
+
+            struct Sample {
+                float cpu;
+                float gpu;
+                uint markers;
+                uint padding;
+            };
+
+            layout(set = 0, binding = 0) readonly buffer Samples {
+                Sample samples[];
+            };
+
+            const uint PASS_COUNT = 6;
+
+            layout(push_constant) uniform PushConstants {
+                uint count;
+                // Milliseconds at the top of the graph.
+                float scale;
+                // Fraction of the VRAM budget in use.
+                float memory_usage;
+                // Milliseconds of each pass in `PASSES` of `frame_graph.rs`.
+                float passes[PASS_COUNT];
+            } constants;
+
+            // Same bits as `MARKER_*` in `frame_graph.rs`.
+            const uint MARKER_SWAPCHAIN = 1;
+            const uint MARKER_UPLOAD = 2;
+            const uint MARKER_TARGETS = 4;
+
+            const float FRAME_60HZ = 1000.0 / 60.0;
+            // Height of the VRAM usage bar at the top as a fraction of the whole.
+            const float MEMORY_BAR = 0.04;
+            // Height of the pass breakdown below it.
+            const float PASS_SECTION = 0.3;
+            // Upload, trace, lighting, adaptive, upscale and post.
+            const vec3 PASS_COLORS[PASS_COUNT] = vec3[](
+                vec3(0.2, 0.4, 1.0),
+                vec3(0.2, 0.9, 0.3),
+                vec3(1.0, 0.85, 0.2),
+                vec3(0.3, 0.9, 0.9),
+                vec3(0.8, 0.4, 1.0),
+                vec3(1.0, 0.5, 0.7)
+            );
+
+            // Draws one bar per pass, the whole width being a 60 Hz frame. Passes taking more
+            // than that are red past the end.
+            vec4 passBreakdown(float y) {
+                float rows = y / PASS_SECTION * float(PASS_COUNT);
+                uint row = min(uint(rows), PASS_COUNT - 1);
+                float fraction = constants.passes[row] / FRAME_60HZ;
+                if (fract(rows) < 0.2) {
+                    return vec4(0.0, 0.0, 0.0, 0.7);
+                }
+                if (v_tex_coords.x < fraction) {
+                    return fraction > 1.0 ? vec4(1.0, 0.2, 0.2, 0.9) : vec4(PASS_COLORS[row], 0.9);
+                }
+                // Ticks at every quarter of the budget.
+                float quarter = mod(v_tex_coords.x + 0.125, 0.25) - 0.125;
+                return abs(quarter) < fwidth(v_tex_coords.x) ? vec4(0.5, 0.5, 0.5, 0.7) : vec4(0.0, 0.0, 0.0, 0.6);
+            }
+
+            void main() {
+                if (v_tex_coords.y > 1.0 - MEMORY_BAR) {
+                    vec4 used = constants.memory_usage > 0.9
+                        ? vec4(1.0, 0.2, 0.2, 0.9)
+                        : vec4(0.3, 0.6, 1.0, 0.9);
+                    f_color = v_tex_coords.x < constants.memory_usage ? used : vec4(0.0, 0.0, 0.0, 0.7);
+                    return;
+                }
+                float graph_top = 1.0 - MEMORY_BAR - PASS_SECTION;
+                if (v_tex_coords.y > graph_top) {
+                    f_color = passBreakdown(1.0 - MEMORY_BAR - v_tex_coords.y);
+                    return;
+                }
+                Sample s = samples[min(uint(v_tex_coords.x * constants.count), constants.count - 1)];
+                float ms = v_tex_coords.y / graph_top * constants.scale;
+                vec4 color = vec4(0.0, 0.0, 0.0, 0.5);
+
+                // Markers tint the whole column, swapchain over targets over uploads.
+                if ((s.markers & MARKER_UPLOAD) != 0) {
+                    color = vec4(0.2, 0.4, 1.0, 0.5);
+                }
+                if ((s.markers & MARKER_TARGETS) != 0) {
+                    color = vec4(1.0, 0.2, 1.0, 0.6);
+                }
+                if ((s.markers & MARKER_SWAPCHAIN) != 0) {
+                    color = vec4(1.0, 0.2, 0.2, 0.6);
+                }
+
+                // The shorter bar is drawn in front of the longer one.
+                bool in_cpu = ms < s.cpu;
+                bool in_gpu = ms < s.gpu;
+                vec4 cpu_color = vec4(1.0, 0.6, 0.1, 0.9);
+                vec4 gpu_color = vec4(0.2, 0.9, 0.3, 0.9);
+                if (in_cpu && in_gpu) {
+                    color = s.cpu < s.gpu ? cpu_color : gpu_color;
+                } else if (in_cpu) {
+                    color = cpu_color;
+                } else if (in_gpu) {
+                    color = gpu_color;
+                }
+
+                // Guides at every 60 Hz frame.
+                float guide = mod(ms + 0.5 * FRAME_60HZ, FRAME_60HZ) - 0.5 * FRAME_60HZ;
+                if (abs(guide) < fwidth(ms) && ms > 0.5 * FRAME_60HZ) {
+                    color = mix(color, vec4(1.0), 0.5);
+                }
+                f_color = color;
+            }
+        ",
+    }
+}
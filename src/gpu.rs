@@ -0,0 +1,256 @@
+use crate::context::Context;
+use rvengine::chunk_palette::MAX_PAGES;
+use vulkano::{
+    device::{
+        physical::{PhysicalDevice, PhysicalDeviceType},
+        DeviceExtensions, Features,
+    },
+    format::{Format, FormatFeatures},
+    instance::{Instance, InstanceCreateInfo},
+    memory::MemoryHeapFlags,
+    Version, VulkanLibrary, VulkanObject,
+};
+
+/// Formats of the storage images the trace and post-process shaders write.
+const STORAGE_FORMATS: [Format; 4] = [
+    Format::R8G8B8A8_UNORM,
+    Format::R16G16B16A16_SFLOAT,
+    Format::R32G32B32A32_SFLOAT,
+    Format::R32_SFLOAT,
+];
+
+/// Returns whether `physical_device` is the one asked for with `--compute-gpu`: `discrete` and
+/// `integrated` match the device type, anything else a part of the device name, ignoring case.
+pub fn matches(physical_device: &PhysicalDevice, selector: &str) -> bool {
+    let properties = physical_device.properties();
+    match selector.to_ascii_lowercase().as_str() {
+        "discrete" => properties.device_type == PhysicalDeviceType::DiscreteGpu,
+        "integrated" => properties.device_type == PhysicalDeviceType::IntegratedGpu,
+        selector => properties
+            .device_name
+            .to_ascii_lowercase()
+            .contains(selector),
+    }
+}
+
+/// Ranks devices for `Context::new`, lowest first: the one matching
+/// `selector`, then discrete, integrated, virtual and CPU devices.
+pub fn priority(physical_device: &PhysicalDevice, selector: Option<&str>) -> u32 {
+    if selector.is_some_and(|selector| matches(physical_device, selector)) {
+        return 0;
+    }
+    match physical_device.properties().device_type {
+        PhysicalDeviceType::DiscreteGpu => 1,
+        PhysicalDeviceType::IntegratedGpu => 2,
+        PhysicalDeviceType::VirtualGpu => 3,
+        PhysicalDeviceType::Cpu => 4,
+        _ => 5,
+    }
+}
+
+/// Features waiting for a frame to reach the display, see `WindowRenderer::wait_presented`.
+const PRESENT_WAIT: Features = Features {
+    present_id: true,
+    present_wait: true,
+    ..Features::empty()
+};
+
+/// Returns whether `physical_device` can tell when a presented frame reached the display.
+fn supports_present_wait(physical_device: &PhysicalDevice) -> bool {
+    let extensions = physical_device.supported_extensions();
+    extensions.khr_present_id
+        && extensions.khr_present_wait
+        && physical_device.supported_features().contains(&PRESENT_WAIT)
+}
+
+/// Returns the extensions to enable on `physical_device`: the swapchain, and the ones behind
+/// the features of `optional_features` it supports.
+pub fn enabled_extensions(physical_device: &PhysicalDevice) -> DeviceExtensions {
+    let present_wait = supports_present_wait(physical_device);
+    DeviceExtensions {
+        khr_swapchain: true,
+        khr_present_id: present_wait,
+        khr_present_wait: present_wait,
+        ..DeviceExtensions::empty()
+    }
+}
+
+/// Returns the features worth enabling on `physical_device` beyond the ones every device has:
+/// waiting for presents to reach the display, pacing `--low-latency`, buffer device addresses
+/// reading the world's pages through a table of their addresses, see
+/// `ChunkBinding::DeviceAddresses`, and the descriptor indexing ones binding them as an array
+/// instead, see `ChunkBinding::DescriptorArray`, when it supports all of them and binds as many
+/// pages.
+pub fn optional_features(physical_device: &PhysicalDevice) -> Features {
+    let present_wait = if supports_present_wait(physical_device) {
+        PRESENT_WAIT
+    } else {
+        Features::empty()
+    };
+    if physical_device.api_version() < Version::V1_2 {
+        return present_wait;
+    }
+    let buffer_device_address = Features {
+        buffer_device_address: true,
+        ..Features::empty()
+    };
+    let properties = physical_device.properties();
+    // Room for the trace shader's other buffers too.
+    let storage_buffers = MAX_PAGES + 32;
+    let descriptor_indexing = Features {
+        runtime_descriptor_array: true,
+        shader_storage_buffer_array_non_uniform_indexing: true,
+        descriptor_binding_variable_descriptor_count: true,
+        ..Features::empty()
+    };
+    let supported = physical_device.supported_features();
+    let mut features = present_wait;
+    if supported.contains(&buffer_device_address) {
+        features = features.union(&buffer_device_address);
+    }
+    if supported.contains(&descriptor_indexing)
+        && properties.max_per_stage_descriptor_storage_buffers >= storage_buffers
+        && properties.max_descriptor_set_storage_buffers >= storage_buffers
+    {
+        features = features.union(&descriptor_indexing);
+    }
+    features
+}
+
+/// Prints which device tracing and presenting run on when there is a choice, warning when it
+/// isn't the one asked for.
+pub fn report_topology(context: &Context, selector: Option<&str>) {
+    let chosen = context.device().physical_device();
+    let devices: Vec<_> = context
+        .instance()
+        .enumerate_physical_devices()
+        .map(|devices| devices.collect())
+        .unwrap_or_default();
+    if let Some(selector) = selector {
+        if !matches(chosen, selector) {
+            println!("no usable GPU matches `{selector}`");
+        }
+    } else if devices.len() < 2 {
+        return;
+    }
+    println!("GPUs:");
+    for device in &devices {
+        let properties = device.properties();
+        // Identical cards share a name, so the chosen one is told apart by its handle.
+        let marker = if device.handle() == chosen.handle() {
+            '*'
+        } else {
+            ' '
+        };
+        println!(
+            " {marker} {} ({:?})",
+            properties.device_name, properties.device_type
+        );
+    }
+    // The traced image is sampled straight from the compute target when presenting, so both
+    // share a device. A monitor attached to another GPU gets its frames copied by the driver.
+    println!(
+        "compute and present on {}, connect the display to it to avoid cross GPU copies",
+        chosen.properties().device_name
+    );
+}
+
+/// Prints the Vulkan version and what every device offers that the renderer relies on, for
+/// `rvengine info` and bug reports.
+pub fn print_info() -> Result<(), String> {
+    let library = VulkanLibrary::new().map_err(|err| format!("can't load Vulkan: {err}"))?;
+    println!("Vulkan {}", version(library.api_version()));
+    let instance = Instance::new(
+        library,
+        InstanceCreateInfo {
+            enumerate_portability: true,
+            ..Default::default()
+        },
+    )
+    .map_err(|err| format!("can't create a Vulkan instance: {err}"))?;
+    let devices = instance
+        .enumerate_physical_devices()
+        .map_err(|err| format!("can't list GPUs: {err}"))?;
+    for device in devices {
+        let properties = device.properties();
+        println!();
+        println!("{} ({:?})", properties.device_name, properties.device_type);
+        println!(
+            "  vendor {:#06x} device {:#06x}, api {}, driver {} {:#x}{}",
+            properties.vendor_id,
+            properties.device_id,
+            version(properties.api_version),
+            properties.driver_name.as_deref().unwrap_or("?"),
+            properties.driver_version,
+            properties
+                .driver_info
+                .as_deref()
+                .map_or(String::new(), |info| format!(" ({info})")),
+        );
+        println!(
+            "  push constants {} bytes, compute shared memory {} bytes, workgroup size {:?} up \
+             to {} invocations",
+            properties.max_push_constants_size,
+            properties.max_compute_shared_memory_size,
+            properties.max_compute_work_group_size,
+            properties.max_compute_work_group_invocations,
+        );
+        println!(
+            "  storage buffers up to {} bytes, 2D images up to {} texels, {} descriptor sets",
+            properties.max_storage_buffer_range,
+            properties.max_image_dimension2_d,
+            properties.max_bound_descriptor_sets,
+        );
+        let subgroup = |size: Option<u32>| size.map_or("?".to_string(), |size| size.to_string());
+        println!(
+            "  subgroup size {} ({} to {})",
+            subgroup(properties.subgroup_size),
+            subgroup(properties.min_subgroup_size),
+            subgroup(properties.max_subgroup_size),
+        );
+        let extensions = device.supported_extensions();
+        // Presenting, pacing with `--low-latency` and the subgroup sizes above.
+        let relevant = [
+            ("VK_KHR_swapchain", extensions.khr_swapchain),
+            ("VK_KHR_present_id", extensions.khr_present_id),
+            ("VK_KHR_present_wait", extensions.khr_present_wait),
+            (
+                "VK_EXT_subgroup_size_control",
+                extensions.ext_subgroup_size_control,
+            ),
+        ];
+        for (name, supported) in relevant {
+            println!("  {name}: {}", if supported { "yes" } else { "no" });
+        }
+        for format in STORAGE_FORMATS {
+            let storage = device.format_properties(format).is_ok_and(|properties| {
+                properties
+                    .optimal_tiling_features
+                    .intersects(FormatFeatures::STORAGE_IMAGE)
+            });
+            println!(
+                "  {format:?} storage image: {}",
+                if storage { "yes" } else { "no" }
+            );
+        }
+        for (index, family) in device.queue_family_properties().iter().enumerate() {
+            println!(
+                "  queue family {index}: {} queues, {:?}",
+                family.queue_count, family.queue_flags
+            );
+        }
+        for heap in &device.memory_properties().memory_heaps {
+            let kind = if heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL) {
+                "device local"
+            } else {
+                "host"
+            };
+            println!("  {kind} heap of {} MiB", heap.size >> 20);
+        }
+    }
+    Ok(())
+}
+
+fn version(version: Version) -> String {
+    format!("{}.{}.{}", version.major, version.minor, version.patch)
+}
@@ -0,0 +1,213 @@
+use crate::chunk_palette::{MAX_PAGES, PAGE_BITS, PAGE_WORDS};
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, BufferCopy, CopyBufferInfoTyped, PrimaryAutoCommandBuffer,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+};
+
+/// How the trace shader reaches the GPU copy of the world, the best way the device supports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChunkBinding {
+    /// A single buffer, replaced with a larger copy of itself once the chunks outgrow it.
+    Buffer,
+    /// Pages of `PAGE_WORDS` words bound once as a runtime descriptor array and indexed in the
+    /// shader. Pages are added as the chunks need them, without copying the others. Needs
+    /// descriptor indexing.
+    DescriptorArray,
+    /// Pages like `DescriptorArray`, read through their device addresses in a table of
+    /// `MAX_PAGES` bound as a single buffer. Needs buffer device addresses.
+    DeviceAddresses,
+}
+
+/// GPU copy of a world's packed chunks, see `World::flush`, laid out the way `binding` reads
+/// it. Offsets into it count words from the start of the first page, which holds the headers.
+/// Only the queue tracing it writes it, uploads on a transfer queue land elsewhere first, see
+/// `StagingRing`.
+pub struct GpuChunks {
+    binding: ChunkBinding,
+    pages: Vec<Subbuffer<[u32]>>,
+    /// Device addresses of the pages with `ChunkBinding::DeviceAddresses`, see
+    /// `page_addresses`.
+    addresses: Option<Subbuffer<[[u32; 2]]>>,
+}
+
+impl GpuChunks {
+    /// Creates a copy holding at least `words` words, cleared by the first flush into it.
+    pub fn new(
+        memory_allocator: &StandardMemoryAllocator,
+        binding: ChunkBinding,
+        words: u32,
+    ) -> GpuChunks {
+        let mut chunks = GpuChunks {
+            binding,
+            pages: Vec::new(),
+            addresses: None,
+        };
+        chunks.pages = match binding {
+            ChunkBinding::Buffer => vec![chunks.buffer(memory_allocator, words)],
+            ChunkBinding::DescriptorArray | ChunkBinding::DeviceAddresses => (0..words
+                .div_ceil(PAGE_WORDS))
+                .map(|_| chunks.buffer(memory_allocator, PAGE_WORDS))
+                .collect(),
+        };
+        if binding == ChunkBinding::DeviceAddresses {
+            // Read for every voxel, small enough to stay cached wherever it lives.
+            chunks.addresses = Some(
+                Buffer::from_iter(
+                    memory_allocator,
+                    BufferCreateInfo {
+                        usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        usage: MemoryUsage::Upload,
+                        ..Default::default()
+                    },
+                    chunks.page_addresses(),
+                )
+                .unwrap(),
+            );
+        }
+        chunks
+    }
+
+    fn buffer(&self, memory_allocator: &StandardMemoryAllocator, words: u32) -> Subbuffer<[u32]> {
+        let usage = match self.binding {
+            ChunkBinding::DeviceAddresses => BufferUsage::SHADER_DEVICE_ADDRESS,
+            _ => BufferUsage::STORAGE_BUFFER,
+        };
+        Buffer::new_slice(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: usage | BufferUsage::TRANSFER_SRC | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+            words as u64,
+        )
+        .unwrap()
+    }
+
+    pub fn binding(&self) -> ChunkBinding {
+        self.binding
+    }
+
+    /// Returns the buffers of the copy, a single one with `ChunkBinding::Buffer`.
+    pub fn pages(&self) -> &[Subbuffer<[u32]>] {
+        &self.pages
+    }
+
+    /// Returns the table of page addresses with `ChunkBinding::DeviceAddresses`.
+    pub fn addresses(&self) -> Option<&Subbuffer<[[u32; 2]]>> {
+        self.addresses.as_ref()
+    }
+
+    /// Returns the device address of every page split into its low and high half, `MAX_PAGES`
+    /// of them. Entries past the last page point at the first one, so reads through headers the
+    /// first flush hasn't cleared yet stay in memory of the copy.
+    fn page_addresses(&self) -> Vec<[u32; 2]> {
+        (0..MAX_PAGES as usize)
+            .map(|page| {
+                let address = self.pages.get(page).unwrap_or(&self.pages[0]);
+                let address = address.device_address().unwrap().get();
+                [address as u32, (address >> 32) as u32]
+            })
+            .collect()
+    }
+
+    /// Returns the words the copy holds.
+    pub fn words(&self) -> u32 {
+        self.pages.iter().map(|page| page.len() as u32).sum()
+    }
+
+    /// Returns the bytes the copy takes.
+    pub fn bytes(&self) -> u64 {
+        let addresses = self
+            .addresses
+            .as_ref()
+            .map_or(0, |addresses| addresses.size());
+        self.pages.iter().map(|page| page.size()).sum::<u64>() + addresses
+    }
+
+    /// Records clearing the whole copy.
+    pub fn clear(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        for page in &self.pages {
+            builder.fill_buffer(page.clone(), 0).unwrap();
+        }
+    }
+
+    /// Makes the copy hold at least `words` words by adding pages, or by replacing the buffer
+    /// with a larger copy of it recorded into `builder`.
+    pub fn grow(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        memory_allocator: &StandardMemoryAllocator,
+        words: u32,
+    ) {
+        match self.binding {
+            ChunkBinding::Buffer => {
+                let grown = self.buffer(memory_allocator, words);
+                builder
+                    .copy_buffer(CopyBufferInfoTyped::buffers(
+                        self.pages[0].clone(),
+                        grown.clone(),
+                    ))
+                    .unwrap();
+                self.pages[0] = grown;
+            }
+            ChunkBinding::DescriptorArray | ChunkBinding::DeviceAddresses => {
+                while self.words() < words {
+                    let page = self.buffer(memory_allocator, PAGE_WORDS);
+                    self.pages.push(page);
+                }
+                if let Some(addresses) = &self.addresses {
+                    builder
+                        .update_buffer(addresses.clone(), self.page_addresses().into_boxed_slice())
+                        .unwrap();
+                }
+            }
+        }
+    }
+
+    /// Records copying `regions` of `source` into the copy. None of them may straddle two
+    /// pages.
+    pub fn copy_from(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        source: Subbuffer<[u32]>,
+        regions: Vec<BufferCopy>,
+    ) {
+        if self.binding == ChunkBinding::Buffer {
+            builder
+                .copy_buffer(CopyBufferInfoTyped {
+                    regions: regions.into(),
+                    ..CopyBufferInfoTyped::buffers(source, self.pages[0].clone())
+                })
+                .unwrap();
+            return;
+        }
+        let mut page_regions = vec![Vec::new(); self.pages.len()];
+        for region in regions {
+            page_regions[(region.dst_offset >> PAGE_BITS) as usize].push(BufferCopy {
+                dst_offset: region.dst_offset % PAGE_WORDS as u64,
+                ..region
+            });
+        }
+        for (page, regions) in self.pages.iter().zip(page_regions) {
+            if regions.is_empty() {
+                continue;
+            }
+            builder
+                .copy_buffer(CopyBufferInfoTyped {
+                    regions: regions.into(),
+                    ..CopyBufferInfoTyped::buffers(source.clone(), page.clone())
+                })
+                .unwrap();
+        }
+    }
+}
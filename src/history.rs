@@ -0,0 +1,94 @@
+use crate::world::{World, WORLD_SIZE};
+
+/// Number of edits that can be undone before the oldest is forgotten.
+const HISTORY_LIMIT: usize = 64;
+
+/// Voxels of a box as they were before an edit.
+#[derive(Clone, Debug)]
+struct Snapshot {
+    min: [u32; 3],
+    size: [u32; 3],
+    voxels: Vec<u32>,
+}
+
+impl Snapshot {
+    fn take(world: &World, min: [u32; 3], max: [u32; 3]) -> Snapshot {
+        Snapshot {
+            min,
+            size: [0, 1, 2].map(|i| max[i] - min[i]),
+            voxels: world.region(min, max),
+        }
+    }
+
+    fn max(&self) -> [u32; 3] {
+        [0, 1, 2].map(|i| self.min[i] + self.size[i])
+    }
+}
+
+/// Undo and redo stacks of world edits. Each edit is stored as the boxes it touched, as they were
+/// before it.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Vec<Snapshot>>,
+    redo: Vec<Vec<Snapshot>>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    /// Remembers the boxes from their lower (inclusive) to upper (exclusive) corner an edit is
+    /// about to change. Has to be called before the edit; boxes are clipped to the world.
+    pub fn record(&mut self, world: &World, boxes: &[([i32; 3], [i32; 3])]) {
+        let snapshots: Vec<_> = boxes
+            .iter()
+            .filter_map(|&(min, max)| {
+                let min = min.map(|c| c.clamp(0, WORLD_SIZE as i32) as u32);
+                let max = max.map(|c| c.clamp(0, WORLD_SIZE as i32) as u32);
+                (0..3)
+                    .all(|i| min[i] < max[i])
+                    .then(|| Snapshot::take(world, min, max))
+            })
+            .collect();
+        if snapshots.is_empty() {
+            return;
+        }
+        if self.undo.len() == HISTORY_LIMIT {
+            self.undo.remove(0);
+        }
+        self.undo.push(snapshots);
+        self.redo.clear();
+    }
+
+    /// Reverts the latest edit. Returns whether there was one.
+    pub fn undo(&mut self, world: &mut World) -> bool {
+        History::restore(&mut self.undo, &mut self.redo, world)
+    }
+
+    /// Applies the latest undone edit again. Returns whether there was one.
+    pub fn redo(&mut self, world: &mut World) -> bool {
+        History::restore(&mut self.redo, &mut self.undo, world)
+    }
+
+    /// Restores the top of `from`, pushing the boxes it overwrites onto `to`.
+    fn restore(
+        from: &mut Vec<Vec<Snapshot>>,
+        to: &mut Vec<Vec<Snapshot>>,
+        world: &mut World,
+    ) -> bool {
+        let Some(snapshots) = from.pop() else {
+            return false;
+        };
+        // All boxes were taken from the same state, so overlapping ones agree on the overlap.
+        let overwritten = snapshots
+            .iter()
+            .map(|snapshot| Snapshot::take(world, snapshot.min, snapshot.max()))
+            .collect();
+        for snapshot in &snapshots {
+            world.set_region(snapshot.min, snapshot.size, &snapshot.voxels);
+        }
+        to.push(overwritten);
+        true
+    }
+}
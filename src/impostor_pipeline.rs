@@ -0,0 +1,398 @@
+use crate::fractal_compute_pipeline::texture_image;
+use rvengine::{
+    impostors::{Billboard, ImpostorAtlas},
+    materials::Palette,
+    objects::VoxelObject,
+};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
+        CommandBufferInheritanceInfo, CommandBufferUsage, CopyBufferToImageInfo,
+        PrimaryAutoCommandBuffer, SecondaryAutoCommandBuffer,
+    },
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Queue,
+    image::{view::ImageView, StorageImage},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    pipeline::{
+        graphics::{
+            input_assembly::InputAssemblyState,
+            vertex_input::Vertex,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline, PipelineBindPoint,
+    },
+    render_pass::Subpass,
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+};
+use vulkano_util::renderer::DeviceImageView;
+
+/// What the graphics pass needs to draw the voxel objects past `Settings::impostor_distance`
+/// over the traced frame.
+pub struct ImpostorFrame {
+    /// GPU copy of the `ImpostorAtlas` the billboards are textured from.
+    pub atlas: Arc<ImageView<StorageImage>>,
+    /// Motion of every traced pixel, whose hit distance hides impostors behind the world.
+    pub motion: DeviceImageView,
+    pub billboards: Vec<Billboard>,
+    /// Eye position, rotation and `camera_dir` of the camera the frame was traced from.
+    pub eye: [f32; 3],
+    pub rotation: [f32; 3],
+    pub camera_dir: [f32; 3],
+}
+
+/// Views of the models of the voxel objects the ones past `Settings::impostor_distance` are
+/// drawn with, and their GPU copy.
+pub struct ImpostorImage {
+    atlas: ImpostorAtlas,
+    /// Whether the models or the palette changed since `atlas` was baked, which happens with the
+    /// next frame drawing impostors.
+    dirty: bool,
+    /// GPU copy of `atlas`, replaced with one of its size whenever it is baked.
+    image: Arc<ImageView<StorageImage>>,
+}
+
+impl ImpostorImage {
+    pub fn new(memory_allocator: &StandardMemoryAllocator, queue: &Arc<Queue>) -> ImpostorImage {
+        let atlas = ImpostorAtlas::default();
+        let image = texture_image(memory_allocator, queue, atlas.size());
+        ImpostorImage {
+            atlas,
+            dirty: true,
+            image,
+        }
+    }
+
+    /// Bakes the atlas again with the next frame drawing impostors.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Returns the atlas, `None` while it waits to be baked again.
+    pub fn atlas(&self) -> Option<&ImpostorAtlas> {
+        (!self.dirty).then_some(&self.atlas)
+    }
+
+    pub fn image(&self) -> &Arc<ImageView<StorageImage>> {
+        &self.image
+    }
+
+    /// Returns the bytes the GPU copy takes.
+    pub fn bytes(&self) -> u64 {
+        let [width, height] = self.atlas.size();
+        width as u64 * height as u64 * 4
+    }
+
+    /// Bakes the atlas from `objects` colored by `palette` and records copying it into a GPU copy
+    /// of its size when they changed.
+    pub fn record_bake(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        memory_allocator: &StandardMemoryAllocator,
+        queue: &Arc<Queue>,
+        objects: &[VoxelObject],
+        palette: &Palette,
+    ) {
+        if !self.dirty {
+            return;
+        }
+        self.atlas = ImpostorAtlas::bake(objects, palette);
+        self.image = texture_image(memory_allocator, queue, self.atlas.size());
+        let texels = Buffer::from_iter(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            self.atlas.texels().iter().copied(),
+        )
+        .unwrap();
+        builder
+            .copy_buffer_to_image(CopyBufferToImageInfo::buffer_image(
+                texels,
+                self.image.image().clone(),
+            ))
+            .unwrap();
+        self.dirty = false;
+    }
+}
+
+/// Vertex of a billboard, see `impostors::Billboard`.
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct ImpostorVertex {
+    #[format(R32G32B32_SFLOAT)]
+    position: [f32; 3],
+    #[format(R32G32_SFLOAT)]
+    tex_coords: [f32; 2],
+    #[format(R32_SFLOAT)]
+    radius: f32,
+}
+
+/// A subpass pipeline drawing the billboards of distant voxel objects over the traced frame,
+/// hidden wherever the frame's camera rays hit the world in front of them.
+pub struct ImpostorPipeline {
+    gfx_queue: Arc<Queue>,
+    subpass: Subpass,
+    pipeline: Arc<GraphicsPipeline>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    /// Keeps the atlas' texels sharp like voxels.
+    sampler: Arc<Sampler>,
+}
+
+impl ImpostorPipeline {
+    pub fn new(
+        gfx_queue: Arc<Queue>,
+        subpass: Subpass,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    ) -> ImpostorPipeline {
+        let pipeline = {
+            let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+            let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+            GraphicsPipeline::start()
+                .vertex_input_state(ImpostorVertex::per_vertex())
+                .vertex_shader(vs.entry_point("main").unwrap(), ())
+                .input_assembly_state(InputAssemblyState::new())
+                .fragment_shader(fs.entry_point("main").unwrap(), ())
+                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .render_pass(subpass.clone())
+                .build(gfx_queue.device().clone())
+                .unwrap()
+        };
+        let sampler = Sampler::new(
+            gfx_queue.device().clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Nearest,
+                min_filter: Filter::Nearest,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        ImpostorPipeline {
+            gfx_queue,
+            subpass,
+            pipeline,
+            memory_allocator,
+            command_buffer_allocator,
+            descriptor_set_allocator,
+            sampler,
+        }
+    }
+
+    /// Draws the billboards of `impostors` over a frame of `viewport_dimensions`.
+    pub fn draw(
+        &self,
+        viewport_dimensions: [u32; 2],
+        impostors: &ImpostorFrame,
+    ) -> SecondaryAutoCommandBuffer {
+        let mut builder = AutoCommandBufferBuilder::secondary(
+            &self.command_buffer_allocator,
+            self.gfx_queue.queue_family_index(),
+            CommandBufferUsage::MultipleSubmit,
+            CommandBufferInheritanceInfo {
+                render_pass: Some(self.subpass.clone().into()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let vertices = Buffer::from_iter(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::VERTEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            impostors
+                .billboards
+                .iter()
+                .flat_map(|billboard| {
+                    (0..4).map(|corner| ImpostorVertex {
+                        position: billboard.corners[corner],
+                        tex_coords: billboard.tex_coords[corner],
+                        radius: billboard.radius,
+                    })
+                })
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        // Two triangles per billboard, wound like `textured_quad`.
+        let indices = Buffer::from_iter(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::INDEX_BUFFER,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            (0..impostors.billboards.len() as u32)
+                .flat_map(|billboard| [0, 2, 1, 0, 3, 2].map(|corner| billboard * 4 + corner))
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let desc_set = PersistentDescriptorSet::new(
+            &self.descriptor_set_allocator,
+            self.pipeline
+                .layout()
+                .set_layouts()
+                .first()
+                .unwrap()
+                .clone(),
+            [
+                WriteDescriptorSet::image_view_sampler(
+                    0,
+                    impostors.atlas.clone(),
+                    self.sampler.clone(),
+                ),
+                WriteDescriptorSet::image_view(1, impostors.motion.clone()),
+            ],
+        )
+        .unwrap();
+        let push_constants = vs::PushConstants {
+            eye: impostors.eye,
+            width: viewport_dimensions[0] as f32,
+            rotation: impostors.rotation,
+            height: viewport_dimensions[1] as f32,
+            camera_dir: impostors.camera_dir,
+        };
+        builder
+            .set_viewport(
+                0,
+                [Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [viewport_dimensions[0] as f32, viewport_dimensions[1] as f32],
+                    depth_range: 0.0..1.0,
+                }],
+            )
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_descriptor_sets(
+                PipelineBindPoint::Graphics,
+                self.pipeline.layout().clone(),
+                0,
+                desc_set,
+            )
+            .push_constants(self.pipeline.layout().clone(), 0, push_constants)
+            .bind_vertex_buffers(0, vertices)
+            .bind_index_buffer(indices.clone())
+            .draw_indexed(indices.len() as u32, 1, 0, 0, 0)
+            .unwrap();
+        builder.build().unwrap()
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: r"
+            #version 450
+            layout(location = 0) in vec3 position;
+            layout(location = 1) in vec2 tex_coords;
+            layout(location = 2) in float radius;
+
+            layout(location = 0) out vec2 v_tex_coords;
+            layout(location = 1) out vec3 v_camera;
+            layout(location = 2) flat out float v_radius;
+            layout(location = 3) flat out vec2 v_viewport;
+
+            layout(push_constant) uniform PushConstants {
+                // Eye position in the world.
+                vec3 eye;
+                // Width of the frame in pixels.
+                float width;
+                vec3 rotation;
+                float height;
+                // Direction the camera looks along before rotation, see `cameraRay` in
+                // compute.glsl.
+                vec3 camera_dir;
+            } constants;
+
+            // Distance in front of the eye quads are cut off at.
+            const float NEAR = 0.1;
+
+            vec2 rotate2d(vec2 v, float a) {
+                float sinA = sin(a);
+                float cosA = cos(a);
+                return vec2(v.x * cosA - v.y * sinA, v.y * cosA + v.x * sinA);
+            }
+
+            // Undoes `rotateToWorld` in compute.glsl.
+            vec3 rotateFromWorld(vec3 v, vec3 rotation) {
+                v.xz = rotate2d(v.xz, -rotation.y);
+                v.yz = rotate2d(v.yz, -rotation.x);
+                v.xy = rotate2d(v.xy, -rotation.z);
+                return v;
+            }
+
+            void main() {
+                vec3 p = rotateFromWorld(position - constants.eye, constants.rotation);
+                // Where the camera ray through `p` starts on screen, see `previousScreenPos` in
+                // compute.glsl, before dividing by the depth. The traced frame is drawn with its
+                // first row at the bottom, so up on screen is down in clip space.
+                vec2 screen = p.xy * constants.camera_dir.z - constants.camera_dir.xy * p.z;
+                screen.y *= -constants.width / constants.height;
+                gl_Position = vec4(screen, p.z - NEAR, p.z);
+                v_tex_coords = tex_coords;
+                v_camera = p;
+                v_radius = radius;
+                v_viewport = vec2(constants.width, constants.height);
+            }
+        ",
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        src: r"
+            #version 450
+            layout(location = 0) in vec2 v_tex_coords;
+            layout(location = 1) in vec3 v_camera;
+            layout(location = 2) flat in float v_radius;
+            layout(location = 3) flat in vec2 v_viewport;
+
+            layout(location = 0) out vec4 f_color;
+
+            layout(set = 0, binding = 0) uniform sampler2D atlas;
+            // Screen space motion of every traced pixel with its hit distance in z, -1 for the
+            // sky, see `writeMotion` in compute.glsl.
+            layout(set = 0, binding = 1, rgba16f) uniform readonly image2D motion;
+
+            void main() {
+                vec4 color = texture(atlas, v_tex_coords);
+                if (color.a < 0.5) {
+                    discard;
+                }
+                // The traced pixel under the fragment, the frame being stretched over the whole
+                // viewport with its first row at the bottom.
+                ivec2 size = imageSize(motion);
+                vec2 uv = gl_FragCoord.xy / v_viewport;
+                ivec2 pixel = clamp(ivec2(vec2(uv.x, 1.0 - uv.y) * vec2(size)), ivec2(0), size - 1);
+                float dist = imageLoad(motion, pixel).z;
+                // The object reaches `v_radius` past the quad towards the eye, so only the world
+                // hit in front of that hides it.
+                if (dist >= 0.0 && dist < length(v_camera) - v_radius) {
+                    discard;
+                }
+                f_color = vec4(color.rgb, 1.0);
+            }
+        ",
+    }
+}
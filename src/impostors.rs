@@ -0,0 +1,307 @@
+use crate::{materials::Palette, objects::VoxelObject};
+use std::{collections::HashMap, f32::consts::TAU, sync::Arc};
+
+/// Directions around its y axis each model is baked from, evenly spaced starting at its +z side.
+pub const IMPOSTOR_VIEWS: usize = 8;
+
+/// Largest width and height of the atlas, which every Vulkan device supports.
+const MAX_ATLAS_SIZE: u32 = 4096;
+/// Edge length in texels of the square cell each view of a model gets, as long as the atlas
+/// fits. Models wider or taller than this are baked at fewer texels per voxel.
+const MAX_CELL_SIZE: u32 = 64;
+/// Edge length the cells shrink to at most when many models share the atlas.
+const MIN_CELL_SIZE: u32 = 4;
+
+/// Where the views of one model lie in the atlas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Region {
+    /// Top left texel of the first view, the others follow to the right of it a cell apart.
+    origin: [u32; 2],
+    /// Edge length of the cells in texels.
+    cell: u32,
+    /// Texels each view covers from the top left of its cell.
+    size: [u32; 2],
+    /// Voxels of the model along one texel.
+    voxels_per_texel: f32,
+}
+
+/// A quad standing upright at a distant object, facing the eye, textured with the view of the
+/// object's model closest to the side the eye sees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Billboard {
+    /// Bottom left, top left, top right and bottom right corners in the world.
+    pub corners: [[f32; 3]; 4],
+    /// Atlas coordinates (0 to 1) of each corner.
+    pub tex_coords: [[f32; 2]; 4],
+    /// How far the object reaches towards the eye past the quad, which runs through its center.
+    pub radius: f32,
+}
+
+/// Side views of the models of voxel objects, baked once so objects far away can be drawn as a
+/// textured quad instead of being traced. Each model is seen from `IMPOSTOR_VIEWS` directions
+/// around its y axis, looking straight across, without lighting.
+///
+/// Texels are RGBA8 packed into a word each like those of `detail_normals::DetailNormals`, rows
+/// from top to bottom. Alpha is 255 where the model covers a texel and 0 elsewhere.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImpostorAtlas {
+    size: [u32; 2],
+    texels: Vec<u32>,
+    /// Region of the model of every object baked from.
+    regions: Vec<Region>,
+}
+
+impl Default for ImpostorAtlas {
+    /// An atlas without models, a single clear texel.
+    fn default() -> Self {
+        ImpostorAtlas {
+            size: [1, 1],
+            texels: vec![0],
+            regions: Vec::new(),
+        }
+    }
+}
+
+impl ImpostorAtlas {
+    /// Bakes the views of the models of `objects`, colored by `palette`. Models shared by
+    /// several objects are baked once. A row of cells holds the views of one model and the rows
+    /// are laid out side by side, shrinking the cells until they all fit.
+    pub fn bake(objects: &[VoxelObject], palette: &Palette) -> ImpostorAtlas {
+        let mut models: Vec<&VoxelObject> = Vec::new();
+        let mut model_indices = HashMap::new();
+        let object_models: Vec<usize> = objects
+            .iter()
+            .map(|object| {
+                *model_indices
+                    .entry(Arc::as_ptr(&object.model))
+                    .or_insert_with(|| {
+                        models.push(object);
+                        models.len() - 1
+                    })
+            })
+            .collect();
+        if models.is_empty() {
+            return ImpostorAtlas::default();
+        }
+
+        let mut cell = MAX_CELL_SIZE;
+        let row_width = |cell: u32| cell * IMPOSTOR_VIEWS as u32;
+        let rows_across = |cell: u32| MAX_ATLAS_SIZE / row_width(cell);
+        while cell > MIN_CELL_SIZE
+            && (models.len() as u32).div_ceil(rows_across(cell)) * cell > MAX_ATLAS_SIZE
+        {
+            cell /= 2;
+        }
+        let across = rows_across(cell).min(models.len() as u32);
+        let size = [
+            across * row_width(cell),
+            (models.len() as u32).div_ceil(across) * cell,
+        ];
+
+        let mut atlas = ImpostorAtlas {
+            size,
+            texels: vec![0; (size[0] * size[1]) as usize],
+            regions: Vec::new(),
+        };
+        let model_regions: Vec<Region> = models
+            .iter()
+            .enumerate()
+            .map(|(index, object)| {
+                let index = index as u32;
+                let origin = [index % across * row_width(cell), index / across * cell];
+                atlas.bake_model(object, palette, origin, cell)
+            })
+            .collect();
+        atlas.regions = object_models
+            .into_iter()
+            .map(|model| model_regions[model])
+            .collect();
+        atlas
+    }
+
+    /// Returns the width and height in texels.
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    pub fn texels(&self) -> &[u32] {
+        &self.texels
+    }
+
+    /// Bakes the views of the model of `object` into the row of cells of edge length `cell`
+    /// starting at `origin`.
+    fn bake_model(
+        &mut self,
+        object: &VoxelObject,
+        palette: &Palette,
+        origin: [u32; 2],
+        cell: u32,
+    ) -> Region {
+        let model = &object.model;
+        let [sx, sy, sz] = model.size.map(|s| s as f32);
+        // Wide enough for the model seen from any side.
+        let width = sx.hypot(sz).ceil();
+        let voxels_per_texel = (width.max(sy) / cell as f32).max(1.0);
+        let region = Region {
+            origin,
+            cell,
+            size: [width, sy].map(|s| ((s / voxels_per_texel).ceil() as u32).min(cell)),
+            voxels_per_texel,
+        };
+        let [size_x, size_y, size_z] = model.size.map(i64::from);
+        let solid = |x: i64, y: i64, z: i64| {
+            (0..size_x).contains(&x)
+                && (0..size_y).contains(&y)
+                && (0..size_z).contains(&z)
+                && model.voxels[((x * size_y + y) * size_z + z) as usize] != 0
+        };
+
+        for view in 0..IMPOSTOR_VIEWS {
+            let angle = view as f32 * TAU / IMPOSTOR_VIEWS as f32;
+            // Towards the viewer, and to the right as the viewer sees it.
+            let toward = [angle.sin(), angle.cos()];
+            let right = [-angle.cos(), angle.sin()];
+            let left = origin[0] + view as u32 * cell;
+            let mut depths = vec![f32::NEG_INFINITY; (region.size[0] * region.size[1]) as usize];
+            for (index, &voxel) in model.voxels.iter().enumerate() {
+                if voxel == 0 {
+                    continue;
+                }
+                let index = index as i64;
+                let [x, y, z] = [
+                    index / (size_z * size_y),
+                    index / size_z % size_y,
+                    index % size_z,
+                ];
+                let hidden = [[1, 0, 0], [0, 1, 0], [0, 0, 1]]
+                    .iter()
+                    .all(|[dx, dy, dz]| {
+                        solid(x + dx, y + dy, z + dz) && solid(x - dx, y - dy, z - dz)
+                    });
+                if hidden {
+                    continue;
+                }
+                // Corners of the voxel's footprint relative to the model's center.
+                let corners = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]]
+                    .map(|[cx, cz]| [x as f32 + cx - sx * 0.5, z as f32 + cz - sz * 0.5]);
+                let across = corners.map(|[cx, cz]| cx * right[0] + cz * right[1]);
+                let from = (across.iter().copied().fold(f32::INFINITY, f32::min) + width * 0.5)
+                    / voxels_per_texel;
+                let to = (across.iter().copied().fold(f32::NEG_INFINITY, f32::max) + width * 0.5)
+                    / voxels_per_texel;
+                let depth = (x as f32 + 0.5 - sx * 0.5) * toward[0]
+                    + (z as f32 + 0.5 - sz * 0.5) * toward[1];
+                let top = (sy - y as f32 - 1.0) / voxels_per_texel;
+                let bottom = (sy - y as f32) / voxels_per_texel;
+                let color = palette.materials.get(voxel as usize).map_or(
+                    u32::from_le_bytes([255, 0, 255, 255]),
+                    |material| {
+                        let [r, g, b] = material
+                            .color
+                            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                        u32::from_le_bytes([r, g, b, 255])
+                    },
+                );
+                // Texels whose center the voxel covers, at least the one under its center.
+                let columns = texel_span(from, to, region.size[0]);
+                let rows = texel_span(top, bottom, region.size[1]);
+                for row in rows {
+                    for column in columns.clone() {
+                        let texel = (row * region.size[0] + column) as usize;
+                        if depth > depths[texel] {
+                            depths[texel] = depth;
+                            let atlas_texel =
+                                ((origin[1] + row) * self.size[0] + left + column) as usize;
+                            self.texels[atlas_texel] = color;
+                        }
+                    }
+                }
+            }
+        }
+        region
+    }
+
+    /// Returns the billboards of the objects of `objects` whose center lies farther than
+    /// `switch_distance` from `eye`, leaving out those whose bounding sphere lies wholly
+    /// beyond `cull_distance` and those on a layer set in `hidden_layers`, like
+    /// `cull_objects.glsl` does. `objects` must be those the atlas
+    /// was baked from. Billboards stay upright, only the objects' turn around y picks the view.
+    pub fn billboards(
+        &self,
+        objects: &[VoxelObject],
+        eye: [f32; 3],
+        switch_distance: f32,
+        cull_distance: f32,
+        hidden_layers: u32,
+    ) -> Vec<Billboard> {
+        let mut billboards = Vec::new();
+        for (object, region) in objects.iter().zip(&self.regions) {
+            if hidden_layers.checked_shr(object.layer).unwrap_or(0) & 1 != 0 {
+                continue;
+            }
+            let transform = &object.transform;
+            let to_center = [0, 1, 2].map(|i| transform.translation[i] - eye[i]);
+            let distance = to_center.iter().map(|c| c * c).sum::<f32>().sqrt();
+            let size = object.model.size.map(|s| s as f32);
+            let radius = size.iter().map(|s| s * s).sum::<f32>().sqrt() * 0.5 * transform.scale;
+            if distance <= switch_distance || distance - radius > cull_distance {
+                continue;
+            }
+            let horizontal = to_center[0].hypot(to_center[2]);
+            if horizontal <= 0.0 {
+                continue;
+            }
+
+            // The view baked from the side of the model closest to the eye.
+            let local_eye = transform.to_local(eye);
+            let angle = local_eye[0].atan2(local_eye[2]);
+            let view = (angle / TAU * IMPOSTOR_VIEWS as f32).round() as i32;
+            let view = view.rem_euclid(IMPOSTOR_VIEWS as i32) as u32;
+
+            let span = region
+                .size
+                .map(|s| s as f32 * region.voxels_per_texel * transform.scale);
+            let width = size[0].hypot(size[2]).ceil() * transform.scale;
+            let forward = [to_center[0] / horizontal, to_center[2] / horizontal];
+            let right = [forward[1], 0.0, -forward[0]];
+            let top = transform.translation[1] + size[1] * 0.5 * transform.scale;
+            let corner = |across: f32, up: f32| {
+                [
+                    transform.translation[0] + right[0] * (across - width * 0.5),
+                    top - up,
+                    transform.translation[2] + right[2] * (across - width * 0.5),
+                ]
+            };
+            let [u0, v0] = [
+                (region.origin[0] + view * region.cell) as f32,
+                region.origin[1] as f32,
+            ];
+            let [u1, v1] = [u0 + region.size[0] as f32, v0 + region.size[1] as f32];
+            let uv = |u: f32, v: f32| [u / self.size[0] as f32, v / self.size[1] as f32];
+            billboards.push(Billboard {
+                corners: [
+                    corner(0.0, span[1]),
+                    corner(0.0, 0.0),
+                    corner(span[0], 0.0),
+                    corner(span[0], span[1]),
+                ],
+                tex_coords: [uv(u0, v1), uv(u0, v0), uv(u1, v0), uv(u1, v1)],
+                radius: width * 0.5,
+            });
+        }
+        billboards
+    }
+}
+
+/// Returns the texels from 0 to `count` whose centers lie between `from` and `to`, in texels,
+/// or the one under the middle when the span falls between two centers.
+fn texel_span(from: f32, to: f32, count: u32) -> std::ops::Range<u32> {
+    let first = (from - 0.5).ceil().max(0.0) as u32;
+    let end = ((to - 0.5).ceil().max(0.0) as u32).min(count);
+    if first < end {
+        first..end
+    } else {
+        let middle = (((from + to) * 0.5) as u32).min(count.saturating_sub(1));
+        middle..middle + 1
+    }
+}
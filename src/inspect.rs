@@ -0,0 +1,84 @@
+use crate::{
+    distance_field::CHUNKS,
+    materials::{self, MATERIAL_COUNT},
+    world::{World, WORLD_SIZE},
+};
+use std::{collections::BTreeMap, fmt};
+
+/// What a world holds, for checking worlds written by importers and other tools.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub size: [u32; 3],
+    /// Number of chunks in the world.
+    pub chunks: usize,
+    /// Number of chunks holding solid voxels.
+    pub occupied_chunks: usize,
+    /// How many voxels there are of each type present, empty ones included.
+    pub type_counts: BTreeMap<u32, usize>,
+}
+
+impl Report {
+    pub fn new(world: &World) -> Report {
+        let mut occupied_chunks = 0;
+        for x in 0..CHUNKS {
+            for y in 0..CHUNKS {
+                for z in 0..CHUNKS {
+                    occupied_chunks += world.chunk_occupied([x, y, z]) as usize;
+                }
+            }
+        }
+        Report {
+            size: [WORLD_SIZE; 3],
+            chunks: (CHUNKS * CHUNKS * CHUNKS) as usize,
+            occupied_chunks,
+            type_counts: world.type_counts(),
+        }
+    }
+
+    /// Returns the number of solid voxels.
+    pub fn solid_voxels(&self) -> usize {
+        self.type_counts
+            .iter()
+            .filter(|(&voxel, _)| voxel != 0)
+            .map(|(_, &count)| count)
+            .sum()
+    }
+
+    /// Returns the types present which have no material, which the renderer can't show as
+    /// intended, with how many voxels there are of each.
+    pub fn invalid_types(&self) -> impl Iterator<Item = (u32, usize)> + '_ {
+        self.type_counts
+            .range(MATERIAL_COUNT as u32..)
+            .map(|(&voxel, &count)| (voxel, count))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [x, y, z] = self.size;
+        writeln!(f, "size: {x}x{y}x{z} voxels")?;
+        writeln!(
+            f,
+            "chunks: {} of {} hold voxels",
+            self.occupied_chunks, self.chunks
+        )?;
+        writeln!(f, "solid voxels: {}", self.solid_voxels())?;
+        writeln!(f, "types:")?;
+        let total = (x * y * z) as f64;
+        for (&voxel, &count) in &self.type_counts {
+            let name = materials::type_name(voxel).map_or(String::new(), |name| format!(" {name}"));
+            writeln!(
+                f,
+                "  {voxel}{name}: {count} ({:.3}%)",
+                count as f64 / total * 100.0
+            )?;
+        }
+        for (voxel, count) in self.invalid_types() {
+            writeln!(
+                f,
+                "invalid: {count} voxels of type {voxel}, which has no material"
+            )?;
+        }
+        Ok(())
+    }
+}
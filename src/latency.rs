@@ -0,0 +1,66 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Slack left between the predicted end of a frame and the next refresh.
+const MARGIN: Duration = Duration::from_millis(1);
+
+/// Delays sampling input until just before the next frame has to start to make the coming
+/// refresh, so the camera state the frame is traced with is as fresh as possible. Frames are
+/// presented one display refresh apart and the time a frame takes is predicted from the
+/// previous ones.
+pub struct LatencyLimiter {
+    refresh_interval: Duration,
+    /// Time from sampling input to the frame having been presented, rising at once with slow
+    /// frames and falling slowly with fast ones.
+    work: Duration,
+    frame_start: Instant,
+    last_present: Option<Instant>,
+}
+
+impl LatencyLimiter {
+    /// Paces frames for a display refreshing at `refresh_rate_millihertz`.
+    pub fn new(refresh_rate_millihertz: u32) -> LatencyLimiter {
+        LatencyLimiter {
+            refresh_interval: Duration::from_secs_f64(
+                1000.0 / refresh_rate_millihertz.max(1) as f64,
+            ),
+            work: Duration::ZERO,
+            frame_start: Instant::now(),
+            last_present: None,
+        }
+    }
+
+    /// Sleeps until input has to be sampled for the next frame, then starts it.
+    pub fn begin_frame(&mut self) {
+        if let Some(last_present) = self.last_present {
+            let wake = (last_present + self.refresh_interval)
+                .checked_sub(self.work + MARGIN)
+                .unwrap_or(last_present);
+            let now = Instant::now();
+            if wake > now {
+                thread::sleep(wake - now);
+            }
+        }
+        self.frame_start = Instant::now();
+    }
+
+    /// Returns the time between two refreshes of the display.
+    pub fn refresh_interval(&self) -> Duration {
+        self.refresh_interval
+    }
+
+    /// Ends the frame once it was presented, or reached the display when the device can tell,
+    /// see `WindowRenderer::wait_presented`.
+    pub fn end_frame(&mut self) {
+        let now = Instant::now();
+        let work = now - self.frame_start;
+        self.work = if work > self.work {
+            work
+        } else {
+            (self.work * 15 + work) / 16
+        };
+        self.last_present = Some(now);
+    }
+}
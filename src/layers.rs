@@ -0,0 +1,64 @@
+/// Layers a scene can have, one bit each of the mask of hidden layers.
+pub const MAX_LAYERS: usize = 32;
+
+/// Layer of the ground, the default of every material.
+pub const TERRAIN: u32 = 0;
+/// Layer of built structures.
+pub const BUILDINGS: u32 = 1;
+/// Layer of small things placed on the others, the default of voxel objects.
+pub const PROPS: u32 = 2;
+
+/// The names of the layers materials and voxel objects are tagged with, so parts of a scene can
+/// be hidden together while editing or rendered on their own. Layer `i` is named `names()[i]`
+/// and is hidden by bit `i` of `Settings::hidden_layers`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layers {
+    names: Vec<String>,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Layers {
+            names: ["terrain", "buildings", "props"].map(String::from).to_vec(),
+        }
+    }
+}
+
+impl Layers {
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Returns the layer named `name`.
+    pub fn find(&self, name: &str) -> Result<u32, String> {
+        self.names
+            .iter()
+            .position(|other| other == name)
+            .map(|layer| layer as u32)
+            .ok_or_else(|| format!("there is no layer `{name}`"))
+    }
+
+    /// Adds a layer named `name`, returning it.
+    pub fn add(&mut self, name: &str) -> Result<u32, String> {
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("invalid layer name `{name}`"));
+        }
+        if self.find(name).is_ok() {
+            return Err(format!("there already is a layer `{name}`"));
+        }
+        if self.names.len() >= MAX_LAYERS {
+            return Err(format!("there can't be more than {MAX_LAYERS} layers"));
+        }
+        self.names.push(name.to_string());
+        Ok(self.names.len() as u32 - 1)
+    }
+
+    /// Returns the name of `layer`, or its number for layers added by another session, like
+    /// those of a loaded palette.
+    pub fn name(&self, layer: u32) -> String {
+        self.names
+            .get(layer as usize)
+            .cloned()
+            .unwrap_or_else(|| layer.to_string())
+    }
+}
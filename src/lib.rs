@@ -0,0 +1,56 @@
+//! Parts of the engine usable on their own, without the window and renderer of the binary.
+//!
+//! Only `World::flush`, the `gpu_chunks` it uploads into and the `staging` buffers it uploads
+//! through need Vulkan. Building without the default `vulkan` feature leaves them out, so the
+//! rest builds for targets without Vulkan such as `wasm32-unknown-unknown`.
+//!
+//! `ffi` exposes a C API rendering worlds with the CPU `tracer`, see `include/rayvox.h`, and
+//! `web`, built with the `web` feature for wasm32, traces them in the browser with WebGPU.
+
+pub mod agents;
+pub mod animation;
+pub mod anvil;
+pub mod autosave;
+pub mod camera;
+pub mod caves;
+pub mod chunk_palette;
+pub mod clipping;
+pub mod dataset;
+pub mod decals;
+pub mod demo;
+pub mod detail_normals;
+pub mod distance_field;
+pub mod entities;
+pub mod ffi;
+pub mod flythrough;
+#[cfg(feature = "vulkan")]
+pub mod gpu_chunks;
+pub mod history;
+pub mod impostors;
+pub mod inspect;
+pub mod layers;
+pub mod light_bake;
+pub mod materials;
+pub mod objects;
+pub mod portal;
+pub mod prefab;
+pub mod projectiles;
+pub mod recent;
+pub mod reflection_probes;
+pub mod scene;
+pub mod screenshot;
+pub mod simulation;
+#[cfg(feature = "vulkan")]
+pub mod staging;
+pub mod structures;
+pub mod symmetry;
+pub mod tiled_map;
+pub mod tracer;
+pub mod veins;
+pub mod vox;
+pub mod vox_scene;
+#[cfg(all(feature = "web", target_arch = "wasm32"))]
+pub mod web;
+pub mod world;
+pub mod world_file;
+pub mod worldgen;
@@ -0,0 +1,370 @@
+use crate::{
+    chunk_palette::{ChunkSlots, HEADER_WORDS},
+    distance_field::CHUNKS,
+    materials::Palette,
+    scene::DEFAULT_TIME_OF_DAY,
+    tracer::SKY_COLOR,
+    world::{World, CHUNK_SIZE, WORLD_SIZE},
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use std::{f32::consts::PI, fs, path::Path};
+
+/// Extension of light bake files, saved next to the world they were baked for.
+pub const BAKE_EXTENSION: &str = "rvlight";
+/// Version written to light bake files.
+const BAKE_VERSION: u32 = 1;
+/// Brightest light a face stores, its color channels are 10 bit fractions of it.
+pub const MAX_BAKED_LIGHT: f32 = 4.0;
+/// Faces of a voxel, as `face` numbers them.
+const FACES: u32 = 6;
+
+/// How `LightBake::bake` traces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BakeSettings {
+    /// Paths traced per face.
+    pub samples: u32,
+    /// Diffuse bounces per path, as `Settings::max_bounces`.
+    pub max_bounces: u32,
+    /// Distance in voxels rays travel before hitting the sky, as `Settings::render_distance`.
+    pub max_distance: f32,
+    /// Hour of the day the sun stands at, see `sunDirection` in the compute shader.
+    pub time_of_day: f32,
+}
+
+impl Default for BakeSettings {
+    fn default() -> Self {
+        BakeSettings {
+            samples: 64,
+            max_bounces: 2,
+            max_distance: WORLD_SIZE as f32,
+            time_of_day: DEFAULT_TIME_OF_DAY,
+        }
+    }
+}
+
+/// Light reaching every face of a static world, path traced offline with the lighting terms of
+/// the compute shader: ambient occlusion, sun shadows and diffuse bounces. The renderer then only
+/// looks faces up instead of tracing, which looks like accumulated global illumination at the
+/// cost of a primary ray.
+///
+/// Only faces of solid voxels towards empty ones are stored. The words are what the compute
+/// shader reads: for every chunk, indexed by `(x * CHUNKS + y) * CHUNKS + z`, where its entries
+/// start, followed by where the last chunk's end. Then the entries of each chunk sorted by their
+/// key, two words each: the key `voxel * 6 + face`, `voxel` indexing the chunk like
+/// `PackedChunk::pack` and `face` numbering the faces towards +x, -x, +y, -y, +z and -z, and the
+/// light in 10 bits per channel.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LightBake {
+    words: Vec<u32>,
+}
+
+impl LightBake {
+    /// Bakes the light of every face of `world` lit with `palette`, chunks in parallel on
+    /// rayon's thread pool. Rays see the materials' plain colors, without face shading.
+    pub fn bake(world: &World, palette: &Palette, settings: &BakeSettings) -> LightBake {
+        let chunks: Vec<Vec<[u32; 2]>> = (0..HEADER_WORDS)
+            .into_par_iter()
+            .map(|index| {
+                let chunk = [
+                    index / (CHUNKS * CHUNKS),
+                    index / CHUNKS % CHUNKS,
+                    index % CHUNKS,
+                ];
+                bake_chunk(world, palette, settings, chunk, index as u64)
+            })
+            .collect();
+        let mut words = Vec::with_capacity(HEADER_WORDS as usize + 1);
+        let mut start = 0;
+        for entries in &chunks {
+            words.push(start);
+            start += entries.len() as u32;
+        }
+        words.push(start);
+        words.extend(chunks.into_iter().flatten().flatten());
+        LightBake { words }
+    }
+
+    /// Returns the number of faces baked.
+    pub fn faces(&self) -> usize {
+        self.words[HEADER_WORDS as usize] as usize
+    }
+
+    /// Returns the words the compute shader reads.
+    pub fn words(&self) -> &[u32] {
+        &self.words
+    }
+
+    /// Returns the light baked for the face of the voxel at `position` facing `normal`, `None`
+    /// when it wasn't baked.
+    pub fn light(&self, position: [i32; 3], normal: [i32; 3]) -> Option<[f32; 3]> {
+        if position.iter().any(|&c| c < 0 || c >= WORLD_SIZE as i32) {
+            return None;
+        }
+        let position = position.map(|c| c as u32);
+        let chunk = ChunkSlots::index(position.map(|c| c / CHUNK_SIZE));
+        let key = face_key(position.map(|c| c % CHUNK_SIZE), face(normal)?);
+        let entries = &self.words[HEADER_WORDS as usize + 1..];
+        let end = self.words[chunk + 1] as usize;
+        // Searched the same way the compute shader searches.
+        let (mut low, mut high) = (self.words[chunk] as usize, end);
+        while low < high {
+            let middle = (low + high) / 2;
+            if entries[middle * 2] < key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        (low < end && entries[low * 2] == key).then(|| unpack_light(entries[low * 2 + 1]))
+    }
+
+    /// Writes the bake to `path`: `RVLB`, the version, the edge length of the world and the
+    /// words, all little endian u32s.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut bytes = b"RVLB".to_vec();
+        bytes.extend_from_slice(&BAKE_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&WORLD_SIZE.to_le_bytes());
+        for word in &self.words {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        fs::write(path, bytes)
+            .map_err(|err| format!("can't write light bake `{}`: {err}", path.display()))
+    }
+
+    /// Reads a bake written by `save`.
+    pub fn load(path: impl AsRef<Path>) -> Result<LightBake, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .map_err(|err| format!("can't read light bake `{}`: {err}", path.display()))?;
+        LightBake::from_bytes(&bytes)
+            .map_err(|err| format!("invalid light bake `{}`: {err}", path.display()))
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<LightBake, String> {
+        let words: Vec<u32> = bytes
+            .chunks(4)
+            .map(|word| {
+                word.try_into()
+                    .map(u32::from_le_bytes)
+                    .map_err(|_| "unexpected end of file".to_string())
+            })
+            .collect::<Result<_, _>>()?;
+        let [magic, version, size, words @ ..] = &words[..] else {
+            return Err("unexpected end of file".to_string());
+        };
+        if magic.to_le_bytes() != *b"RVLB" {
+            return Err("not an .rvlight file".to_string());
+        }
+        if *version != BAKE_VERSION {
+            return Err(format!("unsupported version {version}"));
+        }
+        if *size != WORLD_SIZE {
+            return Err(format!(
+                "baked for a world {size} voxels wide instead of {WORLD_SIZE}"
+            ));
+        }
+        let Some(starts) = words.get(..=HEADER_WORDS as usize) else {
+            return Err("unexpected end of file".to_string());
+        };
+        if starts[0] != 0 || starts.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err("chunks out of order".to_string());
+        }
+        let entries = starts[HEADER_WORDS as usize] as usize;
+        if words.len() != HEADER_WORDS as usize + 1 + entries * 2 {
+            return Err(format!("expected {entries} faces"));
+        }
+        Ok(LightBake {
+            words: words.to_vec(),
+        })
+    }
+}
+
+/// Returns the entries of `chunk`, sorted by key.
+fn bake_chunk(
+    world: &World,
+    palette: &Palette,
+    settings: &BakeSettings,
+    chunk: [u32; 3],
+    seed: u64,
+) -> Vec<[u32; 2]> {
+    let mut entries = Vec::new();
+    if !world.chunk_occupied(chunk) {
+        return entries;
+    }
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    for x in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                let local = [x, y, z];
+                let voxel = [0, 1, 2].map(|i| (chunk[i] * CHUNK_SIZE + local[i]) as i32);
+                if world.voxel(voxel) == 0 {
+                    continue;
+                }
+                for normal in NORMALS {
+                    let cell = [0, 1, 2].map(|i| voxel[i] + normal[i]);
+                    if world.voxel(cell) != 0 {
+                        continue;
+                    }
+                    let light = face_light(world, palette, settings, voxel, normal, &mut rng);
+                    let key = face_key(local, face(normal).unwrap());
+                    entries.push([key, pack_light(light)]);
+                }
+            }
+        }
+    }
+    entries
+}
+
+/// Normals of the faces in the order `face` numbers them.
+const NORMALS: [[i32; 3]; FACES as usize] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+/// Returns the number of the face facing `normal`, `None` when it isn't along an axis.
+fn face(normal: [i32; 3]) -> Option<u32> {
+    NORMALS
+        .iter()
+        .position(|&other| other == normal)
+        .map(|face| face as u32)
+}
+
+/// Returns the key of the face `face` of the voxel at `local` in its chunk.
+fn face_key(local: [u32; 3], face: u32) -> u32 {
+    ((local[0] * CHUNK_SIZE + local[1]) * CHUNK_SIZE + local[2]) * FACES + face
+}
+
+fn pack_light(light: [f32; 3]) -> u32 {
+    light
+        .map(|c| ((c / MAX_BAKED_LIGHT).clamp(0.0, 1.0) * 1023.0).round() as u32)
+        .iter()
+        .enumerate()
+        .fold(0, |word, (i, c)| word | c << (i * 10))
+}
+
+fn unpack_light(word: u32) -> [f32; 3] {
+    [0, 1, 2].map(|i| (word >> (i * 10) & 1023) as f32 / 1023.0 * MAX_BAKED_LIGHT)
+}
+
+/// Returns the mean light of `settings.samples` paths leaving random points of the face of the
+/// voxel at `voxel` facing `normal`, lit the way `lighting` in the compute shader lights it.
+/// Bounces scatter diffusely, the light mustn't depend on where the face is seen from.
+fn face_light(
+    world: &World,
+    palette: &Palette,
+    settings: &BakeSettings,
+    voxel: [i32; 3],
+    normal: [i32; 3],
+    rng: &mut ChaCha8Rng,
+) -> [f32; 3] {
+    let cell = [0, 1, 2].map(|i| voxel[i] + normal[i]);
+    let tangent = [normal[1], normal[2], normal[0]];
+    let bitangent = [normal[2], normal[0], normal[1]];
+    let occlusion = ambient_occlusion(world, cell, tangent, bitangent);
+    let sun = sun_direction(settings.time_of_day);
+    let mut total = [0.0; 3];
+    for _ in 0..settings.samples {
+        let (u, v) = (rng.gen::<f32>() - 0.5, rng.gen::<f32>() - 0.5);
+        let position = [0, 1, 2].map(|i| {
+            voxel[i] as f32
+                + 0.5
+                + normal[i] as f32 * 0.501
+                + tangent[i] as f32 * u
+                + bitangent[i] as f32 * v
+        });
+        let shadow = if sun[1] <= 0.0
+            || world
+                .raycast(position, sun, settings.max_distance)
+                .is_some()
+        {
+            0.5
+        } else {
+            1.0
+        };
+        let light = occlusion * shadow;
+        let bounce = bounce_light(world, palette, settings, position, normal, 0.3 * light, rng);
+        for i in 0..3 {
+            total[i] += light + bounce[i];
+        }
+    }
+    total.map(|c| c / settings.samples.max(1) as f32)
+}
+
+/// Darkens corners by counting the solid voxels around the empty `cell` in front of a face, as
+/// `ambientOcclusion` in the compute shader does.
+fn ambient_occlusion(world: &World, cell: [i32; 3], tangent: [i32; 3], bitangent: [i32; 3]) -> f32 {
+    let mut occluded = 0;
+    for u in -1..=1 {
+        for v in -1..=1 {
+            let neighbor = [0, 1, 2].map(|i| cell[i] + tangent[i] * u + bitangent[i] * v);
+            if (u != 0 || v != 0) && world.voxel(neighbor) != 0 {
+                occluded += 1;
+            }
+        }
+    }
+    1.0 - occluded as f32 / 8.0 * 0.6
+}
+
+/// Returns the direction towards the sun at `time_of_day`, as `sunDirection` in the compute
+/// shader does.
+fn sun_direction(time_of_day: f32) -> [f32; 3] {
+    let angle = (time_of_day - 6.0) / 12.0 * PI;
+    let direction = [angle.cos(), angle.sin(), -0.3];
+    let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+    direction.map(|d| d / length)
+}
+
+/// Returns the light bouncing off the world onto a face at `position` facing `normal`, as
+/// `bounceLight` in the compute shader returns it for a fully rough surface.
+fn bounce_light(
+    world: &World,
+    palette: &Palette,
+    settings: &BakeSettings,
+    mut position: [f32; 3],
+    mut normal: [i32; 3],
+    mut weight: f32,
+    rng: &mut ChaCha8Rng,
+) -> [f32; 3] {
+    let mut light = [0.0; 3];
+    let mut tint = [1.0; 3];
+    for _ in 0..settings.max_bounces {
+        let direction = sample_hemisphere(normal, rng);
+        let Some(hit) = world.raycast(position, direction, settings.max_distance) else {
+            for i in 0..3 {
+                light[i] += weight * tint[i] * SKY_COLOR[i];
+            }
+            break;
+        };
+        let material = &palette.materials[(hit.voxel as usize).min(palette.materials.len() - 1)];
+        for i in 0..3 {
+            light[i] +=
+                weight * tint[i] * (material.color[i] + material.color[i] * material.emissive);
+            tint[i] *= material.color[i];
+        }
+        weight *= 0.3;
+        normal = hit.normal;
+        position =
+            [0, 1, 2].map(|i| position[i] + direction[i] * hit.distance + normal[i] as f32 * 0.001);
+    }
+    light
+}
+
+/// Returns a cosine weighted direction around `normal`.
+fn sample_hemisphere(normal: [i32; 3], rng: &mut ChaCha8Rng) -> [f32; 3] {
+    let phi = 2.0 * PI * rng.gen::<f32>();
+    let r = rng.gen::<f32>().sqrt();
+    let (sin, cos) = phi.sin_cos();
+    let up = (1.0 - r * r).sqrt();
+    let tangent = [normal[1], normal[2], normal[0]];
+    let bitangent = [normal[2], normal[0], normal[1]];
+    [0, 1, 2].map(|i| {
+        tangent[i] as f32 * r * cos + bitangent[i] as f32 * r * sin + normal[i] as f32 * up
+    })
+}
@@ -0,0 +1,89 @@
+use crate::fractal_compute_pipeline::word_buffer;
+use rvengine::{chunk_palette::HEADER_WORDS, light_bake::LightBake};
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, CopyBufferInfo, PrimaryAutoCommandBuffer},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+};
+
+/// The light baked for the world, lighting surfaces with `Settings::baked_lighting`, with its
+/// GPU copy, updated with the frame after it was replaced.
+pub struct LightBakeBuffer {
+    bake: Option<LightBake>,
+    /// Whether `bake` changed since it was last copied to `buffer`.
+    dirty: bool,
+    /// GPU copy of `bake`, replaced with one of its size whenever it changes. Without a bake it
+    /// holds no faces, so every face is lit fully.
+    buffer: Subbuffer<[u32]>,
+}
+
+impl LightBakeBuffer {
+    pub fn new(memory_allocator: &StandardMemoryAllocator) -> LightBakeBuffer {
+        LightBakeBuffer {
+            bake: None,
+            dirty: true,
+            buffer: word_buffer(memory_allocator, HEADER_WORDS as u64 + 1),
+        }
+    }
+
+    pub fn bake(&self) -> Option<&LightBake> {
+        self.bake.as_ref()
+    }
+
+    /// Replaces the light bake, which reaches the GPU with the next frame.
+    pub fn set(&mut self, bake: Option<LightBake>) {
+        self.bake = bake;
+        self.dirty = true;
+    }
+
+    pub fn buffer(&self) -> &Subbuffer<[u32]> {
+        &self.buffer
+    }
+
+    /// Returns the bytes the GPU copy takes.
+    pub fn bytes(&self) -> u64 {
+        self.buffer.size()
+    }
+
+    /// Records copying the bake into a GPU copy of its size when it changed, returning whether
+    /// it did.
+    pub fn record_upload(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        memory_allocator: &StandardMemoryAllocator,
+    ) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        let words = self
+            .bake
+            .as_ref()
+            .map_or(HEADER_WORDS as usize + 1, |bake| bake.words().len());
+        self.buffer = word_buffer(memory_allocator, words as u64);
+        match &self.bake {
+            Some(bake) => {
+                let staging = Buffer::from_iter(
+                    memory_allocator,
+                    BufferCreateInfo {
+                        usage: BufferUsage::TRANSFER_SRC,
+                        ..Default::default()
+                    },
+                    AllocationCreateInfo {
+                        usage: MemoryUsage::Upload,
+                        ..Default::default()
+                    },
+                    bake.words().iter().copied(),
+                )
+                .unwrap();
+                builder
+                    .copy_buffer(CopyBufferInfo::buffers(staging, self.buffer.clone()))
+                    .unwrap();
+            }
+            None => {
+                builder.fill_buffer(self.buffer.clone(), 0).unwrap();
+            }
+        }
+        self.dirty = false;
+        true
+    }
+}
@@ -0,0 +1,208 @@
+use rvengine::{simulation::LiquidBatch, world::CHUNK_SIZE};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{AutoCommandBufferBuilder, CopyBufferInfo, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Queue,
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+};
+
+/// Edge length of the cubic workgroups of the liquid simulation shader, two along every axis of
+/// a chunk.
+const GROUP_SIZE: u32 = 8;
+
+/// Runs the ticks of `LiquidBatch`es on the GPU and reads their results back, see
+/// `Simulation`.
+pub struct LiquidsPipeline {
+    pipeline: Arc<ComputePipeline>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    /// Batch to run with the next frame.
+    request: Option<LiquidBatch>,
+    /// Host visible buffer the cells after the last tick of a batch are copied into, until they
+    /// are read back, and the batch.
+    readback: Option<(Subbuffer<[u32]>, LiquidBatch)>,
+    /// A batch read back along with its cells, until taken with `take_simulated`.
+    simulated: Option<(LiquidBatch, Vec<u32>)>,
+}
+
+impl LiquidsPipeline {
+    pub fn new(
+        queue: &Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
+    ) -> LiquidsPipeline {
+        let shader = cs::load(queue.device().clone()).unwrap();
+        let pipeline = ComputePipeline::new(
+            queue.device().clone(),
+            shader.entry_point("main").unwrap(),
+            &(),
+            None,
+            |_| {},
+        )
+        .unwrap();
+        LiquidsPipeline {
+            pipeline,
+            memory_allocator,
+            descriptor_set_allocator,
+            request: None,
+            readback: None,
+            simulated: None,
+        }
+    }
+
+    /// Runs the ticks of `batch` with the next frame. `take_simulated` returns the result a few
+    /// frames later.
+    pub fn simulate(&mut self, batch: LiquidBatch) {
+        self.request = Some(batch);
+    }
+
+    /// Returns a batch passed to `simulate` along with the cells of its chunks after it, once
+    /// they are read back.
+    pub fn take_simulated(&mut self) -> Option<(LiquidBatch, Vec<u32>)> {
+        self.simulated.take()
+    }
+
+    /// Reads back the cells of the last batch if the GPU is done with them and records the
+    /// requested batch, if any.
+    pub fn record(&mut self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        // Fails without blocking while the GPU still writes the readback buffer.
+        let cells = self
+            .readback
+            .as_ref()
+            .and_then(|(readback, _)| readback.read().ok().map(|cells| cells.to_vec()));
+        if let Some(cells) = cells {
+            if let Some((_, batch)) = self.readback.take() {
+                self.simulated = Some((batch, cells));
+            }
+        }
+        if let Some(batch) = self.request.take() {
+            self.record_batch(builder, batch);
+        }
+    }
+
+    /// Records the ticks of `batch`. Every tick reads the cells of one buffer and writes their
+    /// next state into the other, the next tick swapping them, so a tick only sees the one
+    /// before. Both start out with all cells, chunks which are only read stay the same in both.
+    /// The cells after the last tick are copied into a new readback buffer.
+    fn record_batch(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        batch: LiquidBatch,
+    ) {
+        let upload = |usage, words: &[u32]| {
+            Buffer::from_iter(
+                &self.memory_allocator,
+                BufferCreateInfo {
+                    usage,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    usage: MemoryUsage::Upload,
+                    ..Default::default()
+                },
+                words.iter().copied(),
+            )
+            .unwrap()
+        };
+        let staging = upload(BufferUsage::TRANSFER_SRC, &batch.cells);
+        let neighbors = upload(
+            BufferUsage::STORAGE_BUFFER,
+            &batch
+                .neighbors
+                .iter()
+                // Read as ints, -1 staying -1.
+                .map(|&index| index as u32)
+                .collect::<Vec<_>>(),
+        );
+        let cells = [(); 2].map(|_| {
+            Buffer::new_slice::<u32>(
+                &self.memory_allocator,
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER
+                        | BufferUsage::TRANSFER_SRC
+                        | BufferUsage::TRANSFER_DST,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    usage: MemoryUsage::DeviceOnly,
+                    ..Default::default()
+                },
+                batch.cells.len() as u64,
+            )
+            .unwrap()
+        });
+        let readback = Buffer::new_slice::<u32>(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Download,
+                ..Default::default()
+            },
+            batch.cells.len() as u64,
+        )
+        .unwrap();
+        for buffer in &cells {
+            builder
+                .copy_buffer(CopyBufferInfo::buffers(staging.clone(), buffer.clone()))
+                .unwrap();
+        }
+
+        let layout = self.pipeline.layout();
+        // Reads the first buffer and writes the second, then the other way around.
+        let sets = [0, 1].map(|front| {
+            PersistentDescriptorSet::new(
+                &self.descriptor_set_allocator,
+                layout.set_layouts()[0].clone(),
+                [
+                    WriteDescriptorSet::buffer(0, cells[front].clone()),
+                    WriteDescriptorSet::buffer(1, cells[1 - front].clone()),
+                    WriteDescriptorSet::buffer(2, neighbors.clone()),
+                ],
+            )
+            .unwrap()
+        });
+        let groups = CHUNK_SIZE / GROUP_SIZE;
+        builder.bind_pipeline_compute(self.pipeline.clone());
+        for tick in 0..batch.ticks {
+            builder
+                .bind_descriptor_sets(
+                    PipelineBindPoint::Compute,
+                    layout.clone(),
+                    0,
+                    sets[tick as usize % 2].clone(),
+                )
+                .push_constants(
+                    layout.clone(),
+                    0,
+                    cs::PushConstants {
+                        tick: batch.first_tick + tick,
+                        falling_blocks: batch.falling_blocks as u32,
+                    },
+                )
+                .dispatch([batch.simulated as u32 * groups, groups, groups])
+                .unwrap();
+        }
+        builder
+            .copy_buffer(CopyBufferInfo::buffers(
+                cells[batch.ticks as usize % 2].clone(),
+                readback.clone(),
+            ))
+            .unwrap();
+        self.readback = Some((readback, batch));
+    }
+}
+
+mod cs {
+    vulkano_shaders::shader! {
+         ty: "compute",
+         path: "./assets/shader/liquids.glsl"
+    }
+}
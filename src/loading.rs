@@ -0,0 +1,174 @@
+use crate::settings::{AaMode, Settings, Upscaler};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage},
+    command_buffer::{
+        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, BufferImageCopy,
+        ClearColorImageInfo, CommandBufferUsage, CopyBufferToImageInfo,
+    },
+    device::Queue,
+    image::ImageAccess,
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    sync::GpuFuture,
+};
+use vulkano_util::renderer::DeviceImageView;
+
+/// Steps of starting up, shown as consecutive parts of the progress bar.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    /// Generating the world on the CPU.
+    World,
+    /// Creating the compute pipelines.
+    Pipelines,
+    /// Copying the world to the GPU.
+    Upload,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 3] = [Stage::World, Stage::Pipelines, Stage::Upload];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::World => "generating world",
+            Stage::Pipelines => "creating pipelines",
+            Stage::Upload => "uploading world",
+        }
+    }
+
+    fn color(&self) -> [u8; 4] {
+        match self {
+            Stage::World => [230, 140, 40, 255],
+            Stage::Pipelines => [70, 130, 230, 255],
+            Stage::Upload => [80, 200, 110, 255],
+        }
+    }
+}
+
+const BACKGROUND: [f32; 4] = [0.05, 0.05, 0.06, 1.0];
+const BAR_BACKGROUND: [u8; 4] = [40, 40, 45, 255];
+/// Size of the progress bar relative to the window.
+const BAR_WIDTH: f32 = 0.6;
+const BAR_HEIGHT: f32 = 0.02;
+
+/// Progress bar shown while starting up, drawn into the image the tracer renders to so it is
+/// placed over the frame the same way.
+pub struct LoadingScreen {
+    queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    settings: Settings,
+}
+
+impl LoadingScreen {
+    pub fn new(
+        queue: Arc<Queue>,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
+    ) -> LoadingScreen {
+        LoadingScreen {
+            queue,
+            memory_allocator,
+            command_buffer_allocator,
+            settings: Settings {
+                upscaler: Upscaler::Temporal,
+                aa_mode: AaMode::Off,
+                ..Settings::default()
+            },
+        }
+    }
+
+    /// Returns the settings placing the whole loading screen over the frame as it is.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Draws the progress of every stage, 0 to 1 in the order of `Stage::ALL`, into `view` once
+    /// `before_future` is done.
+    pub fn draw<F>(
+        &self,
+        before_future: F,
+        view: DeviceImageView,
+        progress: [f32; 3],
+    ) -> Box<dyn GpuFuture>
+    where
+        F: GpuFuture + 'static,
+    {
+        let [width, height] = view.image().dimensions().width_height();
+        let bar_size = [
+            ((width as f32 * BAR_WIDTH) as u32).max(Stage::ALL.len() as u32 * 2),
+            ((height as f32 * BAR_HEIGHT) as u32).max(1),
+        ];
+        let bar_min = [0, 1].map(|i| ([width, height][i] - bar_size[i]) / 2);
+        let stage_width = bar_size[0] / Stage::ALL.len() as u32;
+
+        // Every stage is a background rectangle with its filled part on top.
+        let mut rects = Vec::new();
+        for (index, stage) in Stage::ALL.iter().enumerate() {
+            let x = bar_min[0] + index as u32 * stage_width;
+            let filled = ((stage_width - 1) as f32 * progress[index].clamp(0.0, 1.0)) as u32;
+            rects.push((
+                [x, bar_min[1]],
+                [stage_width - 1, bar_size[1]],
+                BAR_BACKGROUND,
+            ));
+            rects.push(([x, bar_min[1]], [filled, bar_size[1]], stage.color()));
+        }
+        rects.retain(|(_, size, _)| size[0] > 0);
+
+        let mut builder = AutoCommandBufferBuilder::primary(
+            &self.command_buffer_allocator,
+            self.queue.queue_family_index(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        builder
+            .clear_color_image(ClearColorImageInfo {
+                clear_value: BACKGROUND.into(),
+                ..ClearColorImageInfo::image(view.image().clone())
+            })
+            .unwrap();
+        let pixels = Buffer::from_iter(
+            &self.memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::TRANSFER_SRC,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::Upload,
+                ..Default::default()
+            },
+            rects
+                .iter()
+                .flat_map(|(_, size, color)| {
+                    std::iter::repeat_n(*color, (size[0] * size[1]) as usize)
+                })
+                .collect::<Vec<_>>(),
+        )
+        .unwrap();
+        let mut buffer_offset = 0;
+        let regions: Vec<_> = rects
+            .iter()
+            .map(|&(min, size, _)| {
+                let region = BufferImageCopy {
+                    buffer_offset,
+                    image_subresource: view.image().subresource_layers(),
+                    image_offset: [min[0], min[1], 0],
+                    image_extent: [size[0], size[1], 1],
+                    ..Default::default()
+                };
+                buffer_offset += (size[0] * size[1]) as u64 * 4;
+                region
+            })
+            .collect();
+        builder
+            .copy_buffer_to_image(CopyBufferToImageInfo {
+                regions: regions.into(),
+                ..CopyBufferToImageInfo::buffer_image(pixels, view.image().clone())
+            })
+            .unwrap();
+        before_future
+            .then_execute(self.queue.clone(), builder.build().unwrap())
+            .unwrap()
+            .boxed()
+    }
+}
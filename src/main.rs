@@ -1,10 +1,42 @@
-use crate::app::FractalApp;
-use vulkano::{image::ImageUsage, swapchain::PresentMode, sync::GpuFuture};
-use vulkano_util::{
-    context::{VulkanoConfig, VulkanoContext},
-    renderer::{VulkanoWindowRenderer, DEFAULT_IMAGE_FORMAT},
-    window::{VulkanoWindows, WindowDescriptor},
+use crate::{
+    app::FractalApp,
+    cli::Args,
+    context::Context,
+    fractal_compute_pipeline::Controller,
+    latency::LatencyLimiter,
+    loading::{LoadingScreen, Stage},
+    place_over_frame::RenderPassPlaceOverFrame,
+    startup_menu::{Choice, MenuEntries},
+    window_renderer::WindowRenderer,
 };
+use egui_winit_vulkano::{Gui, GuiConfig};
+use rvengine::{
+    autosave::Autosave,
+    materials::Palette,
+    prefab::Prefab,
+    recent::{RecentImports, DEFAULT_RECENT_PATH},
+    scene::SceneFile,
+    structures::Structures,
+    tiled_map::{MapStream, TiledMap},
+    veins::{Veined, Veins},
+    world::{World, WORLD_SIZE},
+    worldgen,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Instant,
+};
+use vulkano::{
+    command_buffer::allocator::StandardCommandBufferAllocator,
+    descriptor_set::allocator::StandardDescriptorSetAllocator, image::ImageUsage,
+    memory::allocator::StandardMemoryAllocator, swapchain::PresentMode,
+};
+use vulkano_util::renderer::DEFAULT_IMAGE_FORMAT;
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
@@ -12,52 +44,402 @@ use winit::{
 };
 
 mod app;
+mod audio;
+mod autotune;
+mod cli;
+mod console;
+mod context;
+mod decal_buffer;
 mod fractal_compute_pipeline;
+mod frame_graph;
+mod frame_graph_pipeline;
+mod gpu;
+mod impostor_pipeline;
+mod latency;
+mod light_bake_buffer;
+mod liquids_pipeline;
+mod loading;
+mod memory_budget;
+mod permutations;
+mod picking;
 mod pixels_draw_pipeline;
 mod place_over_frame;
+mod post_process;
+mod probe_images;
+mod settings;
+mod shader_build;
+mod startup_menu;
+mod stats;
+mod swapchain;
+mod terrain_panel;
+mod timing;
+mod tools;
+mod trace_variants;
+mod window_renderer;
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 1 {
-        println!("no render distance");
-        return;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match tools::run(&args) {
+        Some(Ok(())) => return,
+        Some(Err(err)) => {
+            println!("{err}");
+            // Scripts running subcommands check the exit code.
+            std::process::exit(1);
+        }
+        None => {}
     }
-    let render_distance = match args[1].parse::<u32>() {
-        Ok(v) => v,
-        Err(err) => panic!("{}", err),
+    // Without arguments the world is picked from the startup menu instead.
+    let show_menu = args.is_empty();
+    let mut args = match Args::parse(args) {
+        Ok(args) => args,
+        Err(err) => {
+            println!("{err}\n{}\n{}", cli::USAGE, tools::USAGE);
+            return;
+        }
+    };
+    let palette = match &args.palette {
+        Some(path) => match Palette::load(path) {
+            Ok(palette) => palette,
+            Err(err) => {
+                println!("{err}");
+                return;
+            }
+        },
+        None => Palette::default(),
+    };
+    let veins = match &args.veins {
+        Some(path) => match Veins::load(path) {
+            Ok(veins) => veins,
+            Err(err) => {
+                println!("{err}");
+                return;
+            }
+        },
+        None => Veins::default(),
+    };
+    let structures = match &args.structures {
+        Some(path) => match Structures::load(path) {
+            Ok(structures) => structures,
+            Err(err) => {
+                println!("{err}");
+                return;
+            }
+        },
+        None => Structures::default(),
     };
+    let palette_path = args
+        .palette
+        .unwrap_or_else(|| cli::DEFAULT_PALETTE_PATH.to_string());
     let mut event_loop = EventLoop::new();
-    let context = VulkanoContext::new(VulkanoConfig::default());
-    let mut windows = VulkanoWindows::default();
-    let _id = windows.create_window(
+    let context = match Context::new(args.compute_gpu.as_deref()) {
+        Ok(context) => context,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    gpu::report_topology(&context, args.compute_gpu.as_deref());
+    let memory_allocator = Arc::new(StandardMemoryAllocator::new_default(
+        context.device().clone(),
+    ));
+    if let Some(count) = args.swapchain_images {
+        swapchain::set_image_count(count);
+    }
+    // Fifo is the only mode every surface supports, the preferred one is switched to once the
+    // surface exists and its supported modes are known.
+    let mut window_renderer = match WindowRenderer::new(
         &event_loop,
         &context,
-        &WindowDescriptor {
-            title: "RayVox".to_string(),
-            present_mode: PresentMode::Fifo,
-            ..Default::default()
-        },
-        |_| {},
-    );
+        memory_allocator.clone(),
+        "RayVox",
+        PresentMode::Fifo,
+        swapchain::apply_image_count,
+    ) {
+        Ok(window_renderer) => window_renderer,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
 
     let render_target_id = 0;
-    let primary_window_renderer = windows.get_primary_renderer_mut().unwrap();
+    let primary_window_renderer = &mut window_renderer;
+    let preferred_present_mode = args.present_mode.unwrap_or(PresentMode::Fifo);
+    let present_mode = swapchain::choose_present_mode(
+        preferred_present_mode,
+        context
+            .device()
+            .physical_device()
+            .surface_present_modes(&primary_window_renderer.surface())
+            .unwrap(),
+    );
+    if present_mode != preferred_present_mode {
+        println!(
+            "present mode {} isn't supported, using {}",
+            swapchain::present_mode_name(preferred_present_mode),
+            swapchain::present_mode_name(present_mode)
+        );
+    }
+    primary_window_renderer.set_present_mode(present_mode);
+    let mut recent = RecentImports::load(DEFAULT_RECENT_PATH).unwrap_or_else(|err| {
+        println!("{err}");
+        RecentImports::default()
+    });
+    if show_menu {
+        let entries = MenuEntries::find(&recent);
+        match startup_menu::choose(&mut event_loop, primary_window_renderer, &entries) {
+            Some(Choice::Scene(path)) => args.scene = Some(path),
+            Some(Choice::Generator(name)) => args.generator = name,
+            Some(Choice::Model(path)) => args.view = Some(path),
+            None => return,
+        }
+    }
+    let scene = match args.scene.as_ref().map(SceneFile::load).transpose() {
+        Ok(scene) => scene,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    let prefabs = match args.prefabs.iter().map(Prefab::load).collect() {
+        Ok(prefabs) => prefabs,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    let model = match args.view.as_ref().map(Prefab::load).transpose() {
+        Ok(model) => model,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    if !args.prefabs.is_empty() || args.view.is_some() {
+        for path in args.prefabs.iter().chain(&args.view) {
+            recent.add(path);
+        }
+        if let Err(err) = recent.save(DEFAULT_RECENT_PATH) {
+            println!("{err}");
+        }
+    }
+    let model_origin = match model.as_ref().map(Prefab::centered_in_world).transpose() {
+        Ok(origin) => origin,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    let map = match args.map.as_ref().map(TiledMap::open).transpose() {
+        Ok(map) => map,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    // Starts in the middle of the map.
+    let map_origin = map.as_ref().map(|map| {
+        let [x, _, z] = map.size();
+        map.window_around([x as f32 / 2.0, 0.0, z as f32 / 2.0])
+    });
+    let mut latency_limiter = args.low_latency.then(|| {
+        LatencyLimiter::new(
+            primary_window_renderer
+                .window()
+                .current_monitor()
+                .and_then(|monitor| monitor.refresh_rate_millihertz())
+                .unwrap_or(60_000),
+        )
+    });
 
     primary_window_renderer.add_additional_image_view(
         render_target_id,
         DEFAULT_IMAGE_FORMAT,
-        ImageUsage::SAMPLED | ImageUsage::STORAGE | ImageUsage::TRANSFER_DST,
+        ImageUsage::SAMPLED
+            | ImageUsage::STORAGE
+            | ImageUsage::TRANSFER_SRC
+            | ImageUsage::TRANSFER_DST,
     );
 
     let gfx_queue = context.graphics_queue();
+    let transfer_queue = context.transfer_queue().cloned();
+    if let Some(transfer_queue) = &transfer_queue {
+        println!(
+            "streaming the world on queue family {}",
+            transfer_queue.queue_family_index()
+        );
+    }
+    let command_buffer_allocator = Arc::new(StandardCommandBufferAllocator::new(
+        gfx_queue.device().clone(),
+        Default::default(),
+    ));
+    let descriptor_set_allocator = Arc::new(StandardDescriptorSetAllocator::new(
+        gfx_queue.device().clone(),
+    ));
+    let mut place_over_frame = RenderPassPlaceOverFrame::new(
+        gfx_queue.clone(),
+        memory_allocator.clone(),
+        command_buffer_allocator.clone(),
+        descriptor_set_allocator.clone(),
+        primary_window_renderer.swapchain_format(),
+    );
+    let loading_screen = LoadingScreen::new(
+        gfx_queue.clone(),
+        memory_allocator.clone(),
+        command_buffer_allocator.clone(),
+    );
+
+    // The world is generated and the pipelines are created on their own thread, so the window
+    // stays responsive and shows how far along they are.
+    let chunk_count = worldgen::all_chunks().len();
+    // Printed so the world can be generated again with `--seed`.
+    let seed = args.seed.unwrap_or_else(rand::random);
+    println!("world seed {seed}");
+    let generated_chunks = Arc::new(AtomicUsize::new(0));
+    let gpu_terrain = args.gpu_terrain;
+    // Known to exist, `Args::parse` checked the name.
+    let generator = worldgen::preset(&args.generator, seed, args.generator_size).unwrap();
+    let startup = {
+        let queue = gfx_queue.clone();
+        let generated_chunks = generated_chunks.clone();
+        let scene = scene.clone();
+        let model = model.clone();
+        let map = map.clone();
+        let post_shader = args.post_process.clone();
+        let trace_shader = args.trace_shader.clone();
+        thread::spawn(move || {
+            let mut world = World::new();
+            if let Some(scene) = &scene {
+                scene.load_world(&mut world)?;
+                generated_chunks.store(chunk_count, Ordering::Relaxed);
+            } else if let (Some(model), Some(origin)) = (&model, model_origin) {
+                world.paste_region(origin, model.size, &model.voxels);
+                generated_chunks.store(chunk_count, Ordering::Relaxed);
+            } else if let (Some(map), Some(origin)) = (&map, map_origin) {
+                world.set_region([0; 3], [WORLD_SIZE; 3], &map.window(origin)?);
+                generated_chunks.store(chunk_count, Ordering::Relaxed);
+            } else if gpu_terrain {
+                // Generated with the first frame instead.
+                generated_chunks.store(chunk_count, Ordering::Relaxed);
+            } else {
+                let start = Instant::now();
+                let generator = Veined {
+                    generator: &*generator,
+                    veins: &veins,
+                    seed,
+                };
+                worldgen::generate(
+                    &mut world,
+                    &generator,
+                    &worldgen::all_chunks(),
+                    |done, _| {
+                        generated_chunks.fetch_max(done, Ordering::Relaxed);
+                    },
+                );
+                let placed = structures.place(seed, &mut world);
+                println!("generated world in {:.2}s", start.elapsed().as_secs_f32());
+                if placed > 0 {
+                    println!("placed {placed} structures");
+                }
+            }
+            let mut controller = Controller::new(
+                queue,
+                transfer_queue,
+                memory_allocator,
+                command_buffer_allocator,
+                descriptor_set_allocator,
+                world,
+            );
+            controller.set_veins(veins);
+            controller.set_structures(structures);
+            controller.load_post_process(post_shader.as_deref().map(Path::new))?;
+            controller.load_trace_shader(trace_shader.as_deref().map(Path::new))?;
+            Ok::<_, String>(controller)
+        })
+    };
+    while !startup.is_finished() {
+        if !handle_loading_events(&mut event_loop, primary_window_renderer) {
+            return;
+        }
+        let generated = generated_chunks.load(Ordering::Relaxed) as f32 / chunk_count as f32;
+        let stage = if generated < 1.0 {
+            Stage::World
+        } else {
+            Stage::Pipelines
+        };
+        primary_window_renderer
+            .window()
+            .set_title(&format!("RayVox [{}...]", stage.name()));
+        render_loading(
+            primary_window_renderer,
+            render_target_id,
+            &mut place_over_frame,
+            &loading_screen,
+            [generated, 0.0, 0.0],
+        );
+    }
 
+    let mut controller = match startup.join().unwrap() {
+        Ok(controller) => controller,
+        Err(err) => {
+            println!("{err}");
+            return;
+        }
+    };
+    // Workgroup sizes are timed on the first start on a GPU and when asked to.
+    let device_name = gfx_queue
+        .device()
+        .physical_device()
+        .properties()
+        .device_name
+        .clone();
+    let group_size = autotune::load(Path::new(autotune::DEFAULT_AUTOTUNE_PATH), &device_name)
+        .unwrap_or_else(|err| {
+            println!("{err}");
+            None
+        });
+    if let Some(group_size) = group_size {
+        controller.set_trace_group_size(group_size);
+    }
     let mut app = FractalApp::new(
-        gfx_queue.clone(),
+        controller,
+        place_over_frame,
+        args.settings,
+        palette,
+        palette_path.into(),
+        prefabs,
+        args.stats.map(PathBuf::from),
+    );
+    app.set_autosave(Autosave::new(args.autosave, args.autosave_slots));
+    if args.autotune || group_size.is_none() {
+        app.start_autotune(device_name);
+    }
+    match (scene, model.zip(model_origin), map.zip(map_origin)) {
+        (Some(scene), _, _) => app.apply_scene(scene),
+        (None, Some((model, origin)), _) => app.view_model(&model.name, origin, model.size),
+        (None, None, Some((map, origin))) => app.explore_map(MapStream::new(map, origin)),
+        (None, None, None) if gpu_terrain => app.generate_terrain(seed),
+        (None, None, None) => app.set_world_seed(seed),
+    }
+    // Windows drawn over the frame, see `FractalApp::draw_ui`.
+    let mut gui = Gui::new(
+        &event_loop,
+        primary_window_renderer.surface(),
+        primary_window_renderer.graphics_queue(),
         primary_window_renderer.swapchain_format(),
-        render_distance,
+        GuiConfig {
+            is_overlay: true,
+            ..Default::default()
+        },
     );
+    // The loading screen stays up until the whole world reached the GPU.
+    let initial_pending_chunks = app.pending_chunks().max(1);
+    let mut uploading = true;
     loop {
-        if !handle_events(&mut event_loop, primary_window_renderer, &mut app) {
+        if let Some(latency_limiter) = &mut latency_limiter {
+            latency_limiter.begin_frame();
+        }
+        if !handle_events(&mut event_loop, primary_window_renderer, &mut app, &mut gui) {
             break;
         }
 
@@ -70,21 +452,92 @@ fn main() {
         }
 
         app.update_state_after_inputs(primary_window_renderer);
-        compute_then_render(primary_window_renderer, &mut app, render_target_id);
+        uploading &= app.pending_chunks() > 0 || app.generating_terrain();
+        let loading = uploading.then(|| {
+            let uploaded = 1.0 - app.pending_chunks() as f32 / initial_pending_chunks as f32;
+            (&loading_screen, [1.0, 1.0, uploaded])
+        });
+        compute_then_render(
+            primary_window_renderer,
+            &mut app,
+            &mut gui,
+            render_target_id,
+            loading,
+        );
+        if let Some(latency_limiter) = &mut latency_limiter {
+            // Paces from when the frame reached the display where VK_KHR_present_wait tells,
+            // from when presenting returned elsewhere.
+            primary_window_renderer.wait_presented(latency_limiter.refresh_interval() * 2);
+            latency_limiter.end_frame();
+        }
         app.reset_input_state();
         app.update_time();
-        primary_window_renderer.window().set_title(&format!(
-            "RayVox [fps: {:.2} dt: {:.2}]",
-            app.avg_fps(),
-            app.dt(),
-        ));
+        if app.photo_mode() {
+            primary_window_renderer
+                .window()
+                .set_title(&format!("RayVox [photo mode, spp: {}]", app.samples()));
+            continue;
+        }
+        primary_window_renderer.window().set_title(&window_title(&app));
     }
+    app.save_stats_on_exit();
+}
+
+/// Returns the title of the window outside of photo mode: how fast frames are made, what they
+/// are made with and what is being edited.
+fn window_title(app: &FractalApp) -> String {
+    let settings = app.settings();
+    let (material_index, material) = app.selected_material();
+    let (memory_used, memory_budget) = app.memory_usage();
+    let mut segments = vec![
+        format!("fps: {:.2}", app.avg_fps()),
+        format!("dt: {:.2}", app.dt()),
+        format!(
+            "preset: {}",
+            settings.preset.map_or("custom", |preset| preset.name())
+        ),
+        format!("steps: {}", settings.max_ray_steps),
+        format!("bounces: {}", settings.max_bounces),
+        format!(
+            "scale: {:.2} ({})",
+            settings.render_scale,
+            settings.upscaler.name()
+        ),
+        format!("spp: {}", app.samples()),
+        format!(
+            "material: {material_index} {:?} e: {} r: {} m: {}",
+            material.color, material.emissive, material.roughness, material.metalness
+        ),
+        format!(
+            "mirror: {} {:?}",
+            app.symmetry().axes_name(),
+            app.symmetry().origin
+        ),
+        format!(
+            "prefab: {}",
+            app.active_prefab().map_or("-", |prefab| &prefab.name)
+        ),
+        format!(
+            "selection: {}",
+            app.selection_box()
+                .map_or("-".to_string(), |(min, max)| format!("{min:?}..{max:?}"))
+        ),
+        format!("target: {}", app.target_info()),
+        format!("vram: {}/{} MiB", memory_used >> 20, memory_budget >> 20),
+        format!("flythrough: {}", app.flight_info()),
+    ];
+    // The pass breakdown is listed while the frame graph shows its bars.
+    if let Some(graph) = app.frame_graph() {
+        segments.push(format!("gpu: {}", graph.pass_summary()));
+    }
+    format!("RayVox [{}]", segments.join(" "))
 }
 
 fn handle_events(
     event_loop: &mut EventLoop<()>,
-    renderer: &mut VulkanoWindowRenderer,
+    renderer: &mut WindowRenderer,
     app: &mut FractalApp,
+    gui: &mut Gui,
 ) -> bool {
     let mut is_running = true;
 
@@ -103,16 +556,84 @@ fn handle_events(
             _ => (),
         }
 
+        // Clicks and keys going to a window over the frame don't also edit the world.
+        if let Event::WindowEvent { event, .. } = &event {
+            if gui.update(event) {
+                return;
+            }
+        }
         app.handle_input(renderer.window_size(), &event);
     });
 
     is_running && app.is_running()
 }
 
+/// Handles the window's events while starting up. Returns false once the window was closed.
+fn handle_loading_events(
+    event_loop: &mut EventLoop<()>,
+    renderer: &mut WindowRenderer,
+) -> bool {
+    let mut is_running = true;
+
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+
+        match &event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => is_running = false,
+                WindowEvent::Resized(..) | WindowEvent::ScaleFactorChanged { .. } => {
+                    renderer.resize()
+                }
+                _ => (),
+            },
+            Event::MainEventsCleared => *control_flow = ControlFlow::Exit,
+            _ => (),
+        }
+    });
+
+    is_running
+}
+
+/// Shows the loading screen with `progress` of every stage before the app exists.
+fn render_loading(
+    renderer: &mut WindowRenderer,
+    target_image_id: usize,
+    place_over_frame: &mut RenderPassPlaceOverFrame,
+    loading_screen: &LoadingScreen,
+    progress: [f32; 3],
+) {
+    if renderer.window_size().contains(&0.0) {
+        return;
+    }
+    let before_future = match renderer.acquire() {
+        Err(e) => {
+            println!("{e}");
+            return;
+        }
+        Ok(future) => future,
+    };
+    let image = renderer.get_additional_image_view(target_image_id);
+    let after_loading = loading_screen.draw(before_future, image.clone(), progress);
+    let after_renderpass_future = place_over_frame.render(
+        after_loading,
+        image,
+        renderer.swapchain_image_view(),
+        loading_screen.settings(),
+        None,
+        None,
+    );
+    renderer.present(after_renderpass_future, true);
+}
+
+/// Traces and presents a frame with the app's windows over it. While `loading` is given the
+/// loading screen with its progress is shown in place of the traced image, which keeps the world
+/// uploading.
 fn compute_then_render(
-    renderer: &mut VulkanoWindowRenderer,
+    renderer: &mut WindowRenderer,
     app: &mut FractalApp,
+    gui: &mut Gui,
     target_image_id: usize,
+    loading: Option<(&LoadingScreen, [f32; 3])>,
 ) {
     let before_pipeline_future = match renderer.acquire() {
         Err(e) => {
@@ -124,11 +645,26 @@ fn compute_then_render(
 
     let image = renderer.get_additional_image_view(target_image_id);
 
-    let after_compute = app.compute(image.clone()).join(before_pipeline_future);
+    let after_compute = app.compute(image.clone(), before_pipeline_future);
 
-    let after_renderpass_future =
-        app.place_over_frame
-            .render(after_compute, image, renderer.swapchain_image_view());
+    let after_renderpass_future = match loading {
+        Some((loading_screen, progress)) => {
+            let after_loading = loading_screen.draw(after_compute, image.clone(), progress);
+            app.place_over_frame.render(
+                after_loading,
+                image,
+                renderer.swapchain_image_view(),
+                loading_screen.settings(),
+                None,
+                None,
+            )
+        }
+        None => app.render_frame(after_compute, image, renderer.swapchain_image_view()),
+    };
 
-    renderer.present(after_renderpass_future, true);
+    gui.immediate_ui(|gui| app.draw_ui(&gui.context()));
+    let after_gui = gui.draw_on_image(after_renderpass_future, renderer.swapchain_image_view());
+
+    app.end_cpu_frame();
+    renderer.present(after_gui, true);
 }
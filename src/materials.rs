@@ -0,0 +1,221 @@
+use crate::{
+    agents::AGENT,
+    layers::{MAX_LAYERS, TERRAIN},
+    simulation::{GRAVEL, LAVA, PORTAL, SAND, STONE, WATER},
+};
+use std::{fs, path::Path};
+
+/// What `parse_material` expects.
+const MATERIAL_SYNTAX: &str =
+    "expected `type r g b emissive roughness [transparency [metalness [detail [layer]]]]`";
+
+/// Number of materials in a palette, voxel types index into it. Type 0 is empty space.
+pub const MATERIAL_COUNT: usize = 32;
+/// First voxel type past those with rules of their own, the types from here on only differ in
+/// their material.
+const FIRST_PLAIN_TYPE: usize = 16;
+
+/// Returns whether voxels of type `voxel` follow rules of their own: they flow, fall or send
+/// rays to another portal. Imported models never use these types, see `prefab::voxel_type`.
+pub fn has_rules(voxel: u32) -> bool {
+    matches!(voxel, WATER | LAVA | SAND | GRAVEL | PORTAL)
+}
+
+/// Returns the name of voxel types with rules of their own, `None` for the others.
+pub fn type_name(voxel: u32) -> Option<&'static str> {
+    match voxel {
+        0 => Some("empty"),
+        STONE => Some("stone"),
+        WATER => Some("water"),
+        LAVA => Some("lava"),
+        SAND => Some("sand"),
+        GRAVEL => Some("gravel"),
+        PORTAL => Some("portal"),
+        _ => None,
+    }
+}
+
+/// Surface look of one voxel type.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Material {
+    pub color: [f32; 3],
+    /// Light emitted as a multiple of `color`, independent of the lighting.
+    pub emissive: f32,
+    /// 0 reflects bounces like a mirror, 1 scatters them diffusely.
+    pub roughness: f32,
+    /// Fraction of the sun's light passing through, tinted by `color`, when shadows are colored.
+    /// 0 blocks it fully. Rays otherwise stop at the voxel like at any other.
+    pub transparency: f32,
+    /// 0 for dielectrics like stone and plastic, which reflect the sun untinted and weakly, 1 for
+    /// metals, which reflect it tinted by `color`.
+    pub metalness: f32,
+    /// Whether its faces are perturbed by the loaded `detail_normals::DetailNormals`.
+    pub detail_normals: bool,
+    /// Layer its voxels belong to, see `layers::Layers`.
+    pub layer: u32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            color: [0.5; 3],
+            emissive: 0.0,
+            roughness: 1.0,
+            transparency: 0.0,
+            metalness: 0.0,
+            detail_normals: false,
+            layer: TERRAIN,
+        }
+    }
+}
+
+/// The materials of every voxel type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub materials: [Material; MATERIAL_COUNT],
+}
+
+impl Palette {
+    /// Reads a palette saved with `save`. Types missing from the file keep their default.
+    pub fn load(path: impl AsRef<Path>) -> Result<Palette, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("can't read palette `{}`: {err}", path.display()))?;
+        let mut palette = Palette::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (index, material) = parse_material(line)
+                .map_err(|err| format!("{}:{}: {err}", path.display(), number + 1))?;
+            palette.materials[index] = material;
+        }
+        Ok(palette)
+    }
+
+    /// Writes the palette as one `type r g b emissive roughness transparency metalness detail
+    /// layer` line per voxel type.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let mut text =
+            String::from("# type r g b emissive roughness transparency metalness detail layer\n");
+        for (index, material) in self.materials.iter().enumerate().skip(1) {
+            text += &material_line(index, material);
+            text.push('\n');
+        }
+        fs::write(path, text)
+            .map_err(|err| format!("can't write palette `{}`: {err}", path.display()))
+    }
+}
+
+/// Parses a `type r g b emissive roughness [transparency [metalness [detail [layer]]]]` line
+/// into the voxel type and its material, `detail` being 1 for detail normals and 0 without.
+/// Values missing from lines written before they existed are 0.
+pub fn parse_material(line: &str) -> Result<(usize, Material), String> {
+    let values = line
+        .split_whitespace()
+        .map(|value| value.parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?;
+    let [index, r, g, b, emissive, roughness, ref optional @ ..] = values[..] else {
+        return Err(MATERIAL_SYNTAX.to_string());
+    };
+    if optional.len() > 4 {
+        return Err(MATERIAL_SYNTAX.to_string());
+    }
+    let optional = |i: usize| optional.get(i).copied().unwrap_or(0.0);
+    let [transparency, metalness, detail, layer] = [0, 1, 2, 3].map(optional);
+    if index < 1.0 || index >= MATERIAL_COUNT as f32 || index.fract() != 0.0 {
+        return Err(format!("invalid voxel type {index}"));
+    }
+    if layer < 0.0 || layer >= MAX_LAYERS as f32 || layer.fract() != 0.0 {
+        return Err(format!("invalid layer {layer}"));
+    }
+    Ok((
+        index as usize,
+        Material {
+            color: [r, g, b],
+            emissive,
+            roughness: roughness.clamp(0.0, 1.0),
+            transparency: transparency.clamp(0.0, 1.0),
+            metalness: metalness.clamp(0.0, 1.0),
+            detail_normals: detail != 0.0,
+            layer: layer as u32,
+        },
+    ))
+}
+
+/// Formats the material of voxel type `index` the way `parse_material` reads it.
+pub fn material_line(index: usize, material: &Material) -> String {
+    let [r, g, b] = material.color;
+    format!(
+        "{index} {r} {g} {b} {} {} {} {} {} {}",
+        material.emissive,
+        material.roughness,
+        material.transparency,
+        material.metalness,
+        material.detail_normals as u32,
+        material.layer
+    )
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        let mut materials = [Material::default(); MATERIAL_COUNT];
+        let colors = [
+            [0.9, 0.45, 0.45],
+            [0.45, 0.9, 0.45],
+            [0.45, 0.45, 0.9],
+            [0.3, 0.4, 0.5],
+            [0.6, 0.3, 0.9],
+            [0.1, 0.4, 0.6],
+            [0.8, 0.3, 0.6],
+            [0.2, 0.9, 0.4],
+            [0.1, 0.5, 0.8],
+        ];
+        for (material, color) in materials[1..].iter_mut().zip(colors) {
+            material.color = color;
+        }
+        // Darker shades of the same colors, so imported models folded onto these keep their
+        // parts apart.
+        for (material, color) in materials[FIRST_PLAIN_TYPE..]
+            .iter_mut()
+            .zip(colors.iter().cycle())
+        {
+            material.color = color.map(|c| c * 0.6);
+        }
+        materials[WATER as usize] = Material {
+            color: [0.15, 0.35, 0.8],
+            emissive: 0.0,
+            roughness: 0.1,
+            transparency: 0.6,
+            metalness: 0.0,
+            detail_normals: false,
+            layer: TERRAIN,
+        };
+        materials[LAVA as usize] = Material {
+            color: [1.0, 0.35, 0.05],
+            emissive: 2.0,
+            roughness: 1.0,
+            transparency: 0.0,
+            metalness: 0.0,
+            detail_normals: false,
+            layer: TERRAIN,
+        };
+        materials[SAND as usize].color = [0.85, 0.75, 0.5];
+        materials[GRAVEL as usize].color = [0.45, 0.43, 0.4];
+        // Only seen where a portal isn't linked.
+        materials[PORTAL as usize] = Material {
+            color: [0.6, 0.2, 1.0],
+            emissive: 0.5,
+            roughness: 1.0,
+            transparency: 0.0,
+            metalness: 0.0,
+            detail_normals: false,
+            layer: TERRAIN,
+        };
+        materials[AGENT as usize].color = [0.95, 0.8, 0.2];
+        Palette { materials }
+    }
+}
@@ -0,0 +1,87 @@
+use vulkano::{device::physical::PhysicalDevice, memory::MemoryHeapFlags};
+
+/// What an allocation tracked by `MemoryBudget` holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// The GPU copy of the world.
+    World,
+    /// Images and buffers sized to the traced extent.
+    Targets,
+    /// Buffers world flushes upload through.
+    Staging,
+    /// The light baked for the world.
+    LightBake,
+    /// The voxels of the voxel objects.
+    Objects,
+}
+
+impl MemoryKind {
+    pub const ALL: [MemoryKind; 5] = [
+        MemoryKind::World,
+        MemoryKind::Targets,
+        MemoryKind::Staging,
+        MemoryKind::LightBake,
+        MemoryKind::Objects,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryKind::World => "world",
+            MemoryKind::Targets => "targets",
+            MemoryKind::Staging => "staging",
+            MemoryKind::LightBake => "light_bake",
+            MemoryKind::Objects => "objects",
+        }
+    }
+}
+
+/// Bytes of GPU memory the renderer's large allocations take, against the size of the largest
+/// device local heap. Allocations are checked against it beforehand, so running out of memory
+/// is reported instead of panicking inside the allocator.
+pub struct MemoryBudget {
+    budget: u64,
+    /// Bytes in use, indexed by `MemoryKind`.
+    used: [u64; MemoryKind::ALL.len()],
+}
+
+impl MemoryBudget {
+    pub fn new(physical_device: &PhysicalDevice) -> MemoryBudget {
+        let budget = physical_device
+            .memory_properties()
+            .memory_heaps
+            .iter()
+            .filter(|heap| heap.flags.intersects(MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .max()
+            .unwrap_or(0);
+        MemoryBudget {
+            budget,
+            used: [0; MemoryKind::ALL.len()],
+        }
+    }
+
+    /// Returns the bytes available to the renderer.
+    pub fn budget(&self) -> u64 {
+        self.budget
+    }
+
+    /// Returns the bytes in use by allocations of every kind.
+    pub fn used(&self) -> u64 {
+        self.used.iter().sum()
+    }
+
+    /// Returns the bytes in use by allocations of `kind`.
+    pub fn used_by(&self, kind: MemoryKind) -> u64 {
+        self.used[kind as usize]
+    }
+
+    /// Returns whether replacing the allocations of `kind` by `bytes` keeps within the budget.
+    pub fn fits(&self, kind: MemoryKind, bytes: u64) -> bool {
+        self.used() - self.used_by(kind) + bytes <= self.budget
+    }
+
+    /// Records that the allocations of `kind` now take `bytes`.
+    pub fn set(&mut self, kind: MemoryKind, bytes: u64) {
+        self.used[kind as usize] = bytes;
+    }
+}
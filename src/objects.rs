@@ -0,0 +1,160 @@
+use crate::{
+    camera::{camera_to_world, world_to_camera},
+    layers::PROPS,
+    prefab::Prefab,
+    world::{World, WORLD_SIZE},
+};
+use rand::Rng;
+use std::{collections::HashMap, f32::consts::TAU, sync::Arc};
+
+/// Where an object lies in the world: its model is scaled by `scale` and turned by `rotation`
+/// around its center, which is then moved to `translation`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: [f32; 3],
+    /// Rotation in radians around z, then x, then y, like the camera's, see `camera::Camera`.
+    pub rotation: [f32; 3],
+    /// Edge length of the object's voxels in voxels of the world.
+    pub scale: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: [0.0; 3],
+            rotation: [0.0; 3],
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    /// Returns where the point `local`, relative to the center of the object's model in its
+    /// voxels, lies in the world.
+    pub fn to_world(&self, local: [f32; 3]) -> [f32; 3] {
+        let turned = camera_to_world(local.map(|c| c * self.scale), self.rotation);
+        [0, 1, 2].map(|i| turned[i] + self.translation[i])
+    }
+
+    /// Returns where the point `world` lies relative to the center of the object's model, in
+    /// its voxels, the inverse of `to_world`.
+    pub fn to_local(&self, world: [f32; 3]) -> [f32; 3] {
+        let offset = [0, 1, 2].map(|i| world[i] - self.translation[i]);
+        world_to_camera(offset, self.rotation).map(|c| c / self.scale)
+    }
+}
+
+/// A voxel model drawn by the tracer under a transform of its own, on top of the world's grid,
+/// so it can sit anywhere, turned any way and at any scale, and move without touching the world.
+/// Objects cast shadows and bounce light like voxels of the world, but don't darken the ambient
+/// occlusion of the voxels around them. Faces of turned objects are lit as if they faced the
+/// axis of the world closest to their normal.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelObject {
+    /// The object's model, shared by every object cloned from it. The tracer keeps one copy of
+    /// each shared model, so scattering many instances of a model costs no more voxel memory
+    /// than one.
+    pub model: Arc<Prefab>,
+    pub transform: Transform,
+    /// Layer the object belongs to as a whole, see `layers::Layers`. The layers of its voxels'
+    /// materials don't hide parts of it.
+    pub layer: u32,
+}
+
+/// Returns the index of the first object of `objects` sharing the model of each, so every model
+/// is packed once.
+fn first_users(objects: &[VoxelObject]) -> Vec<usize> {
+    let mut firsts = HashMap::new();
+    objects
+        .iter()
+        .enumerate()
+        .map(|(index, object)| *firsts.entry(Arc::as_ptr(&object.model)).or_insert(index))
+        .collect()
+}
+
+/// Packs the models of `objects` for the tracer one after another, a byte per voxel and four to
+/// a word. Models shared by several objects are packed once, see `model_starts`.
+pub fn voxel_words(objects: &[VoxelObject]) -> Vec<u32> {
+    let mut words = Vec::new();
+    for (index, first) in first_users(objects).into_iter().enumerate() {
+        if first != index {
+            continue;
+        }
+        words.extend(objects[index].model.voxels.chunks(4).map(|voxels| {
+            voxels
+                .iter()
+                .enumerate()
+                .fold(0, |word, (i, &voxel)| word | voxel.min(255) << (i * 8))
+        }));
+    }
+    words
+}
+
+/// Returns the word of `voxel_words` the model of every object of `objects` starts at.
+pub fn model_starts(objects: &[VoxelObject]) -> Vec<u32> {
+    let mut starts = Vec::with_capacity(objects.len());
+    let mut end = 0;
+    for (index, first) in first_users(objects).into_iter().enumerate() {
+        if first == index {
+            starts.push(end);
+            end += objects[index].model.voxels.len().div_ceil(4) as u32;
+        } else {
+            starts.push(starts[first]);
+        }
+    }
+    starts
+}
+
+/// Packs `objects` for the tracer as laid out by `Objects` in `compute.glsl`: their count and 3
+/// words of padding, then 12 words per object holding its rotation and scale, its
+/// translation and layer, and the size of its model and where in `voxel_words` it
+/// starts.
+pub fn object_words(objects: &[VoxelObject]) -> Vec<u32> {
+    let mut words = vec![objects.len() as u32, 0, 0, 0];
+    for (object, start) in objects.iter().zip(model_starts(objects)) {
+        let Transform {
+            translation,
+            rotation,
+            scale,
+        } = object.transform;
+        let size = object.model.size;
+        words.extend([rotation[0], rotation[1], rotation[2], scale].map(f32::to_bits));
+        words.extend(translation.map(f32::to_bits));
+        words.push(object.layer);
+        words.extend([size[0], size[1], size[2], start]);
+    }
+    words
+}
+
+/// Returns up to `count` objects of `model` standing on the ground of `world` at random columns,
+/// turned at random around y. Columns without ground are left out.
+pub fn scatter(
+    model: &Arc<Prefab>,
+    count: usize,
+    world: &World,
+    rng: &mut impl Rng,
+) -> Vec<VoxelObject> {
+    let mut objects = Vec::new();
+    for _ in 0..count {
+        let column = [0, 2].map(|_| rng.gen_range(1..WORLD_SIZE - 1) as f32 + 0.5);
+        let yaw = rng.gen_range(0.0..TAU);
+        let top = [column[0], WORLD_SIZE as f32 - 0.5, column[1]];
+        let Some(ground) = world.raycast(top, [0.0, -1.0, 0.0], WORLD_SIZE as f32) else {
+            continue;
+        };
+        objects.push(VoxelObject {
+            model: model.clone(),
+            transform: Transform {
+                translation: [
+                    column[0],
+                    (ground.position[1] + 1) as f32 + model.size[1] as f32 * 0.5,
+                    column[1],
+                ],
+                rotation: [0.0, yaw, 0.0],
+                scale: 1.0,
+            },
+            layer: PROPS,
+        });
+    }
+    objects
+}
@@ -0,0 +1,28 @@
+use std::{collections::HashMap, hash::Hash};
+
+/// Every variant of a set of pipelines the settings asked for so far, by what it is built for:
+/// the defines its shaders are compiled with and their specialization constants. Variants are
+/// built the first time they are asked for and kept, so switching settings back and forth only
+/// compiles each variant once.
+pub struct Permutations<K, P> {
+    /// Built variants, `None` for those which failed to build.
+    built: HashMap<K, Option<P>>,
+}
+
+impl<K: Copy + Eq + Hash, P> Permutations<K, P> {
+    /// Starts with the variant `key` built beforehand, like the pipelines built into the binary.
+    pub fn new(key: K, pipelines: P) -> Self {
+        Permutations {
+            built: HashMap::from([(key, Some(pipelines))]),
+        }
+    }
+
+    /// Returns the variant `key`, building it with `build` the first time. A variant failing to
+    /// build prints why once and is `None` from then on, for the caller to fall back from.
+    pub fn get(&mut self, key: K, build: impl FnOnce(K) -> Result<P, String>) -> Option<&P> {
+        self.built
+            .entry(key)
+            .or_insert_with(|| build(key).map_err(|err| println!("{err}")).ok())
+            .as_ref()
+    }
+}
@@ -0,0 +1,167 @@
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+};
+
+/// Number of frames the GPU gets to finish a pick before its buffer is written again.
+const PICK_RING_SIZE: usize = 3;
+
+/// Layout of the `Pick` buffer in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct PickData {
+    pixel: [i32; 2],
+    voxel: u32,
+    distance: f32,
+    position: [i32; 4],
+    normal: [i32; 4],
+    light: [f32; 4],
+    rays: u32,
+    truncated_rays: u32,
+}
+
+impl PickData {
+    /// Data requesting the voxel seen at `pixel`, or nothing for `None`.
+    fn request(pixel: Option<[u32; 2]>) -> Self {
+        PickData {
+            pixel: pixel.map_or([-1, -1], |pixel| pixel.map(|d| d as i32)),
+            voxel: 0,
+            distance: 0.0,
+            position: [0; 4],
+            normal: [0; 4],
+            light: [0.0; 4],
+            rays: 0,
+            truncated_rays: 0,
+        }
+    }
+}
+
+/// The voxel seen at a traced pixel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pick {
+    pub pixel: [u32; 2],
+    /// Voxel type, 0 when the pixel showed sky.
+    pub voxel: u32,
+    pub position: [i32; 3],
+    /// Normal of the face that was hit.
+    pub normal: [i32; 3],
+    /// Distance along the primary ray to the hit.
+    pub distance: f32,
+    /// Factor the lighting multiplied the surface's color with, `None` before it was lit.
+    pub light: Option<[f32; 3]>,
+}
+
+/// Primary rays of a traced frame, counted with `Settings::step_log`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RayCount {
+    pub rays: u32,
+    /// Rays which ran out of steps before reaching the render distance.
+    pub truncated: u32,
+}
+
+impl RayCount {
+    /// Returns the percentage of rays which ran out of steps.
+    pub fn truncated_percent(&self) -> f32 {
+        self.truncated as f32 * 100.0 / self.rays.max(1) as f32
+    }
+}
+
+/// Ring of host visible buffers the compute shader writes pick results and ray counts into. A
+/// result is read back once the GPU is done with its buffer, a frame or more later, so picking
+/// never waits on the GPU.
+pub struct PickRing {
+    buffers: [Subbuffer<PickData>; PICK_RING_SIZE],
+    pending: [bool; PICK_RING_SIZE],
+    counting: [bool; PICK_RING_SIZE],
+    next: usize,
+    result: Option<Pick>,
+    ray_counts: Vec<RayCount>,
+}
+
+impl PickRing {
+    pub fn new(memory_allocator: &StandardMemoryAllocator) -> Self {
+        let buffers = [(); PICK_RING_SIZE].map(|_| {
+            Buffer::from_data(
+                memory_allocator,
+                BufferCreateInfo {
+                    usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    usage: MemoryUsage::Download,
+                    ..Default::default()
+                },
+                PickData::request(None),
+            )
+            .unwrap()
+        });
+        PickRing {
+            buffers,
+            pending: [false; PICK_RING_SIZE],
+            counting: [false; PICK_RING_SIZE],
+            next: 0,
+            result: None,
+            ray_counts: Vec::new(),
+        }
+    }
+
+    /// Collects the picks and ray counts the GPU has finished and returns the buffer the coming
+    /// frame writes to, along with the data it has to be reset to before the trace. `count_rays`
+    /// tells whether the frame counts its rays.
+    pub fn next_frame(
+        &mut self,
+        pixel: Option<[u32; 2]>,
+        count_rays: bool,
+    ) -> (Subbuffer<PickData>, PickData) {
+        // Oldest first, so the newest finished pick wins.
+        for offset in 0..PICK_RING_SIZE {
+            let slot = (self.next + offset) % PICK_RING_SIZE;
+            if !self.pending[slot] && !self.counting[slot] {
+                continue;
+            }
+            // Fails without blocking while the GPU still uses the buffer.
+            let Ok(data) = self.buffers[slot].read() else {
+                continue;
+            };
+            if self.counting[slot] {
+                self.ray_counts.push(RayCount {
+                    rays: data.rays,
+                    truncated: data.truncated_rays,
+                });
+                self.counting[slot] = false;
+            }
+            if self.pending[slot] {
+                self.result = Some(Pick {
+                    pixel: data.pixel.map(|d| d as u32),
+                    voxel: data.voxel,
+                    position: [data.position[0], data.position[1], data.position[2]],
+                    normal: [data.normal[0], data.normal[1], data.normal[2]],
+                    distance: data.distance,
+                    light: (data.light[3] > 0.0).then_some([
+                        data.light[0],
+                        data.light[1],
+                        data.light[2],
+                    ]),
+                });
+                self.pending[slot] = false;
+            }
+        }
+
+        // A pick still in flight after a whole trip around the ring is dropped.
+        let slot = self.next;
+        self.next = (self.next + 1) % PICK_RING_SIZE;
+        self.pending[slot] = pixel.is_some();
+        self.counting[slot] = count_rays;
+        (self.buffers[slot].clone(), PickData::request(pixel))
+    }
+
+    /// Returns the most recent pick that was read back since the last call.
+    pub fn take(&mut self) -> Option<Pick> {
+        self.result.take()
+    }
+
+    /// Returns the ray counts of the frames read back since the last call, oldest first.
+    pub fn take_ray_counts(&mut self) -> Vec<RayCount> {
+        std::mem::take(&mut self.ray_counts)
+    }
+}
@@ -7,6 +7,7 @@
 // notice may not be copied, modified, or distributed except
 // according to those terms.
 
+use crate::permutations::Permutations;
 use std::sync::Arc;
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
@@ -70,7 +71,10 @@ pub fn textured_quad(width: f32, height: f32) -> (Vec<TexturedVertex>, Vec<u32>)
 pub struct PixelsDrawPipeline {
     gfx_queue: Arc<Queue>,
     subpass: Subpass,
-    pipeline: Arc<GraphicsPipeline>,
+    /// The pipeline without FXAA, drawn with when the one with it fails to build.
+    plain: Arc<GraphicsPipeline>,
+    /// The pipeline with FXAA and the one without, by whether FXAA is on.
+    pipelines: Permutations<bool, Arc<GraphicsPipeline>>,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
     descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
     vertices: Subbuffer<[TexturedVertex]>,
@@ -113,24 +117,14 @@ impl PixelsDrawPipeline {
         )
         .unwrap();
 
-        let pipeline = {
-            let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
-            let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
-            GraphicsPipeline::start()
-                .vertex_input_state(TexturedVertex::per_vertex())
-                .vertex_shader(vs.entry_point("main").unwrap(), ())
-                .input_assembly_state(InputAssemblyState::new())
-                .fragment_shader(fs.entry_point("main").unwrap(), ())
-                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-                .render_pass(subpass.clone())
-                .build(gfx_queue.device().clone())
-                .unwrap()
-        };
+        // FXAA is off unless the settings say otherwise, the other variant is built once it is on.
+        let pipeline = build_pipeline(&gfx_queue, &subpass, false).unwrap();
 
         PixelsDrawPipeline {
             gfx_queue,
             subpass,
-            pipeline,
+            plain: pipeline.clone(),
+            pipelines: Permutations::new(false, pipeline),
             command_buffer_allocator,
             descriptor_set_allocator,
             vertices: vertex_buffer,
@@ -140,9 +134,10 @@ impl PixelsDrawPipeline {
 
     fn create_descriptor_set(
         &self,
+        pipeline: &GraphicsPipeline,
         image: Arc<dyn ImageViewAbstract>,
     ) -> Arc<PersistentDescriptorSet> {
-        let layout = self.pipeline.layout().set_layouts().get(0).unwrap();
+        let layout = pipeline.layout().set_layouts().first().unwrap();
         let sampler = Sampler::new(
             self.gfx_queue.device().clone(),
             SamplerCreateInfo {
@@ -167,11 +162,14 @@ impl PixelsDrawPipeline {
         .unwrap()
     }
 
-    /// Draws input `image` over a quad of size -1.0 to 1.0.
+    /// Draws input `image` over a quad of size -1.0 to 1.0. Only the `uv_scale` fraction of the
+    /// image is sampled, optionally smoothing its edges with FXAA.
     pub fn draw(
-        &self,
+        &mut self,
         viewport_dimensions: [u32; 2],
         image: Arc<dyn ImageViewAbstract>,
+        uv_scale: [f32; 2],
+        fxaa: bool,
     ) -> SecondaryAutoCommandBuffer {
         let mut builder = AutoCommandBufferBuilder::secondary(
             &self.command_buffer_allocator,
@@ -183,7 +181,14 @@ impl PixelsDrawPipeline {
             },
         )
         .unwrap();
-        let desc_set = self.create_descriptor_set(image);
+        let (queue, subpass) = (&self.gfx_queue, &self.subpass);
+        let pipeline = self
+            .pipelines
+            .get(fxaa, |fxaa| build_pipeline(queue, subpass, fxaa))
+            .cloned()
+            .unwrap_or_else(|| self.plain.clone());
+        let desc_set = self.create_descriptor_set(&pipeline, image);
+        let push_constants = fs::PushConstants { uv_scale };
         builder
             .set_viewport(
                 0,
@@ -193,13 +198,14 @@ impl PixelsDrawPipeline {
                     depth_range: 0.0..1.0,
                 }],
             )
-            .bind_pipeline_graphics(self.pipeline.clone())
+            .bind_pipeline_graphics(pipeline.clone())
             .bind_descriptor_sets(
                 PipelineBindPoint::Graphics,
-                self.pipeline.layout().clone(),
+                pipeline.layout().clone(),
                 0,
                 desc_set,
             )
+            .push_constants(pipeline.layout().clone(), 0, push_constants)
             .bind_vertex_buffers(0, self.vertices.clone())
             .bind_index_buffer(self.indices.clone())
             .draw_indexed(self.indices.len() as u32, 1, 0, 0, 0)
@@ -208,6 +214,28 @@ impl PixelsDrawPipeline {
     }
 }
 
+/// Builds the pipeline drawing into `subpass`, smoothing edges with FXAA when `fxaa`.
+fn build_pipeline(
+    gfx_queue: &Arc<Queue>,
+    subpass: &Subpass,
+    fxaa: bool,
+) -> Result<Arc<GraphicsPipeline>, String> {
+    let vs = vs::load(gfx_queue.device().clone()).map_err(|err| err.to_string())?;
+    let fs = fs::load(gfx_queue.device().clone()).map_err(|err| err.to_string())?;
+    GraphicsPipeline::start()
+        .vertex_input_state(TexturedVertex::per_vertex())
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .input_assembly_state(InputAssemblyState::new())
+        .fragment_shader(
+            fs.entry_point("main").unwrap(),
+            fs::SpecializationConstants { FXAA: fxaa as u32 },
+        )
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .render_pass(subpass.clone())
+        .build(gfx_queue.device().clone())
+        .map_err(|err| format!("can't build the FXAA {fxaa} present pipeline: {err}"))
+}
+
 mod vs {
     vulkano_shaders::shader! {
         ty: "vertex",
@@ -237,8 +265,61 @@ mod fs {
 
             layout(set = 0, binding = 0) uniform sampler2D tex;
 
+            // Whether edges are smoothed with FXAA, one pipeline each.
+            layout(constant_id = 0) const uint FXAA = 0;
+
+            layout(push_constant) uniform PushConstants {
+                vec2 uv_scale;
+            } constants;
+
+            float luma(vec3 color) {
+                return dot(color, vec3(0.299, 0.587, 0.114));
+            }
+
+            // A cheap FXAA variant: blends along the edge direction found from the luma gradient.
+            vec3 fxaa(vec2 uv, vec2 texel) {
+                vec3 rgb_nw = texture(tex, uv + vec2(-1.0, -1.0) * texel).rgb;
+                vec3 rgb_ne = texture(tex, uv + vec2(1.0, -1.0) * texel).rgb;
+                vec3 rgb_sw = texture(tex, uv + vec2(-1.0, 1.0) * texel).rgb;
+                vec3 rgb_se = texture(tex, uv + vec2(1.0, 1.0) * texel).rgb;
+                vec3 rgb_m = texture(tex, uv).rgb;
+                float luma_nw = luma(rgb_nw);
+                float luma_ne = luma(rgb_ne);
+                float luma_sw = luma(rgb_sw);
+                float luma_se = luma(rgb_se);
+                float luma_m = luma(rgb_m);
+                float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+                float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+                vec2 dir = vec2(
+                    -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+                    (luma_nw + luma_sw) - (luma_ne + luma_se)
+                );
+                float dir_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.03125, 1.0 / 128.0);
+                float rcp_dir_min = 1.0 / (min(abs(dir.x), abs(dir.y)) + dir_reduce);
+                dir = clamp(dir * rcp_dir_min, vec2(-8.0), vec2(8.0)) * texel;
+
+                vec3 rgb_a = 0.5 * (
+                    texture(tex, uv + dir * (1.0 / 3.0 - 0.5)).rgb +
+                    texture(tex, uv + dir * (2.0 / 3.0 - 0.5)).rgb
+                );
+                vec3 rgb_b = rgb_a * 0.5 + 0.25 * (
+                    texture(tex, uv - dir * 0.5).rgb +
+                    texture(tex, uv + dir * 0.5).rgb
+                );
+                float luma_b = luma(rgb_b);
+                return (luma_b < luma_min || luma_b > luma_max) ? rgb_a : rgb_b;
+            }
+
             void main() {
-                f_color = texture(tex, v_tex_coords);
+                vec2 texel = 1.0 / vec2(textureSize(tex, 0));
+                // Stay away from the untraced texels past the scaled region when filtering.
+                vec2 uv = min(v_tex_coords * constants.uv_scale, constants.uv_scale - 0.5 * texel);
+                if (FXAA != 0) {
+                    f_color = vec4(fxaa(uv, texel), 1.0);
+                } else {
+                    f_color = texture(tex, uv);
+                }
             }
         ",
     }
@@ -1,4 +1,10 @@
-use crate::pixels_draw_pipeline::PixelsDrawPipeline;
+use crate::{
+    frame_graph::FrameGraph,
+    frame_graph_pipeline::FrameGraphPipeline,
+    impostor_pipeline::{ImpostorFrame, ImpostorPipeline},
+    pixels_draw_pipeline::PixelsDrawPipeline,
+    settings::{AaMode, Settings, Upscaler},
+};
 use std::sync::Arc;
 use vulkano::{
     command_buffer::{
@@ -9,7 +15,7 @@ use vulkano::{
     device::Queue,
     format::Format,
     image::ImageAccess,
-    memory::allocator::MemoryAllocator,
+    memory::allocator::StandardMemoryAllocator,
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
     sync::GpuFuture,
 };
@@ -20,13 +26,15 @@ pub struct RenderPassPlaceOverFrame {
     gfx_queue: Arc<Queue>,
     render_pass: Arc<RenderPass>,
     pixels_draw_pipeline: PixelsDrawPipeline,
+    impostor_pipeline: ImpostorPipeline,
+    frame_graph_pipeline: FrameGraphPipeline,
     command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
 }
 
 impl RenderPassPlaceOverFrame {
     pub fn new(
         gfx_queue: Arc<Queue>,
-        memory_allocator: &impl MemoryAllocator,
+        memory_allocator: Arc<StandardMemoryAllocator>,
         command_buffer_allocator: Arc<StandardCommandBufferAllocator>,
         descriptor_set_allocator: Arc<StandardDescriptorSetAllocator>,
         output_format: Format,
@@ -49,6 +57,20 @@ impl RenderPassPlaceOverFrame {
         .unwrap();
         let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
         let pixels_draw_pipeline = PixelsDrawPipeline::new(
+            gfx_queue.clone(),
+            subpass.clone(),
+            &memory_allocator,
+            command_buffer_allocator.clone(),
+            descriptor_set_allocator.clone(),
+        );
+        let impostor_pipeline = ImpostorPipeline::new(
+            gfx_queue.clone(),
+            subpass.clone(),
+            memory_allocator.clone(),
+            command_buffer_allocator.clone(),
+            descriptor_set_allocator.clone(),
+        );
+        let frame_graph_pipeline = FrameGraphPipeline::new(
             gfx_queue.clone(),
             subpass,
             memory_allocator,
@@ -60,17 +82,23 @@ impl RenderPassPlaceOverFrame {
             gfx_queue,
             render_pass,
             pixels_draw_pipeline,
+            impostor_pipeline,
+            frame_graph_pipeline,
             command_buffer_allocator,
         }
     }
 
     /// Places the view exactly over the target swapchain image. The texture draw pipeline uses a
-    /// quad onto which it places the view.
+    /// quad onto which it places the view. The billboards of `impostors` and then `frame_graph`
+    /// are drawn over it when given.
     pub fn render<F>(
-        &self,
+        &mut self,
         before_future: F,
         view: DeviceImageView,
         target: SwapchainImageView,
+        settings: &Settings,
+        impostors: Option<&ImpostorFrame>,
+        frame_graph: Option<&FrameGraph>,
     ) -> Box<dyn GpuFuture>
     where
         F: GpuFuture + 'static,
@@ -78,6 +106,18 @@ impl RenderPassPlaceOverFrame {
         // Get dimensions.
         let img_dims = target.image().dimensions();
 
+        // Only the top left part of the view was traced when rendering below native resolution,
+        // unless the temporal upscaler already filled all of it.
+        let view_dims = view.image().dimensions().width_height();
+        let traced_dims = match settings.upscaler {
+            Upscaler::Bilinear => settings.scaled_extent(view_dims),
+            Upscaler::Temporal => view_dims,
+        };
+        let uv_scale = [
+            traced_dims[0] as f32 / view_dims[0] as f32,
+            traced_dims[1] as f32 / view_dims[1] as f32,
+        ];
+
         // Create framebuffer (must be in same order as render pass description in `new`.
         let framebuffer = Framebuffer::new(
             self.render_pass.clone(),
@@ -108,12 +148,27 @@ impl RenderPassPlaceOverFrame {
             .unwrap();
 
         // Create secondary command buffer from texture pipeline & send draw commands.
-        let cb = self
-            .pixels_draw_pipeline
-            .draw(img_dims.width_height(), view);
+        let cb = self.pixels_draw_pipeline.draw(
+            img_dims.width_height(),
+            view,
+            uv_scale,
+            settings.aa_mode == AaMode::Fxaa,
+        );
 
         // Execute above commands (subpass).
         command_buffer_builder.execute_commands(cb).unwrap();
+        if let Some(impostors) = impostors {
+            let cb = self
+                .impostor_pipeline
+                .draw(img_dims.width_height(), impostors);
+            command_buffer_builder.execute_commands(cb).unwrap();
+        }
+        if let Some(frame_graph) = frame_graph {
+            let cb = self
+                .frame_graph_pipeline
+                .draw(img_dims.width_height(), frame_graph);
+            command_buffer_builder.execute_commands(cb).unwrap();
+        }
 
         // End render pass.
         command_buffer_builder.end_render_pass().unwrap();
@@ -0,0 +1,110 @@
+use std::f32::consts::FRAC_PI_2;
+
+/// Portals the renderer keeps room for.
+pub const MAX_PORTALS: usize = 16;
+
+/// A box of portal voxels. Rays and the camera entering it come out of `target`, moved by the
+/// offset between the centers of both boxes and turned around y by `quarter_turns`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Portal {
+    /// Lower (inclusive) corner.
+    pub min: [i32; 3],
+    /// Upper (exclusive) corner.
+    pub max: [i32; 3],
+    /// Index of the portal things come out of.
+    pub target: usize,
+    /// Quarter turns around y, in the direction turning the camera's yaw up turns the view.
+    pub quarter_turns: u32,
+}
+
+impl Portal {
+    pub fn contains(&self, position: [i32; 3]) -> bool {
+        (0..3).all(|i| position[i] >= self.min[i] && position[i] < self.max[i])
+    }
+
+    fn center(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| (self.min[i] + self.max[i]) as f32 * 0.5)
+    }
+}
+
+/// Pairs of linked portals.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Portals {
+    portals: Vec<Portal>,
+}
+
+impl Portals {
+    pub fn new() -> Portals {
+        Portals::default()
+    }
+
+    pub fn portals(&self) -> &[Portal] {
+        &self.portals
+    }
+
+    /// Links the boxes `a` and `b`, each given by its lower (inclusive) and upper (exclusive)
+    /// corner, both ways. Going through `a` turns things by `quarter_turns`, going back through
+    /// `b` turns them back.
+    pub fn link(
+        &mut self,
+        a: ([i32; 3], [i32; 3]),
+        b: ([i32; 3], [i32; 3]),
+        quarter_turns: u32,
+    ) -> Result<(), String> {
+        if self.portals.len() + 2 > MAX_PORTALS {
+            return Err(format!("there can't be more than {MAX_PORTALS} portals"));
+        }
+        let first = self.portals.len();
+        self.portals.push(Portal {
+            min: a.0,
+            max: a.1,
+            target: first + 1,
+            quarter_turns: quarter_turns % 4,
+        });
+        self.portals.push(Portal {
+            min: b.0,
+            max: b.1,
+            target: first,
+            quarter_turns: (4 - quarter_turns % 4) % 4,
+        });
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.portals.clear();
+    }
+
+    /// Returns the index of the first portal containing the voxel at `position`.
+    pub fn find(&self, position: [i32; 3]) -> Option<usize> {
+        self.portals
+            .iter()
+            .position(|portal| portal.contains(position))
+    }
+
+    /// Moves `point` and `direction` from `portal` to its target. The point keeps its place
+    /// relative to the center of the box.
+    pub fn teleport(
+        &self,
+        portal: usize,
+        point: [f32; 3],
+        direction: [f32; 3],
+    ) -> ([f32; 3], [f32; 3]) {
+        let from = &self.portals[portal];
+        let center = from.center();
+        let to = self.portals[from.target].center();
+        let local = rotate_y([0, 1, 2].map(|i| point[i] - center[i]), from.quarter_turns);
+        (
+            [0, 1, 2].map(|i| to[i] + local[i]),
+            rotate_y(direction, from.quarter_turns),
+        )
+    }
+}
+
+/// Rotates `v` around y the way the compute shader's `rotate2d` turns xz, by `quarter_turns`
+/// times 90°.
+pub fn rotate_y(v: [f32; 3], quarter_turns: u32) -> [f32; 3] {
+    let (sin, cos) = (quarter_turns as f32 * FRAC_PI_2).sin_cos();
+    // Exact for quarter turns.
+    let (sin, cos) = (sin.round(), cos.round());
+    [v[0] * cos - v[2] * sin, v[1], v[2] * cos + v[0] * sin]
+}
@@ -0,0 +1,148 @@
+use crate::fractal_compute_pipeline::{TraceOutputs, TILE_SIZE};
+use crate::shader_build;
+use std::{fs, path::Path, sync::Arc};
+use vulkano::{
+    buffer::BufferContents,
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    descriptor_set::{
+        allocator::StandardDescriptorSetAllocator, PersistentDescriptorSet, WriteDescriptorSet,
+    },
+    device::Device,
+    pipeline::{ComputePipeline, Pipeline, PipelineBindPoint},
+};
+use vulkano_util::renderer::DeviceImageView;
+
+/// Declarations every post-process shader starts with, see `assets/shader/post.glsl`.
+const HEADER: &str = include_str!("../assets/shader/post.glsl");
+
+/// Post-process shaders built into the renderer, by the name which loads them instead of a path.
+const BUILTIN: &[(&str, &str)] = &[("toon", include_str!("../assets/post/toon.glsl"))];
+
+/// Bindings of the post-process shader's descriptor set.
+pub const BINDING_COLOR: u32 = 0;
+pub const BINDING_MOTION: u32 = 1;
+pub const BINDING_OUTPUT: u32 = 2;
+pub const BINDING_MATERIAL: u32 = 3;
+pub const BINDING_DEPTH: u32 = 4;
+pub const BINDING_NORMAL: u32 = 5;
+
+/// Layout of `PushConstants` in `post.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct PushConstants {
+    pub resolution: [u32; 2],
+    pub trace_resolution: [u32; 2],
+    pub time: f32,
+    pub frame: u32,
+}
+
+/// A user post-process shader, run over the finished frame.
+pub struct PostProcess {
+    pub pipeline: Arc<ComputePipeline>,
+    /// Whether the shader reads the motion image, which it then has to be bound to.
+    pub uses_motion: bool,
+    /// Whether the shader reads the material image, which the trace pass then has to write.
+    pub uses_material: bool,
+    /// Whether the shader reads the depth image, which the trace pass then has to write.
+    pub uses_depth: bool,
+    /// Whether the shader reads the normal image, which the trace pass then has to write.
+    pub uses_normal: bool,
+}
+
+impl PostProcess {
+    /// Whether the shader reads any of the images the trace pass only writes on request.
+    pub fn uses_outputs(&self) -> bool {
+        self.uses_material || self.uses_depth || self.uses_normal
+    }
+
+    /// Records running the shader over the finished frame in `input` into `output`, binding the
+    /// images of `outputs` it reads.
+    pub fn record(
+        &self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        descriptor_set_allocator: &StandardDescriptorSetAllocator,
+        input: DeviceImageView,
+        output: DeviceImageView,
+        outputs: TraceOutputs,
+        push_constants: PushConstants,
+    ) {
+        let layout = self.pipeline.layout();
+        let mut writes = vec![
+            WriteDescriptorSet::image_view(BINDING_COLOR, input),
+            WriteDescriptorSet::image_view(BINDING_OUTPUT, output),
+        ];
+        for (used, binding, image) in [
+            (self.uses_motion, BINDING_MOTION, outputs.motion),
+            (self.uses_material, BINDING_MATERIAL, outputs.material),
+            (self.uses_depth, BINDING_DEPTH, outputs.depth),
+            (self.uses_normal, BINDING_NORMAL, outputs.normal),
+        ] {
+            if used {
+                writes.push(WriteDescriptorSet::image_view(binding, image));
+            }
+        }
+        let set = PersistentDescriptorSet::new(
+            descriptor_set_allocator,
+            layout.set_layouts().first().unwrap().clone(),
+            writes,
+        )
+        .unwrap();
+        let [width, height] = push_constants.resolution;
+        builder
+            .bind_pipeline_compute(self.pipeline.clone())
+            .bind_descriptor_sets(PipelineBindPoint::Compute, layout.clone(), 0, set)
+            .push_constants(layout.clone(), 0, push_constants)
+            .dispatch([width.div_ceil(TILE_SIZE), height.div_ceil(TILE_SIZE), 1])
+            .unwrap();
+    }
+}
+
+/// Compiles the post-process shader at `path` for `device`. A GLSL file defines
+/// `vec4 postProcess(ivec2 pixel)` and is appended to the header, with errors reported at lines
+/// of the file itself. It may include files next to it, see `shader_build::compile_text`. A
+/// `.spv` file is loaded as it is and has to be built from the header already. The name of a
+/// built-in shader, `toon`, compiles that one.
+pub fn compile(device: &Arc<Device>, path: &Path) -> Result<PostProcess, String> {
+    let builtin = BUILTIN
+        .iter()
+        .find(|(name, _)| path.as_os_str() == *name)
+        .map(|(_, source)| source.to_string());
+    let module = if path.extension().is_some_and(|extension| extension == "spv") {
+        shader_build::load_spirv(device, path)?
+    } else {
+        let source = match builtin {
+            Some(source) => source,
+            None => fs::read_to_string(path).map_err(|err| {
+                format!("can't read post-process shader `{}`: {err}", path.display())
+            })?,
+        };
+        let source = format!("{HEADER}\n#line 1\n{source}");
+        shader_build::compile_text(device, &path.display().to_string(), &source, &[])?
+    };
+    let pipeline = ComputePipeline::new(
+        device.clone(),
+        module.entry_point("main").unwrap(),
+        &(),
+        None,
+        |_| {},
+    )
+    .map_err(|err| format!("invalid post-process shader `{}`: {err}", path.display()))?;
+    let uses_binding = |binding| {
+        pipeline
+            .layout()
+            .set_layouts()
+            .first()
+            .is_some_and(|layout| layout.bindings().contains_key(&binding))
+    };
+    let uses_motion = uses_binding(BINDING_MOTION);
+    let uses_material = uses_binding(BINDING_MATERIAL);
+    let uses_depth = uses_binding(BINDING_DEPTH);
+    let uses_normal = uses_binding(BINDING_NORMAL);
+    Ok(PostProcess {
+        pipeline,
+        uses_motion,
+        uses_material,
+        uses_depth,
+        uses_normal,
+    })
+}
@@ -0,0 +1,119 @@
+use crate::{
+    materials::{self, MATERIAL_COUNT},
+    vox,
+    world::WORLD_SIZE,
+};
+use std::{fs, path::Path};
+
+/// A small voxel model stamped into the world as a whole.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prefab {
+    pub name: String,
+    pub size: [u32; 3],
+    /// Voxel types with z varying fastest, as taken by `World::paste_region`. 0 is empty.
+    pub voxels: Vec<u32>,
+}
+
+impl Prefab {
+    /// Loads the first model of a MagicaVoxel `.vox` file. Palette indices are folded onto the
+    /// voxel types of the material palette and MagicaVoxel's z up becomes y up.
+    pub fn load(path: impl AsRef<Path>) -> Result<Prefab, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .map_err(|err| format!("can't read prefab `{}`: {err}", path.display()))?;
+        let name = path
+            .file_stem()
+            .map_or_else(String::new, |stem| stem.to_string_lossy().into_owned());
+        Prefab::from_vox(name, &bytes)
+            .map_err(|err| format!("invalid prefab `{}`: {err}", path.display()))
+    }
+
+    /// Parses the first model of the `.vox` file `bytes`.
+    pub fn from_vox(name: String, bytes: &[u8]) -> Result<Prefab, String> {
+        let mut size = None;
+        for chunk in vox::chunks(bytes)? {
+            let vox::Chunk { id, mut content } = chunk?;
+            match id {
+                b"SIZE" if size.is_none() => {
+                    let [x, z, y] = [content.u32()?, content.u32()?, content.u32()?];
+                    // z up in MagicaVoxel, y up here.
+                    size = Some([x, y, z]);
+                }
+                b"XYZI" => {
+                    let size: [u32; 3] = size.ok_or("XYZI chunk before SIZE")?;
+                    let mut voxels = vec![0; (size[0] * size[1] * size[2]) as usize];
+                    let count = content.u32()? as usize;
+                    let data = content.take(count.saturating_mul(4))?;
+                    for voxel in data.chunks_exact(4) {
+                        let [x, z, y, index] =
+                            [voxel[0], voxel[1], voxel[2], voxel[3]].map(u32::from);
+                        if x >= size[0] || y >= size[1] || z >= size[2] || index == 0 {
+                            continue;
+                        }
+                        voxels[((x * size[1] + y) * size[2] + z) as usize] = voxel_type(index);
+                    }
+                    return Ok(Prefab { name, size, voxels });
+                }
+                _ => (),
+            }
+        }
+        Err("no model in file".to_string())
+    }
+
+    /// Returns the prefab turned a quarter around the y axis.
+    pub fn rotated(&self) -> Prefab {
+        let [sx, sy, sz] = self.size;
+        let size = [sz, sy, sx];
+        let mut voxels = vec![0; self.voxels.len()];
+        for x in 0..sx {
+            for y in 0..sy {
+                for z in 0..sz {
+                    let [rx, ry, rz] = [sz - 1 - z, y, x];
+                    voxels[((rx * size[1] + ry) * size[2] + rz) as usize] =
+                        self.voxels[((x * sy + y) * sz + z) as usize];
+                }
+            }
+        }
+        Prefab {
+            name: self.name.clone(),
+            size,
+            voxels,
+        }
+    }
+
+    /// Returns the corner a stamp has to start at so the prefab is centered on `anchor`
+    /// horizontally with its bottom layer at `anchor`'s height.
+    pub fn origin_at(&self, anchor: [i32; 3]) -> [i32; 3] {
+        [
+            anchor[0] - self.size[0] as i32 / 2,
+            anchor[1],
+            anchor[2] - self.size[2] as i32 / 2,
+        ]
+    }
+
+    /// Returns the corner a stamp has to start at so the prefab sits in the middle of the world,
+    /// or an error if the world is too small for it.
+    pub fn centered_in_world(&self) -> Result<[i32; 3], String> {
+        if self.size.iter().any(|&size| size > WORLD_SIZE) {
+            let [x, y, z] = self.size;
+            return Err(format!(
+                "`{}` is {x}x{y}x{z} voxels, more than fits into the {WORLD_SIZE} voxel world",
+                self.name
+            ));
+        }
+        Ok(self.size.map(|size| ((WORLD_SIZE - size) / 2) as i32))
+    }
+}
+
+/// Folds the MagicaVoxel palette index `index`, from 1 to 255, onto the voxel types of the
+/// material palette without rules of their own, so imported models don't flow, fall or turn
+/// into portals. Indices naming such a type already stay that type, which `world_file::to_vox`
+/// relies on.
+pub fn voxel_type(index: u32) -> u32 {
+    if (index as usize) < MATERIAL_COUNT && !materials::has_rules(index) {
+        return index;
+    }
+    let mut plain = (1..MATERIAL_COUNT as u32).filter(|&voxel| !materials::has_rules(voxel));
+    let count = plain.clone().count() as u32;
+    plain.nth(((index - 1) % count) as usize).unwrap()
+}
@@ -0,0 +1,189 @@
+use rvengine::{
+    materials::Palette,
+    reflection_probes::{ReflectionProbes, MAX_PROBES, PROBE_FACES, PROBE_SIZE},
+    world::World,
+};
+use std::sync::Arc;
+use vulkano::{
+    buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
+    command_buffer::{
+        AutoCommandBufferBuilder, BufferImageCopy, CopyBufferToImageInfo, PrimaryAutoCommandBuffer,
+    },
+    device::Queue,
+    format::Format,
+    image::{
+        view::ImageView, ImageAccess, ImageCreateFlags, ImageDimensions, ImageSubresourceLayers,
+        ImageUsage, StorageImage,
+    },
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    sampler::{Filter, Sampler, SamplerAddressMode, SamplerCreateInfo},
+};
+
+/// Layout of the `Probes` buffer in `compute.glsl`.
+#[derive(BufferContents, Clone, Copy)]
+#[repr(C)]
+pub struct GpuProbes {
+    count: u32,
+    _padding: [u32; 3],
+    /// Position in xyz, w is unused.
+    positions: [[f32; 4]; MAX_PROBES],
+}
+
+/// The reflection probes with their GPU copy, updated with the frame after they were edited or
+/// rendered.
+pub struct ProbeImages {
+    probes: ReflectionProbes,
+    /// Whether `probes` changed since the last upload.
+    dirty: bool,
+    buffer: Subbuffer<GpuProbes>,
+    /// GPU copy of the probes' cubemaps, six layers of a 2D array image per probe with room for
+    /// `MAX_PROBES`, sampled with `sampler`.
+    images: Arc<ImageView<StorageImage>>,
+    sampler: Arc<Sampler>,
+}
+
+impl ProbeImages {
+    pub fn new(memory_allocator: &StandardMemoryAllocator, queue: &Arc<Queue>) -> ProbeImages {
+        let buffer = Buffer::new_sized(
+            memory_allocator,
+            BufferCreateInfo {
+                usage: BufferUsage::STORAGE_BUFFER | BufferUsage::TRANSFER_DST,
+                ..Default::default()
+            },
+            AllocationCreateInfo {
+                usage: MemoryUsage::DeviceOnly,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let images = ImageView::new_default(
+            StorageImage::with_usage(
+                memory_allocator,
+                ImageDimensions::Dim2d {
+                    width: PROBE_SIZE,
+                    height: PROBE_SIZE,
+                    array_layers: PROBE_FACES * MAX_PROBES as u32,
+                },
+                Format::R8G8B8A8_UNORM,
+                ImageUsage::SAMPLED | ImageUsage::TRANSFER_DST,
+                ImageCreateFlags::empty(),
+                [queue.queue_family_index()],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let sampler = Sampler::new(
+            queue.device().clone(),
+            SamplerCreateInfo {
+                mag_filter: Filter::Linear,
+                min_filter: Filter::Linear,
+                address_mode: [SamplerAddressMode::ClampToEdge; 3],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        ProbeImages {
+            probes: ReflectionProbes::new(),
+            dirty: true,
+            buffer,
+            images,
+            sampler,
+        }
+    }
+
+    pub fn probes(&self) -> &ReflectionProbes {
+        &self.probes
+    }
+
+    /// Returns the reflection probes for editing. Edits reach the GPU with the next frame.
+    pub fn probes_mut(&mut self) -> &mut ReflectionProbes {
+        self.dirty = true;
+        &mut self.probes
+    }
+
+    /// Places a reflection probe at `position`, rendered from `world` as it is, and returns its
+    /// index.
+    pub fn add(
+        &mut self,
+        world: &World,
+        palette: &Palette,
+        position: [f32; 3],
+    ) -> Result<usize, String> {
+        self.dirty = true;
+        self.probes.add(world, palette, position)
+    }
+
+    /// Renders every reflection probe again from `world` as it is.
+    pub fn render(&mut self, world: &World, palette: &Palette) {
+        self.dirty = true;
+        self.probes.render(world, palette);
+    }
+
+    pub fn buffer(&self) -> &Subbuffer<GpuProbes> {
+        &self.buffer
+    }
+
+    pub fn images(&self) -> &Arc<ImageView<StorageImage>> {
+        &self.images
+    }
+
+    pub fn sampler(&self) -> &Arc<Sampler> {
+        &self.sampler
+    }
+
+    /// Records copying the probes and their cubemaps to the GPU when they changed, returning
+    /// whether they did.
+    pub fn record_upload(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        memory_allocator: &StandardMemoryAllocator,
+    ) -> bool {
+        if !self.dirty {
+            return false;
+        }
+        let positions = self.probes.positions();
+        let mut probes = GpuProbes {
+            count: positions.len() as u32,
+            _padding: [0; 3],
+            positions: [[0.0; 4]; MAX_PROBES],
+        };
+        for (gpu, position) in probes.positions.iter_mut().zip(positions) {
+            *gpu = [position[0], position[1], position[2], 0.0];
+        }
+        builder
+            .update_buffer(self.buffer.clone(), Box::new(probes))
+            .unwrap();
+        if !positions.is_empty() {
+            let texels = Buffer::from_iter(
+                memory_allocator,
+                BufferCreateInfo {
+                    usage: BufferUsage::TRANSFER_SRC,
+                    ..Default::default()
+                },
+                AllocationCreateInfo {
+                    usage: MemoryUsage::Upload,
+                    ..Default::default()
+                },
+                self.probes.texels().iter().copied(),
+            )
+            .unwrap();
+            let image = self.images.image().clone();
+            let region = BufferImageCopy {
+                image_subresource: ImageSubresourceLayers {
+                    array_layers: 0..PROBE_FACES * positions.len() as u32,
+                    ..image.subresource_layers()
+                },
+                image_extent: [PROBE_SIZE, PROBE_SIZE, 1],
+                ..Default::default()
+            };
+            builder
+                .copy_buffer_to_image(CopyBufferToImageInfo {
+                    regions: [region].into(),
+                    ..CopyBufferToImageInfo::buffer_image(texels, image)
+                })
+                .unwrap();
+        }
+        self.dirty = false;
+        true
+    }
+}
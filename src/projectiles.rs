@@ -0,0 +1,123 @@
+use crate::{
+    entities::EntityBox,
+    simulation::{LAVA, PORTAL},
+    world::{Hit, World, WORLD_SIZE},
+};
+
+/// Voxels per second squared projectiles fall with.
+const GRAVITY: f32 = 20.0;
+/// Seconds after which a projectile which hit nothing is dropped.
+const MAX_AGE: f32 = 10.0;
+/// Edge length in voxels of the glowing cubes projectiles are drawn as.
+const PROJECTILE_SIZE: f32 = 0.6;
+
+/// A shot flying through the world.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Projectile {
+    position: [f32; 3],
+    /// Voxels per second.
+    velocity: [f32; 3],
+    /// Seconds since it was fired.
+    age: f32,
+}
+
+/// Shots flying on ballistic arcs which blast a crater into the world where they hit. They
+/// collide with `World::raycast` along the way they move each update, so fast shots don't
+/// tunnel through thin walls, and each crater is written as a single region, so its chunks are
+/// uploaded and woken up in the simulation once.
+///
+/// Projectiles are drawn as glowing cubes, see `entities`.
+pub struct Projectiles {
+    projectiles: Vec<Projectile>,
+    /// Radius in voxels of the craters blasted.
+    pub crater_radius: u32,
+}
+
+impl Projectiles {
+    pub fn new(crater_radius: u32) -> Projectiles {
+        Projectiles {
+            projectiles: Vec::new(),
+            crater_radius,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.projectiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.projectiles.is_empty()
+    }
+
+    /// Fires a projectile from `origin` towards `direction` at `speed` voxels per second.
+    pub fn fire(&mut self, origin: [f32; 3], direction: [f32; 3], speed: f32) {
+        let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+        if length == 0.0 || !length.is_finite() {
+            return;
+        }
+        self.projectiles.push(Projectile {
+            position: origin,
+            velocity: direction.map(|d| d / length * speed),
+            age: 0.0,
+        });
+    }
+
+    /// Returns the boxes the projectiles in flight are drawn as, lit like lava.
+    pub fn entities(&self) -> impl Iterator<Item = EntityBox> + '_ {
+        self.projectiles
+            .iter()
+            .map(|projectile| EntityBox::cube(projectile.position, PROJECTILE_SIZE, LAVA))
+    }
+
+    /// Drops every projectile in flight.
+    pub fn clear(&mut self) {
+        self.projectiles.clear();
+    }
+
+    /// Moves every projectile `dt` seconds further and blasts craters where they hit. Returns
+    /// what they hit.
+    pub fn update(&mut self, world: &mut World, dt: f32) -> Vec<Hit> {
+        let mut hits = Vec::new();
+        self.projectiles.retain_mut(|projectile| {
+            projectile.velocity[1] -= GRAVITY * dt;
+            projectile.age += dt;
+            let travel = projectile.velocity.map(|v| v * dt);
+            let distance = travel.iter().map(|d| d * d).sum::<f32>().sqrt();
+            if let Some(hit) = world.raycast(projectile.position, travel, distance) {
+                crater(world, hit.position, self.crater_radius);
+                hits.push(hit);
+                return false;
+            }
+            projectile.position = [0, 1, 2].map(|i| projectile.position[i] + travel[i]);
+            projectile.age < MAX_AGE && projectile.position[1] >= 0.0
+        });
+        hits
+    }
+}
+
+/// Empties the voxels within `radius` of the center of the voxel at `center`, apart from portal
+/// voxels, whose links would be left dangling.
+pub fn crater(world: &mut World, center: [i32; 3], radius: u32) {
+    let radius = radius as i32;
+    let min = center.map(|c| (c - radius).clamp(0, WORLD_SIZE as i32) as u32);
+    let max = center.map(|c| (c + radius + 1).clamp(0, WORLD_SIZE as i32) as u32);
+    if (0..3).any(|i| min[i] >= max[i]) {
+        return;
+    }
+    let size = [0, 1, 2].map(|i| max[i] - min[i]);
+    let mut voxels = world.region(min, max);
+    let mut index = 0;
+    for x in min[0]..max[0] {
+        for y in min[1]..max[1] {
+            for z in min[2]..max[2] {
+                let position = [x, y, z].map(|c| c as i32);
+                let squared: i32 = (0..3).map(|i| (position[i] - center[i]).pow(2)).sum();
+                if squared <= radius * radius && voxels[index] != PORTAL {
+                    voxels[index] = 0;
+                }
+                index += 1;
+            }
+        }
+    }
+    world.set_region(min, size, &voxels);
+}
@@ -0,0 +1,50 @@
+use std::{fs, io::ErrorKind, path::Path};
+
+/// File the recently imported models are kept in.
+pub const DEFAULT_RECENT_PATH: &str = "recent_imports.txt";
+/// Imports remembered at most, the oldest are forgotten first.
+pub const MAX_RECENT: usize = 8;
+
+/// `.vox` models imported lately with `--prefab` or `view`, newest first, listed by the startup
+/// menu. Stored as a text file with one path per line.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecentImports {
+    paths: Vec<String>,
+}
+
+impl RecentImports {
+    /// Reads the list at `path`, an empty one when the file doesn't exist.
+    pub fn load(path: impl AsRef<Path>) -> Result<RecentImports, String> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(RecentImports::default()),
+            Err(err) => return Err(format!("can't read {}: {err}", path.display())),
+        };
+        let paths = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .take(MAX_RECENT)
+            .map(str::to_string)
+            .collect();
+        Ok(RecentImports { paths })
+    }
+
+    pub fn paths(&self) -> &[String] {
+        &self.paths
+    }
+
+    /// Moves `import` to the front, forgetting the oldest import when there are too many.
+    pub fn add(&mut self, import: &str) {
+        self.paths.retain(|path| path != import);
+        self.paths.insert(0, import.to_string());
+        self.paths.truncate(MAX_RECENT);
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let text: String = self.paths.iter().map(|path| format!("{path}\n")).collect();
+        fs::write(path, text).map_err(|err| format!("can't write {}: {err}", path.display()))
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    materials::Palette,
+    tracer::trace_ray,
+    world::{World, WORLD_SIZE},
+};
+use rayon::prelude::*;
+
+/// Probes the renderer keeps room for.
+pub const MAX_PROBES: usize = 8;
+/// Edge length in texels of every face of a probe.
+pub const PROBE_SIZE: u32 = 32;
+/// Faces of a probe's cubemap, in the order Vulkan lays out cubemap layers.
+pub const PROBE_FACES: u32 = 6;
+/// Texels of a probe, all six faces.
+const PROBE_TEXELS: usize = (PROBE_FACES * PROBE_SIZE * PROBE_SIZE) as usize;
+
+/// Cubemaps of the world around a few placed points, traced on the CPU with `tracer::trace_ray`.
+/// The compute shader samples the nearest one for glossy surfaces instead of tracing their
+/// blurred reflections, which only needs the reflections to be roughly right.
+///
+/// Texels are RGBA8 packed into a word each, red in the lowest byte. Each probe holds six faces
+/// towards +x, -x, +y, -y, +z and -z, each `PROBE_SIZE` rows of `PROBE_SIZE` texels, laid out
+/// like the faces of a cubemap array. Probes see the world as it was when they were rendered,
+/// `render` updates them after edits.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReflectionProbes {
+    positions: Vec<[f32; 3]>,
+    texels: Vec<u32>,
+}
+
+impl ReflectionProbes {
+    pub fn new() -> ReflectionProbes {
+        ReflectionProbes::default()
+    }
+
+    pub fn positions(&self) -> &[[f32; 3]] {
+        &self.positions
+    }
+
+    /// Returns the texels of every probe, one probe after the other.
+    pub fn texels(&self) -> &[u32] {
+        &self.texels
+    }
+
+    /// Places a probe at `position` and renders it, returning its index.
+    pub fn add(
+        &mut self,
+        world: &World,
+        palette: &Palette,
+        position: [f32; 3],
+    ) -> Result<usize, String> {
+        if self.positions.len() >= MAX_PROBES {
+            return Err(format!(
+                "there can't be more than {MAX_PROBES} reflection probes"
+            ));
+        }
+        self.positions.push(position);
+        self.texels.extend(render_probe(world, palette, position));
+        Ok(self.positions.len() - 1)
+    }
+
+    /// Removes the probe at `index`, moving the ones after it down.
+    pub fn remove(&mut self, index: usize) -> Result<(), String> {
+        if index >= self.positions.len() {
+            return Err(format!("there is no reflection probe {index}"));
+        }
+        self.positions.remove(index);
+        self.texels
+            .drain(index * PROBE_TEXELS..(index + 1) * PROBE_TEXELS);
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.texels.clear();
+    }
+
+    /// Renders every probe again, after the world or palette changed.
+    pub fn render(&mut self, world: &World, palette: &Palette) {
+        self.texels = self
+            .positions
+            .iter()
+            .flat_map(|&position| render_probe(world, palette, position))
+            .collect();
+    }
+}
+
+/// Traces the six faces of a probe at `position`, texels in parallel on rayon's thread pool.
+fn render_probe(world: &World, palette: &Palette, position: [f32; 3]) -> Vec<u32> {
+    (0..PROBE_TEXELS)
+        .into_par_iter()
+        .map(|index| {
+            let size = PROBE_SIZE as usize;
+            let (face, t, s) = (index / (size * size), index / size % size, index % size);
+            let [u, v] = [s, t].map(|c| (c as f32 + 0.5) / PROBE_SIZE as f32 * 2.0 - 1.0);
+            let direction = face_direction(face, u, v);
+            let (color, _) = trace_ray(world, palette, position, direction, WORLD_SIZE as f32);
+            let [r, g, b] = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u32);
+            r | g << 8 | b << 16 | 255 << 24
+        })
+        .collect()
+}
+
+/// Returns the direction through the point `u`, `v` (-1 to 1, right and down) of cubemap face
+/// `face`, as `probeTexel` in the compute shader picks faces.
+fn face_direction(face: usize, u: f32, v: f32) -> [f32; 3] {
+    match face {
+        0 => [1.0, -v, -u],
+        1 => [-1.0, -v, u],
+        2 => [u, 1.0, v],
+        3 => [u, -1.0, -v],
+        4 => [u, -v, 1.0],
+        _ => [-u, -v, -1.0],
+    }
+}
@@ -1,3 +1,5 @@
+#[path = "../permutations.rs"]
+mod permutations;
 mod render;
 
 use render::Render;
@@ -14,14 +16,10 @@ use vulkano::{
     image::{view::ImageView, ImageAccess, SwapchainImage},
     memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
     pipeline::{
-        graphics::{
-            input_assembly::{InputAssemblyState, PrimitiveTopology},
-            vertex_input::Vertex,
-            viewport::{Viewport, ViewportState},
-        },
-        ComputePipeline, GraphicsPipeline, Pipeline, PipelineBindPoint,
+        graphics::{vertex_input::Vertex, viewport::Viewport},
+        ComputePipeline, Pipeline, PipelineBindPoint,
     },
-    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass},
     swapchain::{
         acquire_next_image, AcquireError, SwapchainCreateInfo, SwapchainCreationError,
         SwapchainPresentInfo,
@@ -51,20 +49,6 @@ fn main() {
     // to force rustc to use a defined layout for our data, as the default representation has *no
     // guarantees*.
 
-    mod vs {
-        vulkano_shaders::shader! {
-            ty: "vertex",
-            path: "./assets/shader/vert.glsl"
-        }
-    }
-
-    mod fs {
-        vulkano_shaders::shader! {
-            ty: "fragment",
-            path: "./assets/shader/frag.glsl"
-        }
-    }
-
     mod cs {
         vulkano_shaders::shader! {
             ty: "compute",
@@ -72,8 +56,6 @@ fn main() {
         }
     }
 
-    let vs = vs::load(render.device.clone()).unwrap();
-    let fs = fs::load(render.device.clone()).unwrap();
     let cs = cs::load(render.device.clone()).unwrap();
 
     let push_constants = cs::PushConstantData {
@@ -101,18 +83,7 @@ fn main() {
     .unwrap();
 
     let mut vertices = render::get_vertices_for_entire_screen();
-    render.add_pipelines(
-        GraphicsPipeline::start()
-            .render_pass(Subpass::from(render.render_pass.clone(), 0).unwrap())
-            .vertex_input_state(RayVVertex::per_vertex())
-            .input_assembly_state(
-                InputAssemblyState::new().topology(PrimitiveTopology::TriangleList),
-            )
-            .vertex_shader(vs.entry_point("main").unwrap(), ())
-            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-            .fragment_shader(fs.entry_point("main").unwrap(), ())
-            .build(render.device.clone())
-            .unwrap(),
+    render.add_compute_pipeline(
         ComputePipeline::new(
             render.device.clone(),
             cs.entry_point("main").unwrap(),
@@ -123,9 +94,12 @@ fn main() {
         .unwrap(),
     );
 
+    let mut screen = render
+        .screen_pipeline(render.swapchain.image_format())
+        .unwrap();
     let mut framebuffers = window_size_dependent_setup(
         &render.images,
-        render.render_pass.clone(),
+        screen.render_pass.clone(),
         &mut render.viewport,
     );
 
@@ -170,9 +144,25 @@ fn main() {
                 previous_frame_end.as_mut().unwrap().cleanup_finished();
 
                 if recreate_swapchain {
+                    // The surface may prefer another format by now, on another monitor. Stays
+                    // with the current one when there is no pipeline for it.
+                    let preferred = render
+                        .device
+                        .physical_device()
+                        .surface_formats(&render.surface, Default::default())
+                        .unwrap()[0]
+                        .0;
+                    let image_format = match render.screen_pipeline(preferred) {
+                        Some(pipeline) => {
+                            screen = pipeline;
+                            preferred
+                        }
+                        None => render.swapchain.image_format(),
+                    };
                     let (new_swapchain, new_images) =
                         match render.swapchain.recreate(SwapchainCreateInfo {
                             image_extent: dimensions.into(),
+                            image_format: Some(image_format),
                             ..render.swapchain.create_info()
                         }) {
                             Ok(r) => r,
@@ -184,7 +174,7 @@ fn main() {
 
                     framebuffers = window_size_dependent_setup(
                         &new_images,
-                        render.render_pass.clone(),
+                        screen.render_pass.clone(),
                         &mut render.viewport,
                     );
 
@@ -273,7 +263,7 @@ fn main() {
                     )
                     .unwrap()
                     .set_viewport(0, [render.viewport.clone()])
-                    .bind_pipeline_graphics(render.graphics_pipeline.clone().unwrap().clone())
+                    .bind_pipeline_graphics(screen.pipeline.clone())
                     .bind_vertex_buffers(0, vertex_buffer.clone())
                     .draw(vertex_buffer.len() as u32, 1, 0, 0)
                     .unwrap()
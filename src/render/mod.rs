@@ -1,3 +1,4 @@
+use crate::permutations::Permutations;
 use std::sync::Arc;
 use vulkano::{
     buffer::{Buffer, BufferCreateInfo, BufferUsage},
@@ -10,8 +11,15 @@ use vulkano::{
     image::{ImageUsage, StorageImage, SwapchainImage},
     instance::{Instance, InstanceCreateInfo},
     memory::allocator::{AllocationCreateInfo, MemoryUsage},
-    pipeline::{graphics::viewport::Viewport, ComputePipeline, GraphicsPipeline},
-    render_pass::{Framebuffer, RenderPass},
+    pipeline::{
+        graphics::{
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
+            vertex_input::Vertex as _,
+            viewport::{Viewport, ViewportState},
+        },
+        ComputePipeline, GraphicsPipeline,
+    },
+    render_pass::{Framebuffer, RenderPass, Subpass},
     swapchain::{AcquireError, Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError},
     sync, VulkanLibrary,
 };
@@ -29,11 +37,21 @@ use winit::{
 
 use crate::RayVVertex as Vertex;
 
+/// The render pass drawing the screen into swapchain images of one format and the graphics
+/// pipeline drawing in it.
+#[derive(Clone)]
+pub struct ScreenPipeline {
+    pub render_pass: Arc<RenderPass>,
+    pub pipeline: Arc<GraphicsPipeline>,
+}
+
 pub struct Render {
     pub instance: Arc<Instance>,
     pub event_loop: EventLoop<()>,
     pub library: Arc<VulkanLibrary>,
-    pub graphics_pipeline: Option<Arc<GraphicsPipeline>>,
+    /// Screen pipelines by the format of the swapchain images they draw into, built the first
+    /// time the swapchain is created with that format.
+    pub screen_pipelines: Permutations<Format, ScreenPipeline>,
     pub compute_pipeline: Option<Arc<ComputePipeline>>,
     pub surface: Arc<Surface>,
     pub physical_device: Arc<PhysicalDevice>,
@@ -41,7 +59,6 @@ pub struct Render {
     pub queue_family_index: u32,
     pub queue: Arc<Queue>,
     pub swapchain: Arc<Swapchain>,
-    pub render_pass: Arc<RenderPass>,
     pub viewport: Viewport,
     pub images: Vec<Arc<SwapchainImage>>,
     pub image: DeviceImageView,
@@ -160,23 +177,10 @@ impl Render {
             .unwrap()
         };
 
-        let render_pass = vulkano::single_pass_renderpass!(
-            device.clone(),
-            attachments: {
-                // `color` is a custom name we give to the first and only attachment.
-                color: {
-                    load: Clear,
-                    store: Store,
-                    format: swapchain.image_format(),
-                    samples: 1,
-                },
-            },
-            pass: {
-                color: [color],
-                depth_stencil: {},
-            },
-        )
-        .unwrap();
+        let screen_pipelines = Permutations::new(
+            swapchain.image_format(),
+            screen_pipeline(&device, swapchain.image_format()).unwrap(),
+        );
 
         let viewport = Viewport {
             origin: [0.0, 0.0],
@@ -199,7 +203,7 @@ impl Render {
             instance,
             event_loop,
             library,
-            graphics_pipeline: None,
+            screen_pipelines,
             compute_pipeline: None,
             surface,
             physical_device,
@@ -207,21 +211,74 @@ impl Render {
             queue_family_index,
             queue,
             swapchain,
-            render_pass,
             viewport,
             images,
             image,
             context,
         }
     }
-    pub fn add_pipelines(
-        &mut self,
-        graphics_pipeline: Arc<GraphicsPipeline>,
-        compute_pipeline: Arc<ComputePipeline>,
-    ) {
-        self.graphics_pipeline = Some(graphics_pipeline);
+    pub fn add_compute_pipeline(&mut self, compute_pipeline: Arc<ComputePipeline>) {
         self.compute_pipeline = Some(compute_pipeline);
     }
+
+    /// Returns the screen pipeline drawing into swapchain images of `format`, building it when
+    /// the format is new, or `None` when building failed.
+    pub fn screen_pipeline(&mut self, format: Format) -> Option<ScreenPipeline> {
+        let device = self.device.clone();
+        self.screen_pipelines
+            .get(format, |format| screen_pipeline(&device, format))
+            .cloned()
+    }
+}
+
+/// Builds the render pass drawing into images of `format` and the screen pipeline drawing in it.
+fn screen_pipeline(device: &Arc<Device>, format: Format) -> Result<ScreenPipeline, String> {
+    let render_pass = vulkano::single_pass_renderpass!(
+        device.clone(),
+        attachments: {
+            // `color` is a custom name we give to the first and only attachment.
+            color: {
+                load: Clear,
+                store: Store,
+                format: format,
+                samples: 1,
+            },
+        },
+        pass: {
+            color: [color],
+            depth_stencil: {},
+        },
+    )
+    .map_err(|err| err.to_string())?;
+    let vs = vs::load(device.clone()).map_err(|err| err.to_string())?;
+    let fs = fs::load(device.clone()).map_err(|err| err.to_string())?;
+    let pipeline = GraphicsPipeline::start()
+        .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+        .vertex_input_state(Vertex::per_vertex())
+        .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::TriangleList))
+        .vertex_shader(vs.entry_point("main").unwrap(), ())
+        .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+        .fragment_shader(fs.entry_point("main").unwrap(), ())
+        .build(device.clone())
+        .map_err(|err| format!("can't build the screen pipeline for {format:?}: {err}"))?;
+    Ok(ScreenPipeline {
+        render_pass,
+        pipeline,
+    })
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "./assets/shader/vert.glsl"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        path: "./assets/shader/frag.glsl"
+    }
 }
 
 pub fn get_vertices_for_entire_screen() -> Vec<Vertex> {
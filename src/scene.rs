@@ -0,0 +1,216 @@
+use crate::{
+    materials::{material_line, parse_material, Palette},
+    world::{World, WORLD_SIZE},
+    world_file,
+};
+use std::{
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Hour of the day the sun stands at unless a scene says otherwise.
+pub const DEFAULT_TIME_OF_DAY: f32 = 10.0;
+
+/// A named camera placement.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bookmark {
+    pub name: String,
+    /// Position of the eye in the world.
+    pub eye: [f32; 3],
+    /// Rotation in radians around z, then x, then y, see `camera::Camera`.
+    pub rotation: [f32; 3],
+}
+
+/// A voxel placed over the world once it is loaded. The renderer lights scenes with emissive
+/// voxels, so lights are voxels of a type whose material is emissive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Light {
+    pub position: [u32; 3],
+    pub voxel: u32,
+}
+
+/// Everything needed to reopen a setup: the world, where the camera can be, the sun, the
+/// materials and lights. Stored as an `.rvscene` text file with one entry per line:
+///
+/// ```text
+/// world castle.rvox
+/// bake castle.rvlight
+/// sun 10
+/// camera name x y z rotation_x rotation_y rotation_z
+/// material type r g b emissive roughness transparency metalness detail
+/// light x y z type
+/// ```
+///
+/// Lines starting with `#` are comments. The world is any of `world_file::FORMATS` and the bake
+/// the light baked for it, see `light_bake`, both relative to the scene file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneFile {
+    pub world: Option<PathBuf>,
+    pub light_bake: Option<PathBuf>,
+    /// The camera starts at the first bookmark.
+    pub bookmarks: Vec<Bookmark>,
+    /// Hour of the day from 0 to 24 setting where the sun stands.
+    pub time_of_day: f32,
+    /// Types without a `material` line keep their default.
+    pub palette: Palette,
+    pub lights: Vec<Light>,
+}
+
+impl SceneFile {
+    /// Reads a scene saved with `save`.
+    pub fn load(path: impl AsRef<Path>) -> Result<SceneFile, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("can't read scene `{}`: {err}", path.display()))?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut scene = SceneFile::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            scene
+                .parse_line(line, directory)
+                .map_err(|err| format!("{}:{}: {err}", path.display(), number + 1))?;
+        }
+        Ok(scene)
+    }
+
+    fn parse_line(&mut self, line: &str, directory: &Path) -> Result<(), String> {
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        match keyword {
+            "world" if rest.is_empty() => return Err("expected `world file`".to_string()),
+            "world" => self.world = Some(directory.join(rest)),
+            "bake" if rest.is_empty() => return Err("expected `bake file`".to_string()),
+            "bake" => self.light_bake = Some(directory.join(rest)),
+            "sun" => {
+                let [hour] = parse_values::<f32>(rest)?[..] else {
+                    return Err("expected `sun hour`".to_string());
+                };
+                self.time_of_day = hour.rem_euclid(24.0);
+            }
+            "camera" => {
+                let (name, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                let [x, y, z, rx, ry, rz] = parse_values::<f32>(rest)?[..] else {
+                    return Err(
+                        "expected `camera name x y z rotation_x rotation_y rotation_z`".to_string(),
+                    );
+                };
+                self.bookmarks.push(Bookmark {
+                    name: name.to_string(),
+                    eye: [x, y, z],
+                    rotation: [rx, ry, rz],
+                });
+            }
+            "material" => {
+                let (index, material) = parse_material(rest)?;
+                self.palette.materials[index] = material;
+            }
+            "light" => {
+                let [x, y, z, voxel] = parse_values::<u32>(rest)?[..] else {
+                    return Err("expected `light x y z type`".to_string());
+                };
+                if [x, y, z].iter().any(|&c| c >= WORLD_SIZE) {
+                    return Err(format!("light at {:?} is outside the world", [x, y, z]));
+                }
+                self.lights.push(Light {
+                    position: [x, y, z],
+                    voxel,
+                });
+            }
+            _ => return Err(format!("unknown entry `{line}`")),
+        }
+        Ok(())
+    }
+
+    /// Writes the scene as text, with the world and bake paths relative to the directory of `path`
+    /// when they lie inside it. The world and bake themselves aren't written, see `World::save`
+    /// and `LightBake::save`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut text = String::from("# rvscene\n");
+        if let Some(world) = &self.world {
+            let world = world.strip_prefix(directory).unwrap_or(world);
+            text += &format!("world {}\n", world.display());
+        }
+        if let Some(light_bake) = &self.light_bake {
+            let light_bake = light_bake.strip_prefix(directory).unwrap_or(light_bake);
+            text += &format!("bake {}\n", light_bake.display());
+        }
+        text += &format!("sun {}\n", self.time_of_day);
+        for bookmark in &self.bookmarks {
+            let [x, y, z] = bookmark.eye;
+            let [rx, ry, rz] = bookmark.rotation;
+            text += &format!("camera {} {x} {y} {z} {rx} {ry} {rz}\n", bookmark.name);
+        }
+        for (index, material) in self.palette.materials.iter().enumerate().skip(1) {
+            text += &format!("material {}\n", material_line(index, material));
+        }
+        for light in &self.lights {
+            let [x, y, z] = light.position;
+            text += &format!("light {x} {y} {z} {}\n", light.voxel);
+        }
+        world_file::write_atomically(path, text.as_bytes())
+            .map_err(|err| format!("can't write scene `{}`: {err}", path.display()))
+    }
+
+    /// Replaces `world` with the scene's world file, empty without one, and places the lights.
+    pub fn load_world(&self, world: &mut World) -> Result<(), String> {
+        match &self.world {
+            Some(path) => world_file::load(world, path)?,
+            None => world.fill([0; 3], [WORLD_SIZE; 3], 0),
+        }
+        for light in &self.lights {
+            world.set_voxel(light.position, light.voxel);
+        }
+        Ok(())
+    }
+
+    /// Returns the bookmark called `name`.
+    pub fn bookmark(&self, name: &str) -> Option<&Bookmark> {
+        self.bookmarks.iter().find(|bookmark| bookmark.name == name)
+    }
+
+    /// Adds a bookmark, replacing the one with the same name.
+    pub fn set_bookmark(&mut self, bookmark: Bookmark) {
+        match self
+            .bookmarks
+            .iter_mut()
+            .find(|other| other.name == bookmark.name)
+        {
+            Some(other) => *other = bookmark,
+            None => self.bookmarks.push(bookmark),
+        }
+    }
+}
+
+/// Parses the whitespace separated values of `text`.
+fn parse_values<T: FromStr>(text: &str) -> Result<Vec<T>, String>
+where
+    T::Err: Display,
+{
+    text.split_whitespace()
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|err| format!("invalid value `{value}`: {err}"))
+        })
+        .collect()
+}
+
+impl Default for SceneFile {
+    fn default() -> Self {
+        SceneFile {
+            world: None,
+            light_bake: None,
+            bookmarks: Vec::new(),
+            time_of_day: DEFAULT_TIME_OF_DAY,
+            palette: Palette::default(),
+            lights: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,795 @@
+use crate::{camera::Lens, tracer::SKY_COLOR};
+use std::{fmt::Write as _, fs, path::Path};
+
+/// Start of the comment lines in a screenshot's header holding its `ShotMetadata`.
+const METADATA_PREFIX: &str = "# rayvox ";
+
+/// Extensions of the files `HdrScreenshot::save` writes, 32-bit float OpenEXR and 16-bit PNG.
+/// Screenshots to other files are written as 8-bit PPM by `Screenshot::save`.
+pub const HDR_EXTENSIONS: [&str; 2] = ["exr", "png"];
+
+/// Rows of the 3x5 glyphs captions are drawn in, top first, the left column in the highest bit.
+/// Lowercase letters are drawn as uppercase ones and characters without a glyph as `?`.
+const GLYPHS: [(char, [u8; 5]); 44] = [
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (',', [0b000, 0b000, 0b000, 0b010, 0b100]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('(', [0b010, 0b100, 0b100, 0b100, 0b010]),
+    (')', [0b010, 0b001, 0b001, 0b001, 0b010]),
+];
+/// Drawn for characters without a glyph.
+const UNKNOWN_GLYPH: [u8; 5] = [0b111, 0b001, 0b010, 0b000, 0b010];
+/// Image rows per pixel of a glyph's height, so captions keep their size relative to the shot.
+const CAPTION_ROWS_PER_PIXEL: u32 = 270;
+
+/// How a screenshot was taken, written into its header so the shot can be set up again.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShotMetadata {
+    /// Seed the world was generated from, `None` for worlds loaded from a file or a demo.
+    pub seed: Option<u64>,
+    /// Position of the eye in the world.
+    pub eye: [f32; 3],
+    /// Rotation in radians around z, then x, then y, see `camera::Camera`.
+    pub rotation: [f32; 3],
+    /// Horizontal field of view in degrees.
+    pub fov: f32,
+    pub lens: Lens,
+    /// Hour of the day from 0 to 24.
+    pub time_of_day: f32,
+}
+
+impl ShotMetadata {
+    /// Reads the metadata from the header of a screenshot written by `Screenshot::save` or
+    /// `HdrScreenshot::save`.
+    pub fn load(path: impl AsRef<Path>) -> Result<ShotMetadata, String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .map_err(|err| format!("can't read screenshot `{}`: {err}", path.display()))?;
+        parse_metadata(&bytes).map_err(|err| format!("screenshot `{}`: {err}", path.display()))
+    }
+
+    /// Returns the header lines holding the metadata.
+    fn header(&self) -> String {
+        let mut header = String::new();
+        if let Some(seed) = self.seed {
+            writeln!(header, "{METADATA_PREFIX}seed {seed}").unwrap();
+        }
+        let [x, y, z] = self.eye;
+        writeln!(header, "{METADATA_PREFIX}eye {x} {y} {z}").unwrap();
+        let [x, y, z] = self.rotation;
+        writeln!(header, "{METADATA_PREFIX}rotation {x} {y} {z}").unwrap();
+        writeln!(header, "{METADATA_PREFIX}fov {}", self.fov).unwrap();
+        writeln!(header, "{METADATA_PREFIX}aperture {}", self.lens.aperture).unwrap();
+        writeln!(
+            header,
+            "{METADATA_PREFIX}focus {}",
+            self.lens.focus_distance
+        )
+        .unwrap();
+        writeln!(header, "{METADATA_PREFIX}time {}", self.time_of_day).unwrap();
+        header
+    }
+
+    /// Returns the lines `Screenshot::burn_in` draws into the shot: the eye, the rotation in
+    /// degrees, the seed, the time of day and the name of the settings preset the shot was
+    /// taken with.
+    pub fn caption(&self, preset: &str) -> Vec<String> {
+        let [x, y, z] = self.eye;
+        let [pitch, yaw, roll] = self.rotation.map(f32::to_degrees);
+        let minutes = (self.time_of_day * 60.0).round() as u32 % (24 * 60);
+        vec![
+            format!("eye {x:.1} {y:.1} {z:.1}"),
+            format!("rotation {pitch:.1} {yaw:.1} {roll:.1}"),
+            match self.seed {
+                Some(seed) => format!("seed {seed}"),
+                None => "seed none".to_string(),
+            },
+            format!("time {:02}:{:02}", minutes / 60, minutes % 60),
+            format!("preset {preset}"),
+        ]
+    }
+}
+
+/// A finished frame, RGB8 with rows from top to bottom.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Screenshot {
+    size: [u32; 2],
+    pixels: Vec<u8>,
+}
+
+impl Screenshot {
+    /// Takes the frame from the RGBA8 pixels of the traced image, whose rows are shown bottom up.
+    pub fn from_traced(size: [u32; 2], rgba: &[u8]) -> Screenshot {
+        let row = size[0] as usize * 4;
+        let pixels = rgba[..row * size[1] as usize]
+            .chunks(row)
+            .rev()
+            .flat_map(|row| row.chunks(4).flat_map(|pixel| &pixel[..3]))
+            .copied()
+            .collect();
+        Screenshot { size, pixels }
+    }
+
+    /// Takes the frame from RGBA8 pixels with rows from top to bottom, like `tracer::render`
+    /// writes them.
+    pub fn from_rgba(size: [u32; 2], rgba: &[u8]) -> Screenshot {
+        let pixels = rgba[..size[0] as usize * size[1] as usize * 4]
+            .chunks(4)
+            .flat_map(|pixel| &pixel[..3])
+            .copied()
+            .collect();
+        Screenshot { size, pixels }
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Draws `lines` in white over a darkened box in the bottom left corner, so the shot carries
+    /// how it was taken wherever it is shared, see `ShotMetadata::caption`. Text running past
+    /// the right edge is cut off.
+    pub fn burn_in(&mut self, lines: &[String]) {
+        caption_pixels(self.size, lines, |index, text| {
+            let pixel = &mut self.pixels[index * 3..index * 3 + 3];
+            for channel in pixel {
+                *channel = if text { 255 } else { *channel / 3 };
+            }
+        });
+    }
+
+    /// Writes the frame as a binary PPM (`P6`) file, with `metadata` in comments of its header.
+    pub fn save(&self, path: impl AsRef<Path>, metadata: &ShotMetadata) -> Result<(), String> {
+        let path = path.as_ref();
+        let [width, height] = self.size;
+        let mut bytes = format!("P6\n{}{width} {height}\n255\n", metadata.header()).into_bytes();
+        bytes.extend_from_slice(&self.pixels);
+        fs::write(path, bytes)
+            .map_err(|err| format!("can't write screenshot `{}`: {err}", path.display()))
+    }
+}
+
+/// A finished frame before it was clamped to 8 bits for display, RGB floats with rows from top
+/// to bottom, so bright parts keep their detail when the shot is graded elsewhere. Taken from
+/// the accumulated samples at the traced extent, before upscaling and post-processing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HdrScreenshot {
+    size: [u32; 2],
+    /// Premultiplied by `alpha` once the sky was masked.
+    pixels: Vec<[f32; 3]>,
+    /// How much of every pixel shows voxels instead of sky, see `mask_sky`.
+    alpha: Option<Vec<f32>>,
+}
+
+impl HdrScreenshot {
+    /// Takes the frame from the little endian RGBA texels of the traced accumulation image,
+    /// whose rows are shown bottom up, half floats when `half` and 32-bit floats otherwise.
+    /// `coverage` holds the little endian 32-bit floats of the traced coverage image to mask
+    /// the sky with, see `mask_sky`.
+    pub fn from_traced(
+        size: [u32; 2],
+        texels: &[u8],
+        half: bool,
+        coverage: Option<&[u8]>,
+    ) -> Result<HdrScreenshot, String> {
+        let texel = if half { 8 } else { 16 };
+        let row = size[0] as usize * texel;
+        let pixels = texels[..row * size[1] as usize]
+            .chunks(row)
+            .rev()
+            .flat_map(|row| row.chunks(texel))
+            .map(|texel| {
+                [0, 1, 2].map(|channel| {
+                    if half {
+                        let bytes = [texel[channel * 2], texel[channel * 2 + 1]];
+                        half_to_f32(u16::from_le_bytes(bytes))
+                    } else {
+                        let bytes = &texel[channel * 4..channel * 4 + 4];
+                        f32::from_le_bytes(bytes.try_into().unwrap())
+                    }
+                })
+            })
+            .collect();
+        let mut screenshot = HdrScreenshot {
+            size,
+            pixels,
+            alpha: None,
+        };
+        if let Some(coverage) = coverage {
+            let row = size[0] as usize * 4;
+            let coverage: Vec<f32> = coverage
+                .get(..row * size[1] as usize)
+                .ok_or_else(|| format!("{} coverage bytes for {size:?} pixels", coverage.len()))?
+                .chunks(row)
+                .rev()
+                .flat_map(|row| row.chunks(4))
+                .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                .collect();
+            screenshot.mask_sky(&coverage)?;
+        }
+        Ok(screenshot)
+    }
+
+    /// Takes the frame from 3 values per pixel with rows from top to bottom, like
+    /// `tracer::Outputs::color` holds.
+    pub fn from_rgb(size: [u32; 2], rgb: &[f32]) -> HdrScreenshot {
+        let pixels = rgb
+            .chunks(3)
+            .take(size[0] as usize * size[1] as usize)
+            .map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect();
+        HdrScreenshot {
+            size,
+            pixels,
+            alpha: None,
+        }
+    }
+
+    /// Makes the sky transparent, so the shot can be laid over another background.
+    /// `coverage` holds how much of every pixel, rows from top to bottom, shows voxels instead
+    /// of sky, which becomes its alpha. `tracer::SKY_COLOR` is taken out of the pixels the sky
+    /// shows through, so edges don't keep a fringe of it. Pixels holding less of it than their
+    /// coverage leaves to the sky, from noise, keep more alpha instead of losing color, so laid
+    /// over `SKY_COLOR` the shot looks as it did.
+    pub fn mask_sky(&mut self, coverage: &[f32]) -> Result<(), String> {
+        let coverage = coverage.get(..self.pixels.len()).ok_or_else(|| {
+            format!(
+                "{} coverage values for {} pixels",
+                coverage.len(),
+                self.pixels.len()
+            )
+        })?;
+        let alpha = self
+            .pixels
+            .iter_mut()
+            .zip(coverage)
+            .map(|(pixel, coverage)| {
+                let held = [0, 1, 2]
+                    .map(|i| pixel[i].max(0.0) / SKY_COLOR[i])
+                    .into_iter()
+                    .fold(f32::INFINITY, f32::min);
+                let sky = (1.0 - coverage.clamp(0.0, 1.0)).min(held);
+                *pixel = [0, 1, 2].map(|i| pixel[i] - sky * SKY_COLOR[i]);
+                1.0 - sky
+            })
+            .collect();
+        self.alpha = Some(alpha);
+        Ok(())
+    }
+
+    pub fn size(&self) -> [u32; 2] {
+        self.size
+    }
+
+    /// Draws `lines` like `Screenshot::burn_in`, the text at 1. The caption is opaque over a
+    /// masked sky.
+    pub fn burn_in(&mut self, lines: &[String]) {
+        caption_pixels(self.size, lines, |index, text| {
+            if let Some(alpha) = &mut self.alpha {
+                alpha[index] = 1.0;
+            }
+            let pixel = &mut self.pixels[index];
+            *pixel = if text {
+                [1.0; 3]
+            } else {
+                pixel.map(|c| c / 3.0)
+            };
+        });
+    }
+
+    /// Writes the frame as 32-bit float OpenEXR to paths ending in `.exr` and as 16-bit PNG to
+    /// those ending in `.png`, see `HDR_EXTENSIONS`, with `metadata` in a comment. PNG keeps
+    /// values from 0 to 1 only, at finer steps than 8 bits, while EXR keeps every value. A
+    /// masked sky is written as an alpha channel, premultiplied in EXR and straight in PNG as
+    /// each format expects.
+    pub fn save(&self, path: impl AsRef<Path>, metadata: &ShotMetadata) -> Result<(), String> {
+        let path = path.as_ref();
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        let bytes = match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("exr") => self.exr_bytes(&metadata.header()),
+            Some("png") => self.png_bytes(&metadata.header()),
+            _ => {
+                return Err(format!(
+                    "can't tell the format of `{}`, expected one of {}",
+                    path.display(),
+                    HDR_EXTENSIONS
+                        .map(|extension| format!(".{extension}"))
+                        .join(", ")
+                ))
+            }
+        };
+        fs::write(path, bytes)
+            .map_err(|err| format!("can't write screenshot `{}`: {err}", path.display()))
+    }
+
+    /// Returns the frame as an OpenEXR file with one 32-bit float channel per color and alpha,
+    /// see `exr_bytes`.
+    fn exr_bytes(&self, comments: &str) -> Vec<u8> {
+        let channel = |channel: usize| -> Vec<f32> {
+            self.pixels.iter().map(|pixel| pixel[channel]).collect()
+        };
+        let [r, g, b] = [0, 1, 2].map(channel);
+        let mut channels = vec![
+            ("R", ExrValues::Float(&r)),
+            ("G", ExrValues::Float(&g)),
+            ("B", ExrValues::Float(&b)),
+        ];
+        if let Some(alpha) = &self.alpha {
+            channels.push(("A", ExrValues::Float(alpha)));
+        }
+        exr_bytes(self.size, &channels, comments)
+    }
+
+    /// Returns the frame as a 16-bit RGB PNG file, RGBA with a masked sky, `comments` in a
+    /// `Comment` text chunk.
+    fn png_bytes(&self, comments: &str) -> Vec<u8> {
+        let [width, height] = self.size;
+        let mut bytes = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+        let mut chunk = |kind: &[u8; 4], data: &[u8]| {
+            bytes.extend((data.len() as u32).to_be_bytes());
+            let start = bytes.len();
+            bytes.extend(kind);
+            bytes.extend(data);
+            let crc = crc32(&bytes[start..]);
+            bytes.extend(crc.to_be_bytes());
+        };
+        let mut header = Vec::new();
+        header.extend(width.to_be_bytes());
+        header.extend(height.to_be_bytes());
+        // 16 bits per channel, RGB or RGBA, deflate, adaptive filtering, not interlaced.
+        let color_type = if self.alpha.is_some() { 6 } else { 2 };
+        header.extend([16, color_type, 0, 0, 0]);
+        chunk(b"IHDR", &header);
+        chunk(
+            b"tEXt",
+            &[b"Comment\0".as_slice(), comments.as_bytes()].concat(),
+        );
+        let channels = if self.alpha.is_some() { 4 } else { 3 };
+        let mut raw = Vec::with_capacity((width as usize * channels * 2 + 1) * height as usize);
+        for (y, row) in self.pixels.chunks(width as usize).enumerate() {
+            // Rows aren't filtered.
+            raw.push(0);
+            for (x, pixel) in row.iter().enumerate() {
+                let mut values = pixel.to_vec();
+                if let Some(alpha) = &self.alpha {
+                    let alpha = alpha[y * width as usize + x];
+                    // PNG colors aren't premultiplied.
+                    for value in &mut values {
+                        *value = if alpha > 0.0 { *value / alpha } else { 0.0 };
+                    }
+                    values.push(alpha);
+                }
+                raw.extend(
+                    values
+                        .iter()
+                        .flat_map(|c| ((c.clamp(0.0, 1.0) * 65535.0).round() as u16).to_be_bytes()),
+                );
+            }
+        }
+        chunk(
+            b"IDAT",
+            &miniz_oxide::deflate::compress_to_vec_zlib(&raw, 6),
+        );
+        chunk(b"IEND", &[]);
+        bytes
+    }
+}
+
+/// Values of a channel of an OpenEXR image, one per pixel with rows from top to bottom.
+#[derive(Clone, Copy, Debug)]
+pub enum ExrValues<'a> {
+    Float(&'a [f32]),
+    Uint(&'a [u32]),
+}
+
+/// Writes an OpenEXR image of `size` holding `channels` by name, like the render passes of
+/// `tracer::Outputs`, with `metadata` in a comment.
+pub fn save_exr(
+    path: impl AsRef<Path>,
+    size: [u32; 2],
+    channels: &[(&str, ExrValues)],
+    metadata: &ShotMetadata,
+) -> Result<(), String> {
+    let path = path.as_ref();
+    let bytes = exr_bytes(size, channels, &metadata.header());
+    fs::write(path, bytes).map_err(|err| format!("can't write image `{}`: {err}", path.display()))
+}
+
+/// Returns an uncompressed scanline OpenEXR file of `size` holding `channels`, 32-bit floats or
+/// unsigned integers, with `comments` in the standard `comments` attribute.
+fn exr_bytes(size: [u32; 2], channels: &[(&str, ExrValues)], comments: &str) -> Vec<u8> {
+    let [width, height] = size;
+    let mut bytes = vec![0x76, 0x2f, 0x31, 0x01];
+    // Version 2, single part scanlines.
+    bytes.extend(2u32.to_le_bytes());
+    let mut attribute = |name: &str, kind: &str, value: &[u8]| {
+        for text in [name, kind] {
+            bytes.extend(text.as_bytes());
+            bytes.push(0);
+        }
+        bytes.extend((value.len() as u32).to_le_bytes());
+        bytes.extend(value);
+    };
+    // Channels are listed in alphabetical order and stored in it.
+    let mut channels = channels.to_vec();
+    channels.sort_by_key(|&(name, _)| name);
+    let mut list = Vec::new();
+    for (name, values) in &channels {
+        list.extend(name.as_bytes());
+        list.push(0);
+        let kind: u32 = match values {
+            ExrValues::Uint(_) => 0,
+            ExrValues::Float(_) => 2,
+        };
+        // Pixel type, not linear in perception, reserved, x and y sampling.
+        list.extend(kind.to_le_bytes());
+        list.extend([0; 4]);
+        list.extend(1u32.to_le_bytes());
+        list.extend(1u32.to_le_bytes());
+    }
+    list.push(0);
+    attribute("channels", "chlist", &list);
+    attribute("compression", "compression", &[0]);
+    let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+        .into_iter()
+        .flat_map(i32::to_le_bytes)
+        .collect();
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1f32.to_le_bytes());
+    attribute("comments", "string", comments.as_bytes());
+    bytes.push(0);
+
+    // One scanline per block, each its y, its size and then every channel's values, all of
+    // them 4 bytes.
+    let width = width as usize;
+    let line_bytes = width * channels.len() * 4;
+    let first_block = bytes.len() + height as usize * 8;
+    for y in 0..height as usize {
+        let offset = first_block + y * (8 + line_bytes);
+        bytes.extend((offset as u64).to_le_bytes());
+    }
+    for y in 0..height as usize {
+        bytes.extend((y as i32).to_le_bytes());
+        bytes.extend((line_bytes as u32).to_le_bytes());
+        let row = y * width..(y + 1) * width;
+        for (_, values) in &channels {
+            match values {
+                ExrValues::Float(values) => {
+                    bytes.extend(values[row.clone()].iter().flat_map(|v| v.to_le_bytes()))
+                }
+                ExrValues::Uint(values) => {
+                    bytes.extend(values[row.clone()].iter().flat_map(|v| v.to_le_bytes()))
+                }
+            }
+        }
+    }
+    bytes
+}
+
+/// Returns whether `path` names a file `HdrScreenshot::save` writes, by its extension.
+pub fn is_hdr_path(path: impl AsRef<Path>) -> bool {
+    let extension = path
+        .as_ref()
+        .extension()
+        .and_then(|extension| extension.to_str());
+    extension.is_some_and(|extension| {
+        HDR_EXTENSIONS
+            .iter()
+            .any(|hdr| extension.eq_ignore_ascii_case(hdr))
+    })
+}
+
+/// Calls `visit` with the index of every pixel of an image of `size` the caption of `lines`
+/// covers, see `Screenshot::burn_in`: first those of the box behind it, with `false`, then
+/// those of its text, with `true`.
+fn caption_pixels(size: [u32; 2], lines: &[String], mut visit: impl FnMut(usize, bool)) {
+    let [width, height] = size;
+    let scale = (height / CAPTION_ROWS_PER_PIXEL).max(1);
+    // A glyph and the space after it, a line and the space below it.
+    let [advance, line_height] = [4 * scale, 6 * scale];
+    let columns = lines.iter().map(|line| line.chars().count()).max();
+    let Some(columns) = columns.filter(|&columns| columns > 0) else {
+        return;
+    };
+    let box_size = [
+        columns as u32 * advance + scale,
+        lines.len() as u32 * line_height + scale,
+    ];
+    let top = height.saturating_sub(box_size[1]);
+    for y in top..height {
+        for x in 0..box_size[0].min(width) {
+            visit((y * width + x) as usize, false);
+        }
+    }
+    for (row, line) in lines.iter().enumerate() {
+        let line_top = top + scale + row as u32 * line_height;
+        for (column, c) in line.chars().enumerate() {
+            let glyph = GLYPHS
+                .iter()
+                .find(|(glyph, _)| *glyph == c.to_ascii_uppercase())
+                .map_or(UNKNOWN_GLYPH, |&(_, rows)| rows);
+            let left = scale + column as u32 * advance;
+            for (glyph_y, bits) in glyph.into_iter().enumerate() {
+                for glyph_x in 0..3 {
+                    if bits & (0b100 >> glyph_x) == 0 {
+                        continue;
+                    }
+                    // The square of `scale` pixels the glyph's pixel covers.
+                    let [x, y] = [left + glyph_x * scale, line_top + glyph_y as u32 * scale];
+                    for y in y..(y + scale).min(height) {
+                        for x in x..(x + scale).min(width) {
+                            visit((y * width + x) as usize, true);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Returns the value of the IEEE half float `bits`.
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (bits >> 10 & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32 / 1024.0;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-14),
+        31 if mantissa == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + mantissa) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Returns the CRC-32 PNG chunks end in.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Reads the metadata comments of a screenshot. In a PPM header they come before the first
+/// line holding anything but a comment after the magic number, in EXR and PNG files they
+/// start the comment holding them.
+fn parse_metadata(bytes: &[u8]) -> Result<ShotMetadata, String> {
+    let mut metadata = ShotMetadata {
+        seed: None,
+        eye: [0.0; 3],
+        rotation: [0.0; 3],
+        fov: 0.0,
+        lens: Lens::default(),
+        time_of_day: 0.0,
+    };
+    let comments = if bytes.starts_with(b"P6") {
+        let first_line = bytes.iter().position(|&byte| byte == b'\n');
+        first_line.map_or(&[][..], |end| &bytes[end + 1..])
+    } else {
+        let prefix = METADATA_PREFIX.as_bytes();
+        let start = bytes
+            .windows(prefix.len())
+            .position(|window| window == prefix);
+        start.map_or(&[][..], |start| &bytes[start..])
+    };
+    let mut found = Vec::new();
+    for line in comments.split(|&byte| byte == b'\n') {
+        let line = String::from_utf8_lossy(line);
+        if !line.starts_with('#') {
+            break;
+        }
+        let Some(entry) = line.strip_prefix(METADATA_PREFIX) else {
+            continue;
+        };
+        let (key, values) = entry.split_once(' ').unwrap_or((entry, ""));
+        let invalid = || format!("invalid {key} `{values}`");
+        match key {
+            "seed" => metadata.seed = Some(values.parse().map_err(|_| invalid())?),
+            "eye" => metadata.eye = parse_floats(values).ok_or_else(invalid)?,
+            "rotation" => metadata.rotation = parse_floats(values).ok_or_else(invalid)?,
+            "fov" => [metadata.fov] = parse_floats(values).ok_or_else(invalid)?,
+            "aperture" => [metadata.lens.aperture] = parse_floats(values).ok_or_else(invalid)?,
+            "focus" => [metadata.lens.focus_distance] = parse_floats(values).ok_or_else(invalid)?,
+            "time" => [metadata.time_of_day] = parse_floats(values).ok_or_else(invalid)?,
+            // Written by a later version.
+            _ => continue,
+        }
+        found.push(key.to_string());
+    }
+    for key in ["eye", "rotation", "fov"] {
+        if !found.iter().any(|found| found == key) {
+            return Err(format!("no `{key}` in the header"));
+        }
+    }
+    Ok(metadata)
+}
+
+/// Parses exactly `N` whitespace separated numbers.
+fn parse_floats<const N: usize>(text: &str) -> Option<[f32; N]> {
+    let values = text
+        .split_whitespace()
+        .map(|value| value.parse().ok())
+        .collect::<Option<Vec<f32>>>()?;
+    values.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> ShotMetadata {
+        ShotMetadata {
+            seed: Some(42),
+            eye: [12.5, 40.25, -3.0],
+            rotation: [0.1, -1.2, 3.0],
+            fov: 70.0,
+            lens: Lens {
+                aperture: 0.5,
+                focus_distance: 32.0,
+            },
+            time_of_day: 17.75,
+        }
+    }
+
+    /// Reads the little endian word at `offset`.
+    fn word(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn crc32_matches_png() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+    }
+
+    #[test]
+    fn half_to_f32_reads_every_kind_of_value() {
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        // The smallest subnormal.
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn exr_offsets_point_at_their_scanlines() {
+        let red = [1.0, 2.0, 3.0, 4.0];
+        let green = [0.5; 4];
+        let bytes = exr_bytes(
+            [2, 2],
+            &[
+                ("R", ExrValues::Float(&red)),
+                ("G", ExrValues::Float(&green)),
+            ],
+            "",
+        );
+        // The magic number, the version and the attributes: channels, compression, data and
+        // display windows, line order, pixel aspect ratio, screen window center and width,
+        // comments, then the byte ending them.
+        let header = 8 + 57 + 29 + 37 + 40 + 25 + 31 + 35 + 32 + 20 + 1;
+        let line = 8 + 2 * 2 * 4;
+        assert_eq!(bytes.len(), header + 2 * 8 + 2 * line);
+        let first = header + 2 * 8;
+        assert_eq!(word(&bytes, header) as usize, first);
+        assert_eq!(word(&bytes, header + 8) as usize, first + line);
+        for y in 0..2 {
+            let block = first + y * line;
+            assert_eq!(word(&bytes, block) as usize, y);
+            assert_eq!(word(&bytes, block + 4), 2 * 2 * 4);
+            // G before R.
+            let values: Vec<f32> = (0..4)
+                .map(|i| f32::from_bits(word(&bytes, block + 8 + i * 4)))
+                .collect();
+            assert_eq!(values, [0.5, 0.5, red[y * 2], red[y * 2 + 1]]);
+        }
+    }
+
+    #[test]
+    fn metadata_survives_the_header() {
+        let metadata = metadata();
+        assert_eq!(parse_metadata(metadata.header().as_bytes()), Ok(metadata));
+        let unseeded = ShotMetadata {
+            seed: None,
+            ..metadata
+        };
+        let exr = exr_bytes(
+            [1, 1],
+            &[("R", ExrValues::Float(&[0.5]))],
+            &unseeded.header(),
+        );
+        assert_eq!(parse_metadata(&exr), Ok(unseeded));
+        assert_eq!(
+            parse_metadata(b"P6\n# rayvox eye 1 2 3\n2 2\n255\n"),
+            Err("no `rotation` in the header".to_string())
+        );
+    }
+
+    #[test]
+    fn mask_sky_keeps_the_color_of_partly_covered_pixels() {
+        let voxel = [0.8, 0.4, 0.2];
+        let half_covered = [0, 1, 2].map(|i| 0.5 * voxel[i] + 0.5 * SKY_COLOR[i]);
+        // Holds less sky than a coverage of 0.25 leaves to it.
+        let noisy = [0.02, 0.3, 0.3];
+        let mut screenshot =
+            HdrScreenshot::from_rgb([3, 1], &[half_covered, noisy, voxel].concat());
+        screenshot.mask_sky(&[0.5, 0.25, 1.0]).unwrap();
+        let alpha = screenshot.alpha.clone().unwrap();
+        assert_eq!(alpha[0], 0.5);
+        assert!((alpha[1] - 0.8).abs() < 1e-6);
+        assert_eq!(alpha[2], 1.0);
+        let original = [half_covered, noisy, voxel];
+        for ((pixel, alpha), original) in screenshot.pixels.iter().zip(&alpha).zip(original) {
+            assert!(pixel.iter().all(|&c| c >= 0.0), "{pixel:?}");
+            for i in 0..3 {
+                let over_sky = pixel[i] + (1.0 - alpha) * SKY_COLOR[i];
+                assert!(
+                    (over_sky - original[i]).abs() < 1e-6,
+                    "{pixel:?} {original:?}"
+                );
+            }
+        }
+        let masked = screenshot.pixels[0];
+        assert!(voxel
+            .iter()
+            .zip(masked)
+            .all(|(voxel, masked)| (masked - 0.5 * voxel).abs() < 1e-6));
+    }
+
+    #[test]
+    fn mask_sky_rejects_too_little_coverage() {
+        let mut screenshot = HdrScreenshot::from_rgb([2, 2], &[0.5; 12]);
+        assert_eq!(
+            screenshot.mask_sky(&[1.0; 3]),
+            Err("3 coverage values for 4 pixels".to_string())
+        );
+        assert!(HdrScreenshot::from_traced([1, 1], &[0; 16], false, Some(&[0; 2])).is_err());
+    }
+}
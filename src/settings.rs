@@ -0,0 +1,362 @@
+/// Bit set in the `flags` push constant when ambient occlusion is enabled.
+pub const FLAG_AMBIENT_OCCLUSION: u32 = 1 << 0;
+/// Bit set in the `flags` push constant when sun shadows are enabled.
+pub const FLAG_SHADOWS: u32 = 1 << 1;
+/// Bit set in the `flags` push constant when diffuse bounces are enabled.
+pub const FLAG_GLOBAL_ILLUMINATION: u32 = 1 << 2;
+/// Bit set in the `flags` push constant when rays cut short by `max_ray_steps` are tinted.
+pub const FLAG_STEP_WARNING: u32 = 1 << 3;
+/// Bit set in the `flags` push constant when samples are accumulated across frames.
+pub const FLAG_ACCUMULATE: u32 = 1 << 4;
+/// Bit set in the `flags` push constant for the dispatch tracing the adaptive sampling queue.
+pub const FLAG_WORK_QUEUE: u32 = 1 << 5;
+/// Bit set in the `flags` push constant for the dispatch writing albedo and G-buffer only.
+pub const FLAG_GBUFFER_PASS: u32 = 1 << 6;
+/// Bit set in the `flags` push constant for the half resolution lighting dispatch.
+pub const FLAG_LIGHTING_PASS: u32 = 1 << 7;
+/// Bit set in the `flags` push constant for the dispatch upsampling the lighting.
+pub const FLAG_UPSAMPLE_PASS: u32 = 1 << 8;
+/// Bit set in the `flags` push constant when the temporal upscaler consumes the traced image.
+pub const FLAG_TEMPORAL_UPSCALE: u32 = 1 << 9;
+/// Bit set in the `flags` push constant when rays skip empty chunks using the distance field.
+pub const FLAG_DISTANCE_FIELD: u32 = 1 << 10;
+/// Bit set in the `flags` push constant when every face direction is shaded differently.
+pub const FLAG_FACE_SHADING: u32 = 1 << 11;
+/// Bit set in the `flags` push constant when voxel edges are drawn as lines.
+pub const FLAG_GRID_LINES: u32 = 1 << 12;
+/// Bit set in the `flags` push constant when the image shows motion vectors instead of colors.
+pub const FLAG_MOTION_VIEW: u32 = 1 << 13;
+/// Bit set in the `flags` push constant when surfaces are lit by the light bake instead of the
+/// lighting terms.
+pub const FLAG_BAKED_LIGHTING: u32 = 1 << 14;
+/// Bit set in the `flags` push constant when shadow rays pass through transparent materials,
+/// tinted by them.
+pub const FLAG_COLORED_SHADOWS: u32 = 1 << 15;
+/// Bit set in the `flags` push constant when chunk borders and states are drawn over the image.
+pub const FLAG_CHUNK_OVERLAY: u32 = 1 << 16;
+/// Bit set in the `flags` push constant when primary rays and the ones cut short by
+/// `max_ray_steps` are counted.
+pub const FLAG_STEP_LOG: u32 = 1 << 17;
+/// Bit set in the `flags` push constant when the axis gizmo is drawn in a corner.
+pub const FLAG_AXIS_GIZMO: u32 = 1 << 18;
+/// Bit set in the `flags` push constant when the depth, normal and material outputs are written,
+/// see `Controller::request_outputs`.
+pub const FLAG_OUTPUTS: u32 = 1 << 19;
+
+/// Render scales cycled through from the keyboard.
+pub const RENDER_SCALES: [f32; 3] = [1.0, 0.67, 0.5];
+
+/// Upper bound for `Settings::max_ray_steps` so a single dispatch can't hang the GPU.
+pub const MAX_RAY_STEPS_LIMIT: u32 = 8192;
+/// Upper bound for `Settings::max_bounces`.
+pub const MAX_BOUNCES_LIMIT: u32 = 8;
+
+/// Named quality levels which set every rendering knob at once.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Preset {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [Preset::Low, Preset::Medium, Preset::High, Preset::Ultra];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Low => "low",
+            Preset::Medium => "medium",
+            Preset::High => "high",
+            Preset::Ultra => "ultra",
+        }
+    }
+
+    /// Looks a preset up by its (case insensitive) name.
+    pub fn from_name(name: &str) -> Option<Preset> {
+        Preset::ALL
+            .into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// Anti-aliasing applied when the traced image is placed over the frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AaMode {
+    Off,
+    Fxaa,
+}
+
+impl AaMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AaMode::Off => "off",
+            AaMode::Fxaa => "fxaa",
+        }
+    }
+}
+
+/// How an image traced below native resolution is brought to the output resolution.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Upscaler {
+    /// Stretches the traced image with a linear sampler.
+    Bilinear,
+    /// Jitters the traced pixels every frame and accumulates them into a native resolution
+    /// history, reprojected with per pixel motion vectors.
+    Temporal,
+}
+
+impl Upscaler {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Upscaler::Bilinear => "bilinear",
+            Upscaler::Temporal => "temporal",
+        }
+    }
+}
+
+/// Every user facing rendering knob.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    /// The preset these settings came from, `None` once a knob was changed by hand.
+    pub preset: Option<Preset>,
+    /// Maximum distance in voxels a primary ray travels before hitting the sky.
+    pub render_distance: u32,
+    /// Maximum number of traversal steps per ray.
+    pub max_ray_steps: u32,
+    /// Distance in voxels from the eye beyond which voxel objects are culled, see
+    /// `objects::VoxelObject`.
+    pub object_distance: u32,
+    /// Distance in voxels from the eye beyond which voxel objects are drawn as impostors by the
+    /// graphics pass instead of being traced, see `impostors::ImpostorAtlas`. 0 traces them all.
+    pub impostor_distance: u32,
+    /// Bit `i` is set when layer `i` is hidden, see `layers::Layers`.
+    pub hidden_layers: u32,
+    /// Number of diffuse bounces traced per pixel when global illumination is enabled.
+    pub max_bounces: u32,
+    /// Tints pixels whose primary ray ran out of steps before reaching the render distance.
+    pub step_warning: bool,
+    /// Counts the primary rays that ran out of steps and logs their share of every frame.
+    pub step_log: bool,
+    pub ambient_occlusion: bool,
+    pub shadows: bool,
+    /// Lets shadow rays pass through transparent materials, tinted by their color, instead of
+    /// stopping at them. Costs a traversal for every transparent voxel passed.
+    pub colored_shadows: bool,
+    pub global_illumination: bool,
+    /// Lights surfaces with the light baked for the world, see `light_bake`, in place of ambient
+    /// occlusion, shadows and global illumination.
+    pub baked_lighting: bool,
+    /// Averages samples across frames while the camera stands still.
+    pub accumulate: bool,
+    /// Traces extra samples for noisy pixels while accumulating.
+    pub adaptive_sampling: bool,
+    /// Variance of a pixel's mean luminance above which it receives extra samples.
+    pub variance_threshold: f32,
+    /// Accumulates in half floats, halving the accumulation target's memory and bandwidth, where
+    /// the GPU supports it. Falls back to 32-bit floats otherwise.
+    pub half_float_accumulation: bool,
+    /// Computes ambient occlusion, shadows and bounces at half resolution and upsamples them.
+    pub half_res_lighting: bool,
+    /// Classifies screen tiles on the GPU so tiles which only see sky skip traversal.
+    pub tile_classification: bool,
+    /// Skips empty chunks in a single step instead of walking every voxel.
+    pub distance_field: bool,
+    /// Shades every face direction differently so shapes read without lighting.
+    pub face_shading: bool,
+    /// Darkens the edges of every voxel face.
+    pub grid_lines: bool,
+    /// Shows every pixel's motion since the previous frame instead of its color, red for x and
+    /// green for y with gray standing still.
+    pub motion_view: bool,
+    /// Draws the borders of the chunks and tints them by state: resident, streaming (uploaded this
+    /// frame), dirty (waiting for upload) or culled (empty, skipped by rays).
+    pub chunk_overlay: bool,
+    /// Draws the world axes as the camera sees them in the bottom right corner.
+    pub axis_gizmo: bool,
+    /// Fraction of the window resolution that is actually traced.
+    pub render_scale: f32,
+    pub upscaler: Upscaler,
+    pub aa_mode: AaMode,
+}
+
+impl Settings {
+    pub fn from_preset(preset: Preset) -> Settings {
+        let (render_distance, max_ray_steps) = match preset {
+            Preset::Low => (64, 128),
+            Preset::Medium => (128, 256),
+            Preset::High => (256, 512),
+            Preset::Ultra => (512, 1024),
+        };
+        Settings {
+            preset: Some(preset),
+            render_distance,
+            max_ray_steps,
+            object_distance: render_distance,
+            impostor_distance: 0,
+            hidden_layers: 0,
+            max_bounces: match preset {
+                Preset::Low | Preset::Medium => 1,
+                Preset::High => 2,
+                Preset::Ultra => 3,
+            },
+            step_warning: true,
+            step_log: false,
+            ambient_occlusion: preset != Preset::Low,
+            shadows: matches!(preset, Preset::High | Preset::Ultra),
+            colored_shadows: preset == Preset::Ultra,
+            global_illumination: preset == Preset::Ultra,
+            baked_lighting: false,
+            accumulate: false,
+            adaptive_sampling: true,
+            variance_threshold: 1e-4,
+            half_float_accumulation: false,
+            half_res_lighting: preset == Preset::High,
+            tile_classification: true,
+            distance_field: true,
+            face_shading: true,
+            grid_lines: false,
+            motion_view: false,
+            chunk_overlay: false,
+            axis_gizmo: false,
+            render_scale: match preset {
+                Preset::Low => 0.5,
+                Preset::Medium => 0.67,
+                Preset::High | Preset::Ultra => 1.0,
+            },
+            upscaler: match preset {
+                Preset::Low | Preset::Medium => Upscaler::Temporal,
+                Preset::High | Preset::Ultra => Upscaler::Bilinear,
+            },
+            aa_mode: if preset == Preset::Low {
+                AaMode::Off
+            } else {
+                AaMode::Fxaa
+            },
+        }
+    }
+
+    /// Returns these settings as photo mode renders with them: samples accumulate while the
+    /// camera stands still and nothing is drawn over the image.
+    pub fn photo(&self) -> Settings {
+        Settings {
+            accumulate: true,
+            step_warning: false,
+            grid_lines: false,
+            motion_view: false,
+            chunk_overlay: false,
+            axis_gizmo: false,
+            ..*self
+        }
+    }
+
+    /// Replaces every knob with the values of `preset`, keeping the hidden layers, which pick
+    /// what is drawn rather than how.
+    pub fn apply_preset(&mut self, preset: Preset) {
+        *self = Settings {
+            hidden_layers: self.hidden_layers,
+            ..Settings::from_preset(preset)
+        };
+    }
+
+    /// Doubles (`steps > 0`) or halves (`steps < 0`) the traversal step cap `steps.abs()` times.
+    pub fn scale_max_ray_steps(&mut self, steps: i32) {
+        if steps == 0 {
+            return;
+        }
+        let factor = 2u32.saturating_pow(steps.unsigned_abs());
+        self.max_ray_steps = if steps > 0 {
+            self.max_ray_steps.saturating_mul(factor)
+        } else {
+            self.max_ray_steps / factor
+        }
+        .clamp(1, MAX_RAY_STEPS_LIMIT);
+        self.preset = None;
+    }
+
+    /// Adds `change` to the per-pixel bounce budget.
+    pub fn change_max_bounces(&mut self, change: i32) {
+        if change == 0 {
+            return;
+        }
+        self.max_bounces = self
+            .max_bounces
+            .saturating_add_signed(change)
+            .min(MAX_BOUNCES_LIMIT);
+        self.preset = None;
+    }
+
+    /// Switches to the next of `RENDER_SCALES`.
+    pub fn cycle_render_scale(&mut self) {
+        let current = RENDER_SCALES
+            .iter()
+            .position(|&scale| scale == self.render_scale)
+            .unwrap_or(RENDER_SCALES.len() - 1);
+        self.render_scale = RENDER_SCALES[(current + 1) % RENDER_SCALES.len()];
+        self.preset = None;
+    }
+
+    /// Returns the lighting toggles packed for the compute shader.
+    pub fn flags(&self) -> u32 {
+        let mut flags = 0;
+        // The traced terms are left out so the variant without them is used.
+        if self.baked_lighting {
+            flags |= FLAG_BAKED_LIGHTING;
+        } else {
+            if self.ambient_occlusion {
+                flags |= FLAG_AMBIENT_OCCLUSION;
+            }
+            if self.shadows {
+                flags |= FLAG_SHADOWS;
+            }
+            if self.colored_shadows {
+                flags |= FLAG_COLORED_SHADOWS;
+            }
+            if self.global_illumination {
+                flags |= FLAG_GLOBAL_ILLUMINATION;
+            }
+        }
+        if self.step_warning {
+            flags |= FLAG_STEP_WARNING;
+        }
+        if self.accumulate {
+            flags |= FLAG_ACCUMULATE;
+        }
+        if self.upscaler == Upscaler::Temporal {
+            flags |= FLAG_TEMPORAL_UPSCALE;
+        }
+        if self.distance_field {
+            flags |= FLAG_DISTANCE_FIELD;
+        }
+        if self.face_shading {
+            flags |= FLAG_FACE_SHADING;
+        }
+        if self.grid_lines {
+            flags |= FLAG_GRID_LINES;
+        }
+        if self.motion_view {
+            flags |= FLAG_MOTION_VIEW;
+        }
+        if self.chunk_overlay {
+            flags |= FLAG_CHUNK_OVERLAY;
+        }
+        if self.step_log {
+            flags |= FLAG_STEP_LOG;
+        }
+        if self.axis_gizmo {
+            flags |= FLAG_AXIS_GIZMO;
+        }
+        flags
+    }
+
+    /// Returns the part of an image of size `extent` which is traced at the current render scale.
+    pub fn scaled_extent(&self, extent: [u32; 2]) -> [u32; 2] {
+        extent.map(|d| ((d as f32 * self.render_scale).round() as u32).clamp(1, d.max(1)))
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::from_preset(Preset::Medium)
+    }
+}
@@ -0,0 +1,180 @@
+use std::{fs, path::Path, sync::Arc};
+use vulkano::{device::Device, shader::ShaderModule};
+
+/// Shader sources built at runtime, embedded so the binary runs without the assets directory.
+/// Names are relative to `assets/shader`, which is how `#include`s refer to them.
+const SOURCES: [(&str, &str); 11] = [
+    (
+        "compute.glsl",
+        include_str!("../assets/shader/compute.glsl"),
+    ),
+    (
+        "adaptive.glsl",
+        include_str!("../assets/shader/adaptive.glsl"),
+    ),
+    (
+        "trace/traversal.glsl",
+        include_str!("../assets/shader/trace/traversal.glsl"),
+    ),
+    (
+        "trace/objects.glsl",
+        include_str!("../assets/shader/trace/objects.glsl"),
+    ),
+    (
+        "trace/entities.glsl",
+        include_str!("../assets/shader/trace/entities.glsl"),
+    ),
+    (
+        "trace/brdf.glsl",
+        include_str!("../assets/shader/trace/brdf.glsl"),
+    ),
+    (
+        "trace/ambient_occlusion.glsl",
+        include_str!("../assets/shader/trace/ambient_occlusion.glsl"),
+    ),
+    (
+        "trace/shadows.glsl",
+        include_str!("../assets/shader/trace/shadows.glsl"),
+    ),
+    (
+        "trace/global_illumination.glsl",
+        include_str!("../assets/shader/trace/global_illumination.glsl"),
+    ),
+    (
+        "trace/baked_lighting.glsl",
+        include_str!("../assets/shader/trace/baked_lighting.glsl"),
+    ),
+    (
+        "trace/reflection_probes.glsl",
+        include_str!("../assets/shader/trace/reflection_probes.glsl"),
+    ),
+];
+
+/// Compiles the embedded compute shader `name` with `defines`, see `SOURCES`.
+pub fn compile(
+    device: &Arc<Device>,
+    name: &str,
+    defines: &[&str],
+) -> Result<Arc<ShaderModule>, String> {
+    let source = embedded(name).ok_or(format!("no shader source `{name}`"))?;
+    compile_text(device, name, source, defines)
+}
+
+/// Loads the compute shader at `path`, SPIR-V when its extension is `spv` and GLSL compiled
+/// with `defines` otherwise.
+pub fn load(
+    device: &Arc<Device>,
+    path: &Path,
+    defines: &[&str],
+) -> Result<Arc<ShaderModule>, String> {
+    if path.extension().is_some_and(|extension| extension == "spv") {
+        return load_spirv(device, path);
+    }
+    let source = fs::read_to_string(path)
+        .map_err(|err| format!("can't read shader `{}`: {err}", path.display()))?;
+    compile_text(device, &path.display().to_string(), &source, defines)
+}
+
+/// Loads the precompiled SPIR-V shader at `path`.
+pub fn load_spirv(device: &Arc<Device>, path: &Path) -> Result<Arc<ShaderModule>, String> {
+    let bytes =
+        fs::read(path).map_err(|err| format!("can't read shader `{}`: {err}", path.display()))?;
+    if bytes.len() % 4 > 0 {
+        return Err(format!("`{}` isn't SPIR-V", path.display()));
+    }
+    // Safety: vulkano checks the module's structure, the SPIR-V itself is trusted like the
+    // shaders built into the binary.
+    unsafe { ShaderModule::from_bytes(device.clone(), &bytes) }
+        .map_err(|err| format!("invalid shader `{}`: {err}", path.display()))
+}
+
+/// Compiles the compute shader `source`, reported as `name` in errors, with each of `defines`
+/// defined. `#include "file"` is resolved relative to the including file, falling back to the
+/// embedded source of that name, and `#include <file>` by its name in `SOURCES`. Files which
+/// aren't embedded are read from disk.
+#[cfg(feature = "runtime-shaders")]
+pub fn compile_text(
+    device: &Arc<Device>,
+    name: &str,
+    source: &str,
+    defines: &[&str],
+) -> Result<Arc<ShaderModule>, String> {
+    let compiler = shaderc::Compiler::new().ok_or("can't create the shader compiler")?;
+    let mut options = shaderc::CompileOptions::new().ok_or("can't create the shader compiler")?;
+    for define in defines {
+        options.add_macro_definition(define, None);
+    }
+    // Reading the world through buffer device addresses needs SPIR-V 1.5.
+    if device.api_version() >= vulkano::Version::V1_2 {
+        options.set_target_env(
+            shaderc::TargetEnv::Vulkan,
+            shaderc::EnvVersion::Vulkan1_2 as u32,
+        );
+    }
+    options.set_include_callback(|requested, include_type, requesting, _| {
+        resolve(requested, include_type, requesting)
+    });
+    let spirv = compiler
+        .compile_into_spirv(
+            source,
+            shaderc::ShaderKind::Compute,
+            name,
+            "main",
+            Some(&options),
+        )
+        .map_err(|err| format!("can't compile shader `{name}`:\n{err}"))?;
+    // Safety: the words come from the compiler, which only emits valid SPIR-V.
+    unsafe { ShaderModule::from_words(device.clone(), spirv.as_binary()) }
+        .map_err(|err| format!("invalid shader `{name}`: {err}"))
+}
+
+/// Fails, GLSL is only compiled at runtime with the `runtime-shaders` feature. SPIR-V still
+/// loads, see `load_spirv`.
+#[cfg(not(feature = "runtime-shaders"))]
+pub fn compile_text(
+    _device: &Arc<Device>,
+    name: &str,
+    _source: &str,
+    _defines: &[&str],
+) -> Result<Arc<ShaderModule>, String> {
+    Err(format!(
+        "can't compile shader `{name}`, built without the runtime-shaders feature"
+    ))
+}
+
+fn embedded(name: &str) -> Option<&'static str> {
+    SOURCES
+        .iter()
+        .find(|(source, _)| *source == name)
+        .map(|(_, text)| *text)
+}
+
+#[cfg(feature = "runtime-shaders")]
+fn resolve(
+    requested: &str,
+    include_type: shaderc::IncludeType,
+    requesting: &str,
+) -> Result<shaderc::ResolvedInclude, String> {
+    let path = match include_type {
+        shaderc::IncludeType::Relative => Path::new(requesting)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(requested),
+        shaderc::IncludeType::Standard => Path::new(requested).to_path_buf(),
+    };
+    let name = path.to_string_lossy().into_owned();
+    let (name, content) = match embedded(&name) {
+        Some(text) => (name, text.to_string()),
+        None => match fs::read_to_string(&path) {
+            Ok(text) => (name, text),
+            Err(err) => match embedded(requested) {
+                Some(text) => (requested.to_string(), text.to_string()),
+                None => return Err(format!("can't read included shader `{name}`: {err}")),
+            },
+        },
+    };
+    Ok(shaderc::ResolvedInclude {
+        resolved_name: name,
+        content,
+    })
+}
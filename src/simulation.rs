@@ -0,0 +1,256 @@
+use crate::world::{World, CHUNK_SIZE, WORLD_SIZE};
+use std::collections::{BTreeSet, HashMap};
+
+/// Voxel types with rules of their own.
+pub const STONE: u32 = 4;
+pub const WATER: u32 = 10;
+pub const LAVA: u32 = 11;
+pub const SAND: u32 = 12;
+pub const GRAVEL: u32 = 13;
+/// Sends rays and the camera to the linked portal, see `portal::Portals`.
+pub const PORTAL: u32 = 14;
+
+/// Seconds between two ticks of the simulation.
+pub const TICK: f32 = 0.1;
+/// Lava only flows every this many ticks.
+const LAVA_TICKS: u32 = 3;
+/// Ticks caught up on at most in a single batch, so a long frame doesn't stall the next one.
+const MAX_TICKS_PER_UPDATE: u32 = 4;
+/// Cells of a chunk in a `LiquidBatch`.
+pub const CHUNK_CELLS: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+const NEIGHBORS: [[i32; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+/// Ticks of the simulation run on the GPU by liquids.glsl, over the chunks which may change and
+/// the chunks around them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiquidBatch {
+    /// The simulated chunks, then the chunks around them, which are only read.
+    pub chunks: Vec<[u32; 3]>,
+    /// How many of `chunks` are simulated.
+    pub simulated: usize,
+    /// For every simulated chunk, the index in `chunks` of each chunk of the 3x3x3 block around
+    /// it, indexed by `(x * 3 + y) * 3 + z`, or -1 outside the world.
+    pub neighbors: Vec<i32>,
+    /// The voxels of `chunks` before the batch, `CHUNK_CELLS` per chunk indexed by
+    /// `(x * CHUNK_SIZE + y) * CHUNK_SIZE + z`: the voxel type in the lowest byte and the level
+    /// of flowing liquid in the next one, 0 for voxels which don't flow.
+    pub cells: Vec<u32>,
+    /// The tick the batch starts with, counted from the simulation's start.
+    pub first_tick: u32,
+    pub ticks: u32,
+    /// Whether sand and gravel fall.
+    pub falling_blocks: bool,
+}
+
+/// Cellular liquid flow on the voxel grid, advanced in fixed ticks on the GPU.
+///
+/// Liquid voxels placed in the world are sources. Liquid falls into the empty voxel below it and
+/// spreads sideways over solid ground, a level further from its source per voxel, until it
+/// reaches the liquid's spread. Flowing liquid which isn't fed anymore dries up, and lava
+/// touching water turns into stone. With `falling_blocks` sand and gravel fall a voxel per tick
+/// while the voxel below them is empty.
+///
+/// Only chunks which changed and their neighbors are simulated. `update` hands the ticks due to
+/// the GPU as a `LiquidBatch`, whose result comes back to `apply` a few frames later; no other
+/// batch starts meanwhile. Every voxel's next state is computed from the previous tick into a
+/// second buffer, so the order voxels are visited in doesn't matter. Liquid reaching a chunk
+/// which isn't simulated waits for the next batch to flow on.
+pub struct Simulation {
+    /// Whether sand and gravel fall.
+    pub falling_blocks: bool,
+    /// Levels of flowing liquid voxels, 1 next to what feeds them. Liquid voxels missing here
+    /// are sources.
+    levels: HashMap<[i32; 3], u8>,
+    /// Chunks to simulate in the next batch.
+    active: BTreeSet<[u32; 3]>,
+    /// Time not yet simulated.
+    time: f32,
+    ticks: u32,
+    /// Whether a batch is running on the GPU.
+    in_flight: bool,
+}
+
+impl Simulation {
+    pub fn new() -> Simulation {
+        Simulation {
+            falling_blocks: false,
+            levels: HashMap::new(),
+            active: BTreeSet::new(),
+            time: 0.0,
+            ticks: 0,
+            in_flight: false,
+        }
+    }
+
+    /// Returns the ticks due after `dt` more seconds as a batch to run on the GPU, `None` while
+    /// the last batch is still running or nothing needs simulating.
+    pub fn update(&mut self, world: &mut World, dt: f32) -> Option<LiquidBatch> {
+        self.time = (self.time + dt).min(TICK * MAX_TICKS_PER_UPDATE as f32);
+        if self.in_flight || self.time < TICK {
+            return None;
+        }
+        let ticks = (self.time / TICK) as u32;
+        self.time -= ticks as f32 * TICK;
+        let first_tick = self.ticks + 1;
+        self.ticks += ticks;
+        let lava_tick = (first_tick..=self.ticks).any(|tick| tick.is_multiple_of(LAVA_TICKS));
+        for chunk in world.take_edited_chunks() {
+            self.wake(chunk);
+        }
+
+        let mut chunks = Vec::new();
+        for chunk in std::mem::take(&mut self.active) {
+            let near = contents_near(world, chunk);
+            if !(near.water || near.lava || (near.falling && self.falling_blocks)) {
+                continue;
+            }
+            // Lava waiting for its tick has to be looked at again.
+            if near.lava && !lava_tick {
+                self.active.insert(chunk);
+            }
+            chunks.push(chunk);
+        }
+        if chunks.is_empty() {
+            return None;
+        }
+        let simulated = chunks.len();
+        let mut indices: HashMap<[u32; 3], usize> = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, &chunk)| (chunk, index))
+            .collect();
+        let mut neighbors = Vec::with_capacity(simulated * 27);
+        let last_chunk = (WORLD_SIZE / CHUNK_SIZE) as i32 - 1;
+        for index in 0..simulated {
+            let chunk = chunks[index];
+            for offset in 0..27 {
+                let offset = [offset / 9, offset / 3 % 3, offset % 3].map(|c| c - 1);
+                let neighbor = [0, 1, 2].map(|i| chunk[i] as i32 + offset[i]);
+                if neighbor.iter().any(|&c| c < 0 || c > last_chunk) {
+                    neighbors.push(-1);
+                    continue;
+                }
+                let neighbor = neighbor.map(|c| c as u32);
+                let index = *indices.entry(neighbor).or_insert_with(|| {
+                    chunks.push(neighbor);
+                    chunks.len() - 1
+                });
+                neighbors.push(index as i32);
+            }
+        }
+        let mut cells = Vec::with_capacity(chunks.len() * CHUNK_CELLS);
+        for &chunk in &chunks {
+            let min = chunk.map(|c| c * CHUNK_SIZE);
+            let voxels = world.region(min, min.map(|c| c + CHUNK_SIZE));
+            cells.extend(voxels.into_iter().enumerate().map(|(index, voxel)| {
+                let size = CHUNK_SIZE as usize;
+                let local = [index / size / size, index / size % size, index % size];
+                let position = [0, 1, 2].map(|i| (min[i] as usize + local[i]) as i32);
+                let level = self.levels.get(&position).copied().unwrap_or(0);
+                voxel | (level as u32) << 8
+            }));
+        }
+        self.in_flight = true;
+        Some(LiquidBatch {
+            chunks,
+            simulated,
+            neighbors,
+            cells,
+            first_tick,
+            ticks,
+            falling_blocks: self.falling_blocks,
+        })
+    }
+
+    /// Writes the result of `batch` into `world`, `cells` holding the voxels of its chunks after
+    /// it like `LiquidBatch::cells`. Voxels edited since the batch started keep the edit, and
+    /// results of a batch this simulation didn't start are dropped.
+    pub fn apply(&mut self, world: &mut World, batch: &LiquidBatch, cells: &[u32]) {
+        if !self.in_flight {
+            return;
+        }
+        self.in_flight = false;
+        for (index, &chunk) in batch.chunks[..batch.simulated].iter().enumerate() {
+            let range = index * CHUNK_CELLS..(index + 1) * CHUNK_CELLS;
+            let min = chunk.map(|c| c * CHUNK_SIZE);
+            let mut changed = false;
+            for (cell, (&before, &after)) in batch.cells[range.clone()]
+                .iter()
+                .zip(&cells[range])
+                .enumerate()
+            {
+                let size = CHUNK_SIZE as usize;
+                let local = [cell / size / size, cell / size % size, cell % size];
+                let position = [0, 1, 2].map(|i| min[i] + local[i] as u32);
+                let signed = position.map(|c| c as i32);
+                let voxel = after & 0xff;
+                if before == after || world.voxel(signed) != before & 0xff {
+                    continue;
+                }
+                if world.voxel(signed) != voxel {
+                    world.set_voxel(position, voxel);
+                }
+                match after >> 8 & 0xff {
+                    0 => self.levels.remove(&signed),
+                    level => self.levels.insert(signed, level as u8),
+                };
+                changed = true;
+            }
+            if changed {
+                self.wake(chunk);
+            }
+        }
+    }
+
+    /// Simulates `chunk` and its neighbors in the next batch.
+    fn wake(&mut self, chunk: [u32; 3]) {
+        let chunks = WORLD_SIZE / CHUNK_SIZE;
+        self.active.insert(chunk);
+        for offset in NEIGHBORS {
+            let neighbor = [0, 1, 2].map(|i| chunk[i] as i32 + offset[i]);
+            if neighbor.iter().all(|&c| c >= 0 && c < chunks as i32) {
+                self.active.insert(neighbor.map(|c| c as u32));
+            }
+        }
+    }
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation::new()
+    }
+}
+
+/// Which voxel types with rules of their own are in a chunk or the voxels bordering it.
+#[derive(Default)]
+struct Near {
+    water: bool,
+    lava: bool,
+    falling: bool,
+}
+
+fn contents_near(world: &World, chunk: [u32; 3]) -> Near {
+    let min = chunk.map(|c| (c * CHUNK_SIZE) as i32 - 1);
+    let mut near = Near::default();
+    for x in min[0]..min[0] + CHUNK_SIZE as i32 + 2 {
+        for y in min[1]..min[1] + CHUNK_SIZE as i32 + 2 {
+            for z in min[2]..min[2] + CHUNK_SIZE as i32 + 2 {
+                match world.voxel([x, y, z]) {
+                    WATER => near.water = true,
+                    LAVA => near.lava = true,
+                    SAND | GRAVEL => near.falling = true,
+                    _ => {}
+                }
+            }
+        }
+    }
+    near
+}
@@ -0,0 +1,123 @@
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+    sync::Sharing,
+};
+
+/// Number of uploads which can be in flight before the next one waits for the GPU to finish
+/// copying out of the oldest.
+const STAGING_RING_SIZE: usize = 3;
+
+/// Ring of host visible buffers uploads are written into, allocated once and kept mapped rather
+/// than allocating a new buffer for every upload. A buffer is only written again once the GPU is
+/// done copying out of it, so uploading never waits on the GPU.
+///
+/// With a transfer queue every buffer has a device local twin the transfer queue copies it into
+/// while the previous frame is still traced. The frame's own queue then only copies from device
+/// memory into the world, and the world is never written while a trace reads it.
+pub struct StagingRing {
+    buffers: [Subbuffer<[u32]>; STAGING_RING_SIZE],
+    /// Device local twins of `buffers` shared between the transfer queue's family and the
+    /// frame's, see `new`.
+    landing: Option<[Subbuffer<[u32]>; STAGING_RING_SIZE]>,
+    next: usize,
+}
+
+/// Words written into a `StagingRing`.
+pub struct Staged {
+    /// The part of a host visible buffer holding them.
+    pub host: Subbuffer<[u32]>,
+    /// The part of its device local twin they are copied into first, if it has one.
+    pub landing: Option<Subbuffer<[u32]>>,
+}
+
+impl StagingRing {
+    /// Creates a ring of buffers holding `words` words each. When `queue_families` holds a
+    /// transfer queue's family after the frame's, the buffers get device local twins shared
+    /// between them.
+    pub fn new(
+        memory_allocator: &StandardMemoryAllocator,
+        words: u64,
+        queue_families: &[u32],
+    ) -> StagingRing {
+        let buffer = |usage: BufferUsage, queue_families: &[u32], memory_usage| {
+            Buffer::new_slice(
+                memory_allocator,
+                buffer_info(usage, queue_families),
+                AllocationCreateInfo {
+                    usage: memory_usage,
+                    ..Default::default()
+                },
+                words,
+            )
+            .unwrap()
+        };
+        let buffers = [(); STAGING_RING_SIZE]
+            .map(|_| buffer(BufferUsage::TRANSFER_SRC, &[], MemoryUsage::Upload));
+        let landing = (queue_families.len() > 1).then(|| {
+            [(); STAGING_RING_SIZE].map(|_| {
+                buffer(
+                    BufferUsage::TRANSFER_SRC | BufferUsage::TRANSFER_DST,
+                    queue_families,
+                    MemoryUsage::DeviceOnly,
+                )
+            })
+        });
+        StagingRing {
+            buffers,
+            landing,
+            next: 0,
+        }
+    }
+
+    /// Returns the words each buffer holds.
+    pub fn words(&self) -> u64 {
+        self.buffers[0].len()
+    }
+
+    /// Returns the bytes all buffers and their twins take together.
+    pub fn bytes(&self) -> u64 {
+        self.buffers
+            .iter()
+            .chain(self.landing.iter().flatten())
+            .map(|buffer| buffer.size())
+            .sum()
+    }
+
+    /// Returns whether a buffer is free to be written. Its twin is too then, the copies into
+    /// and out of it finish with the same frame.
+    pub fn ready(&self) -> bool {
+        self.buffers[self.next].write().is_ok()
+    }
+
+    /// Copies `words`, which must fit in a buffer and not be empty, into the next buffer and
+    /// returns where they are, `None` when the GPU still copies out of it.
+    pub fn write(&mut self, words: &[u32]) -> Option<Staged> {
+        let buffer = &self.buffers[self.next];
+        buffer.write().ok()?[..words.len()].copy_from_slice(words);
+        let range = 0..words.len() as u64;
+        let staged = Staged {
+            host: buffer.clone().slice(range.clone()),
+            landing: self
+                .landing
+                .as_ref()
+                .map(|landing| landing[self.next].clone().slice(range)),
+        };
+        self.next = (self.next + 1) % STAGING_RING_SIZE;
+        Some(staged)
+    }
+}
+
+/// Returns the create info of a buffer with `usage` used on all of `queue_families`, shared
+/// between them when there are several so it needn't be handed over between them.
+pub fn buffer_info(usage: BufferUsage, queue_families: &[u32]) -> BufferCreateInfo {
+    BufferCreateInfo {
+        sharing: if queue_families.len() > 1 {
+            Sharing::Concurrent(queue_families.iter().copied().collect())
+        } else {
+            Sharing::Exclusive
+        },
+        usage,
+        ..Default::default()
+    }
+}
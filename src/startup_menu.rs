@@ -0,0 +1,146 @@
+use crate::window_renderer::WindowRenderer;
+use egui_winit_vulkano::{egui, Gui, GuiConfig};
+use rvengine::{recent::RecentImports, worldgen};
+use std::{fs, path::Path};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+};
+
+/// Extension of the scene files listed as saved worlds.
+const SCENE_EXTENSION: &str = "rvscene";
+
+/// What was picked in the startup menu.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Choice {
+    /// Scene file to open, as with `--scene`.
+    Scene(String),
+    /// Name of the `worldgen::preset` to generate a world with, as with `--generator`.
+    Generator(String),
+    /// `.vox` model to open in the model viewer, as with `view`.
+    Model(String),
+}
+
+/// Everything the startup menu offers.
+pub struct MenuEntries {
+    /// Scene files in the working directory, sorted by name.
+    scenes: Vec<String>,
+    imports: Vec<String>,
+}
+
+impl MenuEntries {
+    /// Lists the scenes in the working directory and the recent imports that still exist.
+    pub fn find(recent: &RecentImports) -> MenuEntries {
+        let mut scenes: Vec<String> = fs::read_dir(".")
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == SCENE_EXTENSION)
+            })
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect();
+        scenes.sort();
+        let imports = recent
+            .paths()
+            .iter()
+            .filter(|path| Path::new(path).exists())
+            .cloned()
+            .collect();
+        MenuEntries { scenes, imports }
+    }
+}
+
+/// Shows the startup menu in the window until a world is picked. Returns `None` when the window
+/// was closed instead.
+pub fn choose(
+    event_loop: &mut EventLoop<()>,
+    renderer: &mut WindowRenderer,
+    entries: &MenuEntries,
+) -> Option<Choice> {
+    let mut gui = Gui::new(
+        event_loop,
+        renderer.surface(),
+        renderer.graphics_queue(),
+        renderer.swapchain_format(),
+        GuiConfig::default(),
+    );
+    renderer.window().set_title("RayVox");
+    loop {
+        let mut is_running = true;
+        event_loop.run_return(|event, _, control_flow| {
+            *control_flow = ControlFlow::Wait;
+            match &event {
+                Event::WindowEvent { event, .. } => {
+                    gui.update(event);
+                    match event {
+                        WindowEvent::CloseRequested => is_running = false,
+                        WindowEvent::Resized(..) | WindowEvent::ScaleFactorChanged { .. } => {
+                            renderer.resize()
+                        }
+                        _ => (),
+                    }
+                }
+                Event::MainEventsCleared => *control_flow = ControlFlow::Exit,
+                _ => (),
+            }
+        });
+        if !is_running {
+            return None;
+        }
+
+        let mut choice = None;
+        gui.immediate_ui(|gui| {
+            let ctx = gui.context();
+            egui::CentralPanel::default().show(&ctx, |ui| {
+                ui.heading("RayVox");
+                ui.separator();
+                ui.label("Saved worlds");
+                if entries.scenes.is_empty() {
+                    ui.weak("none in this directory, save one with `scene save <file>`");
+                }
+                for scene in &entries.scenes {
+                    if ui.button(scene).clicked() {
+                        choice = Some(Choice::Scene(scene.clone()));
+                    }
+                }
+                ui.separator();
+                ui.label("New world");
+                for name in worldgen::PRESETS {
+                    if ui.button(name).clicked() {
+                        choice = Some(Choice::Generator(name.to_string()));
+                    }
+                }
+                ui.separator();
+                ui.label("Recent imports");
+                if entries.imports.is_empty() {
+                    ui.weak("none yet, open one with `rvengine view <model.vox>`");
+                }
+                for import in &entries.imports {
+                    if ui.button(import).clicked() {
+                        choice = Some(Choice::Model(import.clone()));
+                    }
+                }
+            });
+        });
+        if choice.is_some() {
+            return choice;
+        }
+
+        if renderer.window_size().contains(&0.0) {
+            continue;
+        }
+        let before_future = match renderer.acquire() {
+            Err(e) => {
+                println!("{e}");
+                continue;
+            }
+            Ok(future) => future,
+        };
+        let after_future = gui.draw_on_image(before_future, renderer.swapchain_image_view());
+        renderer.present(after_future, true);
+    }
+}
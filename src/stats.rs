@@ -0,0 +1,223 @@
+use crate::{memory_budget::MemoryKind, settings::Settings};
+use std::{collections::VecDeque, fmt::Write, fs, path::Path, time::Instant};
+
+/// Report file `stats` writes to when neither a path nor `--stats` is given.
+pub const DEFAULT_STATS_PATH: &str = "rayvox-stats.json";
+/// Frames whose times are kept for the percentiles of the report, ten minutes at 60 frames a
+/// second. Older frames only count towards the mean and the maximum.
+const RECENT_FRAMES: usize = 36_000;
+
+/// Per frame measurements of a session, summarized into a JSON report for bug reports.
+pub struct SessionStats {
+    start: Instant,
+    frames: u64,
+    /// Sum and maximum of the frame times in milliseconds.
+    frame_time_sum: f64,
+    frame_time_max: f32,
+    /// Times of the last `RECENT_FRAMES` frames in milliseconds, oldest first.
+    recent_frame_times: VecDeque<f32>,
+    /// Sum and maximum of the bytes the world uploaded to the GPU each frame.
+    upload_bytes: u64,
+    upload_bytes_max: u64,
+    /// Sum, maximum and number of measurements of every GPU pass in milliseconds, in the order
+    /// the passes were first seen.
+    passes: Vec<(&'static str, f64, f32, u32)>,
+}
+
+/// What the report says about the scene, besides the measurements.
+pub struct Scene {
+    pub world_size: u32,
+    pub solid_voxels: usize,
+    pub resolution: [u32; 2],
+    pub settings: Settings,
+    pub memory_budget: u64,
+    /// Bytes of GPU memory in use by kind of allocation.
+    pub memory_used: [(&'static str, u64); MemoryKind::ALL.len()],
+}
+
+impl SessionStats {
+    pub fn new() -> SessionStats {
+        SessionStats {
+            start: Instant::now(),
+            frames: 0,
+            frame_time_sum: 0.0,
+            frame_time_max: 0.0,
+            recent_frame_times: VecDeque::with_capacity(RECENT_FRAMES),
+            upload_bytes: 0,
+            upload_bytes_max: 0,
+            passes: Vec::new(),
+        }
+    }
+
+    /// Adds a frame which took `frame_time` milliseconds and uploaded `upload_bytes`, along with
+    /// the latest GPU pass timings.
+    pub fn record_frame(
+        &mut self,
+        frame_time: f32,
+        upload_bytes: u64,
+        pass_timings: &[(&'static str, f32)],
+    ) {
+        self.frames += 1;
+        self.frame_time_sum += frame_time as f64;
+        self.frame_time_max = self.frame_time_max.max(frame_time);
+        if self.recent_frame_times.len() == RECENT_FRAMES {
+            self.recent_frame_times.pop_front();
+        }
+        self.recent_frame_times.push_back(frame_time);
+        self.upload_bytes += upload_bytes;
+        self.upload_bytes_max = self.upload_bytes_max.max(upload_bytes);
+        for &(name, time) in pass_timings {
+            match self.passes.iter_mut().find(|pass| pass.0 == name) {
+                Some(pass) => {
+                    pass.1 += time as f64;
+                    pass.2 = pass.2.max(time);
+                    pass.3 += 1;
+                }
+                None => self.passes.push((name, time as f64, time, 1)),
+            }
+        }
+    }
+
+    /// Returns the report as JSON.
+    pub fn to_json(&self, scene: &Scene) -> String {
+        let frames = self.frames;
+        let mut sorted: Vec<f32> = self.recent_frame_times.iter().copied().collect();
+        sorted.sort_by(f32::total_cmp);
+        let mean_frame_time = self.frame_time_sum / frames.max(1) as f64;
+        let total_upload = self.upload_bytes;
+        let settings = &scene.settings;
+
+        let mut json = String::from("{\n");
+        let duration = self.start.elapsed().as_secs_f32();
+        writeln!(json, "  \"duration_s\": {duration:.3},").unwrap();
+        writeln!(json, "  \"frames\": {frames},").unwrap();
+        writeln!(json, "  \"frame_time_ms\": {{").unwrap();
+        writeln!(json, "    \"mean\": {mean_frame_time:.3},").unwrap();
+        for p in [50.0, 90.0, 95.0, 99.0] {
+            writeln!(json, "    \"p{p}\": {:.3},", percentile(&sorted, p)).unwrap();
+        }
+        writeln!(json, "    \"max\": {:.3}", self.frame_time_max).unwrap();
+        writeln!(json, "  }},").unwrap();
+        writeln!(json, "  \"gpu_passes_ms\": {{").unwrap();
+        for (index, &(name, sum, max, count)) in self.passes.iter().enumerate() {
+            let separator = if index + 1 < self.passes.len() {
+                ","
+            } else {
+                ""
+            };
+            writeln!(
+                json,
+                "    \"{name}\": {{ \"mean\": {:.3}, \"max\": {max:.3} }}{separator}",
+                sum / count as f64
+            )
+            .unwrap();
+        }
+        writeln!(json, "  }},").unwrap();
+        writeln!(json, "  \"upload_bytes_per_frame\": {{").unwrap();
+        writeln!(json, "    \"mean\": {},", total_upload / frames.max(1)).unwrap();
+        writeln!(json, "    \"max\": {},", self.upload_bytes_max).unwrap();
+        writeln!(json, "    \"total\": {total_upload}").unwrap();
+        writeln!(json, "  }},").unwrap();
+        writeln!(json, "  \"world\": {{").unwrap();
+        writeln!(json, "    \"size\": {},", scene.world_size).unwrap();
+        writeln!(json, "    \"solid_voxels\": {}", scene.solid_voxels).unwrap();
+        writeln!(json, "  }},").unwrap();
+        writeln!(json, "  \"memory_bytes\": {{").unwrap();
+        for (name, bytes) in scene.memory_used {
+            writeln!(json, "    \"{name}\": {bytes},").unwrap();
+        }
+        writeln!(json, "    \"budget\": {}", scene.memory_budget).unwrap();
+        writeln!(json, "  }},").unwrap();
+        writeln!(json, "  \"resolution\": {:?},", scene.resolution).unwrap();
+        writeln!(json, "  \"settings\": {{").unwrap();
+        writeln!(
+            json,
+            "    \"preset\": \"{}\",",
+            settings.preset.map_or("custom", |preset| preset.name())
+        )
+        .unwrap();
+        writeln!(
+            json,
+            "    \"render_distance\": {},",
+            settings.render_distance
+        )
+        .unwrap();
+        writeln!(json, "    \"max_ray_steps\": {},", settings.max_ray_steps).unwrap();
+        writeln!(json, "    \"max_bounces\": {},", settings.max_bounces).unwrap();
+        for (name, enabled) in [
+            ("step_warning", settings.step_warning),
+            ("ambient_occlusion", settings.ambient_occlusion),
+            ("shadows", settings.shadows),
+            ("colored_shadows", settings.colored_shadows),
+            ("global_illumination", settings.global_illumination),
+            ("baked_lighting", settings.baked_lighting),
+            ("accumulate", settings.accumulate),
+            ("adaptive_sampling", settings.adaptive_sampling),
+            ("half_res_lighting", settings.half_res_lighting),
+            ("tile_classification", settings.tile_classification),
+        ] {
+            writeln!(json, "    \"{name}\": {enabled},").unwrap();
+        }
+        writeln!(
+            json,
+            "    \"variance_threshold\": {},",
+            settings.variance_threshold
+        )
+        .unwrap();
+        writeln!(json, "    \"render_scale\": {},", settings.render_scale).unwrap();
+        writeln!(json, "    \"upscaler\": \"{}\",", settings.upscaler.name()).unwrap();
+        writeln!(json, "    \"aa_mode\": \"{}\"", settings.aa_mode.name()).unwrap();
+        writeln!(json, "  }}").unwrap();
+        json.push_str("}\n");
+        json
+    }
+
+    /// Writes the report to `path`.
+    pub fn save(&self, path: impl AsRef<Path>, scene: &Scene) -> Result<(), String> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json(scene))
+            .map_err(|err| format!("can't write stats `{}`: {err}", path.display()))
+    }
+}
+
+/// Returns the `p`th percentile of the ascending `sorted` values by the nearest rank, 0 without
+/// any.
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    let rank = (sorted.len() as f32 * p / 100.0).ceil() as usize;
+    sorted
+        .get(rank.clamp(1, sorted.len().max(1)) - 1)
+        .copied()
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_take_the_nearest_rank() {
+        let sorted: Vec<f32> = (1..=100).map(|time| time as f32).collect();
+        assert_eq!(percentile(&sorted, 50.0), 50.0);
+        assert_eq!(percentile(&sorted, 99.0), 99.0);
+        assert_eq!(percentile(&sorted, 100.0), 100.0);
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&[], 50.0), 0.0);
+    }
+
+    #[test]
+    fn frame_times_keep_only_the_recent_ones() {
+        let mut stats = SessionStats::new();
+        for time in 1..=100 {
+            stats.record_frame(time as f32, time, &[("trace", 1.0)]);
+        }
+        assert_eq!(stats.frames, 100);
+        assert_eq!(stats.frame_time_sum / 100.0, 50.5);
+        for _ in 0..RECENT_FRAMES {
+            stats.record_frame(1.0, 0, &[]);
+        }
+        assert_eq!(stats.recent_frame_times.len(), RECENT_FRAMES);
+        assert_eq!(stats.frames, RECENT_FRAMES as u64 + 100);
+        assert_eq!(stats.frame_time_max, 100.0);
+        assert_eq!(stats.upload_bytes, 5050);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::{
+    materials::MATERIAL_COUNT,
+    prefab::Prefab,
+    simulation::{LAVA, WATER},
+    world::{World, WORLD_SIZE},
+    worldgen::chunk_rng,
+};
+use rand::Rng;
+use std::{fs, path::Path};
+
+/// What `parse_rule` expects.
+const RULE_SYNTAX: &str =
+    "expected `model.vox [on <type>...] [altitude <min> <max>] [spacing <voxels>] [rotate]`";
+/// Spacing of rules which don't give one.
+pub const DEFAULT_SPACING: u32 = 64;
+/// Mixed into the world's seed so structures don't draw the same random numbers as the veins.
+const STRUCTURE_SEED: u64 = 0x7374_7275_6374;
+
+/// Where a structure may be placed, and the structure.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructureRule {
+    /// The structure in every orientation it may be placed in, a quarter turn apart.
+    pub rotations: Vec<Prefab>,
+    /// Voxel types the structure may stand on, any solid one when empty. The ground stands in
+    /// for the biome, grass for meadows and sand for beaches.
+    pub ground: Vec<u32>,
+    /// Lowest and highest height of the structure's bottom layer.
+    pub altitude: [u32; 2],
+    /// Edge length of the squares of the world holding one structure at most, placed in their
+    /// lower half so two are at least half the spacing apart.
+    pub spacing: u32,
+}
+
+/// Landmarks stamped onto the surface of generated worlds, read from a file with one rule per
+/// line, see `parse_rule`. Structures are placed from the world's seed alone, so a world comes
+/// out the same every time it is generated.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Structures {
+    pub rules: Vec<StructureRule>,
+}
+
+impl Structures {
+    /// Reads the rules at `path`. Models are found relative to the file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Structures, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("can't read structures `{}`: {err}", path.display()))?;
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let mut rules = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            rules.push(
+                parse_rule(line, directory)
+                    .map_err(|err| format!("{}:{}: {err}", path.display(), number + 1))?,
+            );
+        }
+        Ok(Structures { rules })
+    }
+
+    /// Stamps the structures of a world generated from `seed` onto the surface of `world`,
+    /// returning how many were placed. Every square of a rule's spacing gets a random column,
+    /// which holds the structure if its highest voxel is ground of the rule at a fitting height.
+    pub fn place(&self, seed: u64, world: &mut World) -> usize {
+        let mut placed = 0;
+        for (index, rule) in self.rules.iter().enumerate() {
+            let squares = WORLD_SIZE.div_ceil(rule.spacing);
+            for square_x in 0..squares {
+                for square_z in 0..squares {
+                    let mut rng =
+                        chunk_rng(seed ^ STRUCTURE_SEED, [square_x, index as u32, square_z]);
+                    let [x, z] = [square_x, square_z]
+                        .map(|square| square * rule.spacing + rng.gen_range(0..rule.spacing / 2));
+                    let prefab = &rule.rotations[rng.gen_range(0..rule.rotations.len())];
+                    let Some(y) = surface(world, [x, z]) else {
+                        continue;
+                    };
+                    let ground = world.voxel([x, y, z].map(|c| c as i32));
+                    let bottom = y + 1;
+                    if [0, WATER, LAVA].contains(&ground)
+                        || !(rule.ground.is_empty() || rule.ground.contains(&ground))
+                        || bottom < rule.altitude[0]
+                        || bottom > rule.altitude[1]
+                    {
+                        continue;
+                    }
+                    let origin = prefab.origin_at([x, bottom, z].map(|c| c as i32));
+                    world.paste_region(origin, prefab.size, &prefab.voxels);
+                    placed += 1;
+                }
+            }
+        }
+        placed
+    }
+}
+
+/// Returns the height of the highest voxel of the column at `[x, z]`, `None` if it is empty or
+/// outside of the world.
+fn surface(world: &World, [x, z]: [u32; 2]) -> Option<u32> {
+    (0..WORLD_SIZE)
+        .rev()
+        .find(|&y| world.voxel([x, y, z].map(|c| c as i32)) != 0)
+}
+
+/// Parses a `model.vox [on <type>...] [altitude <min> <max>] [spacing <voxels>] [rotate]` line
+/// into a rule, loading the model relative to `directory`. `rotate` places the structure in any
+/// of the four orientations around y instead of only as it was modeled.
+pub fn parse_rule(line: &str, directory: &Path) -> Result<StructureRule, String> {
+    let mut words = line.split_whitespace().peekable();
+    let model = words.next().ok_or(RULE_SYNTAX)?;
+    let prefab = Prefab::load(directory.join(model))?;
+    let mut rule = StructureRule {
+        rotations: vec![prefab],
+        ground: Vec::new(),
+        altitude: [0, WORLD_SIZE],
+        spacing: DEFAULT_SPACING,
+    };
+    let number = |word: Option<&str>, what: &str| {
+        let word = word.ok_or(RULE_SYNTAX)?;
+        word.parse::<u32>()
+            .map_err(|err| format!("invalid {what} `{word}`: {err}"))
+    };
+    while let Some(word) = words.next() {
+        match word {
+            "on" => {
+                while let Some(ground) = words.next_if(|word| word.parse::<u32>().is_ok()) {
+                    let ground = number(Some(ground), "ground")?;
+                    if ground == 0 || ground as usize >= MATERIAL_COUNT {
+                        return Err(format!("invalid voxel type {ground}"));
+                    }
+                    rule.ground.push(ground);
+                }
+                if rule.ground.is_empty() {
+                    return Err("`on` needs voxel types".to_string());
+                }
+            }
+            "altitude" => {
+                rule.altitude = [
+                    number(words.next(), "altitude")?,
+                    number(words.next(), "altitude")?,
+                ];
+                if rule.altitude[0] > rule.altitude[1] {
+                    return Err(format!(
+                        "lowest altitude {} above highest {}",
+                        rule.altitude[0], rule.altitude[1]
+                    ));
+                }
+            }
+            "spacing" => {
+                rule.spacing = number(words.next(), "spacing")?;
+                if rule.spacing < 2 {
+                    return Err("spacing has to be at least 2 voxels".to_string());
+                }
+            }
+            "rotate" => {
+                while rule.rotations.len() < 4 {
+                    let turned = rule.rotations[rule.rotations.len() - 1].rotated();
+                    rule.rotations.push(turned);
+                }
+            }
+            _ => return Err(format!("unexpected `{word}`, {RULE_SYNTAX}")),
+        }
+    }
+    Ok(rule)
+}
@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use vulkano::swapchain::{PresentMode, SwapchainCreateInfo};
+
+/// Swapchain images asked for with `--swapchain-images`, 0 leaves the surface's minimum.
+static SWAPCHAIN_IMAGES: AtomicU32 = AtomicU32::new(0);
+
+/// Present modes which can be asked for on the command line.
+pub const PRESENT_MODES: [(&str, PresentMode); 4] = [
+    ("fifo", PresentMode::Fifo),
+    ("relaxed", PresentMode::FifoRelaxed),
+    ("mailbox", PresentMode::Mailbox),
+    ("immediate", PresentMode::Immediate),
+];
+
+/// Looks a present mode up by its name in `PRESENT_MODES`.
+pub fn present_mode_from_name(name: &str) -> Option<PresentMode> {
+    PRESENT_MODES
+        .iter()
+        .find(|(mode_name, _)| mode_name.eq_ignore_ascii_case(name))
+        .map(|&(_, mode)| mode)
+}
+
+pub fn present_mode_name(mode: PresentMode) -> &'static str {
+    PRESENT_MODES
+        .iter()
+        .find(|(_, other)| *other == mode)
+        .map_or("other", |(name, _)| name)
+}
+
+/// Returns `preferred` if the surface supports it, otherwise the closest supported mode: the
+/// other low latency mode for mailbox and immediate, then fifo which every surface supports.
+pub fn choose_present_mode(
+    preferred: PresentMode,
+    supported: impl IntoIterator<Item = PresentMode>,
+) -> PresentMode {
+    let supported: Vec<_> = supported.into_iter().collect();
+    let fallbacks: &[PresentMode] = match preferred {
+        PresentMode::Mailbox => &[PresentMode::Mailbox, PresentMode::Immediate],
+        PresentMode::Immediate => &[PresentMode::Immediate, PresentMode::Mailbox],
+        PresentMode::FifoRelaxed => &[PresentMode::FifoRelaxed],
+        _ => &[],
+    };
+    fallbacks
+        .iter()
+        .copied()
+        .find(|mode| supported.contains(mode))
+        .unwrap_or(PresentMode::Fifo)
+}
+
+/// Sets the number of swapchain images `apply_image_count` asks for, 2 for double and 3 for
+/// triple buffering. Has to be called before the window is created.
+pub fn set_image_count(count: u32) {
+    SWAPCHAIN_IMAGES.store(count, Ordering::Relaxed);
+}
+
+/// Raises the swapchain's image count to the one set with `set_image_count`. The surface's
+/// minimum is kept when it is larger, so asking for double buffering falls back to triple
+/// buffering on drivers which don't allow it.
+pub fn apply_image_count(create_info: &mut SwapchainCreateInfo) {
+    let count = SWAPCHAIN_IMAGES.load(Ordering::Relaxed);
+    create_info.min_image_count = create_info.min_image_count.max(count);
+}
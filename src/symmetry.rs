@@ -0,0 +1,51 @@
+/// Mirror planes edits are repeated across. Each enabled axis mirrors through the center of the
+/// voxel at `origin`, so with all three enabled an edit is applied up to eight times.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Symmetry {
+    /// Whether the plane perpendicular to x, y and z respectively is enabled.
+    pub axes: [bool; 3],
+    pub origin: [i32; 3],
+}
+
+impl Symmetry {
+    pub fn new(origin: [i32; 3]) -> Symmetry {
+        Symmetry {
+            axes: [false; 3],
+            origin,
+        }
+    }
+
+    /// Returns `position` followed by its distinct mirror images.
+    pub fn positions(&self, position: [i32; 3]) -> Vec<[i32; 3]> {
+        let mut positions = vec![position];
+        for axis in 0..3 {
+            if !self.axes[axis] {
+                continue;
+            }
+            for i in 0..positions.len() {
+                let mut mirrored = positions[i];
+                mirrored[axis] = 2 * self.origin[axis] - mirrored[axis];
+                if !positions.contains(&mirrored) {
+                    positions.push(mirrored);
+                }
+            }
+        }
+        positions
+    }
+
+    /// Returns the enabled axes as letters, `-` when none is.
+    pub fn axes_name(&self) -> String {
+        let name: String = self
+            .axes
+            .iter()
+            .zip(['x', 'y', 'z'])
+            .filter(|(enabled, _)| **enabled)
+            .map(|(_, axis)| axis)
+            .collect();
+        if name.is_empty() {
+            "-".to_string()
+        } else {
+            name
+        }
+    }
+}
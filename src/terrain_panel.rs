@@ -0,0 +1,56 @@
+use egui_winit_vulkano::egui;
+use rvengine::{world::WORLD_SIZE, worldgen::Terrain};
+
+/// Window tuning the noise, erosion and caves of the terrain generated on the GPU, opened with `terrain panel on`.
+/// Every change regenerates the world, at most one generation in flight at a time.
+pub struct TerrainPanel {
+    /// Parameters as the sliders show them.
+    terrain: Terrain,
+    /// Parameters the world was last generated with.
+    generated: Terrain,
+}
+
+impl TerrainPanel {
+    /// Starts from the world generated with `terrain`.
+    pub fn new(terrain: Terrain) -> TerrainPanel {
+        TerrainPanel {
+            terrain,
+            generated: terrain,
+        }
+    }
+
+    /// Draws the panel and returns the terrain to generate when the sliders moved since the
+    /// last generation. `generating` holds changes back until the one in flight is done.
+    pub fn show(&mut self, ctx: &egui::Context, generating: bool) -> Option<Terrain> {
+        let terrain = &mut self.terrain;
+        egui::Window::new("Terrain").show(ctx, |ui| {
+            ui.add(egui::Slider::new(&mut terrain.seed, 0..=u32::MAX).text("seed"));
+            ui.add(
+                egui::Slider::new(&mut terrain.frequency, 1.0 / 512.0..=1.0 / 4.0)
+                    .logarithmic(true)
+                    .text("frequency"),
+            );
+            ui.add(egui::Slider::new(&mut terrain.octaves, 1..=8).text("octaves"));
+            ui.add(egui::Slider::new(&mut terrain.amplitude, 0.0..=128.0).text("amplitude"));
+            ui.add(egui::Slider::new(&mut terrain.warp, 0.0..=128.0).text("warp"));
+            let height = 0.0..=WORLD_SIZE as f32;
+            ui.add(egui::Slider::new(&mut terrain.base_height, height.clone()).text("base height"));
+            ui.add(egui::Slider::new(&mut terrain.water_level, height).text("water level"));
+            ui.add(egui::Slider::new(&mut terrain.erosion, 0..=500).text("erosion steps"));
+            ui.add_enabled(
+                terrain.erosion > 0,
+                egui::Slider::new(&mut terrain.talus, 0.1..=4.0).text("talus"),
+            );
+            ui.add(egui::Slider::new(&mut terrain.caves, 0.0..=0.3).text("cave width"));
+            ui.add(egui::Slider::new(&mut terrain.worms, 0..=64).text("cave entrances"));
+            if generating {
+                ui.weak("generating...");
+            }
+        });
+        if generating || self.terrain == self.generated {
+            return None;
+        }
+        self.generated = self.terrain;
+        Some(self.terrain)
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{world::WORLD_SIZE, world_file};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+};
+
+/// Edge length of the cubes a map is stored in, the world holds two along every axis.
+pub const TILE_SIZE: u32 = 128;
+/// Voxels in a tile.
+const TILE_VOXELS: usize = (TILE_SIZE * TILE_SIZE * TILE_SIZE) as usize;
+/// Tiles of the world along every axis.
+const WINDOW_TILES: u32 = WORLD_SIZE / TILE_SIZE;
+/// File in a map's directory holding its size.
+pub const MAP_INDEX: &str = "map.txt";
+/// Tiles a `TileWriter` keeps in memory, 8 MiB each, before writing the least recently used.
+pub const MAX_CACHED_TILES: usize = 32;
+/// Voxels from the edge of the world the camera comes at most before `MapStream` moves the
+/// world a tile further. Below half a tile, so the camera isn't near the opposite edge right
+/// after the move.
+pub const STREAM_MARGIN: f32 = 48.0;
+
+/// Start of a tile file.
+const TILE_MAGIC: &[u8; 4] = b"RVTL";
+const TILE_VERSION: u32 = 1;
+
+/// A voxel map larger than the world, stored in a directory as `TILE_SIZE`³ tiles which are
+/// loaded a world at a time, see `MapStream`. Tiles without a file are empty, so empty space
+/// takes no room on disk.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TiledMap {
+    directory: PathBuf,
+    /// Voxels along every axis.
+    size: [u32; 3],
+}
+
+impl TiledMap {
+    /// Creates an empty map of `size` voxels in `directory`, creating the directory. Tiles of
+    /// a map which was there before are left, so the directory should be new or empty.
+    pub fn create(directory: impl AsRef<Path>, size: [u32; 3]) -> Result<TiledMap, String> {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory)
+            .map_err(|err| format!("can't create map `{}`: {err}", directory.display()))?;
+        let [x, y, z] = size;
+        let index = directory.join(MAP_INDEX);
+        fs::write(&index, format!("size {x} {y} {z}\n"))
+            .map_err(|err| format!("can't write `{}`: {err}", index.display()))?;
+        Ok(TiledMap {
+            directory: directory.to_path_buf(),
+            size,
+        })
+    }
+
+    /// Opens the map in `directory`, written by `create`.
+    pub fn open(directory: impl AsRef<Path>) -> Result<TiledMap, String> {
+        let directory = directory.as_ref();
+        let index = directory.join(MAP_INDEX);
+        let text = fs::read_to_string(&index)
+            .map_err(|err| format!("can't read map `{}`: {err}", index.display()))?;
+        let size = text
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("size "))
+            .and_then(|size| {
+                let size = size
+                    .split_whitespace()
+                    .map(|value| value.parse().ok())
+                    .collect::<Option<Vec<u32>>>()?;
+                size.try_into().ok()
+            })
+            .ok_or_else(|| format!("no valid `size x y z` line in `{}`", index.display()))?;
+        Ok(TiledMap {
+            directory: directory.to_path_buf(),
+            size,
+        })
+    }
+
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+
+    fn tile_path(&self, [x, y, z]: [u32; 3]) -> PathBuf {
+        self.directory.join(format!("tile_{x}_{y}_{z}.rvtile"))
+    }
+
+    /// Returns the voxels of `tile`, indexed like the world's with `TILE_SIZE` for its size.
+    pub fn read_tile(&self, tile: [u32; 3]) -> Result<Vec<u32>, String> {
+        let path = self.tile_path(tile);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![0; TILE_VOXELS]),
+            Err(err) => return Err(format!("can't read tile `{}`: {err}", path.display())),
+        };
+        parse_tile(&bytes).map_err(|err| format!("invalid tile `{}`: {err}", path.display()))
+    }
+
+    /// Writes the voxels of `tile`, removing its file instead when it is empty.
+    pub fn write_tile(&self, tile: [u32; 3], voxels: &[u32]) -> Result<(), String> {
+        let path = self.tile_path(tile);
+        if voxels.len() != TILE_VOXELS {
+            return Err(format!(
+                "can't write tile `{}` of {} voxels instead of {TILE_VOXELS}",
+                path.display(),
+                voxels.len()
+            ));
+        }
+        if voxels.iter().all(|&voxel| voxel == 0) {
+            return match fs::remove_file(&path) {
+                Err(err) if err.kind() != ErrorKind::NotFound => {
+                    Err(format!("can't remove tile `{}`: {err}", path.display()))
+                }
+                _ => Ok(()),
+            };
+        }
+        world_file::write_atomically(&path, &tile_bytes(voxels))
+            .map_err(|err| format!("can't write tile `{}`: {err}", path.display()))
+    }
+
+    /// Returns the voxels of the world sized window of the map starting at `origin`, a multiple
+    /// of `TILE_SIZE`, indexed like the world's. Whatever is outside of the map is empty.
+    pub fn window(&self, origin: [u32; 3]) -> Result<Vec<u32>, String> {
+        let size = WORLD_SIZE as usize;
+        let tile_size = TILE_SIZE as usize;
+        let mut voxels = vec![0; size * size * size];
+        for x in 0..WINDOW_TILES {
+            for y in 0..WINDOW_TILES {
+                for z in 0..WINDOW_TILES {
+                    let offset = [x, y, z];
+                    let tile = [0, 1, 2].map(|i| origin[i] / TILE_SIZE + offset[i]);
+                    let tile_voxels = self.read_tile(tile)?;
+                    let [ox, oy, oz] = offset.map(|c| (c * TILE_SIZE) as usize);
+                    for (row, tile_row) in tile_voxels.chunks_exact(tile_size).enumerate() {
+                        let [tx, ty] = [row / tile_size, row % tile_size];
+                        let start = ((ox + tx) * size + oy + ty) * size + oz;
+                        voxels[start..start + tile_size].copy_from_slice(tile_row);
+                    }
+                }
+            }
+        }
+        Ok(voxels)
+    }
+
+    /// Returns the origin of the window centered on `position` horizontally and starting at
+    /// the bottom of the map, as far as the map allows.
+    pub fn window_around(&self, position: [f32; 3]) -> [u32; 3] {
+        [0, 1, 2].map(|i| {
+            if i == 1 {
+                return 0;
+            }
+            let tile = ((position[i] - WORLD_SIZE as f32 / 2.0) / TILE_SIZE as f32).round();
+            (tile.max(0.0) as u32).min(self.last_window_tile(i)) * TILE_SIZE
+        })
+    }
+
+    /// Returns the tile the window starts at at most along `axis`, where it ends with the map.
+    fn last_window_tile(&self, axis: usize) -> u32 {
+        self.size[axis]
+            .div_ceil(TILE_SIZE)
+            .saturating_sub(WINDOW_TILES)
+    }
+}
+
+/// Writes voxels into the tiles of a map in any order, keeping only `MAX_CACHED_TILES` in memory,
+/// so importers can write maps far larger than memory.
+pub struct TileWriter {
+    map: TiledMap,
+    /// Tiles in memory, the most recently used last.
+    tiles: Vec<([u32; 3], Vec<u32>)>,
+}
+
+impl TileWriter {
+    pub fn new(map: TiledMap) -> TileWriter {
+        TileWriter {
+            map,
+            tiles: Vec::new(),
+        }
+    }
+
+    /// Sets the voxel at `position` in the map, ignoring positions outside of it.
+    pub fn set(&mut self, position: [u32; 3], voxel: u32) -> Result<(), String> {
+        if (0..3).any(|i| position[i] >= self.map.size[i]) {
+            return Ok(());
+        }
+        let tile = position.map(|c| c / TILE_SIZE);
+        let [x, y, z] = position.map(|c| c % TILE_SIZE);
+        let voxels = self.tile_mut(tile)?;
+        voxels[((x * TILE_SIZE + y) * TILE_SIZE + z) as usize] = voxel;
+        Ok(())
+    }
+
+    /// Returns the voxels of `tile`, reading it into memory if it isn't there, written before
+    /// when importing overlapping models.
+    fn tile_mut(&mut self, tile: [u32; 3]) -> Result<&mut Vec<u32>, String> {
+        let last = self.tiles.len().wrapping_sub(1);
+        if self.tiles.get(last).map(|(cached, _)| *cached) != Some(tile) {
+            match self.tiles.iter().position(|(cached, _)| *cached == tile) {
+                Some(index) => {
+                    let entry = self.tiles.remove(index);
+                    self.tiles.push(entry);
+                }
+                None => {
+                    if self.tiles.len() >= MAX_CACHED_TILES {
+                        let (evicted, voxels) = self.tiles.remove(0);
+                        self.map.write_tile(evicted, &voxels)?;
+                    }
+                    let voxels = self.map.read_tile(tile)?;
+                    self.tiles.push((tile, voxels));
+                }
+            }
+        }
+        Ok(&mut self.tiles.last_mut().unwrap().1)
+    }
+
+    /// Writes the tiles still in memory and returns the map.
+    pub fn finish(self) -> Result<TiledMap, String> {
+        for (tile, voxels) in &self.tiles {
+            self.map.write_tile(*tile, voxels)?;
+        }
+        Ok(self.map)
+    }
+}
+
+/// Window of a map read by `MapStream`, to replace the world with.
+pub struct MapMove {
+    /// How far the world's origin moved in the map, the camera has to move back as far.
+    pub moved: [i32; 3],
+    /// Voxels of the world, indexed like the world's.
+    pub voxels: Vec<u32>,
+}
+
+/// Keeps the world on the part of a `TiledMap` around the camera, moving it a tile at a time
+/// as the camera nears its edge. Windows are read on a worker thread. The map is only read,
+/// edits are lost when the world moves on.
+pub struct MapStream {
+    map: TiledMap,
+    /// Position in the map of the world's origin.
+    origin: [u32; 3],
+    /// Reads the window starting at the origin it returns along with the voxels.
+    worker: Option<JoinHandle<Result<MapWindow, String>>>,
+}
+
+impl MapStream {
+    /// Streams `map`, the world currently holding the window starting at `origin`.
+    pub fn new(map: TiledMap, origin: [u32; 3]) -> MapStream {
+        MapStream {
+            map,
+            origin,
+            worker: None,
+        }
+    }
+
+    pub fn origin(&self) -> [u32; 3] {
+        self.origin
+    }
+
+    /// Starts reading the next window when `eye`, in world coordinates, is within
+    /// `STREAM_MARGIN` of an edge of the world the map goes on beyond.
+    pub fn update(&mut self, eye: [f32; 3]) {
+        if self.worker.is_some() {
+            return;
+        }
+        let origin = [0, 1, 2].map(|i| {
+            let tile = self.origin[i] / TILE_SIZE;
+            if eye[i] < STREAM_MARGIN {
+                tile.saturating_sub(1) * TILE_SIZE
+            } else if eye[i] > WORLD_SIZE as f32 - STREAM_MARGIN {
+                (tile + 1).min(self.map.last_window_tile(i)) * TILE_SIZE
+            } else {
+                self.origin[i]
+            }
+        });
+        if origin == self.origin {
+            return;
+        }
+        let map = self.map.clone();
+        self.worker = Some(thread::spawn(move || {
+            Ok(MapWindow {
+                origin,
+                voxels: map.window(origin)?,
+            })
+        }));
+    }
+
+    /// Returns the window read since the last call, once it is.
+    pub fn poll(&mut self) -> Option<Result<MapMove, String>> {
+        if !self.worker.as_ref()?.is_finished() {
+            return None;
+        }
+        let worker = self.worker.take()?;
+        let result = worker
+            .join()
+            .unwrap_or_else(|_| Err("map streaming worker panicked".to_string()));
+        Some(result.map(|window| {
+            let moved = [0, 1, 2].map(|i| window.origin[i] as i32 - self.origin[i] as i32);
+            self.origin = window.origin;
+            MapMove {
+                moved,
+                voxels: window.voxels,
+            }
+        }))
+    }
+}
+
+/// Window of a map read on `MapStream`'s worker.
+struct MapWindow {
+    origin: [u32; 3],
+    voxels: Vec<u32>,
+}
+
+/// Returns `voxels` as a tile file, the header followed by the runs of `world_file::write_runs`.
+fn tile_bytes(voxels: &[u32]) -> Vec<u8> {
+    let mut bytes = TILE_MAGIC.to_vec();
+    bytes.extend_from_slice(&TILE_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&TILE_SIZE.to_le_bytes());
+    world_file::write_runs(&mut bytes, voxels);
+    bytes
+}
+
+fn parse_tile(bytes: &[u8]) -> Result<Vec<u32>, String> {
+    let words = world_file::read_words(bytes)?;
+    let [magic, version, size, runs @ ..] = &words[..] else {
+        return Err("unexpected end of file".to_string());
+    };
+    if magic.to_le_bytes() != *TILE_MAGIC {
+        return Err("not a tile".to_string());
+    }
+    if *version != TILE_VERSION {
+        return Err(format!("unsupported version {version}"));
+    }
+    if *size != TILE_SIZE {
+        return Err(format!("tile is {size} voxels wide instead of {TILE_SIZE}"));
+    }
+    world_file::read_runs(runs, TILE_VOXELS)
+}
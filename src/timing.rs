@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, PrimaryAutoCommandBuffer},
+    device::Queue,
+    query::{QueryPool, QueryPoolCreateInfo, QueryResultFlags, QueryType},
+    sync::PipelineStage,
+};
+
+/// Number of frames the GPU gets to finish before their timestamps are overwritten.
+const TIMER_RING_SIZE: usize = 3;
+/// Most timestamps written in one frame.
+const MAX_MARKS: u32 = 16;
+
+/// Ring of timestamp query ranges measuring how long the GPU spends on each pass of a frame.
+/// Like picks, results are read back a frame or more later without waiting on the GPU.
+pub struct GpuTimer {
+    /// `None` when the queue can't write timestamps.
+    pool: Option<Arc<QueryPool>>,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Names of the passes ended by each mark after the first, per slot.
+    passes: [Vec<&'static str>; TIMER_RING_SIZE],
+    next: usize,
+    /// Slot marks are currently written to.
+    current: usize,
+    /// Duration in milliseconds of every pass of the latest frame read back.
+    latest: Vec<(&'static str, f32)>,
+}
+
+impl GpuTimer {
+    pub fn new(queue: &Arc<Queue>) -> Self {
+        let physical_device = queue.device().physical_device();
+        let supported = physical_device.queue_family_properties()
+            [queue.queue_family_index() as usize]
+            .timestamp_valid_bits
+            .is_some();
+        let pool = supported.then(|| {
+            QueryPool::new(
+                queue.device().clone(),
+                QueryPoolCreateInfo {
+                    query_count: MAX_MARKS * TIMER_RING_SIZE as u32,
+                    ..QueryPoolCreateInfo::query_type(QueryType::Timestamp)
+                },
+            )
+            .unwrap()
+        });
+        GpuTimer {
+            pool,
+            period: physical_device.properties().timestamp_period,
+            passes: Default::default(),
+            next: 0,
+            current: 0,
+            latest: Vec::new(),
+        }
+    }
+
+    /// Reads back the oldest frame if the GPU finished it, then resets its queries for the
+    /// coming frame and writes the timestamp its first pass starts at.
+    pub fn begin_frame(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+    ) {
+        let Some(pool) = self.pool.clone() else {
+            return;
+        };
+        let slot = self.next;
+        self.next = (self.next + 1) % TIMER_RING_SIZE;
+        self.current = slot;
+        let first = slot as u32 * MAX_MARKS;
+        let passes = std::mem::take(&mut self.passes[slot]);
+        if !passes.is_empty() {
+            let mut timestamps = vec![0u64; passes.len() + 1];
+            let range = first..first + timestamps.len() as u32;
+            // Fails without blocking while the GPU still works on the frame, which is dropped.
+            if let Ok(true) = pool
+                .queries_range(range)
+                .unwrap()
+                .get_results(&mut timestamps, QueryResultFlags::empty())
+            {
+                self.latest = passes
+                    .into_iter()
+                    .zip(timestamps.windows(2))
+                    .map(|(name, pair)| {
+                        let ticks = pair[1].saturating_sub(pair[0]);
+                        (name, ticks as f32 * self.period / 1e6)
+                    })
+                    .collect();
+            }
+        }
+        unsafe {
+            builder
+                .reset_query_pool(pool.clone(), first..first + MAX_MARKS)
+                .unwrap()
+                .write_timestamp(pool, first, PipelineStage::BottomOfPipe)
+                .unwrap();
+        }
+    }
+
+    /// Writes a timestamp ending the pass `name` once every command recorded so far finished.
+    pub fn mark(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        name: &'static str,
+    ) {
+        let Some(pool) = self.pool.clone() else {
+            return;
+        };
+        let passes = &mut self.passes[self.current];
+        if passes.len() as u32 + 1 >= MAX_MARKS {
+            return;
+        }
+        passes.push(name);
+        let query = self.current as u32 * MAX_MARKS + passes.len() as u32;
+        unsafe {
+            builder
+                .write_timestamp(pool, query, PipelineStage::BottomOfPipe)
+                .unwrap();
+        }
+    }
+
+    /// Returns the duration in milliseconds of every pass of the latest frame the GPU finished,
+    /// in the order they ran.
+    pub fn latest(&self) -> &[(&'static str, f32)] {
+        &self.latest
+    }
+}
@@ -0,0 +1,459 @@
+use crate::gpu;
+use rvengine::{
+    anvil::{self, BlockMapping, ChunkArea},
+    camera::{self, world_to_camera, Camera, Lens, CAMERA_DIR},
+    dataset::{self, DatasetFrame, PoseSampler},
+    inspect::Report,
+    light_bake::{BakeSettings, LightBake, BAKE_EXTENSION},
+    scene::SceneFile,
+    screenshot::{self, ExrValues, HdrScreenshot, Screenshot, ShotMetadata},
+    tiled_map::{TileWriter, TiledMap},
+    tracer::{self, Outputs},
+    vox_scene::VoxScene,
+    world::World,
+    world_file,
+};
+use std::{fs, path::Path, time::Instant};
+
+pub const USAGE: &str = "usage: rvengine convert <input.vox|input.rvox> <output.vox|output.rvox>\n\
+                         usage: rvengine inspect <world.vox|world.rvox>\n\
+                         usage: rvengine bake <scene.rvscene> [samples]\n\
+                         usage: rvengine render <scene.rvscene> <output.ppm|output.exr|output.png> [width height] [--sky-alpha] [--aovs]\n\
+                         usage: rvengine dataset <scene.rvscene> <output directory> <count> [width height] [--seed <number>] [--clearance <voxels>]\n\
+                         usage: rvengine import <scene.vox> <map directory>\n\
+                         usage: rvengine import-mca <save directory> <chunk x0> <chunk z0> <chunk x1> <chunk z1> <map directory> [mapping.txt]\n\
+                         usage: rvengine info";
+
+/// Runs the headless subcommand named by the first argument, without opening a window. Returns
+/// `None` when the arguments don't start with a subcommand.
+pub fn run(args: &[String]) -> Option<Result<(), String>> {
+    let (name, args) = args.split_first()?;
+    match name.as_str() {
+        "convert" => Some(match args {
+            [input, output] => convert(input, output),
+            _ => Err(USAGE.to_string()),
+        }),
+        "inspect" => Some(match args {
+            [path] => inspect(path),
+            _ => Err(USAGE.to_string()),
+        }),
+        "bake" => Some(match args {
+            [scene] => bake(scene, None),
+            [scene, samples] => samples
+                .parse()
+                .ok()
+                .filter(|&samples| samples > 0)
+                .ok_or(format!("invalid sample count `{samples}`"))
+                .and_then(|samples| bake(scene, Some(samples))),
+            _ => Err(USAGE.to_string()),
+        }),
+        "render" => {
+            let mut options = RenderOptions::default();
+            let mut positional = Vec::new();
+            for arg in args {
+                match arg.as_str() {
+                    "--sky-alpha" => options.sky_alpha = true,
+                    "--aovs" => options.aovs = true,
+                    arg => positional.push(arg),
+                }
+            }
+            Some(match positional[..] {
+                [scene, output] => render(scene, output, RENDER_EXTENT, options),
+                [scene, output, width, height] => parse_extent(width, height)
+                    .and_then(|extent| render(scene, output, extent, options)),
+                _ => Err(USAGE.to_string()),
+            })
+        }
+        "dataset" => {
+            let mut seed = dataset::DEFAULT_SEED;
+            let mut clearance = dataset::DEFAULT_CLEARANCE;
+            let mut positional = Vec::new();
+            let mut args = args.iter();
+            while let Some(arg) = args.next() {
+                match arg.as_str() {
+                    "--seed" => match args.next().map(|value| value.parse()) {
+                        Some(Ok(value)) => seed = value,
+                        _ => return Some(Err("--seed needs a number".to_string())),
+                    },
+                    "--clearance" => match args.next().map(|value| value.parse()) {
+                        Some(Ok(value)) => clearance = value,
+                        _ => return Some(Err("--clearance needs a number".to_string())),
+                    },
+                    arg => positional.push(arg),
+                }
+            }
+            let sampler = PoseSampler::new(seed, clearance);
+            let count = |count: &str| {
+                count
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|&count| count > 0)
+                    .ok_or(format!("invalid frame count `{count}`"))
+            };
+            Some(match positional[..] {
+                [scene, output, frames] => count(frames)
+                    .and_then(|frames| dataset(scene, output, frames, RENDER_EXTENT, sampler)),
+                [scene, output, frames, width, height] => count(frames).and_then(|frames| {
+                    let extent = parse_extent(width, height)?;
+                    dataset(scene, output, frames, extent, sampler)
+                }),
+                _ => Err(USAGE.to_string()),
+            })
+        }
+        "import" => Some(match args {
+            [input, map] => import(input, map),
+            _ => Err(USAGE.to_string()),
+        }),
+        "import-mca" => Some(match args {
+            [save, x0, z0, x1, z1, map] => import_mca(save, [x0, z0, x1, z1], map, None),
+            [save, x0, z0, x1, z1, map, mapping] => {
+                import_mca(save, [x0, z0, x1, z1], map, Some(mapping))
+            }
+            _ => Err(USAGE.to_string()),
+        }),
+        "info" => Some(match args {
+            [] => gpu::print_info(),
+            _ => Err(USAGE.to_string()),
+        }),
+        _ => None,
+    }
+}
+
+/// Reads the world file `input` and writes it to `output`, each in the format its extension
+/// names, see `world_file::FORMATS`.
+fn convert(input: &str, output: &str) -> Result<(), String> {
+    let start = Instant::now();
+    let mut world = World::new();
+    world_file::load(&mut world, input)?;
+    world_file::save(&world, output)?;
+    println!(
+        "converted {input} to {output} ({} solid voxels) in {:.2}s",
+        world.solid_voxels(),
+        start.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+/// Prints what the world file at `path` holds. Fails when it can't be read or holds voxels of
+/// types without a material.
+fn inspect(path: &str) -> Result<(), String> {
+    let mut world = World::new();
+    world_file::load(&mut world, path)?;
+    let report = Report::new(&world);
+    print!("{report}");
+    let invalid: usize = report.invalid_types().map(|(_, count)| count).sum();
+    if invalid > 0 {
+        return Err(format!("{path} holds {invalid} voxels of invalid types"));
+    }
+    Ok(())
+}
+
+/// Imports every model of the MagicaVoxel scene `input` into a new tiled map in the directory
+/// `map`, explored with `--map`. Maps can be far larger than the world and than memory.
+fn import(input: &str, map: &str) -> Result<(), String> {
+    let start = Instant::now();
+    let bytes = fs::read(input).map_err(|err| format!("can't read `{input}`: {err}"))?;
+    let scene = VoxScene::parse(&bytes).map_err(|err| format!("invalid scene `{input}`: {err}"))?;
+    let (min, max) = scene.bounds();
+    let size = [0, 1, 2].map(|i| (max[i] - min[i] + 1) as u32);
+    let mut writer = TileWriter::new(TiledMap::create(map, size)?);
+    let mut voxels = 0u64;
+    let mut result = Ok(());
+    scene.for_each_voxel(|position, voxel| {
+        if result.is_ok() {
+            result = writer.set([0, 1, 2].map(|i| (position[i] - min[i]) as u32), voxel);
+            voxels += 1;
+        }
+    });
+    result?;
+    writer.finish()?;
+    let [x, y, z] = size;
+    println!(
+        "imported {} models, {voxels} voxels, of {input} into the {x}x{y}x{z} map {map} in {:.2}s",
+        scene.instance_count(),
+        start.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+/// Imports the chunks from `chunks[0..2]` to `chunks[2..4]` of the Minecraft save `save` into a
+/// new tiled map in the directory `map`, explored with `--map`. Blocks get their voxel types from
+/// the file `mapping`, `anvil::DEFAULT_MAPPING` without one.
+fn import_mca(
+    save: &str,
+    chunks: [&String; 4],
+    map: &str,
+    mapping: Option<&String>,
+) -> Result<(), String> {
+    let start = Instant::now();
+    let [x0, z0, x1, z1] = chunks.map(|chunk| {
+        chunk
+            .parse::<i32>()
+            .map_err(|err| format!("invalid chunk `{chunk}`: {err}"))
+    });
+    let area = ChunkArea::new([x0?, z0?], [x1?, z1?]);
+    let mapping = match mapping {
+        Some(path) => BlockMapping::load(path)?,
+        None => BlockMapping::default(),
+    };
+    let size = area.map_size()?;
+    let mut writer = TileWriter::new(TiledMap::create(map, size)?);
+    let import = anvil::import(
+        anvil::region_directory(save),
+        area,
+        &mapping,
+        |position, voxel| writer.set(position, voxel),
+    )?;
+    writer.finish()?;
+    for skipped in &import.skipped {
+        println!("skipped {skipped}");
+    }
+    if !import.unmapped.is_empty() {
+        let mut unmapped: Vec<_> = import.unmapped.iter().collect();
+        unmapped.sort_by_key(|(_, &count)| std::cmp::Reverse(count));
+        let listed: Vec<String> = unmapped
+            .iter()
+            .take(10)
+            .map(|(block, count)| format!("{block} ({count})"))
+            .collect();
+        println!(
+            "left out blocks of {} unmapped types, add them to the mapping: {}",
+            unmapped.len(),
+            listed.join(", ")
+        );
+    }
+    let [x, y, z] = size;
+    println!(
+        "imported {} chunks ({} never generated), {} voxels, of {save} into the {x}x{y}x{z} map {map} in {:.2}s",
+        import.chunks,
+        import.missing,
+        import.voxels,
+        start.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+/// Width and height `render` renders at unless given others.
+const RENDER_EXTENT: [u32; 2] = [1280, 720];
+/// Distance `render` traces primary rays to.
+const RENDER_DISTANCE: f32 = 512.0;
+
+/// Parses the width and height of a render.
+fn parse_extent(width: &str, height: &str) -> Result<[u32; 2], String> {
+    let parse = |value: &str| {
+        value
+            .parse::<u32>()
+            .ok()
+            .filter(|&value| value > 0)
+            .ok_or(format!("invalid image size `{value}`"))
+    };
+    Ok([parse(width)?, parse(height)?])
+}
+
+/// What `render` writes besides the image.
+#[derive(Clone, Copy, Debug, Default)]
+struct RenderOptions {
+    /// Leaves the sky transparent, which only the HDR formats can hold.
+    sky_alpha: bool,
+    /// Writes the depth, normal and material passes next to the image, see `render_image`.
+    aovs: bool,
+}
+
+/// Names of the render passes `render_image` writes with `RenderOptions::aovs`, also the
+/// extensions of their files before `.exr`.
+const PASSES: [&str; 3] = ["depth", "normal", "material"];
+
+/// Renders the scene at `path` with the CPU tracer from its first bookmark into `output`, see
+/// `render_image`.
+fn render(
+    path: &str,
+    output: &str,
+    extent: [u32; 2],
+    options: RenderOptions,
+) -> Result<(), String> {
+    let start = Instant::now();
+    let scene = SceneFile::load(path)?;
+    let mut world = World::new();
+    scene.load_world(&mut world)?;
+    let bookmark = scene
+        .bookmarks
+        .first()
+        .ok_or(format!("{path} has no bookmark to render from"))?;
+    let camera = Camera {
+        position: world_to_camera(bookmark.eye, bookmark.rotation),
+        rotation: bookmark.rotation,
+    };
+    render_image(&world, &scene, &camera, extent, Path::new(output), options)?;
+    let [width, height] = extent;
+    println!(
+        "rendered {path} from bookmark {} into the {width}x{height} image {output} in {:.2}s",
+        bookmark.name,
+        start.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+/// Renders `world` lit by `scene` as seen by `camera` with the CPU tracer into `output`, an
+/// 8-bit PPM image or, for the extensions of `screenshot::HDR_EXTENSIONS`, an OpenEXR or 16-bit
+/// PNG one keeping the colors unclamped, see `HdrScreenshot`. With `options.aovs` the passes of
+/// `tracer::Outputs` are written to OpenEXR files named after `output`, see `PASSES`: the
+/// distance to the face hit in `Z` of `.depth.exr`, infinite for sky, its normal in `R`, `G`
+/// and `B` of `.normal.exr` and its voxel type as an integer in `id` of `.material.exr`, 0 for
+/// sky.
+fn render_image(
+    world: &World,
+    scene: &SceneFile,
+    camera: &Camera,
+    extent: [u32; 2],
+    output: &Path,
+    options: RenderOptions,
+) -> Result<(), String> {
+    let hdr = screenshot::is_hdr_path(output);
+    if options.sky_alpha && !hdr {
+        return Err(format!(
+            "can't leave the sky transparent in `{}`, expected a .exr or .png file",
+            output.display()
+        ));
+    }
+    let metadata = ShotMetadata {
+        seed: None,
+        eye: camera.eye(),
+        rotation: camera.rotation,
+        fov: camera::fov(CAMERA_DIR),
+        lens: Lens::default(),
+        time_of_day: scene.time_of_day,
+    };
+    let pixels = extent[0] as usize * extent[1] as usize;
+    let mut rgba = vec![0; pixels * 4];
+    let mut color = hdr.then(|| vec![0.0; pixels * 3]);
+    let mut depth = (options.sky_alpha || options.aovs).then(|| vec![0.0; pixels]);
+    let mut normal = options.aovs.then(|| vec![0.0; pixels * 3]);
+    let mut material = options.aovs.then(|| vec![0; pixels]);
+    let outputs = Outputs {
+        color: color.as_deref_mut(),
+        depth: depth.as_deref_mut(),
+        normal: normal.as_deref_mut(),
+        material: material.as_deref_mut(),
+        ..Outputs::default()
+    };
+    tracer::render_with_outputs(
+        world,
+        &scene.palette,
+        camera,
+        camera,
+        extent,
+        RENDER_DISTANCE,
+        &mut rgba,
+        outputs,
+    );
+    if let Some(color) = &color {
+        let mut screenshot = HdrScreenshot::from_rgb(extent, color);
+        if let (true, Some(depth)) = (options.sky_alpha, &depth) {
+            // Sky is infinitely far away.
+            let coverage: Vec<f32> = depth
+                .iter()
+                .map(|depth| if depth.is_finite() { 1.0 } else { 0.0 })
+                .collect();
+            screenshot.mask_sky(&coverage)?;
+        }
+        screenshot.save(output, &metadata)?;
+    } else {
+        Screenshot::from_rgba(extent, &rgba).save(output, &metadata)?;
+    }
+    if let (Some(depth), Some(normal), Some(material)) = (&depth, &normal, &material) {
+        let normal =
+            |axis: usize| -> Vec<f32> { normal.iter().skip(axis).step_by(3).copied().collect() };
+        let [x, y, z] = [0, 1, 2].map(normal);
+        let passes: [Vec<(&str, ExrValues)>; 3] = [
+            vec![("Z", ExrValues::Float(depth))],
+            vec![
+                ("R", ExrValues::Float(&x)),
+                ("G", ExrValues::Float(&y)),
+                ("B", ExrValues::Float(&z)),
+            ],
+            vec![("id", ExrValues::Uint(material))],
+        ];
+        for (name, channels) in PASSES.into_iter().zip(passes) {
+            let path = output.with_extension(format!("{name}.exr"));
+            screenshot::save_exr(&path, extent, &channels, &metadata)?;
+        }
+    }
+    Ok(())
+}
+
+/// Extension of the color images `dataset` writes.
+const DATASET_IMAGE_EXTENSION: &str = "png";
+
+/// Renders the scene at `path` from `count` camera poses picked by `sampler` into the directory
+/// `output`: the colors, the render passes of `render_image` and `cameras.json` holding the
+/// intrinsics and the pose of every frame, see `dataset::cameras_json`, as synthetic training
+/// data.
+fn dataset(
+    path: &str,
+    output: &str,
+    count: u32,
+    extent: [u32; 2],
+    mut sampler: PoseSampler,
+) -> Result<(), String> {
+    let start = Instant::now();
+    let scene = SceneFile::load(path)?;
+    let mut world = World::new();
+    scene.load_world(&mut world)?;
+    let output = Path::new(output);
+    fs::create_dir_all(output)
+        .map_err(|err| format!("can't create directory `{}`: {err}", output.display()))?;
+    let options = RenderOptions {
+        sky_alpha: false,
+        aovs: true,
+    };
+    let mut frames = Vec::new();
+    for index in 0..count {
+        let frame = DatasetFrame {
+            name: format!("frame_{index:05}"),
+            camera: sampler.sample(&world)?,
+        };
+        let image = output.join(format!("{}.{DATASET_IMAGE_EXTENSION}", frame.name));
+        render_image(&world, &scene, &frame.camera, extent, &image, options)?;
+        frames.push(frame);
+    }
+    let json = dataset::cameras_json(extent, DATASET_IMAGE_EXTENSION, &PASSES, &frames);
+    let cameras = output.join("cameras.json");
+    fs::write(&cameras, json)
+        .map_err(|err| format!("can't write `{}`: {err}", cameras.display()))?;
+    let [width, height] = extent;
+    println!(
+        "rendered {count} {width}x{height} frames of {path} into {} in {:.2}s",
+        output.display(),
+        start.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
+
+/// Bakes the lighting of the scene at `path` with `samples` paths per face, writes the bake next
+/// to the scene and adds it to the scene file, so opening the scene lights it with the bake.
+fn bake(path: &str, samples: Option<u32>) -> Result<(), String> {
+    let start = Instant::now();
+    let path = Path::new(path);
+    let mut scene = SceneFile::load(path)?;
+    let mut world = World::new();
+    scene.load_world(&mut world)?;
+    let defaults = BakeSettings::default();
+    let settings = BakeSettings {
+        samples: samples.unwrap_or(defaults.samples),
+        time_of_day: scene.time_of_day,
+        ..defaults
+    };
+    let bake = LightBake::bake(&world, &scene.palette, &settings);
+    let bake_path = path.with_extension(BAKE_EXTENSION);
+    bake.save(&bake_path)?;
+    scene.light_bake = Some(bake_path.clone());
+    scene.save(path)?;
+    println!(
+        "baked {} faces of {} into {} in {:.2}s",
+        bake.faces(),
+        path.display(),
+        bake_path.display(),
+        start.elapsed().as_secs_f32()
+    );
+    Ok(())
+}
@@ -0,0 +1,355 @@
+use crate::fractal_compute_pipeline::TILE_SIZE;
+use crate::permutations::Permutations;
+use crate::settings::{FLAG_AMBIENT_OCCLUSION, FLAG_GLOBAL_ILLUMINATION, FLAG_SHADOWS};
+use crate::shader_build;
+use rvengine::{chunk_palette::MAX_PAGES, gpu_chunks::ChunkBinding};
+use std::{path::Path, sync::Arc};
+use vulkano::{
+    device::{Device, Queue},
+    pipeline::{ComputePipeline, Pipeline},
+    shader::{ShaderCreationError, ShaderModule},
+};
+
+/// Values of the compute shader's `TILE_CLASS` specialization constant. The bins are laid out in
+/// this order in the tile bin buffer, starting with `TILE_CLASS_COMPLEX`.
+pub const TILE_CLASS_ALL: u32 = 0;
+pub const TILE_CLASS_COMPLEX: u32 = 1;
+pub const TILE_CLASS_SIMPLE: u32 = 2;
+pub const TILE_CLASS_SKY: u32 = 3;
+pub const TILE_CLASS_CLASSIFY: u32 = 4;
+pub const TILE_BINS: u32 = 3;
+
+/// Lighting terms the trace shader is compiled with, each with the setting flag enabling it. See
+/// `LIGHTING_VARIANT` in `compute.glsl`.
+const LIGHTING_DEFINES: [(u32, &str); 3] = [
+    (FLAG_AMBIENT_OCCLUSION, "AMBIENT_OCCLUSION"),
+    (FLAG_SHADOWS, "SHADOWS"),
+    (FLAG_GLOBAL_ILLUMINATION, "GLOBAL_ILLUMINATION"),
+];
+pub const ALL_LIGHTING: u32 = FLAG_AMBIENT_OCCLUSION | FLAG_SHADOWS | FLAG_GLOBAL_ILLUMINATION;
+
+/// What a set of tracing pipelines is compiled for.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceVariant {
+    /// Flags of the lighting terms compiled in, see `LIGHTING_DEFINES`.
+    pub lighting: u32,
+    /// Whether the accumulation target holds half floats, see `HALF_ACCUMULATION` in
+    /// `compute.glsl`.
+    pub half_accumulation: bool,
+    /// Workgroup size of the trace shader, which screen tiles match.
+    pub group_size: [u32; 2],
+}
+
+impl TraceVariant {
+    /// The variant built into the binary.
+    pub const BUILTIN: TraceVariant = TraceVariant {
+        lighting: ALL_LIGHTING,
+        half_accumulation: false,
+        group_size: [TILE_SIZE; 2],
+    };
+}
+
+/// The pipelines tracing a frame, all of which bind the accumulation target.
+#[derive(Clone)]
+pub struct TracePipelines {
+    /// What the pipelines are built for, which the trace targets have to match.
+    pub variant: TraceVariant,
+    pub all: Arc<ComputePipeline>,
+    /// Specialized per tile bin, indexed by `TILE_CLASS_* - 1`.
+    pub tiles: [Arc<ComputePipeline>; TILE_BINS as usize],
+    pub classify: Arc<ComputePipeline>,
+    pub adaptive: Arc<ComputePipeline>,
+}
+
+/// Every set of tracing pipelines the settings asked for, reading the world the way the device
+/// supports, see `ChunkBinding`.
+pub struct TraceVariants {
+    binding: ChunkBinding,
+    /// Tracing pipelines built into the binary, with every lighting term and 32-bit
+    /// accumulation, whose layouts all variants share.
+    builtin: TracePipelines,
+    /// Tracing pipelines by what they are compiled for. Other variants than the built-in one are
+    /// compiled the first time the settings ask for them.
+    built: Permutations<TraceVariant, TracePipelines>,
+    /// Tracing pipelines of a user supplied kernel, used instead of the built-in ones.
+    custom: Option<TracePipelines>,
+}
+
+impl TraceVariants {
+    /// Creates the built-in pipelines on `queue` reading the world the way `binding` says.
+    pub fn new(queue: &Arc<Queue>, binding: ChunkBinding) -> TraceVariants {
+        let builtin = TracePipelines {
+            variant: TraceVariant::BUILTIN,
+            all: trace_pipeline(queue, binding, TILE_CLASS_ALL),
+            tiles: [TILE_CLASS_COMPLEX, TILE_CLASS_SIMPLE, TILE_CLASS_SKY]
+                .map(|tile_class| trace_pipeline(queue, binding, tile_class)),
+            classify: trace_pipeline(queue, binding, TILE_CLASS_CLASSIFY),
+            adaptive: {
+                let shader = adaptive_cs::load(queue.device().clone()).unwrap();
+                ComputePipeline::new(
+                    queue.device().clone(),
+                    shader.entry_point("main").unwrap(),
+                    &(),
+                    None,
+                    |_| {},
+                )
+                .unwrap()
+            },
+        };
+        TraceVariants {
+            binding,
+            built: Permutations::new(TraceVariant::BUILTIN, builtin.clone()),
+            builtin,
+            custom: None,
+        }
+    }
+
+    /// Returns the built-in pipelines, whose layouts every variant shares.
+    pub fn builtin(&self) -> &TracePipelines {
+        &self.builtin
+    }
+
+    /// Returns whether a user supplied kernel replaces the built-in pipelines.
+    pub fn has_custom(&self) -> bool {
+        self.custom.is_some()
+    }
+
+    /// Returns the tracing pipelines built for `variant`, building them the first time. Falls
+    /// back to the built-in shader when compiling fails, then to its default workgroup size. A
+    /// custom kernel replaces them all.
+    pub fn get(&mut self, device: &Arc<Device>, variant: TraceVariant) -> TracePipelines {
+        if let Some(pipelines) = &self.custom {
+            return pipelines.clone();
+        }
+        // Without compiling GLSL at runtime only the specialization of the built-in shader
+        // changes.
+        let variant = if cfg!(feature = "runtime-shaders") {
+            variant
+        } else {
+            TraceVariant {
+                lighting: ALL_LIGHTING,
+                half_accumulation: false,
+                ..variant
+            }
+        };
+        let (builtin, binding) = (&self.builtin, self.binding);
+        let pipelines = self
+            .built
+            .get(variant, |variant| {
+                build_trace_variant(device, builtin, binding, variant)
+            })
+            .cloned();
+        match pipelines {
+            Some(pipelines) => pipelines,
+            None if variant.lighting != ALL_LIGHTING || variant.half_accumulation => self.get(
+                device,
+                TraceVariant {
+                    lighting: ALL_LIGHTING,
+                    half_accumulation: false,
+                    ..variant
+                },
+            ),
+            // Always built.
+            None => self.builtin.clone(),
+        }
+    }
+
+    /// Traces with the compute shader at `path` instead of the built-in one, or goes back to the
+    /// built-in one with `None`, see `Controller::load_trace_shader`. Keeps the previous shader
+    /// when loading fails.
+    pub fn load_custom(&mut self, device: &Arc<Device>, path: Option<&Path>) -> Result<(), String> {
+        self.custom = match path {
+            Some(path) => {
+                let shader = shader_build::load(device, path, chunk_defines(self.binding))?;
+                Some(
+                    trace_pipelines_from(device, &self.builtin, &shader, TraceVariant::BUILTIN)
+                        .map_err(|err| {
+                            format!("trace shader `{}` doesn't fit: {err}", path.display())
+                        })?,
+                )
+            }
+            None => None,
+        };
+        Ok(())
+    }
+}
+
+/// Builds the tracing pipelines of `variant` reading the world the way `binding` says, with the
+/// layouts of the `builtin` ones.
+fn build_trace_variant(
+    device: &Arc<Device>,
+    builtin: &TracePipelines,
+    binding: ChunkBinding,
+    variant: TraceVariant,
+) -> Result<TracePipelines, String> {
+    if variant.lighting == ALL_LIGHTING && !variant.half_accumulation {
+        // Only specialized differently from the built-in pipelines, nothing to compile.
+        let shader = builtin_trace_shader(device, binding).map_err(|err| err.to_string())?;
+        return trace_pipelines_from(device, builtin, &shader, variant);
+    }
+    let mut defines = vec!["LIGHTING_VARIANT"];
+    defines.extend(chunk_defines(binding));
+    for (flag, define) in LIGHTING_DEFINES {
+        if variant.lighting & flag != 0 {
+            defines.push(define);
+        }
+    }
+    if variant.half_accumulation {
+        defines.push("HALF_ACCUMULATION");
+    }
+    let shader = shader_build::compile(device, "compute.glsl", &defines)?;
+    let mut pipelines = trace_pipelines_from(device, builtin, &shader, variant)
+        .map_err(|err| format!("invalid trace shader variant {defines:?}: {err}"))?;
+    if variant.half_accumulation {
+        let shader = shader_build::compile(device, "adaptive.glsl", &["HALF_ACCUMULATION"])?;
+        pipelines.adaptive = ComputePipeline::with_pipeline_layout(
+            device.clone(),
+            shader.entry_point("main").ok_or("no `main` function")?,
+            &(),
+            builtin.adaptive.layout().clone(),
+            None,
+        )
+        .map_err(|err| format!("invalid half float adaptive sampling shader: {err}"))?;
+    }
+    Ok(pipelines)
+}
+
+/// Creates the tracing pipelines of `variant` from `shader`, which has to fit the layout of the
+/// built-in trace shader. Adaptive sampling keeps the built-in shader.
+fn trace_pipelines_from(
+    device: &Arc<Device>,
+    builtin: &TracePipelines,
+    shader: &ShaderModule,
+    variant: TraceVariant,
+) -> Result<TracePipelines, String> {
+    let pipeline = |tile_class| {
+        ComputePipeline::with_pipeline_layout(
+            device.clone(),
+            shader.entry_point("main").ok_or("no `main` function")?,
+            &trace_constants(tile_class, variant.group_size),
+            builtin.all.layout().clone(),
+            None,
+        )
+        .map_err(|err| err.to_string())
+    };
+    Ok(TracePipelines {
+        variant,
+        all: pipeline(TILE_CLASS_ALL)?,
+        tiles: [
+            pipeline(TILE_CLASS_COMPLEX)?,
+            pipeline(TILE_CLASS_SIMPLE)?,
+            pipeline(TILE_CLASS_SKY)?,
+        ],
+        classify: pipeline(TILE_CLASS_CLASSIFY)?,
+        adaptive: builtin.adaptive.clone(),
+    })
+}
+
+/// Returns how the trace shader reads the world on `device`, the first it was created with the
+/// features for, see `gpu::optional_features`: pages through their device addresses, pages
+/// bound as a descriptor array, otherwise a single buffer.
+pub fn chunk_binding(device: &Device) -> ChunkBinding {
+    let features = device.enabled_features();
+    if features.buffer_device_address {
+        ChunkBinding::DeviceAddresses
+    } else if features.runtime_descriptor_array
+        && features.shader_storage_buffer_array_non_uniform_indexing
+        && features.descriptor_binding_variable_descriptor_count
+    {
+        ChunkBinding::DescriptorArray
+    } else {
+        ChunkBinding::Buffer
+    }
+}
+
+/// Returns the defines compiling the trace shader at runtime for reading the world the way
+/// `binding` says.
+fn chunk_defines(binding: ChunkBinding) -> &'static [&'static str] {
+    match binding {
+        ChunkBinding::Buffer => &[],
+        ChunkBinding::DescriptorArray => &["CHUNK_PAGES"],
+        ChunkBinding::DeviceAddresses => &["CHUNK_ADDRESSES"],
+    }
+}
+
+/// Loads the trace shader built into the binary for reading the world the way `binding` says.
+fn builtin_trace_shader(
+    device: &Arc<Device>,
+    binding: ChunkBinding,
+) -> Result<Arc<ShaderModule>, ShaderCreationError> {
+    match binding {
+        ChunkBinding::Buffer => cs::load(device.clone()),
+        ChunkBinding::DescriptorArray => cs_pages::load(device.clone()),
+        ChunkBinding::DeviceAddresses => cs_addresses::load(device.clone()),
+    }
+}
+
+/// Creates the tracing pipeline reading the world the way `binding` says, with its
+/// `TILE_CLASS` specialization constant set to `tile_class`.
+fn trace_pipeline(
+    queue: &Arc<Queue>,
+    binding: ChunkBinding,
+    tile_class: u32,
+) -> Arc<ComputePipeline> {
+    let shader = builtin_trace_shader(queue.device(), binding).unwrap();
+    ComputePipeline::new(
+        queue.device().clone(),
+        shader.entry_point("main").unwrap(),
+        &trace_constants(tile_class, [TILE_SIZE; 2]),
+        None,
+        |set_layouts| {
+            // The runtime array of pages holds as many as the set is allocated with.
+            if binding == ChunkBinding::DescriptorArray {
+                let pages = set_layouts[1].bindings.get_mut(&0).unwrap();
+                pages.variable_descriptor_count = true;
+                pages.descriptor_count = MAX_PAGES;
+            }
+        },
+    )
+    .unwrap()
+}
+
+/// Returns the specialization constants of the trace shader for `tile_class` and workgroups of
+/// `group_size`.
+fn trace_constants(tile_class: u32, group_size: [u32; 2]) -> cs::SpecializationConstants {
+    // The workgroup size constants have no names in the shader.
+    cs::SpecializationConstants {
+        TILE_CLASS: tile_class,
+        constant_1: group_size[0],
+        constant_2: group_size[1],
+    }
+}
+
+pub mod cs {
+    vulkano_shaders::shader! {
+         ty: "compute",
+         path: "./assets/shader/compute.glsl"
+    }
+}
+
+/// The trace shader reading the world from pages, see `ChunkBinding::DescriptorArray`.
+mod cs_pages {
+    vulkano_shaders::shader! {
+         ty: "compute",
+         path: "./assets/shader/compute.glsl",
+         define: [("CHUNK_PAGES", "")]
+    }
+}
+
+/// The trace shader reading the world from pages through their device addresses, see
+/// `ChunkBinding::DeviceAddresses`.
+mod cs_addresses {
+    vulkano_shaders::shader! {
+         ty: "compute",
+         path: "./assets/shader/compute.glsl",
+         define: [("CHUNK_ADDRESSES", "")],
+         vulkan_version: "1.2"
+    }
+}
+
+pub mod adaptive_cs {
+    vulkano_shaders::shader! {
+         ty: "compute",
+         path: "./assets/shader/adaptive.glsl"
+    }
+}
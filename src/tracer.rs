@@ -0,0 +1,213 @@
+use crate::{
+    camera::{world_to_camera, Camera, CAMERA_DIR},
+    materials::Palette,
+    world::{Hit, World},
+};
+use std::thread;
+
+/// Color of rays which hit nothing, as in the compute shader.
+pub const SKY_COLOR: [f32; 3] = [0.1; 3];
+
+/// Traces the primary ray of `pixel` in an image of `extent` pixels and returns its color, lit
+/// like the compute shader lights it with every lighting term off, along with what it hit.
+pub fn trace_pixel(
+    world: &World,
+    palette: &Palette,
+    camera: &Camera,
+    pixel: [u32; 2],
+    extent: [u32; 2],
+    max_distance: f32,
+) -> ([f32; 3], Option<Hit>) {
+    let screen_pos = [0, 1].map(|i| (pixel[i] as f32 + 0.5) / extent[i] as f32 * 2.0 - 1.0);
+    let (origin, direction) = camera.ray(screen_pos, extent[0] as f32 / extent[1] as f32);
+    trace_ray(world, palette, origin, direction, max_distance)
+}
+
+/// Traces the ray from `origin` towards `direction` and returns its color, lit like `trace_pixel`
+/// lights it, along with what it hit.
+pub fn trace_ray(
+    world: &World,
+    palette: &Palette,
+    origin: [f32; 3],
+    direction: [f32; 3],
+    max_distance: f32,
+) -> ([f32; 3], Option<Hit>) {
+    let Some(hit) = world.raycast(origin, direction, max_distance) else {
+        return (SKY_COLOR, None);
+    };
+    let material = &palette.materials[(hit.voxel as usize).min(palette.materials.len() - 1)];
+    // Faces are shaded by their axis so the grid stays readable without lighting.
+    let shade = if hit.normal[0] != 0 {
+        0.5
+    } else if hit.normal[1] != 0 {
+        1.0
+    } else {
+        0.75
+    };
+    let color = material.color.map(|c| c * shade + c * material.emissive);
+    (color, Some(hit))
+}
+
+/// Renders the world as seen by `camera` into `rgba`, an image of `extent` pixels with 4 bytes
+/// per pixel, rows from top to bottom. Rows are spread over all cores.
+pub fn render(
+    world: &World,
+    palette: &Palette,
+    camera: &Camera,
+    extent: [u32; 2],
+    max_distance: f32,
+    rgba: &mut [u8],
+) {
+    render_with_outputs(
+        world,
+        palette,
+        camera,
+        camera,
+        extent,
+        max_distance,
+        rgba,
+        Outputs::default(),
+    );
+}
+
+/// Images `render_with_outputs` writes besides the color, for building post effects on top of
+/// the tracer. Each holds the given number of values per pixel, rows from top to bottom. Outputs
+/// left `None` aren't written.
+#[derive(Debug, Default)]
+pub struct Outputs<'a> {
+    /// Distance from the eye to the face hit, infinite for sky. 1 value.
+    pub depth: Option<&'a mut [f32]>,
+    /// Normal of the face hit, zero for sky and rays starting inside a voxel. 3 values.
+    pub normal: Option<&'a mut [f32]>,
+    /// Voxel type hit, 0 for sky. 1 value.
+    pub material: Option<&'a mut [u32]>,
+    /// How far what the pixel shows moved on screen since the previous camera saw it, as a
+    /// fraction of the image's width and height, x right and y down. Matches the compute
+    /// shader's motion vectors. 2 values.
+    pub motion: Option<&'a mut [f32]>,
+    /// Color before it is clamped and rounded to 8 bits, for high dynamic range images. 3
+    /// values.
+    pub color: Option<&'a mut [f32]>,
+}
+
+/// Renders like `render` and writes `outputs` along with the color. `previous_camera` is the
+/// camera of the previous frame, which the motion is measured against.
+#[allow(clippy::too_many_arguments)]
+pub fn render_with_outputs(
+    world: &World,
+    palette: &Palette,
+    camera: &Camera,
+    previous_camera: &Camera,
+    extent: [u32; 2],
+    max_distance: f32,
+    rgba: &mut [u8],
+    outputs: Outputs,
+) {
+    let pixels = extent[0] as usize * extent[1] as usize;
+    assert_eq!(
+        rgba.len(),
+        pixels * 4,
+        "image data doesn't match its extent"
+    );
+    for (len, values) in [
+        (outputs.depth.as_ref().map(|depth| depth.len()), 1),
+        (outputs.normal.as_ref().map(|normal| normal.len()), 3),
+        (outputs.material.as_ref().map(|material| material.len()), 1),
+        (outputs.motion.as_ref().map(|motion| motion.len()), 2),
+        (outputs.color.as_ref().map(|color| color.len()), 3),
+    ] {
+        assert!(
+            len.is_none_or(|len| len == pixels * values),
+            "output data doesn't match its extent"
+        );
+    }
+    if rgba.is_empty() {
+        return;
+    }
+    let width = extent[0] as usize;
+    let aspect = extent[0] as f32 / extent[1] as f32;
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let rows_per_thread = (extent[1] as usize).div_ceil(threads);
+    // Every image is split into the same rows for each thread.
+    let pixels_per_thread = rows_per_thread * width;
+    let mut depth = outputs
+        .depth
+        .map(|depth| depth.chunks_mut(pixels_per_thread));
+    let mut normal = outputs
+        .normal
+        .map(|normal| normal.chunks_mut(pixels_per_thread * 3));
+    let mut material = outputs
+        .material
+        .map(|material| material.chunks_mut(pixels_per_thread));
+    let mut motion = outputs
+        .motion
+        .map(|motion| motion.chunks_mut(pixels_per_thread * 2));
+    let mut unclamped = outputs
+        .color
+        .map(|color| color.chunks_mut(pixels_per_thread * 3));
+    thread::scope(|scope| {
+        for (chunk, rgba) in rgba.chunks_mut(pixels_per_thread * 4).enumerate() {
+            let mut depth = depth.as_mut().and_then(Iterator::next);
+            let mut normal = normal.as_mut().and_then(Iterator::next);
+            let mut material = material.as_mut().and_then(Iterator::next);
+            let mut motion = motion.as_mut().and_then(Iterator::next);
+            let mut unclamped = unclamped.as_mut().and_then(Iterator::next);
+            scope.spawn(move || {
+                for (index, pixel) in rgba.chunks_mut(4).enumerate() {
+                    let x = (index % width) as u32;
+                    let y = (chunk * rows_per_thread + index / width) as u32;
+                    let (color, hit) =
+                        trace_pixel(world, palette, camera, [x, y], extent, max_distance);
+                    let [r, g, b] = color.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                    pixel.copy_from_slice(&[r, g, b, 255]);
+                    if let Some(unclamped) = &mut unclamped {
+                        unclamped[index * 3..index * 3 + 3].copy_from_slice(&color);
+                    }
+                    if let Some(depth) = &mut depth {
+                        depth[index] = hit.map_or(f32::INFINITY, |hit| hit.distance);
+                    }
+                    if let Some(normal) = &mut normal {
+                        let value = hit.map_or([0; 3], |hit| hit.normal).map(|n| n as f32);
+                        normal[index * 3..index * 3 + 3].copy_from_slice(&value);
+                    }
+                    if let Some(material) = &mut material {
+                        material[index] = hit.map_or(0, |hit| hit.voxel);
+                    }
+                    if let Some(motion) = &mut motion {
+                        let screen_pos = [x, y].map(|c| c as f32 + 0.5);
+                        let screen_pos =
+                            [0, 1].map(|i| screen_pos[i] / extent[i] as f32 * 2.0 - 1.0);
+                        let (origin, direction) = camera.ray(screen_pos, aspect);
+                        // Sky only moves with the rotation.
+                        let previous = match hit {
+                            Some(hit) => previous_screen_pos(
+                                previous_camera,
+                                [0, 1, 2].map(|i| origin[i] + direction[i] * hit.distance),
+                                false,
+                                aspect,
+                            ),
+                            None => previous_screen_pos(previous_camera, direction, true, aspect),
+                        };
+                        let value = [0, 1].map(|i| (screen_pos[i] - previous[i]) * 0.5);
+                        motion[index * 2..index * 2 + 2].copy_from_slice(&value);
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Returns where `camera` sees the point `p` on screen (-1 to 1 on both axes, y down), or where
+/// it sees the direction `p` when `direction` is set, the way the compute shader's
+/// `previousScreenPos` does.
+fn previous_screen_pos(camera: &Camera, p: [f32; 3], direction: bool, aspect: f32) -> [f32; 2] {
+    let mut p = world_to_camera(p, camera.rotation);
+    if !direction {
+        p = [0, 1, 2].map(|i| p[i] - camera.position[i]);
+    }
+    let scale = CAMERA_DIR[2] / p[2];
+    [
+        p[0] * scale - CAMERA_DIR[0],
+        (p[1] * scale - CAMERA_DIR[1]) * aspect,
+    ]
+}
@@ -0,0 +1,217 @@
+use crate::{
+    materials::MATERIAL_COUNT,
+    simulation::{LAVA, WATER},
+    world::{CHUNK_SIZE, WORLD_SIZE},
+    worldgen::{chunk_rng, Generator},
+};
+use rand::Rng;
+use std::{fs, path::Path};
+
+/// What `parse_vein` expects.
+const VEIN_SYNTAX: &str = "expected `type frequency min_height max_height size`";
+
+/// Edge length of the cubes of the world veins are scattered over, every cube on its own.
+const VEIN_CELL: u32 = 32;
+/// Upper bound for `Vein::size`, which bounds how far veins reach into the cells around them.
+pub const MAX_VEIN_SIZE: u32 = 256;
+
+/// Directions a vein grows in, one voxel at a time.
+const STEPS: [[i32; 3]; 6] = [
+    [1, 0, 0],
+    [-1, 0, 0],
+    [0, 1, 0],
+    [0, -1, 0],
+    [0, 0, 1],
+    [0, 0, -1],
+];
+
+/// Clusters of one voxel type replacing solid voxels, the ores of a mining game.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vein {
+    pub voxel: u32,
+    /// Veins started per 32³ voxels between `min_height` and `max_height`, below 1 for rare ones.
+    pub frequency: f32,
+    /// Lowest height above the bottom of the world veins start at.
+    pub min_height: u32,
+    /// Height veins start below, they may grow a little above it.
+    pub max_height: u32,
+    /// Steps of the random walk growing a vein, roughly the voxels in it. Steps crossing voxels
+    /// already walked make veins smaller.
+    pub size: u32,
+}
+
+/// Veins scattered through the solid voxels of generated worlds, read from a file with one
+/// `type frequency min_height max_height size` line per vein, see `parse_vein`. Veins only replace
+/// solid voxels, never water, lava or empty space, so they follow the shape of the terrain.
+///
+/// Veins are placed from the world's seed alone, so chunks get the same veins no matter in which
+/// order or on which thread they are generated.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Veins {
+    pub veins: Vec<Vein>,
+}
+
+impl Veins {
+    pub fn load(path: impl AsRef<Path>) -> Result<Veins, String> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("can't read veins `{}`: {err}", path.display()))?;
+        let mut veins = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            veins.push(
+                parse_vein(line)
+                    .map_err(|err| format!("{}:{}: {err}", path.display(), number + 1))?,
+            );
+        }
+        Ok(Veins { veins })
+    }
+
+    /// Replaces the solid voxels of `chunk`, given with z varying fastest like
+    /// `Generator::chunk` returns them, with the veins of a world generated from `seed`.
+    pub fn carve_chunk(&self, seed: u64, chunk: [u32; 3], voxels: &mut [u32]) {
+        let min = chunk.map(|c| c * CHUNK_SIZE);
+        self.walk(seed, min, min.map(|c| c + CHUNK_SIZE), |position, voxel| {
+            let [x, y, z] = [0, 1, 2].map(|i| position[i] - min[i]);
+            replace_solid(
+                &mut voxels[((x * CHUNK_SIZE + y) * CHUNK_SIZE + z) as usize],
+                voxel,
+            );
+        });
+    }
+
+    /// Replaces the solid voxels of a whole world, indexed like `World`'s, with the veins of a
+    /// world generated from `seed`.
+    pub fn carve_world(&self, seed: u64, voxels: &mut [u32]) {
+        self.walk(seed, [0; 3], [WORLD_SIZE; 3], |[x, y, z], voxel| {
+            replace_solid(
+                &mut voxels[((x * WORLD_SIZE + y) * WORLD_SIZE + z) as usize],
+                voxel,
+            );
+        });
+    }
+
+    /// Grows the veins reaching into the box from `min` to `max`, calling `visit` with every
+    /// voxel of them inside the box and their voxel type.
+    fn walk(&self, seed: u64, min: [u32; 3], max: [u32; 3], mut visit: impl FnMut([u32; 3], u32)) {
+        let cells = WORLD_SIZE.div_ceil(VEIN_CELL);
+        for (index, vein) in self.veins.iter().enumerate() {
+            let reach = vein.size;
+            let first = min.map(|c| c.saturating_sub(reach) / VEIN_CELL);
+            let last = max.map(|c| ((c + reach) / VEIN_CELL).min(cells - 1));
+            // Every vein type gets its own random numbers, adding one moves none of the others.
+            let seed = seed ^ (index as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+            for x in first[0]..=last[0] {
+                for y in first[1]..=last[1] {
+                    for z in first[2]..=last[2] {
+                        vein.walk_cell(seed, [x, y, z], |position| {
+                            if (0..3).all(|i| position[i] >= min[i] && position[i] < max[i]) {
+                                visit(position, vein.voxel);
+                            }
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Vein {
+    /// Grows the veins starting in `cell`, calling `visit` with every voxel of them inside the
+    /// world, some more than once.
+    fn walk_cell(&self, seed: u64, cell: [u32; 3], mut visit: impl FnMut([u32; 3])) {
+        let bottom = (cell[1] * VEIN_CELL).max(self.min_height);
+        let top = ((cell[1] + 1) * VEIN_CELL).min(self.max_height);
+        if bottom >= top {
+            return;
+        }
+        let mut rng = chunk_rng(seed, cell);
+        // Fewer veins in cells only partly within the heights.
+        let expected = self.frequency * (top - bottom) as f32 / VEIN_CELL as f32;
+        let count = expected as u32 + rng.gen_bool(expected.fract() as f64) as u32;
+        for _ in 0..count {
+            let mut position = [
+                (cell[0] * VEIN_CELL + rng.gen_range(0..VEIN_CELL)) as i32,
+                rng.gen_range(bottom..top) as i32,
+                (cell[2] * VEIN_CELL + rng.gen_range(0..VEIN_CELL)) as i32,
+            ];
+            for _ in 0..self.size {
+                if position.iter().all(|&c| c >= 0 && c < WORLD_SIZE as i32) {
+                    visit(position.map(|c| c as u32));
+                }
+                let step = STEPS[rng.gen_range(0..STEPS.len())];
+                position = [0, 1, 2].map(|i| position[i] + step[i]);
+            }
+        }
+    }
+}
+
+/// Sets `voxel` to `vein` unless it is empty or a liquid.
+fn replace_solid(voxel: &mut u32, vein: u32) {
+    if ![0, WATER, LAVA].contains(voxel) {
+        *voxel = vein;
+    }
+}
+
+/// Parses a `type frequency min_height max_height size` line into a vein.
+pub fn parse_vein(line: &str) -> Result<Vein, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let [voxel, frequency, min_height, max_height, size] = words[..] else {
+        return Err(VEIN_SYNTAX.to_string());
+    };
+    let voxel = voxel
+        .parse::<u32>()
+        .ok()
+        .filter(|&voxel| voxel >= 1 && (voxel as usize) < MATERIAL_COUNT)
+        .ok_or(format!("invalid voxel type `{voxel}`"))?;
+    let frequency = frequency
+        .parse::<f32>()
+        .ok()
+        .filter(|frequency| frequency.is_finite() && *frequency >= 0.0)
+        .ok_or(format!("invalid frequency `{frequency}`"))?;
+    let height = |height: &str| {
+        height
+            .parse::<u32>()
+            .map(|height| height.min(WORLD_SIZE))
+            .map_err(|err| format!("invalid height `{height}`: {err}"))
+    };
+    let (min_height, max_height) = (height(min_height)?, height(max_height)?);
+    if min_height > max_height {
+        return Err(format!(
+            "min height {min_height} above max height {max_height}"
+        ));
+    }
+    let size = size
+        .parse::<u32>()
+        .ok()
+        .filter(|size| (1..=MAX_VEIN_SIZE).contains(size))
+        .ok_or(format!(
+            "vein size `{size}` isn't from 1 to {MAX_VEIN_SIZE}"
+        ))?;
+    Ok(Vein {
+        voxel,
+        frequency,
+        min_height,
+        max_height,
+        size,
+    })
+}
+
+/// Generator adding `veins` to what `generator` generates, chunk by chunk.
+pub struct Veined<'a> {
+    pub generator: &'a dyn Generator,
+    pub veins: &'a Veins,
+    /// Seed of the world, the veins are placed from.
+    pub seed: u64,
+}
+
+impl Generator for Veined<'_> {
+    fn chunk(&self, chunk: [u32; 3]) -> Vec<u32> {
+        let mut voxels = self.generator.chunk(chunk);
+        self.veins.carve_chunk(self.seed, chunk, &mut voxels);
+        voxels
+    }
+}
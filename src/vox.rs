@@ -0,0 +1,95 @@
+/// A chunk of a MagicaVoxel `.vox` file.
+pub struct Chunk<'a> {
+    /// Four letter name of the chunk, such as `SIZE` or `XYZI`.
+    pub id: &'a [u8],
+    /// Reads the chunk's content, without its children.
+    pub content: Reader<'a>,
+}
+
+/// The chunks of a `.vox` file in the order they are stored, see `chunks`.
+pub struct Chunks<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+/// Returns the chunks of the `.vox` file `bytes`. The MAIN chunk's children follow its header
+/// directly and every other chunk is flat, so they come as a single list.
+pub fn chunks(bytes: &[u8]) -> Result<Chunks<'_>, String> {
+    if bytes.get(0..4) != Some(b"VOX ") {
+        return Err("not a .vox file".to_string());
+    }
+    Ok(Chunks {
+        bytes,
+        offset: 8 + 12,
+    })
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut header = Reader {
+            bytes: self.bytes,
+            offset: self.offset,
+        };
+        let id = header.take(4).ok()?;
+        let content_size = header.u32().ok()? as usize;
+        let children_size = header.u32().ok()? as usize;
+        let content = header.offset;
+        let Some((end, next)) = content
+            .checked_add(content_size)
+            .and_then(|end| Some((end, end.checked_add(children_size)?)))
+        else {
+            // Nothing past a chunk this large can be found.
+            self.offset = self.bytes.len();
+            return Some(Err("chunk larger than the file".to_string()));
+        };
+        self.offset = next;
+        Some(Ok(Chunk {
+            id,
+            content: Reader {
+                bytes: &self.bytes[content..end.min(self.bytes.len())],
+                offset: 0,
+            },
+        }))
+    }
+}
+
+/// Reads the little endian values of a chunk.
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let bytes = self
+            .offset
+            .checked_add(count)
+            .and_then(|end| self.bytes.get(self.offset..end))
+            .ok_or("unexpected end of file")?;
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, String> {
+        self.u32().map(|value| value as i32)
+    }
+
+    pub fn string(&mut self) -> Result<String, String> {
+        let length = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.take(length)?).into_owned())
+    }
+
+    pub fn dictionary(&mut self) -> Result<Vec<(String, String)>, String> {
+        let count = self.u32()?;
+        (0..count)
+            .map(|_| Ok((self.string()?, self.string()?)))
+            .collect()
+    }
+}
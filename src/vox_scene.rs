@@ -0,0 +1,491 @@
+use crate::{
+    layers::PROPS,
+    objects::{Transform, VoxelObject},
+    prefab::{voxel_type, Prefab},
+    vox,
+};
+use std::{collections::HashMap, sync::Arc};
+
+/// A model of a MagicaVoxel scene, in MagicaVoxel's z up coordinates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Model {
+    size: [u32; 3],
+    /// Position and palette index of every voxel.
+    voxels: Vec<[u8; 4]>,
+}
+
+/// A model placed in the scene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Instance {
+    model: usize,
+    /// Rows of the rotation matrix, one entry of every row is 1 or -1.
+    rotation: [[i32; 3]; 3],
+    translation: [i32; 3],
+}
+
+/// Node of the scene graph of a `.vox` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Transform {
+        child: i32,
+        rotation: [[i32; 3]; 3],
+        translation: [i32; 3],
+    },
+    Group(Vec<i32>),
+    Shape(Vec<usize>),
+}
+
+const IDENTITY: [[i32; 3]; 3] = [[1, 0, 0], [0, 1, 0], [0, 0, 1]];
+/// Largest edge of a model MagicaVoxel writes.
+const MAX_MODEL_SIZE: u32 = 256;
+/// How far voxels of a model get from its translation at most, see `instance_voxels`.
+const MAX_MODEL_REACH: i32 = MAX_MODEL_SIZE as i32 + 1;
+
+/// Every model of a MagicaVoxel `.vox` file where its scene graph places it. Unlike `Prefab`,
+/// which takes the first model only, scenes are as large as MagicaVoxel allows, thousands of
+/// voxels across, so they are imported into a `tiled_map::TiledMap` instead of the world.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxScene {
+    models: Vec<Model>,
+    instances: Vec<Instance>,
+}
+
+impl VoxScene {
+    /// Parses the models and the scene graph of the `.vox` file `bytes`. Files without a scene
+    /// graph, written before MagicaVoxel had one, place every model at the origin.
+    pub fn parse(bytes: &[u8]) -> Result<VoxScene, String> {
+        let mut models = Vec::new();
+        let mut nodes = Vec::new();
+        let mut size = None;
+        for chunk in vox::chunks(bytes)? {
+            let vox::Chunk {
+                id,
+                content: mut reader,
+            } = chunk?;
+            match id {
+                b"SIZE" => {
+                    let model_size = [reader.u32()?, reader.u32()?, reader.u32()?];
+                    if model_size.iter().any(|&size| size > MAX_MODEL_SIZE) {
+                        return Err(format!(
+                            "model of {model_size:?} voxels, larger than {MAX_MODEL_SIZE}"
+                        ));
+                    }
+                    size = Some(model_size);
+                }
+                b"XYZI" => {
+                    let size = size.take().ok_or("XYZI chunk before SIZE")?;
+                    let count = reader.u32()? as usize;
+                    let data = reader.take(count.saturating_mul(4))?;
+                    let voxels = data
+                        .chunks_exact(4)
+                        .map(|voxel| [voxel[0], voxel[1], voxel[2], voxel[3]])
+                        .filter(|voxel| {
+                            voxel[3] != 0 && (0..3).all(|i| (voxel[i] as u32) < size[i])
+                        })
+                        .collect();
+                    models.push(Model { size, voxels });
+                }
+                b"nTRN" => {
+                    let id = reader.i32()?;
+                    reader.dictionary()?;
+                    let child = reader.i32()?;
+                    // Reserved and layer.
+                    reader.take(8)?;
+                    let frames = reader.i32()?;
+                    let mut rotation = IDENTITY;
+                    let mut translation = [0; 3];
+                    // Only the first frame of animations.
+                    for frame in 0..frames {
+                        let attributes = reader.dictionary()?;
+                        if frame > 0 {
+                            continue;
+                        }
+                        for (key, value) in attributes {
+                            match key.as_str() {
+                                "_r" => rotation = parse_rotation(&value)?,
+                                "_t" => translation = parse_translation(&value)?,
+                                _ => (),
+                            }
+                        }
+                    }
+                    nodes.push((
+                        id,
+                        Node::Transform {
+                            child,
+                            rotation,
+                            translation,
+                        },
+                    ));
+                }
+                b"nGRP" => {
+                    let id = reader.i32()?;
+                    reader.dictionary()?;
+                    let count = reader.i32()?;
+                    let children = (0..count).map(|_| reader.i32()).collect::<Result<_, _>>()?;
+                    nodes.push((id, Node::Group(children)));
+                }
+                b"nSHP" => {
+                    let id = reader.i32()?;
+                    reader.dictionary()?;
+                    let count = reader.i32()?;
+                    let mut shapes = Vec::new();
+                    for _ in 0..count {
+                        shapes.push(reader.i32()? as usize);
+                        reader.dictionary()?;
+                    }
+                    nodes.push((id, Node::Shape(shapes)));
+                }
+                _ => (),
+            }
+        }
+        if models.is_empty() {
+            return Err("no model in file".to_string());
+        }
+
+        let mut instances = Vec::new();
+        if nodes.is_empty() {
+            // Cornered at the origin like `Prefab`s, not centered like models of a scene.
+            instances.extend((0..models.len()).map(|model| Instance {
+                model,
+                rotation: IDENTITY,
+                translation: models[model].size.map(|size| (size / 2) as i32),
+            }));
+        } else {
+            let mut stack = vec![(0, IDENTITY, [0; 3])];
+            // Bounds the walk through graphs with cycles, which MagicaVoxel doesn't write.
+            let mut visits = 0;
+            while let Some((id, rotation, translation)) = stack.pop() {
+                visits += 1;
+                if visits > nodes.len() * 4 {
+                    return Err("scene graph has cycles".to_string());
+                }
+                let Some((_, node)) = nodes.iter().find(|(node, _)| *node == id) else {
+                    return Err(format!("missing scene node {id}"));
+                };
+                match node {
+                    Node::Transform {
+                        child,
+                        rotation: local_rotation,
+                        translation: local_translation,
+                    } => {
+                        let translation = rotate(rotation, *local_translation)
+                            .and_then(|moved| add(translation, moved))
+                            .ok_or("translation out of range")?;
+                        let rotation =
+                            multiply(rotation, *local_rotation).ok_or("rotation out of range")?;
+                        stack.push((*child, rotation, translation));
+                    }
+                    Node::Group(children) => {
+                        stack.extend(children.iter().map(|&child| (child, rotation, translation)));
+                    }
+                    Node::Shape(shapes) => {
+                        for &model in shapes {
+                            if model >= models.len() {
+                                return Err(format!("missing model {model}"));
+                            }
+                            // Leaves room for the voxels around it.
+                            let reach = |c: i32| {
+                                c.checked_add(MAX_MODEL_REACH)
+                                    .and(c.checked_sub(MAX_MODEL_REACH))
+                            };
+                            if translation.into_iter().any(|c| reach(c).is_none()) {
+                                return Err("translation out of range".to_string());
+                            }
+                            instances.push(Instance {
+                                model,
+                                rotation,
+                                translation,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(VoxScene { models, instances })
+    }
+
+    /// Returns the models placed in the scene.
+    pub fn instance_count(&self) -> usize {
+        self.instances.len()
+    }
+
+    /// Calls `visit` with the position of every voxel of the scene, y up like the world's, and
+    /// its voxel type. Voxels of overlapping models are visited once for every model.
+    pub fn for_each_voxel(&self, mut visit: impl FnMut([i32; 3], u32)) {
+        for instance in &self.instances {
+            self.instance_voxels(instance, &mut visit);
+        }
+    }
+
+    /// Returns every model placed in the scene as an object of its own, keeping the scene's
+    /// layout: each holds its model turned the way the scene turns it, centered on where the
+    /// scene places it, y up. Instances of a model turned the same way share it. Models without
+    /// voxels are left out.
+    pub fn objects(&self) -> Vec<VoxelObject> {
+        let mut objects = Vec::new();
+        let mut shared = HashMap::new();
+        for instance in &self.instances {
+            let mut min = [i32::MAX; 3];
+            let mut max = [i32::MIN; 3];
+            self.instance_voxels(instance, |position, _| {
+                for i in 0..3 {
+                    min[i] = min[i].min(position[i]);
+                    max[i] = max[i].max(position[i]);
+                }
+            });
+            if min[0] > max[0] {
+                continue;
+            }
+            let size = [0, 1, 2].map(|i| (max[i] - min[i] + 1) as u32);
+            let key = (instance.model, instance.rotation);
+            let model = match shared.get(&key) {
+                Some(model) => Arc::clone(model),
+                None => {
+                    let mut voxels = vec![0; (size[0] * size[1] * size[2]) as usize];
+                    self.instance_voxels(instance, |position, voxel| {
+                        let [x, y, z] = [0, 1, 2].map(|i| (position[i] - min[i]) as u32);
+                        voxels[((x * size[1] + y) * size[2] + z) as usize] = voxel;
+                    });
+                    let model = Arc::new(Prefab {
+                        name: format!("model {}", instance.model),
+                        size,
+                        voxels,
+                    });
+                    shared.insert(key, model.clone());
+                    model
+                }
+            };
+            objects.push(VoxelObject {
+                model,
+                transform: Transform {
+                    translation: [0, 1, 2].map(|i| min[i] as f32 + size[i] as f32 * 0.5),
+                    ..Transform::default()
+                },
+                layer: PROPS,
+            });
+        }
+        objects
+    }
+
+    /// Calls `visit` with the position of every voxel of `instance`, y up, and its voxel type.
+    fn instance_voxels(&self, instance: &Instance, mut visit: impl FnMut([i32; 3], u32)) {
+        let model = &self.models[instance.model];
+        // Models are centered on their translation, rounded down.
+        let half = model.size.map(|size| (size / 2) as i32);
+        for &[x, y, z, index] in &model.voxels {
+            let local = [x as i32 - half[0], y as i32 - half[1], z as i32 - half[2]];
+            // Voxels rotate around their center, not their corner, so a negative axis takes the
+            // voxel one further.
+            let rotated = rotate(instance.rotation, local).expect("voxels of models are in range");
+            let corner = [0, 1, 2].map(|i| instance.rotation[i].iter().sum::<i32>().min(0));
+            let [x, y, z] = [0, 1, 2].map(|i| rotated[i] + corner[i] + instance.translation[i]);
+            // z up in MagicaVoxel, y up here.
+            visit([x, z, y], voxel_type(index as u32));
+        }
+    }
+
+    /// Returns the smallest and largest position of a voxel of the scene, y up.
+    pub fn bounds(&self) -> ([i32; 3], [i32; 3]) {
+        let mut min = [i32::MAX; 3];
+        let mut max = [i32::MIN; 3];
+        self.for_each_voxel(|position, _| {
+            for i in 0..3 {
+                min[i] = min[i].min(position[i]);
+                max[i] = max[i].max(position[i]);
+            }
+        });
+        (min, max)
+    }
+}
+
+/// Parses the `_r` attribute of a transform: bits 0 and 1 hold the column of the first row's
+/// non-zero entry, bits 2 and 3 the second row's, and bits 4 to 6 make the rows' entries -1.
+fn parse_rotation(value: &str) -> Result<[[i32; 3]; 3], String> {
+    let bits: u8 = value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid rotation `{value}`"))?;
+    let first = (bits & 3) as usize;
+    let second = (bits >> 2 & 3) as usize;
+    if first > 2 || second > 2 || first == second {
+        return Err(format!("invalid rotation `{value}`"));
+    }
+    let columns = [first, second, 3 - first - second];
+    let mut rotation = [[0; 3]; 3];
+    for (row, &column) in columns.iter().enumerate() {
+        rotation[row][column] = if bits >> (4 + row) & 1 == 1 { -1 } else { 1 };
+    }
+    Ok(rotation)
+}
+
+/// Parses the `_t` attribute of a transform, three whitespace separated integers.
+fn parse_translation(value: &str) -> Result<[i32; 3], String> {
+    let values = value
+        .split_whitespace()
+        .map(|value| value.parse().ok())
+        .collect::<Option<Vec<i32>>>();
+    values
+        .and_then(|values| values.try_into().ok())
+        .ok_or(format!("invalid translation `{value}`"))
+}
+
+/// Returns the dot product of `a` and `b`, `None` when it overflows.
+fn dot(a: [i32; 3], b: [i32; 3]) -> Option<i32> {
+    (0..3).try_fold(0i32, |sum, i| sum.checked_add(a[i].checked_mul(b[i])?))
+}
+
+/// Returns `a + b`, `None` when it overflows.
+fn add(a: [i32; 3], b: [i32; 3]) -> Option<[i32; 3]> {
+    let [x, y, z] = [0, 1, 2].map(|i| a[i].checked_add(b[i]));
+    Some([x?, y?, z?])
+}
+
+/// Returns `v` turned by `rotation`, `None` when it overflows.
+fn rotate(rotation: [[i32; 3]; 3], v: [i32; 3]) -> Option<[i32; 3]> {
+    let [x, y, z] = rotation.map(|row| dot(row, v));
+    Some([x?, y?, z?])
+}
+
+/// Returns the product of `a` and `b`, `None` when it overflows.
+fn multiply(a: [[i32; 3]; 3], b: [[i32; 3]; 3]) -> Option<[[i32; 3]; 3]> {
+    let columns = [0, 1, 2].map(|column| b.map(|row| row[column]));
+    let [x, y, z] = a.map(|row| rotate(columns, row));
+    Some([x?, y?, z?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend((content.len() as u32).to_le_bytes());
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(content);
+        bytes
+    }
+
+    /// Little endian words followed by a dictionary of `entries`.
+    fn words_and_dictionary(words: &[i32], entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        bytes.extend((entries.len() as u32).to_le_bytes());
+        for text in entries.iter().flat_map(|&(key, value)| [key, value]) {
+            bytes.extend((text.len() as u32).to_le_bytes());
+            bytes.extend(text.as_bytes());
+        }
+        bytes
+    }
+
+    fn transform(id: i32, child: i32, attributes: &[(&str, &str)]) -> Vec<u8> {
+        let mut content = words_and_dictionary(&[id], &[]);
+        // Child, reserved, layer and a single frame.
+        content.extend(words_and_dictionary(&[child, -1, 0, 1], attributes));
+        chunk(b"nTRN", &content)
+    }
+
+    fn group(id: i32, children: &[i32]) -> Vec<u8> {
+        let mut content = words_and_dictionary(&[id], &[]);
+        content.extend((children.len() as i32).to_le_bytes());
+        content.extend(children.iter().flat_map(|child| child.to_le_bytes()));
+        chunk(b"nGRP", &content)
+    }
+
+    fn shape(id: i32, model: i32) -> Vec<u8> {
+        let mut content = words_and_dictionary(&[id], &[]);
+        content.extend(words_and_dictionary(&[1, model], &[]));
+        chunk(b"nSHP", &content)
+    }
+
+    /// A `.vox` file of a model of `size` holding a voxel of palette index 1 at its corner,
+    /// followed by `nodes`.
+    fn vox(size: [u32; 3], nodes: &[Vec<u8>]) -> Vec<u8> {
+        let size: Vec<u8> = size.iter().flat_map(|size| size.to_le_bytes()).collect();
+        let mut children = chunk(b"SIZE", &size);
+        children.extend(chunk(b"XYZI", &[1, 0, 0, 0, 0, 0, 0, 1]));
+        children.extend(nodes.concat());
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend(150u32.to_le_bytes());
+        bytes.extend(b"MAIN");
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend((children.len() as u32).to_le_bytes());
+        bytes.extend(children);
+        bytes
+    }
+
+    fn voxels(scene: &VoxScene) -> Vec<([i32; 3], u32)> {
+        let mut voxels = Vec::new();
+        scene.for_each_voxel(|position, voxel| voxels.push((position, voxel)));
+        voxels
+    }
+
+    #[test]
+    fn rotation_bits_pick_columns_and_signs() {
+        assert_eq!(parse_rotation("4"), Ok(IDENTITY));
+        // x and y swapped, the first row negated, then y and z swapped with every row negated.
+        assert_eq!(parse_rotation("17"), Ok([[0, -1, 0], [1, 0, 0], [0, 0, 1]]));
+        assert_eq!(
+            parse_rotation("120"),
+            Ok([[-1, 0, 0], [0, 0, -1], [0, -1, 0]])
+        );
+        for invalid in ["0", "3", "x"] {
+            assert!(parse_rotation(invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_places_models_along_the_scene_graph() {
+        let bytes = vox(
+            [2, 2, 2],
+            &[
+                transform(0, 1, &[("_t", "10 20 30")]),
+                group(1, &[2]),
+                transform(2, 3, &[("_r", "17"), ("_t", "1 2 3")]),
+                shape(3, 0),
+            ],
+        );
+        let scene = VoxScene::parse(&bytes).unwrap();
+        assert_eq!(scene.instance_count(), 1);
+        // The corner voxel is at -1 -1 -1 from the model's center, turned to 1 -1 -1, a voxel
+        // further along the negated x at 0 -1 -1, moved by both translations to 11 21 32, then
+        // made y up.
+        assert_eq!(voxels(&scene), [([11, 32, 21], voxel_type(1))]);
+    }
+
+    #[test]
+    fn parse_without_a_scene_graph_corners_models_at_the_origin() {
+        let scene = VoxScene::parse(&vox([2, 2, 2], &[])).unwrap();
+        assert_eq!(voxels(&scene), [([0, 0, 0], voxel_type(1))]);
+    }
+
+    #[test]
+    fn parse_rejects_broken_scene_graphs() {
+        let parse = |nodes: &[Vec<u8>]| VoxScene::parse(&vox([2, 2, 2], nodes));
+        assert_eq!(
+            parse(&[transform(0, 1, &[]), group(1, &[0])]),
+            Err("scene graph has cycles".to_string())
+        );
+        assert_eq!(
+            parse(&[transform(0, 5, &[])]),
+            Err("missing scene node 5".to_string())
+        );
+        assert_eq!(
+            parse(&[transform(0, 1, &[]), shape(1, 3)]),
+            Err("missing model 3".to_string())
+        );
+        let out_of_range = Err("translation out of range".to_string());
+        assert_eq!(
+            parse(&[
+                transform(0, 1, &[("_t", "2147483000 0 0")]),
+                transform(1, 2, &[("_t", "1000 0 0")]),
+                shape(2, 0),
+            ]),
+            out_of_range
+        );
+        // Too close to the end of the range for the voxels around it.
+        assert_eq!(
+            parse(&[transform(0, 1, &[("_t", "0 -2147483600 0")]), shape(1, 0)]),
+            out_of_range
+        );
+        assert!(VoxScene::parse(&vox([257, 1, 1], &[])).is_err());
+    }
+}
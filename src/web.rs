@@ -0,0 +1,478 @@
+//! Browser build of the viewer, for sharing scenes as a link: the world is traced by a WebGPU
+//! shader into a canvas and walked through with the keyboard and mouse. Built with the `web`
+//! feature for `wasm32-unknown-unknown`, see `web/index.html`.
+//!
+//! The page's query picks the scene, `?demo=mirrors` builds one of `demo::DEMOS` and
+//! `?world=scene.rvox` fetches a world saved with `World::save`. Nothing here blocks: the GPU is
+//! set up and worlds are fetched with futures the browser runs, and frames are drawn from
+//! `requestAnimationFrame` without waiting on the GPU.
+
+use crate::{
+    camera::{camera_to_world, world_to_camera, Camera, CAMERA_DIR},
+    demo::{self, DEMOS},
+    materials::Palette,
+    tracer::SKY_COLOR,
+    world::{World, WORLD_SIZE},
+};
+use std::{cell::RefCell, rc::Rc};
+use wasm_bindgen::{closure::WasmClosure, prelude::*, JsCast};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{HtmlCanvasElement, KeyboardEvent, MouseEvent, Response, UrlSearchParams};
+use wgpu::util::DeviceExt;
+
+/// Distance the shader traces rays to, across the whole world.
+const RENDER_DISTANCE: f32 = 512.0;
+/// Voxels the camera moves per second while a movement key is held.
+const MOVE_SPEED: f32 = 5.0;
+/// Radians the view turns per pixel the mouse moves while the pointer is locked.
+const MOUSE_SENSITIVITY: f32 = 0.003;
+
+/// Called by the browser with the time in milliseconds before drawing to the page.
+type FrameCallback = Closure<dyn FnMut(f64)>;
+
+/// Traces the world into a canvas with WebGPU. The world is uploaded once, a byte per voxel.
+pub struct WebRenderer {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    view_buffer: wgpu::Buffer,
+}
+
+impl WebRenderer {
+    /// Sets up a device drawing to `surface`, `size` pixels large, and uploads `world` and the
+    /// materials of `palette` to it.
+    pub async fn new(
+        instance: &wgpu::Instance,
+        surface: wgpu::Surface,
+        size: [u32; 2],
+        world: &World,
+        palette: &Palette,
+    ) -> Result<WebRenderer, String> {
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter: false,
+                compatible_surface: Some(&surface),
+            })
+            .await
+            .ok_or("no WebGPU adapter, the browser may not support WebGPU")?;
+        let voxel_bytes = (WORLD_SIZE as u64).pow(3);
+        if adapter.limits().max_storage_buffer_binding_size < voxel_bytes as u32 {
+            return Err(format!(
+                "the GPU binds storage buffers of at most {} bytes, the world takes {voxel_bytes}",
+                adapter.limits().max_storage_buffer_binding_size
+            ));
+        }
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    features: wgpu::Features::empty(),
+                    limits: wgpu::Limits {
+                        max_storage_buffer_binding_size: voxel_bytes as u32,
+                        max_buffer_size: voxel_bytes,
+                        ..wgpu::Limits::downlevel_defaults()
+                    },
+                },
+                None,
+            )
+            .await
+            .map_err(|err| format!("can't create a WebGPU device: {err}"))?;
+
+        let capabilities = surface.get_capabilities(&adapter);
+        // The shader writes colors as the CPU tracer does, without converting them to sRGB.
+        let format = capabilities
+            .formats
+            .iter()
+            .copied()
+            .find(|format| !format.is_srgb())
+            .or(capabilities.formats.first().copied())
+            .ok_or("the canvas supports no texture format")?;
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: size[0].max(1),
+            height: size[1].max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: capabilities.alpha_modes[0],
+            view_formats: Vec::new(),
+        };
+        surface.configure(&device, &config);
+
+        let source = format!(
+            "const WORLD_SIZE: i32 = {WORLD_SIZE};\nconst SKY_COLOR = vec3<f32>({:?}, {:?}, {:?});\n{}",
+            SKY_COLOR[0],
+            SKY_COLOR[1],
+            SKY_COLOR[2],
+            include_str!("../assets/shader/web_trace.wgsl"),
+        );
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("web_trace"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("web_trace"),
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vertex",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fragment",
+                targets: &[Some(format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let view_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("view"),
+            size: std::mem::size_of::<[f32; 16]>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let voxel_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("voxels"),
+            contents: &voxel_bytes_of(world),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let material_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("materials"),
+            contents: &palette
+                .materials
+                .iter()
+                .flat_map(|material| {
+                    let [r, g, b] = material.color;
+                    [r, g, b, material.emissive]
+                })
+                .flat_map(f32::to_le_bytes)
+                .collect::<Vec<u8>>(),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: voxel_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: material_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Ok(WebRenderer {
+            device,
+            queue,
+            surface,
+            config,
+            pipeline,
+            bind_group,
+            view_buffer,
+        })
+    }
+
+    /// Draws to a canvas `size` pixels large from now on. Does nothing if the size is unchanged.
+    pub fn resize(&mut self, size: [u32; 2]) {
+        let size = size.map(|c| c.max(1));
+        if size != [self.config.width, self.config.height] {
+            [self.config.width, self.config.height] = size;
+            self.surface.configure(&self.device, &self.config);
+        }
+    }
+
+    /// Draws the world as seen by `camera`. The frame is queued and shows once the GPU finished
+    /// it, nothing waits for it here.
+    pub fn render(&mut self, camera: &Camera) -> Result<(), String> {
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            // The canvas changed under the surface, the next frame draws to a fresh one.
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(&self.device, &self.config);
+                return Ok(());
+            }
+            Err(err) => return Err(format!("can't get the canvas texture: {err}")),
+        };
+        let [right, down, forward] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], CAMERA_DIR]
+            .map(|axis| camera_to_world(axis, camera.rotation));
+        let [width, height] = [self.config.width as f32, self.config.height as f32];
+        let eye = camera.eye();
+        let view = [
+            eye[0],
+            eye[1],
+            eye[2],
+            width / height,
+            right[0],
+            right[1],
+            right[2],
+            RENDER_DISTANCE,
+            down[0],
+            down[1],
+            down[2],
+            width,
+            forward[0],
+            forward[1],
+            forward[2],
+            height,
+        ];
+        let view: Vec<u8> = view.into_iter().flat_map(f32::to_le_bytes).collect();
+        self.queue.write_buffer(&self.view_buffer, 0, &view);
+
+        let target = frame
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("web_trace"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &target,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.draw(0..3, 0..1);
+        }
+        self.queue.submit([encoder.finish()]);
+        frame.present();
+        Ok(())
+    }
+}
+
+/// Returns the voxels of `world` a byte each, in the order the shader's `getVoxel` reads them.
+fn voxel_bytes_of(world: &World) -> Vec<u8> {
+    world
+        .region([0; 3], [WORLD_SIZE; 3])
+        .into_iter()
+        .map(|voxel| voxel.min(u8::MAX as u32) as u8)
+        .collect()
+}
+
+/// Keys held and mouse movement since the last frame, filled in by the page's event listeners.
+#[derive(Debug, Default)]
+pub struct Input {
+    pub forward: bool,
+    pub backward: bool,
+    pub left: bool,
+    pub right: bool,
+    pub up: bool,
+    pub down: bool,
+    /// Pixels the mouse moved right and down while the pointer was locked to the canvas.
+    pub mouse: [f32; 2],
+}
+
+impl Input {
+    /// Updates the movement keys for a key event with `KeyboardEvent.code` `code`. Returns
+    /// whether the key moves the camera, so the page doesn't scroll with it.
+    pub fn key(&mut self, code: &str, pressed: bool) -> bool {
+        let key = match code {
+            "KeyW" | "ArrowUp" => &mut self.forward,
+            "KeyS" | "ArrowDown" => &mut self.backward,
+            "KeyA" | "ArrowLeft" => &mut self.left,
+            "KeyD" | "ArrowRight" => &mut self.right,
+            "Space" => &mut self.up,
+            "ShiftLeft" | "ShiftRight" => &mut self.down,
+            _ => return false,
+        };
+        *key = pressed;
+        true
+    }
+
+    /// Walks and turns `camera` as the desktop build does for `dt` seconds of input, and
+    /// forgets the mouse movement.
+    pub fn apply(&mut self, camera: &mut Camera, dt: f32) {
+        let step = MOVE_SPEED * dt;
+        let axis = |positive: bool, negative: bool| (positive as i32 - negative as i32) as f32;
+        camera.walk([
+            axis(self.right, self.left) * step,
+            axis(self.up, self.down) * step,
+            axis(self.forward, self.backward) * step,
+        ]);
+        let [x, y] = std::mem::take(&mut self.mouse);
+        camera.turn(-y * MOUSE_SENSITIVITY, x * MOUSE_SENSITIVITY);
+    }
+}
+
+/// Builds the scene the page's query asks for and draws it into the canvas with the id
+/// `canvas_id` every animation frame until the page closes. The promise JavaScript gets
+/// resolves once the first frame is queued, or rejects with why the scene or WebGPU failed.
+#[wasm_bindgen]
+pub async fn start(canvas_id: String) -> Result<(), JsValue> {
+    run(&canvas_id).await.map_err(|err| JsValue::from_str(&err))
+}
+
+async fn run(canvas_id: &str) -> Result<(), String> {
+    let window = web_sys::window().ok_or("not running in a browser window")?;
+    let document = window.document().ok_or("the window has no document")?;
+    let canvas: HtmlCanvasElement = document
+        .get_element_by_id(canvas_id)
+        .ok_or(format!("no element with the id `{canvas_id}`"))?
+        .dyn_into()
+        .map_err(|_| format!("`{canvas_id}` isn't a canvas"))?;
+
+    let query = window
+        .location()
+        .search()
+        .map_err(|_| "can't read the page's query")?;
+    let query = UrlSearchParams::new_with_str(&query).map_err(|_| "invalid page query")?;
+    let mut world = World::new();
+    let mut palette = Palette::default();
+    let mut camera = Camera::default();
+    if let Some(url) = query.get("world") {
+        world.load_rvox(&fetch(&window, &url).await?)?;
+    } else {
+        let name = query.get("demo").unwrap_or(DEMOS[0].to_string());
+        let scene = demo::load(&name, &mut world, &mut palette)?;
+        camera = Camera {
+            position: world_to_camera(scene.eye, [0.0; 3]),
+            rotation: [0.0; 3],
+        };
+    }
+
+    let size = canvas_size(&canvas);
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::BROWSER_WEBGPU,
+        ..Default::default()
+    });
+    let surface = instance
+        .create_surface_from_canvas(canvas.clone())
+        .map_err(|err| format!("can't draw to the canvas with WebGPU: {err}"))?;
+    let mut renderer = WebRenderer::new(&instance, surface, size, &world, &palette).await?;
+    renderer.render(&camera)?;
+
+    let input = Rc::new(RefCell::new(Input::default()));
+    listen_to_input(&window, &canvas, &input)?;
+
+    // Every frame queues the next one, so the closure holds a handle to itself.
+    let frame: Rc<RefCell<Option<FrameCallback>>> = Rc::new(RefCell::new(None));
+    let next_frame = frame.clone();
+    let frame_window = window.clone();
+    let mut last_time = None;
+    *frame.borrow_mut() = Some(Closure::new(move |time: f64| {
+        let dt = last_time.map_or(0.0, |last| ((time - last) / 1000.0) as f32);
+        last_time = Some(time);
+        input.borrow_mut().apply(&mut camera, dt);
+        renderer.resize(canvas_size(&canvas));
+        if let Err(err) = renderer.render(&camera) {
+            web_sys::console::error_1(&err.into());
+            return;
+        }
+        request_animation_frame(&frame_window, next_frame.borrow().as_ref().unwrap());
+    }));
+    request_animation_frame(&window, frame.borrow().as_ref().unwrap());
+    Ok(())
+}
+
+/// Returns the size of `canvas` in device pixels, setting its drawing buffer to it.
+fn canvas_size(canvas: &HtmlCanvasElement) -> [u32; 2] {
+    let scale = web_sys::window().map_or(1.0, |window| window.device_pixel_ratio());
+    let size = [canvas.client_width(), canvas.client_height()]
+        .map(|c| (c.max(1) as f64 * scale).round() as u32);
+    if [canvas.width(), canvas.height()] != size {
+        canvas.set_width(size[0]);
+        canvas.set_height(size[1]);
+    }
+    size
+}
+
+fn request_animation_frame(window: &web_sys::Window, callback: &FrameCallback) {
+    window
+        .request_animation_frame(callback.as_ref().unchecked_ref())
+        .expect("requestAnimationFrame is available in every browser with WebGPU");
+}
+
+/// Fills `input` from key presses anywhere on the page and mouse movement while the pointer is
+/// locked, which a click on the canvas does.
+fn listen_to_input(
+    window: &web_sys::Window,
+    canvas: &HtmlCanvasElement,
+    input: &Rc<RefCell<Input>>,
+) -> Result<(), String> {
+    for (event, pressed) in [("keydown", true), ("keyup", false)] {
+        let input = input.clone();
+        listen(
+            window,
+            event,
+            Closure::<dyn FnMut(KeyboardEvent)>::new(move |event: KeyboardEvent| {
+                if input.borrow_mut().key(&event.code(), pressed) {
+                    event.prevent_default();
+                }
+            }),
+        )?;
+    }
+    let locked_canvas = canvas.clone();
+    listen(
+        canvas,
+        "click",
+        Closure::<dyn FnMut(MouseEvent)>::new(move |_: MouseEvent| {
+            locked_canvas.request_pointer_lock()
+        }),
+    )?;
+    let input = input.clone();
+    let canvas: web_sys::Element = canvas.clone().into();
+    listen(
+        window,
+        "mousemove",
+        Closure::<dyn FnMut(MouseEvent)>::new(move |event: MouseEvent| {
+            let document = web_sys::window().and_then(|window| window.document());
+            let locked = document
+                .and_then(|document| document.pointer_lock_element())
+                .is_some_and(|element| element == canvas);
+            if locked {
+                let mut input = input.borrow_mut();
+                input.mouse[0] += event.movement_x() as f32;
+                input.mouse[1] += event.movement_y() as f32;
+            }
+        }),
+    )
+}
+
+fn listen<T: ?Sized + WasmClosure>(
+    target: &web_sys::EventTarget,
+    event: &str,
+    listener: Closure<T>,
+) -> Result<(), String> {
+    let result = target.add_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+    // The listeners live as long as the page.
+    listener.forget();
+    result.map_err(|_| format!("can't listen to `{event}` events"))
+}
+
+/// Returns the body of the response to a GET of `url`.
+async fn fetch(window: &web_sys::Window, url: &str) -> Result<Vec<u8>, String> {
+    let failed = |err: JsValue| format!("can't fetch `{url}`: {err:?}");
+    let response: Response = JsFuture::from(window.fetch_with_str(url))
+        .await
+        .map_err(failed)?
+        .dyn_into()
+        .map_err(failed)?;
+    if !response.ok() {
+        return Err(format!("can't fetch `{url}`: HTTP {}", response.status()));
+    }
+    let body = JsFuture::from(response.array_buffer().map_err(failed)?)
+        .await
+        .map_err(failed)?;
+    Ok(js_sys::Uint8Array::new(&body).to_vec())
+}
@@ -0,0 +1,292 @@
+use crate::context::Context;
+use std::{collections::HashMap, num::NonZeroU64, sync::Arc, time::Duration};
+use vulkano::{
+    device::Queue,
+    format::Format,
+    image::{view::ImageView, ImageUsage, StorageImage},
+    memory::allocator::StandardMemoryAllocator,
+    swapchain::{
+        self, AcquireError, PresentMode, PresentWaitError, Surface, Swapchain, SwapchainCreateInfo,
+        SwapchainCreationError, SwapchainPresentInfo,
+    },
+    sync::{self, FlushError, GpuFuture},
+};
+use vulkano_util::renderer::{DeviceImageView, SwapchainImageView};
+use winit::{
+    dpi::LogicalSize,
+    event_loop::EventLoop,
+    window::{Window, WindowBuilder},
+};
+
+/// Size of the window when it opens, in logical pixels.
+pub const WINDOW_SIZE: [f64; 2] = [1280.0, 720.0];
+
+/// The window with its swapchain and the images drawn at its size, like `vulkano_util`'s
+/// `VulkanoWindowRenderer` but on a device created by `Context`.
+pub struct WindowRenderer {
+    window: Arc<Window>,
+    surface: Arc<Surface>,
+    graphics_queue: Arc<Queue>,
+    memory_allocator: Arc<StandardMemoryAllocator>,
+    swapchain: Arc<Swapchain>,
+    swapchain_views: Vec<SwapchainImageView>,
+    /// Images resized along with the swapchain, with the format and usage they are created
+    /// with, see `add_additional_image_view`.
+    additional_image_views: HashMap<usize, (DeviceImageView, Format, ImageUsage)>,
+    present_mode: PresentMode,
+    recreate_swapchain: bool,
+    previous_frame_end: Option<Box<dyn GpuFuture>>,
+    image_index: u32,
+    /// Presents made, each tagged with its count when the device can wait for them to reach
+    /// the display, see `wait_presented`.
+    presents: u64,
+    /// Id of the last present when it was tagged and went through.
+    last_present_id: Option<NonZeroU64>,
+}
+
+impl WindowRenderer {
+    /// Opens a window titled `title` presenting with `present_mode`, letting
+    /// `swapchain_create_info_modify` change how its swapchain is created.
+    pub fn new(
+        event_loop: &EventLoop<()>,
+        context: &Context,
+        memory_allocator: Arc<StandardMemoryAllocator>,
+        title: &str,
+        present_mode: PresentMode,
+        swapchain_create_info_modify: fn(&mut SwapchainCreateInfo),
+    ) -> Result<WindowRenderer, String> {
+        let window = Arc::new(
+            WindowBuilder::new()
+                .with_inner_size(LogicalSize::new(WINDOW_SIZE[0], WINDOW_SIZE[1]))
+                .with_title(title)
+                .build(event_loop)
+                .map_err(|err| format!("can't open a window: {err}"))?,
+        );
+        let surface =
+            vulkano_win::create_surface_from_winit(window.clone(), context.instance().clone())
+                .map_err(|err| format!("can't present to the window: {err}"))?;
+        let device = context.device();
+        let physical_device = device.physical_device();
+        let capabilities = physical_device
+            .surface_capabilities(&surface, Default::default())
+            .map_err(|err| format!("can't query the window's surface: {err}"))?;
+        let image_format = physical_device
+            .surface_formats(&surface, Default::default())
+            .map_err(|err| format!("can't query the window's surface: {err}"))?[0]
+            .0;
+        let mut create_info = SwapchainCreateInfo {
+            min_image_count: capabilities.min_image_count,
+            image_format: Some(image_format),
+            image_extent: window.inner_size().into(),
+            image_usage: ImageUsage::COLOR_ATTACHMENT,
+            composite_alpha: capabilities
+                .supported_composite_alpha
+                .into_iter()
+                .next()
+                .unwrap(),
+            present_mode,
+            ..Default::default()
+        };
+        swapchain_create_info_modify(&mut create_info);
+        let (swapchain, images) = Swapchain::new(device.clone(), surface.clone(), create_info)
+            .map_err(|err| format!("can't create the swapchain: {err}"))?;
+        Ok(WindowRenderer {
+            window,
+            surface,
+            graphics_queue: context.graphics_queue().clone(),
+            memory_allocator,
+            swapchain,
+            swapchain_views: images
+                .into_iter()
+                .map(|image| ImageView::new_default(image).unwrap())
+                .collect(),
+            additional_image_views: HashMap::new(),
+            present_mode,
+            recreate_swapchain: false,
+            previous_frame_end: Some(sync::now(device.clone()).boxed()),
+            image_index: 0,
+            presents: 0,
+            last_present_id: None,
+        })
+    }
+
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    /// Returns the window's size in physical pixels.
+    pub fn window_size(&self) -> [f32; 2] {
+        let size = self.window.inner_size();
+        [size.width as f32, size.height as f32]
+    }
+
+    pub fn surface(&self) -> Arc<Surface> {
+        self.surface.clone()
+    }
+
+    pub fn graphics_queue(&self) -> Arc<Queue> {
+        self.graphics_queue.clone()
+    }
+
+    pub fn swapchain_format(&self) -> Format {
+        self.swapchain.image_format()
+    }
+
+    /// Returns the swapchain image acquired last.
+    pub fn swapchain_image_view(&self) -> SwapchainImageView {
+        self.swapchain_views[self.image_index as usize].clone()
+    }
+
+    /// Adds an image of the swapchain's size under `key`, replaced by one of the new size
+    /// whenever the swapchain is resized.
+    pub fn add_additional_image_view(&mut self, key: usize, format: Format, usage: ImageUsage) {
+        let view = StorageImage::general_purpose_image_view(
+            &*self.memory_allocator,
+            self.graphics_queue.clone(),
+            self.swapchain.image_extent(),
+            format,
+            usage,
+        )
+        .unwrap();
+        self.additional_image_views
+            .insert(key, (view, format, usage));
+    }
+
+    /// Returns the image added under `key`, see `add_additional_image_view`.
+    pub fn get_additional_image_view(&self, key: usize) -> DeviceImageView {
+        self.additional_image_views[&key].0.clone()
+    }
+
+    /// Recreates the swapchain before the next image is acquired, after the window changed
+    /// size.
+    pub fn resize(&mut self) {
+        self.recreate_swapchain = true;
+    }
+
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        if self.present_mode != present_mode {
+            self.present_mode = present_mode;
+            self.recreate_swapchain = true;
+        }
+    }
+
+    /// Acquires the next swapchain image, returning when it is ready joined with the end of the
+    /// previous frame.
+    pub fn acquire(&mut self) -> Result<Box<dyn GpuFuture>, AcquireError> {
+        if self.recreate_swapchain {
+            self.recreate_swapchain_and_views();
+        }
+        let (image_index, suboptimal, acquire_future) =
+            match swapchain::acquire_next_image(self.swapchain.clone(), None) {
+                Ok(acquired) => acquired,
+                Err(AcquireError::OutOfDate) => {
+                    self.recreate_swapchain = true;
+                    return Err(AcquireError::OutOfDate);
+                }
+                Err(err) => panic!("can't acquire a swapchain image: {err}"),
+            };
+        if suboptimal {
+            self.recreate_swapchain = true;
+        }
+        self.image_index = image_index;
+        Ok(self
+            .previous_frame_end
+            .take()
+            .unwrap()
+            .join(acquire_future)
+            .boxed())
+    }
+
+    /// Presents the acquired image after `after_future`, waiting for the frame to finish on the
+    /// GPU when `wait_future`.
+    pub fn present(&mut self, after_future: Box<dyn GpuFuture>, wait_future: bool) {
+        self.presents += 1;
+        let present_id = NonZeroU64::new(self.presents)
+            .filter(|_| self.graphics_queue.device().enabled_features().present_id);
+        let future = after_future
+            .then_swapchain_present(
+                self.graphics_queue.clone(),
+                SwapchainPresentInfo {
+                    present_id,
+                    ..SwapchainPresentInfo::swapchain_image_index(
+                        self.swapchain.clone(),
+                        self.image_index,
+                    )
+                },
+            )
+            .then_signal_fence_and_flush();
+        self.last_present_id = None;
+        match future {
+            Ok(mut future) => {
+                self.last_present_id = present_id;
+                if wait_future {
+                    if let Err(err) = future.wait(None) {
+                        println!("{err}");
+                    }
+                } else {
+                    future.cleanup_finished();
+                }
+                self.previous_frame_end = Some(future.boxed());
+            }
+            Err(err) => {
+                if matches!(err, FlushError::OutOfDate) {
+                    self.recreate_swapchain = true;
+                } else {
+                    println!("can't present: {err}");
+                }
+                self.previous_frame_end =
+                    Some(sync::now(self.graphics_queue.device().clone()).boxed());
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the last presented frame to reach the display. Returns whether
+    /// it did, false as well when the device can't tell, see `gpu::optional_features`.
+    pub fn wait_presented(&mut self, timeout: Duration) -> bool {
+        let Some(present_id) = self.last_present_id else {
+            return false;
+        };
+        match swapchain::wait_for_present(self.swapchain.clone(), present_id.get(), Some(timeout)) {
+            Ok(suboptimal) => {
+                self.recreate_swapchain |= suboptimal;
+                true
+            }
+            Err(PresentWaitError::OutOfDate) => {
+                self.recreate_swapchain = true;
+                false
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn recreate_swapchain_and_views(&mut self) {
+        let recreated = self.swapchain.recreate(SwapchainCreateInfo {
+            image_extent: self.window.inner_size().into(),
+            present_mode: self.present_mode,
+            ..self.swapchain.create_info()
+        });
+        let (swapchain, images) = match recreated {
+            Ok(recreated) => recreated,
+            // Happens while the window is minimized, tried again on the next frame.
+            Err(err @ SwapchainCreationError::ImageExtentNotSupported { .. }) => {
+                println!("{err}");
+                return;
+            }
+            Err(err) => panic!("can't recreate the swapchain: {err}"),
+        };
+        self.swapchain = swapchain;
+        self.swapchain_views = images
+            .into_iter()
+            .map(|image| ImageView::new_default(image).unwrap())
+            .collect();
+        let additional: Vec<_> = self
+            .additional_image_views
+            .iter()
+            .map(|(&key, &(_, format, usage))| (key, format, usage))
+            .collect();
+        for (key, format, usage) in additional {
+            self.add_additional_image_view(key, format, usage);
+        }
+        self.recreate_swapchain = false;
+    }
+}
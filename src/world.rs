@@ -0,0 +1,893 @@
+#[cfg(feature = "vulkan")]
+use crate::{
+    camera::screen_coverage,
+    chunk_palette::{ChunkSlots, PackedChunk, MAX_PACKED_WORDS},
+    gpu_chunks::{ChunkBinding, GpuChunks},
+    staging::{Staged, StagingRing},
+};
+use crate::{distance_field::ChunkDistances, world_file};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+#[cfg(feature = "vulkan")]
+use vulkano::{
+    command_buffer::{
+        AutoCommandBufferBuilder, BufferCopy, CopyBufferInfoTyped, PrimaryAutoCommandBuffer,
+    },
+    memory::allocator::StandardMemoryAllocator,
+};
+
+/// Edge length of the cubic world in voxels.
+pub const WORLD_SIZE: u32 = 256;
+/// Edge length of the cubic chunks edits are tracked and uploaded in.
+pub const CHUNK_SIZE: u32 = 16;
+/// Bytes uploaded by a single `World::flush` unless changed with `World::set_upload_budget`.
+pub const DEFAULT_UPLOAD_BUDGET: usize = 8 << 20;
+/// Version of the `.rvox` files `World::to_rvox` writes.
+pub const RVOX_VERSION: u32 = 1;
+
+/// Words of the GPU copy `World::gpu_chunks` is first created with, an eighth of what the world
+/// takes unpacked. `World::flush` replaces it once the packed chunks outgrow it.
+#[cfg(feature = "vulkan")]
+pub const INITIAL_GPU_WORDS: u32 = WORLD_SIZE * WORLD_SIZE * WORLD_SIZE / 8;
+
+/// A voxel found by `World::raycast`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub voxel: u32,
+    pub position: [i32; 3],
+    /// Normal of the face the ray entered through, zero when the ray started inside the voxel.
+    pub normal: [i32; 3],
+    /// Distance from the ray origin to the entered face.
+    pub distance: f32,
+}
+
+/// CPU copy of the voxel grid. Edits land here right away and the chunks they touched are
+/// uploaded to the GPU copy by `flush`, or `stage` and `StagedChunks::record` apart, a limited
+/// number of bytes per frame. The GPU copy holds every chunk packed against a palette of the
+/// voxel types in it, see `chunk_palette`.
+pub struct World {
+    /// Voxel types indexed by `(x * WORLD_SIZE + y) * WORLD_SIZE + z`, 0 is empty.
+    voxels: Vec<u32>,
+    /// Chunks changed since they were last uploaded.
+    dirty_chunks: BTreeSet<[u32; 3]>,
+    /// Chunks changed since `take_edited_chunks` was last called.
+    edited_chunks: BTreeSet<[u32; 3]>,
+    distance_field: ChunkDistances,
+    upload_budget: usize,
+    /// Whether the GPU copy was cleared by a first flush.
+    #[cfg(feature = "vulkan")]
+    gpu_cleared: bool,
+    /// Where the packed chunks are in the GPU copy.
+    #[cfg(feature = "vulkan")]
+    gpu_slots: ChunkSlots,
+    /// Buffers flushes upload through, created by the first flush and again when the upload
+    /// budget changed.
+    #[cfg(feature = "vulkan")]
+    staging: Option<StagingRing>,
+    /// Queue families uploads are copied on, see `set_queue_families`.
+    #[cfg(feature = "vulkan")]
+    queue_families: Vec<u32>,
+    /// Chunks uploaded since `take_flushed_chunks` was last called.
+    #[cfg(feature = "vulkan")]
+    flushed_chunks: Vec<[u32; 3]>,
+    /// Bytes copied to the GPU by all flushes so far.
+    uploaded_bytes: u64,
+}
+
+impl World {
+    /// An empty world.
+    pub fn new() -> World {
+        let len = (WORLD_SIZE * WORLD_SIZE * WORLD_SIZE) as usize;
+        World {
+            voxels: vec![0; len],
+            dirty_chunks: BTreeSet::new(),
+            edited_chunks: BTreeSet::new(),
+            distance_field: ChunkDistances::new(),
+            upload_budget: DEFAULT_UPLOAD_BUDGET,
+            #[cfg(feature = "vulkan")]
+            gpu_cleared: false,
+            #[cfg(feature = "vulkan")]
+            gpu_slots: ChunkSlots::new(0, false),
+            #[cfg(feature = "vulkan")]
+            staging: None,
+            #[cfg(feature = "vulkan")]
+            queue_families: Vec::new(),
+            #[cfg(feature = "vulkan")]
+            flushed_chunks: Vec::new(),
+            uploaded_bytes: 0,
+        }
+    }
+
+    fn index(position: [u32; 3]) -> usize {
+        ((position[0] * WORLD_SIZE + position[1]) * WORLD_SIZE + position[2]) as usize
+    }
+
+    /// Returns the voxel at `position`, 0 outside the world.
+    pub fn voxel(&self, position: [i32; 3]) -> u32 {
+        if position.iter().any(|&c| c < 0 || c >= WORLD_SIZE as i32) {
+            return 0;
+        }
+        self.voxels[World::index(position.map(|c| c as u32))]
+    }
+
+    /// Returns the first solid voxel along the ray from `origin` towards `direction` within
+    /// `max_distance`. Walks the grid the same way the compute shader's `traverse` does, so it
+    /// agrees with what is on screen.
+    pub fn raycast(&self, origin: [f32; 3], direction: [f32; 3], max_distance: f32) -> Option<Hit> {
+        let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+        if length == 0.0 || !length.is_finite() {
+            return None;
+        }
+        let direction = direction.map(|d| d / length);
+        let step = direction.map(|d| {
+            if d > 0.0 {
+                1
+            } else if d < 0.0 {
+                -1
+            } else {
+                0
+            }
+        });
+        let delta_distance = direction.map(|d| (1.0 / d).abs());
+        let mut position = origin.map(|c| c.floor() as i32);
+        let mut side_distance = [0, 1, 2].map(|i| {
+            let sign = step[i] as f32;
+            (sign * (position[i] as f32 - origin[i]) + sign * 0.5 + 0.5) * delta_distance[i]
+        });
+        let mut normal = [0; 3];
+        let mut distance = 0.0;
+
+        while distance <= max_distance {
+            // Nothing left to hit once the ray is outside the world and moving away from it.
+            if (0..3).any(|i| {
+                (position[i] < 0 && step[i] <= 0)
+                    || (position[i] >= WORLD_SIZE as i32 && step[i] >= 0)
+            }) {
+                return None;
+            }
+            let voxel = self.voxel(position);
+            if voxel != 0 {
+                return Some(Hit {
+                    voxel,
+                    position,
+                    normal,
+                    distance,
+                });
+            }
+            let axis = if side_distance[0] < side_distance[1] {
+                if side_distance[0] < side_distance[2] {
+                    0
+                } else {
+                    2
+                }
+            } else if side_distance[1] < side_distance[2] {
+                1
+            } else {
+                2
+            };
+            distance = side_distance[axis];
+            side_distance[axis] += delta_distance[axis];
+            position[axis] += step[axis];
+            normal = [0; 3];
+            normal[axis] = -step[axis];
+        }
+        None
+    }
+
+    /// Returns whether nothing solid lies on the segment from `from` to `to`. Solid voxels
+    /// containing `from` or `to` block the line.
+    pub fn line_of_sight(&self, from: [f32; 3], to: [f32; 3]) -> bool {
+        let direction = [0, 1, 2].map(|i| to[i] - from[i]);
+        let distance = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+        self.raycast(from, direction, distance).is_none()
+    }
+
+    /// Sets the voxel at `position`. Positions outside the world are ignored.
+    pub fn set_voxel(&mut self, position: [u32; 3], voxel: u32) {
+        self.fill(position, position.map(|c| c + 1), voxel);
+    }
+
+    /// Sets every voxel from `min` (inclusive) to `max` (exclusive) to `voxel`. The box is
+    /// clipped to the world.
+    pub fn fill(&mut self, min: [u32; 3], max: [u32; 3], voxel: u32) {
+        self.edit_box(min, max, |row, _| row.fill(voxel));
+    }
+
+    /// Copies `voxels`, a box of `size` voxels with z varying fastest, into the world starting at
+    /// `min`. The box is clipped to the world.
+    pub fn set_region(&mut self, min: [u32; 3], size: [u32; 3], voxels: &[u32]) {
+        assert_eq!(
+            voxels.len(),
+            (size[0] * size[1] * size[2]) as usize,
+            "region data doesn't match its size"
+        );
+        let max = [0, 1, 2].map(|i| min[i].saturating_add(size[i]));
+        self.edit_box(min, max, |row, [x, y]| {
+            let start = ((x * size[1] + y) * size[2]) as usize;
+            row.copy_from_slice(&voxels[start..start + row.len()]);
+        });
+    }
+
+    /// Changes every voxel of type `from` from `min` (inclusive) to `max` (exclusive) to `to`.
+    /// The box is clipped to the world.
+    pub fn replace(&mut self, min: [u32; 3], max: [u32; 3], from: u32, to: u32) {
+        self.edit_box(min, max, |row, _| {
+            for voxel in row.iter_mut().filter(|voxel| **voxel == from) {
+                *voxel = to;
+            }
+        });
+    }
+
+    /// Empties the inside of the box from `min` (inclusive) to `max` (exclusive), keeping its
+    /// one voxel thick shell.
+    pub fn hollow(&mut self, min: [u32; 3], max: [u32; 3]) {
+        self.fill(
+            min.map(|c| c.saturating_add(1)),
+            max.map(|c| c.saturating_sub(1)),
+            0,
+        );
+    }
+
+    /// Sets the four sides of the box from `min` (inclusive) to `max` (exclusive) around the y
+    /// axis to `voxel`, leaving its top, bottom and inside as they are.
+    pub fn walls(&mut self, min: [u32; 3], max: [u32; 3], voxel: u32) {
+        if (0..3).any(|i| min[i] >= max[i]) {
+            return;
+        }
+        self.fill(min, [min[0] + 1, max[1], max[2]], voxel);
+        self.fill([max[0] - 1, min[1], min[2]], max, voxel);
+        self.fill(min, [max[0], max[1], min[2] + 1], voxel);
+        self.fill([min[0], min[1], max[2] - 1], max, voxel);
+    }
+
+    /// Returns the voxels from `min` (inclusive) to `max` (exclusive), which must lie in the
+    /// world, with z varying fastest.
+    pub fn region(&self, min: [u32; 3], max: [u32; 3]) -> Vec<u32> {
+        let mut voxels = Vec::new();
+        for x in min[0]..max[0] {
+            for y in min[1]..max[1] {
+                voxels.extend_from_slice(
+                    &self.voxels[World::index([x, y, min[2]])..World::index([x, y, max[2]])],
+                );
+            }
+        }
+        voxels
+    }
+
+    /// Copies the solid voxels of `voxels`, a box of `size` voxels with z varying fastest, into the
+    /// world starting at `min`, leaving the world as is where `voxels` is empty. The box is
+    /// clipped to the world.
+    pub fn paste_region(&mut self, min: [i32; 3], size: [u32; 3], voxels: &[u32]) {
+        assert_eq!(
+            voxels.len(),
+            (size[0] * size[1] * size[2]) as usize,
+            "region data doesn't match its size"
+        );
+        let clipped_min = min.map(|c| c.max(0) as u32);
+        let clipped_max =
+            [0, 1, 2].map(|i| (min[i] as i64 + size[i] as i64).clamp(0, WORLD_SIZE as i64) as u32);
+        // Offset of the clipped box inside the pasted one.
+        let skipped = [0, 1, 2].map(|i| (clipped_min[i] as i32 - min[i]) as u32);
+        self.edit_box(clipped_min, clipped_max, |row, [x, y]| {
+            let start =
+                (((x + skipped[0]) * size[1] + y + skipped[1]) * size[2] + skipped[2]) as usize;
+            for (voxel, &pasted) in row.iter_mut().zip(&voxels[start..]) {
+                if pasted != 0 {
+                    *voxel = pasted;
+                }
+            }
+        });
+    }
+
+    /// Writes the world to `path` as an `.rvox` file, see `to_rvox`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        world_file::write_atomically(path, &self.to_rvox())
+            .map_err(|err| format!("can't write world `{}`: {err}", path.display()))
+    }
+
+    /// Returns the world as an `.rvox` file: `RVOX`, the version and the edge length of the world
+    /// followed by runs of equal voxels as their length and voxel type, all little endian u32s.
+    /// Voxels are in the order of `voxels`, z varying fastest.
+    pub fn to_rvox(&self) -> Vec<u8> {
+        rvox_bytes(&self.voxels)
+    }
+
+    /// Replaces every voxel with the `.rvox` file at `path`.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let bytes = fs::read(path)
+            .map_err(|err| format!("can't read world `{}`: {err}", path.display()))?;
+        self.load_rvox(&bytes)
+            .map_err(|err| format!("invalid world `{}`: {err}", path.display()))
+    }
+
+    /// Replaces every voxel with the `.rvox` file `bytes`. The world is left as is when they
+    /// aren't valid.
+    pub fn load_rvox(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let words = world_file::read_words(bytes)?;
+        let [magic, version, size, runs @ ..] = &words[..] else {
+            return Err("unexpected end of file".to_string());
+        };
+        if magic.to_le_bytes() != *b"RVOX" {
+            return Err("not an .rvox file".to_string());
+        }
+        if *version != RVOX_VERSION {
+            return Err(format!("unsupported version {version}"));
+        }
+        if *size != WORLD_SIZE {
+            return Err(format!(
+                "world is {size} voxels wide instead of {WORLD_SIZE}"
+            ));
+        }
+        let voxels = world_file::read_runs(runs, self.voxels.len())?;
+        self.set_region([0; 3], [WORLD_SIZE; 3], &voxels);
+        Ok(())
+    }
+
+    /// Limits how many bytes a single flush uploads. At least one chunk is uploaded per flush
+    /// regardless.
+    pub fn set_upload_budget(&mut self, bytes: usize) {
+        self.upload_budget = bytes;
+    }
+
+    /// Returns the number of solid voxels.
+    pub fn solid_voxels(&self) -> usize {
+        self.voxels.iter().filter(|&&voxel| voxel != 0).count()
+    }
+
+    /// Returns how many voxels there are of each type present, empty ones included.
+    pub fn type_counts(&self) -> BTreeMap<u32, usize> {
+        let mut counts = BTreeMap::new();
+        for run in self.voxels.chunk_by(|a, b| a == b) {
+            *counts.entry(run[0]).or_insert(0) += run.len();
+        }
+        counts
+    }
+
+    /// Returns the number of bytes all flushes so far copied to the GPU.
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.uploaded_bytes
+    }
+
+    /// Returns the number of chunks whose edits haven't reached the GPU yet.
+    pub fn pending_chunks(&self) -> usize {
+        self.dirty_chunks.len()
+    }
+
+    /// Returns the chunks whose edits haven't reached the GPU yet.
+    pub fn dirty_chunks(&self) -> impl Iterator<Item = [u32; 3]> + '_ {
+        self.dirty_chunks.iter().copied()
+    }
+
+    /// Returns the chunks changed since the last call, for systems reacting to edits.
+    pub fn take_edited_chunks(&mut self) -> BTreeSet<[u32; 3]> {
+        std::mem::take(&mut self.edited_chunks)
+    }
+
+    /// Returns the distance of every chunk to the nearest chunk holding voxels, as of the last
+    /// `update_distance_field`.
+    pub fn distance_field(&self) -> &ChunkDistances {
+        &self.distance_field
+    }
+
+    /// Brings the distance field up to date with the edits since the last call. Returns whether
+    /// any distance changed.
+    pub fn update_distance_field(&mut self) -> bool {
+        let voxels = &self.voxels;
+        self.distance_field
+            .update(|chunk| World::any_solid(voxels, chunk))
+    }
+
+    /// Returns whether any voxel of `chunk` is solid.
+    pub fn chunk_occupied(&self, chunk: [u32; 3]) -> bool {
+        World::any_solid(&self.voxels, chunk)
+    }
+
+    fn any_solid(voxels: &[u32], chunk: [u32; 3]) -> bool {
+        let min = chunk.map(|c| c * CHUNK_SIZE);
+        (min[0]..min[0] + CHUNK_SIZE).any(|x| {
+            (min[1]..min[1] + CHUNK_SIZE).any(|y| {
+                let start = World::index([x, y, min[2]]);
+                voxels[start..start + CHUNK_SIZE as usize]
+                    .iter()
+                    .any(|&voxel| voxel != 0)
+            })
+        })
+    }
+
+    /// Marks the chunks overlapping the box from `min` (inclusive) to `max` (exclusive), which
+    /// must lie in the world, for upload and as edited.
+    fn mark_dirty(&mut self, min: [u32; 3], max: [u32; 3]) {
+        for x in min[0] / CHUNK_SIZE..=(max[0] - 1) / CHUNK_SIZE {
+            for y in min[1] / CHUNK_SIZE..=(max[1] - 1) / CHUNK_SIZE {
+                for z in min[2] / CHUNK_SIZE..=(max[2] - 1) / CHUNK_SIZE {
+                    self.dirty_chunks.insert([x, y, z]);
+                    self.edited_chunks.insert([x, y, z]);
+                    self.distance_field.mark_edited([x, y, z]);
+                }
+            }
+        }
+    }
+
+    /// Calls `edit` with every row along z of the box clipped to the world, along with the row's
+    /// x and y relative to `min`, and marks the chunks it touched as dirty.
+    fn edit_box(
+        &mut self,
+        min: [u32; 3],
+        max: [u32; 3],
+        mut edit: impl FnMut(&mut [u32], [u32; 2]),
+    ) {
+        let max = max.map(|c| c.min(WORLD_SIZE));
+        if (0..3).any(|i| min[i] >= max[i]) {
+            return;
+        }
+        for x in min[0]..max[0] {
+            for y in min[1]..max[1] {
+                let row = World::index([x, y, min[2]])..World::index([x, y, max[2] - 1]) + 1;
+                edit(&mut self.voxels[row], [x - min[0], y - min[1]]);
+            }
+        }
+        self.mark_dirty(min, max);
+    }
+
+    /// Returns the dirty chunks, those covering the most of the image seen from `eye` looking
+    /// along `forward` first, see `camera::screen_coverage`, then those off screen nearest
+    /// first.
+    #[cfg(feature = "vulkan")]
+    fn dirty_by_priority(&self, eye: [f32; 3], forward: [f32; 3]) -> Vec<[u32; 3]> {
+        // Of the sphere around a chunk.
+        let radius = CHUNK_SIZE as f32 * 3f32.sqrt() / 2.0;
+        let mut chunks: Vec<_> = self
+            .dirty_chunks
+            .iter()
+            .map(|&chunk| {
+                let center = chunk.map(|c| (c as f32 + 0.5) * CHUNK_SIZE as f32);
+                let coverage = screen_coverage(eye, forward, center, radius);
+                let distance: f32 = (0..3).map(|i| (center[i] - eye[i]).powi(2)).sum();
+                (coverage, distance, chunk)
+            })
+            .collect();
+        chunks.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.total_cmp(&b.1)));
+        chunks.into_iter().map(|(_, _, chunk)| chunk).collect()
+    }
+
+    /// Returns every chunk of the world.
+    #[cfg(feature = "vulkan")]
+    fn all_chunks() -> impl Iterator<Item = [u32; 3]> {
+        let chunks = WORLD_SIZE / CHUNK_SIZE;
+        (0..chunks)
+            .flat_map(move |x| (0..chunks).flat_map(move |y| (0..chunks).map(move |z| [x, y, z])))
+    }
+
+    /// Returns the voxels of `chunk` packed for the GPU copy.
+    #[cfg(feature = "vulkan")]
+    fn pack_chunk(&self, chunk: [u32; 3]) -> PackedChunk {
+        let min = chunk.map(|c| c * CHUNK_SIZE);
+        PackedChunk::pack(&self.region(min, min.map(|c| c + CHUNK_SIZE)))
+    }
+
+    /// Replaces every voxel with `voxels`, generated on the GPU and read back. The GPU copy is
+    /// cleared by the next flush and the chunks which aren't empty are uploaded again, they also
+    /// count as edited. Edits which haven't reached the GPU yet are dropped.
+    #[cfg(feature = "vulkan")]
+    pub fn replace_with_generated(&mut self, voxels: &[u32]) {
+        self.voxels.copy_from_slice(voxels);
+        self.dirty_chunks.clear();
+        self.gpu_cleared = false;
+        for chunk in World::all_chunks() {
+            let occupied = self.chunk_occupied(chunk);
+            if occupied {
+                self.edited_chunks.insert(chunk);
+                self.dirty_chunks.insert(chunk);
+            }
+            self.distance_field.set_occupied(chunk, occupied);
+        }
+    }
+
+    /// Returns the bytes of the buffers flushes upload through.
+    #[cfg(feature = "vulkan")]
+    pub fn staging_bytes(&self) -> u64 {
+        self.staging.as_ref().map_or(0, StagingRing::bytes)
+    }
+
+    /// Returns the chunks flushes uploaded since the last call, for keeping data derived from the
+    /// GPU copy in step with it.
+    #[cfg(feature = "vulkan")]
+    pub fn take_flushed_chunks(&mut self) -> Vec<[u32; 3]> {
+        std::mem::take(&mut self.flushed_chunks)
+    }
+
+    /// Sets the queue families uploads are copied on: the one tracing the GPU copy, then a
+    /// transfer queue's if there is one, which copies them into device local memory first, see
+    /// `StagingRing`. Has to be set before the first flush.
+    #[cfg(feature = "vulkan")]
+    pub fn set_queue_families(&mut self, queue_families: Vec<u32>) {
+        self.queue_families = queue_families;
+    }
+
+    /// Creates a GPU copy read the way `binding` says, holding at least `words` words, cleared
+    /// by the first flush into it.
+    #[cfg(feature = "vulkan")]
+    pub fn gpu_chunks(
+        &self,
+        memory_allocator: &StandardMemoryAllocator,
+        binding: ChunkBinding,
+        words: u32,
+    ) -> GpuChunks {
+        GpuChunks::new(memory_allocator, binding, words)
+    }
+
+    /// Records the copies bringing `gpu_chunks` closer to this world into `builder`, see
+    /// `stage` and `StagedChunks::record`. Returns whether anything changed.
+    #[cfg(feature = "vulkan")]
+    pub fn flush(
+        &mut self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        memory_allocator: &StandardMemoryAllocator,
+        gpu_chunks: &mut GpuChunks,
+        eye: [f32; 3],
+        forward: [f32; 3],
+    ) -> bool {
+        match self.stage(memory_allocator, gpu_chunks, eye, forward) {
+            Some(staged) => {
+                staged.record(builder, memory_allocator, gpu_chunks);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Packs as many dirty chunks as the budget allows into the staging buffer up next, with
+    /// the ones covering the most of the image seen from `eye` looking along `forward` first.
+    /// The length of `forward` sets the field of view like `camera::CAMERA_DIR`'s. Where they go
+    /// in `gpu_chunks` is settled right away, the copies are recorded by
+    /// `StagedChunks::record`, which has to happen before the next call. Nothing is staged while
+    /// the GPU still copies out of the staging buffer up next.
+    #[cfg(feature = "vulkan")]
+    pub fn stage(
+        &mut self,
+        memory_allocator: &StandardMemoryAllocator,
+        gpu_chunks: &GpuChunks,
+        eye: [f32; 3],
+        forward: [f32; 3],
+    ) -> Option<StagedChunks> {
+        // The budget is checked before adding a chunk, so a flush may go over by one chunk.
+        let staging_words = (self.upload_budget / 4 + MAX_PACKED_WORDS + 1) as u64;
+        let staging = match &mut self.staging {
+            Some(staging) if staging.words() == staging_words => staging,
+            staging => staging.insert(StagingRing::new(
+                memory_allocator,
+                staging_words,
+                &self.queue_families,
+            )),
+        };
+        if !staging.ready() {
+            return None;
+        }
+        // Chunks which were never edited are empty, which the cleared GPU copy already is.
+        let clear = !self.gpu_cleared;
+        if clear {
+            self.gpu_cleared = true;
+            self.gpu_slots = ChunkSlots::new(
+                gpu_chunks.words(),
+                gpu_chunks.binding() != ChunkBinding::Buffer,
+            );
+            self.flushed_chunks.extend(World::all_chunks());
+        }
+
+        // The words of all uploaded chunks are packed back to back into a single staging
+        // buffer, followed by their headers.
+        let mut words = Vec::new();
+        let mut headers = Vec::new();
+        let mut regions = Vec::new();
+        let mut capacity = gpu_chunks.words();
+        for chunk in self.dirty_by_priority(eye, forward) {
+            if !headers.is_empty() && (words.len() + headers.len()) * 4 >= self.upload_budget {
+                break;
+            }
+            let packed = self.pack_chunk(chunk);
+            let len = packed.words().len() as u32;
+            let mut offset = 0;
+            if len > 0 {
+                offset = match self.gpu_slots.place(chunk, len) {
+                    Some(offset) => offset,
+                    None => {
+                        capacity = self.gpu_slots.grow(len);
+                        self.gpu_slots.place(chunk, len).unwrap()
+                    }
+                };
+                regions.push(BufferCopy {
+                    src_offset: words.len() as u64,
+                    dst_offset: offset as u64,
+                    size: len as u64,
+                    ..Default::default()
+                });
+                words.extend_from_slice(packed.words());
+            } else {
+                self.gpu_slots.release(chunk);
+            }
+            headers.push((ChunkSlots::index(chunk), packed.header(offset)));
+            self.dirty_chunks.remove(&chunk);
+            self.flushed_chunks.push(chunk);
+        }
+        if headers.is_empty() && !clear {
+            return None;
+        }
+        for (index, header) in headers {
+            regions.push(BufferCopy {
+                src_offset: words.len() as u64,
+                dst_offset: index as u64,
+                size: 1,
+                ..Default::default()
+            });
+            words.push(header);
+        }
+        self.uploaded_bytes += words.len() as u64 * 4;
+
+        Some(StagedChunks {
+            // Checked to be ready above.
+            words: (!words.is_empty())
+                .then(|| self.staging.as_mut().unwrap().write(&words).unwrap()),
+            regions,
+            capacity,
+            clear,
+        })
+    }
+}
+
+/// Chunks packed by `World::stage`, waiting to be copied into the GPU copy.
+#[cfg(feature = "vulkan")]
+pub struct StagedChunks {
+    /// `None` when there is nothing to copy but the GPU copy has to be cleared.
+    words: Option<Staged>,
+    regions: Vec<BufferCopy>,
+    /// Words the GPU copy has to hold before the copies.
+    capacity: u32,
+    /// Whether the GPU copy has to be cleared before the copies.
+    clear: bool,
+}
+
+#[cfg(feature = "vulkan")]
+impl StagedChunks {
+    /// Records copying the words into the device local twin of their staging buffer, on the
+    /// transfer queue the twin is shared with. Records nothing when there is no twin.
+    pub fn record_landing(&self, builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>) {
+        if let Some(Staged {
+            host,
+            landing: Some(landing),
+        }) = &self.words
+        {
+            builder
+                .copy_buffer(CopyBufferInfoTyped::buffers(host.clone(), landing.clone()))
+                .unwrap();
+        }
+    }
+
+    /// Records bringing `gpu_chunks` up to date with the staged chunks on the queue tracing it:
+    /// clearing it first when staging started over, growing it when they don't fit, see
+    /// `GpuChunks::grow`, then copying them in from the device local twin of their staging
+    /// buffer if there is one, otherwise from the staging buffer itself.
+    pub fn record(
+        self,
+        builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        memory_allocator: &StandardMemoryAllocator,
+        gpu_chunks: &mut GpuChunks,
+    ) {
+        if self.clear {
+            gpu_chunks.clear(builder);
+        }
+        if self.capacity > gpu_chunks.words() {
+            gpu_chunks.grow(builder, memory_allocator, self.capacity);
+        }
+        if let Some(words) = self.words {
+            gpu_chunks.copy_from(builder, words.landing.unwrap_or(words.host), self.regions);
+        }
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        World::new()
+    }
+}
+
+/// Returns the box between the voxels `a` and `b`, both included and in any order, from its
+/// lower (inclusive) to its upper (exclusive) corner, clipped to the world.
+pub fn box_between(a: [i32; 3], b: [i32; 3]) -> ([u32; 3], [u32; 3]) {
+    let min = [0, 1, 2].map(|i| a[i].min(b[i]).clamp(0, WORLD_SIZE as i32) as u32);
+    let max =
+        [0, 1, 2].map(|i| a[i].max(b[i]).saturating_add(1).clamp(0, WORLD_SIZE as i32) as u32);
+    (min, max)
+}
+
+/// Returns `voxels`, every voxel of a world in the order of `World::region`, as an `.rvox` file,
+/// see `World::to_rvox`. Used for copies of the world taken with `region` to be written elsewhere.
+pub fn rvox_bytes(voxels: &[u32]) -> Vec<u8> {
+    let mut bytes = b"RVOX".to_vec();
+    bytes.extend_from_slice(&RVOX_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&WORLD_SIZE.to_le_bytes());
+    world_file::write_runs(&mut bytes, voxels);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A world holding a single voxel of type 3 at `position`.
+    fn world_with(position: [u32; 3]) -> World {
+        let mut world = World::new();
+        world.set_voxel(position, 3);
+        world
+    }
+
+    #[test]
+    fn raycast_hits_the_first_solid_voxel() {
+        let world = world_with([10, 10, 20]);
+        let hit = world
+            .raycast([10.5, 10.5, 5.5], [0.0, 0.0, 1.0], 100.0)
+            .unwrap();
+        assert_eq!(hit.voxel, 3);
+        assert_eq!(hit.position, [10, 10, 20]);
+        assert_eq!(hit.normal, [0, 0, -1]);
+        assert_eq!(hit.distance, 14.5);
+    }
+
+    #[test]
+    fn raycast_reports_the_face_entered() {
+        let world = world_with([10, 10, 10]);
+        let from_above = world
+            .raycast([10.5, 30.0, 10.5], [0.0, -1.0, 0.0], 100.0)
+            .unwrap();
+        assert_eq!(from_above.normal, [0, 1, 0]);
+        assert_eq!(from_above.distance, 19.0);
+        let from_side = world
+            .raycast([0.5, 10.5, 10.5], [1.0, 0.0, 0.0], 100.0)
+            .unwrap();
+        assert_eq!(from_side.normal, [-1, 0, 0]);
+        let diagonal = world
+            .raycast([5.5, 5.5, 5.5], [1.0, 1.0, 1.0], 100.0)
+            .unwrap();
+        assert_eq!(diagonal.position, [10, 10, 10]);
+        assert_eq!(diagonal.normal.iter().map(|n| n.abs()).sum::<i32>(), 1);
+    }
+
+    #[test]
+    fn raycast_starting_inside_a_voxel_has_no_normal() {
+        let world = world_with([10, 10, 10]);
+        let hit = world
+            .raycast([10.5, 10.5, 10.5], [1.0, 0.0, 0.0], 100.0)
+            .unwrap();
+        assert_eq!(hit.normal, [0; 3]);
+        assert_eq!(hit.distance, 0.0);
+    }
+
+    #[test]
+    fn raycast_stops_at_the_maximum_distance() {
+        let world = world_with([10, 10, 20]);
+        assert!(world
+            .raycast([10.5, 10.5, 5.5], [0.0, 0.0, 1.0], 10.0)
+            .is_none());
+        assert!(world
+            .raycast([10.5, 10.5, 5.5], [0.0, 0.0, 0.0], 100.0)
+            .is_none());
+    }
+
+    #[test]
+    fn raycast_sees_the_voxels_on_the_world_border() {
+        // The compute shader's `getVoxel` treats coordinate 0 as inside the world too.
+        let world = world_with([0, 0, 0]);
+        let hit = world
+            .raycast([0.5, 0.5, 8.5], [0.0, 0.0, -1.0], 100.0)
+            .unwrap();
+        assert_eq!(hit.position, [0, 0, 0]);
+        let far = world_with([WORLD_SIZE - 1; 3]);
+        let edge = WORLD_SIZE as f32 - 0.5;
+        assert!(far
+            .raycast([edge, edge, 100.0], [0.0, 0.0, 1.0], 1000.0)
+            .is_some());
+    }
+
+    #[test]
+    fn raycast_enters_the_world_from_outside() {
+        let world = world_with([10, 10, 0]);
+        let hit = world
+            .raycast([10.5, 10.5, -20.0], [0.0, 0.0, 1.0], 100.0)
+            .unwrap();
+        assert_eq!(hit.position, [10, 10, 0]);
+        assert_eq!(hit.normal, [0, 0, -1]);
+        assert_eq!(hit.distance, 20.0);
+    }
+
+    #[test]
+    fn raycast_leaving_the_world_gives_up() {
+        let world = world_with([10, 10, 10]);
+        // Outside and moving away, nothing can be hit however far the ray may go.
+        assert!(world
+            .raycast([-5.0, 10.5, 10.5], [-1.0, 0.0, 0.0], f32::MAX)
+            .is_none());
+        assert!(world
+            .raycast([10.5, 300.0, 10.5], [0.0, 1.0, 0.0], f32::MAX)
+            .is_none());
+        // Inside, the ray runs out of the world past the voxel's row.
+        assert!(world
+            .raycast([11.5, 10.5, 10.5], [1.0, 0.0, 0.0], f32::MAX)
+            .is_none());
+    }
+
+    #[test]
+    fn line_of_sight_is_blocked_by_solid_voxels() {
+        let world = world_with([10, 10, 10]);
+        assert!(!world.line_of_sight([5.5, 10.5, 10.5], [15.5, 10.5, 10.5]));
+        assert!(world.line_of_sight([5.5, 12.5, 10.5], [15.5, 12.5, 10.5]));
+        // The segment ends before the voxel.
+        assert!(world.line_of_sight([5.5, 10.5, 10.5], [9.5, 10.5, 10.5]));
+        // Solid voxels at either end block it.
+        assert!(!world.line_of_sight([10.5, 10.5, 10.5], [10.5, 20.5, 10.5]));
+    }
+
+    #[test]
+    fn box_between_takes_corners_in_any_order() {
+        let expected = ([2, 3, 4], [8, 9, 10]);
+        assert_eq!(box_between([2, 3, 4], [7, 8, 9]), expected);
+        assert_eq!(box_between([7, 8, 9], [2, 3, 4]), expected);
+        assert_eq!(box_between([7, 3, 9], [2, 8, 4]), expected);
+        // Clipped to the world.
+        let size = WORLD_SIZE as i32;
+        assert_eq!(
+            box_between([-5, 10, i32::MAX], [3, size + 10, 0]),
+            ([0, 10, 0], [4, WORLD_SIZE, WORLD_SIZE])
+        );
+    }
+
+    #[test]
+    fn edits_of_swapped_corners_cover_the_same_box() {
+        let (min, max) = box_between([9, 9, 9], [5, 5, 5]);
+        let mut world = World::new();
+        world.fill(min, max, 2);
+        assert_eq!(world.solid_voxels(), 125);
+        world.replace(min, max, 2, 4);
+        assert_eq!(world.voxel([7, 7, 7]), 4);
+        world.hollow(min, max);
+        assert_eq!(world.voxel([7, 7, 7]), 0);
+        assert_eq!(world.voxel([5, 7, 7]), 4);
+        assert_eq!(world.solid_voxels(), 125 - 27);
+        world.fill(min, max, 0);
+        world.walls(min, max, 1);
+        // Four sides of five voxels around y, four of them corners shared by two sides.
+        assert_eq!(world.solid_voxels(), (4 * 5 - 4) * 5);
+        assert_eq!(world.voxel([7, 7, 7]), 0);
+    }
+
+    #[test]
+    fn edits_at_the_end_of_the_range_do_nothing() {
+        let mut world = World::new();
+        world.hollow([u32::MAX; 3], [u32::MAX; 3]);
+        world.walls([u32::MAX; 3], [u32::MAX; 3], 1);
+        world.fill([WORLD_SIZE; 3], [u32::MAX; 3], 1);
+        assert_eq!(world.solid_voxels(), 0);
+    }
+
+    #[test]
+    fn paste_region_clips_and_keeps_what_is_under_empty_voxels() {
+        let mut world = world_with([0, 0, 1]);
+        // 2x1x2, empty at the second voxel.
+        let voxels = [5, 0, 6, 7];
+        world.paste_region([-1, 0, 0], [2, 1, 2], &voxels);
+        assert_eq!(world.voxel([0, 0, 0]), 6);
+        assert_eq!(world.voxel([0, 0, 1]), 7);
+        world.paste_region([0, 0, 0], [2, 1, 2], &voxels);
+        assert_eq!(world.voxel([0, 0, 0]), 5);
+        assert_eq!(world.voxel([0, 0, 1]), 7);
+        assert_eq!(world.voxel([1, 0, 1]), 7);
+        let far = WORLD_SIZE as i32 - 1;
+        world.paste_region([far, far, i32::MAX], [2, 1, 2], &voxels);
+        world.paste_region([far; 3], [2, 1, 2], &voxels);
+        assert_eq!(world.voxel([far; 3]), 5);
+        assert_eq!(world.solid_voxels(), 5);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{
+    prefab::Prefab,
+    world::{World, WORLD_SIZE},
+};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+};
+
+/// Extensions of the world files `load` and `save` handle: RayVox's own `.rvox`, see
+/// `World::to_rvox`, and MagicaVoxel's `.vox`, see `to_vox`.
+pub const FORMATS: [&str; 2] = ["rvox", "vox"];
+
+/// Returns the extension of `path` if it is one of `FORMATS`.
+fn format(path: &Path) -> Result<&'static str, String> {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    FORMATS
+        .into_iter()
+        .find(|&format| Some(format) == extension)
+        .ok_or_else(|| {
+            format!(
+                "`{}` isn't a world file, expected one of .{}",
+                path.display(),
+                FORMATS.join(", .")
+            )
+        })
+}
+
+/// Replaces every voxel of `world` with the world file at `path`. A `.vox` file's first model
+/// is placed at the origin of an otherwise empty world.
+pub fn load(world: &mut World, path: impl AsRef<Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    match format(path)? {
+        "vox" => {
+            let bytes = fs::read(path)
+                .map_err(|err| format!("can't read world `{}`: {err}", path.display()))?;
+            let model = Prefab::from_vox(String::new(), &bytes)
+                .map_err(|err| format!("invalid world `{}`: {err}", path.display()))?;
+            if model.size.iter().any(|&size| size > WORLD_SIZE) {
+                return Err(format!(
+                    "`{}` is {:?} voxels large, more than the world holds",
+                    path.display(),
+                    model.size
+                ));
+            }
+            world.fill([0; 3], [WORLD_SIZE; 3], 0);
+            world.paste_region([0; 3], model.size, &model.voxels);
+            Ok(())
+        }
+        _ => world.load(path),
+    }
+}
+
+/// Writes `world` to `path` in the format its extension names.
+pub fn save(world: &World, path: impl AsRef<Path>) -> Result<(), String> {
+    let path = path.as_ref();
+    match format(path)? {
+        "vox" => write_atomically(path, &to_vox(world))
+            .map_err(|err| format!("can't write world `{}`: {err}", path.display())),
+        _ => world.save(path),
+    }
+}
+
+/// Writes `bytes` to a temporary file next to `path` and renames it over `path` once it is
+/// complete, so a crash while writing leaves the previous file as it was.
+pub fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+/// Appends `voxels` to `bytes` as runs of equal voxels, each its length and voxel type as little
+/// endian u32s, the way `.rvox` files and map tiles store them, see `read_runs`.
+pub fn write_runs(bytes: &mut Vec<u8>, voxels: &[u32]) {
+    for run in voxels.chunk_by(|a, b| a == b) {
+        bytes.extend_from_slice(&(run.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&run[0].to_le_bytes());
+    }
+}
+
+/// Returns `bytes` as little endian u32s, failing when they don't end at a whole one.
+pub fn read_words(bytes: &[u8]) -> Result<Vec<u32>, String> {
+    bytes
+        .chunks(4)
+        .map(|word| {
+            word.try_into()
+                .map(u32::from_le_bytes)
+                .map_err(|_| "unexpected end of file".to_string())
+        })
+        .collect()
+}
+
+/// Returns the `count` voxels of the runs written by `write_runs`, failing unless the runs hold
+/// exactly as many, when one of them is empty or when the last one misses its voxel type.
+pub fn read_runs(runs: &[u32], count: usize) -> Result<Vec<u32>, String> {
+    let mut voxels = Vec::with_capacity(count);
+    for run in runs.chunks(2) {
+        let &[length, voxel] = run else {
+            return Err("unexpected end of file".to_string());
+        };
+        if length == 0 {
+            return Err("empty run of voxels".to_string());
+        }
+        if voxels.len() + length as usize > count {
+            return Err(format!("more than {count} voxels"));
+        }
+        voxels.resize(voxels.len() + length as usize, voxel);
+    }
+    if voxels.len() != count {
+        return Err(format!("fewer than {count} voxels"));
+    }
+    Ok(voxels)
+}
+
+/// Returns `world` as a MagicaVoxel `.vox` file holding one model as large as the world. Voxel
+/// types become palette indices, which `Prefab::from_vox` reads back as the same types as long
+/// as they are below `MATERIAL_COUNT` and without rules of their own, and y up becomes
+/// MagicaVoxel's z up. Water, lava and the other types with rules come back as plain ones.
+pub fn to_vox(world: &World) -> Vec<u8> {
+    let voxels = world.region([0; 3], [WORLD_SIZE; 3]);
+    let mut xyzi = Vec::new();
+    for (index, &voxel) in voxels.iter().enumerate() {
+        if voxel == 0 {
+            continue;
+        }
+        let size = WORLD_SIZE as usize;
+        let [x, y, z] = [index / size / size, index / size % size, index % size];
+        // Palette indices are a byte, index 0 is empty.
+        let color = (voxel - 1) % 255 + 1;
+        xyzi.extend_from_slice(&[x as u8, z as u8, y as u8, color as u8]);
+    }
+    let chunk = |id: &[u8; 4], content: &[u8]| -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes
+    };
+    let size: Vec<u8> = [WORLD_SIZE; 3]
+        .iter()
+        .flat_map(|size| size.to_le_bytes())
+        .collect();
+    let mut model = chunk(b"SIZE", &size);
+    let mut content = ((xyzi.len() / 4) as u32).to_le_bytes().to_vec();
+    content.extend_from_slice(&xyzi);
+    model.extend_from_slice(&chunk(b"XYZI", &content));
+
+    let mut bytes = b"VOX ".to_vec();
+    bytes.extend_from_slice(&150u32.to_le_bytes());
+    bytes.extend_from_slice(b"MAIN");
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&(model.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(&model);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_round_trip() {
+        let voxels = [0, 0, 0, 5, 5, 1, 0];
+        let mut bytes = Vec::new();
+        write_runs(&mut bytes, &voxels);
+        let words = read_words(&bytes).unwrap();
+        assert_eq!(words, [3, 0, 2, 5, 1, 1, 1, 0]);
+        assert_eq!(read_runs(&words, voxels.len()), Ok(voxels.to_vec()));
+    }
+
+    #[test]
+    fn read_runs_rejects_broken_runs() {
+        let end = Err("unexpected end of file".to_string());
+        assert_eq!(read_words(&[1, 0, 0, 0, 7]), end);
+        // A length without its voxel type.
+        assert_eq!(read_runs(&[2, 5, 1], 3), end);
+        assert_eq!(
+            read_runs(&[2, 5, 0, 1, 1, 1], 3),
+            Err("empty run of voxels".to_string())
+        );
+        assert_eq!(
+            read_runs(&[2, 5, 2, 1], 3),
+            Err("more than 3 voxels".to_string())
+        );
+        assert_eq!(
+            read_runs(&[2, 5], 3),
+            Err("fewer than 3 voxels".to_string())
+        );
+    }
+}
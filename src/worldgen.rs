@@ -0,0 +1,300 @@
+use crate::world::{World, CHUNK_SIZE, WORLD_SIZE};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Number of voxels in a chunk.
+pub const CHUNK_VOXELS: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+/// Fills chunks with voxels. `generate` calls it for many chunks at once from rayon's threads,
+/// so every chunk has to be generated on its own.
+pub trait Generator: Sync {
+    /// Returns the `CHUNK_VOXELS` voxels of `chunk` with z varying fastest.
+    fn chunk(&self, chunk: [u32; 3]) -> Vec<u32>;
+}
+
+/// Scatters random voxel types over the lower `extent`³ corner of the world, roughly one solid
+/// voxel in twenty.
+pub struct Scatter {
+    pub extent: u32,
+    pub seed: u64,
+}
+
+impl Scatter {
+    pub fn new(seed: u64) -> Scatter {
+        Scatter { extent: 250, seed }
+    }
+}
+
+impl Generator for Scatter {
+    fn chunk(&self, chunk: [u32; 3]) -> Vec<u32> {
+        let mut rng = chunk_rng(self.seed, chunk);
+        let mut voxels = vec![0; CHUNK_VOXELS];
+        for (index, voxel) in voxels.iter_mut().enumerate() {
+            let position = chunk_position(chunk, index);
+            if position.iter().all(|&c| c < self.extent) && rng.gen_range(1..20) == 1 {
+                *voxel = rng.gen_range(1..10);
+            }
+        }
+        voxels
+    }
+}
+
+/// Names of the generators `preset` knows.
+pub const PRESETS: [&str; 4] = ["scatter", "menger", "sierpinski", "mandelbulb"];
+
+/// Returns the generator called `name`, filling the lower `size`³ corner of the world or a
+/// size fitting the generator when `None`.
+pub fn preset(name: &str, seed: u64, size: Option<u32>) -> Option<Box<dyn Generator + Send>> {
+    let size = size.map(|size| size.clamp(1, WORLD_SIZE));
+    Some(match name {
+        "scatter" => Box::new(Scatter {
+            extent: size.unwrap_or(250),
+            seed,
+        }),
+        "menger" => Box::new(Menger::new(size.unwrap_or(WORLD_SIZE))),
+        "sierpinski" => Box::new(Sierpinski {
+            size: size.unwrap_or(WORLD_SIZE),
+        }),
+        "mandelbulb" => Box::new(Mandelbulb::new(size.unwrap_or(WORLD_SIZE))),
+        _ => return None,
+    })
+}
+
+/// Menger sponge: a cube split into 27 with the center and the middle of every face removed,
+/// repeated on the remaining cubes down to single voxels.
+pub struct Menger {
+    /// Edge length, the largest power of 3 fitting the requested size.
+    pub size: u32,
+}
+
+impl Menger {
+    pub fn new(size: u32) -> Menger {
+        let mut power = 1;
+        while power * 3 <= size {
+            power *= 3;
+        }
+        Menger { size: power }
+    }
+}
+
+impl Generator for Menger {
+    fn chunk(&self, chunk: [u32; 3]) -> Vec<u32> {
+        fractal_chunk(chunk, self.size, |position| {
+            let mut level = 1;
+            let mut depth = 0;
+            while level < self.size {
+                let middles = position.iter().filter(|&&c| c / level % 3 == 1).count();
+                if middles >= 2 {
+                    return 0;
+                }
+                depth += middles as u32;
+                level *= 3;
+            }
+            // Colored by how many levels of holes the voxel lines.
+            1 + depth % 9
+        })
+    }
+}
+
+/// Sierpinski tetrahedron: the voxels where the binary digits of no two coordinates overlap,
+/// a corner tetrahedron made of four half sized copies of itself.
+pub struct Sierpinski {
+    pub size: u32,
+}
+
+impl Generator for Sierpinski {
+    fn chunk(&self, chunk: [u32; 3]) -> Vec<u32> {
+        fractal_chunk(chunk, self.size, |[x, y, z]| {
+            if x & y != 0 || y & z != 0 || x & z != 0 {
+                return 0;
+            }
+            // Colored in bands along the height.
+            1 + y * 9 / self.size
+        })
+    }
+}
+
+/// Voxelization of the Mandelbulb, the points of the cube from -1.2 to 1.2 on every axis which
+/// don't escape iterating `z = z^power + c` in spherical coordinates, at `size` voxels per edge.
+pub struct Mandelbulb {
+    pub size: u32,
+    pub power: f32,
+    pub iterations: u32,
+}
+
+impl Mandelbulb {
+    /// Radius of the sampled cube around the origin.
+    const EXTENT: f32 = 1.2;
+
+    pub fn new(size: u32) -> Mandelbulb {
+        Mandelbulb {
+            size,
+            power: 8.0,
+            iterations: 10,
+        }
+    }
+
+    /// Returns how close the orbit of `c` came to the origin, or `None` when it escapes.
+    fn orbit_trap(&self, c: [f32; 3]) -> Option<f32> {
+        let mut z = c;
+        let mut trap = f32::MAX;
+        for _ in 0..self.iterations {
+            let r = z.iter().map(|c| c * c).sum::<f32>().sqrt();
+            if r > 2.0 {
+                return None;
+            }
+            trap = trap.min(r);
+            if r == 0.0 {
+                z = c;
+                continue;
+            }
+            let theta = (z[1] / r).acos() * self.power;
+            let phi = z[2].atan2(z[0]) * self.power;
+            let r = r.powf(self.power);
+            z = [
+                r * theta.sin() * phi.cos() + c[0],
+                r * theta.cos() + c[1],
+                r * theta.sin() * phi.sin() + c[2],
+            ];
+        }
+        Some(trap)
+    }
+}
+
+impl Generator for Mandelbulb {
+    fn chunk(&self, chunk: [u32; 3]) -> Vec<u32> {
+        fractal_chunk(chunk, self.size, |position| {
+            let c = position
+                .map(|p| ((p as f32 + 0.5) / self.size as f32 * 2.0 - 1.0) * Mandelbulb::EXTENT);
+            match self.orbit_trap(c) {
+                Some(trap) => 1 + (trap * 9.0) as u32 % 9,
+                None => 0,
+            }
+        })
+    }
+}
+
+/// Returns the voxels of `chunk`, set to `voxel` of their position inside the lower `size`³
+/// corner of the world and empty outside of it.
+fn fractal_chunk(chunk: [u32; 3], size: u32, voxel: impl Fn([u32; 3]) -> u32) -> Vec<u32> {
+    let mut voxels = vec![0; CHUNK_VOXELS];
+    if chunk.iter().any(|&c| c * CHUNK_SIZE >= size) {
+        return voxels;
+    }
+    for (index, value) in voxels.iter_mut().enumerate() {
+        let position = chunk_position(chunk, index);
+        if position.iter().all(|&c| c < size) {
+            *value = voxel(position);
+        }
+    }
+    voxels
+}
+
+/// Parameters of the rolling terrain the renderer generates straight into its GPU copy of the
+/// world: a heightmap of value noise covered in grass, with sand beaches and water filling
+/// everything empty below `water_level`. Nothing is generated on the CPU, the world is read back
+/// once the GPU is done.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Terrain {
+    pub seed: u32,
+    /// Noise features per voxel of the coarsest octave.
+    pub frequency: f32,
+    /// Octaves of noise summed, each at twice the frequency and half the weight of the one
+    /// before, adding finer detail.
+    pub octaves: u32,
+    /// Height of the hills above and depth of the valleys below `base_height`.
+    pub amplitude: f32,
+    /// Voxels the heightmap is sampled away from where it is drawn at most, by another noise
+    /// pattern, twisting hills into winding ridges. 0 leaves them round.
+    pub warp: f32,
+    pub base_height: f32,
+    pub water_level: f32,
+    /// Steps of thermal erosion run over the heightmap before it is filled with voxels, each
+    /// sliding material down slopes steeper than `talus`, carving valleys and sharpening ridges.
+    /// 0 leaves the raw noise.
+    pub erosion: u32,
+    /// Height difference between neighboring columns erosion leaves standing.
+    pub talus: f32,
+    /// Width of the network of tunnels 3D noise hollows out below the surface, in noise units
+    /// up to about 0.3. 0 leaves the ground solid.
+    pub caves: f32,
+    /// Tunnels dug down from the surface once the terrain is read back, the entrances to the
+    /// caves, see `caves::carve_worms`.
+    pub worms: u32,
+}
+
+impl Terrain {
+    pub fn new(seed: u64) -> Terrain {
+        Terrain {
+            // Both halves, so seeds differing in the upper bits still differ.
+            seed: (seed ^ (seed >> 32)) as u32,
+            frequency: 1.0 / 64.0,
+            octaves: 5,
+            amplitude: 40.0,
+            warp: 0.0,
+            base_height: WORLD_SIZE as f32 * 0.3,
+            water_level: WORLD_SIZE as f32 * 0.28,
+            erosion: 0,
+            talus: 1.0,
+            caves: 0.0,
+            worms: 0,
+        }
+    }
+}
+
+/// Returns the random number generator of `chunk` in a world generated from `seed`. Every chunk
+/// gets its own, so worlds come out the same no matter which thread generates which chunk. ChaCha
+/// is used as its output, unlike `StdRng`'s, is stable across versions of `rand`.
+pub fn chunk_rng(seed: u64, chunk: [u32; 3]) -> ChaCha8Rng {
+    let index = ((chunk[0] as u64) << 42) | ((chunk[1] as u64) << 21) | chunk[2] as u64;
+    // Spreads neighboring chunks far apart before seeding, see splitmix64.
+    let mut z = seed ^ index.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    ChaCha8Rng::seed_from_u64(z ^ (z >> 31))
+}
+
+/// Returns the world position of the voxel at `index` in the voxels of `chunk`.
+pub fn chunk_position(chunk: [u32; 3], index: usize) -> [u32; 3] {
+    let index = index as u32;
+    [
+        chunk[0] * CHUNK_SIZE + index / (CHUNK_SIZE * CHUNK_SIZE),
+        chunk[1] * CHUNK_SIZE + index / CHUNK_SIZE % CHUNK_SIZE,
+        chunk[2] * CHUNK_SIZE + index % CHUNK_SIZE,
+    ]
+}
+
+/// Returns every chunk of the world.
+pub fn all_chunks() -> Vec<[u32; 3]> {
+    let chunks = WORLD_SIZE / CHUNK_SIZE;
+    (0..chunks * chunks * chunks)
+        .map(|i| [i / (chunks * chunks), i / chunks % chunks, i % chunks])
+        .collect()
+}
+
+/// Generates `chunks` in parallel on rayon's thread pool and writes them into `world`, replacing
+/// what was there. `progress` is called from the generating threads with the number of chunks
+/// done so far and the total as chunks finish.
+///
+/// Generating a whole world passes `all_chunks`, streaming the chunks coming into view.
+pub fn generate(
+    world: &mut World,
+    generator: &dyn Generator,
+    chunks: &[[u32; 3]],
+    progress: impl Fn(usize, usize) + Sync,
+) {
+    let done = AtomicUsize::new(0);
+    let generated: Vec<_> = chunks
+        .par_iter()
+        .map(|&chunk| {
+            let voxels = generator.chunk(chunk);
+            progress(done.fetch_add(1, Ordering::Relaxed) + 1, chunks.len());
+            (chunk, voxels)
+        })
+        .collect();
+    for (chunk, voxels) in generated {
+        world.set_region(chunk.map(|c| c * CHUNK_SIZE), [CHUNK_SIZE; 3], &voxels);
+    }
+}